@@ -0,0 +1,20 @@
+//! Helpers shared by the benchmarks.
+
+use std::time::{Duration, Instant};
+
+/// Runs `f` on a fresh input from `setup` `iterations` times, and returns the mean duration of
+/// `f`.
+pub fn measure<T, S, F>(iterations: u32, mut setup: S, mut f: F) -> Duration
+where
+    S: FnMut() -> T,
+    F: FnMut(T),
+{
+    let mut total = Duration::from_secs(0);
+    for _ in 0..iterations {
+        let input = setup();
+        let start = Instant::now();
+        f(input);
+        total += start.elapsed();
+    }
+    total / iterations
+}
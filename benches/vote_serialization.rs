@@ -0,0 +1,102 @@
+//! Benchmarks the serialization overhead along the path of a vote: The voter signs it, it is sent
+//! over the network, and the recipient deserializes it and verifies the signature.
+//!
+//! Each vote is serialized once when it is signed, and the recipient keeps the bytes it received,
+//! so that verifying the signature doesn't serialize the vote again. Verifying the signatures
+//! dominates unless the mock cryptography is enabled:
+//!
+//! ```text
+//! $ cargo bench --features use-insecure-test-only-mock-crypto --bench vote_serialization
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hbbft::dynamic_honey_badger::{Change, SignedVote, VoteCounter, VoteLimits};
+use hbbft::honey_badger::{ChangeQuorum, ConflictPolicy};
+use hbbft::NetworkInfo;
+
+use crate::util::measure;
+
+mod util;
+
+/// The number of times each case is run.
+const ITERATIONS: u32 = 10;
+
+/// Returns a new counter for the given validator.
+fn new_counter(netinfo: &NetworkInfo<usize>) -> VoteCounter<usize> {
+    VoteCounter::new(
+        Arc::new(netinfo.clone()),
+        0,
+        VoteLimits::default(),
+        ChangeQuorum::TwoFaultyPlusOne,
+        ConflictPolicy::FirstWins,
+        None,
+    )
+}
+
+fn main() {
+    let mut rng = rand::thread_rng();
+    for &size in &[4, 16, 100] {
+        let netinfos = NetworkInfo::generate_map(0..size, &mut rng).expect("generate keys");
+        let netinfo = &netinfos[&0];
+        let mut pub_keys = netinfo.public_key_map().clone();
+        pub_keys.remove(&0);
+        let change = Change::NodeChange(pub_keys);
+
+        let sign = measure(
+            ITERATIONS,
+            || (),
+            |()| {
+                for netinfo in netinfos.values() {
+                    let mut counter = new_counter(netinfo);
+                    let _ = counter.sign_vote_for(change.clone()).expect("sign vote");
+                }
+            },
+        );
+        let votes: BTreeMap<usize, SignedVote<usize>> = netinfos
+            .iter()
+            .map(|(id, netinfo)| {
+                let mut counter = new_counter(netinfo);
+                let vote = counter.sign_vote_for(change.clone()).expect("sign vote");
+                (*id, vote.clone())
+            })
+            .collect();
+
+        let encode = measure(
+            ITERATIONS,
+            || (),
+            |()| {
+                for vote in votes.values() {
+                    let _ = bincode::serialize(vote).expect("serialize vote");
+                }
+            },
+        );
+        let encoded: Vec<(usize, Vec<u8>)> = votes
+            .iter()
+            .map(|(id, vote)| (*id, bincode::serialize(vote).expect("serialize vote")))
+            .collect();
+
+        let receive = measure(
+            ITERATIONS,
+            || new_counter(netinfo),
+            |mut counter| {
+                for (sender_id, bytes) in &encoded {
+                    let vote: SignedVote<usize> =
+                        bincode::deserialize(bytes).expect("deserialize vote");
+                    let faults = counter.add_pending_vote(sender_id, vote);
+                    assert!(faults.expect("add vote").is_empty());
+                }
+            },
+        );
+
+        let per_vote = |duration: std::time::Duration| duration / size as u32;
+        println!(
+            "{} validators: per vote {:?} signing, {:?} encoding, {:?} decoding and validating",
+            size,
+            per_vote(sign),
+            per_vote(encode),
+            per_vote(receive)
+        );
+    }
+}
@@ -0,0 +1,87 @@
+//! Benchmarks counting the committed votes for changes to the set of validators, in networks with
+//! 100 or more validators.
+//!
+//! Each validator's vote is committed and then superseded by a second vote, and the winner is
+//! looked up after every vote, like `DynamicHoneyBadger` does. Verifying the votes' signatures
+//! dominates unless the mock cryptography is enabled:
+//!
+//! ```text
+//! $ cargo bench --features use-insecure-test-only-mock-crypto --bench vote_tally
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use hbbft::dynamic_honey_badger::{Change, SignedVote, VoteCounter, VoteLimits};
+use hbbft::honey_badger::{ChangeQuorum, ConflictPolicy};
+use hbbft::NetworkInfo;
+
+use crate::util::measure;
+
+mod util;
+
+/// The number of times each case is run.
+const ITERATIONS: u32 = 10;
+
+/// The number of different changes the validators vote for.
+const NUM_CHANGES: usize = 10;
+
+/// Returns a new counter for the given validator.
+fn new_counter(netinfo: &NetworkInfo<usize>) -> VoteCounter<usize> {
+    VoteCounter::new(
+        Arc::new(netinfo.clone()),
+        0,
+        VoteLimits::default(),
+        ChangeQuorum::TwoFaultyPlusOne,
+        ConflictPolicy::FirstWins,
+        None,
+    )
+}
+
+/// Returns each validator's two votes: the first one for removing one of the first `NUM_CHANGES`
+/// validators, and the second one for removing validator 0.
+fn sign_votes(netinfos: &BTreeMap<usize, NetworkInfo<usize>>) -> Vec<SignedVote<usize>> {
+    let pub_keys = netinfos[&0].public_key_map().clone();
+    let removal = |id: usize| {
+        let mut pub_keys = pub_keys.clone();
+        pub_keys.remove(&id);
+        Change::NodeChange(pub_keys)
+    };
+    let mut first_votes = Vec::new();
+    let mut second_votes = Vec::new();
+    for (id, netinfo) in netinfos {
+        let mut counter = new_counter(netinfo);
+        let vote = counter.sign_vote_for(removal(id % NUM_CHANGES));
+        first_votes.push(vote.expect("sign vote").clone());
+        let vote = counter.sign_vote_for(removal(0));
+        second_votes.push(vote.expect("sign vote").clone());
+    }
+    first_votes.into_iter().chain(second_votes).collect()
+}
+
+fn main() {
+    let mut rng = rand::thread_rng();
+    for &size in &[100, 200, 400] {
+        let netinfos = NetworkInfo::generate_map(0..size, &mut rng).expect("generate keys");
+        let votes = sign_votes(&netinfos);
+        let netinfo = &netinfos[&0];
+        let duration = measure(
+            ITERATIONS,
+            || (new_counter(netinfo), votes.clone()),
+            |(mut counter, votes)| {
+                for vote in votes {
+                    let proposer_id = *vote.voter();
+                    let faults = counter.add_committed_vote(&proposer_id, vote);
+                    assert!(faults.expect("add vote").is_empty());
+                    let _ = counter.compute_winner();
+                }
+                assert!(counter.compute_winner().is_some());
+            },
+        );
+        println!(
+            "{} validators: {:?} per committed vote",
+            size,
+            duration / (2 * size) as u32
+        );
+    }
+}
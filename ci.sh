@@ -15,6 +15,7 @@ cargo fmt -- --check
 
 # We only test with mocktography, to ensure tests aren't unreasonably long.
 cargo test --features=use-insecure-test-only-mock-crypto --release
+cargo test --features=use-insecure-test-only-mock-crypto,parallel --release
 cargo doc
 cargo deadlinks --dir target/doc/hbbft/
 cargo audit
@@ -120,6 +120,15 @@ impl<M: Send> Messaging<M> {
                                             }
                                         }).map_err(Error::from);
                                 },
+                                Target::AllExcept(ref exclude) => {
+                                    // Send the message to all remote nodes that are not excluded,
+                                    // stopping at the first error.
+                                    result = txs_to_comms.iter()
+                                        .enumerate()
+                                        .filter(|(i, _)| !exclude.contains(i))
+                                        .try_for_each(|(_, tx)| tx.send(tm.message.clone()))
+                                        .map_err(Error::from);
+                                },
                                 Target::Node(i) => {
                                     result = if i < txs_to_comms.len() {
                                         txs_to_comms[i].send(tm.message)
@@ -41,12 +41,13 @@ use std::{iter, process, thread, time};
 
 use crossbeam;
 use log::{debug, error};
+use rand::{rngs::StdRng, SeedableRng};
 
 use crate::network::messaging::Messaging;
 use crate::network::{commst, connection};
 use hbbft::broadcast::{Broadcast, Message};
-use hbbft::crypto::{poly::Poly, SecretKey, SecretKeySet};
-use hbbft::{ConsensusProtocol, NetworkInfo, SourcedMessage};
+use hbbft::crypto::{SecretKey, SecretKeySet};
+use hbbft::{util, ConsensusProtocol, NetworkInfo, SourcedMessage};
 
 /// This is a structure to start a consensus node.
 pub struct Node<T> {
@@ -84,8 +85,10 @@ impl<T: Clone + Debug + AsRef<[u8]> + PartialEq + Send + Sync + From<Vec<u8>> +
         // FIXME: This example doesn't call algorithms that use cryptography. However the keys are
         // required by the interface to all algorithms in Honey Badger. Therefore we set placeholder
         // keys here. A fully-featured application would need to take appropriately initialized keys
-        // from elsewhere.
-        let secret_key_set = SecretKeySet::from(Poly::zero());
+        // from elsewhere. The key set is generated from a fixed seed, so that all nodes agree on
+        // it, and has the threshold that `NetworkInfo::validate` expects.
+        let mut key_rng = StdRng::from_seed([0; 32]);
+        let secret_key_set = SecretKeySet::random(util::max_faulty(all_ids.len()), &mut key_rng);
         let sk_share = secret_key_set.secret_key_share(our_id);
         let pub_key_set = secret_key_set.public_keys();
         let sk = SecretKey::default();
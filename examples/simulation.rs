@@ -274,9 +274,9 @@ where
     {
         for ts_msg in msgs {
             match ts_msg.target {
-                Target::All => {
+                Target::All | Target::AllExcept(_) => {
                     for node in self.nodes.values_mut() {
-                        if node.id != ts_msg.sender_id {
+                        if node.id != ts_msg.sender_id && ts_msg.target.contains(&node.id) {
                             node.add_message(ts_msg.clone())
                         }
                     }
@@ -407,6 +407,11 @@ fn main() {
         println!("{}", msg.red().bold());
     }
 
+    if hbbft::util::INSECURE_MOCK_CRYPTO {
+        let msg = "Using insecure mock cryptography: CPU times exclude pairings.";
+        println!("{}", msg.yellow().bold());
+    }
+
     println!("Simulating Honey Badger with:");
     println!("{} nodes, {} faulty", args.flag_n, args.flag_f);
     println!(
@@ -17,6 +17,8 @@
 pub mod adversary;
 pub mod err;
 pub mod proptest;
+pub mod scenario;
+pub mod soak;
 pub mod util;
 
 #[cfg(test)]
@@ -146,6 +148,18 @@ impl<D: ConsensusProtocol> Node<D> {
         self.faults.as_slice()
     }
 
+    /// Discards the outputs so far, e.g. to bound the memory usage of long-running tests.
+    #[inline]
+    pub fn clear_outputs(&mut self) {
+        self.outputs.clear();
+    }
+
+    /// Discards the faults so far, e.g. to bound the memory usage of long-running tests.
+    #[inline]
+    pub fn clear_faults(&mut self) {
+        self.faults.clear();
+    }
+
     /// Collects all outputs and faults (not required for network operation) for user convenience.
     fn store_step(&mut self, step: &CpStep<D>)
     where
@@ -251,8 +265,11 @@ where
                 ));
             }
             // Broadcast messages get expanded into multiple direct messages.
-            hbbft::Target::All => {
-                for to in nodes.keys().filter(|&to| to != &stepped_id) {
+            target @ hbbft::Target::All | target @ hbbft::Target::AllExcept(_) => {
+                for to in nodes
+                    .keys()
+                    .filter(|&to| to != &stepped_id && target.contains(to))
+                {
                     if !faulty {
                         message_count = message_count.saturating_add(1);
                     }
@@ -817,7 +834,7 @@ where
                 message_limit: None,
                 time_limit: None,
                 start_time: time::Instant::now(),
-                error_on_fault: true,
+                error_on_fault,
                 removed_nodes: BTreeSet::new(),
             },
             steps.into_iter().collect(),
@@ -0,0 +1,335 @@
+//! Declarative test scenarios.
+//!
+//! A `Scenario` describes a simulation run: the number of nodes, how many of them are faulty and
+//! how they misbehave, and whether the network is partitioned at some point. It compiles to a
+//! `VirtualNet` driven by a seeded random number generator, so the same scenario always produces
+//! the same run:
+//!
+//! ```rust,ignore
+//! let mut run = Scenario::new()
+//!     .nodes(10)
+//!     .adversaries(3, Strategy::Equivocate)
+//!     .partition_at_epoch(5)
+//!     .heal_after(1000)
+//!     .build(|node_info| /* construct the algorithm */)?;
+//! run.run_until(|net| net.correct_nodes().all(|node| node.outputs().len() >= 10))?;
+//! ```
+//!
+//! The adversaries only control the faulty nodes; the partition is simulated by delaying all
+//! messages between the two halves of the network, so no message is ever dropped.
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use rand::{Rng, SeedableRng};
+
+use hbbft::{ConsensusProtocol, CpStep};
+
+use crate::adversary::{random_node, Adversary, NetMutHandle, QueuePosition};
+use crate::proptest::{TestRng, TestRngSeed};
+use crate::{CrankError, NetBuilder, NetMessage, NetworkMessage, NewNodeInfo, VirtualNet};
+
+/// The way the faulty nodes in a scenario misbehave.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    /// The faulty nodes follow the protocol.
+    Correct,
+    /// The faulty nodes handle messages, but never send any.
+    Silent,
+    /// The faulty nodes follow the protocol, and additionally send each message they receive on to
+    /// another random node, as if it were their own.
+    Replay,
+    /// The faulty nodes follow the protocol, but send each recipient a message that was meant for
+    /// another one, so that different nodes see different messages from them.
+    Equivocate,
+}
+
+/// A declarative description of a simulation run.
+#[derive(Clone, Debug)]
+pub struct Scenario {
+    /// The total number of nodes.
+    num_nodes: u16,
+    /// The number of faulty nodes.
+    num_faulty: u16,
+    /// The behavior of the faulty nodes.
+    strategy: Strategy,
+    /// The epoch at which the network is partitioned, if any.
+    partition_epoch: Option<usize>,
+    /// The number of cranks after which the partition heals, if any.
+    heal_after: Option<usize>,
+    /// The seed of the random number generator.
+    seed: TestRngSeed,
+    /// The maximum number of cranks, if any.
+    crank_limit: Option<usize>,
+    /// Whether the default time limit is lifted.
+    no_time_limit: bool,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Scenario {
+            num_nodes: 4,
+            num_faulty: 0,
+            strategy: Strategy::Correct,
+            partition_epoch: None,
+            heal_after: None,
+            seed: [0; 16],
+            crank_limit: None,
+            no_time_limit: false,
+        }
+    }
+}
+
+impl Scenario {
+    /// Creates a scenario with four correct nodes, no partition and a fixed seed.
+    pub fn new() -> Self {
+        Scenario::default()
+    }
+
+    /// Sets the total number of nodes, including the faulty ones.
+    pub fn nodes(mut self, num_nodes: u16) -> Self {
+        self.num_nodes = num_nodes;
+        self
+    }
+
+    /// Sets the number of faulty nodes, and how they misbehave. The faulty nodes are the ones with
+    /// the lowest IDs.
+    pub fn adversaries(mut self, num_faulty: u16, strategy: Strategy) -> Self {
+        self.num_faulty = num_faulty;
+        self.strategy = strategy;
+        self
+    }
+
+    /// Partitions the network into two halves once a correct node has produced `epoch` outputs.
+    /// Messages between the halves are delayed until no other messages are left, or until the
+    /// partition heals.
+    pub fn partition_at_epoch(mut self, epoch: usize) -> Self {
+        self.partition_epoch = Some(epoch);
+        self
+    }
+
+    /// Heals the partition after the given number of cranks.
+    pub fn heal_after(mut self, cranks: usize) -> Self {
+        self.heal_after = Some(cranks);
+        self
+    }
+
+    /// Sets the seed of the random number generator that drives the run.
+    pub fn seed(mut self, seed: TestRngSeed) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the maximum number of cranks.
+    pub fn crank_limit(mut self, crank_limit: usize) -> Self {
+        self.crank_limit = Some(crank_limit);
+        self
+    }
+
+    /// Removes the time limit, e.g. for long-running soak tests.
+    pub fn no_time_limit(mut self) -> Self {
+        self.no_time_limit = true;
+        self
+    }
+
+    /// Creates the network, using `cons` to construct each node. The nodes' IDs are `0`, `1`, ...
+    ///
+    /// # Panics
+    ///
+    /// Panics if the total number of nodes is not `> 3 * num_faulty`.
+    pub fn build<D, F>(&self, cons: F) -> Result<ScenarioRun<D>, CrankError<D>>
+    where
+        D: ConsensusProtocol,
+        D::NodeId: From<u16>,
+        D::Message: Clone,
+        D::Output: Clone,
+        F: Fn(NewNodeInfo<D>) -> (D, CpStep<D>) + 'static,
+    {
+        let mut rng = TestRng::from_seed(self.seed);
+        let mut builder = NetBuilder::new((0..self.num_nodes).map(D::NodeId::from))
+            .num_faulty(self.num_faulty as usize)
+            .adversary(ScenarioAdversary::new(self.strategy))
+            .using_step(cons);
+        if let Some(crank_limit) = self.crank_limit {
+            builder = builder.crank_limit(crank_limit);
+        }
+        if self.no_time_limit {
+            builder = builder.no_time_limit();
+        }
+        let (net, _) = builder.build(&mut rng)?;
+        let first_half = (0..self.num_nodes / 2).map(D::NodeId::from).collect();
+        Ok(ScenarioRun {
+            net,
+            rng,
+            first_half,
+            partition_epoch: self.partition_epoch,
+            heal_after: self.heal_after,
+            partitioned_cranks: None,
+        })
+    }
+}
+
+/// A network built from a `Scenario`, together with the random number generator driving it.
+pub struct ScenarioRun<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    /// The simulated network.
+    pub net: VirtualNet<D, ScenarioAdversary>,
+    /// The random number generator, which should be used for any inputs, too.
+    pub rng: TestRng,
+    /// The nodes on one side of the partition.
+    first_half: BTreeSet<D::NodeId>,
+    /// The epoch at which the network is partitioned, if any.
+    partition_epoch: Option<usize>,
+    /// The number of cranks after which the partition heals, if any.
+    heal_after: Option<usize>,
+    /// The number of cranks since the partition began, if it has.
+    partitioned_cranks: Option<usize>,
+}
+
+impl<D> fmt::Debug for ScenarioRun<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScenarioRun")
+            .field("first_half", &self.first_half)
+            .field("partition_epoch", &self.partition_epoch)
+            .field("heal_after", &self.heal_after)
+            .field("partitioned_cranks", &self.partitioned_cranks)
+            .finish()
+    }
+}
+
+impl<D> ScenarioRun<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    /// Returns `true` if the network is currently partitioned.
+    pub fn is_partitioned(&self) -> bool {
+        match (self.partitioned_cranks, self.heal_after) {
+            (Some(cranks), Some(heal_after)) => cranks < heal_after,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Delivers one message, and returns the receiving node's ID and step, or `None` if the queue
+    /// is empty.
+    pub fn crank(&mut self) -> Option<Result<(D::NodeId, CpStep<D>), CrankError<D>>> {
+        if self.partitioned_cranks.is_none() {
+            if let Some(epoch) = self.partition_epoch {
+                if self
+                    .net
+                    .correct_nodes()
+                    .any(|node| node.outputs().len() >= epoch)
+                {
+                    self.partitioned_cranks = Some(0);
+                }
+            }
+        }
+        if self.is_partitioned() {
+            let first_half = &self.first_half;
+            let crosses = |msg: &NetMessage<D>| {
+                first_half.contains(&msg.from) != first_half.contains(&msg.to)
+            };
+            self.net
+                .sort_messages_by(|m0, m1| crosses(m0).cmp(&crosses(m1)));
+            self.partitioned_cranks = self.partitioned_cranks.map(|cranks| cranks + 1);
+        }
+        self.net.crank(&mut self.rng)
+    }
+
+    /// Cranks the network until `done` returns `true`. Returns `false` if the message queue ran
+    /// empty before that.
+    pub fn run_until<P>(&mut self, mut done: P) -> Result<bool, CrankError<D>>
+    where
+        P: FnMut(&VirtualNet<D, ScenarioAdversary>) -> bool,
+    {
+        while !done(&self.net) {
+            match self.crank() {
+                None => return Ok(false),
+                Some(result) => {
+                    let _ = result?;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The adversary controlling the faulty nodes in a scenario.
+#[derive(Copy, Clone, Debug)]
+pub struct ScenarioAdversary {
+    /// The behavior of the faulty nodes.
+    strategy: Strategy,
+}
+
+impl ScenarioAdversary {
+    /// Creates an adversary with the given strategy.
+    pub fn new(strategy: Strategy) -> Self {
+        ScenarioAdversary { strategy }
+    }
+}
+
+impl<D> Adversary<D> for ScenarioAdversary
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    fn tamper<R: Rng>(
+        &mut self,
+        mut net: NetMutHandle<'_, D, Self>,
+        msg: NetMessage<D>,
+        rng: &mut R,
+    ) -> Result<CpStep<D>, CrankError<D>> {
+        let sender = msg.to.clone();
+        match self.strategy {
+            Strategy::Correct => net.dispatch_message(msg, rng),
+            Strategy::Silent => {
+                let mut step = net.dispatch_message(msg, rng)?;
+                step.messages.clear();
+                Ok(step)
+            }
+            Strategy::Replay => {
+                if let Some(picked_node) = random_node(&mut net, rng) {
+                    let mut new_msg = msg.clone();
+                    new_msg.from = sender;
+                    new_msg.to = picked_node;
+                    net.inject_message(QueuePosition::Back, new_msg);
+                }
+                net.dispatch_message(msg, rng)
+            }
+            Strategy::Equivocate => {
+                let mut step = net.dispatch_message(msg, rng)?;
+                let node_ids: Vec<D::NodeId> = net.nodes_mut().map(|node| node.id()).collect();
+                let mut recipients = Vec::new();
+                let mut payloads = Vec::new();
+                for tmsg in step.messages.drain(..) {
+                    for id in node_ids.iter().filter(|&id| *id != sender) {
+                        if tmsg.target.contains(id) {
+                            recipients.push(id.clone());
+                            payloads.push(tmsg.message.clone());
+                        }
+                    }
+                }
+                if !payloads.is_empty() {
+                    payloads.rotate_left(1);
+                }
+                for (to, payload) in recipients.into_iter().zip(payloads) {
+                    let new_msg = NetworkMessage::new(sender.clone(), payload, to);
+                    net.inject_message(QueuePosition::Back, new_msg);
+                }
+                Ok(step)
+            }
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Long-running soak tests.
+//!
+//! A `Soak` drives a `ScenarioRun` for a configurable number of epochs, and every few epochs
+//! checks a set of invariants and size bounds. Between the checks, an optional churn function can
+//! send inputs, e.g. transactions or votes to add and remove validators, and the recorded outputs
+//! and faults of the nodes are discarded, so that the harness itself uses constant memory even
+//! over millions of epochs:
+//!
+//! ```rust,ignore
+//! let report = Soak::new(1_000_000)
+//!     .check_every(100)
+//!     .invariant("no faults", |net| net.correct_nodes().all(|node| node.faults().is_empty()))
+//!     .bound_messages(10_000)
+//!     .bound("pending votes", 100, |node| /* the size of the node's vote buffer */)
+//!     .churn(|run, epoch| /* vote validators in or out */)
+//!     .run(&mut scenario_run)?;
+//! ```
+//!
+//! A bound that keeps growing from check to check indicates a leak, e.g. in the pruning of old
+//! epochs' state, even if the network keeps making progress.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hbbft::ConsensusProtocol;
+
+use crate::scenario::{ScenarioAdversary, ScenarioRun};
+use crate::{CrankError, Node, VirtualNet};
+
+/// A condition on the whole network.
+type InvariantFn<D> = Box<dyn Fn(&VirtualNet<D, ScenarioAdversary>) -> bool>;
+
+/// The size of some part of a node's state.
+type SizeFn<D> = Box<dyn Fn(&Node<D>) -> usize>;
+
+/// A function that is called at every check, e.g. to send inputs.
+type ChurnFn<D> = Box<dyn FnMut(&mut ScenarioRun<D>, usize)>;
+
+/// A failed soak test.
+pub enum SoakError<D>
+where
+    D: ConsensusProtocol,
+{
+    /// Cranking the network failed.
+    Crank(CrankError<D>),
+    /// The message queue ran empty before the target number of epochs was reached.
+    Stalled {
+        /// The last epoch in which the invariants were checked.
+        epoch: usize,
+    },
+    /// An invariant did not hold.
+    Violation {
+        /// The name of the invariant.
+        name: String,
+        /// The epoch in which it was checked.
+        epoch: usize,
+    },
+    /// A correct node's state, or the message queue, exceeded its bound.
+    BoundExceeded {
+        /// The name of the bound.
+        name: String,
+        /// The node whose state exceeded it, or `None` for the message queue.
+        node_id: Option<D::NodeId>,
+        /// The epoch in which it was checked.
+        epoch: usize,
+        /// The measured size.
+        size: usize,
+        /// The maximum allowed size.
+        limit: usize,
+    },
+}
+
+impl<D> From<CrankError<D>> for SoakError<D>
+where
+    D: ConsensusProtocol,
+{
+    fn from(err: CrankError<D>) -> Self {
+        SoakError::Crank(err)
+    }
+}
+
+impl<D> fmt::Debug for SoakError<D>
+where
+    D: ConsensusProtocol,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SoakError::Crank(err) => f.debug_tuple("Crank").field(err).finish(),
+            SoakError::Stalled { epoch } => {
+                f.debug_struct("Stalled").field("epoch", epoch).finish()
+            }
+            SoakError::Violation { name, epoch } => f
+                .debug_struct("Violation")
+                .field("name", name)
+                .field("epoch", epoch)
+                .finish(),
+            SoakError::BoundExceeded {
+                name,
+                node_id,
+                epoch,
+                size,
+                limit,
+            } => f
+                .debug_struct("BoundExceeded")
+                .field("name", name)
+                .field("node_id", node_id)
+                .field("epoch", epoch)
+                .field("size", size)
+                .field("limit", limit)
+                .finish(),
+        }
+    }
+}
+
+/// The result of a successful soak test.
+#[derive(Clone, Debug, Default)]
+pub struct SoakReport {
+    /// The number of epochs every correct node has completed.
+    pub epochs: usize,
+    /// The number of times the invariants were checked.
+    pub checks: usize,
+    /// The number of delivered messages.
+    pub cranks: usize,
+    /// The largest size measured for each bound, across all checks and correct nodes.
+    pub peaks: BTreeMap<String, usize>,
+}
+
+/// A long-running test with periodic invariant checks.
+pub struct Soak<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    /// The number of epochs to run.
+    epochs: usize,
+    /// The number of epochs between two checks.
+    check_interval: usize,
+    /// The named invariants.
+    invariants: Vec<(String, InvariantFn<D>)>,
+    /// The named bounds on the correct nodes' state, with their limits.
+    bounds: Vec<(String, usize, SizeFn<D>)>,
+    /// The maximum number of queued messages, if any.
+    message_limit: Option<usize>,
+    /// The function called at every check.
+    churn: Option<ChurnFn<D>>,
+}
+
+impl<D> fmt::Debug for Soak<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let invariants: Vec<_> = self.invariants.iter().map(|(name, _)| name).collect();
+        let bounds: Vec<_> = self
+            .bounds
+            .iter()
+            .map(|(name, limit, _)| (name, limit))
+            .collect();
+        f.debug_struct("Soak")
+            .field("epochs", &self.epochs)
+            .field("check_interval", &self.check_interval)
+            .field("invariants", &invariants)
+            .field("bounds", &bounds)
+            .field("message_limit", &self.message_limit)
+            .field("churn", &self.churn.is_some())
+            .finish()
+    }
+}
+
+impl<D> Soak<D>
+where
+    D: ConsensusProtocol,
+    D::Message: Clone,
+    D::Output: Clone,
+{
+    /// Creates a soak test that runs for the given number of epochs, and checks every 100 epochs.
+    ///
+    /// An epoch is counted once every correct node has produced another output.
+    pub fn new(epochs: usize) -> Self {
+        Soak {
+            epochs,
+            check_interval: 100,
+            invariants: Vec::new(),
+            bounds: Vec::new(),
+            message_limit: None,
+            churn: None,
+        }
+    }
+
+    /// Sets the number of epochs between two checks.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `epochs` is `0`.
+    pub fn check_every(mut self, epochs: usize) -> Self {
+        assert!(epochs > 0, "the check interval must be positive");
+        self.check_interval = epochs;
+        self
+    }
+
+    /// Adds an invariant that must hold at every check. Since the nodes' outputs and faults are
+    /// discarded after each check, the invariant only sees the ones since the previous check.
+    pub fn invariant<F>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn(&VirtualNet<D, ScenarioAdversary>) -> bool + 'static,
+    {
+        self.invariants.push((name.to_string(), Box::new(check)));
+        self
+    }
+
+    /// Adds a bound that the given size of every correct node's state must not exceed at any
+    /// check, e.g. the number of buffered votes or of retained epochs.
+    pub fn bound<F>(mut self, name: &str, limit: usize, size: F) -> Self
+    where
+        F: Fn(&Node<D>) -> usize + 'static,
+    {
+        self.bounds.push((name.to_string(), limit, Box::new(size)));
+        self
+    }
+
+    /// Sets the maximum number of messages that may be queued in the network at any check.
+    pub fn bound_messages(mut self, limit: usize) -> Self {
+        self.message_limit = Some(limit);
+        self
+    }
+
+    /// Sets a function that is called at every check with the current epoch, e.g. to send inputs
+    /// to the nodes, or to vote validators in or out.
+    pub fn churn<F>(mut self, churn: F) -> Self
+    where
+        F: FnMut(&mut ScenarioRun<D>, usize) + 'static,
+    {
+        self.churn = Some(Box::new(churn));
+        self
+    }
+
+    /// Runs the network until the target number of epochs is reached, and returns a report, or the
+    /// first failure.
+    pub fn run(&mut self, run: &mut ScenarioRun<D>) -> Result<SoakReport, SoakError<D>> {
+        let mut report = SoakReport::default();
+        if let Some(churn) = self.churn.as_mut() {
+            churn(run, 0);
+        }
+        while report.epochs < self.epochs {
+            let interval = self.check_interval.min(self.epochs - report.epochs);
+            while !run
+                .net
+                .correct_nodes()
+                .all(|node| node.outputs().len() >= interval)
+            {
+                match run.crank() {
+                    None => {
+                        return Err(SoakError::Stalled {
+                            epoch: report.epochs,
+                        })
+                    }
+                    Some(result) => {
+                        let _ = result?;
+                        report.cranks += 1;
+                    }
+                }
+            }
+            report.epochs += interval;
+            report.checks += 1;
+            self.check(run, &mut report)?;
+            for node in run.net.nodes_mut() {
+                node.clear_outputs();
+                node.clear_faults();
+            }
+            if let Some(churn) = self.churn.as_mut() {
+                churn(run, report.epochs);
+            }
+        }
+        Ok(report)
+    }
+
+    /// Checks all invariants and bounds, and records the peak sizes.
+    fn check(&self, run: &ScenarioRun<D>, report: &mut SoakReport) -> Result<(), SoakError<D>> {
+        let epoch = report.epochs;
+        for (name, check) in &self.invariants {
+            if !check(&run.net) {
+                let name = name.clone();
+                return Err(SoakError::Violation { name, epoch });
+            }
+        }
+        for (name, limit, size_fn) in &self.bounds {
+            for node in run.net.correct_nodes() {
+                let size = size_fn(node);
+                let peak = report.peaks.entry(name.clone()).or_insert(0);
+                *peak = (*peak).max(size);
+                if size > *limit {
+                    return Err(SoakError::BoundExceeded {
+                        name: name.clone(),
+                        node_id: Some(node.id().clone()),
+                        epoch,
+                        size,
+                        limit: *limit,
+                    });
+                }
+            }
+        }
+        if let Some(limit) = self.message_limit {
+            let size = run.net.messages_len();
+            let peak = report.peaks.entry("messages".to_string()).or_insert(0);
+            *peak = (*peak).max(size);
+            if size > limit {
+                return Err(SoakError::BoundExceeded {
+                    name: "messages".to_string(),
+                    node_id: None,
+                    epoch,
+                    size,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,479 @@
+//! # Asynchronous Key Generation
+//!
+//! A dealerless distributed key generation that, unlike `SyncKeyGen`, doesn't need the nodes to
+//! handle the same messages in the same order, and thus doesn't need an existing consensus
+//! instance or a coordinator: It runs over the same asynchronous message-passing model as the
+//! other algorithms, and can be used to bootstrap a network from nothing but every node's regular
+//! key pair. It succeeds if at most _f_ of the _N > 3 f_ nodes are faulty, and the resulting key
+//! set has threshold _f_.
+//!
+//! ## Usage
+//!
+//! Every node creates an `AsyncKeyGen` instance with its own secret key and all nodes' public
+//! keys, and sends the messages in the returned step. It then handles every message it receives
+//! from the other nodes. Once the key generation is complete, every node outputs a `NetworkInfo`
+//! with its secret key share and the new public key set, which can e.g. be used to start a
+//! `DynamicHoneyBadger` instance.
+//!
+//! ## How it works
+//!
+//! Every node creates a `SyncKeyGen` instance, and sends its `Part` to all other nodes via
+//! `Broadcast`, so that all correct nodes receive the same `Part` from every proposer, if any.
+//! When a node receives a valid `Part`, it multicasts its `Ack`. There is one `BinaryAgreement`
+//! instance per proposer, deciding whether its `Part` is used: A node votes `true` once it has
+//! received _2 f + 1_ `Ack`s for it, so that at least _f + 1_ of them are from correct nodes, which
+//! will eventually send every node a valid value for its key share. Once _N - f_ proposals have
+//! been accepted, a node votes `false` on all others. When all instances have decided and a node
+//! has received enough valid values, it computes its key share and the public key set from the
+//! accepted `Part`s.
+//!
+//! Since the nodes don't have threshold keys yet, the `BinaryAgreement` instances use a
+//! `LocalCoin`. By default, that is a `PrfCoin` keyed by the session ID, which everyone can
+//! predict: An adversary that controls the scheduling of all messages could delay the key
+//! generation indefinitely, though it can't break its safety. If the nodes share a secret, they
+//! should use it as the coin's key instead: see `set_local_coin`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Arc;
+
+use crate::crypto::PublicKey;
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::binary_agreement::{self, BinaryAgreement, LocalCoin, PrfCoin};
+use crate::broadcast::{self, Broadcast};
+use crate::fault_log::Fault;
+use crate::sync_key_gen::{Ack, AckFault, AckOutcome, Part, PartFault, PartOutcome, SyncKeyGen};
+use crate::{
+    sync_key_gen, ConsensusProtocol, IntoSigner, NetworkInfo, NodeIdT, ShareSigner, Target,
+};
+
+/// An asynchronous key generation error.
+#[derive(Clone, PartialEq, Debug, Fail)]
+pub enum Error {
+    /// Error creating `SyncKeyGen`.
+    #[fail(display = "Error creating SyncKeyGen: {}", _0)]
+    NewKeyGen(sync_key_gen::Error),
+    /// Error creating a `Broadcast` instance.
+    #[fail(display = "Error creating Broadcast: {}", _0)]
+    NewBroadcast(broadcast::Error),
+    /// Error creating a `BinaryAgreement` instance.
+    #[fail(display = "Error creating BinaryAgreement: {}", _0)]
+    NewAgreement(binary_agreement::Error),
+    /// Error handling a `Part` or `Ack`, or generating the keys.
+    #[fail(display = "Error in SyncKeyGen: {}", _0)]
+    KeyGen(sync_key_gen::Error),
+    /// Error handling a `Broadcast` message or input.
+    #[fail(display = "Error handling Broadcast: {}", _0)]
+    HandleBroadcast(broadcast::Error),
+    /// Error handling a `BinaryAgreement` message or input.
+    #[fail(display = "Error handling BinaryAgreement: {}", _0)]
+    HandleAgreement(binary_agreement::Error),
+    /// Failed to serialize our `Part`.
+    #[fail(display = "Error serializing our Part: {}", _0)]
+    SerializePart(String),
+    /// The message refers to an unknown proposer.
+    #[fail(display = "Unknown proposer")]
+    UnknownProposer,
+    /// Unknown sender.
+    #[fail(display = "Unknown sender")]
+    UnknownSender,
+}
+
+/// An asynchronous key generation result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A faulty node's behavior detected by `AsyncKeyGen`.
+#[derive(Clone, Debug, Fail, PartialEq)]
+pub enum FaultKind {
+    /// A `Broadcast` instance detected a fault.
+    #[fail(display = "Broadcast fault: {}", _0)]
+    Broadcast(broadcast::FaultKind),
+    /// A `BinaryAgreement` instance detected a fault.
+    #[fail(display = "BinaryAgreement fault: {}", _0)]
+    Agreement(binary_agreement::FaultKind),
+    /// The proposer broadcast a value that isn't a `Part`.
+    #[fail(display = "The proposer broadcast a value that isn't a Part")]
+    DeserializePart,
+    /// The proposer's `Part` is invalid.
+    #[fail(display = "Invalid Part: {}", _0)]
+    Part(PartFault),
+    /// The sender's `Ack` is invalid.
+    #[fail(display = "Invalid Ack: {}", _0)]
+    Ack(AckFault),
+}
+
+/// An `AsyncKeyGen` message.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Message<N> {
+    /// A message of the `Broadcast` instance that sends the given proposer's `Part`.
+    Part(N, broadcast::Message),
+    /// An `Ack` for a proposer's `Part`. It contains the proposer's index.
+    Ack(Ack),
+    /// A message of the `BinaryAgreement` instance that decides whether the given proposer's
+    /// `Part` is used.
+    Agreement(N, binary_agreement::Message),
+}
+
+/// An `AsyncKeyGen` step. It contains at most one output: the new keys.
+pub type Step<N> = crate::CpStep<AsyncKeyGen<N>>;
+
+/// The session ID of a proposer's `BinaryAgreement` instance.
+#[derive(Clone, Debug, Serialize)]
+struct AgreementId {
+    session_id: u64,
+    proposer_idx: u64,
+}
+
+impl fmt::Display for AgreementId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "key generation {}, proposer #{}",
+            self.session_id, self.proposer_idx
+        )
+    }
+}
+
+/// An asynchronous algorithm for dealerless distributed key generation.
+#[derive(Debug)]
+pub struct AsyncKeyGen<N> {
+    /// The node IDs and regular public keys. There are no threshold keys yet.
+    netinfo: Arc<NetworkInfo<N>>,
+    /// The key generation, which handles the `Part`s and `Ack`s in the order we receive them.
+    key_gen: SyncKeyGen<N>,
+    /// The `Broadcast` instances that send the proposers' `Part`s.
+    broadcasts: BTreeMap<N, Broadcast<N>>,
+    /// The `BinaryAgreement` instances that decide which `Part`s are used.
+    agreements: BTreeMap<N, BinaryAgreement<N, AgreementId>>,
+    /// The proposers whose `Part`s we have received, valid or not.
+    received_parts: BTreeSet<N>,
+    /// The `Ack`s whose `Part` we haven't received yet, by proposer index and sender.
+    pending_acks: BTreeMap<u64, BTreeMap<N, Ack>>,
+    /// The agreements' decisions, by proposer.
+    decisions: BTreeMap<N, bool>,
+    /// Whether we have already output the new keys.
+    terminated: bool,
+}
+
+impl<N: NodeIdT> ConsensusProtocol for AsyncKeyGen<N> {
+    type NodeId = N;
+    type Input = ();
+    type Output = NetworkInfo<N>;
+    type Message = Message<N>;
+    type Error = Error;
+    type FaultKind = FaultKind;
+
+    /// There is no input: Our `Part` is already sent on creation.
+    fn handle_input<R: Rng>(&mut self, _input: (), _rng: &mut R) -> Result<Step<N>> {
+        Ok(Step::default())
+    }
+
+    fn handle_message<R: Rng>(
+        &mut self,
+        sender_id: &N,
+        message: Message<N>,
+        rng: &mut R,
+    ) -> Result<Step<N>> {
+        self.handle_message(sender_id, message, rng)
+    }
+
+    fn terminated(&self) -> bool {
+        self.terminated
+    }
+
+    fn our_id(&self) -> &N {
+        self.netinfo.our_id()
+    }
+}
+
+impl<N: NodeIdT> AsyncKeyGen<N> {
+    /// Creates a new `AsyncKeyGen` instance, and returns it together with a step containing the
+    /// messages that send our `Part` to all nodes.
+    ///
+    /// The participants are the nodes in `pub_keys`. If we are not one of them, we only observe
+    /// the key generation, and will only output the public key set. If several key generations run
+    /// in the same network, they must use different session IDs.
+    pub fn new<S: IntoSigner, R: Rng>(
+        our_id: N,
+        sec_key: S,
+        pub_keys: BTreeMap<N, PublicKey>,
+        session_id: u64,
+        rng: &mut R,
+    ) -> Result<(Self, Step<N>)> {
+        // There are no threshold keys yet: The agreements use a local coin instead.
+        let sec_key = sec_key.into_signer();
+        let netinfo = Arc::new(NetworkInfo::without_threshold_keys(
+            our_id.clone(),
+            sec_key.clone(),
+            pub_keys.clone(),
+        ));
+        let threshold = netinfo.num_faulty();
+        let (key_gen, opt_part) =
+            SyncKeyGen::new(our_id, sec_key, pub_keys, threshold, rng).map_err(Error::NewKeyGen)?;
+        let ser_session_id = bincode::serialize(&session_id)
+            .map_err(|err| Error::SerializePart(format!("{:?}", err)))?;
+        let coin: Arc<dyn LocalCoin> = Arc::new(PrfCoin::new(ser_session_id));
+        let mut broadcasts = BTreeMap::new();
+        let mut agreements = BTreeMap::new();
+        for (idx, id) in netinfo.all_ids().enumerate() {
+            let bc = Broadcast::new(netinfo.clone(), id.clone()).map_err(Error::NewBroadcast)?;
+            broadcasts.insert(id.clone(), bc);
+            let ba_id = AgreementId {
+                session_id,
+                proposer_idx: idx as u64,
+            };
+            let mut ba =
+                BinaryAgreement::new(netinfo.clone(), ba_id).map_err(Error::NewAgreement)?;
+            ba.set_local_coin(coin.clone())
+                .map_err(Error::NewAgreement)?;
+            agreements.insert(id.clone(), ba);
+        }
+        let mut akg = AsyncKeyGen {
+            netinfo,
+            key_gen,
+            broadcasts,
+            agreements,
+            received_parts: BTreeSet::new(),
+            pending_acks: BTreeMap::new(),
+            decisions: BTreeMap::new(),
+            terminated: false,
+        };
+        let part = match opt_part {
+            Some(part) => part,
+            None => return Ok((akg, Step::default())), // We are only an observer.
+        };
+        let ser_part =
+            bincode::serialize(&part).map_err(|err| Error::SerializePart(format!("{:?}", err)))?;
+        let our_id = akg.our_id().clone();
+        let bc_step = akg
+            .broadcasts
+            .get_mut(&our_id)
+            .ok_or(Error::UnknownProposer)?
+            .broadcast(ser_part)
+            .map_err(Error::HandleBroadcast)?;
+        let step = akg.convert_broadcast_step(&our_id, bc_step, rng)?;
+        Ok((akg, step))
+    }
+
+    /// Sets the coin that the `BinaryAgreement` instances use. This must be called before handling
+    /// any messages, with equivalent coins on all nodes.
+    pub fn set_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        for ba in self.agreements.values_mut() {
+            ba.set_local_coin(coin.clone())
+                .map_err(Error::NewAgreement)?;
+        }
+        Ok(())
+    }
+
+    /// Handles a message received from `sender_id`.
+    ///
+    /// This must be called with every message we receive from another node.
+    pub fn handle_message<R: Rng>(
+        &mut self,
+        sender_id: &N,
+        message: Message<N>,
+        rng: &mut R,
+    ) -> Result<Step<N>> {
+        let step = match message {
+            Message::Part(proposer_id, bc_msg) => {
+                let bc_step = self
+                    .broadcasts
+                    .get_mut(&proposer_id)
+                    .ok_or(Error::UnknownProposer)?
+                    .handle_message(sender_id, bc_msg)
+                    .map_err(Error::HandleBroadcast)?;
+                self.convert_broadcast_step(&proposer_id, bc_step, rng)?
+            }
+            Message::Ack(ack) => self.handle_ack(sender_id, ack)?,
+            Message::Agreement(proposer_id, ba_msg) => {
+                let ba_step = self
+                    .agreements
+                    .get_mut(&proposer_id)
+                    .ok_or(Error::UnknownProposer)?
+                    .handle_message(sender_id, ba_msg)
+                    .map_err(Error::HandleAgreement)?;
+                self.convert_agreement_step(&proposer_id, ba_step)?
+            }
+        };
+        Ok(step.join(self.try_output()?))
+    }
+
+    /// Returns the proposers whose `Part`s have been accepted so far.
+    pub fn accepted_proposers(&self) -> impl Iterator<Item = &N> {
+        self.decisions
+            .iter()
+            .filter(|(_, decision)| **decision)
+            .map(|(id, _)| id)
+    }
+
+    /// Wraps the `Broadcast` step's messages and faults, and handles the `Part` if it was output.
+    fn convert_broadcast_step<R: Rng>(
+        &mut self,
+        proposer_id: &N,
+        bc_step: broadcast::Step<N>,
+        rng: &mut R,
+    ) -> Result<Step<N>> {
+        let mut step = Step::default();
+        let to_msg = |bc_msg| Message::Part(proposer_id.clone(), bc_msg);
+        let values = step.extend_with(bc_step, FaultKind::Broadcast, to_msg);
+        for value in values {
+            step.extend(self.handle_part(proposer_id, &value, rng)?);
+        }
+        Ok(step)
+    }
+
+    /// Wraps the `BinaryAgreement` step's messages and faults, and records the decision, if any.
+    fn convert_agreement_step(
+        &mut self,
+        proposer_id: &N,
+        ba_step: binary_agreement::Step<N>,
+    ) -> Result<Step<N>> {
+        let mut step = Step::default();
+        let to_msg = |ba_msg| Message::Agreement(proposer_id.clone(), ba_msg);
+        let decisions = step.extend_with(ba_step, FaultKind::Agreement, to_msg);
+        if let Some(decision) = decisions.into_iter().next() {
+            self.decisions.insert(proposer_id.clone(), decision);
+            step.extend(self.vote_false()?);
+        }
+        Ok(step)
+    }
+
+    /// Handles a `Part` that was delivered by the proposer's `Broadcast` instance, multicasts our
+    /// `Ack` if it is valid, and handles the `Ack`s that we received before the `Part`.
+    fn handle_part<R: Rng>(
+        &mut self,
+        proposer_id: &N,
+        ser_part: &[u8],
+        rng: &mut R,
+    ) -> Result<Step<N>> {
+        let part: Part = match bincode::deserialize(ser_part) {
+            Ok(part) => part,
+            Err(_) => return Ok(Fault::new(proposer_id.clone(), FaultKind::DeserializePart).into()),
+        };
+        let mut step = Step::default();
+        let outcome = self
+            .key_gen
+            .handle_part(proposer_id, part, rng)
+            .map_err(Error::KeyGen)?;
+        self.received_parts.insert(proposer_id.clone());
+        match outcome {
+            PartOutcome::Valid(Some(ack)) => {
+                step.messages
+                    .push(Target::All.message(Message::Ack(ack.clone())));
+                let our_id = self.our_id().clone();
+                step.extend(self.handle_ack(&our_id, ack)?);
+            }
+            PartOutcome::Valid(None) => (),
+            PartOutcome::Invalid(fault) => {
+                step.fault_log
+                    .append(proposer_id.clone(), FaultKind::Part(fault));
+            }
+        }
+        let proposer_idx = self.proposer_index(proposer_id)?;
+        let pending = self.pending_acks.remove(&proposer_idx).unwrap_or_default();
+        for (sender_id, ack) in pending {
+            step.extend(self.handle_ack(&sender_id, ack)?);
+        }
+        Ok(step)
+    }
+
+    /// Handles an `Ack`, or stores it if we haven't received the `Part` yet. Votes for accepting
+    /// the `Part` if it has enough `Ack`s now.
+    fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<Step<N>> {
+        if !self.netinfo.is_node_validator(sender_id) {
+            return Err(Error::UnknownSender);
+        }
+        let proposer_idx = ack.proposer_index();
+        let proposer_id = match self.netinfo.all_ids().nth(proposer_idx as usize) {
+            Some(proposer_id) => proposer_id.clone(),
+            None => {
+                let fault_kind = FaultKind::Ack(AckFault::MissingPart);
+                return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+            }
+        };
+        if !self.received_parts.contains(&proposer_id) {
+            let acks = self.pending_acks.entry(proposer_idx).or_default();
+            acks.entry(sender_id.clone()).or_insert(ack);
+            return Ok(Step::default());
+        }
+        let mut step = Step::default();
+        let outcome = self
+            .key_gen
+            .handle_ack(sender_id, ack)
+            .map_err(Error::KeyGen)?;
+        if let AckOutcome::Invalid(fault) = outcome {
+            step.fault_log
+                .append(sender_id.clone(), FaultKind::Ack(fault));
+        }
+        if self.key_gen.is_node_ready(&proposer_id) {
+            let ba_step = self
+                .agreements
+                .get_mut(&proposer_id)
+                .ok_or(Error::UnknownProposer)?
+                .propose(true)
+                .map_err(Error::HandleAgreement)?;
+            step.extend(self.convert_agreement_step(&proposer_id, ba_step)?);
+        }
+        Ok(step)
+    }
+
+    /// Votes for rejecting all remaining `Part`s if `N - f` have been accepted.
+    fn vote_false(&mut self) -> Result<Step<N>> {
+        let mut step = Step::default();
+        if self.accepted_proposers().count() < self.netinfo.num_correct() {
+            return Ok(step);
+        }
+        let undecided: Vec<N> = self
+            .agreements
+            .iter()
+            .filter(|(_, ba)| ba.can_propose())
+            .map(|(id, _)| id.clone())
+            .collect();
+        for proposer_id in undecided {
+            let ba_step = self
+                .agreements
+                .get_mut(&proposer_id)
+                .ok_or(Error::UnknownProposer)?
+                .propose(false)
+                .map_err(Error::HandleAgreement)?;
+            step.extend(self.convert_agreement_step(&proposer_id, ba_step)?);
+        }
+        Ok(step)
+    }
+
+    /// Outputs the new keys if all agreements have decided, and we have received the accepted
+    /// `Part`s and enough values for our key share.
+    fn try_output(&mut self) -> Result<Step<N>> {
+        if self.terminated || self.decisions.len() < self.netinfo.num_nodes() {
+            return Ok(Step::default());
+        }
+        let accepted: Vec<N> = self.accepted_proposers().cloned().collect();
+        let ready = |id: &N| self.received_parts.contains(id) && self.key_gen.has_values(id);
+        if !accepted.iter().all(ready) {
+            return Ok(Step::default()); // Still waiting for some values.
+        }
+        let (pk_set, opt_sk) = self
+            .key_gen
+            .generate_from(&accepted)
+            .map_err(Error::KeyGen)?;
+        self.terminated = true;
+        let share_signer = opt_sk.map(|sks| Arc::new(sks) as Arc<dyn ShareSigner>);
+        let netinfo = NetworkInfo::with_signers(
+            self.our_id().clone(),
+            share_signer,
+            pk_set,
+            self.netinfo.signer().clone(),
+            self.netinfo.public_key_map().clone(),
+        );
+        Ok(Step::default().with_output(netinfo))
+    }
+
+    /// Returns the index of the proposer.
+    fn proposer_index(&self, proposer_id: &N) -> Result<u64> {
+        let idx = self.netinfo.node_index(proposer_id);
+        idx.map(|idx| idx as u64).ok_or(Error::UnknownProposer)
+    }
+}
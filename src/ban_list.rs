@@ -0,0 +1,137 @@
+//! # Ban list
+//!
+//! A list of banned nodes, whose messages are dropped. Nodes can be banned by the operator, or
+//! automatically, once they have been reported for a configurable number of faults. The list is
+//! serializable, so that it can be persisted across restarts.
+//!
+//! A `SenderQueue` keeps a ban list and drops all messages from banned nodes before they reach the
+//! wrapped algorithm. Optionally, it also votes for the removal of a validator as soon as it is
+//! banned.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use failure::Fail;
+use serde::{Deserialize, Serialize};
+
+use crate::fault_log::FaultLog;
+use crate::NodeIdT;
+
+/// A list of banned nodes, and the number of faults reported for each node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BanList<N: Ord> {
+    /// The banned nodes.
+    banned: BTreeSet<N>,
+    /// The number of faults reported for each node that is not banned.
+    fault_counts: BTreeMap<N, u64>,
+    /// The number of faults after which a node is banned automatically, if any.
+    max_faults: Option<u64>,
+    /// Whether to vote for the removal of banned validators.
+    vote_to_remove: bool,
+}
+
+impl<N: Ord> Default for BanList<N> {
+    fn default() -> Self {
+        BanList {
+            banned: BTreeSet::new(),
+            fault_counts: BTreeMap::new(),
+            max_faults: None,
+            vote_to_remove: false,
+        }
+    }
+}
+
+impl<N: NodeIdT> BanList<N> {
+    /// Returns a new, empty ban list that doesn't ban nodes automatically.
+    pub fn new() -> Self {
+        BanList::default()
+    }
+
+    /// Sets the number of reported faults after which a node is banned automatically. With
+    /// `None`, nodes are only banned explicitly.
+    pub fn set_max_faults(&mut self, max_faults: Option<u64>) {
+        self.max_faults = max_faults;
+    }
+
+    /// Sets whether to vote for the removal of validators once they are banned. This only applies
+    /// to algorithms that support validator set changes.
+    pub fn set_vote_to_remove(&mut self, vote_to_remove: bool) {
+        self.vote_to_remove = vote_to_remove;
+    }
+
+    /// Returns whether to vote for the removal of validators once they are banned.
+    pub fn vote_to_remove(&self) -> bool {
+        self.vote_to_remove
+    }
+
+    /// Bans the given node. Returns `false` if it was already banned.
+    pub fn ban(&mut self, id: N) -> bool {
+        self.fault_counts.remove(&id);
+        self.banned.insert(id)
+    }
+
+    /// Lifts the ban on the given node, and resets its fault count. Returns `false` if it was not
+    /// banned.
+    pub fn unban(&mut self, id: &N) -> bool {
+        self.fault_counts.remove(id);
+        self.banned.remove(id)
+    }
+
+    /// Returns `true` if the given node is banned.
+    pub fn is_banned(&self, id: &N) -> bool {
+        self.banned.contains(id)
+    }
+
+    /// Returns an iterator over the banned nodes.
+    pub fn banned(&self) -> impl Iterator<Item = &N> {
+        self.banned.iter()
+    }
+
+    /// Returns the number of faults reported for the given node since it was last unbanned.
+    pub fn fault_count(&self, id: &N) -> u64 {
+        self.fault_counts.get(id).cloned().unwrap_or(0)
+    }
+
+    /// Counts the faults in the log, and returns the nodes that got banned because they reached
+    /// the maximum number of faults.
+    pub fn record_faults<F: Fail>(&mut self, fault_log: &FaultLog<N, F>) -> Vec<N> {
+        let mut newly_banned = Vec::new();
+        for fault in &fault_log.0 {
+            if self.banned.contains(&fault.node_id) {
+                continue;
+            }
+            let count = self.fault_counts.entry(fault.node_id.clone()).or_insert(0);
+            *count += 1;
+            if let Some(max_faults) = self.max_faults {
+                if *count >= max_faults {
+                    self.ban(fault.node_id.clone());
+                    newly_banned.push(fault.node_id.clone());
+                }
+            }
+        }
+        newly_banned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BanList;
+    use crate::fault_log::FaultLog;
+    use crate::honey_badger::FaultKind;
+
+    #[test]
+    fn test_ban_after_max_faults() {
+        let mut ban_list = BanList::new();
+        ban_list.set_max_faults(Some(2));
+        let fault_log = FaultLog::init(1, FaultKind::UnexpectedDecryptionShare);
+        assert!(ban_list.record_faults(&fault_log).is_empty());
+        assert_eq!(1, ban_list.fault_count(&1));
+        assert_eq!(vec![1], ban_list.record_faults(&fault_log));
+        assert!(ban_list.is_banned(&1));
+        // Further faults by a banned node are ignored.
+        assert!(ban_list.record_faults(&fault_log).is_empty());
+        assert!(ban_list.unban(&1));
+        assert_eq!(0, ban_list.fault_count(&1));
+        assert!(ban_list.ban(2));
+        assert_eq!(vec![&2], ban_list.banned().collect::<Vec<_>>());
+    }
+}
@@ -1,17 +1,27 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, result};
 
 use crate::crypto::SignatureShare;
 use bincode;
+use derivative::Derivative;
 use log::debug;
 use rand::Rng;
 
 use super::bool_multimap::BoolMultimap;
 use super::bool_set::{self, BoolSet};
+use super::certificate::{self, DecisionCertificate};
+use super::coin::LocalCoin;
 use super::sbv_broadcast::{self, Message as SbvMessage, SbvBroadcast};
-use super::{Error, FaultKind, Message, MessageContent, Result, Step};
+use super::{
+    CoinRecord, CoinStats, Error, FaultKind, Justification, Message, MessageContent, Result, Stall,
+    Step, TrueFalseCoin, Variant, EPOCH_BUDGET,
+};
+use crate::fair_queue::FairQueue;
 use crate::fault_log::Fault;
+use crate::header::Algorithm;
+use crate::instrument::{self, Instrument, NoInstrument, Timing, Transition};
 use crate::threshold_sign::{self, Message as TsMessage, ThresholdSign};
 use crate::{ConsensusProtocol, NetworkInfo, NodeIdT, SessionIdT, Target};
 
@@ -104,6 +114,8 @@ impl ReceivedMessages {
                     return Some(FaultKind::AgreementEpoch);
                 }
             }
+            // Decision shares are handled regardless of their epoch, and never queued.
+            MessageContent::DecisionShare(_) => (),
         }
         None
     }
@@ -138,7 +150,8 @@ impl ReceivedMessages {
 }
 
 /// Binary Agreement instance
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct BinaryAgreement<N, S> {
     /// Shared network information.
     netinfo: Arc<NetworkInfo<N>>,
@@ -148,6 +161,11 @@ pub struct BinaryAgreement<N, S> {
     epoch: u64,
     /// Maximum number of future epochs for which incoming messages are accepted.
     max_future_epochs: u64,
+    /// The round structure of the protocol.
+    #[derivative(Debug = "ignore")]
+    variant: Arc<dyn Variant>,
+    /// The number of epochs after which the peers that are holding up the decision are reported.
+    max_epochs: Option<u64>,
     /// This epoch's Synchronized Binary Value Broadcast instance.
     sbv_broadcast: SbvBroadcast<N>,
     /// Received `Conf` messages. Reset on every epoch update.
@@ -163,12 +181,38 @@ pub struct BinaryAgreement<N, S> {
     /// ever there at all. While the output value will still be required in a later epoch to decide
     /// the termination state.
     decision: Option<bool>,
+    /// The evidence that caused the decision, if we have decided.
+    justification: Option<Justification>,
+    /// The threshold signature of the decision, if certificates are enabled.
+    certifier: Option<Box<ThresholdSign<N>>>,
+    /// The certificate of the decision, once enough signature shares have been received.
+    certificate: Option<DecisionCertificate>,
     /// A cache for messages for future epochs that cannot be handled yet.
     incoming_queue: BTreeMap<u64, BTreeMap<N, ReceivedMessages>>,
     /// The values we found in the first _N - f_ `Aux` messages that were in `bin_values`.
     conf_values: Option<BoolSet>,
     /// The state of this epoch's coin.
     coin_state: CoinState<N>,
+    /// Our precomputed coin shares for future epochs.
+    coin_shares: BTreeMap<u64, SignatureShare>,
+    /// Whether the `ThresholdSign` instances use batch verification.
+    batch_verification: bool,
+    /// The coin to use instead of the threshold signature, if any.
+    local_coin: Option<Arc<dyn LocalCoin>>,
+    /// The total cost of the messages received from each peer in the current epoch. Reset on
+    /// every epoch update.
+    spent_budget: BTreeMap<N, u64>,
+    /// The peers whose signature share of the decision we received. Decision shares are not
+    /// subject to the epoch budget, but a correct node only sends one per instance.
+    decision_share_senders: BTreeSet<N>,
+    /// The outcomes of the threshold coin so far, and the parities of the shares they were
+    /// combined from.
+    coin_record: CoinRecord<N>,
+    /// The time the instance was created.
+    started: Instant,
+    /// The receiver of instrumentation events.
+    #[derivative(Debug = "ignore")]
+    instrument: Arc<dyn Instrument<N>>,
 }
 
 impl<N: NodeIdT, S: SessionIdT> ConsensusProtocol for BinaryAgreement<N, S> {
@@ -195,7 +239,7 @@ impl<N: NodeIdT, S: SessionIdT> ConsensusProtocol for BinaryAgreement<N, S> {
 
     /// Whether the algorithm has terminated.
     fn terminated(&self) -> bool {
-        self.decision.is_some()
+        self.decision.is_some() && (self.certifier.is_none() || self.certificate.is_some())
     }
 
     fn our_id(&self) -> &Self::NodeId {
@@ -207,19 +251,33 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
     /// Creates a new `BinaryAgreement` instance with the given session identifier, to prevent
     /// replaying messages in other instances.
     pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: S) -> Result<Self> {
+        netinfo.validate().map_err(Error::InvalidNetworkInfo)?;
         Ok(BinaryAgreement {
             netinfo: netinfo.clone(),
             session_id,
             epoch: 0,
             max_future_epochs: 1000,
+            variant: Arc::new(TrueFalseCoin),
+            max_epochs: None,
             sbv_broadcast: SbvBroadcast::new(netinfo),
             received_conf: BTreeMap::new(),
             received_term: BoolMultimap::default(),
             estimated: None,
             decision: None,
+            justification: None,
+            certifier: None,
+            certificate: None,
             incoming_queue: BTreeMap::new(),
             conf_values: None,
             coin_state: CoinState::Decided(true),
+            coin_shares: BTreeMap::new(),
+            batch_verification: false,
+            local_coin: None,
+            spent_budget: BTreeMap::new(),
+            decision_share_senders: BTreeSet::new(),
+            coin_record: CoinRecord::default(),
+            started: Instant::now(),
+            instrument: Arc::new(NoInstrument),
         })
     }
 
@@ -236,14 +294,180 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         // Set the initial estimated value to the input value.
         self.estimated = Some(input);
         let sbvb_step = self.sbv_broadcast.send_bval(input)?;
-        self.handle_sbvb_step(sbvb_step)
+        let step = self.handle_sbvb_step(sbvb_step)?;
+        self.report_faults(&step);
+        Ok(step)
+    }
+
+    /// Sets the receiver of instrumentation events.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        self.instrument = instrument;
+    }
+
+    /// Sets the round structure of the protocol. This must be called before handling any messages,
+    /// with equivalent variants on all nodes.
+    pub fn set_variant(&mut self, variant: Arc<dyn Variant>) -> Result<()> {
+        self.variant = variant;
+        self.reset_coin()
+    }
+
+    /// Sets the number of epochs after which a stalled instance is reported: If it still hasn't
+    /// decided at the end of epoch `max_epochs - 1`, and at the end of every later epoch, the
+    /// instrument's `agreement_stalled` is called with the validators whose `Aux` we didn't receive
+    /// in it, and that epoch's `bin_values`. The instance keeps running.
+    pub fn set_max_epochs(&mut self, max_epochs: u64) {
+        self.max_epochs = Some(max_epochs);
+    }
+
+    /// Sets a coin that is computed locally, to use instead of the threshold signature. This must
+    /// be called before handling any messages, with equivalent coins on all nodes.
+    pub fn set_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        self.local_coin = Some(coin);
+        self.reset_coin()
+    }
+
+    /// Enables or disables batch verification of the coin's and the certificate's signature shares:
+    /// see `ThresholdSign::set_batch_verification`.
+    pub fn set_batch_verification(&mut self, batch_verification: bool) {
+        self.batch_verification = batch_verification;
+        if let CoinState::InProgress(ref mut ts) = self.coin_state {
+            ts.set_batch_verification(batch_verification);
+        }
+        if let Some(ref mut ts) = self.certifier {
+            ts.set_batch_verification(batch_verification);
+        }
+    }
+
+    /// Enables or disables decision certificates. This must be called before handling any
+    /// messages, with the same value on all nodes.
+    ///
+    /// If enabled, the validators threshold-sign the decision, and the instance only terminates
+    /// once `certificate` returns the resulting `DecisionCertificate`. This takes one more message
+    /// round after the decision, whose signature shares are not subject to the epoch budget.
+    pub fn set_certify(&mut self, certify: bool) {
+        self.certifier = if certify {
+            let mut ts = ThresholdSign::new(self.netinfo.clone());
+            ts.set_batch_verification(self.batch_verification);
+            Some(Box::new(ts))
+        } else {
+            None
+        };
+    }
+
+    /// Returns the certificate of the decision, or `None` if certificates are disabled or not
+    /// enough signature shares have been received yet.
+    ///
+    /// The certificate can be verified by anyone who knows the session ID and the validators'
+    /// public key set.
+    pub fn certificate(&self) -> Option<&DecisionCertificate> {
+        self.certificate.as_ref()
+    }
+
+    /// Returns the evidence that caused the decision, or `None` if we haven't decided yet.
+    ///
+    /// This can be used to analyze in which epochs and by which rule instances terminate.
+    pub fn justification(&self) -> Option<Justification> {
+        self.justification
+    }
+
+    /// Computes our coin shares for the next `count` epochs in which the coin is flipped, so that
+    /// they don't need to be computed when the coin is invoked. This is an expensive operation,
+    /// meant to be called while the node is idle. It does nothing if we are an observer or use a
+    /// local coin.
+    pub fn precompute_coin_shares(&mut self, count: usize) -> Result<()> {
+        let sks = match self.netinfo.share_signer() {
+            Some(sks) if self.local_coin.is_none() => sks,
+            _ => return Ok(()),
+        };
+        // The current epoch's coin, if any, has already been created.
+        let variant = self.variant.clone();
+        let flipped = (self.epoch + 1..).filter(|epoch| variant.fixed_coin(*epoch).is_none());
+        for epoch in flipped.take(count) {
+            if !self.coin_shares.contains_key(&epoch) {
+                let share = sks.sign_share(&self.coin_doc(epoch)?);
+                self.coin_shares.insert(epoch, share);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the current epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Returns the current epoch's estimate, or `None` if we haven't proposed yet.
+    pub fn estimate(&self) -> Option<bool> {
+        self.estimated
+    }
+
+    /// Returns the values for which we received `BVal`s from _2 f + 1_ validators in the current
+    /// epoch.
+    pub fn bin_values(&self) -> BoolSet {
+        self.sbv_broadcast.bin_values()
+    }
+
+    /// Returns the values of the `BVal`s `sender_id` sent us in the current epoch. A `Term` counts
+    /// as a `BVal` in every epoch.
+    pub fn received_bval(&self, sender_id: &N) -> BoolSet {
+        self.sbv_broadcast.received_bval(sender_id)
+    }
+
+    /// Returns the values of the `Aux`s `sender_id` sent us in the current epoch. A `Term` counts
+    /// as an `Aux` in every epoch.
+    pub fn received_aux(&self, sender_id: &N) -> BoolSet {
+        self.sbv_broadcast.received_aux(sender_id)
+    }
+
+    /// Returns the values of the `Conf` `sender_id` sent us in the current epoch, if any.
+    pub fn received_conf(&self, sender_id: &N) -> Option<BoolSet> {
+        self.received_conf.get(sender_id).cloned()
+    }
+
+    /// Returns the current epoch's coin value, or `None` if it hasn't been revealed yet. In epochs
+    /// in which the variant doesn't flip the coin, e.g. 0 or 1 modulo 3 by default, it is fixed.
+    pub fn coin_value(&self) -> Option<bool> {
+        self.coin_state.value()
+    }
+
+    /// Returns the information about the node IDs in the network, and the cryptographic keys.
+    pub fn netinfo(&self) -> &Arc<NetworkInfo<N>> {
+        &self.netinfo
+    }
+
+    /// Returns the outcomes of the threshold coin in this instance so far.
+    pub fn coin_stats(&self) -> CoinStats {
+        self.coin_record.outcomes
+    }
+
+    /// Returns the outcomes of the threshold coin in this instance so far, and the parities of the
+    /// signature shares each validator contributed to them.
+    pub fn coin_record(&self) -> &CoinRecord<N> {
+        &self.coin_record
     }
 
     /// Handles a message received from `sender_id`.
     ///
     /// This must be called with every message we receive from another node.
     pub fn handle_message(&mut self, sender_id: &N, msg: Message) -> Result<Step<N>> {
+        let step = self.handle_epoch_message(sender_id, msg)?;
+        self.report_faults(&step);
+        Ok(step)
+    }
+
+    /// Whether we can still input a value. It is not an error to input if this returns `false`,
+    /// but it will have no effect on the outcome.
+    pub fn can_propose(&self) -> bool {
+        self.epoch == 0 && self.estimated.is_none()
+    }
+
+    /// Handles a message, or queues it if it belongs to a later epoch.
+    fn handle_epoch_message(&mut self, sender_id: &N, msg: Message) -> Result<Step<N>> {
         let Message { epoch, content } = msg;
+        if let MessageContent::DecisionShare(share) = content {
+            // Decision shares are only sent after deciding, and are valid in every epoch.
+            return self.handle_decision_share(sender_id, *share);
+        }
         if self.decision.is_some() || (epoch < self.epoch && content.can_expire()) {
             // Message is obsolete: We are already in a later epoch or terminated.
             Ok(Step::default())
@@ -263,15 +487,26 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
                 .map_or_else(Step::default, |fault| {
                     Fault::new(sender_id.clone(), fault).into()
                 }))
+        } else if !self.charge_budget(sender_id, &content) {
+            // The sender already sent more than a correct node would in this epoch.
+            Ok(Fault::new(sender_id.clone(), FaultKind::EpochBudgetExceeded).into())
         } else {
             self.handle_message_content(sender_id, content)
         }
     }
 
-    /// Whether we can still input a value. It is not an error to input if this returns `false`,
-    /// but it will have no effect on the outcome.
-    pub fn can_propose(&self) -> bool {
-        self.epoch == 0 && self.estimated.is_none()
+    /// Reports the faults in the step to the instrument.
+    fn report_faults(&self, step: &Step<N>) {
+        let algorithm = Algorithm::BinaryAgreement;
+        instrument::report_faults(&*self.instrument, algorithm, &step.fault_log);
+    }
+
+    /// Adds the cost of the message to the sender's spent budget for the current epoch. Returns
+    /// `false` if that exceeds the budget, in which case the message must not be handled.
+    fn charge_budget(&mut self, sender_id: &N, content: &MessageContent) -> bool {
+        let spent = self.spent_budget.entry(sender_id.clone()).or_insert(0);
+        *spent = spent.saturating_add(content.cost());
+        *spent <= EPOCH_BUDGET
     }
 
     /// Dispatches the message content to the corresponding handling method.
@@ -285,6 +520,7 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
             MessageContent::Conf(v) => self.handle_conf(sender_id, v),
             MessageContent::Term(v) => self.handle_term(sender_id, v),
             MessageContent::Coin(msg) => self.handle_coin(sender_id, *msg),
+            MessageContent::DecisionShare(msg) => self.handle_decision_share(sender_id, *msg),
         }
     }
 
@@ -311,7 +547,7 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
             return Ok(step); // The `Conf` round has already started.
         }
         if let Some(aux_vals) = output.into_iter().next() {
-            // Execute the Coin schedule `false, true, get_coin(), false, true, get_coin(), ...`
+            // Execute the variant's coin schedule, e.g. `true, false, get_coin(), true, ...`
             match self.coin_state {
                 CoinState::Decided(_) => {
                     self.conf_values = Some(aux_vals);
@@ -342,7 +578,11 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         if self.decision.is_some() {
             Ok(Step::default())
         } else if self.received_term[b].len() > self.netinfo.num_faulty() {
-            Ok(self.decide(b))
+            let justification = Justification::Term {
+                epoch: self.epoch,
+                count_term: self.received_term[b].len(),
+            };
+            self.decide(b, justification)
         } else {
             // Otherwise handle the `Term` as a `BVal`, `Aux` and `Conf`.
             let mut sbvb_step = self.sbv_broadcast.handle_bval(sender_id, b)?;
@@ -364,6 +604,44 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         self.on_coin_step(ts_step)
     }
 
+    /// Handles a signature share of the decision. Once there are enough, outputs the certificate.
+    fn handle_decision_share(
+        &mut self,
+        sender_id: &N,
+        msg: threshold_sign::Message,
+    ) -> Result<Step<N>> {
+        if self.certificate.is_some() {
+            return Ok(Step::default()); // We already have a certificate.
+        }
+        if self.certifier.is_some() && !self.decision_share_senders.insert(sender_id.clone()) {
+            let fault_kind = FaultKind::MultipleDecisionShares;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        let ts_step = match self.certifier {
+            None => return Ok(Step::default()), // Certificates are disabled.
+            Some(ref mut ts) => ts
+                .handle_message(sender_id, msg)
+                .map_err(Error::HandleThresholdSign)?,
+        };
+        Ok(self.on_certifier_step(ts_step))
+    }
+
+    /// Handles a step returned from the decision's `ThresholdSign`.
+    fn on_certifier_step(&mut self, ts_step: threshold_sign::Step<N>) -> Step<N> {
+        let mut step = Step::default();
+        let epoch = self.epoch;
+        let to_msg = |c_msg| MessageContent::DecisionShare(Box::new(c_msg)).with_epoch(epoch);
+        let ts_output = step.extend_with(ts_step, FaultKind::DecisionShareFault, to_msg);
+        if let (Some(signature), Some(decision)) = (ts_output.into_iter().next(), self.decision) {
+            debug!("{}: decision certified", self);
+            self.certificate = Some(DecisionCertificate {
+                decision,
+                signature,
+            });
+        }
+        step
+    }
+
     /// Multicasts a `Conf(values)` message, and handles it.
     fn send_conf(&mut self, values: BoolSet) -> Result<Step<N>> {
         if self.conf_values.is_some() {
@@ -400,7 +678,14 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         let to_msg = |c_msg| MessageContent::Coin(Box::new(c_msg)).with_epoch(epoch);
         let ts_output = step.extend_with(ts_step, FaultKind::CoinFault, to_msg);
         if let Some(sig) = ts_output.into_iter().next() {
+            if let CoinState::InProgress(ref ts) = self.coin_state {
+                for (id, share) in ts.received_shares() {
+                    let stats = self.coin_record.shares.entry(id.clone()).or_default();
+                    stats.record(share.0.parity());
+                }
+            }
             // Take the parity of the signature as the coin value.
+            self.coin_record.outcomes.record(sig.parity());
             self.coin_state = sig.parity().into();
             step.extend(self.try_update_epoch()?);
         }
@@ -428,7 +713,14 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         };
 
         if Some(coin) == def_bin_value {
-            Ok(self.decide(coin))
+            let justification = Justification::Coin {
+                epoch: self.epoch,
+                coin,
+                count_bval: self.sbv_broadcast.received_bval_count(coin),
+                count_aux: self.sbv_broadcast.received_aux_count(coin),
+                count_conf: self.count_conf(),
+            };
+            self.decide(coin, justification)
         } else {
             self.update_epoch(def_bin_value.unwrap_or(coin))
         }
@@ -436,35 +728,73 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
 
     /// Creates the initial coin state for the current epoch, i.e. sets it to the predetermined
     /// value, or initializes a `ThresholdSign` instance.
-    fn coin_state(&self) -> Result<CoinState<N>> {
-        match self.epoch % 3 {
-            0 => Ok(CoinState::Decided(true)),
-            1 => Ok(CoinState::Decided(false)),
-            _ => {
-                let coin_id = bincode::serialize(&(&self.session_id, self.epoch))?;
+    fn coin_state(&mut self) -> Result<CoinState<N>> {
+        match self.variant.fixed_coin(self.epoch) {
+            Some(value) => Ok(CoinState::Decided(value)),
+            None => {
+                let coin_id = self.coin_doc(self.epoch)?;
+                if let Some(ref coin) = self.local_coin {
+                    let value = coin.value(&coin_id);
+                    self.coin_record.outcomes.record(value);
+                    return Ok(CoinState::Decided(value));
+                }
                 let mut ts = ThresholdSign::new(self.netinfo.clone());
                 ts.set_document(coin_id).map_err(Error::InvokeCoin)?;
+                ts.set_batch_verification(self.batch_verification);
+                if let Some(share) = self.coin_shares.remove(&self.epoch) {
+                    ts.set_our_share(share);
+                }
                 Ok(CoinState::InProgress(Box::new(ts)))
             }
         }
     }
 
-    /// Decides on a value and broadcasts a `Term` message with that value.
-    fn decide(&mut self, b: bool) -> Step<N> {
+    /// Recreates the first epoch's coin, which depends on the variant and the local coin.
+    fn reset_coin(&mut self) -> Result<()> {
+        self.coin_record = CoinRecord::default();
+        self.coin_state = self.coin_state()?;
+        Ok(())
+    }
+
+    /// Returns the document that is threshold-signed to flip the coin in the given epoch.
+    fn coin_doc(&self, epoch: u64) -> Result<Vec<u8>> {
+        // The key set hash makes sure that the coin is unique to the current validators.
+        let key_set_hash = self.netinfo.key_set_hash();
+        Ok(bincode::serialize(&(
+            &self.session_id,
+            epoch,
+            key_set_hash,
+        ))?)
+    }
+
+    /// Decides on a value and broadcasts a `Term` message with that value. If certificates are
+    /// enabled, also multicasts our signature share of the decision.
+    fn decide(&mut self, b: bool, justification: Justification) -> Result<Step<N>> {
         if self.decision.is_some() {
-            return Step::default();
+            return Ok(Step::default());
         }
         // Output the Binary Agreement value.
         let mut step = Step::default();
         step.output.push(b);
         // Latch the decided state.
         self.decision = Some(b);
-        debug!("{}: decision: {}", self, b);
+        self.justification = Some(justification);
+        let duration = self.started.elapsed();
+        self.instrument.timing(Timing::Agreement, duration);
+        debug!("{}: decision: {}, {:?}", self, b, justification);
         if self.netinfo.is_validator() {
             let msg = MessageContent::Term(b).with_epoch(self.epoch + 1);
             step.messages.push(Target::All.message(msg));
         }
-        step
+        let ts_step = match self.certifier {
+            None => return Ok(step),
+            Some(ref mut ts) => {
+                let doc = certificate::signed_doc(&self.session_id, b)?;
+                ts.set_document(doc).map_err(Error::InvokeCertifier)?;
+                ts.sign().map_err(Error::InvokeCertifier)?
+            }
+        };
+        Ok(step.join(self.on_certifier_step(ts_step)))
     }
 
     /// Checks whether the _N - f_ `Conf` messages have arrived, and if so, activates the coin.
@@ -472,8 +802,11 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         if self.conf_values.is_none() || self.count_conf() < self.netinfo.num_correct() {
             return Ok(Step::default());
         }
+        self.invoke_coin()
+    }
 
-        // Invoke the coin.
+    /// Sends our coin share, and updates the epoch or decides if the coin value is known.
+    fn invoke_coin(&mut self) -> Result<Step<N>> {
         let ts_step = match self.coin_state {
             CoinState::Decided(_) => return Ok(Step::default()), // Coin has already decided.
             CoinState::InProgress(ref mut ts) => ts.sign().map_err(Error::InvokeCoin)?,
@@ -481,6 +814,36 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         Ok(self.on_coin_step(ts_step)?.join(self.try_update_epoch()?))
     }
 
+    /// If the current epoch is at or beyond the limit, reports the validators whose `Aux` is
+    /// missing to the instrument, to diagnose the stall.
+    fn check_epoch_limit(&self) {
+        if self
+            .max_epochs
+            .filter(|max| self.epoch + 1 >= *max)
+            .is_none()
+        {
+            return;
+        }
+        let bin_values = self.sbv_broadcast.bin_values();
+        debug!(
+            "{}: epoch limit exceeded, bin_values: {:?}",
+            self, bin_values
+        );
+        let missing_aux = self
+            .netinfo
+            .all_ids()
+            .filter(|id| *id != self.our_id())
+            .filter(|id| self.sbv_broadcast.received_aux(id) == bool_set::NONE)
+            .cloned()
+            .collect();
+        self.instrument.agreement_stalled(&Stall {
+            session_id: self.session_id.to_string(),
+            epoch: self.epoch,
+            bin_values,
+            missing_aux,
+        });
+    }
+
     /// Counts the number of received `Conf` messages with values in `bin_values`.
     fn count_conf(&self) -> usize {
         let is_bin_val = |conf: &&BoolSet| conf.is_subset(self.sbv_broadcast.bin_values());
@@ -489,13 +852,18 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
 
     /// Increments the epoch, sets the new estimate and handles queued messages.
     fn update_epoch(&mut self, b: bool) -> Result<Step<N>> {
+        self.check_epoch_limit();
         self.sbv_broadcast.clear(&self.received_term);
         self.received_conf.clear();
         for (v, id) in &self.received_term {
             self.received_conf.insert(id.clone(), BoolSet::from(v));
         }
         self.conf_values = None;
+        self.spent_budget.clear();
         self.epoch += 1;
+        let transition = Transition::Epoch(self.epoch);
+        self.instrument
+            .state_transition(Algorithm::BinaryAgreement, transition);
         self.coin_state = self.coin_state()?;
         debug!(
             "{}: epoch started, {} terminated",
@@ -507,13 +875,20 @@ impl<N: NodeIdT, S: SessionIdT> BinaryAgreement<N, S> {
         let sbvb_step = self.sbv_broadcast.send_bval(b)?;
         let mut step = self.handle_sbvb_step(sbvb_step)?;
         let epoch = self.epoch;
+        // Handle the queued messages round-robin, so that no peer's backlog delays the others'.
+        let mut queue = FairQueue::default();
         let epoch_state = self.incoming_queue.remove(&epoch).into_iter().flatten();
-        for (sender_id, received) in epoch_state {
-            for m in received.messages() {
-                step.extend(self.handle_message_content(&sender_id, m)?);
-                if self.decision.is_some() || self.epoch > epoch {
-                    return Ok(step);
-                }
+        queue.extend(epoch_state.map(|(id, received)| (id, received.messages())));
+        debug!("{}: handling {} queued messages", self, queue.len());
+        while let Some((sender_id, content)) = queue.pop() {
+            if !self.charge_budget(&sender_id, &content) {
+                step.fault_log
+                    .append(sender_id, FaultKind::EpochBudgetExceeded);
+                continue;
+            }
+            step.extend(self.handle_message_content(&sender_id, content)?);
+            if self.decision.is_some() || self.epoch > epoch {
+                return Ok(step);
             }
         }
         Ok(step)
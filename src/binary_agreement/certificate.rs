@@ -0,0 +1,36 @@
+//! Threshold-signed certificates for `BinaryAgreement` decisions.
+//!
+//! If certificates are enabled, every validator signs the session ID and the decided value with
+//! its secret key share once it has decided, and multicasts the signature share. Once a node has
+//! _f + 1_ valid shares, it combines them into a `DecisionCertificate`: a signature by the
+//! validators' public key set, which proves to anyone who knows that key set that the instance
+//! decided that value, without having to trust a single validator.
+
+use serde::{Deserialize, Serialize};
+
+use super::Result;
+use crate::crypto::{PublicKeySet, Signature};
+
+/// A threshold signature of a `BinaryAgreement` decision.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecisionCertificate {
+    /// The decided value.
+    pub decision: bool,
+    /// The signature of the session ID and the decision, by the validators' public key set.
+    pub signature: Signature,
+}
+
+impl DecisionCertificate {
+    /// Returns `true` if the certificate is signed by the given public key set, for the instance
+    /// with the given session ID.
+    pub fn verify<S: Serialize>(&self, session_id: &S, pub_key_set: &PublicKeySet) -> Result<bool> {
+        let doc = signed_doc(session_id, self.decision)?;
+        Ok(pub_key_set.public_key().verify(&self.signature, doc))
+    }
+}
+
+/// Returns the document the validators sign for a decision. It is shorter than the coin's
+/// document, which also contains an epoch and the key set hash, so the two can't coincide.
+pub(super) fn signed_doc<S: Serialize>(session_id: &S, decision: bool) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&(session_id, decision))?)
+}
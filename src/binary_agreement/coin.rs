@@ -0,0 +1,52 @@
+//! Alternative sources of the coin values.
+//!
+//! By default, the coin in every third epoch is a threshold signature: No coalition of _f_ or
+//! fewer validators can predict or bias it, but it costs a message round and a signature share
+//! per validator. A `LocalCoin` computes the value without any messages instead. That is useful
+//! in tests, which can use a deterministic coin, and in small deployments in which all validators
+//! trust each other. With a local coin, anyone who can evaluate it can predict every coin value,
+//! and thus stall the network if they also control the scheduling of its messages.
+
+use std::fmt;
+
+use tiny_keccak::sha3_256;
+
+/// A coin whose values are computed locally, without any messages.
+///
+/// All nodes must use the same coin, and it must return the same value on all nodes.
+pub trait LocalCoin: fmt::Debug + Send + Sync {
+    /// Returns the coin value for the given coin ID, which contains the instance's session ID, the
+    /// epoch and the validators' key set hash.
+    fn value(&self, coin_id: &[u8]) -> bool;
+}
+
+/// A coin that is the parity of the SHA3-256 hash of a shared key and the coin ID.
+///
+/// The coin is unpredictable to anyone who doesn't know the key: All validators must be given the
+/// same key over a secure channel.
+#[derive(Clone)]
+pub struct PrfCoin {
+    /// The shared key.
+    key: Vec<u8>,
+}
+
+impl PrfCoin {
+    /// Creates a new coin with the given shared key.
+    pub fn new(key: Vec<u8>) -> Self {
+        PrfCoin { key }
+    }
+}
+
+impl fmt::Debug for PrfCoin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrfCoin {{ .. }}")
+    }
+}
+
+impl LocalCoin for PrfCoin {
+    fn value(&self, coin_id: &[u8]) -> bool {
+        let mut bytes = self.key.clone();
+        bytes.extend_from_slice(coin_id);
+        sha3_256(&bytes)[0] & 1 == 1
+    }
+}
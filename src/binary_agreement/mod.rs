@@ -45,14 +45,15 @@
 //!   * If both values are candidates, we set `e = s` and proceed to the next epoch.
 //!
 //! In epochs that are 0 modulo 3, the value `s` is `true`. In 1 modulo 3, it is `false`. In the
-//! case 2 modulo 3, we flip a coin to determine a pseudorandom `s`.
+//! case 2 modulo 3, we flip a coin to determine a pseudorandom `s`. Other schedules of fixed and
+//! flipped coins can be configured with `set_variant`, e.g. a `CoinSchedule`.
 //!
 //! An adversary that knows each coin value, controls a few validators and controls network
 //! scheduling can delay the delivery of `Aux` and `BVal` messages to influence which candidate
 //! values the nodes will end up with. In some circumstances that allows them to stall the network.
 //! This is even true if the coin is flipped too early: the adversary must not learn about the coin
-//! value early enough to delay enough `Aux` messages. That's why in the third case, the value `s`
-//! is determined as follows:
+//! value early enough to delay enough `Aux` messages. That's why in the epochs in which the coin
+//! is flipped, the value `s` is determined as follows:
 //!
 //! * We multicast a `Conf` message containing our candidate values.
 //!
@@ -62,11 +63,34 @@
 //!
 //! * After _f + 1_ nodes have sent us their coin shares, we receive the coin output and assign it
 //! to `s`.
+//!
+//! Instead of the threshold signature, the coin can also be a `LocalCoin` that every node computes
+//! without exchanging messages, e.g. a `PrfCoin` with a key the validators share: see
+//! `set_local_coin`, or the `HoneyBadger` builders' `agreement_local_coin` option. In that case the
+//! `Conf` round is skipped, too.
+//!
+//! The `Conf` round is not part of the original protocol by Mostéfaoui, Moumen and Raynal, which
+//! triggers the coin as soon as it has the candidate values, and is not live if the adversary
+//! controls the network scheduling. All variants use the `Conf` round.
+//!
+//! ## Decision certificates
+//!
+//! The output alone can't convince anyone outside the network that the instance really decided
+//! that value. With `set_certify`, every validator additionally threshold-signs the session ID and
+//! its decision, and after _f + 1_ shares the instance obtains a `DecisionCertificate` that can be
+//! verified against the validators' public key set. `Subset::set_agreement_certify` enables them
+//! for all of a `Subset`'s instances, and the `HoneyBadger` builders' `agreement_certificates`
+//! option includes them in each batch.
 
 mod binary_agreement;
 mod bool_multimap;
 pub mod bool_set;
+mod certificate;
+mod coin;
 mod sbv_broadcast;
+mod variant;
+
+use std::collections::BTreeMap;
 
 use bincode;
 use failure::Fail;
@@ -77,9 +101,148 @@ use serde::{Deserialize, Serialize};
 
 use self::bool_set::BoolSet;
 use crate::threshold_sign;
+use crate::NetworkInfoError;
 
 pub use self::binary_agreement::BinaryAgreement;
+pub use self::certificate::DecisionCertificate;
+pub use self::coin::{LocalCoin, PrfCoin};
 pub use self::sbv_broadcast::Message as SbvMessage;
+pub use self::variant::{CoinSchedule, TrueFalseCoin, Variant};
+
+/// A `BinaryAgreement` instance that hasn't decided within the maximum number of epochs: see
+/// `BinaryAgreement::set_max_epochs`.
+///
+/// This is a diagnostic, not a fault: The validators whose `Aux` is missing may just be slow, and
+/// the instance keeps running.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stall<N> {
+    /// The instance's session ID, formatted with `Display`.
+    pub session_id: String,
+    /// The last epoch within the limit.
+    pub epoch: u64,
+    /// The values for which we received _2 f + 1_ `BVal`s in that epoch.
+    pub bin_values: BoolSet,
+    /// The validators whose `Aux` we didn't receive in that epoch.
+    pub missing_aux: Vec<N>,
+}
+
+/// The evidence that caused a `BinaryAgreement` instance to decide.
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Justification {
+    /// More than _f_ nodes sent us `Term` messages with the decided value.
+    Term {
+        /// The epoch in which we decided.
+        epoch: u64,
+        /// The number of `Term` messages with the decided value.
+        count_term: usize,
+    },
+    /// All values in _N - f_ `Conf` messages were the decided value, and so was the coin.
+    Coin {
+        /// The epoch in which we decided.
+        epoch: u64,
+        /// The value of the coin, i.e. the decided value.
+        coin: bool,
+        /// The number of `BVal` messages with the decided value in that epoch.
+        count_bval: usize,
+        /// The number of `Aux` messages with the decided value in that epoch.
+        count_aux: usize,
+        /// The number of `Conf` messages with values in `bin_values` in that epoch.
+        count_conf: usize,
+    },
+}
+
+impl Justification {
+    /// Returns the epoch in which the instance decided.
+    pub fn epoch(&self) -> u64 {
+        match *self {
+            Justification::Term { epoch, .. } | Justification::Coin { epoch, .. } => epoch,
+        }
+    }
+}
+
+/// The outcomes of the threshold coin in one or more `BinaryAgreement` epochs.
+///
+/// Epochs in which the coin has a fixed value are not counted. A correct threshold coin is
+/// unbiased, so a large `deviation` indicates a compromised key set or a broken coin derivation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CoinStats {
+    /// The number of flips with the value `true`.
+    pub heads: u64,
+    /// The number of flips with the value `false`.
+    pub tails: u64,
+}
+
+impl CoinStats {
+    /// Records an outcome of the coin.
+    pub fn record(&mut self, value: bool) {
+        if value {
+            self.heads += 1;
+        } else {
+            self.tails += 1;
+        }
+    }
+
+    /// Adds the outcomes recorded in `other`.
+    pub fn merge(&mut self, other: &CoinStats) {
+        self.heads += other.heads;
+        self.tails += other.tails;
+    }
+
+    /// Returns the total number of flips.
+    pub fn total(&self) -> u64 {
+        self.heads + self.tails
+    }
+
+    /// Returns the difference between the numbers of heads and tails, in standard deviations of a
+    /// fair coin, or `0.0` if there were no flips.
+    pub fn deviation(&self) -> f64 {
+        if self.total() == 0 {
+            return 0.0;
+        }
+        let diff = (self.heads as f64 - self.tails as f64).abs();
+        diff / (self.total() as f64).sqrt()
+    }
+
+    /// Returns `true` if there were at least `min_flips`, and their `deviation` is at least
+    /// `max_deviation`.
+    pub fn is_biased(&self, min_flips: u64, max_deviation: f64) -> bool {
+        self.total() >= min_flips && self.deviation() >= max_deviation
+    }
+}
+
+/// The outcomes of the threshold coin, together with the parities of the signature shares each
+/// validator contributed to it.
+///
+/// The coin itself is a unique threshold signature, so no single validator can bias it. But a
+/// validator whose key share is compromised or was dealt maliciously can produce shares that are
+/// valid but whose parities are improbable for a random signature: Their `shares` entry tells
+/// which validator it is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoinRecord<N> {
+    /// The coin values.
+    pub outcomes: CoinStats,
+    /// The parities of the signature shares the coin values were combined from, by sender.
+    pub shares: BTreeMap<N, CoinStats>,
+}
+
+impl<N> Default for CoinRecord<N> {
+    fn default() -> Self {
+        CoinRecord {
+            outcomes: CoinStats::default(),
+            shares: BTreeMap::new(),
+        }
+    }
+}
+
+impl<N: Ord + Clone> CoinRecord<N> {
+    /// Adds the outcomes and share parities recorded in `other`.
+    pub fn merge(&mut self, other: &CoinRecord<N>) {
+        self.outcomes.merge(&other.outcomes);
+        for (id, stats) in &other.shares {
+            self.shares.entry(id.clone()).or_default().merge(stats);
+        }
+    }
+}
 
 /// A `BinaryAgreement` error.
 #[derive(Clone, Eq, PartialEq, Debug, Fail)]
@@ -90,10 +253,16 @@ pub enum Error {
     /// Error invoking the common coin.
     #[fail(display = "Error invoking the common coin: {}", _0)]
     InvokeCoin(threshold_sign::Error),
+    /// Error signing the decision.
+    #[fail(display = "Error signing the decision: {}", _0)]
+    InvokeCertifier(threshold_sign::Error),
     // String because `io` and `bincode` errors lack `Eq` and `Clone`.
     /// Error serializing the session ID for the common coin.
     #[fail(display = "Error serializing session ID for coin: {}", _0)]
     Serialize(String),
+    /// The `NetworkInfo`'s keys are inconsistent.
+    #[fail(display = "Invalid network info: {}", _0)]
+    InvalidNetworkInfo(NetworkInfoError),
 }
 
 impl From<bincode::Error> for Error {
@@ -126,7 +295,24 @@ pub enum FaultKind {
     /// `BinaryAgreement` received a Coin Fault.
     #[fail(display = "`BinaryAgreement` received a Coin Fault.")]
     CoinFault(threshold_sign::FaultKind),
+    /// `BinaryAgreement` received a faulty signature share of the decision.
+    #[fail(display = "`BinaryAgreement` received a faulty decision share.")]
+    DecisionShareFault(threshold_sign::FaultKind),
+    /// `BinaryAgreement` received more messages in an epoch than a correct node would send.
+    #[fail(display = "`BinaryAgreement` received more messages in an epoch than allowed.")]
+    EpochBudgetExceeded,
+    /// `BinaryAgreement` received multiple signature shares of the decision from the same peer.
+    #[fail(display = "`BinaryAgreement` received multiple decision shares.")]
+    MultipleDecisionShares,
 }
+/// The cost of handling a message that doesn't need any cryptographic verification.
+const MESSAGE_COST: u64 = 1;
+/// The cost of handling a `Coin` message, whose signature share needs to be verified.
+const COIN_MESSAGE_COST: u64 = 100;
+/// The maximum total cost of the messages a correct node sends in a single epoch: two `BVal`s,
+/// two `Aux`s, a `Conf`, a `Term` and a `Coin` message.
+const EPOCH_BUDGET: u64 = 6 * MESSAGE_COST + COIN_MESSAGE_COST;
+
 /// A `BinaryAgreement` step, containing at most one output.
 pub type Step<N> = crate::Step<Message, bool, N, FaultKind>;
 
@@ -141,6 +327,8 @@ pub enum MessageContent {
     Term(bool),
     /// `ThresholdSign` message used for the common coin,
     Coin(Box<threshold_sign::Message>),
+    /// `ThresholdSign` message with a signature share of the decision, for its certificate.
+    DecisionShare(Box<threshold_sign::Message>),
 }
 
 impl MessageContent {
@@ -152,10 +340,18 @@ impl MessageContent {
         }
     }
 
+    /// Returns the cost of handling this message, for enforcing the per-epoch budget of each peer.
+    fn cost(&self) -> u64 {
+        match *self {
+            MessageContent::Coin(_) | MessageContent::DecisionShare(_) => COIN_MESSAGE_COST,
+            _ => MESSAGE_COST,
+        }
+    }
+
     /// Returns `true` if this message can be ignored if its epoch has already passed.
     pub fn can_expire(&self) -> bool {
         match *self {
-            MessageContent::Term(_) => false,
+            MessageContent::Term(_) | MessageContent::DecisionShare(_) => false,
             _ => true,
         }
     }
@@ -175,14 +371,62 @@ pub struct Message {
 // with no replacement in sight.
 impl Distribution<MessageContent> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> MessageContent {
-        let message_type = *["sbvb", "conf", "term", "coin"].choose(rng).unwrap();
+        let message_type = *["sbvb", "conf", "term", "coin", "decision_share"]
+            .choose(rng)
+            .unwrap();
 
         match message_type {
             "sbvb" => MessageContent::SbvBroadcast(rng.gen()),
             "conf" => MessageContent::Conf(rng.gen()),
             "term" => MessageContent::Term(rng.gen()),
             "coin" => MessageContent::Coin(Box::new(rng.gen())),
+            "decision_share" => MessageContent::DecisionShare(Box::new(rng.gen())),
             _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{CoinRecord, CoinStats};
+
+    #[test]
+    fn test_coin_stats() {
+        let mut stats = CoinStats::default();
+        assert_eq!(0.0, stats.deviation());
+        for i in 0..100 {
+            stats.record(i % 2 == 0);
+        }
+        assert_eq!(0.0, stats.deviation());
+        assert!(!stats.is_biased(100, 6.0));
+
+        // 100 more heads: The difference is 100, with 200 flips.
+        let mut heads = CoinStats::default();
+        (0..100).for_each(|_| heads.record(true));
+        assert!(!heads.is_biased(101, 6.0));
+        stats.merge(&heads);
+        assert_eq!(
+            CoinStats {
+                heads: 150,
+                tails: 50
+            },
+            stats
+        );
+        assert!((stats.deviation() - 100.0 / 200f64.sqrt()).abs() < 1e-9);
+        assert!(stats.is_biased(100, 6.0));
+    }
+
+    #[test]
+    fn test_coin_record_merge() {
+        let mut record = CoinRecord::default();
+        let mut other = CoinRecord::default();
+        other.outcomes.record(true);
+        other.shares.entry(0).or_default().record(true);
+        other.shares.entry(1).or_default().record(false);
+        record.merge(&other);
+        record.merge(&other);
+        assert_eq!(CoinStats { heads: 2, tails: 0 }, record.outcomes);
+        assert_eq!(CoinStats { heads: 2, tails: 0 }, record.shares[&0]);
+        assert_eq!(CoinStats { heads: 0, tails: 2 }, record.shares[&1]);
+    }
+}
@@ -98,6 +98,26 @@ impl<N: NodeIdT> SbvBroadcast<N> {
         self.bin_values
     }
 
+    /// Returns the number of nodes that sent us `BVal(b)` in this epoch.
+    pub fn received_bval_count(&self, b: bool) -> usize {
+        self.received_bval[b].len()
+    }
+
+    /// Returns the number of nodes that sent us `Aux(b)` in this epoch.
+    pub fn received_aux_count(&self, b: bool) -> usize {
+        self.received_aux[b].len()
+    }
+
+    /// Returns the values of the `BVal`s `sender_id` sent us in this epoch.
+    pub fn received_bval(&self, sender_id: &N) -> BoolSet {
+        received_values(&self.received_bval, sender_id)
+    }
+
+    /// Returns the values of the `Aux`s `sender_id` sent us in this epoch.
+    pub fn received_aux(&self, sender_id: &N) -> BoolSet {
+        received_values(&self.received_aux, sender_id)
+    }
+
     /// Multicasts a `BVal(b)` message, and handles it.
     pub fn send_bval(&mut self, b: bool) -> Result<Step<N>> {
         // Record the value `b` as sent. If it was already there, don't send it again.
@@ -185,3 +205,14 @@ impl<N: NodeIdT> SbvBroadcast<N> {
         (count, values)
     }
 }
+
+/// Returns the set of values `b` for which `sender_id` is in `map[b]`.
+fn received_values<N: NodeIdT>(map: &BoolMultimap<N>, sender_id: &N) -> BoolSet {
+    let mut values = bool_set::NONE;
+    for &b in &[false, true] {
+        if map[b].contains(sender_id) {
+            values.insert(b);
+        }
+    }
+    values
+}
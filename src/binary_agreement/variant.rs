@@ -0,0 +1,115 @@
+//! Round structures of the Binary Agreement protocol.
+//!
+//! A `Variant` determines in which epochs the coin is flipped, and which fixed value it has in the
+//! others. In every epoch in which it is flipped, the coin is only invoked after the `Conf` round,
+//! so that the adversary can't learn its value before the candidate values are fixed. Every variant
+//! that flips the coin in infinitely many epochs is therefore live, even if the adversary controls
+//! the network scheduling. Fixed coin values don't reveal anything the adversary doesn't know
+//! already, but they let the instance terminate early if all correct nodes agree.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// The round structure of the Binary Agreement protocol. All nodes must use the same variant.
+pub trait Variant: fmt::Debug + Send + Sync {
+    /// Returns the coin value in the given epoch if it is fixed, or `None` if the coin is flipped.
+    ///
+    /// The coin must be flipped in infinitely many epochs, otherwise the instance may never
+    /// terminate.
+    fn fixed_coin(&self, epoch: u64) -> Option<bool>;
+}
+
+/// The coin is `true` in epochs that are 0 modulo 3, `false` in those that are 1 modulo 3, and
+/// flipped in the others. This is the default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TrueFalseCoin;
+
+impl Variant for TrueFalseCoin {
+    fn fixed_coin(&self, epoch: u64) -> Option<bool> {
+        match epoch % 3 {
+            0 => Some(true),
+            1 => Some(false),
+            _ => None,
+        }
+    }
+}
+
+/// A fixed coin schedule that repeats every `len` epochs: In epoch `e`, the coin has the value at
+/// index `e % len`, or is flipped if that is `None`.
+///
+/// For example `[None]` flips the coin in every epoch: That costs a coin round in every epoch, but
+/// no epoch is spent on a fixed value that disagrees with the candidate values.
+///
+/// Deserialization fails if the schedule never flips the coin, just like `new`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "Vec<Option<bool>>")]
+pub struct CoinSchedule(Vec<Option<bool>>);
+
+impl CoinSchedule {
+    /// Creates a new coin schedule, or returns `None` if the schedule never flips the coin.
+    pub fn new(schedule: Vec<Option<bool>>) -> Option<Self> {
+        if schedule.contains(&None) {
+            Some(CoinSchedule(schedule))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the schedule.
+    pub fn schedule(&self) -> &[Option<bool>] {
+        &self.0
+    }
+}
+
+impl TryFrom<Vec<Option<bool>>> for CoinSchedule {
+    type Error = &'static str;
+
+    fn try_from(schedule: Vec<Option<bool>>) -> Result<Self, Self::Error> {
+        CoinSchedule::new(schedule).ok_or("the coin schedule never flips the coin")
+    }
+}
+
+impl Variant for CoinSchedule {
+    fn fixed_coin(&self, epoch: u64) -> Option<bool> {
+        if self.0.is_empty() {
+            return None;
+        }
+        self.0[(epoch % self.0.len() as u64) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coin_schedule() {
+        assert_eq!(None, CoinSchedule::new(vec![Some(true), Some(false)]));
+        let schedule = CoinSchedule::new(vec![Some(false), None]).expect("schedule");
+        let coins: Vec<_> = (0..5).map(|epoch| schedule.fixed_coin(epoch)).collect();
+        assert_eq!(
+            vec![Some(false), None, Some(false), None, Some(false)],
+            coins
+        );
+        let coins: Vec<_> = (0..4)
+            .map(|epoch| TrueFalseCoin.fixed_coin(epoch))
+            .collect();
+        assert_eq!(vec![Some(true), Some(false), None, Some(true)], coins);
+    }
+
+    #[test]
+    fn test_coin_schedule_deserialize() {
+        let schedule = CoinSchedule::new(vec![Some(true), None]).expect("schedule");
+        let bytes = bincode::serialize(&schedule).expect("serialize");
+        let deserialized: CoinSchedule = bincode::deserialize(&bytes).expect("deserialize");
+        assert_eq!(schedule, deserialized);
+
+        // Schedules that never flip the coin are rejected, like in `new`.
+        for invalid in vec![vec![], vec![Some(true), Some(false)]] {
+            let bytes = bincode::serialize(&invalid).expect("serialize");
+            assert!(bincode::deserialize::<CoinSchedule>(&bytes).is_err());
+        }
+    }
+}
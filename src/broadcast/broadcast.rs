@@ -1,43 +1,111 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, result};
 
 use byteorder::{BigEndian, ByteOrder};
+use derivative::Derivative;
 use hex_fmt::{HexFmt, HexList};
 use log::{debug, warn};
 use rand::Rng;
-use reed_solomon_erasure as rse;
-use reed_solomon_erasure::ReedSolomon;
 
-use super::merkle::{Digest, MerkleTree, Proof};
+use super::chunk::{Chunk, ChunkBuffer, ChunkError};
+use super::coding::{ErasureCoding, ReedSolomonCoding};
+use super::merkle::{Digest, MerkleHasher, MerkleTree, Proof, Sha3Hasher};
 use super::message::HexProof;
 use super::{Error, FaultKind, Message, Result};
 use crate::fault_log::Fault;
+use crate::header::Algorithm;
+use crate::instrument::{self, Instrument, NoInstrument, Timing};
 use crate::{ConsensusProtocol, NetworkInfo, NodeIdT, Target};
 
-type RseResult<T> = result::Result<T, rse::Error>;
+/// Which validators we send our full `Echo` to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EchoStrategy {
+    /// A full `Echo` to every node that hasn't sent `CanDecode`. This is the default.
+    All,
+    /// A full `Echo` only to the validators following us in the order of IDs, and `EchoHash` to
+    /// the others. If all nodes are correct, every validator receives as many full `Echo`s as there
+    /// are data shards, plus the given redundancy. A node that doesn't have enough of them when it
+    /// needs to decode requests the missing ones with `RequestEcho`.
+    Partial(usize),
+}
 
 /// Broadcast algorithm instance.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct Broadcast<N> {
     /// Shared network data.
     netinfo: Arc<NetworkInfo<N>>,
     /// The ID of the sending node.
     proposer_id: N,
-    /// The Reed-Solomon erasure coding configuration.
-    coding: Coding,
+    /// The erasure coding scheme.
+    coding: Box<dyn ErasureCoding>,
+    /// The hash function of the Merkle trees.
+    hasher: Arc<dyn MerkleHasher>,
     /// If we are the proposer: whether we have already sent the `Value` messages with the shards.
     value_sent: bool,
     /// Whether we have already multicast `Echo`.
     echo_sent: bool,
     /// Whether we have already multicast `Ready`.
     ready_sent: bool,
+    /// Whether we have already multicast `CanDecode`.
+    can_decode_sent: bool,
     /// Whether we have already output a value.
     decided: bool,
-    /// The proofs we have received via `Echo` messages, by sender ID.
-    echos: BTreeMap<N, Proof<Vec<u8>>>,
+    /// The proofs and root hashes we have received via `Echo` and `EchoHash` messages, by sender
+    /// ID.
+    echos: BTreeMap<N, EchoContent>,
     /// The root hashes we received via `Ready` messages, by sender ID.
     readys: BTreeMap<N, Vec<u8>>,
+    /// The IDs of the nodes that sent us a `CanDecode` message, by root hash.
+    can_decodes: BTreeMap<Digest, BTreeSet<N>>,
+    /// Which validators we send our full `Echo` to.
+    echo_strategy: EchoStrategy,
+    /// The IDs of the nodes we have sent `RequestEcho` to.
+    echo_requests_sent: BTreeSet<N>,
+    /// The IDs of the nodes that have sent us `RequestEcho`.
+    echo_requests_received: BTreeSet<N>,
+    /// The maximum size of the value in bytes, if any.
+    max_value_size: Option<usize>,
+    /// The maximum size of a shard in a single message. Larger shards are split into chunks.
+    chunk_size: Option<usize>,
+    /// The chunks of the `Value` we have received so far from the proposer.
+    value_chunks: Option<Box<ChunkBuffer>>,
+    /// The chunks of `Echo`s we have received so far, by sender ID.
+    echo_chunks: BTreeMap<N, ChunkBuffer>,
+    /// The time the instance was created.
+    started: Instant,
+    /// The receiver of instrumentation events.
+    #[derivative(Debug = "ignore")]
+    instrument: Arc<dyn Instrument<N>>,
+}
+
+/// The content of an `Echo` or `EchoHash` message we received.
+#[derive(Debug)]
+enum EchoContent {
+    /// A full `Echo`, containing a proof with a shard of the value.
+    Full(Proof<Vec<u8>>),
+    /// An `EchoHash`, containing only the root hash.
+    Hash(Digest),
+}
+
+impl EchoContent {
+    /// Returns the root hash of the value.
+    fn hash(&self) -> &Digest {
+        match self {
+            EchoContent::Full(p) => p.root_hash(),
+            EchoContent::Hash(hash) => hash,
+        }
+    }
+
+    /// Returns the proof, if this is a full `Echo`.
+    fn proof(&self) -> Option<&Proof<Vec<u8>>> {
+        match self {
+            EchoContent::Full(p) => Some(p),
+            EchoContent::Hash(_) => None,
+        }
+    }
 }
 
 /// A `Broadcast` step, containing at most one output.
@@ -77,24 +145,108 @@ impl<N: NodeIdT> Broadcast<N> {
     /// Creates a new broadcast instance to be used by node `our_id` which expects a value proposal
     /// from node `proposer_id`.
     pub fn new(netinfo: Arc<NetworkInfo<N>>, proposer_id: N) -> Result<Self> {
+        netinfo.validate().map_err(Error::InvalidNetworkInfo)?;
         let parity_shard_num = 2 * netinfo.num_faulty();
         let data_shard_num = netinfo.num_nodes() - parity_shard_num;
-        let coding =
-            Coding::new(data_shard_num, parity_shard_num).map_err(|_| Error::InvalidNodeCount)?;
+        let coding = ReedSolomonCoding::new(data_shard_num, parity_shard_num)
+            .map_err(|_| Error::InvalidNodeCount)?;
 
         Ok(Broadcast {
             netinfo,
             proposer_id,
-            coding,
+            coding: Box::new(coding),
+            hasher: Arc::new(Sha3Hasher),
             value_sent: false,
             echo_sent: false,
             ready_sent: false,
+            can_decode_sent: false,
             decided: false,
             echos: BTreeMap::new(),
             readys: BTreeMap::new(),
+            can_decodes: BTreeMap::new(),
+            echo_strategy: EchoStrategy::All,
+            echo_requests_sent: BTreeSet::new(),
+            echo_requests_received: BTreeSet::new(),
+            max_value_size: None,
+            chunk_size: None,
+            value_chunks: None,
+            echo_chunks: BTreeMap::new(),
+            started: Instant::now(),
+            instrument: Arc::new(NoInstrument),
         })
     }
 
+    /// Sets the maximum size of the value in bytes. Shards of larger values are rejected before
+    /// their proofs are even verified, and their senders reported as `FaultKind::ValueTooLarge`.
+    /// If the decoded value still exceeds the limit, the proposer is reported instead, so that no
+    /// correct node outputs such a value.
+    ///
+    /// All nodes must use the same maximum size.
+    pub fn set_max_value_size(&mut self, max_value_size: usize) {
+        self.max_value_size = Some(max_value_size);
+    }
+
+    /// Sets the maximum size of a shard in a single message, in bytes. `Value`s and `Echo`s with
+    /// larger shards are split into `ValueChunk` and `EchoChunk` messages of at most that size.
+    /// A chunk size of zero is treated as one.
+    ///
+    /// All nodes must use the same chunk size: Larger chunks are rejected as faulty.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = Some(chunk_size.max(1));
+    }
+
+    /// Sets which validators we send our full `Echo` to. With `EchoStrategy::Partial`, the other
+    /// validators only receive an `EchoHash`, which reduces the bandwidth of the `Echo` phase
+    /// from _N²_ to about _N (N - 2 f)_ shards.
+    ///
+    /// This must be called before the `Value` is handled.
+    pub fn set_echo_strategy(&mut self, echo_strategy: EchoStrategy) {
+        self.echo_strategy = echo_strategy;
+    }
+
+    /// Sets the erasure coding scheme. It must have _N_ shards in total, and between _1_ and
+    /// _N - 2 f_ data shards.
+    ///
+    /// This must be called before any value or message is handled, and all nodes must use the same
+    /// scheme.
+    pub fn set_coding<E>(&mut self, coding: E) -> Result<()>
+    where
+        E: ErasureCoding + 'static,
+    {
+        let data_shard_num = coding.data_shard_count();
+        let shard_num = data_shard_num + coding.parity_shard_count();
+        if data_shard_num == 0
+            || data_shard_num > self.netinfo.num_correct() - self.netinfo.num_faulty()
+            || shard_num != self.netinfo.num_nodes()
+        {
+            return Err(Error::InvalidCoding);
+        }
+        self.coding = Box::new(coding);
+        Ok(())
+    }
+
+    /// Sets the number of Reed-Solomon data shards, between _1_ and the default _N - 2 f_. The
+    /// remaining shards are parity shards. See `set_coding`.
+    pub fn set_data_shard_count(&mut self, data_shard_num: usize) -> Result<()> {
+        let parity_shard_num = self.netinfo.num_nodes().saturating_sub(data_shard_num);
+        let coding =
+            ReedSolomonCoding::new(data_shard_num, parity_shard_num).map_err(Error::Coding)?;
+        self.set_coding(coding)
+    }
+
+    /// Sets the hash function of the Merkle trees, instead of the default SHA3-256.
+    ///
+    /// This must be called before any value or message is handled, and all nodes must use the same
+    /// hash function.
+    pub fn set_merkle_hasher(&mut self, hasher: Arc<dyn MerkleHasher>) {
+        self.hasher = hasher;
+    }
+
+    /// Sets the receiver of instrumentation events.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        self.instrument = instrument;
+    }
+
     /// Initiates the broadcast. This must only be called in the proposer node.
     pub fn broadcast(&mut self, input: Vec<u8>) -> Result<Step<N>> {
         if *self.our_id() != self.proposer_id {
@@ -103,13 +255,20 @@ impl<N: NodeIdT> Broadcast<N> {
         if self.value_sent {
             return Err(Error::MultipleInputs);
         }
+        if let Some(max) = self.max_value_size {
+            if input.len() > max {
+                return Err(Error::ValueTooLarge);
+            }
+        }
         self.value_sent = true;
         // Split the value into chunks/shards, encode them with erasure codes.
         // Assemble a Merkle tree from data and parity shards. Take all proofs
         // from this tree and send them, each to its own node.
         let (proof, step) = self.send_shards(input)?;
         let our_id = &self.our_id().clone();
-        Ok(step.join(self.handle_value(our_id, proof)?))
+        let step = step.join(self.handle_value(our_id, proof)?);
+        instrument::report_faults(&*self.instrument, Algorithm::Broadcast, &step.fault_log);
+        Ok(step)
     }
 
     /// Handles a message received from `sender_id`.
@@ -119,11 +278,18 @@ impl<N: NodeIdT> Broadcast<N> {
         if !self.netinfo.is_node_validator(sender_id) {
             return Err(Error::UnknownSender);
         }
-        match message {
+        let step = match message {
             Message::Value(p) => self.handle_value(sender_id, p),
             Message::Echo(p) => self.handle_echo(sender_id, p),
             Message::Ready(ref hash) => self.handle_ready(sender_id, hash),
-        }
+            Message::CanDecode(ref hash) => self.handle_can_decode(sender_id, hash),
+            Message::EchoHash(ref hash) => self.handle_echo_hash(sender_id, hash),
+            Message::RequestEcho(ref hash) => self.handle_request_echo(sender_id, hash),
+            Message::ValueChunk(chunk) => self.handle_value_chunk(sender_id, chunk),
+            Message::EchoChunk(chunk) => self.handle_echo_chunk(sender_id, chunk),
+        }?;
+        instrument::report_faults(&*self.instrument, Algorithm::Broadcast, &step.fault_log);
+        Ok(step)
     }
 
     /// Returns the proposer's node ID.
@@ -159,7 +325,7 @@ impl<N: NodeIdT> Broadcast<N> {
 
         // Construct the parity chunks/shards. This only fails if a shard is empty or the shards
         // have different sizes. Our shards all have size `shard_len`, which is at least 1.
-        self.coding.encode(&mut shards).expect("wrong shard size");
+        self.coding.encode(&mut shards).map_err(Error::Coding)?;
 
         debug!(
             "{}: Value: {} bytes, {} per shard. Shards: {:0.10}",
@@ -170,7 +336,8 @@ impl<N: NodeIdT> Broadcast<N> {
         );
 
         // Create a Merkle tree from the shards.
-        let mtree = MerkleTree::from_vec(shards.into_iter().map(|shard| shard.to_vec()).collect());
+        let values = shards.into_iter().map(|shard| shard.to_vec()).collect();
+        let mtree = MerkleTree::from_vec(values, &*self.hasher);
 
         // Default result in case of `proof` error.
         let mut result = Err(Error::ProofConstructionFailed);
@@ -178,15 +345,15 @@ impl<N: NodeIdT> Broadcast<N> {
 
         let mut step = Step::default();
         // Send each proof to a node.
-        for (index, id) in self.netinfo.all_ids().enumerate() {
-            let proof = mtree.proof(index).ok_or(Error::ProofConstructionFailed)?;
+        for (proof, id) in mtree.proofs().into_iter().zip(self.netinfo.all_ids()) {
             if *id == *self.our_id() {
                 // The proof is addressed to this node.
                 result = Ok(proof);
             } else {
                 // Rest of the proofs are sent to remote nodes.
-                let msg = Target::Node(id.clone()).message(Message::Value(proof));
-                step.messages.push(msg);
+                for msg in self.shard_messages(proof, Message::Value, Message::ValueChunk) {
+                    step.messages.push(Target::Node(id.clone()).message(msg));
+                }
             }
         }
 
@@ -201,7 +368,7 @@ impl<N: NodeIdT> Broadcast<N> {
             return Ok(Fault::new(sender_id.clone(), fault_kind).into());
         }
         if self.echo_sent {
-            if self.echos.get(self.our_id()) == Some(&p) {
+            if self.echos.get(self.our_id()).and_then(EchoContent::proof) == Some(&p) {
                 warn!(
                     "Node {:?} received Value({:?}) multiple times from {:?}.",
                     self.our_id(),
@@ -214,7 +381,11 @@ impl<N: NodeIdT> Broadcast<N> {
             }
         }
 
-        // If the proof is invalid, log the faulty node behavior and ignore.
+        // If the shard is too large or the proof is invalid, log the faulty node behavior and
+        // ignore. The size is checked first, so that we don't need to hash a huge shard.
+        if self.is_shard_too_large(&p) {
+            return Ok(Fault::new(sender_id.clone(), FaultKind::ValueTooLarge).into());
+        }
         if !self.validate_proof(&p, &self.our_id()) {
             return Ok(Fault::new(sender_id.clone(), FaultKind::InvalidProof).into());
         }
@@ -223,11 +394,89 @@ impl<N: NodeIdT> Broadcast<N> {
         self.send_echo(p)
     }
 
+    /// Handles a part of a `Value` message. Once all parts have arrived, the `Value` is handled.
+    fn handle_value_chunk(&mut self, sender_id: &N, chunk: Chunk) -> Result<Step<N>> {
+        if *sender_id != self.proposer_id {
+            let fault_kind = FaultKind::ReceivedValueFromNonProposer;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        if self.echo_sent {
+            return Ok(Step::default()); // We already handled a `Value`.
+        }
+        let (max_chunk_size, max_shard_len) = (self.chunk_size, self.max_shard_len());
+        let hasher = self.hasher.clone();
+        let result = match self.value_chunks {
+            Some(ref mut buffer) => buffer.insert(chunk, max_chunk_size, max_shard_len, &*hasher),
+            None => ChunkBuffer::new(&chunk).and_then(|mut buffer| {
+                let result = buffer.insert(chunk, max_chunk_size, max_shard_len, &*hasher);
+                self.value_chunks = Some(Box::new(buffer));
+                result
+            }),
+        };
+        match result {
+            Ok(None) => Ok(Step::default()),
+            Ok(Some(p)) => {
+                self.value_chunks = None;
+                self.handle_value(sender_id, p)
+            }
+            Err(err) => Ok(Fault::new(sender_id.clone(), chunk_fault_kind(err)).into()),
+        }
+    }
+
+    /// Handles a part of an `Echo` message. Once all parts have arrived, the `Echo` is handled.
+    fn handle_echo_chunk(&mut self, sender_id: &N, chunk: Chunk) -> Result<Step<N>> {
+        if self
+            .echos
+            .get(sender_id)
+            .and_then(EchoContent::proof)
+            .is_some()
+        {
+            return Ok(Step::default()); // We already handled an `Echo`.
+        }
+        let (max_chunk_size, max_shard_len) = (self.chunk_size, self.max_shard_len());
+        let hasher = self.hasher.clone();
+        let result = match self.echo_chunks.get_mut(sender_id) {
+            Some(buffer) => buffer.insert(chunk, max_chunk_size, max_shard_len, &*hasher),
+            None => ChunkBuffer::new(&chunk).and_then(|mut buffer| {
+                let result = buffer.insert(chunk, max_chunk_size, max_shard_len, &*hasher);
+                self.echo_chunks.insert(sender_id.clone(), buffer);
+                result
+            }),
+        };
+        match result {
+            Ok(None) => Ok(Step::default()),
+            Ok(Some(p)) => {
+                self.echo_chunks.remove(sender_id);
+                self.handle_echo(sender_id, p)
+            }
+            Err(err) => Ok(Fault::new(sender_id.clone(), chunk_fault_kind(err)).into()),
+        }
+    }
+
+    /// Returns the messages that contain the proof: a single one created by `msg`, or, if the
+    /// shard is larger than the chunk size, one created by `chunk_msg` for each chunk.
+    fn shard_messages<F, G>(&self, p: Proof<Vec<u8>>, msg: F, chunk_msg: G) -> Vec<Message>
+    where
+        F: Fn(Proof<Vec<u8>>) -> Message,
+        G: Fn(Chunk) -> Message,
+    {
+        match self.chunk_size {
+            Some(chunk_size) if p.value().len() > chunk_size => {
+                Chunk::split(p, chunk_size, &*self.hasher)
+                    .into_iter()
+                    .map(chunk_msg)
+                    .collect()
+            }
+            _ => vec![msg(p)],
+        }
+    }
+
     /// Handles a received `Echo` message.
     fn handle_echo(&mut self, sender_id: &N, p: Proof<Vec<u8>>) -> Result<Step<N>> {
-        // If the sender has already sent `Echo`, ignore.
-        if let Some(old_p) = self.echos.get(sender_id) {
-            if *old_p == p {
+        // If the sender has already sent `Echo`, ignore. If it has sent an `EchoHash` with the same
+        // root hash, this is the full `Echo` we requested.
+        if let Some(old_content) = self.echos.get(sender_id) {
+            if old_content.proof() == Some(&p) {
                 warn!(
                     "Node {:?} received Echo({:?}) multiple times from {:?}.",
                     self.our_id(),
@@ -235,12 +484,16 @@ impl<N: NodeIdT> Broadcast<N> {
                     sender_id,
                 );
                 return Ok(Step::default());
-            } else {
+            } else if old_content.proof().is_some() || old_content.hash() != p.root_hash() {
                 return Ok(Fault::new(sender_id.clone(), FaultKind::MultipleEchos).into());
             }
         }
 
-        // If the proof is invalid, log the faulty-node behavior, and ignore.
+        // If the shard is too large or the proof is invalid, log the faulty-node behavior, and
+        // ignore.
+        if self.is_shard_too_large(&p) {
+            return Ok(Fault::new(sender_id.clone(), FaultKind::ValueTooLarge).into());
+        }
         if !self.validate_proof(&p, sender_id) {
             return Ok(Fault::new(sender_id.clone(), FaultKind::InvalidProof).into());
         }
@@ -248,14 +501,100 @@ impl<N: NodeIdT> Broadcast<N> {
         let hash = *p.root_hash();
 
         // Save the proof for reconstructing the tree later.
-        self.echos.insert(sender_id.clone(), p);
+        self.echos.insert(sender_id.clone(), EchoContent::Full(p));
+
+        // Upon receiving `N - 2 f` `Echo`s with this root hash, multicast `CanDecode`.
+        let step = self.send_can_decode(&hash);
 
         if self.ready_sent || self.count_echos(&hash) < self.netinfo.num_correct() {
-            return self.compute_output(&hash);
+            return Ok(step.join(self.compute_output(&hash)?));
         }
 
         // Upon receiving `N - f` `Echo`s with this root hash, multicast `Ready`.
-        self.send_ready(&hash)
+        Ok(step.join(self.send_ready(&hash)?))
+    }
+
+    /// Returns `true` if the proof's shard is longer than the shards of a value of the maximum
+    /// size. All shards of a value have the same length, so such a value can't be within the limit.
+    fn is_shard_too_large(&self, p: &Proof<Vec<u8>>) -> bool {
+        match self.max_shard_len() {
+            Some(max) => p.value().len() > max,
+            None => false,
+        }
+    }
+
+    /// Returns the length of the shards of a value of the maximum size, if there is a maximum.
+    fn max_shard_len(&self) -> Option<usize> {
+        let data_shard_num = self.coding.data_shard_count();
+        // The value is prefixed with its four-byte length, and the shards are as short as possible:
+        // see `send_shards`. So they are the prefixed maximum size divided by the number of data
+        // shards, rounded up.
+        let max = self.max_value_size?;
+        Some((max + 3) / data_shard_num + 1)
+    }
+
+    /// Handles a received `EchoHash` message.
+    fn handle_echo_hash(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
+        // If the sender has already sent `Echo` or `EchoHash`, ignore.
+        if let Some(old_content) = self.echos.get(sender_id) {
+            if old_content.hash() == hash {
+                warn!(
+                    "Node {:?} received EchoHash({:?}) multiple times from {:?}.",
+                    self.our_id(),
+                    hash,
+                    sender_id,
+                );
+                return Ok(Step::default());
+            } else {
+                return Ok(Fault::new(sender_id.clone(), FaultKind::MultipleEchos).into());
+            }
+        }
+
+        self.echos
+            .insert(sender_id.clone(), EchoContent::Hash(*hash));
+
+        if self.ready_sent || self.count_echos(hash) < self.netinfo.num_correct() {
+            return self.compute_output(hash);
+        }
+
+        // Upon receiving `N - f` `Echo`s or `EchoHash`es with this root hash, multicast `Ready`.
+        self.send_ready(hash)
+    }
+
+    /// Handles a received `RequestEcho` message: If we have sent `Echo` with that root hash, we
+    /// send it to the sender, unless we already received a request from them.
+    fn handle_request_echo(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
+        if !self.echo_requests_received.insert(sender_id.clone()) {
+            return Ok(Step::default());
+        }
+        let p = match self.echos.get(self.our_id()).and_then(EchoContent::proof) {
+            Some(p) if p.root_hash() == hash => p.clone(),
+            _ => return Ok(Step::default()),
+        };
+        let mut step = Step::default();
+        for msg in self.shard_messages(p, Message::Echo, Message::EchoChunk) {
+            step.messages
+                .push(Target::Node(sender_id.clone()).message(msg));
+        }
+        Ok(step)
+    }
+
+    /// Handles a received `CanDecode` message.
+    fn handle_can_decode(&mut self, sender_id: &N, hash: &Digest) -> Result<Step<N>> {
+        // If the sender has already sent `CanDecode` for a different hash, it is faulty.
+        let is_other_sender = |(other_hash, ids): (&Digest, &BTreeSet<N>)| {
+            other_hash != hash && ids.contains(sender_id)
+        };
+        if self.can_decodes.iter().any(is_other_sender) {
+            return Ok(Fault::new(sender_id.clone(), FaultKind::MultipleCanDecodes).into());
+        }
+
+        // From now on, we only need to send the sender an `EchoHash` instead of the full `Echo`.
+        self.can_decodes
+            .entry(*hash)
+            .or_default()
+            .insert(sender_id.clone());
+        Ok(Step::default())
     }
 
     /// Handles a received `Ready` message.
@@ -288,17 +627,89 @@ impl<N: NodeIdT> Broadcast<N> {
     }
 
     /// Sends an `Echo` message and handles it. Does nothing if we are only an observer.
+    ///
+    /// The nodes that have already sent us `CanDecode` only receive an `EchoHash` message.
     fn send_echo(&mut self, p: Proof<Vec<u8>>) -> Result<Step<N>> {
         self.echo_sent = true;
         if !self.netinfo.is_validator() {
             return Ok(Step::default());
         }
-        let echo_msg = Message::Echo(p.clone());
-        let step: Step<_> = Target::All.message(echo_msg).into();
+        let hash = *p.root_hash();
+        let mut step = Step::default();
+        let echo_msgs = self.shard_messages(p.clone(), Message::Echo, Message::EchoChunk);
+        let mut hash_ids = self.can_decodes.get(&hash).cloned().unwrap_or_default();
+        if let EchoStrategy::Partial(redundancy) = self.echo_strategy {
+            hash_ids.extend(self.partial_echo_hash_ids(redundancy));
+        }
+        if hash_ids.is_empty() {
+            for echo_msg in echo_msgs {
+                step.messages.push(Target::All.message(echo_msg));
+            }
+        } else {
+            for echo_msg in echo_msgs {
+                let target = Target::AllExcept(hash_ids.clone());
+                step.messages.push(target.message(echo_msg));
+            }
+            for id in hash_ids {
+                let echo_hash_msg = Message::EchoHash(hash);
+                step.messages.push(Target::Node(id).message(echo_hash_msg));
+            }
+        }
         let our_id = &self.our_id().clone();
         Ok(step.join(self.handle_echo(our_id, p)?))
     }
 
+    /// Returns the IDs of the validators that only receive an `EchoHash` from us with
+    /// `EchoStrategy::Partial`: all but the ones following us in the order of IDs, wrapping around.
+    /// Counting ourselves, every validator receives `redundancy` more full `Echo`s than the number
+    /// of data shards.
+    fn partial_echo_hash_ids(&self, redundancy: usize) -> BTreeSet<N> {
+        let ids: Vec<&N> = self.netinfo.all_ids().collect();
+        let our_index = match self.netinfo.node_index(self.our_id()) {
+            Some(our_index) => our_index,
+            None => return BTreeSet::new(),
+        };
+        let full_num = self.coding.data_shard_count() - 1 + redundancy;
+        (full_num + 1..ids.len())
+            .map(|i| ids[(our_index + i) % ids.len()].clone())
+            .collect()
+    }
+
+    /// Sends `RequestEcho` to all nodes that have only sent us an `EchoHash` with the given root
+    /// hash, and that we haven't sent a request to yet.
+    fn request_echos(&mut self, hash: &Digest) -> Step<N> {
+        let ids: Vec<N> = self
+            .echos
+            .iter()
+            .filter(|(id, content)| {
+                content.proof().is_none()
+                    && content.hash() == hash
+                    && !self.echo_requests_sent.contains(id)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut step = Step::default();
+        for id in ids {
+            self.echo_requests_sent.insert(id.clone());
+            let msg = Target::Node(id).message(Message::RequestEcho(*hash));
+            step.messages.push(msg);
+        }
+        step
+    }
+
+    /// Sends a `CanDecode` message if we have received enough `Echo`s to decode the value with the
+    /// given root hash. Does nothing if we are only an observer or have already sent it.
+    fn send_can_decode(&mut self, hash: &Digest) -> Step<N> {
+        if self.can_decode_sent
+            || !self.netinfo.is_validator()
+            || self.count_full_echos(hash) < self.coding.data_shard_count()
+        {
+            return Step::default();
+        }
+        self.can_decode_sent = true;
+        Target::All.message(Message::CanDecode(*hash)).into()
+    }
+
     /// Sends a `Ready` message and handles it. Does nothing if we are only an observer.
     fn send_ready(&mut self, hash: &Digest) -> Result<Step<N>> {
         self.ready_sent = true;
@@ -314,29 +725,44 @@ impl<N: NodeIdT> Broadcast<N> {
     /// Checks whether the conditions for output are met for this hash, and if so, sets the output
     /// value.
     fn compute_output(&mut self, hash: &Digest) -> Result<Step<N>> {
-        if self.decided
-            || self.count_readys(hash) <= 2 * self.netinfo.num_faulty()
-            || self.count_echos(hash) < self.coding.data_shard_count()
-        {
+        if self.decided || self.count_readys(hash) <= 2 * self.netinfo.num_faulty() {
             return Ok(Step::default());
         }
+        if self.count_full_echos(hash) < self.coding.data_shard_count() {
+            // We need to decode, but don't have enough shards: Request them.
+            return Ok(self.request_echos(hash));
+        }
 
         // Upon receiving 2f + 1 matching Ready(h) messages, wait for N − 2f Echo messages.
         let mut leaf_values: Vec<Option<Box<[u8]>>> = self
             .netinfo
             .all_ids()
             .map(|id| {
-                self.echos.get(id).and_then(|p| {
-                    if p.root_hash() == hash {
-                        Some(p.value().clone().into_boxed_slice())
-                    } else {
-                        None
-                    }
-                })
+                self.echos
+                    .get(id)
+                    .and_then(EchoContent::proof)
+                    .and_then(|p| {
+                        if p.root_hash() == hash {
+                            Some(p.value().clone().into_boxed_slice())
+                        } else {
+                            None
+                        }
+                    })
             })
             .collect();
-        if let Some(value) = self.decode_from_shards(&mut leaf_values, hash) {
+        let value = self.decode_from_shards(&mut leaf_values, hash);
+        if let (Some(value), Some(max)) = (&value, self.max_value_size) {
+            // The shards are rounded up, so their size alone doesn't prove that the value is
+            // within the limit. All correct nodes decode the same value, so they all reject it.
+            if value.len() > max {
+                let fault_kind = FaultKind::ValueTooLarge;
+                return Ok(Fault::new(self.proposer_id.clone(), fault_kind).into());
+            }
+        }
+        if let Some(value) = value {
             self.decided = true;
+            let duration = self.started.elapsed();
+            self.instrument.timing(Timing::Broadcast, duration);
             Ok(Step::default().with_output(value))
         } else {
             let fault_kind = FaultKind::BroadcastDecoding;
@@ -364,7 +790,7 @@ impl<N: NodeIdT> Broadcast<N> {
         debug!("{}: Reconstructed shards: {:0.10}", self, HexList(&shards));
 
         // Construct the Merkle tree.
-        let mtree = MerkleTree::from_vec(shards);
+        let mtree = MerkleTree::from_vec(shards, &*self.hasher);
         // If the root hash of the reconstructed tree does not match the one
         // received with proofs then abort.
         if mtree.root_hash() != root_hash {
@@ -389,13 +815,21 @@ impl<N: NodeIdT> Broadcast<N> {
 
     /// Returns `true` if the proof is valid and has the same index as the node ID.
     fn validate_proof(&self, p: &Proof<Vec<u8>>, id: &N) -> bool {
-        self.netinfo.node_index(id) == Some(p.index()) && p.validate(self.netinfo.num_nodes())
+        self.netinfo.node_index(id) == Some(p.index())
+            && p.validate(self.netinfo.num_nodes(), &*self.hasher)
     }
 
-    /// Returns the number of nodes that have sent us an `Echo` message with this hash.
+    /// Returns the number of nodes that have sent us an `Echo` or `EchoHash` message with this
+    /// hash.
     fn count_echos(&self, hash: &Digest) -> usize {
+        self.echos.values().filter(|c| c.hash() == hash).count()
+    }
+
+    /// Returns the number of nodes that have sent us a full `Echo` message with this hash.
+    fn count_full_echos(&self, hash: &Digest) -> usize {
         self.echos
             .values()
+            .filter_map(EchoContent::proof)
             .filter(|p| p.root_hash() == hash)
             .count()
     }
@@ -409,67 +843,16 @@ impl<N: NodeIdT> Broadcast<N> {
     }
 }
 
-impl<N: NodeIdT> fmt::Display for Broadcast<N> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
-        write!(f, "{:?} Broadcast({:?})", self.our_id(), self.proposer_id)
+/// Returns the fault kind for a rejected chunk.
+fn chunk_fault_kind(err: ChunkError) -> FaultKind {
+    match err {
+        ChunkError::Invalid => FaultKind::InvalidChunk,
+        ChunkError::TooLarge => FaultKind::ValueTooLarge,
     }
 }
 
-/// A wrapper for `ReedSolomon` that doesn't panic if there are no parity shards.
-#[derive(Debug)]
-enum Coding {
-    /// A `ReedSolomon` instance with at least one parity shard.
-    ReedSolomon(Box<ReedSolomon>),
-    /// A no-op replacement that doesn't encode or decode anything.
-    Trivial(usize),
-}
-
-impl Coding {
-    /// Creates a new `Coding` instance with the given number of shards.
-    fn new(data_shard_num: usize, parity_shard_num: usize) -> RseResult<Self> {
-        Ok(if parity_shard_num > 0 {
-            let rs = ReedSolomon::new(data_shard_num, parity_shard_num)?;
-            Coding::ReedSolomon(Box::new(rs))
-        } else {
-            Coding::Trivial(data_shard_num)
-        })
-    }
-
-    /// Returns the number of data shards.
-    fn data_shard_count(&self) -> usize {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.data_shard_count(),
-            Coding::Trivial(dsc) => dsc,
-        }
-    }
-
-    /// Returns the number of parity shards.
-    fn parity_shard_count(&self) -> usize {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.parity_shard_count(),
-            Coding::Trivial(_) => 0,
-        }
-    }
-
-    /// Constructs (and overwrites) the parity shards.
-    fn encode(&self, slices: &mut [&mut [u8]]) -> RseResult<()> {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.encode(slices),
-            Coding::Trivial(_) => Ok(()),
-        }
-    }
-
-    /// If enough shards are present, reconstructs the missing ones.
-    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> RseResult<()> {
-        match *self {
-            Coding::ReedSolomon(ref rs) => rs.reconstruct_shards(shards),
-            Coding::Trivial(_) => {
-                if shards.iter().all(Option::is_some) {
-                    Ok(())
-                } else {
-                    Err(rse::Error::TooFewShardsPresent)
-                }
-            }
-        }
+impl<N: NodeIdT> fmt::Display for Broadcast<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
+        write!(f, "{:?} Broadcast({:?})", self.our_id(), self.proposer_id)
     }
 }
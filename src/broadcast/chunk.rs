@@ -0,0 +1,164 @@
+//! Splitting of large shards into chunks.
+//!
+//! If a chunk size is configured, every `Value` and `Echo` whose shard is larger is sent as a
+//! sequence of `ValueChunk` or `EchoChunk` messages instead. The shard is split into chunks of at
+//! most that size, and these are the leaves of a second Merkle tree, so that every chunk comes
+//! with a proof that it belongs to the same shard as the others. Once all chunks from a sender
+//! have arrived, they are glued together and the shard is handled as if it had been sent in a
+//! single message, including the verification of its proof.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hex_fmt::HexFmt;
+use serde::{Deserialize, Serialize};
+
+use super::merkle::{MerkleHasher, MerkleTree, Proof};
+
+/// A part of a shard, together with a proof that it belongs to the shard's other chunks.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Chunk {
+    /// The proof of the whole shard, with an empty value.
+    shard: Proof<Vec<u8>>,
+    /// The number of chunks the shard is split into.
+    count: usize,
+    /// The chunk, with its proof in the Merkle tree of all the shard's chunks.
+    proof: Proof<Vec<u8>>,
+}
+
+impl Chunk {
+    /// Splits the proof's shard into chunks of at most `chunk_size` bytes.
+    pub(super) fn split(
+        p: Proof<Vec<u8>>,
+        chunk_size: usize,
+        hasher: &dyn MerkleHasher,
+    ) -> Vec<Chunk> {
+        let values = p.value().chunks(chunk_size).map(<[u8]>::to_vec).collect();
+        let mtree = MerkleTree::from_vec(values, hasher);
+        let count = mtree.values().len();
+        let shard = p.with_value(Vec::new());
+        mtree
+            .proofs()
+            .into_iter()
+            .map(|proof| Chunk {
+                shard: shard.clone(),
+                count,
+                proof,
+            })
+            .collect()
+    }
+
+    /// Returns the index of the shard in the value's Merkle tree.
+    pub fn shard_index(&self) -> usize {
+        self.shard.index()
+    }
+
+    /// Returns the index of this chunk in the shard.
+    pub fn index(&self) -> usize {
+        self.proof.index()
+    }
+
+    /// Returns the number of chunks the shard is split into.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the chunk's bytes.
+    pub fn value(&self) -> &[u8] {
+        self.proof.value()
+    }
+}
+
+impl fmt::Debug for Chunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Chunk {{ #{}, {}/{}, root_hash: {:0.10}, value: {:0.10}, .. }}",
+            self.shard.index(),
+            self.proof.index(),
+            self.count,
+            HexFmt(self.shard.root_hash()),
+            HexFmt(self.proof.value())
+        )
+    }
+}
+
+/// The reason why a chunk was rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum ChunkError {
+    /// The chunk's proof is invalid, or it doesn't match the previous chunks.
+    Invalid,
+    /// The shard would be larger than the maximum size.
+    TooLarge,
+}
+
+/// The chunks received so far of a single shard, from a single sender.
+#[derive(Debug)]
+pub(super) struct ChunkBuffer {
+    /// The first chunk, without its value.
+    first: Chunk,
+    /// The chunks' bytes, by index.
+    values: BTreeMap<usize, Vec<u8>>,
+    /// The total number of bytes received so far.
+    len: usize,
+}
+
+impl ChunkBuffer {
+    /// Creates an empty buffer for the chunks of the same shard as `chunk`, and validates it.
+    pub(super) fn new(chunk: &Chunk) -> Result<Self, ChunkError> {
+        if chunk.count == 0 || !chunk.shard.value().is_empty() {
+            return Err(ChunkError::Invalid);
+        }
+        let first = Chunk {
+            shard: chunk.shard.clone(),
+            count: chunk.count,
+            proof: chunk.proof.clone().with_value(Vec::new()),
+        };
+        Ok(ChunkBuffer {
+            first,
+            values: BTreeMap::new(),
+            len: 0,
+        })
+    }
+
+    /// Adds the chunk, and returns the proof with the complete shard, if this was the last one.
+    ///
+    /// Chunks larger than `max_chunk_size`, and chunks that would make the shard larger than
+    /// `max_shard_len`, are rejected.
+    pub(super) fn insert(
+        &mut self,
+        chunk: Chunk,
+        max_chunk_size: Option<usize>,
+        max_shard_len: Option<usize>,
+        hasher: &dyn MerkleHasher,
+    ) -> Result<Option<Proof<Vec<u8>>>, ChunkError> {
+        if chunk.shard != self.first.shard
+            || chunk.count != self.first.count
+            || chunk.proof.root_hash() != self.first.proof.root_hash()
+            || chunk.proof.index() >= chunk.count
+            || !chunk.proof.validate(chunk.count, hasher)
+            || max_chunk_size
+                .filter(|max| chunk.value().len() > *max)
+                .is_some()
+        {
+            return Err(ChunkError::Invalid);
+        }
+        if self.values.contains_key(&chunk.proof.index()) {
+            return Ok(None); // A duplicate: The proof guarantees that it's the same chunk.
+        }
+        self.len += chunk.value().len();
+        if max_shard_len.filter(|max| self.len > *max).is_some() {
+            return Err(ChunkError::TooLarge);
+        }
+        self.values
+            .insert(chunk.proof.index(), chunk.proof.into_value());
+        if self.values.len() < self.first.count {
+            return Ok(None);
+        }
+        let mut value = Vec::with_capacity(self.len);
+        for chunk_value in self.values.values() {
+            value.extend_from_slice(chunk_value);
+        }
+        Ok(Some(self.first.shard.clone().with_value(value)))
+    }
+}
@@ -0,0 +1,182 @@
+//! Erasure coding of the broadcast value.
+//!
+//! The proposer splits the value into _D_ data shards and computes _N - D_ parity shards, so that
+//! any _D_ of the _N_ shards suffice to reconstruct it. A node sends `Ready` after _N - f_ `Echo`s,
+//! at most _f_ of which can be from faulty nodes, so the value can only be decoded from the correct
+//! nodes' shards if _D ≤ N - 2 f_.
+
+use std::fmt;
+
+use failure::Fail;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use reed_solomon_erasure as rse;
+use reed_solomon_erasure::ReedSolomon;
+
+/// The number of bytes of each shard that are encoded together on one thread.
+#[cfg(feature = "parallel")]
+const SEGMENT_LEN: usize = 16 * 1024;
+
+/// An error in encoding or reconstructing the shards.
+#[derive(Clone, Copy, Debug, Fail, PartialEq, Eq)]
+pub enum CodingError {
+    /// The shards are empty, have different sizes or the wrong number.
+    #[fail(display = "Invalid shards")]
+    InvalidShards,
+    /// Too few shards are present to reconstruct the missing ones.
+    #[fail(display = "Too few shards present")]
+    TooFewShards,
+}
+
+impl From<rse::Error> for CodingError {
+    fn from(err: rse::Error) -> Self {
+        match err {
+            rse::Error::TooFewShardsPresent => CodingError::TooFewShards,
+            _ => CodingError::InvalidShards,
+        }
+    }
+}
+
+/// An erasure coding scheme for the shards of a broadcast value.
+///
+/// All nodes must use the same scheme, with the same number of shards.
+pub trait ErasureCoding: fmt::Debug + Send + Sync {
+    /// Returns the number of data shards, i.e. the number of shards needed for reconstruction.
+    fn data_shard_count(&self) -> usize;
+    /// Returns the number of parity shards.
+    fn parity_shard_count(&self) -> usize;
+    /// Constructs (and overwrites) the parity shards, which come after the data shards. All shards
+    /// have the same, nonzero size.
+    fn encode(&self, shards: &mut [&mut [u8]]) -> Result<(), CodingError>;
+    /// If enough shards are present, reconstructs the missing ones.
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<(), CodingError>;
+}
+
+/// A Reed-Solomon erasure coding scheme. This is the default.
+#[derive(Debug)]
+pub struct ReedSolomonCoding(Coding);
+
+/// A wrapper for `ReedSolomon` that doesn't panic if there are no parity shards.
+#[derive(Debug)]
+enum Coding {
+    /// A `ReedSolomon` instance with at least one parity shard.
+    ReedSolomon(Box<ReedSolomon>),
+    /// A no-op replacement that doesn't encode or decode anything.
+    Trivial(usize),
+}
+
+impl ReedSolomonCoding {
+    /// Creates a new `ReedSolomonCoding` instance with the given number of shards.
+    ///
+    /// Due to a limitation in `reed_solomon_erasure`, only up to 256 shards are supported.
+    pub fn new(data_shard_num: usize, parity_shard_num: usize) -> Result<Self, CodingError> {
+        Ok(ReedSolomonCoding(if parity_shard_num > 0 {
+            let rs = ReedSolomon::new(data_shard_num, parity_shard_num)?;
+            Coding::ReedSolomon(Box::new(rs))
+        } else if data_shard_num > 0 {
+            Coding::Trivial(data_shard_num)
+        } else {
+            return Err(CodingError::InvalidShards);
+        }))
+    }
+}
+
+impl ErasureCoding for ReedSolomonCoding {
+    fn data_shard_count(&self) -> usize {
+        match self.0 {
+            Coding::ReedSolomon(ref rs) => rs.data_shard_count(),
+            Coding::Trivial(dsc) => dsc,
+        }
+    }
+
+    fn parity_shard_count(&self) -> usize {
+        match self.0 {
+            Coding::ReedSolomon(ref rs) => rs.parity_shard_count(),
+            Coding::Trivial(_) => 0,
+        }
+    }
+
+    fn encode(&self, shards: &mut [&mut [u8]]) -> Result<(), CodingError> {
+        match self.0 {
+            Coding::ReedSolomon(ref rs) => encode_rs(rs, shards),
+            Coding::Trivial(_) => Ok(()),
+        }
+    }
+
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<(), CodingError> {
+        match self.0 {
+            Coding::ReedSolomon(ref rs) => Ok(rs.reconstruct_shards(shards)?),
+            Coding::Trivial(_) => {
+                if shards.iter().all(Option::is_some) {
+                    Ok(())
+                } else {
+                    Err(CodingError::TooFewShards)
+                }
+            }
+        }
+    }
+}
+
+/// Constructs the parity shards with the given `ReedSolomon` instance.
+#[cfg(not(feature = "parallel"))]
+fn encode_rs(rs: &ReedSolomon, shards: &mut [&mut [u8]]) -> Result<(), CodingError> {
+    Ok(rs.encode(shards)?)
+}
+
+/// Constructs the parity shards with the given `ReedSolomon` instance, on multiple threads: Each
+/// parity byte only depends on the data bytes at the same position, so the shards are split into
+/// segments at the same positions, which are encoded independently.
+#[cfg(feature = "parallel")]
+fn encode_rs(rs: &ReedSolomon, shards: &mut [&mut [u8]]) -> Result<(), CodingError> {
+    let shard_len = shards.first().map_or(0, |shard| shard.len());
+    if shard_len <= SEGMENT_LEN || shards.iter().any(|shard| shard.len() != shard_len) {
+        return Ok(rs.encode(shards)?);
+    }
+    let segment_num = shards[0].chunks(SEGMENT_LEN).count();
+    let mut segments: Vec<Vec<&mut [u8]>> = (0..segment_num)
+        .map(|_| Vec::with_capacity(shards.len()))
+        .collect();
+    for shard in shards.iter_mut() {
+        for (segment, chunk) in segments.iter_mut().zip(shard.chunks_mut(SEGMENT_LEN)) {
+            segment.push(chunk);
+        }
+    }
+    segments
+        .into_par_iter()
+        .try_for_each(|mut segment| rs.encode(&mut segment))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_large_shards() {
+        // Several segments, and a shorter last one.
+        let shard_len = 100_000;
+        let coding = ReedSolomonCoding::new(3, 4).expect("coding");
+        let mut value: Vec<u8> = (0..7 * shard_len).map(|i| (i * 7 % 251) as u8).collect();
+        let mut expected = value.clone();
+        {
+            let mut shards: Vec<&mut [u8]> = value.chunks_mut(shard_len).collect();
+            coding.encode(&mut shards).expect("encode");
+        }
+        {
+            let rs = ReedSolomon::new(3, 4).expect("reed-solomon");
+            let mut shards: Vec<&mut [u8]> = expected.chunks_mut(shard_len).collect();
+            rs.encode(&mut shards).expect("encode");
+        }
+        assert!(value == expected);
+
+        // Any three shards suffice to reconstruct the others.
+        let mut shards: Vec<Option<Box<[u8]>>> = value
+            .chunks(shard_len)
+            .enumerate()
+            .map(|(i, shard)| Some(shard.into()).filter(|_| i % 2 == 1))
+            .collect();
+        coding.reconstruct_shards(&mut shards).expect("reconstruct");
+        let reconstructed: Vec<u8> = shards.into_iter().flatten().flat_map(Vec::from).collect();
+        assert!(value == reconstructed);
+    }
+}
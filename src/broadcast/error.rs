@@ -1,5 +1,8 @@
 use failure::Fail;
 
+use super::CodingError;
+use crate::NetworkInfoError;
+
 /// A broadcast error.
 #[derive(Clone, PartialEq, Debug, Fail)]
 pub enum Error {
@@ -18,6 +21,18 @@ pub enum Error {
     /// Unknown sender.
     #[fail(display = "Unknown sender")]
     UnknownSender,
+    /// The value is larger than the configured maximum size.
+    #[fail(display = "Value too large")]
+    ValueTooLarge,
+    /// The erasure coding scheme has the wrong number of shards.
+    #[fail(display = "Invalid erasure coding scheme")]
+    InvalidCoding,
+    /// The erasure coding scheme failed to encode the value.
+    #[fail(display = "Erasure coding error: {}", _0)]
+    Coding(CodingError),
+    /// The `NetworkInfo`'s keys are inconsistent.
+    #[fail(display = "Invalid network info: {}", _0)]
+    InvalidNetworkInfo(NetworkInfoError),
 }
 
 /// A broadcast result.
@@ -38,10 +53,19 @@ pub enum FaultKind {
     /// `Broadcast` received multiple different `Ready`s from the same sender.
     #[fail(display = "`Broadcast` received multiple different `Ready`s from the same sender.")]
     MultipleReadys,
+    /// `Broadcast` received multiple different `CanDecode`s from the same sender.
+    #[fail(display = "`Broadcast` received multiple different `CanDecode`s from the same sender.")]
+    MultipleCanDecodes,
     /// `Broadcast` recevied an Echo message containing an invalid proof.
     #[fail(display = "`Broadcast` recevied an Echo message containing an invalid proof.")]
     InvalidProof,
     ///`Broadcast` received shards with valid proofs, that couldn't be decoded.
     #[fail(display = "`Broadcast` received shards with valid proofs, that couldn't be decoded.")]
     BroadcastDecoding,
+    /// `Broadcast` received a shard of a value larger than the configured maximum size.
+    #[fail(display = "`Broadcast` received a shard of a value larger than the maximum size.")]
+    ValueTooLarge,
+    /// `Broadcast` received a chunk with an invalid proof, or that doesn't match the previous ones.
+    #[fail(display = "`Broadcast` received an invalid chunk of a shard.")]
+    InvalidChunk,
 }
@@ -1,10 +1,31 @@
-use std::mem;
+use std::{fmt, mem};
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use tiny_keccak::sha3_256;
 
+/// The hash of a Merkle tree node.
 pub type Digest = [u8; 32];
 
+/// The hash function used for the leaves and inner nodes of the Merkle tree.
+///
+/// All nodes must use the same hash function.
+pub trait MerkleHasher: fmt::Debug + Send + Sync {
+    /// Returns the hash of the given bytes.
+    fn hash(&self, bytes: &[u8]) -> Digest;
+}
+
+/// The SHA3-256 hash function. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sha3Hasher;
+
+impl MerkleHasher for Sha3Hasher {
+    fn hash(&self, bytes: &[u8]) -> Digest {
+        sha3_256(bytes)
+    }
+}
+
 /// A Merkle tree: The leaves are values and their hashes. Each level consists of the hashes of
 /// pairs of values on the previous level. The root is the value in the first level with only one
 /// entry.
@@ -15,13 +36,13 @@ pub struct MerkleTree<T> {
     root_hash: Digest,
 }
 
-impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
-    /// Creates a new Merkle tree with the given values.
-    pub fn from_vec(values: Vec<T>) -> Self {
+impl<T: AsRef<[u8]> + Clone + Send + Sync> MerkleTree<T> {
+    /// Creates a new Merkle tree with the given values, using the given hash function.
+    pub fn from_vec(values: Vec<T>, hasher: &dyn MerkleHasher) -> Self {
         let mut levels = Vec::new();
-        let mut cur_lvl: Vec<Digest> = values.iter().map(hash).collect();
+        let mut cur_lvl: Vec<Digest> = hash_values(&values, hasher);
         while cur_lvl.len() > 1 {
-            let next_lvl = cur_lvl.chunks(2).map(hash_chunk).collect();
+            let next_lvl = hash_level(&cur_lvl, hasher);
             levels.push(mem::replace(&mut cur_lvl, next_lvl));
         }
         let root_hash = cur_lvl[0];
@@ -52,6 +73,23 @@ impl<T: AsRef<[u8]> + Clone> MerkleTree<T> {
         })
     }
 
+    /// Returns the proofs for all entries, in order.
+    #[cfg(not(feature = "parallel"))]
+    pub fn proofs(&self) -> Vec<Proof<T>> {
+        (0..self.values.len())
+            .filter_map(|index| self.proof(index))
+            .collect()
+    }
+
+    /// Returns the proofs for all entries, in order.
+    #[cfg(feature = "parallel")]
+    pub fn proofs(&self) -> Vec<Proof<T>> {
+        (0..self.values.len())
+            .into_par_iter()
+            .filter_map(|index| self.proof(index))
+            .collect()
+    }
+
     /// Returns the root hash of the tree.
     pub fn root_hash(&self) -> &Digest {
         &self.root_hash
@@ -79,9 +117,9 @@ pub struct Proof<T> {
 
 impl<T: AsRef<[u8]>> Proof<T> {
     /// Returns `true` if the digests in this proof constitute a valid branch in a Merkle tree with
-    /// the root hash.
-    pub fn validate(&self, n: usize) -> bool {
-        let mut digest = hash(&self.value);
+    /// the root hash, using the given hash function.
+    pub fn validate(&self, n: usize, hasher: &dyn MerkleHasher) -> bool {
+        let mut digest = hasher.hash(self.value.as_ref());
         let mut lvl_i = self.index;
         let mut lvl_n = n;
         let mut digest_itr = self.digests.iter();
@@ -89,8 +127,8 @@ impl<T: AsRef<[u8]>> Proof<T> {
             if lvl_i ^ 1 < lvl_n {
                 digest = match digest_itr.next() {
                     None => return false, // Not enough levels in the proof.
-                    Some(sibling) if lvl_i & 1 == 1 => hash_pair(&sibling, &digest),
-                    Some(sibling) => hash_pair(&digest, &sibling),
+                    Some(sibling) if lvl_i & 1 == 1 => hash_pair(sibling, &digest, hasher),
+                    Some(sibling) => hash_pair(&digest, sibling, hasher),
                 };
             }
             lvl_i /= 2; // Our index on the next level.
@@ -121,42 +159,84 @@ impl<T: AsRef<[u8]>> Proof<T> {
     pub fn into_value(self) -> T {
         self.value
     }
+
+    /// Returns the proof with the leaf value replaced by `value`. It is only valid if the new value
+    /// has the same hash.
+    pub fn with_value(self, value: T) -> Self {
+        Proof { value, ..self }
+    }
+}
+
+/// Returns the hashes of the values.
+#[cfg(not(feature = "parallel"))]
+fn hash_values<T: AsRef<[u8]>>(values: &[T], hasher: &dyn MerkleHasher) -> Vec<Digest> {
+    values.iter().map(|v| hasher.hash(v.as_ref())).collect()
+}
+
+/// Returns the hashes of the values.
+#[cfg(feature = "parallel")]
+fn hash_values<T: AsRef<[u8]> + Sync>(values: &[T], hasher: &dyn MerkleHasher) -> Vec<Digest> {
+    values.par_iter().map(|v| hasher.hash(v.as_ref())).collect()
+}
+
+/// Returns the next level of the tree, i.e. the hashes of the pairs of digests.
+#[cfg(not(feature = "parallel"))]
+fn hash_level(level: &[Digest], hasher: &dyn MerkleHasher) -> Vec<Digest> {
+    level.chunks(2).map(|c| hash_chunk(c, hasher)).collect()
+}
+
+/// Returns the next level of the tree, i.e. the hashes of the pairs of digests.
+#[cfg(feature = "parallel")]
+fn hash_level(level: &[Digest], hasher: &dyn MerkleHasher) -> Vec<Digest> {
+    level.par_chunks(2).map(|c| hash_chunk(c, hasher)).collect()
 }
 
 /// Takes a chunk of one or two digests. In the former case, returns the digest itself, in the
 /// latter, it returns the hash of the two digests.
-fn hash_chunk(chunk: &[Digest]) -> Digest {
+fn hash_chunk(chunk: &[Digest], hasher: &dyn MerkleHasher) -> Digest {
     if chunk.len() == 1 {
         chunk[0]
     } else {
-        hash_pair(&chunk[0], &chunk[1])
+        hash_pair(&chunk[0], &chunk[1], hasher)
     }
 }
 
 /// Returns the hash of the concatenated bytes of `d0` and `d1`.
-fn hash_pair<T0: AsRef<[u8]>, T1: AsRef<[u8]>>(v0: &T0, v1: &T1) -> Digest {
-    let bytes: Vec<u8> = v0.as_ref().iter().chain(v1.as_ref()).cloned().collect();
-    hash(&bytes)
-}
-
-/// Returns the SHA-256 hash of the value's `[u8]` representation.
-fn hash<T: AsRef<[u8]>>(value: T) -> Digest {
-    sha3_256(value.as_ref())
+fn hash_pair(d0: &Digest, d1: &Digest, hasher: &dyn MerkleHasher) -> Digest {
+    let bytes: Vec<u8> = d0.iter().chain(d1).cloned().collect();
+    hasher.hash(&bytes)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::MerkleTree;
+    use tiny_keccak::keccak256;
+
+    use super::{Digest, MerkleHasher, MerkleTree, Sha3Hasher};
+
+    #[derive(Debug)]
+    struct KeccakHasher;
+
+    impl MerkleHasher for KeccakHasher {
+        fn hash(&self, bytes: &[u8]) -> Digest {
+            keccak256(bytes)
+        }
+    }
 
     #[test]
     fn test_merkle() {
         for &n in &[4, 7, 8, 9, 17] {
-            let tree = MerkleTree::from_vec((0..n).map(|i| vec![i as u8]).collect());
+            let tree = MerkleTree::from_vec((0..n).map(|i| vec![i as u8]).collect(), &Sha3Hasher);
             for i in 0..n {
                 let proof = tree.proof(i).expect("couldn't get proof");
-                assert!(proof.validate(n));
+                assert!(proof.validate(n, &Sha3Hasher));
+                assert!(!proof.validate(n, &KeccakHasher));
             }
             assert!(tree.proof(n).is_none());
+            let proofs = tree.proofs();
+            assert_eq!(n, proofs.len());
+            for (i, proof) in proofs.into_iter().enumerate() {
+                assert_eq!(Some(proof), tree.proof(i));
+            }
         }
     }
 }
@@ -5,7 +5,8 @@ use rand::distributions::{Distribution, Standard};
 use rand::{self, seq::SliceRandom, Rng};
 use serde::{Deserialize, Serialize};
 
-use super::merkle::{Digest, MerkleTree, Proof};
+use super::chunk::Chunk;
+use super::merkle::{Digest, MerkleTree, Proof, Sha3Hasher};
 
 /// The three kinds of message sent during the reliable broadcast stage of the
 /// consensus algorithm.
@@ -17,26 +18,55 @@ pub enum Message {
     Echo(Proof<Vec<u8>>),
     /// Indicates that the sender knows that every node will eventually be able to decode.
     Ready(Digest),
+    /// Indicates that the sender has enough shards to decode the value with the given root hash,
+    /// so it only needs `EchoHash` messages instead of full `Echo`s from now on.
+    CanDecode(Digest),
+    /// The root hash of the value received from the sender, multicast by a validator instead of an
+    /// `Echo` to the nodes that have sent `CanDecode`.
+    EchoHash(Digest),
+    /// A request for the full `Echo` with the given root hash, to a validator that has only sent
+    /// an `EchoHash`.
+    RequestEcho(Digest),
+    /// A part of a `Value` whose shard is larger than the chunk size.
+    ValueChunk(Chunk),
+    /// A part of an `Echo` whose shard is larger than the chunk size.
+    EchoChunk(Chunk),
 }
 
 // A random generation impl is provided for test cases. Unfortunately `#[cfg(test)]` does not work
 // for integration tests.
 impl Distribution<Message> for Standard {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Message {
-        let message_type = *["value", "echo", "ready"].choose(rng).unwrap();
+        let message_type = *[
+            "value",
+            "echo",
+            "ready",
+            "can_decode",
+            "echo_hash",
+            "request_echo",
+            "value_chunk",
+            "echo_chunk",
+        ]
+        .choose(rng)
+        .unwrap();
 
         // Create a random buffer for our proof.
         let mut buffer: [u8; 32] = [0; 32];
         rng.fill_bytes(&mut buffer);
 
         // Generate a dummy proof to fill broadcast messages with.
-        let tree = MerkleTree::from_vec(vec![buffer.to_vec()]);
+        let tree = MerkleTree::from_vec(vec![buffer.to_vec()], &Sha3Hasher);
         let proof = tree.proof(0).unwrap();
 
         match message_type {
             "value" => Message::Value(proof),
             "echo" => Message::Echo(proof),
             "ready" => Message::Ready([b'r'; 32]),
+            "can_decode" => Message::CanDecode([b'c'; 32]),
+            "echo_hash" => Message::EchoHash([b'e'; 32]),
+            "request_echo" => Message::RequestEcho([b'q'; 32]),
+            "value_chunk" => Message::ValueChunk(Chunk::split(proof, 16, &Sha3Hasher).remove(0)),
+            "echo_chunk" => Message::EchoChunk(Chunk::split(proof, 16, &Sha3Hasher).remove(0)),
             _ => unreachable!(),
         }
     }
@@ -48,6 +78,11 @@ impl Debug for Message {
             Message::Value(ref v) => f.debug_tuple("Value").field(&HexProof(v)).finish(),
             Message::Echo(ref v) => f.debug_tuple("Echo").field(&HexProof(v)).finish(),
             Message::Ready(ref b) => write!(f, "Ready({:0.10})", HexFmt(b)),
+            Message::CanDecode(ref b) => write!(f, "CanDecode({:0.10})", HexFmt(b)),
+            Message::EchoHash(ref b) => write!(f, "EchoHash({:0.10})", HexFmt(b)),
+            Message::RequestEcho(ref b) => write!(f, "RequestEcho({:0.10})", HexFmt(b)),
+            Message::ValueChunk(ref c) => f.debug_tuple("ValueChunk").field(c).finish(),
+            Message::EchoChunk(ref c) => f.debug_tuple("EchoChunk").field(c).finish(),
         }
     }
 }
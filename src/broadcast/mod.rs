@@ -19,6 +19,29 @@
 //! terminated and the instance can be dropped. (The messages in the last step still need to be
 //! sent out, though, to allow the other nodes to terminate, too.)
 //!
+//! Optionally, `Broadcast::set_max_value_size` limits the size of the value. It must be called with
+//! the same limit in all nodes. Shards of larger values are rejected as faulty.
+//!
+//! The erasure coding can be configured, too, in the same way on all nodes:
+//! `Broadcast::set_data_shard_count` lowers the number of data shards below the default _N - 2 f_,
+//! making each shard larger but the value decodable from fewer of them, and `Broadcast::set_coding`
+//! replaces the Reed-Solomon code with any other implementation of `ErasureCoding`.
+//!
+//! Very large values make for very large `Value` and `Echo` messages. With
+//! `Broadcast::set_chunk_size`, shards larger than the given size are instead sent as several
+//! `ValueChunk` or `EchoChunk` messages, each with a Merkle proof that it belongs to the same
+//! shard as the other chunks. The receiver reassembles the shard once all chunks have arrived.
+//!
+//! The `Echo` phase, in which every validator sends its shard to every other node, dominates the
+//! bandwidth for large _N_. With `Broadcast::set_echo_strategy` and `EchoStrategy::Partial`, each
+//! validator sends its full `Echo` only to a few of the others, so that every node receives just
+//! enough shards to decode if all are correct, and an `EchoHash` to the rest. A node that still
+//! lacks shards once it has to decode pulls them with `RequestEcho` from the nodes that only sent
+//! it the hash.
+//!
+//! The Merkle trees use SHA3-256 by default. `Broadcast::set_merkle_hasher` replaces it with any
+//! other 256-bit hash function that implements `MerkleHasher`, e.g. to use hardware acceleration.
+//!
 //!
 //! ## How it works
 //!
@@ -78,6 +101,14 @@
 //! _2 f + 1_ `Ready`s **and** _N - 2 f_ `Echo`s with root hash `h`), we know that
 //! everyone else will eventually satisfy it, too. So at that point, we can output and terminate.
 //!
+//! ### Saving bandwidth with `CanDecode`
+//!
+//! As soon as a validator has received _N - 2 f_ `Echo`s with root hash `h`, it has enough chunks
+//! to decode the value, and it multicasts `CanDecode(h)`. Validators that haven't sent their
+//! `Echo` yet then send that node only an `EchoHash(h)` instead of the full `Echo(p[i])`. An
+//! `EchoHash` counts like an `Echo` towards the _N - f_ required for sending `Ready`, but doesn't
+//! contain a chunk, so it isn't used for decoding.
+//!
 //!
 //! ## Example
 //!
@@ -168,6 +199,12 @@
 //!                     on_step(*id, step, &mut messages, &mut finished_nodes);
 //!                 }
 //!             }
+//!             Target::AllExcept(exclude) => {
+//!                 for (id, node) in nodes.iter_mut().filter(|(id, _)| !exclude.contains(id)) {
+//!                     let step = node.handle_message(&source, message.clone())?;
+//!                     on_step(*id, step, &mut messages, &mut finished_nodes);
+//!                 }
+//!             }
 //!             Target::Node(id) => {
 //!                 let step = {
 //!                     let node = nodes.get_mut(&id).unwrap();
@@ -185,10 +222,15 @@
 //! ```
 
 mod broadcast;
+mod chunk;
+mod coding;
 mod error;
 pub(crate) mod merkle;
 mod message;
 
-pub use self::broadcast::{Broadcast, Step};
+pub use self::broadcast::{Broadcast, EchoStrategy, Step};
+pub use self::chunk::Chunk;
+pub use self::coding::{CodingError, ErasureCoding, ReedSolomonCoding};
 pub use self::error::{Error, FaultKind, Result};
+pub use self::merkle::{Digest, MerkleHasher, Sha3Hasher};
 pub use self::message::Message;
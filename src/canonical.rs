@@ -0,0 +1,156 @@
+//! # Canonically encoded contributions
+//!
+//! `HoneyBadger` encodes each contribution as bytes before it encrypts and proposes it, hashes
+//! these bytes to order the contributions in a batch, and decodes them again once the batch is
+//! decided. The encoding is defined by the `CanonicalContribution` trait. Every type that
+//! implements `Serialize` and `DeserializeOwned` implements it automatically, with its `bincode`
+//! serialization as the canonical encoding.
+//!
+//! Some applications' contributions, e.g. blocks in SSZ or RLP encoding, already have a canonical
+//! binary representation that is not driven by serde. Instead of implementing serde for them,
+//! which would encode them a second time, they can implement `CanonicalContribution` themselves.
+//! `DynamicHoneyBadger` and `QueueingHoneyBadger` wrap the contributions in their own messages,
+//! so they still require serde: There, such contributions can be proposed wrapped in `Canonical`,
+//! which is serialized as the canonical bytes, with no further structure.
+
+use std::fmt::{self, Display};
+
+use serde::de::{self, DeserializeOwned, Deserializer, SeqAccess, Visitor};
+use serde::{ser, Deserialize, Serialize, Serializer};
+use tiny_keccak::sha3_256;
+
+use crate::Contribution;
+
+/// A contribution with a canonical binary encoding.
+///
+/// All correct nodes must encode a contribution to the same bytes, and decoding must reject every
+/// byte string that is not an encoding, since a faulty proposer can propose arbitrary bytes.
+pub trait CanonicalContribution: Contribution + Sized {
+    /// The error returned if the contribution can't be encoded, or the bytes are not a valid
+    /// encoding.
+    type Error: Display;
+
+    /// Returns the canonical encoding of the contribution.
+    fn to_canonical_bytes(&self) -> Result<Vec<u8>, Self::Error>;
+
+    /// Decodes a contribution from its canonical encoding.
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, Self::Error>;
+
+    /// Returns the SHA3-256 hash of the canonical encoding.
+    fn canonical_hash(&self) -> Result<[u8; 32], Self::Error> {
+        Ok(sha3_256(&self.to_canonical_bytes()?))
+    }
+}
+
+impl<C> CanonicalContribution for C
+where
+    C: Contribution + Serialize + DeserializeOwned,
+{
+    type Error = bincode::Error;
+
+    fn to_canonical_bytes(&self) -> bincode::Result<Vec<u8>> {
+        bincode::serialize(self)
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> bincode::Result<Self> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// A wrapper that serializes a `CanonicalContribution` as its canonical bytes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Canonical<C>(pub C);
+
+impl<C> Canonical<C> {
+    /// Returns the wrapped contribution.
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<C: CanonicalContribution> Serialize for Canonical<C> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let bytes = self.0.to_canonical_bytes().map_err(ser::Error::custom)?;
+        s.serialize_bytes(&bytes)
+    }
+}
+
+impl<'de, C: CanonicalContribution> Deserialize<'de> for Canonical<C> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let bytes = d.deserialize_byte_buf(BytesVisitor)?;
+        C::from_canonical_bytes(&bytes)
+            .map(Canonical)
+            .map_err(de::Error::custom)
+    }
+}
+
+/// A visitor that accepts a byte array, or a sequence of bytes in formats without byte arrays.
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a canonically encoded contribution")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Canonical, CanonicalContribution};
+
+    /// A contribution encoded as big-endian `u32`s.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+    struct Numbers(Vec<u32>);
+
+    impl CanonicalContribution for Numbers {
+        type Error = &'static str;
+
+        fn to_canonical_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+            Ok(self
+                .0
+                .iter()
+                .flat_map(|n| n.to_be_bytes().to_vec())
+                .collect())
+        }
+
+        fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, Self::Error> {
+            let chunks = bytes.chunks_exact(4);
+            if !chunks.remainder().is_empty() {
+                return Err("invalid length");
+            }
+            let to_u32 = |c: &[u8]| u32::from_be_bytes([c[0], c[1], c[2], c[3]]);
+            Ok(Numbers(chunks.map(to_u32).collect()))
+        }
+    }
+
+    #[test]
+    fn test_canonical_serialization() {
+        let contrib = Canonical(Numbers(vec![1, 2, 0xdead_beef]));
+        let ser = bincode::serialize(&contrib).expect("serialize");
+        // Only the length prefix is added to the canonical bytes.
+        let bytes = contrib.0.to_canonical_bytes().expect("encode");
+        assert_eq!(&ser[8..], &bytes[..]);
+        let de: Canonical<Numbers> = bincode::deserialize(&ser).expect("deserialize");
+        assert_eq!(contrib, de);
+        assert!(
+            bincode::deserialize::<Canonical<Numbers>>(&[3, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3]).is_err()
+        );
+    }
+}
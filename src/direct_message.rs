@@ -0,0 +1,142 @@
+//! # Sealed direct messages
+//!
+//! Some payloads exchanged between two nodes must be confidential, e.g. the encrypted key rows a
+//! joining node receives during a handshake. Instead of requiring the transport to use a second
+//! cryptographic stack, such a payload can be wrapped in a `SealedMessage`: It is encrypted to
+//! the recipient's public key and signed with the sender's secret key, both taken from the
+//! `NetworkInfo`.
+//!
+//! The plaintext contains the sender's ID, and the signature covers the sender, the recipient
+//! and the ciphertext, so a sealed message can neither be attributed to another sender nor
+//! redirected to another recipient.
+
+use crate::crypto::{Ciphertext, Signature};
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{NetworkInfo, NodeIdT};
+
+/// A sealed direct message error.
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// The recipient's public key is unknown.
+    #[fail(display = "Unknown recipient")]
+    UnknownRecipient,
+    /// The sender's public key is unknown.
+    #[fail(display = "Unknown sender")]
+    UnknownSender,
+    /// Failed to serialize the message.
+    #[fail(display = "Serialization error: {}", _0)]
+    Serialize(bincode::ErrorKind),
+    /// The sender's signature is invalid.
+    #[fail(display = "Invalid signature")]
+    InvalidSignature,
+    /// The ciphertext is invalid or was not encrypted to our public key.
+    #[fail(display = "Invalid ciphertext")]
+    InvalidCiphertext,
+    /// The plaintext could not be deserialized.
+    #[fail(display = "Deserialization error: {}", _0)]
+    Deserialize(bincode::ErrorKind),
+    /// The plaintext names a different sender than the one the message was received from.
+    #[fail(display = "Sender mismatch")]
+    SenderMismatch,
+}
+
+/// A sealed direct message result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A payload encrypted to its recipient and signed by its sender.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedMessage {
+    /// The encrypted sender ID and payload.
+    ciphertext: Ciphertext,
+    /// The sender's signature of the sender ID, recipient ID and ciphertext.
+    sig: Signature,
+}
+
+impl SealedMessage {
+    /// Encrypts `payload` to `recipient` and signs it with our secret key.
+    pub fn seal<N, R>(
+        netinfo: &NetworkInfo<N>,
+        recipient: &N,
+        payload: &[u8],
+        rng: &mut R,
+    ) -> Result<Self>
+    where
+        N: NodeIdT + Serialize,
+        R: Rng,
+    {
+        let pk = netinfo
+            .public_key(recipient)
+            .ok_or(Error::UnknownRecipient)?;
+        let plaintext = bincode::serialize(&(netinfo.our_id(), payload))
+            .map_err(|err| Error::Serialize(*err))?;
+        let ciphertext = pk.encrypt_with_rng(rng, plaintext);
+        let ser = signed_bytes(netinfo.our_id(), recipient, &ciphertext)?;
+        let sig = netinfo.signer().sign(&ser);
+        Ok(SealedMessage { ciphertext, sig })
+    }
+
+    /// Verifies that the message was sealed by `sender` for us, and returns the payload.
+    pub fn open<N>(&self, netinfo: &NetworkInfo<N>, sender: &N) -> Result<Vec<u8>>
+    where
+        N: NodeIdT + Serialize + for<'de> Deserialize<'de>,
+    {
+        let pk = netinfo.public_key(sender).ok_or(Error::UnknownSender)?;
+        let ser = signed_bytes(sender, netinfo.our_id(), &self.ciphertext)?;
+        if !pk.verify(&self.sig, ser) {
+            return Err(Error::InvalidSignature);
+        }
+        if !self.ciphertext.verify() {
+            return Err(Error::InvalidCiphertext);
+        }
+        let plaintext = netinfo
+            .signer()
+            .decrypt(&self.ciphertext)
+            .ok_or(Error::InvalidCiphertext)?;
+        let (inner_sender, payload): (N, Vec<u8>) =
+            bincode::deserialize(&plaintext).map_err(|err| Error::Deserialize(*err))?;
+        if inner_sender != *sender {
+            return Err(Error::SenderMismatch);
+        }
+        Ok(payload)
+    }
+}
+
+/// Returns the bytes covered by the sender's signature.
+fn signed_bytes<N: Serialize>(
+    sender: &N,
+    recipient: &N,
+    ciphertext: &Ciphertext,
+) -> Result<Vec<u8>> {
+    bincode::serialize(&(sender, recipient, ciphertext)).map_err(|err| Error::Serialize(*err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, SealedMessage};
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_seal_and_open() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let payload = b"key rows".to_vec();
+        let sealed = SealedMessage::seal(&netinfos[&0], &1, &payload, &mut rng).expect("seal");
+        assert_eq!(payload, sealed.open(&netinfos[&1], &0).expect("open"));
+        // Only the recipient can open it, and only as a message from the actual sender.
+        match sealed.open(&netinfos[&2], &0) {
+            Err(Error::InvalidSignature) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match sealed.open(&netinfos[&1], &2) {
+            Err(Error::InvalidSignature) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match SealedMessage::seal(&netinfos[&0], &7, b"", &mut rng) {
+            Err(Error::UnknownRecipient) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+}
@@ -1,7 +1,12 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
 
-use super::{ChangeState, JoinPlan, Params};
+use bincode;
+use serde::Serialize;
+
+use super::{ChangeState, DynamicParams, JoinPlan, Params};
+use crate::binary_agreement::DecisionCertificate;
+use crate::honey_badger::shuffle_txs;
 use crate::{NetworkInfo, NodeIdT};
 
 /// A batch of transactions the algorithm has output.
@@ -13,6 +18,10 @@ pub struct Batch<C, N: Ord> {
     pub(super) era: u64,
     /// The user contributions committed in this epoch.
     pub(super) contributions: BTreeMap<N, C>,
+    /// The contributors' node IDs, in the configured `ContributionOrder`.
+    pub(super) order: Vec<N>,
+    /// The epoch's random seed, if the `ContributionOrder` is `Shuffled`.
+    pub(super) seed: Option<[u8; 32]>,
     /// The current state of adding or removing a node: whether any is in progress, or completed
     /// this epoch.
     pub(super) change: ChangeState<N>,
@@ -20,6 +29,16 @@ pub struct Batch<C, N: Ord> {
     pub(super) netinfo: Arc<NetworkInfo<N>>,
     /// Parameters controlling Honey Badger's behavior and performance.
     pub(super) params: Params,
+    /// Parameters controlling Dynamic Honey Badger's own behavior.
+    pub(super) dynamic_params: DynamicParams,
+    /// The median of the proposers' timestamps, if any of them included one.
+    pub(super) timestamp: Option<u64>,
+    /// The certificates of the decisions whether to accept each proposer's contribution, if
+    /// enabled.
+    pub(super) agreement_certificates: BTreeMap<N, DecisionCertificate>,
+    /// The contributions as the validators agreed on them, if transactions were excluded from
+    /// `contributions` afterwards. Exports contain these, so that they match the batch certificate.
+    pub(super) agreed_contributions: Option<BTreeMap<N, C>>,
 }
 
 impl<C, N: NodeIdT> Batch<C, N> {
@@ -45,25 +64,88 @@ impl<C, N: NodeIdT> Batch<C, N> {
         &self.netinfo
     }
 
-    /// Returns the contributions and their proposers.
+    /// Returns the parameters that apply to the _next_ epoch after this one, including the current
+    /// protocol version and any scheduled upgrade.
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    /// Returns the `DynamicHoneyBadger` parameters that apply to the _next_ epoch after this one.
+    pub fn dynamic_params(&self) -> &DynamicParams {
+        &self.dynamic_params
+    }
+
+    /// Returns the median of the timestamps the proposers included in their contributions, or
+    /// `None` if none of them did.
+    ///
+    /// If at least _N - f_ contributions are timestamped, the median lies between two correct
+    /// validators' timestamps. For an even number, the lower of the two middle values is used.
+    pub fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    /// Returns the certificates of the decisions whether to accept each proposer's contribution, if
+    /// they are enabled in the `Params`. They are signed by the era's validators, in the
+    /// `HoneyBadger` instance with session ID `era` and epoch `epoch - era`: see
+    /// `honey_badger::Batch::verify_agreement_certificates`.
+    pub fn agreement_certificates(&self) -> &BTreeMap<N, DecisionCertificate> {
+        &self.agreement_certificates
+    }
+
+    /// Returns the contributions and their proposers, in the configured `ContributionOrder`.
     pub fn contributions(&self) -> impl Iterator<Item = (&N, &C)> {
-        self.contributions.iter()
+        let contributions = &self.contributions;
+        self.order
+            .iter()
+            .filter_map(move |id| contributions.get(id).map(|contrib| (id, contrib)))
     }
 
-    /// Returns an iterator over references to all transactions included in the batch.
+    /// Returns an iterator over references to all transactions included in the batch, in the
+    /// configured `ContributionOrder`.
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = <&'a C as IntoIterator>::Item>
     where
         &'a C: IntoIterator,
     {
-        self.contributions.values().flatten()
+        self.contributions().flat_map(|(_, contrib)| contrib)
     }
 
-    /// Returns an iterator over all transactions included in the batch. Consumes the batch.
+    /// Returns an iterator over all transactions included in the batch, in the configured
+    /// `ContributionOrder`. Consumes the batch.
     pub fn into_tx_iter(self) -> impl Iterator<Item = <C as IntoIterator>::Item>
     where
         C: IntoIterator,
     {
-        self.contributions.into_iter().flat_map(|(_, vec)| vec)
+        let Batch {
+            mut contributions,
+            order,
+            ..
+        } = self;
+        order
+            .into_iter()
+            .filter_map(move |id| contributions.remove(&id))
+            .flatten()
+    }
+
+    /// Returns references to all transactions included in the batch, in a pseudorandom order that
+    /// is the same on all nodes, or `None` if the `ContributionOrder` is not `Shuffled`. See
+    /// `honey_badger::Batch::shuffled_txs`.
+    pub fn shuffled_txs<'a, T>(&'a self) -> bincode::Result<Option<Vec<&'a T>>>
+    where
+        &'a C: IntoIterator<Item = &'a T>,
+        T: Serialize,
+    {
+        let seed = match self.seed {
+            Some(ref seed) => seed,
+            None => return Ok(None),
+        };
+        let contributions: Vec<&C> = self.contributions.values().collect();
+        shuffle_txs(seed, &contributions).map(Some)
+    }
+
+    /// Returns the epoch's random seed, if the `ContributionOrder` is `Shuffled`. It is the same on
+    /// all nodes, and unknown to everyone until the contributions are fixed.
+    pub fn seed(&self) -> Option<&[u8; 32]> {
+        self.seed.as_ref()
     }
 
     /// Returns the number of transactions in the batch (without detecting duplicates).
@@ -89,6 +171,38 @@ impl<C, N: NodeIdT> Batch<C, N> {
             .all(<[T]>::is_empty)
     }
 
+    /// Removes the transactions for which `f` returns `false`. The contributions are kept, even if
+    /// they become empty. The batch's export still contains the removed transactions.
+    pub(crate) fn retain_txs<T, F>(&mut self, mut f: F)
+    where
+        C: Clone + AsRef<[T]> + AsMut<Vec<T>>,
+        F: FnMut(&T) -> bool,
+    {
+        let mut txs = self.contributions.values().flat_map(C::as_ref);
+        if txs.all(&mut f) {
+            return;
+        }
+        if self.agreed_contributions.is_none() {
+            self.agreed_contributions = Some(self.contributions.clone());
+        }
+        for contrib in self.contributions.values_mut() {
+            contrib.as_mut().retain(|tx| f(tx));
+        }
+    }
+
+    /// Returns the contributions the validators agreed on, in the configured `ContributionOrder`.
+    /// Unlike `contributions`, these include any transactions that were excluded from the output,
+    /// e.g. because they expired.
+    pub fn agreed_contributions(&self) -> impl Iterator<Item = (&N, &C)> {
+        let contributions = self
+            .agreed_contributions
+            .as_ref()
+            .unwrap_or(&self.contributions);
+        self.order
+            .iter()
+            .filter_map(move |id| contributions.get(id).map(|contrib| (id, contrib)))
+    }
+
     /// Returns the `JoinPlan` to be sent to new observer nodes, if it is possible to join in the
     /// next epoch.
     pub fn join_plan(&self) -> Option<JoinPlan<N>> {
@@ -101,6 +215,9 @@ impl<C, N: NodeIdT> Batch<C, N> {
             pub_key_set: self.netinfo.public_key_set().clone(),
             pub_keys: self.netinfo.public_key_map().clone(),
             params: self.params.clone(),
+            dynamic_params: self.dynamic_params.clone(),
+            addresses: self.netinfo.address_map().clone(),
+            weights: self.netinfo.weight_map().clone(),
         })
     }
 
@@ -113,9 +230,15 @@ impl<C, N: NodeIdT> Batch<C, N> {
         self.epoch == other.epoch
             && self.era == other.era
             && self.contributions == other.contributions
+            && self.agreed_contributions == other.agreed_contributions
+            && self.order == other.order
             && self.change == other.change
             && self.netinfo.public_key_set() == other.netinfo.public_key_set()
             && self.netinfo.public_key_map() == other.netinfo.public_key_map()
+            && self.netinfo.address_map() == other.netinfo.address_map()
+            && self.netinfo.weight_map() == other.netinfo.weight_map()
             && self.params == other.params
+            && self.dynamic_params == other.dynamic_params
+            && self.timestamp == other.timestamp
     }
 }
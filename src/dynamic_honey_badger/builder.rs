@@ -1,14 +1,25 @@
+use std::collections::BTreeMap;
 use std::default::Default;
 use std::iter::once;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
-use crate::crypto::{SecretKey, SecretKeySet};
+use crate::binary_agreement::{CoinSchedule, LocalCoin};
+use crate::crypto::{PublicKey, PublicKeySet, SecretKey, SecretKeySet};
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::{DynamicHoneyBadger, EncryptionSchedule, JoinPlan, Result, Step, VoteCounter};
-use crate::honey_badger::{HoneyBadger, Params, SubsetHandlingStrategy};
-use crate::{Contribution, NetworkInfo, NodeIdT};
+use super::certificate::Certifier;
+use super::{
+    DynamicHoneyBadger, DynamicParams, EncryptionSchedule, Error, JoinPlan, PairingSignatures,
+    Result, SignatureScheme, Step, VoteCounter, VoteLimits,
+};
+use crate::honey_badger::{
+    ChangeQuorum, ConflictPolicy, ContributionOrder, FutureEpochPolicy, HoneyBadger, Params,
+    SubsetHandlingStrategy,
+};
+use crate::instrument::{Instrument, NoInstrument};
+use crate::subscribers::Subscribers;
+use crate::{Contribution, IntoSigner, KeyMaterial, NetworkInfo, NodeIdT};
 
 /// A Dynamic Honey Badger builder, to configure the parameters and create new instances of
 /// `DynamicHoneyBadger`.
@@ -19,6 +30,14 @@ pub struct DynamicHoneyBadgerBuilder<C, N> {
     epoch: u64,
     /// Parameters controlling Honey Badger's behavior and performance.
     params: Params,
+    /// Parameters controlling Dynamic Honey Badger's own behavior.
+    dynamic_params: DynamicParams,
+    /// The receiver of instrumentation events.
+    instrument: Arc<dyn Instrument<N>>,
+    /// The scheme that votes and other messages by individual nodes are signed with.
+    signature_scheme: Arc<dyn SignatureScheme<N>>,
+    /// The coin of the `BinaryAgreement` instances, if it is computed locally.
+    agreement_local_coin: Option<Arc<dyn LocalCoin>>,
     _phantom: PhantomData<(C, N)>,
 }
 
@@ -28,6 +47,10 @@ impl<C, N: Ord> Default for DynamicHoneyBadgerBuilder<C, N> {
             era: 0,
             epoch: 0,
             params: Params::default(),
+            dynamic_params: DynamicParams::default(),
+            instrument: Arc::new(NoInstrument),
+            signature_scheme: Arc::new(PairingSignatures),
+            agreement_local_coin: None,
             _phantom: PhantomData,
         }
     }
@@ -62,6 +85,12 @@ where
         self
     }
 
+    /// Sets how messages for epochs after the next `max_future_epochs` are handled.
+    pub fn future_epoch_policy(&mut self, future_epoch_policy: FutureEpochPolicy) -> &mut Self {
+        self.params.future_epoch_policy = future_epoch_policy;
+        self
+    }
+
     /// Sets the strategy to use when handling `Subset` output.
     pub fn subset_handling_strategy(
         &mut self,
@@ -77,36 +106,196 @@ where
         self
     }
 
+    /// Sets the order in which the contributions of each batch are output.
+    pub fn contribution_order(&mut self, contribution_order: ContributionOrder) -> &mut Self {
+        self.params.contribution_order = contribution_order;
+        self
+    }
+
+    /// Sets the number of votes a change to the set of validators needs to take effect.
+    pub fn change_quorum(&mut self, change_quorum: ChangeQuorum) -> &mut Self {
+        self.params.change_quorum = change_quorum;
+        self
+    }
+
+    /// Sets how contradictory changes to the set of validators are handled.
+    pub fn conflict_policy(&mut self, conflict_policy: ConflictPolicy) -> &mut Self {
+        self.params.conflict_policy = conflict_policy;
+        self
+    }
+
+    /// Sets the number of epochs after which votes for changes expire. By default, they only
+    /// expire at the end of the era.
+    ///
+    /// Votes that are not committed in time are dropped, and committed votes stop counting, so
+    /// that a vote can't suddenly tip the balance long after it was cast.
+    pub fn vote_ttl(&mut self, vote_ttl: u64) -> &mut Self {
+        self.params.vote_ttl = Some(vote_ttl);
+        self
+    }
+
+    /// Sets the number of epochs after which the validators generate new keys, for proactive
+    /// security. By default, keys are only replaced when the set of validators changes.
+    ///
+    /// Once an era has lasted that many epochs, and no other change is in progress, the validators
+    /// start a key generation among themselves, as if a `RotateKeys` change had won the vote. Since
+    /// any change starts a new era, this resets the interval, too.
+    pub fn key_rotation_interval(&mut self, interval: u64) -> &mut Self {
+        self.params.key_rotation_interval = Some(interval);
+        self
+    }
+
+    /// Makes the validators threshold-sign each batch, so that every node obtains a
+    /// `BatchCertificate` for it: see `DynamicHoneyBadger::set_certificate_hook`.
+    pub fn batch_certificates(&mut self, batch_certificates: bool) -> &mut Self {
+        self.params.batch_certificates = batch_certificates;
+        self
+    }
+
+    /// Sets the receiver of instrumentation events, for the new instance and its `HoneyBadger`
+    /// instances.
+    pub fn instrument(&mut self, instrument: Arc<dyn Instrument<N>>) -> &mut Self {
+        self.instrument = instrument;
+        self
+    }
+
+    /// Sets a coin that is computed locally, to use in the `BinaryAgreement` instances instead of
+    /// the threshold signature: see `BinaryAgreement::set_local_coin`. All nodes must use
+    /// equivalent coins. Since it can't be serialized, it is not part of the `Params` or the
+    /// `JoinPlan`: Joining nodes must set it with `DynamicHoneyBadger::set_agreement_local_coin`.
+    pub fn agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> &mut Self {
+        self.agreement_local_coin = Some(coin);
+        self
+    }
+
+    /// Sets the scheme that votes, key generation messages and address announcements are signed
+    /// with. All nodes must use the same scheme. By default, these are signatures with the nodes'
+    /// pairing-based keys; threshold cryptography is not affected.
+    pub fn signature_scheme(&mut self, signature_scheme: Arc<dyn SignatureScheme<N>>) -> &mut Self {
+        self.signature_scheme = signature_scheme;
+        self
+    }
+
+    /// Sets the maximum size in bytes of each proposed value, i.e. of the serialized contribution,
+    /// or of its ciphertext if the epoch is encrypted. Larger proposals are never output, and the
+    /// nodes that send their shards are reported as faulty.
+    pub fn max_contribution_size(&mut self, max_contribution_size: usize) -> &mut Self {
+        self.params.max_contribution_size = Some(max_contribution_size);
+        self
+    }
+
+    /// Sets the coin schedule of the `BinaryAgreement` instances: see
+    /// `BinaryAgreement::set_variant`.
+    pub fn agreement_coin_schedule(&mut self, schedule: CoinSchedule) -> &mut Self {
+        self.params.agreement_coin_schedule = Some(schedule);
+        self
+    }
+
+    /// Enables or disables decision certificates in the `BinaryAgreement` instances: If enabled,
+    /// each batch contains a `DecisionCertificate` for each proposer, proving whether its
+    /// contribution was accepted. This takes one more message round per epoch.
+    pub fn agreement_certificates(&mut self, certify: bool) -> &mut Self {
+        self.params.agreement_certificates = certify;
+        self
+    }
+
+    /// Sets the number of epochs after which stalled `BinaryAgreement` instances are reported to
+    /// the instrument: see `BinaryAgreement::set_max_epochs`.
+    pub fn agreement_max_epochs(&mut self, max_epochs: u64) -> &mut Self {
+        self.params.agreement_max_epochs = Some(max_epochs);
+        self
+    }
+
+    /// Enables or disables batch verification of the signature shares in the `BinaryAgreement`
+    /// instances: see `ThresholdSign::set_batch_verification`. This saves pairings with many
+    /// validators, at the cost of an extra pass to identify the faulty shares if a batch fails.
+    pub fn agreement_batch_verification(&mut self, batch_verification: bool) -> &mut Self {
+        self.params.agreement_batch_verification = batch_verification;
+        self
+    }
+
+    /// Sets the number of coin flips for which each `BinaryAgreement` instance computes our coin
+    /// shares when it is created, instead of when the coin is invoked: see
+    /// `BinaryAgreement::precompute_coin_shares`. Since the instances of future epochs are created
+    /// when their first message arrives, this moves the signing off the critical path.
+    pub fn agreement_precomputed_coin_shares(&mut self, count: usize) -> &mut Self {
+        self.params.agreement_precomputed_coin_shares = count;
+        self
+    }
+
     /// Sets the parameters controlling Honey Badger's behavior and performance.
     pub fn params(&mut self, params: Params) -> &mut Self {
         self.params = params;
         self
     }
 
+    /// Sets the parameters controlling Dynamic Honey Badger's own behavior.
+    pub fn dynamic_params(&mut self, dynamic_params: DynamicParams) -> &mut Self {
+        self.dynamic_params = dynamic_params;
+        self
+    }
+
+    /// Sets the limits on the pending change votes.
+    pub fn vote_limits(&mut self, vote_limits: VoteLimits) -> &mut Self {
+        self.dynamic_params.vote_limits = vote_limits;
+        self
+    }
+
     /// Creates a new Dynamic Honey Badger instance with an empty buffer.
     pub fn build(&mut self, netinfo: NetworkInfo<N>) -> DynamicHoneyBadger<C, N> {
         let DynamicHoneyBadgerBuilder {
             era,
             epoch,
             params,
+            dynamic_params,
+            instrument,
+            signature_scheme,
+            agreement_local_coin,
             _phantom,
         } = self;
         let arc_netinfo = Arc::new(netinfo.clone());
+        let mut vote_counter = VoteCounter::new(
+            arc_netinfo.clone(),
+            0,
+            dynamic_params.vote_limits,
+            params.change_quorum,
+            params.conflict_policy,
+            params.vote_ttl,
+        );
+        vote_counter.set_signature_scheme(signature_scheme.clone());
 
-        let honey_badger = HoneyBadger::builder(arc_netinfo.clone())
+        let mut hb_builder = HoneyBadger::builder(arc_netinfo.clone());
+        hb_builder
             .session_id(*era)
             .epoch(*epoch)
             .params(params.clone())
-            .build();
+            .instrument(instrument.clone());
+        if let Some(coin) = agreement_local_coin {
+            hb_builder.agreement_local_coin(coin.clone());
+        }
+        let honey_badger = hb_builder.build();
 
         DynamicHoneyBadger {
             netinfo,
             max_future_epochs: params.max_future_epochs,
+            dynamic_params: dynamic_params.clone(),
             era: *era,
-            vote_counter: VoteCounter::new(arc_netinfo, 0),
+            vote_counter,
             key_gen_msg_buffer: Vec::new(),
+            address_buffer: Vec::new(),
             honey_badger,
             key_gen_state: None,
+            era_hook: None,
+            clock: None,
+            removal: None,
+            era_change: None,
+            certifier: Certifier::new(),
+            certificate_hook: None,
+            instrument: instrument.clone(),
+            future_message_hook: None,
+            subscribers: Subscribers::default(),
+            signature_scheme: signature_scheme.clone(),
+            agreement_local_coin: agreement_local_coin.clone(),
         }
     }
 
@@ -125,15 +314,51 @@ where
         Ok(self.build(netinfo))
     }
 
+    /// Creates a new `DynamicHoneyBadger` that starts as an observer of the network with the given
+    /// validators, in the configured era and epoch.
+    ///
+    /// The observer has no key share, but follows the epochs. Once a change that adds `our_id`
+    /// begins, it takes part in the key generation, and when that completes, it switches to its
+    /// new key share in the following era and continues as a validator, without being recreated.
+    /// For that, it must be started before the change begins; to join a network at a later point,
+    /// use `DynamicHoneyBadger::new_joining` with a `JoinPlan` instead.
+    pub fn build_observer(
+        &mut self,
+        our_id: N,
+        secret_key: SecretKey,
+        pub_key_set: PublicKeySet,
+        pub_keys: BTreeMap<N, PublicKey>,
+    ) -> Result<DynamicHoneyBadger<C, N>> {
+        let netinfo = NetworkInfo::try_new(our_id, None, pub_key_set, secret_key, pub_keys)
+            .map_err(Error::InvalidObserverKeys)?;
+        Ok(self.build(netinfo))
+    }
+
+    /// Creates a new `DynamicHoneyBadger` with threshold keys that were generated externally, e.g.
+    /// in an offline ceremony, in the configured era and epoch. All validators must start in the
+    /// same era and epoch.
+    ///
+    /// Returns an error if the key material is inconsistent, or doesn't match our secret key.
+    pub fn build_from_key_material(
+        &mut self,
+        our_id: N,
+        secret_key: SecretKey,
+        key_material: KeyMaterial<N>,
+    ) -> Result<DynamicHoneyBadger<C, N>> {
+        let netinfo = NetworkInfo::from_key_material(our_id, secret_key, key_material)
+            .map_err(Error::InvalidKeyMaterial)?;
+        Ok(self.build(netinfo))
+    }
+
     /// Creates a new `DynamicHoneyBadger` configured to join the network at the epoch specified in
     /// the `JoinPlan`. This ignores the builder's configuration settings.
     ///
     /// **Deprecated**: Please use `DynamicHoneyBadger::new_joining` instead.
     #[deprecated]
-    pub fn build_joining<R: rand::Rng>(
+    pub fn build_joining<S: IntoSigner, R: rand::Rng>(
         &mut self,
         our_id: N,
-        secret_key: SecretKey,
+        secret_key: S,
         join_plan: JoinPlan<N>,
         rng: &mut R,
     ) -> Result<(DynamicHoneyBadger<C, N>, Step<C, N>)> {
@@ -0,0 +1,213 @@
+//! Threshold-signed certificates for batches.
+//!
+//! If `Params::batch_certificates` is set, each validator signs the hash of every batch it
+//! outputs with its secret key share, and sends the signature share to all nodes. Once a node has
+//! _f + 1_ valid shares, it combines them into a `BatchCertificate`: a signature by the era's
+//! public key set, which proves to anyone who knows that key set that the batch was agreed on,
+//! without having to trust a single validator.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::crypto::{PublicKeySet, Signature};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tiny_keccak::sha3_256;
+
+use super::{Error, ExportedBatch, FaultKind, Message, Result, Step};
+use crate::fault_log::Fault;
+use crate::threshold_sign::{self, ThresholdSign};
+use crate::{Contribution, NetworkInfo, NodeIdT};
+
+/// A threshold signature of a batch by the validators of the batch's era.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchCertificate {
+    /// The era of the batch.
+    pub era: u64,
+    /// The linear epoch of the batch.
+    pub epoch: u64,
+    /// The hash of the batch's export encoding, without a signature: see `ExportedBatch::hash`.
+    pub hash: [u8; 32],
+    /// The signature of the era, epoch and hash, by the era's public key set.
+    pub signature: Signature,
+}
+
+impl BatchCertificate {
+    /// Returns `true` if the certificate belongs to the given batch, and is signed by the given
+    /// public key set, i.e. that of the batch's era.
+    pub fn verify<N>(&self, batch: &ExportedBatch<N>, pub_key_set: &PublicKeySet) -> Result<bool>
+    where
+        N: NodeIdT + Serialize + DeserializeOwned,
+    {
+        if (self.era, self.epoch) != (batch.era, batch.epoch) || self.hash != batch.hash()? {
+            return Ok(false);
+        }
+        let doc = signed_doc(self.era, self.epoch, &self.hash)?;
+        Ok(pub_key_set.public_key().verify(&self.signature, doc))
+    }
+}
+
+/// Returns the document the validators sign for a batch.
+fn signed_doc(era: u64, epoch: u64, hash: &[u8; 32]) -> Result<Vec<u8>> {
+    bincode::serialize(&(era, epoch, hash)).map_err(|err| Error::SerializeExport(*err))
+}
+
+impl<N: NodeIdT + Serialize + DeserializeOwned> ExportedBatch<N> {
+    /// Returns the hash of the batch's encoding, with the signature removed.
+    pub fn hash(&self) -> Result<[u8; 32]> {
+        let bytes = if self.signature.is_some() {
+            let mut unsigned = self.clone();
+            unsigned.signature = None;
+            unsigned.encode()?
+        } else {
+            self.encode()?
+        };
+        Ok(sha3_256(&bytes))
+    }
+}
+
+/// The signature of a single batch.
+#[derive(Debug)]
+struct Signer<N> {
+    /// The batch's era.
+    era: u64,
+    /// The hash of the batch, once we have output it.
+    hash: Option<[u8; 32]>,
+    /// The network info of the batch's era.
+    netinfo: Arc<NetworkInfo<N>>,
+    /// The threshold signing instance.
+    ts: ThresholdSign<N>,
+}
+
+impl<N: NodeIdT> Signer<N> {
+    fn new(era: u64, netinfo: &Arc<NetworkInfo<N>>) -> Self {
+        Signer {
+            era,
+            hash: None,
+            netinfo: netinfo.clone(),
+            ts: ThresholdSign::new(netinfo.clone()),
+        }
+    }
+}
+
+/// The ongoing signatures of the recent batches.
+#[derive(Debug)]
+pub(super) struct Certifier<N> {
+    /// The signatures, by the batches' linear epochs.
+    signers: BTreeMap<u64, Signer<N>>,
+}
+
+impl<N> Certifier<N>
+where
+    N: NodeIdT + Serialize + DeserializeOwned,
+{
+    pub(super) fn new() -> Self {
+        Certifier {
+            signers: BTreeMap::new(),
+        }
+    }
+
+    /// Signs the batch we just output, using the key share of the batch's era, and returns the
+    /// certificate, if it is complete.
+    pub(super) fn sign_batch<C>(
+        &mut self,
+        netinfo: &Arc<NetworkInfo<N>>,
+        era: u64,
+        epoch: u64,
+        hash: [u8; 32],
+    ) -> Result<(Step<C, N>, Option<BatchCertificate>)>
+    where
+        C: Contribution + Serialize + DeserializeOwned,
+    {
+        let doc = signed_doc(era, epoch, &hash)?;
+        let signer = self
+            .signers
+            .entry(epoch)
+            .or_insert_with(|| Signer::new(era, netinfo));
+        if signer.era != era {
+            // The shares we received early claimed the wrong era.
+            *signer = Signer::new(era, netinfo);
+        }
+        signer.hash = Some(hash);
+        signer
+            .ts
+            .set_document(doc)
+            .map_err(Error::BatchCertificate)?;
+        let ts_step = signer.ts.sign().map_err(Error::BatchCertificate)?;
+        Ok(self.finish(era, epoch, ts_step))
+    }
+
+    /// Handles a signature share for the batch in the given era and epoch. If we haven't output
+    /// the batch yet, `netinfo` must be that of the current era.
+    pub(super) fn handle_share<C>(
+        &mut self,
+        netinfo: &Arc<NetworkInfo<N>>,
+        sender_id: &N,
+        era: u64,
+        epoch: u64,
+        msg: threshold_sign::Message,
+    ) -> Result<(Step<C, N>, Option<BatchCertificate>)>
+    where
+        C: Contribution + Serialize + DeserializeOwned,
+    {
+        let signer = self
+            .signers
+            .entry(epoch)
+            .or_insert_with(|| Signer::new(era, netinfo));
+        if signer.era != era || !signer.netinfo.is_node_validator(sender_id) {
+            let fault_kind = FaultKind::UnexpectedBatchSignature;
+            return Ok((Fault::new(sender_id.clone(), fault_kind).into(), None));
+        }
+        let ts_step = signer
+            .ts
+            .handle_message(sender_id, msg)
+            .map_err(Error::BatchCertificate)?;
+        Ok(self.finish(era, epoch, ts_step))
+    }
+
+    /// Returns `true` if a share for the given epoch can be handled: if we have an ongoing
+    /// signature for it, or haven't output the batch yet but will in the next `max_future_epochs`.
+    pub(super) fn accepts(&self, epoch: u64, next_epoch: u64, max_future_epochs: u64) -> bool {
+        self.signers.contains_key(&epoch)
+            || (epoch >= next_epoch && epoch <= next_epoch + max_future_epochs)
+    }
+
+    /// Drops the incomplete signatures of batches more than `max_future_epochs` before the next
+    /// epoch.
+    pub(super) fn prune(&mut self, next_epoch: u64, max_future_epochs: u64) {
+        let min_epoch = next_epoch.saturating_sub(max_future_epochs + 1);
+        self.signers = self.signers.split_off(&min_epoch);
+    }
+
+    /// Converts the signing step, and if the signature is complete, removes the signer and returns
+    /// the certificate.
+    fn finish<C>(
+        &mut self,
+        era: u64,
+        epoch: u64,
+        ts_step: threshold_sign::Step<N>,
+    ) -> (Step<C, N>, Option<BatchCertificate>)
+    where
+        C: Contribution + Serialize + DeserializeOwned,
+    {
+        let mut step = Step::default();
+        let output = step.extend_with(ts_step, FaultKind::BatchSignatureFault, |msg| {
+            Message::BatchSignature(era, epoch, msg)
+        });
+        let signature = match output.into_iter().next() {
+            Some(signature) => signature,
+            None => return (step, None),
+        };
+        let certificate = match self.signers.remove(&epoch) {
+            Some(Signer {
+                hash: Some(hash), ..
+            }) => Some(BatchCertificate {
+                era,
+                epoch,
+                hash,
+                signature,
+            }),
+            _ => None,
+        };
+        (step, certificate)
+    }
+}
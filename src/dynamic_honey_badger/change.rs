@@ -3,7 +3,7 @@ use std::collections::BTreeMap;
 use crate::crypto::PublicKey;
 use serde::{Deserialize, Serialize};
 
-use super::EncryptionSchedule;
+use super::{ChangeQuorum, ConflictPolicy, EncryptionSchedule, Params, ProtocolUpgrade};
 
 /// A node change action: adding or removing a node.
 #[derive(Clone, Eq, PartialEq, Serialize, Deserialize, Hash, Debug)]
@@ -15,6 +15,74 @@ pub enum Change<N: Ord> {
     /// Change the threshold encryption schedule.
     /// Increase frequency to prevent censorship or decrease frequency for increased throughput.
     EncryptionSchedule(EncryptionSchedule),
+    /// Schedule a switch to a new protocol version. Unlike the other changes, this requires
+    /// _2 f + 1_ votes, so that at least _f + 1_ correct validators are ready for the new version.
+    ProtocolUpgrade(ProtocolUpgrade),
+    /// Change one of the runtime parameters. Like a protocol upgrade, this requires _2 f + 1_
+    /// votes, and it takes effect in the next era.
+    Params(ParamChange),
+    /// Run a new key generation among the current validators, replacing the shared key without
+    /// changing the set of validators. This needs the same number of votes as a `NodeChange`.
+    RotateKeys,
+}
+
+/// A change to one of the runtime parameters in `Params`.
+#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash, Debug)]
+pub enum ParamChange {
+    /// Change the maximum number of future epochs for which messages are handled.
+    MaxFutureEpochs(u64),
+    /// Change the number of votes a change to the set of validators needs.
+    ChangeQuorum(ChangeQuorum),
+    /// Change how contradictory changes to the set of validators are handled.
+    ConflictPolicy(ConflictPolicy),
+    /// Change the number of epochs after which votes expire.
+    VoteTtl(Option<u64>),
+    /// Change the number of epochs after which the keys are rotated.
+    KeyRotationInterval(Option<u64>),
+}
+
+impl ParamChange {
+    /// Applies the change to the given parameters.
+    pub fn apply(self, params: &mut Params) {
+        match self {
+            ParamChange::MaxFutureEpochs(epochs) => params.max_future_epochs = epochs,
+            ParamChange::ChangeQuorum(quorum) => params.change_quorum = quorum,
+            ParamChange::ConflictPolicy(policy) => params.conflict_policy = policy,
+            ParamChange::VoteTtl(vote_ttl) => params.vote_ttl = vote_ttl,
+            ParamChange::KeyRotationInterval(interval) => params.key_rotation_interval = interval,
+        }
+    }
+}
+
+impl<N: Ord> Change<N> {
+    /// Returns `true` if this and the `other` change are both validator set changes, and alter
+    /// the same node of the `current` validators in different ways, e.g. one adds it and the
+    /// other doesn't, or they add it with different keys.
+    pub fn conflicts_with(&self, other: &Change<N>, current: &BTreeMap<N, PublicKey>) -> bool {
+        let (ours, theirs) = match (self, other) {
+            (Change::NodeChange(ours), Change::NodeChange(theirs)) => (ours, theirs),
+            _ => return false,
+        };
+        ours.keys().chain(theirs.keys()).any(|id| {
+            let (ours, theirs, now) = (ours.get(id), theirs.get(id), current.get(id));
+            ours != now && theirs != now && ours != theirs
+        })
+    }
+
+    /// Returns the number of votes a change needs to win, given the quorum for validator set
+    /// changes, and the numbers of validators and faulty nodes.
+    pub(super) fn vote_threshold(
+        &self,
+        quorum: ChangeQuorum,
+        num_nodes: usize,
+        num_faulty: usize,
+    ) -> usize {
+        match self {
+            Change::NodeChange(_) | Change::RotateKeys => quorum.threshold(num_nodes, num_faulty),
+            Change::EncryptionSchedule(_) => num_faulty + 1,
+            Change::ProtocolUpgrade(_) | Change::Params(_) => 2 * num_faulty + 1,
+        }
+    }
 }
 
 /// A change status: whether a change to the network is currently in progress or completed.
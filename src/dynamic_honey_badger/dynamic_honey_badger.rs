@@ -1,26 +1,41 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 use std::{fmt, result};
 
-use crate::crypto::{PublicKey, SecretKey, Signature};
-use bincode;
+use crate::crypto::PublicKey;
 use derivative::Derivative;
 use log::debug;
 use rand::Rng;
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::votes::{SignedVote, VoteCounter};
+use super::certificate::Certifier;
+use super::removal_policy::{RemovalPolicy, RemovalState};
+use super::signed_bytes;
+use super::signing::{NodeSignature, PairingSignatures, SignatureScheme};
+use super::votes::{SignedVote, VoteCounter, VoteState, VoteTally};
 use super::{
-    Batch, Change, ChangeState, DynamicHoneyBadgerBuilder, EncryptionSchedule, Error, FaultKind,
-    Input, InternalContrib, JoinPlan, KeyGenMessage, KeyGenState, Message, Params, Result,
-    SignedKeyGenMsg, Step,
+    Batch, BatchCertificate, Change, ChangeState, DynamicHoneyBadgerBuilder, DynamicParams,
+    EncryptionSchedule, EraTransition, Error, FaultKind, Input, InternalContrib, JoinPlan,
+    KeyGenCheckpoint, KeyGenMessage, KeyGenProgress, KeyGenState, Message, ParamChange, Params,
+    ProtocolUpgrade, Result, SignedAddress, SignedKeyGenMsg, SignedKind, Step,
 };
+use crate::binary_agreement::LocalCoin;
 use crate::fault_log::{Fault, FaultLog};
-use crate::honey_badger::{self, HoneyBadger, Message as HbMessage};
+use crate::honey_badger::{
+    self, BufferedMessages, FutureMessageHook, HoneyBadger, MemoryStats, Message as HbMessage,
+};
 
+use crate::header::{Algorithm, DescribeMessage};
+use crate::instrument::{CryptoOp, Instrument, NoInstrument, Transition};
+use crate::subscribers::Subscribers;
 use crate::sync_key_gen::{Ack, AckOutcome, Part, PartOutcome, SyncKeyGen};
+use crate::threshold_sign;
 use crate::util;
-use crate::{ConsensusProtocol, Contribution, Epoched, NetworkInfo, NodeIdT, Target};
+use crate::{
+    Connectivity, ConsensusProtocol, Contribution, Epoched, IntoSigner, NetworkInfo, NodeIdT,
+    Target,
+};
 
 /// A Honey Badger instance that can handle adding and removing nodes.
 #[derive(Derivative)]
@@ -30,18 +45,58 @@ pub struct DynamicHoneyBadger<C, N: Ord> {
     pub(super) netinfo: NetworkInfo<N>,
     /// The maximum number of future epochs for which we handle messages simultaneously.
     pub(super) max_future_epochs: u64,
+    /// Parameters controlling Dynamic Honey Badger's own behavior.
+    pub(super) dynamic_params: DynamicParams,
     /// The first epoch after the latest node change.
     pub(super) era: u64,
     /// The buffer and counter for the pending and committed change votes.
     pub(super) vote_counter: VoteCounter<N>,
     /// Pending node transactions that we will propose in the next epoch.
     pub(super) key_gen_msg_buffer: Vec<SignedKeyGenMsg<N>>,
+    /// Pending address announcements that we will propose in the next epoch.
+    pub(super) address_buffer: Vec<SignedAddress<N>>,
     /// The `HoneyBadger` instance with the current set of nodes.
     pub(super) honey_badger: HoneyBadger<InternalContrib<C, N>, N>,
     /// The current key generation process, and the change it applies to.
     pub(super) key_gen_state: Option<KeyGenState<N>>,
+    /// The application's hook, called whenever a new era begins.
+    #[derivative(Debug = "ignore")]
+    pub(super) era_hook: Option<EraHook<N>>,
+    /// The application's clock, used to timestamp our contributions.
+    #[derivative(Debug = "ignore")]
+    pub(super) clock: Option<Clock>,
+    /// The policy for voting to remove faulty validators, and the faults counted so far.
+    pub(super) removal: Option<RemovalState<N>>,
+    /// The completed change that started the current era, if any.
+    pub(super) era_change: Option<Change<N>>,
+    /// The ongoing signatures of recent batches.
+    pub(super) certifier: Certifier<N>,
+    /// The application's hook, called with each completed batch certificate.
+    #[derivative(Debug = "ignore")]
+    pub(super) certificate_hook: Option<CertificateHook>,
+    /// The receiver of instrumentation events.
+    #[derivative(Debug = "ignore")]
+    pub(super) instrument: Arc<dyn Instrument<N>>,
+    /// The application's hook for `HoneyBadger` messages from epochs too far in the future.
+    #[derivative(Debug = "ignore")]
+    pub(super) future_message_hook: Option<FutureMessageHook<N>>,
+    /// The application's hooks and channels that receive the batches.
+    pub(super) subscribers: Subscribers<Batch<C, N>>,
+    /// The scheme that votes and other messages by individual nodes are signed with.
+    pub(super) signature_scheme: Arc<dyn SignatureScheme<N>>,
+    /// The coin of the `BinaryAgreement` instances, if it is computed locally.
+    pub(super) agreement_local_coin: Option<Arc<dyn LocalCoin>>,
 }
 
+/// A hook called synchronously at each era transition.
+pub(super) type EraHook<N> = Box<dyn FnMut(&EraTransition<'_, N>) + Send + Sync>;
+
+/// A hook called synchronously with each completed batch certificate.
+pub(super) type CertificateHook = Box<dyn FnMut(&BatchCertificate) + Send + Sync>;
+
+/// A function returning the current time.
+pub(super) type Clock = Box<dyn Fn() -> u64 + Send + Sync>;
+
 impl<C, N> ConsensusProtocol for DynamicHoneyBadger<C, N>
 where
     C: Contribution + Serialize + DeserializeOwned,
@@ -92,40 +147,88 @@ where
     }
 
     /// Creates a new `DynamicHoneyBadger` ready to join the network specified in the `JoinPlan`.
-    pub fn new_joining<R: Rng>(
+    pub fn new_joining<S: IntoSigner, R: Rng>(
+        our_id: N,
+        secret_key: S,
+        join_plan: JoinPlan<N>,
+        rng: &mut R,
+    ) -> Result<(Self, Step<C, N>)> {
+        let scheme = Arc::new(PairingSignatures);
+        DynamicHoneyBadger::new_joining_with_scheme(our_id, secret_key, join_plan, scheme, rng)
+    }
+
+    /// Creates a new `DynamicHoneyBadger` ready to join the network specified in the `JoinPlan`,
+    /// which signs votes and other messages with the given scheme, like the network's nodes.
+    pub fn new_joining_with_scheme<S: IntoSigner, R: Rng>(
         our_id: N,
-        secret_key: SecretKey,
+        secret_key: S,
         join_plan: JoinPlan<N>,
+        signature_scheme: Arc<dyn SignatureScheme<N>>,
         rng: &mut R,
     ) -> Result<(Self, Step<C, N>)> {
-        let netinfo = NetworkInfo::new(
+        let mut netinfo = NetworkInfo::try_with_signers(
             our_id,
             None,
             join_plan.pub_key_set,
-            secret_key,
+            secret_key.into_signer(),
             join_plan.pub_keys,
-        );
+        )
+        .map_err(Error::InvalidJoinPlan)?;
+        for (id, address) in join_plan.addresses {
+            netinfo.set_address(id, address);
+        }
+        for (id, weight) in join_plan.weights {
+            netinfo.set_weight(id, weight);
+        }
         let max_future_epochs = join_plan.params.max_future_epochs;
+        let change_quorum = join_plan.params.change_quorum;
+        let conflict_policy = join_plan.params.conflict_policy;
+        let vote_ttl = join_plan.params.vote_ttl;
         let arc_netinfo = Arc::new(netinfo.clone());
         let honey_badger = HoneyBadger::builder(arc_netinfo.clone())
             .session_id(join_plan.era)
             .params(join_plan.params)
             .build();
+        let mut vote_counter = VoteCounter::new(
+            arc_netinfo,
+            join_plan.era,
+            join_plan.dynamic_params.vote_limits,
+            change_quorum,
+            conflict_policy,
+            vote_ttl,
+        );
+        vote_counter.set_signature_scheme(signature_scheme.clone());
         let mut dhb = DynamicHoneyBadger {
             netinfo,
             max_future_epochs,
+            dynamic_params: join_plan.dynamic_params,
             era: join_plan.era,
-            vote_counter: VoteCounter::new(arc_netinfo, join_plan.era),
+            vote_counter,
             key_gen_msg_buffer: Vec::new(),
+            address_buffer: Vec::new(),
             honey_badger,
             key_gen_state: None,
+            era_hook: None,
+            clock: None,
+            removal: None,
+            era_change: None,
+            certifier: Certifier::new(),
+            certificate_hook: None,
+            instrument: Arc::new(NoInstrument),
+            future_message_hook: None,
+            subscribers: Subscribers::default(),
+            signature_scheme,
+            agreement_local_coin: None,
         };
         let step = match join_plan.change {
-            ChangeState::InProgress(ref change) => match change {
-                Change::NodeChange(change) => dhb.update_key_gen(join_plan.era, change, rng)?,
-                _ => Step::default(),
-            },
-            ChangeState::None | ChangeState::Complete(..) => Step::default(),
+            ChangeState::InProgress(ref change) => {
+                dhb.update_key_gen(join_plan.era, change, rng)?
+            }
+            ChangeState::Complete(change) => {
+                dhb.era_change = Some(change);
+                Step::default()
+            }
+            ChangeState::None => Step::default(),
         };
         Ok((dhb, step))
     }
@@ -142,6 +245,14 @@ where
     /// If we are the only validator, this will immediately output a batch, containing our
     /// proposal.
     pub fn propose<R: Rng>(&mut self, contrib: C, rng: &mut R) -> Result<Step<C, N>> {
+        // If our address announcement was made in an earlier era, sign it again for this one.
+        let mut step = Step::default();
+        let era = self.era;
+        if let Some(pos) = self.address_buffer.iter().position(|sa| sa.0 < era) {
+            let SignedAddress(_, _, address, _) = self.address_buffer.remove(pos);
+            step.extend(self.announce_address(address)?);
+        }
+
         let key_gen_messages = self
             .key_gen_msg_buffer
             .iter()
@@ -149,17 +260,29 @@ where
             .cloned()
             .collect();
 
+        let addresses = self
+            .address_buffer
+            .iter()
+            .filter(|signed_addr| signed_addr.0 == self.era)
+            .cloned()
+            .collect();
+
         let contrib = InternalContrib {
             contrib,
             key_gen_messages,
             votes: self.vote_counter.pending_votes().cloned().collect(),
+            addresses,
+            timestamp: self.clock.as_ref().map(|clock| clock()),
         };
 
-        let step = self
+        let hb_step = self
             .honey_badger
             .propose(&contrib, rng)
             .map_err(Error::ProposeHoneyBadger)?;
-        self.process_output(step, rng)
+        step.extend(self.process_output(hb_step, rng)?);
+        let step = self.apply_removal_policy(step)?;
+        self.report_sent(&step);
+        Ok(step)
     }
 
     /// Casts a vote to change the set of validators or parameters.
@@ -171,8 +294,26 @@ where
             return Ok(Step::default()); // TODO: Return an error?
         }
         let signed_vote = self.vote_counter.sign_vote_for(change)?.clone();
-        let msg = Message::SignedVote(signed_vote);
-        Ok(Target::All.message(msg).into())
+        self.instrument.crypto_op(CryptoOp::Sign);
+        let step = Target::All.message(Message::SignedVote(signed_vote)).into();
+        self.report_sent(&step);
+        Ok(step)
+    }
+
+    /// Retracts our vote, without voting for another change.
+    ///
+    /// This stores a pending vote that revokes our previous one. Once it is included in some future
+    /// batch, the previous vote doesn't count anymore, so a proposed change can be canceled
+    /// before it has enough votes.
+    pub fn retract_vote(&mut self) -> Result<Step<C, N>> {
+        if !self.netinfo.is_validator() {
+            return Ok(Step::default());
+        }
+        let signed_vote = self.vote_counter.sign_retraction()?.clone();
+        self.instrument.crypto_op(CryptoOp::Sign);
+        let step = Target::All.message(Message::SignedVote(signed_vote)).into();
+        self.report_sent(&step);
+        Ok(step)
     }
 
     /// Casts a vote to add a node as a validator.
@@ -185,6 +326,15 @@ where
         self.vote_for(Change::NodeChange(pub_keys))
     }
 
+    /// Casts a vote to add all the given nodes as validators.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, all nodes are added in a single
+    /// key generation, instead of one era for each of them.
+    pub fn vote_to_add_all(&mut self, added: BTreeMap<N, PublicKey>) -> Result<Step<C, N>> {
+        self.vote_to_replace(&BTreeSet::new(), added)
+    }
+
     /// Casts a vote to demote a validator to observer.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -195,6 +345,175 @@ where
         self.vote_for(Change::NodeChange(pub_keys))
     }
 
+    /// Casts a vote to demote the `removed` validators to observers and add the `added` nodes as
+    /// validators, all at once.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, it will take effect in a single
+    /// key generation.
+    pub fn vote_to_replace(
+        &mut self,
+        removed: &BTreeSet<N>,
+        added: BTreeMap<N, PublicKey>,
+    ) -> Result<Step<C, N>> {
+        let pub_keys = self
+            .netinfo
+            .public_key_map()
+            .iter()
+            .filter(|(node_id, _)| !removed.contains(node_id))
+            .map(|(node_id, pub_key)| (node_id.clone(), *pub_key))
+            .chain(added)
+            .collect();
+        self.vote_for(Change::NodeChange(pub_keys))
+    }
+
+    /// Returns the committed votes for changes in the current era, and how many each change needs
+    /// to win, e.g. for monitoring.
+    pub fn vote_tally(&self) -> VoteTally<'_, N> {
+        self.vote_counter.tally()
+    }
+
+    /// Returns a checkpoint of the pending and committed votes in the current era. After a restart,
+    /// it can be passed to `restore_vote_state`, so that the node doesn't forget the votes or sign
+    /// new ones with stale numbers.
+    pub fn vote_state(&self) -> VoteState<N> {
+        self.vote_counter.state()
+    }
+
+    /// Replaces the votes with the given checkpoint, which must belong to the current era.
+    pub fn restore_vote_state(&mut self, state: VoteState<N>) -> Result<()> {
+        if state.era() != self.era {
+            return Err(Error::VoteStateEra(state.era()));
+        }
+        let netinfo = Arc::new(self.netinfo.clone());
+        let params = self.honey_badger.params();
+        self.vote_counter = VoteCounter::restore(
+            netinfo,
+            state,
+            self.dynamic_params.vote_limits,
+            params.change_quorum,
+            params.conflict_policy,
+            params.vote_ttl,
+        )?;
+        self.vote_counter
+            .set_signature_scheme(self.signature_scheme.clone());
+        Ok(())
+    }
+
+    /// Returns a checkpoint of the ongoing key generation, if any. After a restart in the same era,
+    /// it can be passed to `restore_key_gen`, so that the node continues the key generation.
+    pub fn key_gen_checkpoint(&self) -> Option<KeyGenCheckpoint<N>> {
+        let era = self.era;
+        let pending = self
+            .key_gen_msg_buffer
+            .iter()
+            .filter(|kg_msg| kg_msg.era() == era)
+            .cloned()
+            .collect();
+        let kgs = self.key_gen_state.as_ref()?;
+        Some(kgs.checkpoint(era, pending))
+    }
+
+    /// Restores the key generation from the given checkpoint, which must belong to the current
+    /// era.
+    ///
+    /// The committed messages are replayed, and our own `Part` and `Ack`s, if they were neither
+    /// committed nor pending, are sent again. Faults found during the replay are not reported
+    /// again.
+    pub fn restore_key_gen<R: Rng>(
+        &mut self,
+        checkpoint: KeyGenCheckpoint<N>,
+        rng: &mut R,
+    ) -> Result<Step<C, N>> {
+        let KeyGenCheckpoint {
+            era,
+            pub_keys,
+            committed,
+            pending,
+            msg_count,
+            rotation,
+        } = checkpoint;
+        if era != self.era {
+            return Err(Error::KeyGenCheckpointEra(era));
+        }
+        let threshold = util::max_faulty(pub_keys.len());
+        let sk = self.netinfo.signer().clone();
+        let our_id = self.our_id().clone();
+        let (mut key_gen, mut our_part) =
+            SyncKeyGen::new(our_id.clone(), sk, pub_keys, threshold, rng)
+                .map_err(Error::SyncKeyGen)?;
+        // Our part and our acks, by proposer index, that still need to be sent.
+        let mut our_acks = BTreeMap::new();
+        for (sender_id, kg_msg) in &committed {
+            match kg_msg.clone() {
+                KeyGenMessage::Part(part) => {
+                    if *sender_id == our_id {
+                        our_part = None;
+                    }
+                    let outcome = key_gen
+                        .handle_part(sender_id, part, rng)
+                        .map_err(Error::SyncKeyGen)?;
+                    if let PartOutcome::Valid(Some(ack)) = outcome {
+                        our_acks.insert(ack.proposer_index(), ack);
+                    }
+                }
+                KeyGenMessage::Ack(ack) => {
+                    if *sender_id == our_id {
+                        our_acks.remove(&ack.proposer_index());
+                    }
+                    key_gen
+                        .handle_ack(sender_id, ack)
+                        .map_err(Error::SyncKeyGen)?;
+                }
+            }
+        }
+        for signed_msg in pending {
+            if signed_msg.1 == our_id {
+                match signed_msg.2 {
+                    KeyGenMessage::Part(_) => our_part = None,
+                    KeyGenMessage::Ack(ref ack) => {
+                        our_acks.remove(&ack.proposer_index());
+                    }
+                }
+            }
+            if !self.key_gen_msg_buffer.contains(&signed_msg) {
+                self.key_gen_msg_buffer.push(signed_msg);
+            }
+        }
+        let mut kgs = KeyGenState::new(key_gen, rotation);
+        kgs.committed = committed;
+        kgs.msg_count = msg_count;
+        self.key_gen_state = Some(kgs);
+        let mut step = Step::default();
+        if let Some(part) = our_part {
+            step.extend(self.send_transaction(KeyGenMessage::Part(part))?);
+        }
+        for (_, ack) in our_acks {
+            step.extend(self.send_transaction(KeyGenMessage::Ack(ack))?);
+        }
+        Ok(step)
+    }
+
+    /// Announces our network address, in an application-defined encoding.
+    ///
+    /// The signed announcement will be included in some future batch. Once it is committed, all
+    /// nodes add it to their `NetworkInfo`, and it is passed on to new nodes in the `JoinPlan`.
+    /// Observers that are joining as validators can announce their address, too.
+    pub fn announce_address(&mut self, address: Vec<u8>) -> Result<Step<C, N>> {
+        let ser = signed_bytes(SignedKind::Address, self.era, &self.netinfo, &address)
+            .map_err(|err| Error::SerializeAddress(*err))?;
+        let sig = Box::new(self.sign(&ser));
+        if self.netinfo.is_validator() {
+            let our_id = self.our_id().clone();
+            self.address_buffer
+                .retain(|signed_addr| signed_addr.1 != our_id);
+            let signed_addr = SignedAddress(self.era, our_id, address.clone(), *sig.clone());
+            self.address_buffer.push(signed_addr);
+        }
+        let msg = Message::Address(self.era, address, sig);
+        Ok(Target::All.message(msg).into())
+    }
+
     /// Handles a message received from `sender_id`.
     ///
     /// This must be called with every message we receive from another node.
@@ -204,7 +523,31 @@ where
         message: Message<N>,
         rng: &mut R,
     ) -> Result<Step<C, N>> {
-        if message.era() == self.era {
+        // `HoneyBadger` messages are reported by the `HoneyBadger` instance.
+        if let Message::HoneyBadger(..) = message {
+        } else {
+            let (algorithm, epoch) = (message.algorithm(), message.epoch());
+            self.instrument
+                .message_received(sender_id, algorithm, epoch);
+        }
+        let step = self.handle_dhb_message(sender_id, message, rng)?;
+        self.report_sent(&step);
+        Ok(step)
+    }
+
+    /// Handles a message that was reported to the instrument.
+    fn handle_dhb_message<R: Rng>(
+        &mut self,
+        sender_id: &N,
+        message: Message<N>,
+        rng: &mut R,
+    ) -> Result<Step<C, N>> {
+        // Batch signatures are handled in any era: The last batch of an era is signed in the next.
+        if let Message::BatchSignature(era, epoch, msg) = message {
+            let step = self.handle_batch_signature(sender_id, era, epoch, msg)?;
+            return self.apply_removal_policy(step);
+        }
+        let step = if message.era() == self.era {
             match message {
                 Message::HoneyBadger(_, hb_msg) => {
                     self.handle_honey_badger_message(sender_id, hb_msg, rng)
@@ -216,13 +559,57 @@ where
                     .vote_counter
                     .add_pending_vote(sender_id, signed_vote)
                     .map(FaultLog::into),
-            }
+                Message::Address(era, address, sig) => self
+                    .handle_address(sender_id, era, address, *sig)
+                    .map(FaultLog::into),
+                Message::BatchSignature(..) => unreachable!("handled above"),
+            }?
         } else if message.era() > self.era {
-            Ok(Fault::new(sender_id.clone(), FaultKind::UnexpectedDhbMessageEra).into())
+            Fault::new(sender_id.clone(), FaultKind::UnexpectedDhbMessageEra).into()
         } else {
             // The message is late; discard it.
-            Ok(Step::default())
+            Step::default()
+        };
+        self.apply_removal_policy(step)
+    }
+
+    /// Sets the policy for automatically voting to remove validators that were reported for too
+    /// many faults. This resets the faults counted so far.
+    pub fn set_removal_policy(&mut self, policy: RemovalPolicy) {
+        self.removal = Some(RemovalState::new(policy));
+    }
+
+    /// Returns the total weight of the faults the removal policy counted for the given peer, or
+    /// `0` if no policy is set.
+    pub fn fault_weight(&self, node_id: &N) -> u64 {
+        self.removal
+            .as_ref()
+            .map_or(0, |removal| removal.weight(node_id))
+    }
+
+    /// Counts the faults in the step according to the removal policy, and votes to remove the
+    /// validators that reached the threshold.
+    fn apply_removal_policy(&mut self, mut step: Step<C, N>) -> Result<Step<C, N>> {
+        let removal = match self.removal.as_mut() {
+            Some(removal) if self.netinfo.is_validator() => removal,
+            _ => return Ok(step),
+        };
+        let our_id = self.netinfo.our_id();
+        let mut any_flagged = false;
+        for fault in &step.fault_log.0 {
+            if fault.node_id != *our_id && self.netinfo.is_node_validator(&fault.node_id) {
+                any_flagged |= removal.record(&fault.node_id, &fault.kind);
+            }
         }
+        if any_flagged {
+            let removed = removal.flagged().clone();
+            debug!(
+                "{}: Voting to remove faulty validators {:?}.",
+                self, removed
+            );
+            step.extend(self.vote_to_replace(&removed, BTreeMap::new())?);
+        }
+        Ok(step)
     }
 
     /// Returns the information about the node IDs in the network, and the cryptographic keys.
@@ -230,11 +617,110 @@ where
         &self.netinfo
     }
 
+    /// Returns whether enough validators of the current era are reachable for the network to make
+    /// progress, given the IDs of the peers from whom messages were recently received. If not, the
+    /// instrument is notified with the unreachable validators. See `NetworkInfo::connectivity`.
+    pub fn check_connectivity<'a, I>(&self, reachable: I) -> Connectivity<N>
+    where
+        I: IntoIterator<Item = &'a N>,
+        N: 'a,
+    {
+        self.honey_badger.check_connectivity(reachable)
+    }
+
+    /// Sets a hook that is called whenever a new era begins, before any batch of the new era is
+    /// output, and before the batch that concludes the old era is returned.
+    ///
+    /// Applications can use it to migrate state that is tied to the set of validators together
+    /// with the change in membership.
+    pub fn set_era_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&EraTransition<'_, N>) + Send + Sync + 'static,
+    {
+        self.era_hook = Some(Box::new(hook));
+    }
+
+    /// Registers a hook that is called with each batch, before it is returned in the step's output.
+    pub fn add_batch_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Batch<C, N>) + Send + Sync + 'static,
+    {
+        self.subscribers.add_hook(hook);
+    }
+
+    /// Returns a channel that receives a copy of each batch, before it is returned in the step's
+    /// output. Dropping the receiver cancels the subscription.
+    pub fn subscribe(&mut self) -> Receiver<Batch<C, N>>
+    where
+        C: Clone + 'static,
+        N: 'static,
+    {
+        self.subscribers.subscribe()
+    }
+
+    /// Sets a hook that is called with the certificate of each batch, once the validators'
+    /// signature shares for it have been combined. This requires `Params::batch_certificates`.
+    ///
+    /// Certificates are usually completed shortly after the batch has been output, and not
+    /// necessarily in the order of the batches. The certificates of batches whose signature is not
+    /// complete within `max_future_epochs` epochs are dropped.
+    pub fn set_certificate_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&BatchCertificate) + Send + Sync + 'static,
+    {
+        self.certificate_hook = Some(Box::new(hook));
+    }
+
+    /// Sets a hook that is passed the `HoneyBadger` messages for epochs after the next
+    /// `max_future_epochs`, with their senders, if the policy is `FutureEpochPolicy::Forward`.
+    pub fn set_future_message_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&N, HbMessage<N>) + Send + Sync + 'static,
+    {
+        self.future_message_hook = Some(Arc::new(hook));
+        self.pass_future_message_hook();
+    }
+
+    /// Sets the receiver of instrumentation events, for this instance and its `HoneyBadger`
+    /// instances.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        self.honey_badger.set_instrument(instrument.clone());
+        self.instrument = instrument;
+    }
+
+    /// Sets a coin that is computed locally, to use in the `BinaryAgreement` instances of this and
+    /// all later eras instead of the threshold signature: see `BinaryAgreement::set_local_coin`.
+    /// This must be called before handling any messages, with equivalent coins on all nodes.
+    pub fn set_agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        self.honey_badger
+            .set_agreement_local_coin(coin.clone())
+            .map_err(Error::AgreementLocalCoin)?;
+        self.agreement_local_coin = Some(coin);
+        Ok(())
+    }
+
+    /// Sets the clock used to timestamp our contributions, returning the current time in an
+    /// application-defined unit, e.g. seconds since the Unix epoch.
+    ///
+    /// Each batch contains the median of its contributions' timestamps: see `Batch::timestamp`.
+    /// All validators should use the same unit, and set a clock.
+    pub fn set_clock<F>(&mut self, clock: F)
+    where
+        F: Fn() -> u64 + Send + Sync + 'static,
+    {
+        self.clock = Some(Box::new(clock));
+    }
+
     /// Returns a reference to the internal managed `HoneyBadger` instance.
     pub fn honey_badger(&self) -> &HoneyBadger<InternalContrib<C, N>, N> {
         &self.honey_badger
     }
 
+    /// Returns the parameters controlling Dynamic Honey Badger's own behavior.
+    pub fn dynamic_params(&self) -> &DynamicParams {
+        &self.dynamic_params
+    }
+
     /// Returns `true` if we should make our contribution for the next epoch, even if we don't have
     /// content ourselves, to avoid stalling the network.
     ///
@@ -252,8 +738,8 @@ where
         if self.vote_counter.pending_votes().any(is_our_vote) {
             return true; // We have pending input to vote for a validator change.
         }
-        // If we have a pending key gen message, we should propose.
-        !self.key_gen_msg_buffer.is_empty()
+        // If we have a pending key gen message or address announcement, we should propose.
+        !self.key_gen_msg_buffer.is_empty() || !self.address_buffer.is_empty()
     }
 
     /// The epoch of the next batch that will be output.
@@ -261,6 +747,39 @@ where
         self.era + self.honey_badger.next_epoch()
     }
 
+    /// Returns the current era, i.e. the epoch in which it began.
+    pub fn era(&self) -> u64 {
+        self.era
+    }
+
+    /// Returns the state of the validator set change: `InProgress` while key generation for it is
+    /// running, `Complete` if the current era began with a completed change, and `None` otherwise.
+    pub fn change_state(&self) -> ChangeState<N> {
+        if let Some(kgs) = self.key_gen_state.as_ref() {
+            return ChangeState::InProgress(kgs.change());
+        }
+        match self.era_change {
+            Some(ref change) => ChangeState::Complete(change.clone()),
+            None => ChangeState::None,
+        }
+    }
+
+    /// Returns the progress of the ongoing key generation, if any.
+    ///
+    /// If a change won the vote but this doesn't advance, e.g. because some of the new validators
+    /// are offline, the change is stuck in key generation until another one wins.
+    pub fn key_gen_progress(&self) -> Option<KeyGenProgress> {
+        self.key_gen_state.as_ref().map(KeyGenState::progress)
+    }
+
+    /// Returns the ongoing key generation, if any.
+    ///
+    /// Its `received_parts`, `missing_parts` and `missing_acks` show which of the new validators
+    /// the change is waiting for. Only committed messages are taken into account.
+    pub fn key_gen(&self) -> Option<&SyncKeyGen<N>> {
+        self.key_gen_state.as_ref().map(|kgs| &kgs.key_gen)
+    }
+
     /// Handles a message for the `HoneyBadger` instance.
     fn handle_honey_badger_message<R: Rng>(
         &mut self,
@@ -285,12 +804,8 @@ where
         &mut self,
         sender_id: &N,
         kg_msg: KeyGenMessage,
-        sig: Signature,
+        sig: NodeSignature,
     ) -> Result<FaultLog<N, FaultKind>> {
-        if !self.verify_signature(sender_id, &sig, &kg_msg)? {
-            let fault_kind = FaultKind::InvalidKeyGenMessageSignature;
-            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
-        }
         let kgs = match self.key_gen_state {
             Some(ref mut kgs) => kgs,
             None => {
@@ -307,11 +822,92 @@ where
             return Ok(Fault::new(sender_id.clone(), fault_kind).into());
         }
 
+        // Reject malformed messages before verifying the signature, and before buffering them.
+        if let Err(fault_kind) = kgs.validate(&kg_msg) {
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+
+        if !self.verify_signature(sender_id, self.era, &sig, &kg_msg)? {
+            let fault_kind = FaultKind::InvalidKeyGenMessageSignature;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+
         let tx = SignedKeyGenMsg(self.era, sender_id.clone(), kg_msg, sig);
         self.key_gen_msg_buffer.push(tx);
         Ok(FaultLog::default())
     }
 
+    /// Handles a signature share for a batch.
+    fn handle_batch_signature(
+        &mut self,
+        sender_id: &N,
+        era: u64,
+        epoch: u64,
+        msg: threshold_sign::Message,
+    ) -> Result<Step<C, N>> {
+        let next_epoch = self.next_epoch();
+        if !self.honey_badger.params().batch_certificates
+            || !self
+                .certifier
+                .accepts(epoch, next_epoch, self.max_future_epochs)
+        {
+            if epoch < next_epoch {
+                return Ok(Step::default()); // The message is late; discard it.
+            }
+            let fault_kind = FaultKind::UnexpectedBatchSignature;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        if era != self.era && epoch >= next_epoch {
+            let fault_kind = FaultKind::UnexpectedBatchSignature;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        let netinfo = self.honey_badger.netinfo().clone();
+        let (step, certificate) = self
+            .certifier
+            .handle_share(&netinfo, sender_id, era, epoch, msg)?;
+        self.emit_certificate(certificate);
+        Ok(step)
+    }
+
+    /// Reports the messages we send to the instrument, except for the `HoneyBadger` messages, which
+    /// are reported by the `HoneyBadger` instance.
+    fn report_sent(&self, step: &Step<C, N>) {
+        for msg in &step.messages {
+            if let Message::HoneyBadger(..) = msg.message {
+                continue;
+            }
+            let (algorithm, epoch) = (msg.message.algorithm(), msg.message.epoch());
+            self.instrument.message_sent(&msg.target, algorithm, epoch);
+        }
+    }
+
+    /// Passes a completed certificate to the hook, if any.
+    fn emit_certificate(&mut self, certificate: Option<BatchCertificate>) {
+        if let (Some(certificate), Some(hook)) = (certificate, self.certificate_hook.as_mut()) {
+            hook(&certificate);
+        }
+    }
+
+    /// Handles a signed address announcement, which will be committed in a future batch.
+    fn handle_address(
+        &mut self,
+        sender_id: &N,
+        era: u64,
+        address: Vec<u8>,
+        sig: NodeSignature,
+    ) -> Result<FaultLog<N, FaultKind>> {
+        if !self.verify_address_signature(sender_id, era, &address, &sig)? {
+            let fault_kind = FaultKind::InvalidAddressSignature;
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        // Only the latest announcement of each node is kept.
+        self.address_buffer
+            .retain(|signed_addr| signed_addr.1 != *sender_id);
+        let signed_addr = SignedAddress(era, sender_id.clone(), address, sig);
+        self.address_buffer.push(signed_addr);
+        Ok(FaultLog::default())
+    }
+
     /// Processes all pending batches output by Honey Badger.
     fn process_output<R: Rng>(
         &mut self,
@@ -324,8 +920,13 @@ where
         });
         for hb_batch in output {
             let batch_era = self.era;
+            // The batch is signed with the keys of its own era, even if it concludes the era.
+            let batch_netinfo = self.honey_badger.netinfo().clone();
+            let certify = self.honey_badger.params().batch_certificates;
             let batch_epoch = hb_batch.epoch + batch_era;
             let mut batch_contributions = BTreeMap::new();
+            let mut timestamps = Vec::new();
+            self.vote_counter.update_epoch(batch_epoch);
 
             // Add the user transactions to `batch` and handle votes and DKG messages.
             for (id, int_contrib) in hb_batch.contributions {
@@ -333,17 +934,32 @@ where
                     votes,
                     key_gen_messages,
                     contrib,
+                    addresses,
+                    timestamp,
                 } = int_contrib;
+                timestamps.extend(timestamp);
                 step.fault_log
                     .extend(self.vote_counter.add_committed_votes(&id, votes)?);
                 batch_contributions.insert(id.clone(), contrib);
                 self.key_gen_msg_buffer
                     .retain(|skgm| !key_gen_messages.contains(skgm));
+                self.address_buffer
+                    .retain(|signed_addr| !addresses.contains(signed_addr));
+                for SignedAddress(era, s_id, address, sig) in addresses {
+                    if era != self.era
+                        || !self.verify_address_signature(&s_id, era, &address, &sig)?
+                    {
+                        let fault_kind = FaultKind::InvalidCommittedAddress;
+                        step.fault_log.append(id.clone(), fault_kind);
+                    } else {
+                        self.netinfo.set_address(s_id, address);
+                    }
+                }
                 for SignedKeyGenMsg(era, s_id, kg_msg, sig) in key_gen_messages {
                     if era != self.era {
                         let fault_kind = FaultKind::InvalidKeyGenMessageEra;
                         step.fault_log.append(id.clone(), fault_kind);
-                    } else if !self.verify_signature(&s_id, &sig, &kg_msg)? {
+                    } else if !self.verify_signature(&s_id, era, &sig, &kg_msg)? {
                         let fault_kind = FaultKind::InvalidKeyGenMessageSignature;
                         step.fault_log.append(id.clone(), fault_kind);
                     } else {
@@ -357,39 +973,88 @@ where
             let change = if let Some(kgs) = self.take_ready_key_gen() {
                 // If DKG completed, apply the change, restart Honey Badger, and inform the user.
                 debug!("{}: DKG for complete for: {:?}", self, kgs.public_keys());
+                let peer_info = self.netinfo.peer_info().clone();
                 self.netinfo = kgs.key_gen.into_network_info().map_err(Error::SyncKeyGen)?;
+                self.netinfo.set_peer_info(peer_info);
+                self.instrument
+                    .state_transition(Algorithm::DynamicHoneyBadger, Transition::KeyGenCompleted);
                 let params = self.honey_badger.params().clone();
                 self.restart_honey_badger(batch_epoch + 1, params);
                 ChangeState::Complete(Change::NodeChange(self.netinfo.public_key_map().clone()))
-            } else if let Some(change) = self.vote_counter.compute_winner().cloned() {
+            } else if let Some(change) = self.next_change(batch_epoch + 1) {
                 // If there is a new change, restart DKG. Inform the user about the current change.
                 match change {
-                    Change::NodeChange(ref pub_keys) => {
-                        step.extend(self.update_key_gen(batch_epoch + 1, pub_keys, rng)?);
+                    Change::NodeChange(_) | Change::RotateKeys => {
+                        step.extend(self.update_key_gen(batch_epoch + 1, &change, rng)?);
                     }
                     Change::EncryptionSchedule(schedule) => {
                         self.update_encryption_schedule(batch_epoch + 1, schedule);
                     }
+                    Change::ProtocolUpgrade(upgrade) => {
+                        self.schedule_protocol_upgrade(batch_epoch + 1, upgrade);
+                    }
+                    Change::Params(param_change) => {
+                        self.update_params(batch_epoch + 1, param_change);
+                    }
                 }
                 match change {
-                    Change::NodeChange(_) => ChangeState::InProgress(change),
-                    Change::EncryptionSchedule(_) => ChangeState::Complete(change),
+                    Change::NodeChange(_) | Change::RotateKeys => ChangeState::InProgress(change),
+                    Change::EncryptionSchedule(_)
+                    | Change::ProtocolUpgrade(_)
+                    | Change::Params(_) => ChangeState::Complete(change),
                 }
             } else {
                 ChangeState::None
             };
-            step.output.push(Batch {
+            match change {
+                ChangeState::Complete(ref change) => self.era_change = Some(change.clone()),
+                ChangeState::InProgress(_) => self.era_change = None,
+                ChangeState::None => (),
+            }
+            self.apply_due_protocol_upgrade(batch_epoch + 1);
+            let batch = Batch {
                 epoch: batch_epoch,
                 era: batch_era,
                 change,
                 netinfo: Arc::new(self.netinfo.clone()),
                 contributions: batch_contributions,
+                order: hb_batch.order,
+                seed: hb_batch.seed,
+                agreement_certificates: hb_batch.agreement_certificates,
+                agreed_contributions: None,
                 params: self.honey_badger.params().clone(),
-            });
+                dynamic_params: self.dynamic_params.clone(),
+                timestamp: util::lower_median(timestamps),
+            };
+            if certify {
+                let hash = batch.export()?.hash()?;
+                let (cert_step, certificate) =
+                    self.certifier
+                        .sign_batch(&batch_netinfo, batch_era, batch_epoch, hash)?;
+                step.extend(cert_step);
+                self.emit_certificate(certificate);
+                self.certifier
+                    .prune(self.next_epoch(), self.max_future_epochs);
+            }
+            self.subscribers.notify(&batch);
+            step.output.push(batch);
         }
         Ok(step)
     }
 
+    /// Returns the change that won the vote, or `RotateKeys` if no key generation is in progress
+    /// and the current era has lasted long enough for the scheduled key rotation.
+    fn next_change(&self, epoch: u64) -> Option<Change<N>> {
+        if let Some(change) = self.vote_counter.compute_winner() {
+            return Some(change.clone());
+        }
+        let interval = self.honey_badger.params().key_rotation_interval?;
+        if self.key_gen_state.is_none() && epoch >= self.era + interval {
+            return Some(Change::RotateKeys);
+        }
+        None
+    }
+
     /// Restarts Honey Badger with the new encryption schedule.
     pub(super) fn update_encryption_schedule(&mut self, era: u64, schedule: EncryptionSchedule) {
         let mut params = self.honey_badger.params().clone();
@@ -397,26 +1062,69 @@ where
         self.restart_honey_badger(era, params);
     }
 
+    /// Restarts Honey Badger with the changed parameters.
+    pub(super) fn update_params(&mut self, era: u64, param_change: ParamChange) {
+        let mut params = self.honey_badger.params().clone();
+        param_change.apply(&mut params);
+        self.restart_honey_badger(era, params);
+    }
+
+    /// Restarts Honey Badger with the protocol upgrade scheduled. It takes effect no earlier than
+    /// in the new era.
+    pub(super) fn schedule_protocol_upgrade(&mut self, era: u64, upgrade: ProtocolUpgrade) {
+        let mut params = self.honey_badger.params().clone();
+        params.protocol_upgrade = Some(ProtocolUpgrade {
+            epoch: upgrade.epoch.max(era),
+            ..upgrade
+        });
+        self.restart_honey_badger(era, params);
+    }
+
+    /// If the scheduled protocol upgrade takes effect in the given epoch, restarts Honey Badger
+    /// with the new protocol version, so that the new era begins with the upgrade.
+    pub(super) fn apply_due_protocol_upgrade(&mut self, epoch: u64) {
+        let mut params = self.honey_badger.params().clone();
+        let upgrade = match params.protocol_upgrade {
+            Some(upgrade) if upgrade.epoch <= epoch => upgrade,
+            _ => return,
+        };
+        debug!(
+            "{}: Switching to protocol version {}.",
+            self, upgrade.version
+        );
+        params.protocol_version = upgrade.version;
+        params.protocol_upgrade = None;
+        self.restart_honey_badger(epoch, params);
+    }
+
     /// If the winner of the vote has changed, restarts Key Generation for the set of nodes implied
-    /// by the current change.
+    /// by the current change. Changes other than `NodeChange` and `RotateKeys` are ignored.
     pub(super) fn update_key_gen<R: Rng>(
         &mut self,
         era: u64,
-        pub_keys: &BTreeMap<N, PublicKey>,
+        change: &Change<N>,
         rng: &mut R,
     ) -> Result<Step<C, N>> {
+        let (pub_keys, rotation) = match change {
+            Change::NodeChange(pub_keys) => (pub_keys, false),
+            Change::RotateKeys => (self.netinfo.public_key_map(), true),
+            _ => return Ok(Step::default()),
+        };
         if self.key_gen_state.as_ref().map(KeyGenState::public_keys) == Some(pub_keys) {
             return Ok(Step::default()); // The change is the same as before. Continue DKG as is.
         }
+        let pub_keys = pub_keys.clone();
         debug!("{}: Restarting DKG for {:?}.", self, pub_keys);
         let params = self.honey_badger.params().clone();
         self.restart_honey_badger(era, params);
         let threshold = util::max_faulty(pub_keys.len());
-        let sk = self.netinfo.secret_key().clone();
+        let sk = self.netinfo.signer().clone();
         let our_id = self.our_id().clone();
-        let (key_gen, part) = SyncKeyGen::new(our_id, sk, pub_keys.clone(), threshold, rng)
-            .map_err(Error::SyncKeyGen)?;
-        self.key_gen_state = Some(KeyGenState::new(key_gen));
+        let (key_gen, part) =
+            SyncKeyGen::new(our_id, sk, pub_keys, threshold, rng).map_err(Error::SyncKeyGen)?;
+        self.key_gen_state = Some(KeyGenState::new(key_gen, rotation));
+        self.instrument
+            .state_transition(Algorithm::DynamicHoneyBadger, Transition::KeyGenStarted);
         if let Some(part) = part {
             self.send_transaction(KeyGenMessage::Part(part))
         } else {
@@ -424,16 +1132,56 @@ where
         }
     }
 
-    /// Starts a new `HoneyBadger` instance and resets the vote counter.
+    /// Starts a new `HoneyBadger` instance and resets the vote counter. Calls the era hook, if any.
     fn restart_honey_badger(&mut self, era: u64, params: Params) {
+        if let Some(hook) = self.era_hook.as_mut() {
+            hook(&EraTransition {
+                era,
+                old_validators: self.honey_badger.netinfo().public_key_map(),
+                new_validators: self.netinfo.public_key_map(),
+            });
+        }
         self.era = era;
+        self.instrument
+            .state_transition(Algorithm::DynamicHoneyBadger, Transition::Era(era));
+        self.max_future_epochs = params.max_future_epochs;
+        if let Some(removal) = self.removal.as_mut() {
+            removal.retain_validators(&self.netinfo);
+        }
         self.key_gen_msg_buffer.retain(|kg_msg| kg_msg.0 >= era);
+        // Our own announcement is kept: It will be signed again for the new era.
+        let our_id = self.netinfo.our_id();
+        self.address_buffer
+            .retain(|signed_addr| signed_addr.0 >= era || signed_addr.1 == *our_id);
         let netinfo = Arc::new(self.netinfo.clone());
-        self.vote_counter = VoteCounter::new(netinfo.clone(), era);
-        self.honey_badger = HoneyBadger::builder(netinfo)
+        self.vote_counter = VoteCounter::new(
+            netinfo.clone(),
+            era,
+            self.dynamic_params.vote_limits,
+            params.change_quorum,
+            params.conflict_policy,
+            params.vote_ttl,
+        );
+        self.vote_counter
+            .set_signature_scheme(self.signature_scheme.clone());
+        let mut hb_builder = HoneyBadger::builder(netinfo);
+        hb_builder
             .session_id(era)
             .params(params)
-            .build();
+            .instrument(self.instrument.clone());
+        if let Some(ref coin) = self.agreement_local_coin {
+            hb_builder.agreement_local_coin(coin.clone());
+        }
+        self.honey_badger = hb_builder.build();
+        self.pass_future_message_hook();
+    }
+
+    /// Makes the `HoneyBadger` instance forward messages to the application's hook, if any.
+    fn pass_future_message_hook(&mut self) {
+        if let Some(ref hook) = self.future_message_hook {
+            self.honey_badger
+                .set_shared_future_message_hook(hook.clone());
+        }
     }
 
     /// Handles a `Part` message that was output by Honey Badger.
@@ -444,6 +1192,8 @@ where
         rng: &mut R,
     ) -> Result<Step<C, N>> {
         let outcome = if let Some(kgs) = self.key_gen_state.as_mut() {
+            let kg_msg = KeyGenMessage::Part(part.clone());
+            kgs.committed.push((sender_id.clone(), kg_msg));
             kgs.key_gen
                 .handle_part(&sender_id, part, rng)
                 .map_err(Error::SyncKeyGen)?
@@ -466,6 +1216,8 @@ where
     /// Handles an `Ack` message that was output by Honey Badger.
     fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<Step<C, N>> {
         let outcome = if let Some(kgs) = self.key_gen_state.as_mut() {
+            let kg_msg = KeyGenMessage::Ack(ack.clone());
+            kgs.committed.push((sender_id.clone(), kg_msg));
             kgs.key_gen
                 .handle_ack(sender_id, ack)
                 .map_err(Error::SyncKeyGen)?
@@ -486,8 +1238,9 @@ where
 
     /// Signs and sends a `KeyGenMessage` and also tries to commit it.
     fn send_transaction(&mut self, kg_msg: KeyGenMessage) -> Result<Step<C, N>> {
-        let ser = bincode::serialize(&kg_msg).map_err(|err| Error::SerializeKeyGen(*err))?;
-        let sig = Box::new(self.netinfo.secret_key().sign(ser));
+        let ser = signed_bytes(SignedKind::KeyGen, self.era, &self.netinfo, &kg_msg)
+            .map_err(|err| Error::SerializeKeyGen(*err))?;
+        let sig = Box::new(self.sign(&ser));
         if self.netinfo.is_validator() {
             let our_id = self.our_id().clone();
             let signed_msg = SignedKeyGenMsg(self.era, our_id, kg_msg.clone(), *sig.clone());
@@ -514,22 +1267,84 @@ where
         }
     }
 
-    /// Returns `true` if the signature of `kg_msg` by the node with the specified ID is valid.
-    /// Returns an error if the payload fails to serialize.
+    /// Returns `true` if the signature of `kg_msg` in the given era by the node with the specified
+    /// ID is valid. Returns an error if the payload fails to serialize.
     ///
     /// This accepts signatures from both validators and currently joining candidates, if any.
     fn verify_signature(
         &self,
         node_id: &N,
-        sig: &Signature,
+        era: u64,
+        sig: &NodeSignature,
         kg_msg: &KeyGenMessage,
     ) -> Result<bool> {
-        let ser = bincode::serialize(kg_msg).map_err(|err| Error::SerializeKeyGen(*err))?;
-        let verify = |opt_pk: Option<&PublicKey>| opt_pk.map_or(false, |pk| pk.verify(&sig, &ser));
+        let ser = signed_bytes(SignedKind::KeyGen, era, &self.netinfo, kg_msg)
+            .map_err(|err| Error::SerializeKeyGen(*err))?;
+        Ok(self.verify_node_signature(node_id, sig, &ser))
+    }
+
+    /// Returns `true` if the signature of the address announcement by the node with the specified
+    /// ID is valid. Returns an error if the payload fails to serialize.
+    ///
+    /// This accepts signatures from both validators and currently joining candidates, if any.
+    fn verify_address_signature(
+        &self,
+        node_id: &N,
+        era: u64,
+        address: &[u8],
+        sig: &NodeSignature,
+    ) -> Result<bool> {
+        let ser = signed_bytes(SignedKind::Address, era, &self.netinfo, address)
+            .map_err(|err| Error::SerializeAddress(*err))?;
+        Ok(self.verify_node_signature(node_id, sig, &ser))
+    }
+
+    /// Returns our signature of `ser`, using the configured signature scheme.
+    fn sign(&self, ser: &[u8]) -> NodeSignature {
+        self.instrument.crypto_op(CryptoOp::Sign);
+        self.signature_scheme
+            .sign(self.netinfo.signer().as_ref(), ser)
+    }
+
+    /// Returns `true` if the signature of `ser` by a validator or currently joining candidate
+    /// with the specified ID is valid.
+    fn verify_node_signature(&self, node_id: &N, sig: &NodeSignature, ser: &[u8]) -> bool {
+        let verify = |opt_pk: Option<&PublicKey>| {
+            opt_pk.map_or(false, |pk| {
+                self.instrument.crypto_op(CryptoOp::Verify);
+                self.signature_scheme.verify(node_id, pk, ser, sig)
+            })
+        };
         let kgs = self.key_gen_state.as_ref();
         let current_key = self.netinfo.public_key(node_id);
         let candidate_key = kgs.and_then(|kgs| kgs.public_keys().get(node_id));
-        Ok(verify(current_key) || verify(candidate_key))
+        verify(current_key) || verify(candidate_key)
+    }
+
+    /// Returns the number and the epoch range of the messages each peer has sent us for epochs
+    /// after the current one. The epochs are counted from the beginning, not from the current era.
+    ///
+    /// Messages for future eras are not buffered: They are rejected as faulty.
+    pub fn buffered_messages(&self) -> BTreeMap<N, BufferedMessages> {
+        let era = self.era;
+        let to_linear = |(id, buffered): (N, BufferedMessages)| {
+            let linear = BufferedMessages {
+                first_epoch: buffered.first_epoch + era,
+                last_epoch: buffered.last_epoch + era,
+                ..buffered
+            };
+            (id, linear)
+        };
+        self.honey_badger
+            .buffered_messages()
+            .into_iter()
+            .map(to_linear)
+            .collect()
+    }
+
+    /// Returns the amount of state the current era's `HoneyBadger` instance holds in memory.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.honey_badger.memory_stats()
     }
 
     /// Returns the maximum future epochs of the Honey Badger algorithm instance.
@@ -3,6 +3,8 @@ use failure::Fail;
 
 use crate::honey_badger;
 use crate::sync_key_gen;
+use crate::threshold_sign;
+use crate::NetworkInfoError;
 
 /// Dynamic honey badger error variants.
 #[derive(Debug, Fail)]
@@ -10,21 +12,63 @@ pub enum Error {
     /// Failed to serialize a key generation message for signing.
     #[fail(display = "Error serializing a key gen message: {}", _0)]
     SerializeKeyGen(bincode::ErrorKind),
+    /// Failed to serialize an address announcement for signing.
+    #[fail(display = "Error serializing an address announcement: {}", _0)]
+    SerializeAddress(bincode::ErrorKind),
     /// Failed to serialize a vote for signing.
     #[fail(display = "Error serializing a vote: {}", _0)]
     SerializeVote(bincode::ErrorKind),
+    /// Failed to serialize a join request or plan for signing.
+    #[fail(display = "Error serializing a join message: {}", _0)]
+    SerializeJoinMessage(bincode::ErrorKind),
     /// Failed to propose a contribution in `HoneyBadger`.
     #[fail(display = "Error proposing a contribution in HoneyBadger: {}", _0)]
     ProposeHoneyBadger(honey_badger::Error),
     /// Failed to handle a `HoneyBadger` message.
     #[fail(display = "Error handling a HoneyBadger message: {}", _0)]
     HandleHoneyBadgerMessage(honey_badger::Error),
+    /// Failed to set the local coin in `HoneyBadger`.
+    #[fail(display = "Error setting the local coin in HoneyBadger: {}", _0)]
+    AgreementLocalCoin(honey_badger::Error),
     /// Failed to handle a `SyncKeyGen` message.
     #[fail(display = "Error handling SyncKeyGen message: {}", _0)]
     SyncKeyGen(sync_key_gen::Error),
     /// Unknown sender
     #[fail(display = "Unknown sender")]
     UnknownSender,
+    /// The `JoinPlan` is inconsistent.
+    #[fail(display = "Invalid join plan: {}", _0)]
+    InvalidJoinPlan(NetworkInfoError),
+    /// The vote state to restore belongs to a different era.
+    #[fail(display = "The vote state is for era {}, not the current one", _0)]
+    VoteStateEra(u64),
+    /// The key generation checkpoint belongs to a different era.
+    #[fail(
+        display = "The key generation checkpoint is for era {}, not the current one",
+        _0
+    )]
+    KeyGenCheckpointEra(u64),
+    /// The keys for a new observer are inconsistent.
+    #[fail(display = "Invalid observer keys: {}", _0)]
+    InvalidObserverKeys(NetworkInfoError),
+    /// The externally generated key material is inconsistent.
+    #[fail(display = "Invalid key material: {}", _0)]
+    InvalidKeyMaterial(NetworkInfoError),
+    /// Failed to serialize an exported batch or its contributions.
+    #[fail(display = "Error serializing an exported batch: {}", _0)]
+    SerializeExport(bincode::ErrorKind),
+    /// Failed to deserialize an exported batch or its contributions.
+    #[fail(display = "Error deserializing an exported batch: {}", _0)]
+    DecodeExport(bincode::ErrorKind),
+    /// The encoded batch doesn't start with the export magic bytes and version.
+    #[fail(display = "Invalid exported batch header")]
+    InvalidExportHeader,
+    /// The encoded batch has a format version unknown to this version of the crate.
+    #[fail(display = "Unsupported exported batch version: {}", _0)]
+    UnsupportedExportVersion(u8),
+    /// Failed to sign a batch or to combine the signature shares.
+    #[fail(display = "Error signing a batch: {}", _0)]
+    BatchCertificate(threshold_sign::Error),
 }
 
 /// The result of `DynamicHoneyBadger` handling an input or message.
@@ -81,13 +125,50 @@ pub enum FaultKind {
     /// `DynamicHoneyBadger` received a change vote with an invalid signature.
     #[fail(display = "`DynamicHoneyBadger` received a change vote with an invalid signature.")]
     InvalidVoteSignature,
+    /// `DynamicHoneyBadger` received more pending votes from a voter in one era than allowed.
+    #[fail(
+        display = "`DynamicHoneyBadger` received more pending votes from a voter in one era than
+                    allowed."
+    )]
+    TooManyPendingVotes,
+    /// `DynamicHoneyBadger` received a pending vote sooner after the voter's previous one than
+    /// allowed.
+    #[fail(
+        display = "`DynamicHoneyBadger` received a pending vote sooner after the voter's previous
+                    one than allowed."
+    )]
+    PendingVoteTooSoon,
     /// A validator committed an invalid vote in `DynamicHoneyBadger`.
     #[fail(display = "A validator committed an invalid vote in `DynamicHoneyBadger`.")]
     InvalidCommittedVote,
+    /// `DynamicHoneyBadger` received an address announcement with an invalid signature.
+    #[fail(
+        display = "`DynamicHoneyBadger` received an address announcement with an invalid signature."
+    )]
+    InvalidAddressSignature,
+    /// A validator committed an invalid address announcement in `DynamicHoneyBadger`.
+    #[fail(
+        display = "A validator committed an invalid address announcement in `DynamicHoneyBadger`."
+    )]
+    InvalidCommittedAddress,
+    /// A `JoinResponse` was sent by a node that is not a validator in its plan.
+    #[fail(display = "A `JoinResponse` was sent by a node that is not a validator in its plan.")]
+    UnknownJoinPlanSigner,
+    /// A `JoinResponse` has an invalid signature.
+    #[fail(display = "A `JoinResponse` has an invalid signature.")]
+    InvalidJoinPlanSignature,
     /// `DynamicHoneyBadger` received a message with an invalid era.
     #[fail(display = "`DynamicHoneyBadger` received a message with an invalid era.")]
     UnexpectedDhbMessageEra,
     /// `DynamicHoneyBadger` received a fault from `HoneyBadger`.
     #[fail(display = "`DynamicHoneyBadger` received a fault from `HoneyBadger`.")]
     HbFault(honey_badger::FaultKind),
+    /// `DynamicHoneyBadger` received a batch signature share that is not expected: for an epoch
+    /// that is too far ahead or behind, with the wrong era, or from a node that is not a validator
+    /// of the batch's era.
+    #[fail(display = "`DynamicHoneyBadger` received an unexpected batch signature share.")]
+    UnexpectedBatchSignature,
+    /// `DynamicHoneyBadger` received a fault from signing a batch.
+    #[fail(display = "`DynamicHoneyBadger` received a fault from signing a batch.")]
+    BatchSignatureFault(threshold_sign::FaultKind),
 }
@@ -0,0 +1,231 @@
+//! A stable encoding of batches for downstream consumers.
+//!
+//! The in-memory `Batch` contains the validators' private `NetworkInfo`, and its contributions
+//! have an application-defined type. Indexers, message buses and other consumers downstream of a
+//! validator only need the public parts, in a format that doesn't change with the crate's internal
+//! types: An `ExportedBatch` contains the epoch, the proposers and their serialized contributions
+//! in the batch's order, the change state and, if the batch ends an era, the keys of the next one.
+//!
+//! `ExportedBatch::encode` prefixes the `bincode` serialization with the magic bytes `HBBX` and
+//! the version of the format, currently `1`. `ExportedBatch::decode` rejects other versions, so the
+//! format can be extended later without consumers misreading it.
+
+use std::collections::BTreeMap;
+
+use crate::crypto::{PublicKey, PublicKeySet, Signature};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{Batch, Change, ChangeState, Error, Result};
+use crate::NodeIdT;
+
+/// The bytes every encoded batch starts with.
+pub const EXPORT_MAGIC: [u8; 4] = *b"HBBX";
+
+/// The current version of the export format.
+pub const EXPORT_VERSION: u8 = 1;
+
+/// The public parts of a `Batch`, in a stable, versioned format.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct ExportedBatch<N: Ord> {
+    /// The era of the batch.
+    pub era: u64,
+    /// The linear epoch, i.e. the sequence number of the batch.
+    pub epoch: u64,
+    /// The protocol version that applies to the next epoch.
+    pub protocol_version: u64,
+    /// The median of the proposers' timestamps, if any of them included one.
+    pub timestamp: Option<u64>,
+    /// The proposers and their `bincode`-serialized contributions, in the batch's order.
+    pub contributions: Vec<(N, Vec<u8>)>,
+    /// The state of the current change.
+    pub change: ChangeState<N>,
+    /// The keys of the next era, if this batch completed a change.
+    pub transition: Option<ExportedEraTransition<N>>,
+    /// The validators' threshold signature of the batch, if one has been attached.
+    pub signature: Option<Signature>,
+}
+
+/// The keys and validators of a new era, for consumers that follow the validator set.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct ExportedEraTransition<N: Ord> {
+    /// The first epoch of the new era.
+    pub era: u64,
+    /// The completed change.
+    pub change: Change<N>,
+    /// The public key set of the new era.
+    pub pub_key_set: PublicKeySet,
+    /// The validators of the new era, with their public keys.
+    pub validators: BTreeMap<N, PublicKey>,
+}
+
+impl<N: NodeIdT + Serialize + DeserializeOwned> ExportedBatch<N> {
+    /// Creates the export of the given batch, with its contributions serialized as the validators
+    /// agreed on them, and without a signature.
+    pub fn new<C: Serialize>(batch: &Batch<C, N>) -> Result<Self> {
+        let contributions = batch
+            .agreed_contributions()
+            .map(|(id, contrib)| {
+                let bytes = bincode::serialize(contrib).map_err(|err| Error::SerializeExport(*err));
+                Ok((id.clone(), bytes?))
+            })
+            .collect::<Result<_>>()?;
+        let transition = match batch.change {
+            ChangeState::Complete(ref change) => Some(ExportedEraTransition {
+                era: batch.epoch + 1,
+                change: change.clone(),
+                pub_key_set: batch.netinfo.public_key_set().clone(),
+                validators: batch.netinfo.public_key_map().clone(),
+            }),
+            ChangeState::None | ChangeState::InProgress(_) => None,
+        };
+        Ok(ExportedBatch {
+            era: batch.era,
+            epoch: batch.epoch,
+            protocol_version: batch.params.protocol_version,
+            timestamp: batch.timestamp,
+            contributions,
+            change: batch.change.clone(),
+            transition,
+            signature: None,
+        })
+    }
+
+    /// Returns the deserialized contributions, in the batch's order.
+    pub fn decode_contributions<C: DeserializeOwned>(&self) -> Result<Vec<(N, C)>> {
+        self.contributions
+            .iter()
+            .map(|(id, bytes)| {
+                let contrib = bincode::deserialize(bytes).map_err(|err| Error::DecodeExport(*err));
+                Ok((id.clone(), contrib?))
+            })
+            .collect()
+    }
+
+    /// Returns the encoded batch: the magic bytes, the format version and the serialized batch.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut bytes = EXPORT_MAGIC.to_vec();
+        bytes.push(EXPORT_VERSION);
+        bincode::serialize_into(&mut bytes, self).map_err(|err| Error::SerializeExport(*err))?;
+        Ok(bytes)
+    }
+
+    /// Decodes a batch encoded with `encode`.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() <= EXPORT_MAGIC.len() || bytes[..EXPORT_MAGIC.len()] != EXPORT_MAGIC {
+            return Err(Error::InvalidExportHeader);
+        }
+        let version = bytes[EXPORT_MAGIC.len()];
+        if version != EXPORT_VERSION {
+            return Err(Error::UnsupportedExportVersion(version));
+        }
+        bincode::deserialize(&bytes[(EXPORT_MAGIC.len() + 1)..])
+            .map_err(|err| Error::DecodeExport(*err))
+    }
+}
+
+impl<C, N> Batch<C, N>
+where
+    C: Serialize,
+    N: NodeIdT + Serialize + DeserializeOwned,
+{
+    /// Returns the public parts of the batch in the stable export format.
+    pub fn export(&self) -> Result<ExportedBatch<N>> {
+        ExportedBatch::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use super::{ExportedBatch, EXPORT_VERSION};
+    use crate::dynamic_honey_badger::{Batch, Change, ChangeState, DynamicParams, Error};
+    use crate::honey_badger::Params;
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_export_roundtrip() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let netinfo = Arc::new(netinfos[&0].clone());
+        let pub_keys = netinfo.public_key_map().clone();
+        let contributions: BTreeMap<usize, Vec<u8>> =
+            vec![(1, vec![1, 2]), (3, vec![3])].into_iter().collect();
+        let batch = Batch {
+            epoch: 7,
+            era: 5,
+            contributions,
+            order: vec![3, 1],
+            seed: None,
+            change: ChangeState::Complete(Change::NodeChange(pub_keys.clone())),
+            netinfo,
+            params: Params::default(),
+            dynamic_params: DynamicParams::default(),
+            timestamp: Some(1000),
+            agreement_certificates: BTreeMap::new(),
+            agreed_contributions: None,
+        };
+        let exported = batch.export().expect("export");
+        assert_eq!(
+            vec![(3, vec![3]), (1, vec![1, 2])],
+            exported.decode_contributions::<Vec<u8>>().expect("decode")
+        );
+        let transition = exported.transition.as_ref().expect("transition");
+        assert_eq!((8, &pub_keys), (transition.era, &transition.validators));
+
+        let bytes = exported.encode().expect("encode");
+        assert_eq!(b"HBBX", &bytes[..4]);
+        assert_eq!(EXPORT_VERSION, bytes[4]);
+        assert_eq!(exported, ExportedBatch::decode(&bytes).expect("decode"));
+
+        // Other versions and other data are rejected.
+        let mut version = bytes.clone();
+        version[4] = 2;
+        match ExportedBatch::<usize>::decode(&version) {
+            Err(Error::UnsupportedExportVersion(2)) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match ExportedBatch::<usize>::decode(&bytes[..4]) {
+            Err(Error::InvalidExportHeader) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_export_excluded_txs() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let contributions: BTreeMap<usize, Vec<u8>> =
+            vec![(1, vec![1, 2]), (3, vec![3, 4])].into_iter().collect();
+        let mut batch = Batch {
+            epoch: 7,
+            era: 5,
+            contributions,
+            order: vec![3, 1],
+            seed: None,
+            change: ChangeState::None,
+            netinfo: Arc::new(netinfos[&0].clone()),
+            params: Params::default(),
+            dynamic_params: DynamicParams::default(),
+            timestamp: None,
+            agreement_certificates: BTreeMap::new(),
+            agreed_contributions: None,
+        };
+        let exported = batch.export().expect("export");
+
+        // Excluding transactions changes the output, but not the export.
+        batch.retain_txs(|&tx| tx != 1 && tx != 4);
+        assert_eq!(vec![3, 2], batch.iter().cloned().collect::<Vec<u8>>());
+        assert_eq!(exported, batch.export().expect("export"));
+        let agreed: Vec<_> = batch.agreed_contributions().collect();
+        assert_eq!(vec![(&3, &vec![3, 4]), (&1, &vec![1, 2])], agreed);
+
+        // Excluding more transactions keeps the agreed ones.
+        batch.retain_txs(|&tx| tx != 2);
+        assert_eq!(vec![3], batch.iter().cloned().collect::<Vec<u8>>());
+        assert_eq!(exported, batch.export().expect("export"));
+    }
+}
@@ -0,0 +1,349 @@
+//! Messages for new nodes that want to join the network.
+//!
+//! A node that is not part of the network yet has no `DynamicHoneyBadger` instance, so it can't
+//! receive regular messages. Instead it sends a `JoinRequest` to some of the validators. If the
+//! application decides to admit the node, the validators can use the request's public key to vote
+//! for adding it.
+//!
+//! After every batch whose `change` is not `None`, each validator can answer the pending requests
+//! with the `JoinResponse` returned by `Batch::join_response`: the batch's `JoinPlan`, signed by
+//! the validator. The joining node creates a `JoinSync` with the validators it trusts, e.g. the
+//! ones in its configuration, and passes all responses it receives to it. It outputs a plan once
+//! more than _f_ of the trusted validators have signed the same one. The validators listed in the
+//! plan itself are not trusted, so the plan can't be forged by the faulty validators alone. It
+//! contains the era, the keys and the state of key generation the node needs to start observing in
+//! the plan's first epoch.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::crypto::{PublicKey, Signature};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tiny_keccak::sha3_256;
+
+use super::{Batch, Error, FaultKind, JoinPlan, Result, SignedKind};
+use crate::{util, NetworkInfo, NodeIdT, Signer};
+
+/// A request to join the network, signed by the new node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinRequest<N> {
+    /// The ID of the new node.
+    node_id: N,
+    /// The public key of the new node.
+    pub_key: PublicKey,
+    /// The new node's signature of its ID and public key.
+    sig: Signature,
+}
+
+impl<N: NodeIdT + Serialize> JoinRequest<N> {
+    /// Creates a request for the node with the given ID and secret key, e.g. a `SecretKey`.
+    pub fn new(node_id: N, secret_key: &dyn Signer) -> Result<Self> {
+        let pub_key = secret_key.public_key();
+        let ser = request_bytes(&node_id, &pub_key)?;
+        let sig = secret_key.sign(&ser);
+        Ok(JoinRequest {
+            node_id,
+            pub_key,
+            sig,
+        })
+    }
+
+    /// Returns the ID of the new node.
+    pub fn node_id(&self) -> &N {
+        &self.node_id
+    }
+
+    /// Returns the public key of the new node, e.g. to vote for adding it as a validator.
+    pub fn public_key(&self) -> &PublicKey {
+        &self.pub_key
+    }
+
+    /// Returns `true` if the request was signed by the owner of the public key.
+    pub fn verify(&self) -> Result<bool> {
+        let ser = request_bytes(&self.node_id, &self.pub_key)?;
+        Ok(self.pub_key.verify(&self.sig, ser))
+    }
+}
+
+/// A `JoinPlan`, signed by the validator that sent it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: DeserializeOwned"))]
+pub struct JoinResponse<N: Ord> {
+    /// The plan to join the network.
+    plan: JoinPlan<N>,
+    /// The validator's signature of the plan.
+    sig: Signature,
+}
+
+impl<N: NodeIdT + Serialize> JoinResponse<N> {
+    /// Signs the plan with our secret key.
+    fn new(plan: JoinPlan<N>, netinfo: &NetworkInfo<N>) -> Result<Self> {
+        let ser = plan_bytes(&plan)?;
+        let sig = netinfo.signer().sign(&ser);
+        Ok(JoinResponse { plan, sig })
+    }
+
+    /// Returns the epoch of the first batch the new node will observe.
+    pub fn next_epoch(&self) -> u64 {
+        self.plan.next_epoch()
+    }
+}
+
+impl<C, N: NodeIdT + Serialize> Batch<C, N> {
+    /// Returns the `JoinPlan` signed by us, to be sent to the nodes that requested to join, if it
+    /// is possible to join in the next epoch and we are a validator in it.
+    pub fn join_response(&self) -> Result<Option<JoinResponse<N>>> {
+        if !self.netinfo.is_validator() {
+            return Ok(None);
+        }
+        match self.join_plan() {
+            Some(plan) => JoinResponse::new(plan, &self.netinfo).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The result of handling a `JoinResponse`.
+#[derive(Debug)]
+pub enum JoinOutcome<N: Ord> {
+    /// More responses are needed.
+    Pending,
+    /// Enough validators have signed the plan: The node can join the network with it.
+    Ready(Box<JoinPlan<N>>),
+    /// The response is invalid.
+    Invalid(FaultKind),
+}
+
+/// Collects the `JoinResponse`s a new node receives, until enough trusted validators agree on a
+/// plan.
+#[derive(Debug)]
+pub struct JoinSync<N: Ord> {
+    /// The validators whose signatures count, and their public keys.
+    validators: BTreeMap<N, PublicKey>,
+    /// The era and hash of the latest plan each validator sent.
+    latest: BTreeMap<N, (u64, [u8; 32])>,
+    /// The validators that signed each plan, by era and hash.
+    signers: BTreeMap<(u64, [u8; 32]), BTreeSet<N>>,
+}
+
+impl<N: NodeIdT + Serialize> JoinSync<N> {
+    /// Creates a new instance without any responses, that accepts a plan once more than _f_ of
+    /// the given `validators` have signed it.
+    ///
+    /// The validators must come from a trusted source, e.g. the node's configuration: Responses
+    /// from anyone else are rejected, regardless of the validators listed in the plan itself.
+    pub fn new(validators: BTreeMap<N, PublicKey>) -> Self {
+        JoinSync {
+            validators,
+            latest: BTreeMap::new(),
+            signers: BTreeMap::new(),
+        }
+    }
+
+    /// Handles a response received from `sender_id`, and returns the plan if more than _f_ of
+    /// the trusted validators have signed it now.
+    ///
+    /// Only the latest response of each validator counts.
+    pub fn handle_response(
+        &mut self,
+        sender_id: &N,
+        response: JoinResponse<N>,
+    ) -> Result<JoinOutcome<N>> {
+        let JoinResponse { plan, sig } = response;
+        let pub_key = match self.validators.get(sender_id) {
+            Some(pub_key) => pub_key,
+            None => return Ok(JoinOutcome::Invalid(FaultKind::UnknownJoinPlanSigner)),
+        };
+        let ser = plan_bytes(&plan)?;
+        if !pub_key.verify(&sig, &ser) {
+            return Ok(JoinOutcome::Invalid(FaultKind::InvalidJoinPlanSignature));
+        }
+        let key = (plan.era, sha3_256(&ser));
+        if let Some(old_key) = self.latest.insert(sender_id.clone(), key) {
+            if let Some(signers) = self.signers.get_mut(&old_key) {
+                signers.remove(sender_id);
+            }
+        }
+        let signers = self.signers.entry(key).or_default();
+        signers.insert(sender_id.clone());
+        if signers.len() > util::max_faulty(self.validators.len()) {
+            return Ok(JoinOutcome::Ready(Box::new(plan)));
+        }
+        Ok(JoinOutcome::Pending)
+    }
+}
+
+/// Returns the bytes a new node signs to request joining.
+fn request_bytes<N: Serialize>(node_id: &N, pub_key: &PublicKey) -> Result<Vec<u8>> {
+    bincode::serialize(&(SignedKind::JoinRequest, node_id, pub_key))
+        .map_err(|err| Error::SerializeJoinMessage(*err))
+}
+
+/// Returns the bytes a validator signs to vouch for a join plan. The plan contains the era and
+/// the public key set, so the signature can't be replayed in another era.
+fn plan_bytes<N: Ord + Serialize>(plan: &JoinPlan<N>) -> Result<Vec<u8>> {
+    bincode::serialize(&(SignedKind::JoinPlan, plan))
+        .map_err(|err| Error::SerializeJoinMessage(*err))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::iter;
+    use std::sync::Arc;
+
+    use super::{JoinOutcome, JoinRequest, JoinResponse, JoinSync};
+    use crate::crypto::SecretKey;
+    use crate::dynamic_honey_badger::votes::VoteCounter;
+    use crate::dynamic_honey_badger::{
+        Change, ChangeState, DynamicHoneyBadger, DynamicParams, FaultKind, JoinPlan, VoteLimits,
+    };
+    use crate::fault_log::FaultLog;
+    use crate::honey_badger::{ChangeQuorum, ConflictPolicy, Params};
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_join_sync() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let new_plan = |era| JoinPlan {
+            era,
+            change: ChangeState::None,
+            pub_key_set: netinfos[&0].public_key_set().clone(),
+            pub_keys: netinfos[&0].public_key_map().clone(),
+            params: Params::default(),
+            dynamic_params: DynamicParams::default(),
+            addresses: BTreeMap::new(),
+            weights: BTreeMap::new(),
+        };
+        let respond = |id, era| JoinResponse::new(new_plan(era), &netinfos[&id]).expect("sign");
+
+        let sk = SecretKey::random();
+        let request = JoinRequest::new(7usize, &sk).expect("request");
+        assert!(request.verify().expect("verify"));
+        assert_eq!(sk.public_key(), *request.public_key());
+
+        // One validator can't make the new node join, and a node can't support two plans.
+        let mut join_sync = JoinSync::new(netinfos[&0].public_key_map().clone());
+        let handle = |join_sync: &mut JoinSync<_>, id, response| {
+            join_sync.handle_response(&id, response).expect("handle")
+        };
+        match handle(&mut join_sync, 0, respond(0, 5)) {
+            JoinOutcome::Pending => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        match handle(&mut join_sync, 0, respond(0, 9)) {
+            JoinOutcome::Pending => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        // Responses signed by someone else, or by a node that isn't a validator, are rejected.
+        match handle(&mut join_sync, 1, respond(2, 9)) {
+            JoinOutcome::Invalid(FaultKind::InvalidJoinPlanSignature) => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        match handle(&mut join_sync, 4, respond(1, 9)) {
+            JoinOutcome::Invalid(FaultKind::UnknownJoinPlanSigner) => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        match handle(&mut join_sync, 1, respond(1, 5)) {
+            JoinOutcome::Pending => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        // A second validator signing the same plan is enough.
+        match handle(&mut join_sync, 1, respond(1, 9)) {
+            JoinOutcome::Ready(plan) => assert_eq!(9, plan.next_epoch()),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_join_sync_forged_plan() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..5usize, &mut rng).expect("netinfos");
+        // A plan in which the signer is the only validator.
+        let forged_plan = |id: usize| {
+            let pub_key = netinfos[&id].public_key_map()[&id];
+            JoinPlan {
+                era: 9,
+                change: ChangeState::None,
+                pub_key_set: netinfos[&id].public_key_set().clone(),
+                pub_keys: iter::once((id, pub_key)).collect(),
+                params: Params::default(),
+                dynamic_params: DynamicParams::default(),
+                addresses: BTreeMap::new(),
+                weights: BTreeMap::new(),
+            }
+        };
+        let respond = |id| JoinResponse::new(forged_plan(id), &netinfos[&id]).expect("sign");
+        let trusted = netinfos[&0]
+            .public_key_map()
+            .iter()
+            .filter(|(id, _)| **id < 4)
+            .map(|(id, pub_key)| (*id, *pub_key))
+            .collect();
+        let mut join_sync = JoinSync::new(trusted);
+
+        // A single faulty validator can't make the new node join with its forged plan.
+        match join_sync.handle_response(&0, respond(0)).expect("handle") {
+            JoinOutcome::Pending => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+        // A node that lists itself as a validator in its plan, but isn't trusted, is rejected.
+        match join_sync.handle_response(&4, respond(4)).expect("handle") {
+            JoinOutcome::Invalid(FaultKind::UnknownJoinPlanSigner) => (),
+            outcome => panic!("unexpected outcome: {:?}", outcome),
+        }
+    }
+
+    #[test]
+    fn test_join_plan_vote_limits() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let limits = VoteLimits {
+            max_per_voter: Some(1),
+            ..VoteLimits::default()
+        };
+        let plan = JoinPlan {
+            era: 0,
+            change: ChangeState::None,
+            pub_key_set: netinfos[&0].public_key_set().clone(),
+            pub_keys: netinfos[&0].public_key_map().clone(),
+            params: Params::default(),
+            dynamic_params: DynamicParams {
+                vote_limits: limits,
+            },
+            addresses: BTreeMap::new(),
+            weights: BTreeMap::new(),
+        };
+        let (mut dhb, _) = DynamicHoneyBadger::<Vec<usize>, usize>::new_joining(
+            7,
+            SecretKey::random(),
+            plan,
+            &mut rng,
+        )
+        .expect("join");
+        assert_eq!(limits, dhb.dynamic_params().vote_limits);
+
+        // The joining node enforces the network's limits: Validator 0's second vote is rejected.
+        let mut voter = VoteCounter::new(
+            Arc::new(netinfos[&0].clone()),
+            0,
+            VoteLimits::default(),
+            ChangeQuorum::FaultyPlusOne,
+            ConflictPolicy::FirstWins,
+            None,
+        );
+        let pub_keys = netinfos[&0].public_key_map();
+        let mut faults = (1..3).map(|id| {
+            let change = Change::NodeChange(iter::once((id, pub_keys[&id])).collect());
+            let signed_vote = voter.sign_vote_for(change).expect("sign vote").clone();
+            dhb.vote_counter
+                .add_pending_vote(&0, signed_vote)
+                .expect("add pending")
+        });
+        assert_eq!(Some(FaultLog::new()), faults.next());
+        assert_eq!(
+            Some(FaultLog::init(0, FaultKind::TooManyPendingVotes)),
+            faults.next()
+        );
+    }
+}
@@ -13,23 +13,51 @@
 //!
 //! - proposes a change in the set of validators,
 //!
-//! - finalizes that proposed change or
+//! - finalizes that proposed change,
 //!
-//! - updates the encryption schedule.
+//! - updates the encryption schedule,
+//!
+//! - schedules a protocol upgrade or
+//!
+//! - switches to the new protocol version of a scheduled upgrade.
 //!
 //! Unlike Honey Badger, this algorithm allows dynamically adding and removing validators.
 //! As a signal to initiate converting observers to validators or vice versa, it defines a special
 //! `Change` input variant, which contains either a vote `Add(node_id, public_key)`, to add an
-//! existing observer to the set of validators, or `Remove(node_id)` to remove it. Each
-//! validator can have at most one active vote, and casting another vote revokes the previous one.
-//! Once _f + 1_ validators have the same active vote, a reconfiguration process begins: They
-//! create new cryptographic key shares for the new group of validators.
+//! existing observer to the set of validators, or `Remove(node_id)` to remove it. Since the
+//! `NodeChange` a vote is for contains the complete new set of validators, several nodes can also
+//! be added and removed at once, in a single key generation, e.g. to replace one validator with
+//! another, or to grow the network by several validators at once: see `vote_to_replace` and
+//! `vote_to_add_all`. Each validator can have at most one active vote, and casting another vote
+//! revokes the previous one. Once _f + 1_ validators have the same active vote, a reconfiguration
+//! process begins: They create new cryptographic key shares for the new group of validators. A
+//! stricter `ChangeQuorum` for validator set changes can be configured in the `Params`, as well as
+//! a `ConflictPolicy` for changes that contradict each other, e.g. one to remove a node and one to
+//! give it a new key.
 //!
 //! The state of that process after each epoch is communicated via the `change` field in `Batch`.
 //! When this contains an `InProgress(..)` value, key generation begins and the following epoch
 //! starts the next era. The joining validator (in the case of an `Add` change) must be an observer
-//! starting in the following epoch or earlier.  When `change` is `Complete(..)`, the following
-//! epoch starts the next era with the new set of validators.
+//! starting in the following epoch or earlier, e.g. created with the builder's `build_observer`.
+//! When `change` is `Complete(..)`, the following epoch starts the next era with the new set of
+//! validators, and the added nodes switch to their new key shares.
+//! Between batches, `DynamicHoneyBadger::change_state` returns the state of the current change,
+//! and `key_gen_progress` shows how many key generation messages are still missing. A node that
+//! may restart during key generation should persist `key_gen_checkpoint` and, once it is running in
+//! the same era again, pass it to `restore_key_gen`.
+//!
+//! A `RotateKeys` change runs a new key generation among the current validators, and replaces the
+//! shared key without changing the set of validators. For proactive security, the builder's
+//! `key_rotation_interval` makes the validators do that automatically once an era has lasted the
+//! given number of epochs.
+//!
+//! A `ProtocolUpgrade` change needs _2 f + 1_ votes instead. When it wins, it is stored in the
+//! `Params` and the following epoch starts a new era. The era that begins in the upgrade's epoch
+//! uses the new `protocol_version`, which the application can read from each batch's `params()`.
+//!
+//! A `Params` change, e.g. of `max_future_epochs` or the `change_quorum`, needs _2 f + 1_ votes
+//! as well. The winning change is applied to the `Params`, and the following epoch starts a new
+//! era with them on all nodes.
 //!
 //! New observers can only join the network after an epoch where `change` was not `None`. These
 //! epochs' batches contain a `JoinPlan`, which can be sent as an invitation to the new node: The
@@ -37,8 +65,25 @@
 //! following epoch. All `Target::All` messages from that and later epochs must be sent to the new
 //! node.
 //!
+//! A new node that doesn't trust any single validator can send a `JoinRequest` to several of them
+//! instead. They answer with the signed `JoinResponse` from `Batch::join_response`, and a
+//! `JoinSync` outputs the plan once more than _f_ of the validators the node trusts vouched for the
+//! same one.
+//!
 //! Observer nodes can leave the network at any time.
 //!
+//! For consumers downstream of a validator, `Batch::export` returns the public parts of a batch as
+//! an `ExportedBatch`, with a stable, versioned binary encoding. If `Params::batch_certificates`
+//! is set, the validators also threshold-sign each batch, and every node passes the resulting
+//! `BatchCertificate`s to the hook set with `DynamicHoneyBadger::set_certificate_hook`.
+//!
+//! With a `RemovalPolicy`, a validator automatically votes to remove the peers that were reported
+//! for too many faults.
+//!
+//! Votes, key generation messages and address announcements are signed by individual nodes, by
+//! default with their pairing-based keys. A faster `SignatureScheme`, e.g. Ed25519, can be set with
+//! the builder's `signature_scheme`, for all nodes alike.
+//!
 //! These mechanisms create a dynamic network where you can:
 //!
 //! * introduce new nodes as observers,
@@ -46,6 +91,8 @@
 //! * demote validator nodes to observers, and
 //! * remove observer nodes,
 //! * change how frequently nodes use threshold encryption,
+//! * coordinate switching to a new, incompatible protocol version,
+//! * change runtime parameters,
 //!
 //! without interrupting the consensus process.
 //!
@@ -60,33 +107,51 @@
 //! contributions in its own batch. The other transactions are processed: votes are counted and key
 //! generation messages are passed into a `SyncKeyGen` instance.
 //!
-//! Whenever a change receives _f + 1_ votes, the votes are reset and key generation for that
-//! change begins. If key generation completes successfully, the Honey Badger instance is dropped,
-//! and replaced by a new one with the new set of participants. If a different change wins a
-//! vote before that happens, key generation resets again, and is attempted for the new change.
+//! Whenever a change receives enough votes, the votes are reset. That is the configured
+//! `ChangeQuorum` for a validator set change, _2 f + 1_ for a protocol upgrade or a parameter
+//! change, and _f + 1_ for an encryption schedule. For a validator set change, key generation
+//! begins. If it completes successfully, the Honey Badger instance is dropped, and replaced by a
+//! new one with the new set of participants. If a different change wins a vote before that
+//! happens, key generation resets again, and is attempted for the new change. The other changes
+//! take effect immediately, in a new era.
 
 mod batch;
 mod builder;
+mod certificate;
 mod change;
 mod dynamic_honey_badger;
 mod error;
+mod export;
+mod join;
+mod params;
+mod removal_policy;
+mod signing;
 mod votes;
 
 use std::collections::BTreeMap;
+use std::result;
 
-use crate::crypto::{PublicKey, PublicKeySet, Signature};
-use serde::{Deserialize, Serialize};
+use crate::crypto::{PublicKey, PublicKeySet};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use self::votes::{SignedVote, VoteCounter};
-use crate::honey_badger::{EncryptionSchedule, Message as HbMessage, Params};
+use crate::honey_badger::{
+    ChangeQuorum, ConflictPolicy, EncryptionSchedule, Message as HbMessage, Params, ProtocolUpgrade,
+};
 use crate::sync_key_gen::{Ack, Part, SyncKeyGen};
-use crate::NodeIdT;
+use crate::{threshold_sign, util, NetworkInfo, NodeIdT};
 
 pub use self::batch::Batch;
 pub use self::builder::DynamicHoneyBadgerBuilder;
-pub use self::change::{Change, ChangeState};
+pub use self::certificate::BatchCertificate;
+pub use self::change::{Change, ChangeState, ParamChange};
 pub use self::dynamic_honey_badger::DynamicHoneyBadger;
 pub use self::error::{Error, FaultKind, Result};
+pub use self::export::{ExportedBatch, ExportedEraTransition, EXPORT_MAGIC, EXPORT_VERSION};
+pub use self::join::{JoinOutcome, JoinRequest, JoinResponse, JoinSync};
+pub use self::params::DynamicParams;
+pub use self::removal_policy::RemovalPolicy;
+pub use self::signing::{NodeSignature, PairingSignatures, SignatureScheme};
+pub use self::votes::{ChangeVotes, SignedVote, VoteCounter, VoteLimits, VoteState, VoteTally};
 
 /// A `DynamicHoneyBadger` step, possibly containing multiple outputs.
 pub type Step<C, N> = crate::CpStep<DynamicHoneyBadger<C, N>>;
@@ -113,13 +178,18 @@ pub enum KeyGenMessage {
 
 /// A message sent to or received from another node's Honey Badger instance.
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(bound(deserialize = "N: DeserializeOwned"))]
 pub enum Message<N: Ord> {
     /// A message belonging to the `HoneyBadger` algorithm started in the given epoch.
     HoneyBadger(u64, HbMessage<N>),
     /// A transaction to be committed, signed by a node.
-    KeyGen(u64, KeyGenMessage, Box<Signature>),
+    KeyGen(u64, KeyGenMessage, Box<NodeSignature>),
     /// A vote to be committed, signed by a validator.
     SignedVote(SignedVote<N>),
+    /// A network address announcement to be committed, signed by its node.
+    Address(u64, Vec<u8>, Box<NodeSignature>),
+    /// A signature share for the batch with the given era and linear epoch.
+    BatchSignature(u64, u64, threshold_sign::Message),
 }
 
 impl<N: Ord> Message<N> {
@@ -128,6 +198,8 @@ impl<N: Ord> Message<N> {
             Message::HoneyBadger(era, _) => era,
             Message::KeyGen(era, _, _) => era,
             Message::SignedVote(ref signed_vote) => signed_vote.era(),
+            Message::Address(era, _, _) => era,
+            Message::BatchSignature(era, _, _) => era,
         }
     }
 }
@@ -147,6 +219,12 @@ pub struct JoinPlan<N: Ord> {
     pub_keys: BTreeMap<N, PublicKey>,
     /// Parameters controlling Honey Badger's behavior and performance.
     params: Params,
+    /// Parameters controlling Dynamic Honey Badger's own behavior, e.g. the vote limits.
+    dynamic_params: DynamicParams,
+    /// The known network addresses of validators and observers.
+    addresses: BTreeMap<N, Vec<u8>>,
+    /// The explicitly set voting weights of nodes.
+    weights: BTreeMap<N, usize>,
 }
 
 impl<N: Ord> JoinPlan<N> {
@@ -156,6 +234,72 @@ impl<N: Ord> JoinPlan<N> {
     }
 }
 
+/// The transition to a new era, passed to the hook set with `DynamicHoneyBadger::set_era_hook`.
+#[derive(Debug)]
+pub struct EraTransition<'a, N: Ord> {
+    /// The first epoch of the new era.
+    pub era: u64,
+    /// The validators of the previous era, with their public keys.
+    pub old_validators: &'a BTreeMap<N, PublicKey>,
+    /// The validators of the new era, with their public keys.
+    pub new_validators: &'a BTreeMap<N, PublicKey>,
+}
+
+/// The progress of an ongoing key generation, e.g. for monitoring whether it is stuck.
+///
+/// Each participating node sends a `Part`, and then an `Ack` for every `Part`, including its own.
+/// These only count once they have been committed in a batch. Key generation completes as soon as
+/// `required_parts` of the parts have enough acks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyGenProgress {
+    /// The number of nodes participating in the key generation.
+    pub num_nodes: usize,
+    /// The number of committed `Part`s.
+    pub parts: usize,
+    /// The number of nodes whose `Part` has not been committed yet.
+    pub missing_parts: usize,
+    /// The number of committed `Ack`s.
+    pub acks: usize,
+    /// The number of `Ack`s for the committed parts that have not been committed yet.
+    pub missing_acks: usize,
+    /// The number of parts that have enough `Ack`s.
+    pub complete_parts: usize,
+    /// The number of parts that need enough `Ack`s for the key generation to complete.
+    pub required_parts: usize,
+    /// Whether we have received enough values to generate our own key share. Observers don't need
+    /// any.
+    pub share_ready: bool,
+}
+
+/// A serializable checkpoint of an ongoing key generation, so that a node that restarts while a
+/// change is in progress can continue it instead of stalling it.
+///
+/// It contains the committed `Part`s and `Ack`s in the order in which they were handled, and the
+/// key generation messages that were not committed yet. It doesn't include our secret key, which is
+/// taken from the `NetworkInfo` in `DynamicHoneyBadger::restore_key_gen` instead.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct KeyGenCheckpoint<N: Ord> {
+    era: u64,
+    pub_keys: BTreeMap<N, PublicKey>,
+    committed: Vec<(N, KeyGenMessage)>,
+    pending: Vec<SignedKeyGenMsg<N>>,
+    msg_count: BTreeMap<N, usize>,
+    rotation: bool,
+}
+
+impl<N: Ord> KeyGenCheckpoint<N> {
+    /// Returns the epoch in which the checkpointed era began.
+    pub fn era(&self) -> u64 {
+        self.era
+    }
+
+    /// Returns the new validators and their public keys.
+    pub fn public_keys(&self) -> &BTreeMap<N, PublicKey> {
+        &self.pub_keys
+    }
+}
+
 /// The ongoing key generation, together with information about the validator change.
 #[derive(Debug)]
 struct KeyGenState<N: Ord> {
@@ -164,13 +308,42 @@ struct KeyGenState<N: Ord> {
     /// The number of key generation messages received from each peer. At most _N + 1_ are
     /// accepted.
     msg_count: BTreeMap<N, usize>,
+    /// The committed messages that were passed to `key_gen`, in order, to be replayed after a
+    /// restart.
+    committed: Vec<(N, KeyGenMessage)>,
+    /// Whether this is a `RotateKeys` change, i.e. new keys for the current validators.
+    rotation: bool,
 }
 
 impl<N: NodeIdT> KeyGenState<N> {
-    fn new(key_gen: SyncKeyGen<N>) -> Self {
+    fn new(key_gen: SyncKeyGen<N>, rotation: bool) -> Self {
         KeyGenState {
             key_gen,
             msg_count: BTreeMap::new(),
+            committed: Vec::new(),
+            rotation,
+        }
+    }
+
+    /// Returns the change this key generation is for.
+    fn change(&self) -> Change<N> {
+        if self.rotation {
+            Change::RotateKeys
+        } else {
+            Change::NodeChange(self.public_keys().clone())
+        }
+    }
+
+    /// Returns a checkpoint of the key generation in the given era, with the given uncommitted
+    /// messages.
+    fn checkpoint(&self, era: u64, pending: Vec<SignedKeyGenMsg<N>>) -> KeyGenCheckpoint<N> {
+        KeyGenCheckpoint {
+            era,
+            pub_keys: self.public_keys().clone(),
+            committed: self.committed.clone(),
+            pending,
+            msg_count: self.msg_count.clone(),
+            rotation: self.rotation,
         }
     }
 
@@ -185,6 +358,37 @@ impl<N: NodeIdT> KeyGenState<N> {
         self.key_gen.public_keys()
     }
 
+    /// Returns the numbers of handled and outstanding key generation messages.
+    fn progress(&self) -> KeyGenProgress {
+        let kg = &self.key_gen;
+        let num_nodes = kg.num_nodes();
+        let (parts, acks) = (kg.count_parts(), kg.count_acks());
+        KeyGenProgress {
+            num_nodes,
+            parts,
+            missing_parts: num_nodes.saturating_sub(parts),
+            acks,
+            missing_acks: (parts * num_nodes).saturating_sub(acks),
+            complete_parts: kg.count_complete(),
+            required_parts: (util::max_faulty(num_nodes) + 1).max(2 * num_nodes / 3 + 1),
+            share_ready: kg.is_share_ready(),
+        }
+    }
+
+    /// Checks the message's structure, without any cryptographic verification.
+    fn validate(&self, kg_msg: &KeyGenMessage) -> result::Result<(), FaultKind> {
+        match kg_msg {
+            KeyGenMessage::Part(part) => self
+                .key_gen
+                .validate_part(part)
+                .map_err(FaultKind::SyncKeyGenPart),
+            KeyGenMessage::Ack(ack) => self
+                .key_gen
+                .validate_ack(ack)
+                .map_err(FaultKind::SyncKeyGenAck),
+        }
+    }
+
     /// Increments the message count for the given node, and returns the new count.
     fn count_messages(&mut self, node_id: &N) -> usize {
         let count = self.msg_count.entry(node_id.clone()).or_insert(0);
@@ -196,6 +400,7 @@ impl<N: NodeIdT> KeyGenState<N> {
 /// The contribution for the internal `HoneyBadger` instance: this includes a user-defined
 /// application-level contribution as well as internal signed messages.
 #[derive(Clone, Eq, PartialEq, Debug, Serialize, Deserialize, Hash)]
+#[serde(bound(deserialize = "C: Deserialize<'de>, N: DeserializeOwned"))]
 pub struct InternalContrib<C, N: Ord> {
     /// A user-defined contribution.
     contrib: C,
@@ -203,11 +408,15 @@ pub struct InternalContrib<C, N: Ord> {
     key_gen_messages: Vec<SignedKeyGenMsg<N>>,
     /// Signed votes for validator set changes.
     votes: Vec<SignedVote<N>>,
+    /// Signed network address announcements.
+    addresses: Vec<SignedAddress<N>>,
+    /// The proposer's current time, if it has a clock.
+    timestamp: Option<u64>,
 }
 
 /// A signed internal message.
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Hash, Clone)]
-struct SignedKeyGenMsg<N>(u64, N, KeyGenMessage, Signature);
+struct SignedKeyGenMsg<N>(u64, N, KeyGenMessage, NodeSignature);
 
 impl<N> SignedKeyGenMsg<N> {
     /// Returns the era of the ongoing key generation.
@@ -215,3 +424,36 @@ impl<N> SignedKeyGenMsg<N> {
         self.0
     }
 }
+
+/// A network address announcement, signed by the node with the address in the given era.
+#[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Hash, Clone)]
+struct SignedAddress<N>(u64, N, Vec<u8>, NodeSignature);
+
+/// The kind of a signed internal message. It is part of the signed bytes, so that a signature of
+/// one kind of message can't be passed off as another.
+#[derive(Serialize)]
+enum SignedKind {
+    KeyGen,
+    Vote,
+    Address,
+    JoinRequest,
+    JoinPlan,
+}
+
+/// Returns the bytes a node signs to authenticate an internal message of the given kind.
+///
+/// Besides the era, the signature covers the hash of the current public key set. Every key
+/// generation produces a new key set, so if a node is removed and later added again with the same
+/// ID, the messages it signed in its previous tenure can't be replayed.
+fn signed_bytes<N, T>(
+    kind: SignedKind,
+    era: u64,
+    netinfo: &NetworkInfo<N>,
+    payload: &T,
+) -> bincode::Result<Vec<u8>>
+where
+    N: NodeIdT,
+    T: Serialize + ?Sized,
+{
+    bincode::serialize(&(kind, era, netinfo.key_set_hash(), payload))
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+
+use super::VoteLimits;
+
+/// Parameters controlling Dynamic Honey Badger's own behavior, in addition to the `Params` of its
+/// internal `HoneyBadger` instance.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DynamicParams {
+    /// The limits on the pending votes for changes.
+    pub vote_limits: VoteLimits,
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use super::FaultKind;
+use crate::{NetworkInfo, NodeIdT};
+
+/// A policy for automatically voting to remove validators that were reported for too many faults.
+///
+/// Each reported fault is assigned a weight, by default `1` for every kind. Once the total weight
+/// of a validator's faults reaches the threshold, we vote to remove it. If that happens to several
+/// validators, a single vote removes all of them, since each validator can only have one active
+/// vote.
+pub struct RemovalPolicy {
+    /// The total fault weight at which a validator is voted out.
+    threshold: u64,
+    /// The weight of each kind of fault.
+    weight: Box<dyn Fn(&FaultKind) -> u64 + Send + Sync>,
+}
+
+impl fmt::Debug for RemovalPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemovalPolicy")
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl RemovalPolicy {
+    /// Creates a policy that votes to remove a validator once it was reported for `threshold`
+    /// faults of any kind.
+    pub fn new(threshold: u64) -> Self {
+        RemovalPolicy::with_weights(threshold, |_| 1)
+    }
+
+    /// Creates a policy that votes to remove a validator once the total `weight` of its faults
+    /// reaches `threshold`. Faults with weight `0` are ignored, so this can be used to only count
+    /// serious kinds of faults.
+    pub fn with_weights<F>(threshold: u64, weight: F) -> Self
+    where
+        F: Fn(&FaultKind) -> u64 + Send + Sync + 'static,
+    {
+        RemovalPolicy {
+            threshold,
+            weight: Box::new(weight),
+        }
+    }
+}
+
+/// The fault weights of the peers, together with the policy.
+#[derive(Debug)]
+pub(super) struct RemovalState<N> {
+    /// The policy.
+    policy: RemovalPolicy,
+    /// The total weight of the faults reported for each peer that has not reached the threshold.
+    weights: BTreeMap<N, u64>,
+    /// The peers that reached the threshold.
+    flagged: BTreeSet<N>,
+}
+
+impl<N: NodeIdT> RemovalState<N> {
+    pub(super) fn new(policy: RemovalPolicy) -> Self {
+        RemovalState {
+            policy,
+            weights: BTreeMap::new(),
+            flagged: BTreeSet::new(),
+        }
+    }
+
+    /// Adds the weight of a fault, and returns `true` if this made the peer reach the threshold.
+    pub(super) fn record(&mut self, node_id: &N, kind: &FaultKind) -> bool {
+        if self.flagged.contains(node_id) {
+            return false;
+        }
+        let weight = (self.policy.weight)(kind);
+        if weight == 0 {
+            return false;
+        }
+        let total = self.weights.entry(node_id.clone()).or_insert(0);
+        *total = total.saturating_add(weight);
+        if *total < self.policy.threshold {
+            return false;
+        }
+        self.weights.remove(node_id);
+        self.flagged.insert(node_id.clone());
+        true
+    }
+
+    /// Forgets the peers that are not validators anymore, so that they are judged afresh if they
+    /// are added again.
+    pub(super) fn retain_validators(&mut self, netinfo: &NetworkInfo<N>) {
+        self.weights = self
+            .weights
+            .iter()
+            .filter(|(id, _)| netinfo.is_node_validator(id))
+            .map(|(id, weight)| (id.clone(), *weight))
+            .collect();
+        self.flagged = self
+            .flagged
+            .iter()
+            .filter(|id| netinfo.is_node_validator(id))
+            .cloned()
+            .collect();
+    }
+
+    /// Returns the peers that reached the threshold.
+    pub(super) fn flagged(&self) -> &BTreeSet<N> {
+        &self.flagged
+    }
+
+    /// Returns the total weight of the faults reported for the peer.
+    pub(super) fn weight(&self, node_id: &N) -> u64 {
+        if self.flagged.contains(node_id) {
+            return self.policy.threshold;
+        }
+        self.weights.get(node_id).cloned().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RemovalPolicy, RemovalState};
+    use crate::dynamic_honey_badger::FaultKind;
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_fault_weights() {
+        let policy = RemovalPolicy::with_weights(4, |kind| match kind {
+            FaultKind::InvalidVoteSignature => 2,
+            FaultKind::UnexpectedDhbMessageEra => 0,
+            _ => 1,
+        });
+        let mut removal = RemovalState::new(policy);
+        assert!(!removal.record(&1, &FaultKind::UnexpectedDhbMessageEra));
+        assert_eq!(0, removal.weight(&1));
+        assert!(!removal.record(&1, &FaultKind::InvalidVoteSignature));
+        assert!(!removal.record(&1, &FaultKind::TooManyPendingVotes));
+        assert!(!removal.record(&2, &FaultKind::InvalidVoteSignature));
+        assert_eq!(3, removal.weight(&1));
+        // Node 1 reaches the threshold only once.
+        assert!(removal.record(&1, &FaultKind::InvalidCommittedVote));
+        assert!(!removal.record(&1, &FaultKind::InvalidCommittedVote));
+        assert_eq!(4, removal.weight(&1));
+        assert_eq!(
+            vec![1],
+            removal.flagged().iter().cloned().collect::<Vec<_>>()
+        );
+
+        // Nodes that are not validators anymore are forgotten.
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(2..5, &mut rng).expect("netinfos");
+        removal.retain_validators(&netinfos[&2]);
+        assert!(removal.flagged().is_empty());
+        assert_eq!(0, removal.weight(&1));
+        assert_eq!(2, removal.weight(&2));
+    }
+}
@@ -0,0 +1,71 @@
+//! Signatures by individual nodes.
+//!
+//! Votes, key generation messages and address announcements are each signed by a single node. By
+//! default, these are signatures with the nodes' pairing-based keys, which also serve as their
+//! encryption keys in key generation. Verifying those is comparatively slow, so a deployment can
+//! replace them with a different `SignatureScheme`, e.g. Ed25519, while threshold signatures and
+//! encryption keep using the pairing-based keys. All nodes must use the same scheme.
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::crypto::{PublicKey, Signature, SIG_SIZE};
+use crate::{util, Signer};
+
+/// A signature by a single node, in the encoding of the configured `SignatureScheme`.
+#[derive(Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NodeSignature(Vec<u8>);
+
+impl NodeSignature {
+    /// Creates a signature from its encoding.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        NodeSignature(bytes)
+    }
+
+    /// Returns the signature's encoding.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for NodeSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NodeSignature(")?;
+        util::fmt_hex(&self.0, f)?;
+        write!(f, ")")
+    }
+}
+
+/// A signature scheme for the messages that are signed by a single node.
+///
+/// The node signs with its own key, and verifies the other nodes' signatures with their keys;
+/// an implementation other than `PairingSignatures` holds these keys itself. The nodes' pairing
+/// keys are passed in anyway, so that it can e.g. look up the keys by them.
+pub trait SignatureScheme<N>: fmt::Debug + Send + Sync {
+    /// Returns our signature of the document. `signer` holds our pairing-based secret key.
+    fn sign(&self, signer: &dyn Signer, doc: &[u8]) -> NodeSignature;
+
+    /// Returns `true` if `sig` is a valid signature of the document by the given node, whose
+    /// pairing-based public key is `public_key`.
+    fn verify(&self, signer: &N, public_key: &PublicKey, doc: &[u8], sig: &NodeSignature) -> bool;
+}
+
+/// The default signature scheme that uses the nodes' pairing-based keys.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PairingSignatures;
+
+impl<N> SignatureScheme<N> for PairingSignatures {
+    fn sign(&self, signer: &dyn Signer, doc: &[u8]) -> NodeSignature {
+        NodeSignature(signer.sign(doc).to_bytes().to_vec())
+    }
+
+    fn verify(&self, _: &N, public_key: &PublicKey, doc: &[u8], sig: &NodeSignature) -> bool {
+        <[u8; SIG_SIZE]>::try_from(sig.as_bytes())
+            .ok()
+            .and_then(|bytes| Signature::from_bytes(bytes).ok())
+            .filter(|sig| public_key.verify(sig, doc))
+            .is_some()
+    }
+}
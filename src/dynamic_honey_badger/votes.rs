@@ -1,20 +1,51 @@
-use std::collections::{BTreeMap, HashMap};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::sync::Arc;
 
-use crate::crypto::Signature;
 use bincode;
-use serde::{Deserialize, Serialize};
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::{Deserialize, Serialize, Serializer};
+use tiny_keccak::sha3_256;
 
-use super::{Change, Error, FaultKind, Result};
+use super::signing::{NodeSignature, PairingSignatures, SignatureScheme};
+use super::{
+    signed_bytes, Change, ChangeQuorum, ConflictPolicy, Error, FaultKind, Result, SignedKind,
+};
 use crate::fault_log;
-use crate::{NetworkInfo, NodeIdT};
+use crate::{util, NetworkInfo, NodeIdT};
 
 pub type FaultLog<N> = fault_log::FaultLog<N, FaultKind>;
 
+/// Limits on the pending votes a `VoteCounter` buffers. By default, there are none.
+///
+/// Correct nodes rarely change their vote within an era, so these can be set quite low.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteLimits {
+    /// The maximum number of pending votes in the buffer. If it is exceeded, the oldest votes are
+    /// evicted first, starting with those that are already superseded by a committed vote. Our
+    /// own pending vote is never evicted. An evicted vote can't re-enter the buffer: Only a newer
+    /// vote by the same voter can.
+    pub max_pending: Option<usize>,
+    /// The maximum number of pending votes accepted from a single voter within an era. Further
+    /// votes are discarded, and the voter is reported as faulty if it sent the vote itself. Once
+    /// it has exceeded the limit, its votes are discarded without verifying their signatures.
+    pub max_per_voter: Option<u64>,
+    /// The minimum number of epochs between two pending votes accepted from the same voter. A vote
+    /// that arrives sooner is discarded, the voter is reported as faulty if it sent the vote
+    /// itself, and the vote still counts towards `max_per_voter`.
+    pub min_epochs_between: Option<u64>,
+}
+
 /// A buffer and counter collecting pending and committed votes for validator set changes.
 ///
-/// This is reset whenever the set of validators changes or a change reaches _f + 1_ votes. We call
-/// the epochs since the last reset the current _era_.
+/// This is reset whenever the set of validators changes or a change reaches enough votes to win:
+/// the configured `ChangeQuorum` for validator set changes and key rotations, _2 f + 1_ for
+/// protocol upgrades and parameter changes, and _f + 1_ for encryption schedules. We call the
+/// epochs since the last reset the current _era_.
+///
+/// Each vote counts with the voter's weight in the `NetworkInfo`, and the thresholds are computed
+/// from the validators' total weight instead of their number. By default, all weights are 1.
 #[derive(Debug)]
 pub struct VoteCounter<N: Ord> {
     /// Shared network data.
@@ -26,61 +57,312 @@ pub struct VoteCounter<N: Ord> {
     /// Collected votes for adding or removing nodes. Each node has one vote, and casting another
     /// vote revokes the previous one.
     committed: BTreeMap<N, Vote<N>>,
+    /// The limits on the pending votes.
+    limits: VoteLimits,
+    /// The number of votes a validator set change needs to win.
+    quorum: ChangeQuorum,
+    /// How contradictory validator set changes are handled.
+    conflict_policy: ConflictPolicy,
+    /// The number of epochs after which pending and committed votes expire, if any.
+    vote_ttl: Option<u64>,
+    /// The epoch of the latest batch.
+    epoch: u64,
+    /// The epoch in which each voter's pending vote was received.
+    pending_epochs: BTreeMap<N, u64>,
+    /// The epoch in which each voter's committed vote was committed.
+    committed_epochs: BTreeMap<N, u64>,
+    /// The numbers of expired committed votes. Votes with the same or lower numbers are
+    /// obsolete.
+    expired: BTreeMap<N, u64>,
+    /// The arrival number of each voter's pending vote, used to evict the oldest ones first.
+    arrivals: BTreeMap<N, u64>,
+    /// The arrival number of the next pending vote.
+    next_arrival: u64,
+    /// The number of pending votes accepted from each voter in this era.
+    vote_counts: BTreeMap<N, u64>,
+    /// The epoch in which each voter's latest pending vote was accepted.
+    accepted_epochs: BTreeMap<N, u64>,
+    /// The highest number of a validly signed pending vote counted from each voter in this era.
+    /// Unlike the pending votes themselves, these are neither evicted nor expired, so that an old
+    /// vote can't be replayed and counted again.
+    max_nums: BTreeMap<N, u64>,
+    /// The total weight of the committed votes for each change.
+    tallies: HashMap<Change<N>, Tally>,
+    /// The changes that have enough committed votes to win, by decreasing weight of votes and
+    /// increasing hash. The first entry is the winner.
+    qualified: BTreeMap<(Reverse<usize>, [u8; 32]), Change<N>>,
+    /// The scheme the votes are signed with.
+    scheme: Arc<dyn SignatureScheme<N>>,
+}
+
+/// A snapshot of the committed votes in the current era, e.g. for monitoring.
+#[derive(Clone, Debug)]
+pub struct VoteTally<'a, N: Ord> {
+    /// The epoch in which the current era began.
+    pub era: u64,
+    /// The committed votes for each change.
+    pub changes: HashMap<&'a Change<N>, ChangeVotes>,
+    /// The change each validator's committed vote is for.
+    pub votes: BTreeMap<&'a N, &'a Change<N>>,
+    /// The pairs of changes with committed votes that contradict each other.
+    pub conflicts: Vec<(&'a Change<N>, &'a Change<N>)>,
+    /// The validators whose committed vote doesn't count under `ConflictPolicy::RejectConflicting`,
+    /// because it is for a change that conflicts with one that has votes of more than _f_ weight.
+    pub rejected: BTreeSet<&'a N>,
+}
+
+/// The committed votes for a change, and how many it needs to win.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChangeVotes {
+    /// The total weight of the committed votes for the change.
+    pub weight: usize,
+    /// The weight of votes the change needs to win.
+    pub threshold: usize,
+}
+
+/// A serializable checkpoint of a `VoteCounter`'s votes, so that a restarted node neither forgets
+/// the votes of the current era nor signs new votes with stale numbers.
+///
+/// It doesn't include the network information and the limits, quorum and vote lifetime, which
+/// are passed to `VoteCounter::restore` instead.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct VoteState<N: Ord> {
+    era: u64,
+    epoch: u64,
+    pending: BTreeMap<N, SignedVote<N>>,
+    committed: BTreeMap<N, Vote<N>>,
+    pending_epochs: BTreeMap<N, u64>,
+    committed_epochs: BTreeMap<N, u64>,
+    expired: BTreeMap<N, u64>,
+    arrivals: BTreeMap<N, u64>,
+    next_arrival: u64,
+    vote_counts: BTreeMap<N, u64>,
+    accepted_epochs: BTreeMap<N, u64>,
+    max_nums: BTreeMap<N, u64>,
+}
+
+impl<N: Ord> VoteState<N> {
+    /// Returns the epoch in which the checkpointed era began.
+    pub fn era(&self) -> u64 {
+        self.era
+    }
+}
+
+/// The total weight of committed votes for a change, and the change's hash for breaking ties.
+#[derive(Debug)]
+struct Tally {
+    weight: usize,
+    hash: [u8; 32],
 }
 
 impl<N> VoteCounter<N>
 where
     N: NodeIdT + Serialize,
 {
-    /// Creates a new `VoteCounter` object with empty buffer and counter.
-    pub fn new(netinfo: Arc<NetworkInfo<N>>, era: u64) -> Self {
+    /// Creates a new `VoteCounter` object with empty buffer and counter, the given limits on
+    /// pending votes, the given quorum and conflict policy for validator set changes and the given
+    /// vote lifetime in epochs.
+    pub fn new(
+        netinfo: Arc<NetworkInfo<N>>,
+        era: u64,
+        limits: VoteLimits,
+        quorum: ChangeQuorum,
+        conflict_policy: ConflictPolicy,
+        vote_ttl: Option<u64>,
+    ) -> Self {
         VoteCounter {
             era,
             netinfo,
             pending: BTreeMap::new(),
             committed: BTreeMap::new(),
+            limits,
+            quorum,
+            conflict_policy,
+            vote_ttl,
+            epoch: era,
+            pending_epochs: BTreeMap::new(),
+            committed_epochs: BTreeMap::new(),
+            expired: BTreeMap::new(),
+            arrivals: BTreeMap::new(),
+            next_arrival: 0,
+            vote_counts: BTreeMap::new(),
+            accepted_epochs: BTreeMap::new(),
+            max_nums: BTreeMap::new(),
+            tallies: HashMap::new(),
+            qualified: BTreeMap::new(),
+            scheme: Arc::new(PairingSignatures),
+        }
+    }
+
+    /// Sets the scheme that our votes are signed, and the other votes are verified with. By
+    /// default, these are signatures with the validators' pairing-based keys.
+    pub fn set_signature_scheme(&mut self, scheme: Arc<dyn SignatureScheme<N>>) {
+        self.scheme = scheme;
+    }
+
+    /// Creates a `VoteCounter` from a checkpoint taken with `state`, with the given limits on
+    /// pending votes, quorum, conflict policy and vote lifetime. The tallies are computed from the
+    /// committed votes.
+    ///
+    /// The checkpoint is trusted: The signatures of its votes are not verified again.
+    pub fn restore(
+        netinfo: Arc<NetworkInfo<N>>,
+        state: VoteState<N>,
+        limits: VoteLimits,
+        quorum: ChangeQuorum,
+        conflict_policy: ConflictPolicy,
+        vote_ttl: Option<u64>,
+    ) -> Result<Self> {
+        let mut counter = VoteCounter::new(
+            netinfo,
+            state.era,
+            limits,
+            quorum,
+            conflict_policy,
+            vote_ttl,
+        );
+        let changes: Vec<_> = state
+            .committed
+            .iter()
+            .filter_map(|(voter, vote)| {
+                let weight = counter.netinfo.weight(voter);
+                vote.change.clone().map(|change| (change, weight))
+            })
+            .collect();
+        counter.epoch = state.epoch;
+        counter.pending = state.pending;
+        counter.committed = state.committed;
+        counter.pending_epochs = state.pending_epochs;
+        counter.committed_epochs = state.committed_epochs;
+        counter.expired = state.expired;
+        counter.arrivals = state.arrivals;
+        counter.next_arrival = state.next_arrival;
+        counter.vote_counts = state.vote_counts;
+        counter.accepted_epochs = state.accepted_epochs;
+        counter.max_nums = state.max_nums;
+        for (change, weight) in changes {
+            if !counter.tallies.contains_key(&change) {
+                let hash = change_hash(&change)?;
+                counter
+                    .tallies
+                    .insert(change.clone(), Tally { weight: 0, hash });
+            }
+            counter.update_tally(change, |w| w + weight);
+        }
+        Ok(counter)
+    }
+
+    /// Returns a checkpoint of the votes, from which the counter can be restored.
+    pub fn state(&self) -> VoteState<N> {
+        VoteState {
+            era: self.era,
+            epoch: self.epoch,
+            pending: self.pending.clone(),
+            committed: self.committed.clone(),
+            pending_epochs: self.pending_epochs.clone(),
+            committed_epochs: self.committed_epochs.clone(),
+            expired: self.expired.clone(),
+            arrivals: self.arrivals.clone(),
+            next_arrival: self.next_arrival,
+            vote_counts: self.vote_counts.clone(),
+            accepted_epochs: self.accepted_epochs.clone(),
+            max_nums: self.max_nums.clone(),
         }
     }
 
     /// Creates a signed vote for the given change, and inserts it into the pending votes buffer.
     pub fn sign_vote_for(&mut self, change: Change<N>) -> Result<&SignedVote<N>> {
+        self.sign_vote(Some(change))
+    }
+
+    /// Creates a signed vote that retracts our previous vote without voting for another change,
+    /// and inserts it into the pending votes buffer.
+    pub fn sign_retraction(&mut self) -> Result<&SignedVote<N>> {
+        self.sign_vote(None)
+    }
+
+    /// Creates a signed vote for the given change or, if `None`, an abstention, and inserts it
+    /// into the pending votes buffer.
+    fn sign_vote(&mut self, change: Option<Change<N>>) -> Result<&SignedVote<N>> {
         let voter = self.netinfo.our_id().clone();
         let vote = Vote {
             change,
             era: self.era,
             num: self.pending.get(&voter).map_or(0, |sv| sv.vote.num + 1),
         };
-        let ser_vote = bincode::serialize(&vote).map_err(|err| Error::SerializeVote(*err))?;
+        let ser_vote = SerializedVote::new(&vote)?;
+        let ser = signed_bytes(SignedKind::Vote, self.era, &self.netinfo, &ser_vote.0[..])
+            .map_err(|err| Error::SerializeVote(*err))?;
         let signed_vote = SignedVote {
+            sig: self.scheme.sign(self.netinfo.signer().as_ref(), &ser),
             vote,
+            ser_vote,
             voter: voter.clone(),
-            sig: self.netinfo.secret_key().sign(ser_vote),
         };
         self.pending.remove(&voter);
+        self.record_arrival(&voter);
+        self.max_nums.insert(voter.clone(), signed_vote.vote.num);
         Ok(self.pending.entry(voter).or_insert(signed_vote))
     }
 
-    /// Inserts a pending vote into the buffer, if it has a higher number than the existing one.
+    /// Inserts a pending vote into the buffer, if it has a higher number than any vote by the same
+    /// voter that was counted before in this era, or committed.
+    ///
+    /// Violations of the `VoteLimits` are only reported if the voter sent the vote itself:
+    /// Otherwise the sender could replay a correct voter's votes to make it exceed the limits.
     pub fn add_pending_vote(
         &mut self,
         sender_id: &N,
         signed_vote: SignedVote<N>,
     ) -> Result<FaultLog<N>> {
+        let voter = signed_vote.voter.clone();
+        let num = signed_vote.vote.num;
         if signed_vote.vote.era != self.era
-            || self
-                .pending
-                .get(&signed_vote.voter)
-                .map_or(false, |sv| sv.vote.num >= signed_vote.vote.num)
+            || self.max_nums.get(&voter).map_or(false, |&max| max >= num)
+            || self.committed_num(&voter).map_or(false, |max| max >= num)
         {
             return Ok(FaultLog::new()); // The vote is obsolete or already exists.
         }
+        if let (Some(max_per_voter), Some(&count)) =
+            (self.limits.max_per_voter, self.vote_counts.get(&voter))
+        {
+            if count > max_per_voter {
+                return Ok(FaultLog::new()); // The voter has already exceeded the limit.
+            }
+        }
         if !self.validate(&signed_vote)? {
             return Ok(FaultLog::init(
                 sender_id.clone(),
                 FaultKind::InvalidVoteSignature,
             ));
         }
-        self.pending.insert(signed_vote.voter.clone(), signed_vote);
+        let count = self.vote_counts.get(&voter).map_or(1, |count| count + 1);
+        let too_many = self.limits.max_per_voter.map_or(false, |max| count > max);
+        let too_soon = match (
+            self.limits.min_epochs_between,
+            self.accepted_epochs.get(&voter),
+        ) {
+            (Some(min_epochs), Some(&last_epoch)) => {
+                self.epoch < last_epoch.saturating_add(min_epochs)
+            }
+            _ => false,
+        };
+        if (too_many || too_soon) && *sender_id != voter {
+            return Ok(FaultLog::new()); // Only the voter itself is blamed for its votes.
+        }
+        self.vote_counts.insert(voter.clone(), count);
+        self.max_nums.insert(voter.clone(), num);
+        if too_many {
+            return Ok(FaultLog::init(voter, FaultKind::TooManyPendingVotes));
+        }
+        if too_soon {
+            return Ok(FaultLog::init(voter, FaultKind::PendingVoteTooSoon));
+        }
+        self.accepted_epochs.insert(voter.clone(), self.epoch);
+        self.record_arrival(&voter);
+        self.pending.insert(voter, signed_vote);
+        self.evict_pending();
         Ok(FaultLog::new())
     }
 
@@ -88,9 +370,8 @@ where
     /// vote.
     pub fn pending_votes(&self) -> impl Iterator<Item = &SignedVote<N>> {
         self.pending.values().filter(move |signed_vote| {
-            self.committed
-                .get(&signed_vote.voter)
-                .map_or(true, |vote| vote.num < signed_vote.vote.num)
+            self.committed_num(&signed_vote.voter)
+                .map_or(true, |num| num < signed_vote.vote.num)
         })
     }
 
@@ -118,9 +399,8 @@ where
         signed_vote: SignedVote<N>,
     ) -> Result<FaultLog<N>> {
         if self
-            .committed
-            .get(&signed_vote.voter)
-            .map_or(false, |vote| vote.num >= signed_vote.vote.num)
+            .committed_num(&signed_vote.voter)
+            .map_or(false, |num| num >= signed_vote.vote.num)
         {
             return Ok(FaultLog::new()); // The vote is obsolete or already exists.
         }
@@ -130,57 +410,350 @@ where
                 FaultKind::InvalidCommittedVote,
             ));
         }
-        self.committed.insert(signed_vote.voter, signed_vote.vote);
+        let change = signed_vote.vote.change.clone();
+        let new_tally = match change {
+            Some(ref change) if !self.tallies.contains_key(change) => Some(Tally {
+                weight: 0,
+                hash: change_hash(change)?,
+            }),
+            _ => None,
+        };
+        let voter = signed_vote.voter;
+        let weight = self.netinfo.weight(&voter);
+        self.expired.remove(&voter);
+        self.committed_epochs.insert(voter.clone(), self.epoch);
+        if let Some(old_vote) = self.committed.insert(voter.clone(), signed_vote.vote) {
+            if let Some(old_change) = old_vote.change {
+                self.update_tally(old_change, |w| w - weight);
+            }
+        }
+        if let Some(change) = change {
+            if let Some(tally) = new_tally {
+                self.tallies.insert(change.clone(), tally);
+            }
+            self.update_tally(change, |w| w + weight);
+        }
         Ok(FaultLog::new())
     }
 
-    /// Returns the change that has at least _f + 1_ votes, if any.
+    /// Returns the change that has enough votes, if any: _2 f + 1_ for protocol upgrades and
+    /// parameter changes, _f + 1_ for encryption schedules, and the configured quorum for validator
+    /// set changes and key rotations. Votes and _f_ are weighted.
+    ///
+    /// If several changes have enough votes, the one with the greatest weight of votes wins. Ties
+    /// are broken in favor of the change with the lowest hash of its serialized form, so that the
+    /// result doesn't depend on the order in which the votes were counted.
     pub fn compute_winner(&self) -> Option<&Change<N>> {
-        let mut vote_counts: HashMap<&Change<N>, usize> = HashMap::new();
-        for vote in self.committed.values() {
-            let change = &vote.change;
-            let entry = vote_counts.entry(change).or_insert(0);
-            *entry += 1;
-            if *entry > self.netinfo.num_faulty() {
-                return Some(change);
+        self.qualified.values().next()
+    }
+
+    /// Returns the committed votes in the current era.
+    pub fn tally(&self) -> VoteTally<'_, N> {
+        let changes = self
+            .tallies
+            .iter()
+            .map(|(change, tally)| {
+                let votes = ChangeVotes {
+                    weight: tally.weight,
+                    threshold: self.threshold(change),
+                };
+                (change, votes)
+            })
+            .collect();
+        let votes = self
+            .committed
+            .iter()
+            .filter_map(|(voter, vote)| vote.change.as_ref().map(|change| (voter, change)))
+            .collect();
+        let mut by_hash: Vec<_> = self.tallies.iter().collect();
+        by_hash.sort_by_key(|(_, tally)| tally.hash);
+        let current = self.netinfo.public_key_map();
+        let mut conflicts = Vec::new();
+        for (i, (change0, _)) in by_hash.iter().enumerate() {
+            for (change1, _) in &by_hash[(i + 1)..] {
+                if change0.conflicts_with(change1, current) {
+                    conflicts.push((*change0, *change1));
+                }
+            }
+        }
+        VoteTally {
+            era: self.era,
+            changes,
+            votes,
+            conflicts,
+            rejected: self
+                .committed
+                .iter()
+                .filter_map(|(voter, vote)| vote.change.as_ref().map(|change| (voter, change)))
+                .filter(|(_, change)| self.is_blocked(change))
+                .map(|(voter, _)| voter)
+                .collect(),
+        }
+    }
+
+    /// Returns the weight of votes the given change needs to win.
+    fn threshold(&self, change: &Change<N>) -> usize {
+        let total_weight = self.netinfo.total_weight();
+        let faulty_weight = self.netinfo.num_faulty_weight();
+        let threshold = change.vote_threshold(self.quorum, total_weight, faulty_weight);
+        if self.conflict_policy == ConflictPolicy::Supermajority && self.is_contested(change) {
+            threshold.max(2 * faulty_weight + 1)
+        } else {
+            threshold
+        }
+    }
+
+    /// Returns `true` if the change conflicts with another one that has committed votes.
+    fn is_contested(&self, change: &Change<N>) -> bool {
+        let current = self.netinfo.public_key_map();
+        self.tallies
+            .keys()
+            .any(|other| change.conflicts_with(other, current))
+    }
+
+    /// Returns `true` if the change can't win under `ConflictPolicy::RejectConflicting`, because
+    /// it conflicts with another one that has committed votes of more than _f_ weight, i.e. that
+    /// at least one correct validator voted for.
+    fn is_blocked(&self, change: &Change<N>) -> bool {
+        if self.conflict_policy != ConflictPolicy::RejectConflicting {
+            return false;
+        }
+        let current = self.netinfo.public_key_map();
+        let faulty_weight = self.netinfo.num_faulty_weight();
+        self.tallies.iter().any(|(other, tally)| {
+            tally.weight > faulty_weight && change.conflicts_with(other, current)
+        })
+    }
+
+    /// Returns `true` if the change has enough committed votes to win.
+    fn qualifies(&self, change: &Change<N>, weight: usize) -> bool {
+        weight >= self.threshold(change) && !self.is_blocked(change)
+    }
+
+    /// Sets the epoch of the latest batch, and removes the votes that have expired by then: our
+    /// own pending vote is kept, but all other votes expire once `vote_ttl` epochs have passed
+    /// since they were received or committed.
+    ///
+    /// Committed votes only expire in the epoch of a batch, so all validators remove them at the
+    /// same point.
+    pub fn update_epoch(&mut self, epoch: u64) {
+        self.epoch = epoch;
+        let vote_ttl = match self.vote_ttl {
+            Some(vote_ttl) => vote_ttl,
+            None => return,
+        };
+        let is_expired =
+            |(_, vote_epoch): &(&N, &u64)| vote_epoch.saturating_add(vote_ttl) <= epoch;
+        let our_id = self.netinfo.our_id();
+        let expired_pending: Vec<N> = self
+            .pending_epochs
+            .iter()
+            .filter(|entry| is_expired(entry) && entry.0 != our_id)
+            .map(|(voter, _)| voter.clone())
+            .collect();
+        for voter in expired_pending {
+            self.pending.remove(&voter);
+            self.arrivals.remove(&voter);
+            self.pending_epochs.remove(&voter);
+        }
+        let expired_committed: Vec<N> = self
+            .committed_epochs
+            .iter()
+            .filter(is_expired)
+            .map(|(voter, _)| voter.clone())
+            .collect();
+        for voter in expired_committed {
+            self.committed_epochs.remove(&voter);
+            if let Some(vote) = self.committed.remove(&voter) {
+                if let Some(change) = vote.change {
+                    let weight = self.netinfo.weight(&voter);
+                    self.update_tally(change, |w| w - weight);
+                }
+                self.expired.insert(voter, vote.num);
+            }
+        }
+    }
+
+    /// Returns the number of the voter's latest committed vote, even if it has expired.
+    fn committed_num(&self, voter: &N) -> Option<u64> {
+        match self.committed.get(voter) {
+            Some(vote) => Some(vote.num),
+            None => self.expired.get(voter).cloned(),
+        }
+    }
+
+    /// Updates the weight of votes for the given change, which must have a tally, and keeps track
+    /// of whether the change has enough votes to win.
+    fn update_tally<F>(&mut self, change: Change<N>, f: F)
+    where
+        F: FnOnce(usize) -> usize,
+    {
+        let threshold = self.threshold(&change);
+        let tally = match self.tallies.get_mut(&change) {
+            Some(tally) => tally,
+            None => return,
+        };
+        let old_weight = tally.weight;
+        tally.weight = f(old_weight);
+        let (new_weight, hash) = (tally.weight, tally.hash);
+        if old_weight >= threshold {
+            self.qualified.remove(&(Reverse(old_weight), hash));
+        }
+        if new_weight == 0 {
+            self.tallies.remove(&change);
+        } else if new_weight >= threshold {
+            self.qualified.insert((Reverse(new_weight), hash), change);
+        }
+        if self.conflict_policy != ConflictPolicy::FirstWins {
+            // A change of weight can affect whether the changes it conflicts with qualify.
+            self.requalify();
+        }
+    }
+
+    /// Recomputes which changes have enough committed votes to win.
+    fn requalify(&mut self) {
+        let qualified = self
+            .tallies
+            .iter()
+            .filter(|(change, tally)| self.qualifies(change, tally.weight))
+            .map(|(change, tally)| ((Reverse(tally.weight), tally.hash), change.clone()))
+            .collect();
+        self.qualified = qualified;
+    }
+
+    /// Marks the voter's pending vote as the newest one.
+    fn record_arrival(&mut self, voter: &N) {
+        self.arrivals.insert(voter.clone(), self.next_arrival);
+        self.pending_epochs.insert(voter.clone(), self.epoch);
+        self.next_arrival += 1;
+    }
+
+    /// Removes the oldest pending votes until their number is within the limit. Votes that are
+    /// superseded by a committed vote are removed first. The voters' `max_nums` are kept, so the
+    /// evicted votes are not accepted again.
+    fn evict_pending(&mut self) {
+        let max_pending = match self.limits.max_pending {
+            Some(max_pending) => max_pending,
+            None => return,
+        };
+        while self.pending.len() > max_pending {
+            let our_id = self.netinfo.our_id();
+            let evict_key = |signed_vote: &SignedVote<N>| {
+                let is_current = match self.committed_num(&signed_vote.voter) {
+                    Some(num) => num < signed_vote.vote.num,
+                    None => true,
+                };
+                (is_current, self.arrivals.get(&signed_vote.voter).cloned())
+            };
+            let oldest = self
+                .pending
+                .values()
+                .filter(|signed_vote| signed_vote.voter != *our_id)
+                .min_by_key(|signed_vote| evict_key(signed_vote))
+                .map(|signed_vote| signed_vote.voter.clone());
+            match oldest {
+                Some(voter) => {
+                    self.pending.remove(&voter);
+                    self.arrivals.remove(&voter);
+                    self.pending_epochs.remove(&voter);
+                }
+                None => return, // Only our own vote is left.
             }
         }
-        None
     }
 
     /// Returns `true` if the signature is valid.
     fn validate(&self, signed_vote: &SignedVote<N>) -> Result<bool> {
-        let ser_vote =
-            bincode::serialize(&signed_vote.vote).map_err(|err| Error::SerializeVote(*err))?;
-        let pk_opt = self.netinfo.public_key(&signed_vote.voter);
-        Ok(pk_opt.map_or(false, |pk| pk.verify(&signed_vote.sig, ser_vote)))
+        let ser_vote = &signed_vote.ser_vote.0[..];
+        let ser = signed_bytes(SignedKind::Vote, signed_vote.era(), &self.netinfo, ser_vote)
+            .map_err(|err| Error::SerializeVote(*err))?;
+        let voter = &signed_vote.voter;
+        let pk_opt = self.netinfo.public_key(voter);
+        Ok(pk_opt.map_or(false, |pk| {
+            self.scheme.verify(voter, pk, &ser, &signed_vote.sig)
+        }))
     }
 }
 
+/// Returns the hash of the serialized change, which breaks ties between winning changes.
+fn change_hash<N: Ord + Serialize>(change: &Change<N>) -> Result<[u8; 32]> {
+    let ser_change = bincode::serialize(change).map_err(|err| Error::SerializeVote(*err))?;
+    Ok(sha3_256(&ser_change))
+}
+
 /// A vote fore removing or adding a validator.
 #[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Hash, Clone)]
 struct Vote<N: Ord> {
-    /// The change this vote is for.
-    change: Change<N>,
+    /// The change this vote is for, or `None` if the voter abstains.
+    change: Option<Change<N>>,
     /// The epoch in which the current era began.
     era: u64,
     /// The vote number: VoteCounter can be changed by casting another vote with a higher number.
     num: u64,
 }
 
+/// The serialized form of a `Vote`, which is what the voter signs, together with the hash of the
+/// current public key set.
+///
+/// It is computed once when the vote is signed, or received as is from the network, and kept with
+/// the vote, so that validating the signature doesn't need to serialize the vote again.
+#[derive(Eq, PartialEq, Hash, Clone)]
+pub struct SerializedVote(Vec<u8>);
+
+impl SerializedVote {
+    /// Serializes the given vote.
+    fn new<N: Ord + Serialize>(vote: &Vote<N>) -> Result<Self> {
+        let bytes = bincode::serialize(vote).map_err(|err| Error::SerializeVote(*err))?;
+        Ok(SerializedVote(bytes))
+    }
+}
+
+impl fmt::Debug for SerializedVote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SerializedVote(")?;
+        util::fmt_hex(&self.0, f)?;
+        write!(f, ")")
+    }
+}
+
 /// A signed vote for removing or adding a validator.
-#[derive(Eq, PartialEq, Debug, Serialize, Deserialize, Hash, Clone)]
+///
+/// On the wire, only the serialized vote is included, not the vote itself. It is deserialized
+/// once when the signed vote is received.
+#[derive(Eq, PartialEq, Debug, Hash, Clone)]
 pub struct SignedVote<N: Ord> {
     vote: Vote<N>,
+    ser_vote: SerializedVote,
     voter: N,
-    sig: Signature,
+    sig: NodeSignature,
+}
+
+impl<N: Ord + Serialize> Serialize for SignedVote<N> {
+    fn serialize<S: Serializer>(&self, s: S) -> ::std::result::Result<S::Ok, S::Error> {
+        (&self.ser_vote.0, &self.voter, &self.sig).serialize(s)
+    }
+}
+
+impl<'de, N: Ord + DeserializeOwned> Deserialize<'de> for SignedVote<N> {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> ::std::result::Result<Self, D::Error> {
+        let (bytes, voter, sig): (Vec<u8>, N, NodeSignature) = Deserialize::deserialize(d)?;
+        let vote = bincode::deserialize(&bytes).map_err(de::Error::custom)?;
+        Ok(SignedVote {
+            vote,
+            ser_vote: SerializedVote(bytes),
+            voter,
+            sig,
+        })
+    }
 }
 
 impl<N: Ord> SignedVote<N> {
+    /// Returns the epoch in which the era the vote belongs to began.
     pub fn era(&self) -> u64 {
         self.vote.era
     }
 
+    /// Returns the ID of the validator who signed the vote.
     pub fn voter(&self) -> &N {
         &self.voter
     }
@@ -189,12 +762,21 @@ impl<N: Ord> SignedVote<N> {
 #[cfg(test)]
 mod tests {
     use std::iter;
+    use std::ops::Range;
     use std::sync::Arc;
 
-    use super::{Change, FaultKind, SignedVote, VoteCounter};
+    use super::{Change, ChangeVotes, FaultKind, SignedVote, VoteCounter, VoteLimits};
+    use crate::crypto::SecretKeySet;
+    use crate::dynamic_honey_badger::ParamChange;
     use crate::fault_log::FaultLog;
+    use crate::honey_badger::{
+        ChangeQuorum, ConflictPolicy, EncryptionSchedule, Params, ProtocolUpgrade,
+    };
     use crate::NetworkInfo;
     use rand;
+    use tiny_keccak::sha3_256;
+
+    const FIRST_WINS: ConflictPolicy = ConflictPolicy::FirstWins;
 
     /// Returns a vector of `node_num` `VoteCounter`s, and some signed example votes.
     ///
@@ -209,8 +791,10 @@ mod tests {
         let pub_keys = netinfos[&0].public_key_map().clone();
 
         // Create a `VoteCounter` instance for each node.
-        let create_counter =
-            |(_, netinfo): (_, NetworkInfo<_>)| VoteCounter::new(Arc::new(netinfo), era);
+        let (limits, quorum) = (VoteLimits::default(), ChangeQuorum::FaultyPlusOne);
+        let create_counter = |(_, netinfo): (_, NetworkInfo<_>)| {
+            VoteCounter::new(Arc::new(netinfo), era, limits, quorum, FIRST_WINS, None)
+        };
         let mut counters: Vec<_> = netinfos.into_iter().map(create_counter).collect();
 
         // Sign a few votes.
@@ -309,4 +893,568 @@ mod tests {
             winner => panic!("Unexpected winner: {:?}", winner),
         }
     }
+
+    #[test]
+    fn test_restore_state() {
+        let (mut counters, sv) = setup(4, 5);
+        // We will only use counter number 0.
+        let ct = &mut counters[0];
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        let faults = ct
+            .add_committed_votes(&1, vec![sv[1][1].clone(), sv[2][1].clone()])
+            .expect("add committed");
+        assert!(faults.is_empty());
+
+        // The checkpoint survives serialization, and the restored counter has the same tallies.
+        let ser = bincode::serialize(&ct.state()).expect("serialize");
+        let state = bincode::deserialize(&ser).expect("deserialize");
+        let (limits, quorum) = (VoteLimits::default(), ChangeQuorum::FaultyPlusOne);
+        let mut restored =
+            VoteCounter::restore(ct.netinfo.clone(), state, limits, quorum, FIRST_WINS, None)
+                .expect("restore");
+        assert_eq!(ct.state(), restored.state());
+        assert_eq!(ct.compute_winner(), restored.compute_winner());
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            restored.pending_votes().collect::<Vec<_>>()
+        );
+        // Our next vote continues the numbering.
+        let change = sv[0][0].vote.change.clone().expect("change");
+        let signed_vote = restored.sign_vote_for(change).expect("sign vote");
+        assert_eq!(4, signed_vote.vote.num);
+    }
+
+    #[test]
+    fn test_pending_vote_limits() {
+        let node_num = 4;
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        // We will only use counter number 0, and allow only two pending votes, and one per voter.
+        let ct = &mut counters[0];
+        ct.limits = VoteLimits {
+            max_pending: Some(2),
+            max_per_voter: Some(1),
+            min_epochs_between: None,
+        };
+
+        // Node 0 already contains its own vote. The third vote evicts the oldest one by a peer.
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        let faults = ct
+            .add_pending_vote(&2, sv[2][1].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[2][1]]
+        );
+
+        // Node 1's second vote in this era exceeds its limit.
+        let faults = ct
+            .add_pending_vote(&1, sv[1][3].clone())
+            .expect("add pending");
+        assert_eq!(faults, FaultLog::init(1, FaultKind::TooManyPendingVotes));
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[2][1]]
+        );
+        // Further votes by node 1 are discarded without verifying their signature.
+        let fake_vote = SignedVote {
+            sig: sv[2][1].sig.clone(),
+            ..sv[1][3].clone()
+        };
+        let faults = ct.add_pending_vote(&3, fake_vote).expect("add pending");
+        assert!(faults.is_empty());
+    }
+
+    #[test]
+    fn test_pending_vote_replay() {
+        let (mut counters, sv) = setup(4, 5);
+        // We will only use counter number 0, and allow only two pending votes, and one per voter.
+        let ct = &mut counters[0];
+        ct.limits = VoteLimits {
+            max_pending: Some(2),
+            max_per_voter: Some(1),
+            min_epochs_between: Some(2),
+        };
+        ct.vote_ttl = Some(2);
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        // Node 3 floods the buffer with node 2's vote, evicting node 1's, and then replays it.
+        // It is not counted again, and node 1 is not reported.
+        let faults = ct
+            .add_pending_vote(&3, sv[2][1].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        let faults = ct
+            .add_pending_vote(&3, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        assert_eq!(Some(&1), ct.vote_counts.get(&1));
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[2][1]]
+        );
+
+        // An expired vote can't be replayed either.
+        ct.update_epoch(7);
+        assert_eq!(ct.pending_votes().collect::<Vec<_>>(), vec![&sv[0][3]]);
+        let faults = ct
+            .add_pending_vote(&3, sv[2][1].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        assert_eq!(ct.pending_votes().collect::<Vec<_>>(), vec![&sv[0][3]]);
+
+        // Node 2's next vote exceeds its limit. If node 3 relays it, it is dropped, and node 2 is
+        // only reported once it sends one itself.
+        let faults = ct
+            .add_pending_vote(&3, sv[2][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        let faults = ct
+            .add_pending_vote(&2, sv[2][3].clone())
+            .expect("add pending");
+        assert_eq!(faults, FaultLog::init(2, FaultKind::TooManyPendingVotes));
+    }
+
+    #[test]
+    fn test_pending_vote_eviction() {
+        let (mut counters, sv) = setup(4, 5);
+        // We will only use counter number 0, and allow only two pending votes.
+        let ct = &mut counters[0];
+        ct.limits = VoteLimits {
+            max_pending: Some(2),
+            ..VoteLimits::default()
+        };
+        for (id, vote) in &[(1, &sv[1][1]), (2, &sv[2][1]), (1, &sv[1][1])] {
+            let faults = ct
+                .add_pending_vote(id, (*vote).clone())
+                .expect("add pending");
+            assert!(faults.is_empty());
+        }
+        // Node 1's vote was evicted by node 2's, and can't re-enter, even if node 1 sends it again.
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[2][1]]
+        );
+        // A newer vote by node 1 evicts node 2's.
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[1][2]]
+        );
+    }
+
+    #[test]
+    fn test_pending_vote_rate_limit() {
+        let (mut counters, sv) = setup(4, 5);
+        // We will only use counter number 0, and accept a vote per voter every two epochs.
+        let ct = &mut counters[0];
+        ct.limits = VoteLimits {
+            min_epochs_between: Some(2),
+            ..VoteLimits::default()
+        };
+        let faults = ct
+            .add_pending_vote(&1, sv[1][1].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        ct.update_epoch(6);
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert_eq!(faults, FaultLog::init(1, FaultKind::PendingVoteTooSoon));
+        ct.update_epoch(7);
+        let faults = ct
+            .add_pending_vote(&1, sv[1][3].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[1][3]]
+        );
+    }
+
+    #[test]
+    fn test_protocol_upgrade_threshold() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        let (mut counters, _) = setup(node_num, era);
+        let upgrade = Change::ProtocolUpgrade(ProtocolUpgrade {
+            version: 1,
+            epoch: 20,
+        });
+        let upgrade_votes: Vec<_> = counters
+            .iter_mut()
+            .map(|ct| {
+                ct.sign_vote_for(upgrade.clone())
+                    .expect("sign vote")
+                    .clone()
+            })
+            .collect();
+        let ct = &mut counters[0];
+
+        // Unlike other changes, _f + 1_ votes are not enough for an upgrade.
+        let faults = ct
+            .add_committed_votes(&1, upgrade_votes[..2].to_vec())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+
+        // With _2 f + 1_ votes, the upgrade wins.
+        let faults = ct
+            .add_committed_vote(&1, upgrade_votes[2].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), Some(&upgrade));
+    }
+
+    #[test]
+    fn test_param_change_threshold() {
+        let (mut counters, _) = setup(4, 5);
+        let param_change = ParamChange::MaxFutureEpochs(10);
+        let change = Change::Params(param_change);
+        let votes: Vec<_> = counters
+            .iter_mut()
+            .map(|ct| ct.sign_vote_for(change.clone()).expect("sign vote").clone())
+            .collect();
+        let ct = &mut counters[0];
+
+        // Like upgrades, parameter changes need _2 f + 1_ votes.
+        let faults = ct
+            .add_committed_votes(&1, votes[..2].to_vec())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+        let faults = ct
+            .add_committed_vote(&1, votes[2].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), Some(&change));
+
+        let mut params = Params::default();
+        param_change.apply(&mut params);
+        assert_eq!(10, params.max_future_epochs);
+
+        // Switching the encryption schedule is its own kind of change, and only needs _f + 1_.
+        let schedule_change: Change<usize> = Change::EncryptionSchedule(EncryptionSchedule::Never);
+        let quorum = ChangeQuorum::TwoFaultyPlusOne;
+        assert_eq!(2, schedule_change.vote_threshold(quorum, 4, 1));
+        assert_eq!(3, change.vote_threshold(quorum, 4, 1));
+    }
+
+    #[test]
+    fn test_simultaneous_winners() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        let change_hash = |change: &Change<usize>| {
+            sha3_256(&bincode::serialize(change).expect("serialize change"))
+        };
+        let expected = [&sv[0][1].vote.change, &sv[0][2].vote.change]
+            .iter()
+            .filter_map(|change| change.as_ref())
+            .min_by_key(|change| change_hash(change))
+            .cloned();
+
+        // Two changes reach _f + 1_ votes in the same batch. The winner must not depend on the
+        // order of the votes.
+        let vote_batch = vec![
+            sv[0][1].clone(),
+            sv[1][2].clone(),
+            sv[2][1].clone(),
+            sv[3][2].clone(),
+        ];
+        for (i, ct) in counters.iter_mut().enumerate() {
+            let mut votes = vote_batch.clone();
+            votes.rotate_left(i);
+            let faults = ct.add_committed_votes(&1, votes).expect("add committed");
+            assert!(faults.is_empty());
+            assert_eq!(ct.compute_winner(), expected.as_ref());
+        }
+    }
+
+    #[test]
+    fn test_many_voters() {
+        let node_num = 100; // At most 33 faulty nodes.
+        let era = 5;
+        let mut rng = rand::rngs::OsRng::new().expect("could not initialize OsRng");
+        let netinfos = NetworkInfo::generate_map(0..node_num, &mut rng)
+            .expect("Failed to generate `NetworkInfo` map");
+        let pub_keys = netinfos[&0].public_key_map().clone();
+        let (limits, quorum) = (VoteLimits::default(), ChangeQuorum::FaultyPlusOne);
+        let create_counter = |(_, netinfo): (_, NetworkInfo<_>)| {
+            VoteCounter::new(Arc::new(netinfo), era, limits, quorum, FIRST_WINS, None)
+        };
+        let mut counters: Vec<_> = netinfos.into_iter().map(create_counter).collect();
+        let only = |id: usize| Change::NodeChange(iter::once((id, pub_keys[&id])).collect());
+        let mut sign_votes = |range: Range<usize>, change: &Change<usize>| {
+            counters[range.clone()]
+                .iter_mut()
+                .map(|ct| ct.sign_vote_for(change.clone()).expect("sign vote").clone())
+                .collect::<Vec<_>>()
+        };
+
+        // Both changes have enough votes, but the second one has more.
+        let mut votes = sign_votes(0..40, &only(0));
+        votes.extend(sign_votes(40..100, &only(1)));
+        // Sixty voters change their mind, which supersedes their previous votes.
+        let new_votes = sign_votes(20..80, &only(0));
+        let ct = &mut counters[0];
+        let faults = ct.add_committed_votes(&1, votes).expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), Some(&only(1)));
+        let faults = ct
+            .add_committed_votes(&1, new_votes)
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), Some(&only(0)));
+    }
+
+    #[test]
+    fn test_change_quorum() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        // We will only use counter number 0, and require _2 f + 1_ votes for validator changes.
+        let ct = &mut counters[0];
+        ct.quorum = ChangeQuorum::TwoFaultyPlusOne;
+
+        let vote_batch = vec![sv[1][1].clone(), sv[2][1].clone()];
+        let faults = ct
+            .add_committed_votes(&1, vote_batch)
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+
+        // The third vote reaches the quorum.
+        let faults = ct
+            .add_committed_vote(&1, sv[3][1].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), sv[1][1].vote.change.as_ref());
+    }
+
+    #[test]
+    fn test_conflict_policies() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        let run = |policy| {
+            let (mut counters, _) = setup(node_num, era);
+            // Node 1 votes to remove node 3, nodes 2 and 3 vote to give node 3 another key.
+            let pub_keys = counters[0].netinfo.public_key_map().clone();
+            let mut removed = pub_keys.clone();
+            removed.remove(&3);
+            let mut rekeyed = pub_keys.clone();
+            rekeyed.insert(3, pub_keys[&0]);
+            let (remove, rekey) = (Change::NodeChange(removed), Change::NodeChange(rekeyed));
+            assert!(remove.conflicts_with(&rekey, &pub_keys));
+            assert!(!remove.conflicts_with(&remove, &pub_keys));
+            let mut sign = |i: usize, change: &Change<usize>| {
+                counters[i]
+                    .sign_vote_for(change.clone())
+                    .expect("sign vote")
+                    .clone()
+            };
+            let votes = vec![sign(1, &remove), sign(2, &rekey), sign(3, &rekey)];
+            let our_vote = sign(0, &rekey);
+            let mut ct = counters.swap_remove(0);
+            ct.conflict_policy = policy;
+            let faults = ct.add_committed_votes(&1, votes).expect("add committed");
+            assert!(faults.is_empty());
+            (ct, our_vote, remove, rekey)
+        };
+
+        // By default, the change that reaches the quorum wins, and the conflict is reported.
+        let (ct, _, remove, rekey) = run(ConflictPolicy::FirstWins);
+        assert_eq!(ct.compute_winner(), Some(&rekey));
+        let tally = ct.tally();
+        assert_eq!(1, tally.conflicts.len());
+        let (change0, change1) = tally.conflicts[0];
+        assert!((change0, change1) == (&remove, &rekey) || (change0, change1) == (&rekey, &remove));
+
+        // A single faulty voter can't block a change that the quorum supports: Only the votes for
+        // the change that conflicts with one that has more than _f_ votes are not counted.
+        let (mut ct, _, remove, rekey) = run(ConflictPolicy::RejectConflicting);
+        assert_eq!(ct.compute_winner(), Some(&rekey));
+        let tally = ct.tally();
+        assert_eq!(1, tally.conflicts.len());
+        assert_eq!(vec![&1], tally.rejected.into_iter().collect::<Vec<_>>());
+        assert_eq!(1, tally.changes[&remove].weight);
+
+        // If both sides have more than _f_ votes, neither of them wins.
+        let vote = ct.sign_vote_for(remove.clone()).expect("sign vote").clone();
+        let faults = ct.add_committed_vote(&1, vote).expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+        let rejected: Vec<_> = ct.tally().rejected.into_iter().cloned().collect();
+        assert_eq!(vec![0, 1, 2, 3], rejected);
+
+        // Both changes need _2 f + 1_ votes.
+        let (mut ct, our_vote, _, rekey) = run(ConflictPolicy::Supermajority);
+        assert_eq!(ct.compute_winner(), None);
+        let expected = ChangeVotes {
+            weight: 2,
+            threshold: 3,
+        };
+        assert_eq!(expected, ct.tally().changes[&rekey]);
+        let faults = ct.add_committed_vote(&1, our_vote).expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), Some(&rekey));
+    }
+
+    #[test]
+    fn test_signed_vote_serialization() {
+        let (counters, sv) = setup(4, 5);
+        let ser = bincode::serialize(&sv[1][2]).expect("serialize");
+        let de: SignedVote<usize> = bincode::deserialize(&ser).expect("deserialize");
+        assert_eq!(de, sv[1][2]);
+        assert!(counters[0].validate(&de).expect("validate"));
+    }
+
+    #[test]
+    fn test_vote_expiry() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        // We will only use counter number 0, with votes expiring after three epochs.
+        let ct = &mut counters[0];
+        ct.vote_ttl = Some(3);
+
+        let faults = ct
+            .add_committed_vote(&1, sv[1][1].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        ct.update_epoch(7);
+        let faults = ct
+            .add_committed_vote(&1, sv[2][1].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), sv[1][1].vote.change.as_ref());
+
+        // Node 1's vote expires, and can't be committed again.
+        ct.update_epoch(8);
+        assert_eq!(ct.compute_winner(), None);
+        let faults = ct
+            .add_committed_vote(&1, sv[1][1].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+
+        // Pending votes expire, too, except for our own.
+        let faults = ct
+            .add_pending_vote(&1, sv[1][2].clone())
+            .expect("add pending");
+        assert!(faults.is_empty());
+        ct.update_epoch(10);
+        assert_eq!(
+            ct.pending_votes().collect::<Vec<_>>(),
+            vec![&sv[0][3], &sv[1][2]]
+        );
+        ct.update_epoch(11);
+        assert_eq!(ct.pending_votes().collect::<Vec<_>>(), vec![&sv[0][3]]);
+    }
+
+    #[test]
+    fn test_retraction() {
+        let node_num = 4; // At most one faulty node.
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        let retraction = counters[1].sign_retraction().expect("sign").clone();
+        // We will only use counter number 0.
+        let ct = &mut counters[0];
+
+        let faults = ct
+            .add_committed_votes(&1, vec![sv[1][1].clone(), sv[2][1].clone()])
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), sv[1][1].vote.change.as_ref());
+
+        // Node 1 retracts its vote, so the change doesn't have enough votes anymore.
+        let faults = ct
+            .add_committed_vote(&1, retraction)
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+        assert!(!ct.tally().votes.contains_key(&1));
+    }
+
+    #[test]
+    fn test_weighted_votes() {
+        let node_num = 4;
+        let era = 5;
+        // Create the counter instances and the matrix of signed votes.
+        let (mut counters, sv) = setup(node_num, era);
+        // We will only use counter number 0. Node 3 has weight 5, so the total weight is 8, the
+        // faulty weight is 2, and _f + 1_ is 3.
+        let ct = &mut counters[0];
+        Arc::get_mut(&mut ct.netinfo)
+            .expect("unique netinfo")
+            .set_weight(3, 5);
+
+        // Two nodes with weight 1 are not enough.
+        let vote_batch = vec![sv[1][1].clone(), sv[2][1].clone()];
+        let faults = ct
+            .add_committed_votes(&1, vote_batch)
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), None);
+
+        // Node 3 alone outweighs them.
+        let faults = ct
+            .add_committed_vote(&1, sv[3][2].clone())
+            .expect("add committed");
+        assert!(faults.is_empty());
+        assert_eq!(ct.compute_winner(), sv[3][2].vote.change.as_ref());
+
+        let tally = ct.tally();
+        assert_eq!(era, tally.era);
+        let votes = ChangeVotes {
+            weight: 5,
+            threshold: 3,
+        };
+        let change = sv[3][2].vote.change.as_ref().expect("change");
+        assert_eq!(Some(&votes), tally.changes.get(change));
+        assert_eq!(sv[1][1].vote.change.as_ref(), tally.votes.get(&2).cloned());
+    }
+
+    #[test]
+    fn test_vote_replay_across_key_sets() {
+        let mut rng = rand::rngs::OsRng::new().expect("could not initialize OsRng");
+        let (mut counters, _) = setup(4, 0);
+        // Node 1 keeps its key, but the validators get a new key set, as if it had been removed
+        // and added again.
+        let old_netinfo = counters[1].netinfo.clone();
+        let pk_set = SecretKeySet::random(1, &mut rng).public_keys();
+        let netinfo = NetworkInfo::new(
+            1,
+            None,
+            pk_set,
+            old_netinfo.secret_key().expect("secret key").clone(),
+            old_netinfo.public_key_map().clone(),
+        );
+        let (limits, quorum) = (VoteLimits::default(), ChangeQuorum::FaultyPlusOne);
+        let mut counter = VoteCounter::new(Arc::new(netinfo), 0, limits, quorum, FIRST_WINS, None);
+        let change = Change::NodeChange(old_netinfo.public_key_map().clone());
+        let signed_vote = counter.sign_vote_for(change).expect("sign vote").clone();
+        // The vote doesn't validate under the current key set.
+        let faults = counters[0]
+            .add_pending_vote(&1, signed_vote)
+            .expect("add pending");
+        assert_eq!(faults, FaultLog::init(1, FaultKind::InvalidVoteSignature));
+    }
 }
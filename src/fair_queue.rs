@@ -0,0 +1,96 @@
+//! A queue of work items from different peers that is processed round-robin.
+//!
+//! When messages from many peers have accumulated, e.g. because they were meant for a later epoch,
+//! handling them in the order of the senders' IDs would let a single peer with a long backlog
+//! delay everyone else: Until all of its messages are verified, the coin shares of the other peers
+//! are not even looked at. A `FairQueue` instead returns one item from each peer in turn.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::NodeIdT;
+
+/// A queue that returns the items of all peers interleaved, one per peer and round.
+#[derive(Debug)]
+pub(crate) struct FairQueue<N, T> {
+    /// The pending items of each peer.
+    items: BTreeMap<N, VecDeque<T>>,
+    /// The peers with pending items, in the order in which they are served next.
+    turns: VecDeque<N>,
+}
+
+impl<N: NodeIdT, T> Default for FairQueue<N, T> {
+    fn default() -> Self {
+        FairQueue {
+            items: BTreeMap::new(),
+            turns: VecDeque::new(),
+        }
+    }
+}
+
+impl<N: NodeIdT, T> FairQueue<N, T> {
+    /// Appends an item to the peer's queue. A peer that had no pending items is served after all
+    /// peers that already have.
+    pub(crate) fn push(&mut self, sender_id: N, item: T) {
+        let queue = self.items.entry(sender_id.clone()).or_default();
+        if queue.is_empty() {
+            self.turns.push_back(sender_id);
+        }
+        queue.push_back(item);
+    }
+
+    /// Removes and returns the next item of the peer whose turn it is, and moves that peer to
+    /// the back.
+    pub(crate) fn pop(&mut self) -> Option<(N, T)> {
+        let sender_id = self.turns.pop_front()?;
+        let queue = self.items.get_mut(&sender_id)?;
+        let item = queue.pop_front()?;
+        if queue.is_empty() {
+            self.items.remove(&sender_id);
+        } else {
+            self.turns.push_back(sender_id.clone());
+        }
+        Some((sender_id, item))
+    }
+
+    /// Returns the total number of pending items.
+    pub(crate) fn len(&self) -> usize {
+        self.items.values().map(VecDeque::len).sum()
+    }
+}
+
+impl<N: NodeIdT, T, I: IntoIterator<Item = T>> Extend<(N, I)> for FairQueue<N, T> {
+    fn extend<J: IntoIterator<Item = (N, I)>>(&mut self, iter: J) {
+        for (sender_id, items) in iter {
+            for item in items {
+                self.push(sender_id.clone(), item);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FairQueue;
+
+    #[test]
+    fn test_round_robin() {
+        let mut queue = FairQueue::default();
+        queue.extend(vec![(0, vec!['a', 'b', 'c', 'd']), (1, vec!['e'])]);
+        queue.push(2, 'f');
+        queue.push(1, 'g');
+        assert_eq!(7, queue.len());
+        let order: Vec<_> = (0..7).filter_map(|_| queue.pop()).collect();
+        let expected = vec![
+            (0, 'a'),
+            (1, 'e'),
+            (2, 'f'),
+            (0, 'b'),
+            (1, 'g'),
+            (0, 'c'),
+            (0, 'd'),
+        ];
+        assert_eq!(expected, order);
+        assert_eq!(None, queue.pop());
+        assert_eq!(0, queue.len());
+    }
+}
@@ -0,0 +1,345 @@
+//! # Self-describing message headers
+//!
+//! The messages of this crate are serialized by the application, and without the type definitions
+//! the bytes on the wire are opaque. To let network capture tools and trace visualizers classify
+//! the traffic anyway, the application can prefix each serialized message with a `Header`: a
+//! fixed-size, versioned record of the algorithm the message belongs to, its epoch, and a short
+//! hint identifying the sender.
+//!
+//! The header is not authenticated and doesn't replace any part of the message: Recipients must
+//! still deserialize and verify the message itself, and should not trust the header beyond
+//! routing and classification.
+//!
+//! The layout, with all integers in big-endian byte order, is:
+//!
+//! | Bytes  | Field                                  |
+//! |--------|----------------------------------------|
+//! | 0..4   | The magic bytes `HBBF`.                |
+//! | 4      | The header version, currently `1`.     |
+//! | 5      | The `Algorithm` code.                  |
+//! | 6..14  | The epoch.                             |
+//! | 14..18 | The sender hint.                       |
+
+use failure::Fail;
+use serde::Serialize;
+use tiny_keccak::sha3_256;
+
+use crate::{
+    binary_agreement, broadcast, dynamic_honey_badger, honey_badger, sender_queue, subset,
+    threshold_decrypt, threshold_sign,
+};
+
+/// The bytes every header starts with.
+pub const MAGIC: [u8; 4] = *b"HBBF";
+
+/// The current version of the header layout.
+pub const VERSION: u8 = 1;
+
+/// The length of an encoded header, in bytes.
+pub const HEADER_LEN: usize = 18;
+
+/// The algorithm a message belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Algorithm {
+    /// A `Broadcast` message.
+    Broadcast,
+    /// A `BinaryAgreement` message.
+    BinaryAgreement,
+    /// A `Subset` message.
+    Subset,
+    /// A `HoneyBadger` message.
+    HoneyBadger,
+    /// A `DynamicHoneyBadger` message.
+    DynamicHoneyBadger,
+    /// A `SenderQueue` epoch announcement.
+    SenderQueue,
+    /// A `ThresholdSign` message.
+    ThresholdSign,
+    /// A `ThresholdDecrypt` message.
+    ThresholdDecrypt,
+    /// An algorithm unknown to this version of the crate.
+    Unknown(u8),
+}
+
+impl Algorithm {
+    /// Returns the code that represents the algorithm in a header.
+    pub fn code(self) -> u8 {
+        match self {
+            Algorithm::Broadcast => 1,
+            Algorithm::BinaryAgreement => 2,
+            Algorithm::Subset => 3,
+            Algorithm::HoneyBadger => 4,
+            Algorithm::DynamicHoneyBadger => 5,
+            Algorithm::SenderQueue => 6,
+            Algorithm::ThresholdSign => 7,
+            Algorithm::ThresholdDecrypt => 8,
+            Algorithm::Unknown(code) => code,
+        }
+    }
+
+    /// Returns the algorithm with the given code.
+    pub fn from_code(code: u8) -> Self {
+        match code {
+            1 => Algorithm::Broadcast,
+            2 => Algorithm::BinaryAgreement,
+            3 => Algorithm::Subset,
+            4 => Algorithm::HoneyBadger,
+            5 => Algorithm::DynamicHoneyBadger,
+            6 => Algorithm::SenderQueue,
+            7 => Algorithm::ThresholdSign,
+            8 => Algorithm::ThresholdDecrypt,
+            code => Algorithm::Unknown(code),
+        }
+    }
+}
+
+/// A message that can be described by a `Header`.
+pub trait DescribeMessage {
+    /// Returns the algorithm the message belongs to.
+    fn algorithm(&self) -> Algorithm;
+
+    /// Returns the message's epoch, as counted by its algorithm, or `0` if it has none.
+    fn epoch(&self) -> u64;
+}
+
+/// A header error.
+#[derive(Clone, Debug, Fail, PartialEq, Eq)]
+pub enum Error {
+    /// The input is shorter than a header.
+    #[fail(display = "Header truncated: {} bytes", _0)]
+    Truncated(usize),
+    /// The input doesn't start with the magic bytes.
+    #[fail(display = "Invalid header magic bytes")]
+    InvalidMagic,
+    /// The header has a version unknown to this version of the crate.
+    #[fail(display = "Unsupported header version: {}", _0)]
+    UnsupportedVersion(u8),
+}
+
+/// A header result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// A compact description of a message, to be prepended to its serialized bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    /// The algorithm the message belongs to.
+    pub algorithm: Algorithm,
+    /// The message's epoch.
+    pub epoch: u64,
+    /// The first four bytes of the hash of the serialized sender ID: enough to tell the senders
+    /// in a capture apart, but not to identify them.
+    pub sender_hint: u32,
+}
+
+impl Header {
+    /// Returns the header describing a message from `sender_id`.
+    pub fn new<M, N>(message: &M, sender_id: &N) -> bincode::Result<Self>
+    where
+        M: DescribeMessage,
+        N: Serialize,
+    {
+        Ok(Header {
+            algorithm: message.algorithm(),
+            epoch: message.epoch(),
+            sender_hint: sender_hint(sender_id)?,
+        })
+    }
+
+    /// Returns the encoded header.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0u8; HEADER_LEN];
+        bytes[..4].copy_from_slice(&MAGIC);
+        bytes[4] = VERSION;
+        bytes[5] = self.algorithm.code();
+        bytes[6..14].copy_from_slice(&self.epoch.to_be_bytes());
+        bytes[14..].copy_from_slice(&self.sender_hint.to_be_bytes());
+        bytes
+    }
+
+    /// Returns the serialized message, prefixed with the encoded header.
+    pub fn prepend(&self, message_bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LEN + message_bytes.len());
+        bytes.extend_from_slice(&self.to_bytes());
+        bytes.extend_from_slice(message_bytes);
+        bytes
+    }
+
+    /// Decodes the header at the beginning of `bytes`, and returns it together with the remaining
+    /// bytes, i.e. the serialized message.
+    pub fn parse(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::Truncated(bytes.len()));
+        }
+        if bytes[..4] != MAGIC {
+            return Err(Error::InvalidMagic);
+        }
+        if bytes[4] != VERSION {
+            return Err(Error::UnsupportedVersion(bytes[4]));
+        }
+        let mut epoch = [0u8; 8];
+        epoch.copy_from_slice(&bytes[6..14]);
+        let mut sender_hint = [0u8; 4];
+        sender_hint.copy_from_slice(&bytes[14..HEADER_LEN]);
+        let header = Header {
+            algorithm: Algorithm::from_code(bytes[5]),
+            epoch: u64::from_be_bytes(epoch),
+            sender_hint: u32::from_be_bytes(sender_hint),
+        };
+        Ok((header, &bytes[HEADER_LEN..]))
+    }
+}
+
+/// Returns the sender hint for the given node ID.
+pub fn sender_hint<N: Serialize>(sender_id: &N) -> bincode::Result<u32> {
+    let hash = sha3_256(&bincode::serialize(sender_id)?);
+    let mut hint = [0u8; 4];
+    hint.copy_from_slice(&hash[..4]);
+    Ok(u32::from_be_bytes(hint))
+}
+
+impl DescribeMessage for broadcast::Message {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Broadcast
+    }
+
+    fn epoch(&self) -> u64 {
+        0
+    }
+}
+
+impl DescribeMessage for binary_agreement::Message {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::BinaryAgreement
+    }
+
+    fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl<N> DescribeMessage for subset::Message<N> {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::Subset
+    }
+
+    /// Returns the epoch of the wrapped `BinaryAgreement` message, if any.
+    fn epoch(&self) -> u64 {
+        match self.content {
+            subset::MessageContent::Broadcast(_) => 0,
+            subset::MessageContent::Agreement(ref msg) => msg.epoch,
+        }
+    }
+}
+
+impl<N> DescribeMessage for honey_badger::Message<N> {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::HoneyBadger
+    }
+
+    fn epoch(&self) -> u64 {
+        honey_badger::Message::epoch(self)
+    }
+}
+
+impl<N: Ord> DescribeMessage for dynamic_honey_badger::Message<N> {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::DynamicHoneyBadger
+    }
+
+    /// Returns the epoch of the wrapped `HoneyBadger` message, or the first epoch of the era for
+    /// the other kinds of messages.
+    fn epoch(&self) -> u64 {
+        match *self {
+            dynamic_honey_badger::Message::HoneyBadger(era, ref msg) => era + msg.epoch(),
+            dynamic_honey_badger::Message::KeyGen(era, _, _)
+            | dynamic_honey_badger::Message::Address(era, _, _) => era,
+            dynamic_honey_badger::Message::SignedVote(ref signed_vote) => signed_vote.era(),
+            dynamic_honey_badger::Message::BatchSignature(_, epoch, _) => epoch,
+        }
+    }
+}
+
+impl<M> DescribeMessage for sender_queue::Message<M>
+where
+    M: sender_queue::SenderQueueableMessage + DescribeMessage,
+{
+    /// Returns the wrapped algorithm, or `SenderQueue` for epoch announcements.
+    fn algorithm(&self) -> Algorithm {
+        match *self {
+            sender_queue::Message::EpochStarted(_) => Algorithm::SenderQueue,
+            sender_queue::Message::Algo(ref msg) => msg.algorithm(),
+        }
+    }
+
+    /// Returns the epoch of the wrapped message. Epoch announcements have no epoch in the header.
+    fn epoch(&self) -> u64 {
+        match *self {
+            sender_queue::Message::EpochStarted(_) => 0,
+            sender_queue::Message::Algo(ref msg) => msg.epoch(),
+        }
+    }
+}
+
+impl DescribeMessage for threshold_sign::Message {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ThresholdSign
+    }
+
+    fn epoch(&self) -> u64 {
+        0
+    }
+}
+
+impl DescribeMessage for threshold_decrypt::Message {
+    fn algorithm(&self) -> Algorithm {
+        Algorithm::ThresholdDecrypt
+    }
+
+    fn epoch(&self) -> u64 {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sender_hint, Algorithm, Error, Header, HEADER_LEN};
+    use rand::Rng;
+
+    use crate::binary_agreement::MessageContent;
+    use crate::{honey_badger, sender_queue};
+
+    #[test]
+    fn test_header_roundtrip() {
+        let msg = MessageContent::Term(true).with_epoch(3);
+        let header = Header::new(&msg, &5usize).expect("header");
+        assert_eq!(Algorithm::BinaryAgreement, header.algorithm);
+        assert_eq!(3, header.epoch);
+        assert_eq!(sender_hint(&5usize).expect("hint"), header.sender_hint);
+        assert_ne!(header.sender_hint, sender_hint(&6usize).expect("hint"));
+
+        let bytes = header.prepend(b"message");
+        assert_eq!(HEADER_LEN + 7, bytes.len());
+        assert_eq!(Ok((header, &b"message"[..])), Header::parse(&bytes));
+
+        // Unknown algorithms are still parsed, but other versions and other data are rejected.
+        let mut unknown = bytes.clone();
+        unknown[5] = 200;
+        let (parsed, _) = Header::parse(&unknown).expect("parse");
+        assert_eq!(Algorithm::Unknown(200), parsed.algorithm);
+        let mut version = bytes.clone();
+        version[4] = 2;
+        assert_eq!(Err(Error::UnsupportedVersion(2)), Header::parse(&version));
+        assert_eq!(Err(Error::InvalidMagic), Header::parse(&[0; HEADER_LEN]));
+        assert_eq!(Err(Error::Truncated(4)), Header::parse(&bytes[..4]));
+
+        // Wrapped messages are described by the inner algorithm.
+        let hb_msg: honey_badger::Message<usize> = rand::thread_rng().gen();
+        let epoch = hb_msg.epoch();
+        let wrapped = sender_queue::Message::Algo(hb_msg);
+        let header = Header::new(&wrapped, &5usize).expect("header");
+        assert_eq!(
+            (Algorithm::HoneyBadger, epoch),
+            (header.algorithm, header.epoch)
+        );
+    }
+}
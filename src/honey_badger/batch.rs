@@ -1,6 +1,79 @@
 use std::collections::BTreeMap;
 
-use crate::NodeIdT;
+use bincode;
+use serde::{Deserialize, Serialize};
+use tiny_keccak::sha3_256;
+
+use super::epoch_state::EpochId;
+use crate::binary_agreement::DecisionCertificate;
+use crate::subset::BaSessionId;
+use crate::{NetworkInfo, NodeIdT};
+
+/// The order in which the contributions of a batch are output.
+///
+/// This is part of the `Params`, so all nodes must be configured with the same order. The
+/// transactions within each contribution keep the order the proposer gave them; to shuffle all
+/// transactions of a `Shuffled` batch, use `Batch::shuffled_txs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContributionOrder {
+    /// Contributions are ordered by their proposers' IDs.
+    ProposerId,
+    /// Contributions are ordered by the SHA3-256 hash of their canonical encoding.
+    Hash,
+    /// Contributions are ordered by a pseudorandom permutation, derived from the batch's `seed`.
+    ///
+    /// The seed is the hash of a threshold signature of the epoch, i.e. a common coin: The
+    /// validators only send their signature shares once `Subset` is complete, so it is unknown
+    /// until the contributions are fixed, and no proposer can predict or influence their
+    /// position, even in epochs that are not encrypted. This costs one more message round per
+    /// epoch.
+    Shuffled,
+}
+
+impl ContributionOrder {
+    /// Returns the proposers' IDs in this order, given their encoded contributions sorted by
+    /// proposer ID, and the seed if the order is `Shuffled`.
+    pub(super) fn sort<N: NodeIdT>(
+        self,
+        seed: Option<&[u8; 32]>,
+        contributions: &[(N, &Vec<u8>)],
+    ) -> Vec<N> {
+        let hashes = contributions
+            .iter()
+            .map(|(id, ser_contrib)| (sha3_256(ser_contrib), id.clone()));
+        let mut keyed: Vec<([u8; 32], N)> = match (self, seed) {
+            (ContributionOrder::Hash, _) => hashes.collect(),
+            (ContributionOrder::Shuffled, Some(seed)) => hashes
+                .map(|(hash, id)| (sha3_256(&[&seed[..], &hash[..]].concat()), id))
+                .collect(),
+            (ContributionOrder::ProposerId, _) | (ContributionOrder::Shuffled, None) => {
+                return contributions.iter().map(|(id, _)| id.clone()).collect();
+            }
+        };
+        keyed.sort();
+        keyed.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+/// Returns the transactions of the given contributions, sorted by the hashes of their
+/// serializations, keyed with the seed.
+pub(crate) fn shuffle_txs<'a, C, T>(
+    seed: &[u8; 32],
+    contributions: &[&'a C],
+) -> bincode::Result<Vec<&'a T>>
+where
+    &'a C: IntoIterator<Item = &'a T>,
+    T: Serialize,
+{
+    let mut keyed = Vec::new();
+    for tx in contributions.iter().flat_map(|contrib| *contrib) {
+        let ser_tx = bincode::serialize(tx)?;
+        keyed.push((sha3_256(&[&seed[..], &ser_tx[..]].concat()), tx));
+    }
+    // The sort is stable, and transactions with equal keys are equal.
+    keyed.sort_by_key(|(key, _)| *key);
+    Ok(keyed.into_iter().map(|(_, tx)| tx).collect())
+}
 
 /// A batch of contributions the algorithm has output.
 #[derive(Clone, Debug)]
@@ -9,23 +82,90 @@ pub struct Batch<C, N> {
     pub epoch: u64,
     /// The set of agreed contributions, by the contributor's node ID.
     pub contributions: BTreeMap<N, C>,
+    /// The contributors' node IDs, in the configured `ContributionOrder`.
+    pub order: Vec<N>,
+    /// The epoch's random seed, if the `ContributionOrder` is `Shuffled`. It is the same on all
+    /// nodes, and unknown to everyone until the contributions are fixed.
+    pub seed: Option<[u8; 32]>,
+    /// The certificates of the decisions whether to accept each proposer's contribution, if they
+    /// are enabled in the `Params`.
+    pub agreement_certificates: BTreeMap<N, DecisionCertificate>,
 }
 
 impl<C, N: NodeIdT> Batch<C, N> {
-    /// Returns an iterator over references to all transactions included in the batch.
+    /// Returns an iterator over the contributions and their proposers, in the configured
+    /// `ContributionOrder`.
+    pub fn ordered_contributions(&self) -> impl Iterator<Item = (&N, &C)> {
+        let contributions = &self.contributions;
+        self.order
+            .iter()
+            .filter_map(move |id| contributions.get(id).map(|contrib| (id, contrib)))
+    }
+
+    /// Returns an iterator over references to all transactions included in the batch, in the
+    /// configured `ContributionOrder`.
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = <&'a C as IntoIterator>::Item>
     where
         &'a C: IntoIterator,
     {
-        self.contributions.values().flat_map(|item| item)
+        self.ordered_contributions()
+            .flat_map(|(_, contrib)| contrib)
     }
 
-    /// Returns an iterator over all transactions included in the batch. Consumes the batch.
+    /// Returns an iterator over all transactions included in the batch, in the configured
+    /// `ContributionOrder`. Consumes the batch.
     pub fn into_tx_iter(self) -> impl Iterator<Item = <C as IntoIterator>::Item>
     where
         C: IntoIterator,
     {
-        self.contributions.into_iter().flat_map(|(_, vec)| vec)
+        let Batch {
+            mut contributions,
+            order,
+            ..
+        } = self;
+        order
+            .into_iter()
+            .filter_map(move |id| contributions.remove(&id))
+            .flatten()
+    }
+
+    /// Returns references to all transactions included in the batch, in a pseudorandom order that
+    /// is the same on all nodes, derived from the batch's `seed`, or `None` if the
+    /// `ContributionOrder` is not `Shuffled`.
+    ///
+    /// Since the seed is unknown until the contributions are fixed, no proposer can predict or
+    /// influence the positions of their transactions.
+    pub fn shuffled_txs<'a, T>(&'a self) -> bincode::Result<Option<Vec<&'a T>>>
+    where
+        &'a C: IntoIterator<Item = &'a T>,
+        T: Serialize,
+    {
+        let seed = match self.seed {
+            Some(ref seed) => seed,
+            None => return Ok(None),
+        };
+        let contributions: Vec<&C> = self.contributions.values().collect();
+        shuffle_txs(seed, &contributions).map(Some)
+    }
+
+    /// Returns `true` if the batch contains a valid decision certificate for each validator of its
+    /// epoch, and each contribution's certificate decided to accept it. `session_id` is the
+    /// `HoneyBadger` instance's session ID, and `netinfo` contains the epoch's validators.
+    pub fn verify_agreement_certificates(&self, session_id: u64, netinfo: &NetworkInfo<N>) -> bool {
+        let epoch_id = EpochId {
+            hb_id: session_id,
+            epoch: self.epoch,
+        };
+        let pub_key_set = netinfo.public_key_set();
+        let valid = |(idx, id): (usize, &N)| match self.agreement_certificates.get(id) {
+            None => false,
+            Some(cert) => {
+                let ba_id = BaSessionId::new(epoch_id.clone(), idx as u32);
+                let accepted = cert.decision || !self.contributions.contains_key(id);
+                accepted && cert.verify(&ba_id, pub_key_set).unwrap_or(false)
+            }
+        };
+        netinfo.all_ids().enumerate().all(valid)
     }
 
     /// Returns the number of transactions in the batch (without detecting duplicates).
@@ -2,10 +2,17 @@ use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::sync::Arc;
 
-use serde::{de::DeserializeOwned, Serialize};
-
-use super::{EncryptionSchedule, HoneyBadger, Params, SubsetHandlingStrategy};
-use crate::{Contribution, NetworkInfo, NodeIdT};
+use super::honey_badger::ContributionValidator;
+use super::pre_validation::PreValidation;
+use super::{
+    ContributionOrder, EncryptionSchedule, Error, FutureEpochPolicy, HoneyBadger, Params, Result,
+    SubsetHandlingStrategy,
+};
+use crate::binary_agreement::{CoinRecord, CoinSchedule, LocalCoin};
+use crate::canonical::CanonicalContribution;
+use crate::instrument::{Instrument, NoInstrument};
+use crate::subscribers::Subscribers;
+use crate::{NetworkInfo, NodeIdT};
 
 /// A Honey Badger builder, to configure the parameters and create new instances of `HoneyBadger`.
 pub struct HoneyBadgerBuilder<C, N> {
@@ -18,12 +25,20 @@ pub struct HoneyBadgerBuilder<C, N> {
     epoch: u64,
     /// Parameters controlling Honey Badger's behavior and performance.
     params: Params,
+    /// The number of epochs after the current one in which we may already propose.
+    pipeline_depth: u64,
+    /// The receiver of instrumentation events.
+    instrument: Arc<dyn Instrument<N>>,
+    /// The application's validator for decrypted contributions, if any.
+    contribution_validator: Option<ContributionValidator<C, N>>,
+    /// The coin of the `BinaryAgreement` instances, if it is computed locally.
+    agreement_local_coin: Option<Arc<dyn LocalCoin>>,
     _phantom: PhantomData<C>,
 }
 
 impl<C, N> HoneyBadgerBuilder<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     /// Returns a new `HoneyBadgerBuilder` configured to use the node IDs and cryptographic keys
@@ -34,6 +49,10 @@ where
             session_id: 0,
             epoch: 0,
             params: Params::default(),
+            pipeline_depth: 0,
+            instrument: Arc::new(NoInstrument),
+            contribution_validator: None,
+            agreement_local_coin: None,
             _phantom: PhantomData,
         }
     }
@@ -59,6 +78,12 @@ where
         self
     }
 
+    /// Sets how messages for epochs after the next `max_future_epochs` are handled.
+    pub fn future_epoch_policy(&mut self, future_epoch_policy: FutureEpochPolicy) -> &mut Self {
+        self.params.future_epoch_policy = future_epoch_policy;
+        self
+    }
+
     /// Sets the strategy to use when handling `Subset` output.
     pub fn subset_handling_strategy(
         &mut self,
@@ -74,21 +99,138 @@ where
         self
     }
 
+    /// Sets the order in which the contributions of each batch are output.
+    pub fn contribution_order(&mut self, contribution_order: ContributionOrder) -> &mut Self {
+        self.params.contribution_order = contribution_order;
+        self
+    }
+
+    /// Enables pre-validation: Each of our proposals is only input once _f + 1_ validators,
+    /// including us, acknowledged its hash, and validators that don't acknowledge them in
+    /// `threshold` consecutive epochs are reported.
+    pub fn pre_validation(&mut self, threshold: u64) -> &mut Self {
+        self.params.pre_validation = Some(threshold);
+        self
+    }
+
+    /// Sets the maximum size in bytes of each proposed value, i.e. of the encoded contribution, or
+    /// of its ciphertext if the epoch is encrypted. Larger proposals are never output, and the
+    /// nodes that send their shards are reported as faulty.
+    pub fn max_contribution_size(&mut self, max_contribution_size: usize) -> &mut Self {
+        self.params.max_contribution_size = Some(max_contribution_size);
+        self
+    }
+
+    /// Sets the coin schedule of the `BinaryAgreement` instances: see
+    /// `BinaryAgreement::set_variant`.
+    pub fn agreement_coin_schedule(&mut self, schedule: CoinSchedule) -> &mut Self {
+        self.params.agreement_coin_schedule = Some(schedule);
+        self
+    }
+
+    /// Enables or disables decision certificates in the `BinaryAgreement` instances: If enabled,
+    /// each batch contains a `DecisionCertificate` for each proposer, proving whether its
+    /// contribution was accepted. This takes one more message round per epoch.
+    pub fn agreement_certificates(&mut self, certify: bool) -> &mut Self {
+        self.params.agreement_certificates = certify;
+        self
+    }
+
+    /// Sets the number of epochs after which stalled `BinaryAgreement` instances are reported to
+    /// the instrument: see `BinaryAgreement::set_max_epochs`.
+    pub fn agreement_max_epochs(&mut self, max_epochs: u64) -> &mut Self {
+        self.params.agreement_max_epochs = Some(max_epochs);
+        self
+    }
+
+    /// Enables or disables batch verification of the signature shares in the `BinaryAgreement`
+    /// instances: see `ThresholdSign::set_batch_verification`. This saves pairings with many
+    /// validators, at the cost of an extra pass to identify the faulty shares if a batch fails.
+    pub fn agreement_batch_verification(&mut self, batch_verification: bool) -> &mut Self {
+        self.params.agreement_batch_verification = batch_verification;
+        self
+    }
+
+    /// Sets the number of coin flips for which each `BinaryAgreement` instance computes our coin
+    /// shares when it is created, instead of when the coin is invoked: see
+    /// `BinaryAgreement::precompute_coin_shares`. Since the instances of future epochs are created
+    /// when their first message arrives, this moves the signing off the critical path.
+    pub fn agreement_precomputed_coin_shares(&mut self, count: usize) -> &mut Self {
+        self.params.agreement_precomputed_coin_shares = count;
+        self
+    }
+
     /// Sets the parameters controlling Honey Badger's behavior and performance.
     pub fn params(&mut self, params: Params) -> &mut Self {
         self.params = params;
         self
     }
 
-    /// Creates a new Honey Badger instance.
+    /// Enables pipelining: We may propose in up to `depth` epochs after the current one, so that
+    /// their `Subset` instances run while the earlier epochs are still being decrypted. The depth
+    /// is limited by `max_future_epochs`, and pipelining is disabled with pre-validation.
+    ///
+    /// `DynamicHoneyBadger` doesn't pipeline its `HoneyBadger` instances, since each of its key
+    /// generation messages must be committed exactly once.
+    pub fn pipeline_depth(&mut self, depth: u64) -> &mut Self {
+        self.pipeline_depth = depth;
+        self
+    }
+
+    /// Sets a validator for the decrypted contributions: Contributions for which it returns `false`
+    /// are excluded from the batch, and their proposers reported as
+    /// `FaultKind::InvalidContribution`.
+    ///
+    /// It must return the same result on all correct nodes, otherwise they would output different
+    /// batches: It can only depend on the proposer and the contribution, not e.g. on the time.
+    pub fn contribution_validator<F>(&mut self, validator: F) -> &mut Self
+    where
+        F: Fn(&N, &C) -> bool + Send + Sync + 'static,
+    {
+        self.contribution_validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// Sets a coin that is computed locally, to use in the `BinaryAgreement` instances instead of
+    /// the threshold signature: see `BinaryAgreement::set_local_coin`. All nodes must use
+    /// equivalent coins. Since it can't be serialized, it is not part of the `Params`.
+    pub fn agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> &mut Self {
+        self.agreement_local_coin = Some(coin);
+        self
+    }
+
+    /// Sets the receiver of instrumentation events.
+    pub fn instrument(&mut self, instrument: Arc<dyn Instrument<N>>) -> &mut Self {
+        self.instrument = instrument;
+        self
+    }
+
+    /// Creates a new Honey Badger instance, after verifying that the `NetworkInfo`'s keys are
+    /// consistent.
+    pub fn try_build(&mut self) -> Result<HoneyBadger<C, N>> {
+        self.netinfo.validate().map_err(Error::InvalidNetworkInfo)?;
+        Ok(self.build())
+    }
+
+    /// Creates a new Honey Badger instance. Use `try_build` to validate untrusted keys.
     pub fn build(&mut self) -> HoneyBadger<C, N> {
         HoneyBadger {
             netinfo: self.netinfo.clone(),
             session_id: self.session_id,
             epoch: self.epoch,
-            has_input: false,
+            next_proposal_epoch: self.epoch,
+            pipeline_depth: self.pipeline_depth,
             epochs: BTreeMap::new(),
             params: self.params.clone(),
+            future_msg_counts: BTreeMap::new(),
+            future_queue: BTreeMap::new(),
+            future_message_hook: None,
+            contribution_validator: self.contribution_validator.clone(),
+            agreement_local_coin: self.agreement_local_coin.clone(),
+            subscribers: Subscribers::default(),
+            pre_validation: self.params.pre_validation.map(PreValidation::new),
+            coin_record: CoinRecord::default(),
+            instrument: self.instrument.clone(),
         }
     }
 }
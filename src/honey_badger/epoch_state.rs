@@ -5,18 +5,26 @@ use std::marker::PhantomData;
 use std::mem::replace;
 use std::result;
 use std::sync::Arc;
+use std::time::Instant;
 
-use crate::crypto::Ciphertext;
+use crate::crypto::{Ciphertext, Signature};
 use bincode;
 use log::error;
 use rand::Rng;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
-
-use super::{Batch, Error, FaultKind, FaultLog, MessageContent, Result, Step};
+use serde::{Deserialize, Serialize};
+use tiny_keccak::sha3_256;
+
+use super::{
+    Batch, ContributionOrder, Error, FaultKind, FaultLog, MemoryStats, MessageContent, Result, Step,
+};
+use crate::binary_agreement::{CoinRecord, DecisionCertificate, LocalCoin, Variant};
+use crate::canonical::CanonicalContribution;
 use crate::fault_log::Fault;
-use crate::subset::{self as cs, Subset, SubsetOutput};
+use crate::instrument::{Instrument, Timing};
+use crate::subset::{self as cs, ProposerProgress, Subset, SubsetOutput};
 use crate::threshold_decrypt::{self as td, ThresholdDecrypt};
-use crate::{Contribution, NetworkInfo, NodeIdT};
+use crate::threshold_sign::{self as ts, ThresholdSign};
+use crate::{NetworkInfo, NodeIdT};
 
 type CsStep<N> = cs::Step<N>;
 
@@ -27,6 +35,9 @@ enum DecryptionState<N> {
     Ongoing(Box<ThresholdDecrypt<N>>),
     /// Decryption is complete. This contains the plaintext.
     Complete(Vec<u8>),
+    /// The ciphertext wasn't signed by the proposer for this epoch, or the plaintext was labeled
+    /// with a different session or epoch, so the contribution is discarded.
+    Rejected,
 }
 
 impl<N: NodeIdT> DecryptionState<N> {
@@ -39,7 +50,7 @@ impl<N: NodeIdT> DecryptionState<N> {
     fn handle_message(&mut self, sender_id: &N, msg: td::Message) -> td::Result<td::Step<N>> {
         match self {
             DecryptionState::Ongoing(ref mut td) => td.handle_message(sender_id, msg),
-            DecryptionState::Complete(_) => Ok(td::Step::default()),
+            DecryptionState::Complete(_) | DecryptionState::Rejected => Ok(td::Step::default()),
         }
     }
 
@@ -50,11 +61,22 @@ impl<N: NodeIdT> DecryptionState<N> {
                 td.set_ciphertext(ciphertext)?;
                 td.start_decryption()
             }
-            DecryptionState::Complete(_) => Ok(td::Step::default()),
+            DecryptionState::Complete(_) | DecryptionState::Rejected => Ok(td::Step::default()),
         }
     }
 }
 
+/// The status of the epoch's random seed.
+#[derive(Debug)]
+enum SeedState<N> {
+    /// The `ContributionOrder` isn't `Shuffled`, so no seed is needed.
+    Disabled,
+    /// The signature shares are being collected. Ours is sent once `Subset` is complete.
+    Ongoing(Box<ThresholdSign<N>>),
+    /// The seed is complete: It is the hash of the threshold signature.
+    Complete([u8; 32]),
+}
+
 /// The status of the subset algorithm.
 #[derive(Debug)]
 enum SubsetState<N> {
@@ -65,6 +87,73 @@ enum SubsetState<N> {
 }
 
 impl<N: NodeIdT> SubsetState<N> {
+    /// Sets the receiver of instrumentation events, unless the instance has already completed.
+    fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs.set_instrument(instrument),
+            SubsetState::Complete(_) => (),
+        }
+    }
+
+    /// Sets the round structure of the `BinaryAgreement` instances, unless `Subset` has completed.
+    fn set_agreement_variant(&mut self, variant: Arc<dyn Variant>) -> Result<()> {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs
+                .set_agreement_variant(variant)
+                .map_err(Error::CreateSubset),
+            SubsetState::Complete(_) => Ok(()),
+        }
+    }
+
+    /// Sets the local coin of the `BinaryAgreement` instances, unless `Subset` has completed.
+    fn set_agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs
+                .set_agreement_local_coin(coin)
+                .map_err(Error::CreateSubset),
+            SubsetState::Complete(_) => Ok(()),
+        }
+    }
+
+    /// Enables or disables the `BinaryAgreement` instances' decision certificates, unless `Subset`
+    /// has completed.
+    fn set_agreement_certify(&mut self, certify: bool) {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs.set_agreement_certify(certify),
+            SubsetState::Complete(_) => (),
+        }
+    }
+
+    /// Computes our coin shares for the `BinaryAgreement` instances' next `count` coin flips,
+    /// unless `Subset` has completed.
+    fn precompute_agreement_coin_shares(&mut self, count: usize) -> Result<()> {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs
+                .precompute_agreement_coin_shares(count)
+                .map_err(Error::CreateSubset),
+            SubsetState::Complete(_) => Ok(()),
+        }
+    }
+
+    /// Enables or disables batch verification of signature shares in the `BinaryAgreement`
+    /// instances, unless `Subset` has completed.
+    fn set_agreement_batch_verification(&mut self, batch_verification: bool) {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => {
+                cs.set_agreement_batch_verification(batch_verification)
+            }
+            SubsetState::Complete(_) => (),
+        }
+    }
+
+    /// Sets the epoch limit of the `BinaryAgreement` instances, unless `Subset` has completed.
+    fn set_agreement_max_epochs(&mut self, max_epochs: u64) {
+        match self {
+            SubsetState::Ongoing(ref mut cs) => cs.set_agreement_max_epochs(max_epochs),
+            SubsetState::Complete(_) => (),
+        }
+    }
+
     /// Provides input to the Subset instance, unless it has already completed.
     fn handle_input(&mut self, proposal: Vec<u8>) -> Result<CsStep<N>> {
         match self {
@@ -92,6 +181,22 @@ impl<N: NodeIdT> SubsetState<N> {
         }
     }
 
+    /// Returns the progress of each proposer, if the Subset instance is still running.
+    fn progress(&self) -> Option<BTreeMap<N, ProposerProgress>> {
+        match self {
+            SubsetState::Ongoing(ref cs) => Some(cs.progress()),
+            SubsetState::Complete(_) => None,
+        }
+    }
+
+    /// Returns the number of `Broadcast` and of `BinaryAgreement` instances that are still running.
+    fn running_instances(&self) -> (usize, usize) {
+        match self {
+            SubsetState::Ongoing(ref cs) => cs.running_instances(),
+            SubsetState::Complete(_) => (0, 0),
+        }
+    }
+
     /// Returns the IDs of the accepted proposers, if that has already been decided.
     pub fn accepted_ids(&self) -> Option<&BTreeSet<N>> {
         match self {
@@ -177,6 +282,8 @@ impl<N> From<SubsetHandlingStrategy> for SubsetHandler<N> {
 pub struct EpochState<C, N> {
     /// Our epoch number.
     epoch: u64,
+    /// The session and epoch, which every encrypted contribution is labeled with.
+    epoch_id: EpochId,
     /// Shared network data.
     netinfo: Arc<NetworkInfo<N>>,
     /// The status of the subset algorithm.
@@ -189,12 +296,26 @@ pub struct EpochState<C, N> {
     subset_handler: SubsetHandler<N>,
     /// Whether contributions should be encrypted in this epoch.
     require_decryption: bool,
+    /// The order in which the contributions are output in the batch.
+    contribution_order: ContributionOrder,
+    /// The random seed of the `Shuffled` order.
+    seed: SeedState<N>,
+    /// The outcomes of the threshold coin in all of the `Subset`'s agreement instances, and the
+    /// parities of the shares they were combined from, once it is complete.
+    coin_record: CoinRecord<N>,
+    /// The certificates of the `Subset`'s agreement instances' decisions, once it is complete, if
+    /// certificates are enabled.
+    agreement_certificates: BTreeMap<N, DecisionCertificate>,
+    /// The time the epoch state was created.
+    started: Instant,
+    /// The time `Subset` completed, if the contributions were encrypted.
+    decryption_started: Option<Instant>,
     _phantom: PhantomData<C>,
 }
 
 impl<C, N> EpochState<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     /// Creates a new `Subset` instance.
@@ -204,34 +325,77 @@ where
         epoch: u64,
         subset_handling_strategy: SubsetHandlingStrategy,
         require_decryption: bool,
+        contribution_order: ContributionOrder,
+        max_contribution_size: Option<usize>,
     ) -> Result<Self> {
         let epoch_id = EpochId { hb_id, epoch };
-        let cs = Subset::new(netinfo.clone(), epoch_id).map_err(Error::CreateSubset)?;
+        let mut cs = Subset::new(netinfo.clone(), epoch_id.clone()).map_err(Error::CreateSubset)?;
+        if let Some(max) = max_contribution_size {
+            cs.set_max_value_size(max);
+        }
+        let seed = match contribution_order {
+            ContributionOrder::Shuffled => {
+                let doc = [&b"seed"[..], &hb_id.to_le_bytes(), &epoch.to_le_bytes()].concat();
+                let ts =
+                    ThresholdSign::new_with_document(netinfo.clone(), doc).map_err(Error::Seed)?;
+                SeedState::Ongoing(Box::new(ts))
+            }
+            ContributionOrder::ProposerId | ContributionOrder::Hash => SeedState::Disabled,
+        };
         Ok(EpochState {
             epoch,
+            epoch_id,
             netinfo,
             subset: SubsetState::Ongoing(cs),
             decryption: BTreeMap::default(),
             accepted_proposers: Default::default(),
             subset_handler: subset_handling_strategy.into(),
             require_decryption,
+            contribution_order,
+            seed,
+            coin_record: CoinRecord::default(),
+            agreement_certificates: BTreeMap::new(),
+            started: Instant::now(),
+            decryption_started: None,
             _phantom: PhantomData,
         })
     }
 
-    /// If the instance hasn't terminated yet, inputs our encrypted contribution.
-    pub fn propose<R: Rng>(&mut self, proposal: &C, rng: &mut R) -> Result<Step<C, N>> {
-        let ser_prop = bincode::serialize(&proposal).map_err(|err| Error::ProposeBincode(*err))?;
-        let cs_step = self.subset.handle_input(if self.require_decryption {
-            let ciphertext = self
-                .netinfo
-                .public_key_set()
-                .public_key()
-                .encrypt_with_rng(rng, ser_prop);
-            bincode::serialize(&ciphertext).map_err(|err| Error::ProposeBincode(*err))?
-        } else {
-            ser_prop
-        })?;
+    /// Returns the value we input to `Subset` for the given contribution: the canonical encoding
+    /// of the contribution itself, or its ciphertext if this epoch is encrypted.
+    ///
+    /// The ciphertext is signed together with the session and epoch, and the other validators
+    /// verify that signature before they release any decryption share, so that it can't be
+    /// replayed in another epoch or era, even if the key set is the same. The plaintext is labeled
+    /// with the session and epoch, too, so that a faulty proposer that signs an old ciphertext
+    /// can't get its contribution into the batch.
+    pub fn prepare<R: Rng>(&self, proposal: &C, rng: &mut R) -> Result<Vec<u8>> {
+        let ser_prop = proposal
+            .to_canonical_bytes()
+            .map_err(|err| Error::EncodeContribution(err.to_string()))?;
+        if !self.require_decryption {
+            return Ok(ser_prop);
+        }
+        let labeled = bincode::serialize(&(&self.epoch_id, &ser_prop))
+            .map_err(|err| Error::ProposeBincode(*err))?;
+        let ciphertext = self
+            .netinfo
+            .public_key_set()
+            .public_key()
+            .encrypt_with_rng(rng, labeled);
+        let doc = self.label_doc(&ciphertext).map_err(Error::ProposeBincode)?;
+        let signature = self.netinfo.signer().sign(&doc);
+        bincode::serialize(&(&ciphertext, &signature)).map_err(|err| Error::ProposeBincode(*err))
+    }
+
+    /// Returns the document the proposer signs: the session and epoch, and the ciphertext.
+    fn label_doc(&self, ciphertext: &Ciphertext) -> result::Result<Vec<u8>, bincode::ErrorKind> {
+        bincode::serialize(&(&self.epoch_id, ciphertext)).map_err(|err| *err)
+    }
+
+    /// If the instance hasn't terminated yet, inputs a value returned by `prepare`.
+    pub fn propose_prepared(&mut self, value: Vec<u8>) -> Result<Step<C, N>> {
+        let cs_step = self.subset.handle_input(value)?;
         self.process_subset(cs_step)
     }
 
@@ -241,6 +405,78 @@ where
         self.subset.received_proposals()
     }
 
+    /// Returns the progress of each proposer, if the `Subset` instance is still running.
+    pub fn subset_progress(&self) -> Option<BTreeMap<N, ProposerProgress>> {
+        self.subset.progress()
+    }
+
+    /// Sets the receiver of instrumentation events for the `Subset` instance.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        self.subset.set_instrument(instrument);
+    }
+
+    /// Sets the round structure of the `Subset` instance's `BinaryAgreement` instances.
+    pub fn set_agreement_variant(&mut self, variant: Arc<dyn Variant>) -> Result<()> {
+        self.subset.set_agreement_variant(variant)
+    }
+
+    /// Sets the local coin of the `Subset` instance's `BinaryAgreement` instances.
+    pub fn set_agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        self.subset.set_agreement_local_coin(coin)
+    }
+
+    /// Enables or disables the decision certificates of the `Subset` instance's `BinaryAgreement`
+    /// instances.
+    pub fn set_agreement_certify(&mut self, certify: bool) {
+        self.subset.set_agreement_certify(certify);
+    }
+
+    /// Computes our coin shares for the next `count` coin flips of the `Subset` instance's
+    /// `BinaryAgreement` instances.
+    pub fn precompute_agreement_coin_shares(&mut self, count: usize) -> Result<()> {
+        self.subset.precompute_agreement_coin_shares(count)
+    }
+
+    /// Enables or disables batch verification of signature shares in the `Subset` instance's
+    /// `BinaryAgreement` instances.
+    pub fn set_agreement_batch_verification(&mut self, batch_verification: bool) {
+        self.subset
+            .set_agreement_batch_verification(batch_verification);
+    }
+
+    /// Sets the epoch limit of the `Subset` instance's `BinaryAgreement` instances.
+    pub fn set_agreement_max_epochs(&mut self, max_epochs: u64) {
+        self.subset.set_agreement_max_epochs(max_epochs);
+    }
+
+    /// Adds the instances this epoch holds in memory to the statistics.
+    pub fn add_memory_stats(&self, stats: &mut MemoryStats) {
+        let (broadcasts, agreements) = self.subset.running_instances();
+        stats.epochs += 1;
+        stats.broadcasts += broadcasts;
+        stats.agreements += agreements;
+        let ongoing = |state: &&DecryptionState<N>| match state {
+            DecryptionState::Ongoing(_) => true,
+            DecryptionState::Complete(_) | DecryptionState::Rejected => false,
+        };
+        stats.decryptions += self.decryption.values().filter(ongoing).count();
+    }
+
+    /// Reports the durations of the epoch and of its decryption to the instrument.
+    pub fn report_timings(&self, instrument: &dyn Instrument<N>) {
+        instrument.timing(Timing::Epoch(self.epoch), self.started.elapsed());
+        if let Some(decryption_started) = self.decryption_started {
+            let duration = decryption_started.elapsed();
+            instrument.timing(Timing::Decryption(self.epoch), duration);
+        }
+    }
+
+    /// Returns the outcomes of the threshold coin in this epoch, and the parities of the shares
+    /// they were combined from, once `Subset` is complete.
+    pub fn coin_record(&self) -> &CoinRecord<N> {
+        &self.coin_record
+    }
+
     /// Handles a message for the Subset or a Threshold Decrypt instance.
     pub fn handle_message_content(
         &mut self,
@@ -269,19 +505,44 @@ where
                 .map_err(Error::ThresholdDecrypt)?;
                 self.process_decryption(proposer_id, td_step)
             }
+            MessageContent::SeedShare(msg) => {
+                let ts_step = match self.seed {
+                    SeedState::Ongoing(ref mut ts) => {
+                        ts.handle_message(sender_id, msg).map_err(Error::Seed)?
+                    }
+                    SeedState::Complete(_) => return Ok(Step::default()),
+                    SeedState::Disabled => {
+                        let fault_kind =
+                            FaultKind::SeedFault(ts::FaultKind::UnexpectedSignatureShare);
+                        return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+                    }
+                };
+                Ok(self.process_seed(ts_step))
+            }
+            // Pre-validation is handled by `HoneyBadger` itself.
+            MessageContent::ContributionHash(_) | MessageContent::ContributionAck(_) => {
+                Ok(Step::default())
+            }
         }
     }
 
     /// When contributions of transactions have been decrypted for all valid proposers in this
-    /// epoch, moves those contributions into a batch, outputs the batch and updates the epoch.
+    /// epoch, and the random seed is complete if the order is `Shuffled`, moves those
+    /// contributions into a batch, outputs the batch and updates the epoch.
     pub fn try_output_batch(&self) -> Option<(Batch<C, N>, FaultLog<N>)> {
         let proposer_ids = self.subset.accepted_ids()?;
+        let seed = match self.seed {
+            SeedState::Disabled => None,
+            SeedState::Ongoing(_) => return None,
+            SeedState::Complete(seed) => Some(seed),
+        };
         let mut plaintexts = Vec::new();
         // Collect accepted plaintexts. Return if some are not decrypted yet.
         for id in proposer_ids {
             match self.decryption.get(id) {
                 None | Some(DecryptionState::Ongoing(_)) => return None,
                 Some(DecryptionState::Complete(ref pt)) => plaintexts.push((id.clone(), pt)),
+                Some(DecryptionState::Rejected) => (),
             }
         }
 
@@ -289,16 +550,21 @@ where
         let mut batch = Batch {
             epoch: self.epoch,
             contributions: BTreeMap::new(),
+            order: self.contribution_order.sort(seed.as_ref(), &plaintexts),
+            seed,
+            agreement_certificates: self.agreement_certificates.clone(),
         };
-        // Deserialize the output. If it fails, the proposer of that item is faulty.
+        // Decode the output. If it fails, the proposer of that item is faulty.
         for (id, plaintext) in plaintexts {
-            match bincode::deserialize::<C>(plaintext) {
+            match C::from_canonical_bytes(plaintext) {
                 Ok(contrib) => {
                     batch.contributions.insert(id, contrib);
                 }
                 Err(_) => fault_log.append(id, FaultKind::BatchDeserializationFailed),
             }
         }
+        let contributions = &batch.contributions;
+        batch.order.retain(|id| contributions.contains_key(id));
         Some((batch, fault_log))
     }
 
@@ -332,7 +598,19 @@ where
             }
 
             if is_done {
+                if let SubsetState::Ongoing(ref cs) = self.subset {
+                    self.coin_record = cs.coin_record();
+                    self.agreement_certificates = cs.agreement_certificates();
+                }
                 self.subset = SubsetState::Complete(self.accepted_proposers.clone());
+                if self.require_decryption {
+                    self.decryption_started = Some(Instant::now());
+                }
+                // Now that the contributions are fixed, the seed can be revealed.
+                if let SeedState::Ongoing(ref mut ts) = self.seed {
+                    let ts_step = ts.sign().map_err(Error::Seed)?;
+                    step.extend(self.process_seed(ts_step));
+                }
                 let faulty_shares: Vec<_> = self
                     .decryption
                     .keys()
@@ -353,6 +631,19 @@ where
         Ok(step)
     }
 
+    /// Processes a step of the random seed's `ThresholdSign` instance.
+    fn process_seed(&mut self, ts_step: ts::Step<N>) -> Step<C, N> {
+        let mut step = Step::default();
+        let epoch = self.epoch;
+        let opt_sig = step.extend_with(ts_step, FaultKind::SeedFault, |msg| {
+            MessageContent::SeedShare(msg).with_epoch(epoch)
+        });
+        if let Some(sig) = opt_sig.into_iter().next() {
+            self.seed = SeedState::Complete(sha3_256(&sig.to_bytes()));
+        }
+        step
+    }
+
     /// Processes a Threshold Decrypt step.
     fn process_decryption(&mut self, proposer_id: N, td_step: td::Step<N>) -> Result<Step<C, N>> {
         let mut step = Step::default();
@@ -364,21 +655,48 @@ where
             .with_epoch(self.epoch)
         });
         if let Some(output) = opt_output.into_iter().next() {
-            self.decryption
-                .insert(proposer_id, DecryptionState::Complete(output));
+            let labeled: Option<(EpochId, Vec<u8>)> = bincode::deserialize(&output).ok();
+            let state = match labeled {
+                Some((ref epoch_id, _)) if *epoch_id != self.epoch_id => None,
+                labeled => labeled.map(|(_, plaintext)| plaintext),
+            };
+            let state = match state {
+                Some(plaintext) => DecryptionState::Complete(plaintext),
+                None => {
+                    let fault_kind = FaultKind::MislabeledContribution;
+                    step.fault_log.append(proposer_id.clone(), fault_kind);
+                    DecryptionState::Rejected
+                }
+            };
+            self.decryption.insert(proposer_id, state);
         }
         Ok(step)
     }
 
     /// Given the output of the Subset algorithm, inputs the ciphertexts into the Threshold
     /// Decrypt instances and sends our own decryption shares.
+    ///
+    /// If the ciphertext isn't signed by the proposer for this epoch, no share is sent, and the
+    /// contribution is discarded.
     fn send_decryption_share(&mut self, proposer_id: N, v: &[u8]) -> Result<Step<C, N>> {
-        let ciphertext: Ciphertext = match bincode::deserialize(v) {
-            Ok(ciphertext) => ciphertext,
+        let (ciphertext, signature): (Ciphertext, Signature) = match bincode::deserialize(v) {
+            Ok(signed) => signed,
             Err(_) => {
                 return Ok(Fault::new(proposer_id, FaultKind::DeserializeCiphertext).into());
             }
         };
+        let is_signed = match (
+            self.netinfo.public_key(&proposer_id),
+            self.label_doc(&ciphertext),
+        ) {
+            (Some(pk), Ok(doc)) => pk.verify(&signature, doc),
+            (None, _) | (_, Err(_)) => false,
+        };
+        if !is_signed {
+            self.decryption
+                .insert(proposer_id.clone(), DecryptionState::Rejected);
+            return Ok(Fault::new(proposer_id, FaultKind::UnsignedCiphertext).into());
+        }
         let td_result = match self.decryption.entry(proposer_id.clone()) {
             Entry::Occupied(entry) => entry.into_mut(),
             Entry::Vacant(entry) => entry.insert(DecryptionState::new(self.netinfo.clone())),
@@ -396,10 +714,13 @@ where
 
 /// A session identifier for a `Subset` sub-algorithm run within an epoch. It consists of the epoch
 /// number, and an optional `HoneyBadger` session identifier.
-#[derive(Clone, Debug, Serialize)]
-struct EpochId {
-    hb_id: u64,
-    epoch: u64,
+///
+/// It is also the label of encrypted contributions. In `DynamicHoneyBadger`, the session is the
+/// era, so a ciphertext can't be decrypted as part of a batch in another era.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct EpochId {
+    pub(super) hb_id: u64,
+    pub(super) epoch: u64,
 }
 
 impl Display for EpochId {
@@ -407,3 +728,65 @@ impl Display for EpochId {
         write!(f, "{}/{}", self.hb_id, self.epoch)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{EpochState, SubsetHandlingStrategy};
+    use crate::honey_badger::{ContributionOrder, FaultKind, MessageContent};
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_replayed_ciphertext() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let new_epoch_state = |id: usize, hb_id: u64, epoch: u64| {
+            let netinfo = Arc::new(netinfos[&id].clone());
+            let strategy = SubsetHandlingStrategy::Incremental;
+            let order = ContributionOrder::ProposerId;
+            EpochState::<Vec<u8>, usize>::new(netinfo, hb_id, epoch, strategy, true, order, None)
+                .expect("epoch state")
+        };
+
+        // Node 1 encrypts its contribution in era 0, epoch 3.
+        let old = new_epoch_state(1, 0, 3);
+        let value = old
+            .prepare(&b"old contribution".to_vec(), &mut rng)
+            .expect("prepare");
+
+        // In the same era and epoch, node 0 sends its decryption share.
+        let mut state = new_epoch_state(0, 0, 3);
+        let step = state.send_decryption_share(1, &value).expect("share");
+        assert!(step.fault_log.is_empty());
+        let is_share = |content: &MessageContent<usize>| match content {
+            MessageContent::DecryptionShare { proposer_id, .. } => *proposer_id == 1,
+            _ => false,
+        };
+        assert!(step
+            .messages
+            .iter()
+            .any(|msg| is_share(&msg.message.content)));
+
+        // If the ciphertext is replayed in another era or epoch, with the same keys, no share is
+        // sent, and the proposer is reported.
+        for &(hb_id, epoch) in &[(1, 3), (1, 0), (0, 4)] {
+            let mut state = new_epoch_state(0, hb_id, epoch);
+            let step = state.send_decryption_share(1, &value).expect("share");
+            assert!(step.messages.is_empty());
+            let faults: Vec<_> = step
+                .fault_log
+                .0
+                .iter()
+                .map(|f| (f.node_id, &f.kind))
+                .collect();
+            assert_eq!(vec![(1, &FaultKind::UnsignedCiphertext)], faults);
+            assert!(state.try_output_batch().is_none());
+        }
+
+        // Another node can't claim the ciphertext as its own, either.
+        let mut state = new_epoch_state(0, 0, 3);
+        let step = state.send_decryption_share(2, &value).expect("share");
+        assert!(step.messages.is_empty());
+    }
+}
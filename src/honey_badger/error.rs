@@ -4,6 +4,8 @@ use failure::Fail;
 use crate::fault_log;
 use crate::subset;
 use crate::threshold_decrypt;
+use crate::threshold_sign;
+use crate::NetworkInfoError;
 
 /// Honey badger error variants.
 #[derive(Debug, Fail)]
@@ -11,6 +13,9 @@ pub enum Error {
     /// Failed to serialize contribution.
     #[fail(display = "Error serializing contribution: {}", _0)]
     ProposeBincode(bincode::ErrorKind),
+    /// Failed to compute the canonical encoding of the contribution.
+    #[fail(display = "Error encoding contribution: {}", _0)]
+    EncodeContribution(String),
     /// Failed to instantiate `Subset`.
     #[fail(display = "Failed to instantiate Subset: {}", _0)]
     CreateSubset(subset::Error),
@@ -23,9 +28,15 @@ pub enum Error {
     /// Failed to decrypt a contribution.
     #[fail(display = "Threshold decryption error: {}", _0)]
     ThresholdDecrypt(threshold_decrypt::Error),
+    /// Failed to compute the epoch's random seed.
+    #[fail(display = "Threshold signing error: {}", _0)]
+    Seed(threshold_sign::Error),
     /// Unknown sender
     #[fail(display = "Unknown sender")]
     UnknownSender,
+    /// The `NetworkInfo`'s keys are inconsistent.
+    #[fail(display = "Invalid network info: {}", _0)]
+    InvalidNetworkInfo(NetworkInfoError),
 }
 
 /// The result of `HoneyBadger` handling an input or a message.
@@ -43,6 +54,14 @@ pub enum FaultKind {
     /// `HoneyBadger` received an invalid ciphertext from the proposer.
     #[fail(display = "`HoneyBadger` received an invalid ciphertext from the proposer.")]
     InvalidCiphertext,
+    /// `HoneyBadger` received a ciphertext that isn't signed by its proposer for this epoch.
+    #[fail(display = "`HoneyBadger` received a ciphertext that isn't signed for this epoch.")]
+    UnsignedCiphertext,
+    /// `HoneyBadger` decrypted a contribution that was labeled with another session or epoch.
+    #[fail(
+        display = "`HoneyBadger` decrypted a contribution that was labeled with another session or epoch."
+    )]
+    MislabeledContribution,
     /// `HoneyBadger` received a message with an invalid epoch.
     #[fail(display = "`HoneyBadger` received a message with an invalid epoch.")]
     UnexpectedHbMessageEpoch,
@@ -59,6 +78,27 @@ pub enum FaultKind {
     /// `HoneyBadger` received a fault from `ThresholdDecrypt`.
     #[fail(display = "`HoneyBadger` received a fault from `ThresholdDecrypt`.")]
     DecryptionFault(threshold_decrypt::FaultKind),
+    /// `HoneyBadger` received a fault from the `ThresholdSign` instance of the random seed.
+    #[fail(display = "`HoneyBadger` received a fault from the random seed's `ThresholdSign`.")]
+    SeedFault(threshold_sign::FaultKind),
+    /// `HoneyBadger` received an acknowledgment for a hash we didn't send.
+    #[fail(display = "`HoneyBadger` received an acknowledgment for a hash we didn't send.")]
+    InvalidContributionAck,
+    /// A validator has not acknowledged our contributions for too many consecutive epochs.
+    #[fail(
+        display = "A validator has not acknowledged our contributions for too many consecutive epochs."
+    )]
+    WithheldContributionAck,
+    /// The parities of a validator's coin signature shares are improbable for a fair coin. This
+    /// indicates a compromised or maliciously dealt key share.
+    #[fail(display = "The parities of a validator's coin shares are improbable for a fair coin.")]
+    BiasedCoin,
+    /// A proposer's contribution was rejected by the application's validator.
+    #[fail(display = "A proposer's contribution was rejected by the application's validator.")]
+    InvalidContribution,
+    /// A peer sent more messages for future epochs than `FutureEpochPolicy::Queue` allows.
+    #[fail(display = "A peer sent more messages for future epochs than the queue allows.")]
+    FutureEpochQueueFull,
 }
 
 /// The type of fault log whose entries are `HoneyBadger` faults.
@@ -1,16 +1,35 @@
+use std::cmp;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
+use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 
 use derivative::Derivative;
 use rand::Rng;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
+use tiny_keccak::sha3_256;
 
 use super::epoch_state::EpochState;
-use super::{Batch, Error, FaultKind, HoneyBadgerBuilder, Message, Result};
-use crate::{ConsensusProtocol, Contribution, Fault, NetworkInfo, NodeIdT};
+use super::pre_validation::PreValidation;
+use super::{
+    Batch, Error, FaultKind, FaultLog, HoneyBadgerBuilder, Message, MessageContent, Result,
+};
+use crate::binary_agreement::{CoinRecord, CoinStats, LocalCoin};
+use crate::canonical::CanonicalContribution;
+use crate::header::Algorithm;
+use crate::instrument::{CryptoOp, Instrument, Transition};
+use crate::subscribers::Subscribers;
+use crate::subset::ProposerProgress;
+use crate::{subset, Connectivity, ConsensusProtocol, Fault, NetworkInfo, NodeIdT, Target};
 
-use super::Params;
+use super::{FutureEpochPolicy, Params};
+
+/// The minimum number of coin flips before the coin is checked for bias.
+const COIN_BIAS_MIN_FLIPS: u64 = 100;
+
+/// The deviation from a fair coin, in standard deviations, at which the coin is considered biased.
+/// A fair coin exceeds this with a probability of about _2 · 10<sup>-9</sup>_.
+const COIN_BIAS_MAX_DEVIATION: f64 = 6.0;
 
 /// An instance of the Honey Badger Byzantine fault tolerant consensus algorithm.
 #[derive(Derivative)]
@@ -23,20 +42,50 @@ pub struct HoneyBadger<C, N> {
     pub(super) session_id: u64,
     /// The earliest epoch from which we have not yet received output.
     pub(super) epoch: u64,
-    /// Whether we have already submitted a proposal for the current epoch.
-    pub(super) has_input: bool,
+    /// The earliest epoch, not before the current one, for which we haven't submitted a proposal.
+    pub(super) next_proposal_epoch: u64,
+    /// The number of epochs after the current one in which we may already propose.
+    pub(super) pipeline_depth: u64,
     /// The subalgorithms for ongoing epochs.
     pub(super) epochs: BTreeMap<u64, EpochState<C, N>>,
     /// Parameters controlling Honey Badger's behavior and performance.
     pub(super) params: Params,
+    /// The number of messages received from each peer for each epoch after the current one.
+    pub(super) future_msg_counts: BTreeMap<u64, BTreeMap<N, usize>>,
+    /// The messages for epochs too far in the future, queued by sender, if the policy is `Queue`.
+    pub(super) future_queue: BTreeMap<N, Vec<Message<N>>>,
+    /// The receiver of the messages for epochs too far in the future, if the policy is `Forward`.
+    #[derivative(Debug = "ignore")]
+    pub(super) future_message_hook: Option<FutureMessageHook<N>>,
+    /// The application's validator for decrypted contributions, if any.
+    #[derivative(Debug = "ignore")]
+    pub(super) contribution_validator: Option<ContributionValidator<C, N>>,
+    /// The acknowledgments of our proposals, if pre-validation is enabled.
+    pub(super) pre_validation: Option<PreValidation<N>>,
+    /// The outcomes of the threshold coin in the epochs output so far, and the parities of each
+    /// validator's shares since its last bias report.
+    pub(super) coin_record: CoinRecord<N>,
+    /// The receiver of instrumentation events.
+    #[derivative(Debug = "ignore")]
+    pub(super) instrument: Arc<dyn Instrument<N>>,
+    /// The coin of the `BinaryAgreement` instances, if it is computed locally.
+    pub(super) agreement_local_coin: Option<Arc<dyn LocalCoin>>,
+    /// The application's hooks and channels that receive the batches.
+    pub(super) subscribers: Subscribers<Batch<C, N>>,
 }
 
+/// A function that is passed messages for epochs too far in the future, with their senders.
+pub(crate) type FutureMessageHook<N> = Arc<dyn Fn(&N, Message<N>) + Send + Sync>;
+
+/// A function that returns whether a proposer's decrypted contribution may be output.
+pub(super) type ContributionValidator<C, N> = Arc<dyn Fn(&N, &C) -> bool + Send + Sync>;
+
 /// A `HoneyBadger` step, possibly containing multiple outputs.
 pub type Step<C, N> = crate::CpStep<HoneyBadger<C, N>>;
 
 impl<C, N> ConsensusProtocol for HoneyBadger<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     type NodeId = N;
@@ -70,7 +119,7 @@ where
 
 impl<C, N> HoneyBadger<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     /// Returns a new `HoneyBadgerBuilder` configured to use the node IDs and cryptographic keys
@@ -79,19 +128,41 @@ where
         HoneyBadgerBuilder::new(netinfo)
     }
 
-    /// Proposes a contribution in the current epoch.
+    /// Proposes a contribution in the current epoch or, with pipelining, in the earliest of the
+    /// following `pipeline_depth` epochs in which we haven't proposed yet.
     ///
-    /// Returns an error if we already made a proposal in this epoch.
+    /// Returns an error if we already made a proposal in all of these epochs.
     ///
     /// If we are the only validator, this will immediately output a batch, containing our
     /// proposal.
+    ///
+    /// With pre-validation, this only sends the hash of the proposal to the other validators, and
+    /// the proposal is input once _f + 1_ of them acknowledged it.
     pub fn propose<R: Rng>(&mut self, proposal: &C, rng: &mut R) -> Result<Step<C, N>> {
         if !self.netinfo.is_validator() {
             return Ok(Step::default());
         }
-        self.has_input = true;
-        let step = self.epoch_state_mut(self.epoch)?.propose(proposal, rng)?;
-        Ok(step.join(self.try_output_batches()?))
+        let max_epoch = self.epoch + self.effective_pipeline_depth();
+        let epoch = cmp::min(cmp::max(self.epoch, self.next_proposal_epoch), max_epoch);
+        self.next_proposal_epoch = epoch + 1;
+        let value = self.epoch_state_mut(epoch)?.prepare(proposal, rng)?;
+        if self.params.encryption_schedule.use_on_epoch(epoch) {
+            self.instrument.crypto_op(CryptoOp::Encrypt);
+            self.instrument.crypto_op(CryptoOp::Sign);
+        }
+        let mut step = match self.pre_validation {
+            None => self.epoch_state_mut(epoch)?.propose_prepared(value)?,
+            Some(ref mut pre_validation) => {
+                let hash = sha3_256(&value);
+                pre_validation.start(self.netinfo.our_id(), value, hash);
+                let msg = MessageContent::ContributionHash(hash).with_epoch(epoch);
+                Target::All.message(msg).into()
+            }
+        };
+        step.extend(self.propose_pre_validated()?);
+        let step = step.join(self.try_output_batches()?);
+        self.report_sent(&step);
+        Ok(step)
     }
 
     /// Handles a message received from `sender_id`.
@@ -101,13 +172,40 @@ where
         if !self.netinfo.is_node_validator(sender_id) {
             return Err(Error::UnknownSender);
         }
+        let (algorithm, epoch) = (inner_algorithm(&message.content), message.epoch());
+        self.instrument
+            .message_received(sender_id, algorithm, epoch);
+        let step = self.handle_epoch_message(sender_id, message)?;
+        self.report_sent(&step);
+        Ok(step)
+    }
+
+    /// Handles a message from a validator.
+    fn handle_epoch_message(&mut self, sender_id: &N, message: Message<N>) -> Result<Step<C, N>> {
+        if message.epoch > self.epoch + self.params.max_future_epochs {
+            return Ok(self.handle_far_future_message(sender_id, message));
+        }
         let Message { epoch, content } = message;
-        if epoch > self.epoch + self.params.max_future_epochs {
-            Ok(Fault::new(sender_id.clone(), FaultKind::UnexpectedHbMessageEpoch).into())
-        } else if epoch < self.epoch {
+        if epoch < self.epoch {
             // The message is late; discard it.
             Ok(Step::default())
         } else {
+            if epoch > self.epoch {
+                let counts = self.future_msg_counts.entry(epoch).or_default();
+                *counts.entry(sender_id.clone()).or_insert(0) += 1;
+            }
+            match content {
+                MessageContent::ContributionHash(hash) => {
+                    // The acknowledgment only shows that we are live: The contribution behind the
+                    // hash can't be validated before it is decrypted.
+                    let msg = MessageContent::ContributionAck(hash).with_epoch(epoch);
+                    return Ok(Target::Node(sender_id.clone()).message(msg).into());
+                }
+                MessageContent::ContributionAck(hash) => {
+                    return self.handle_contribution_ack(sender_id, epoch, &hash);
+                }
+                _ => (),
+            }
             let step = self
                 .epoch_state_mut(epoch)?
                 .handle_message_content(sender_id, content)?;
@@ -115,9 +213,81 @@ where
         }
     }
 
-    /// Returns `true` if input for the current epoch has already been provided.
+    /// Handles a message for an epoch too far in the future, according to the policy.
+    fn handle_far_future_message(&mut self, sender_id: &N, message: Message<N>) -> Step<C, N> {
+        match self.params.future_epoch_policy {
+            FutureEpochPolicy::Reject => {
+                Fault::new(sender_id.clone(), FaultKind::UnexpectedHbMessageEpoch).into()
+            }
+            FutureEpochPolicy::Queue(max_per_peer) => {
+                let queue = self.future_queue.entry(sender_id.clone()).or_default();
+                if queue.len() >= max_per_peer {
+                    return Fault::new(sender_id.clone(), FaultKind::FutureEpochQueueFull).into();
+                }
+                queue.push(message);
+                Step::default()
+            }
+            FutureEpochPolicy::Forward => {
+                if let Some(ref hook) = self.future_message_hook {
+                    hook(sender_id, message);
+                }
+                Step::default()
+            }
+        }
+    }
+
+    /// Handles the queued messages whose epochs are no longer too far in the future.
+    fn handle_queued_messages(&mut self) -> Result<Step<C, N>> {
+        let max_epoch = self.epoch + self.params.max_future_epochs;
+        let mut ready = Vec::new();
+        for (id, queue) in &mut self.future_queue {
+            let (now, later) = queue.drain(..).partition(|msg| msg.epoch <= max_epoch);
+            *queue = later;
+            ready.extend(now.into_iter().map(|msg: Message<N>| (id.clone(), msg)));
+        }
+        self.future_queue.retain(|_, queue| !queue.is_empty());
+        let mut step = Step::default();
+        for (id, msg) in ready {
+            step.extend(self.handle_epoch_message(&id, msg)?);
+        }
+        Ok(step)
+    }
+
+    /// Returns the information about the node IDs in the network, and the cryptographic keys.
+    pub fn netinfo(&self) -> &Arc<NetworkInfo<N>> {
+        &self.netinfo
+    }
+
+    /// Returns whether enough validators are reachable for the network to make progress, given the
+    /// IDs of the peers from whom messages were recently received. If not, the instrument is
+    /// notified with the unreachable validators. See `NetworkInfo::connectivity`.
+    pub fn check_connectivity<'a, I>(&self, reachable: I) -> Connectivity<N>
+    where
+        I: IntoIterator<Item = &'a N>,
+        N: 'a,
+    {
+        let connectivity = self.netinfo.connectivity(reachable);
+        if let Connectivity::Degraded(ref unreachable) = connectivity {
+            self.instrument.connectivity_degraded(unreachable);
+        }
+        connectivity
+    }
+
+    /// Returns `true` if input for the current epoch has already been provided and, with
+    /// pipelining, for all of the following `pipeline_depth` epochs.
     pub fn has_input(&self) -> bool {
-        !self.netinfo.is_validator() || self.has_input
+        let max_epoch = self.epoch + self.effective_pipeline_depth();
+        !self.netinfo.is_validator() || self.next_proposal_epoch > max_epoch
+    }
+
+    /// Returns the number of epochs after the current one in which we may already propose. This is
+    /// limited by `max_future_epochs`, since the other validators would drop our messages for later
+    /// epochs. Pre-validation only supports proposals in the current epoch.
+    fn effective_pipeline_depth(&self) -> u64 {
+        if self.pre_validation.is_some() {
+            return 0;
+        }
+        cmp::min(self.pipeline_depth, self.params.max_future_epochs)
     }
 
     /// Returns the current encryption schedule that determines in which epochs contributions are
@@ -145,42 +315,288 @@ where
             .map_or(0, EpochState::received_proposals)
     }
 
+    /// Returns the progress of each proposer's `Broadcast` and `BinaryAgreement` instance in the
+    /// given epoch, or `None` if its `Subset` instance has completed or hasn't started yet.
+    ///
+    /// If an epoch doesn't complete, this shows which proposals it is waiting for.
+    pub fn subset_progress(&self, epoch: u64) -> Option<BTreeMap<N, ProposerProgress>> {
+        self.epochs.get(&epoch)?.subset_progress()
+    }
+
+    /// Registers a hook that is called with each batch, before it is returned in the step's output.
+    pub fn add_batch_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(&Batch<C, N>) + Send + Sync + 'static,
+    {
+        self.subscribers.add_hook(hook);
+    }
+
+    /// Returns a channel that receives a copy of each batch, before it is returned in the step's
+    /// output. Dropping the receiver cancels the subscription.
+    pub fn subscribe(&mut self) -> Receiver<Batch<C, N>>
+    where
+        C: Clone + 'static,
+        N: 'static,
+    {
+        self.subscribers.subscribe()
+    }
+
+    /// Sets a hook that is passed the messages for epochs after the next `max_future_epochs`, with
+    /// their senders, if the policy is `FutureEpochPolicy::Forward`.
+    pub fn set_future_message_hook<F>(&mut self, hook: F)
+    where
+        F: Fn(&N, Message<N>) + Send + Sync + 'static,
+    {
+        self.future_message_hook = Some(Arc::new(hook));
+    }
+
+    /// Sets a hook that is shared with other instances.
+    pub(crate) fn set_shared_future_message_hook(&mut self, hook: FutureMessageHook<N>) {
+        self.future_message_hook = Some(hook);
+    }
+
+    /// Returns the number of messages for epochs too far in the future that each peer has in the
+    /// queue, if the policy is `FutureEpochPolicy::Queue`.
+    pub fn queued_messages(&self) -> BTreeMap<N, usize> {
+        let queued = self.future_queue.iter();
+        queued
+            .map(|(id, queue)| (id.clone(), queue.len()))
+            .collect()
+    }
+
+    /// Sets the receiver of instrumentation events.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        self.instrument = instrument;
+    }
+
+    /// Sets a coin that is computed locally, to use in the `BinaryAgreement` instances instead of
+    /// the threshold signature: see `BinaryAgreement::set_local_coin`. This must be called before
+    /// handling any messages, with equivalent coins on all nodes.
+    pub fn set_agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        for epoch_state in self.epochs.values_mut() {
+            epoch_state.set_agreement_local_coin(coin.clone())?;
+        }
+        self.agreement_local_coin = Some(coin);
+        Ok(())
+    }
+
+    /// Returns the outcomes of the threshold coin in all agreement instances of the epochs output
+    /// so far.
+    pub fn coin_stats(&self) -> CoinStats {
+        self.coin_record.outcomes
+    }
+
+    /// Returns the outcomes of the threshold coin in all agreement instances of the epochs output
+    /// so far, and the parities of each validator's signature shares since its last `BiasedCoin`
+    /// report.
+    pub fn coin_record(&self) -> &CoinRecord<N> {
+        &self.coin_record
+    }
+
+    /// Returns the number and the epoch range of the messages each peer has sent us for epochs
+    /// after the current one. Their effects are held in memory until the epochs begin.
+    pub fn buffered_messages(&self) -> BTreeMap<N, BufferedMessages> {
+        let mut buffered: BTreeMap<N, BufferedMessages> = BTreeMap::new();
+        for (&epoch, counts) in &self.future_msg_counts {
+            for (id, &count) in counts {
+                match buffered.entry(id.clone()) {
+                    Entry::Occupied(mut entry) => {
+                        let entry = entry.get_mut();
+                        entry.count += count;
+                        entry.last_epoch = epoch;
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(BufferedMessages {
+                            count,
+                            first_epoch: epoch,
+                            last_epoch: epoch,
+                        });
+                    }
+                }
+            }
+        }
+        buffered
+    }
+
+    /// Returns the amount of state held in memory: the epochs that have not output their batch yet,
+    /// their running sub-algorithms and the messages received ahead of time. Everything that
+    /// belongs to an epoch is dropped once its batch is output, so if these numbers keep growing,
+    /// the node is falling behind, or peers are sending it messages for epochs that never begin.
+    pub fn memory_stats(&self) -> MemoryStats {
+        let mut stats = MemoryStats::default();
+        for epoch_state in self.epochs.values() {
+            epoch_state.add_memory_stats(&mut stats);
+        }
+        let counts = self.future_msg_counts.values().flat_map(BTreeMap::values);
+        stats.buffered_messages = counts.sum();
+        stats.queued_messages = self.future_queue.values().map(Vec::len).sum();
+        stats
+    }
+
+    /// Handles an acknowledgment of our proposal's hash, and inputs the proposal if it now has
+    /// enough of them.
+    fn handle_contribution_ack(
+        &mut self,
+        sender_id: &N,
+        epoch: u64,
+        hash: &[u8; 32],
+    ) -> Result<Step<C, N>> {
+        let pre_validation = match self.pre_validation {
+            // Since we only send hashes in the current epoch, the acknowledgment is outdated.
+            Some(ref mut pre_validation) if epoch == self.epoch => pre_validation,
+            _ => return Ok(Step::default()),
+        };
+        if let Some(fault_kind) = pre_validation.handle_ack(sender_id, hash) {
+            return Ok(Fault::new(sender_id.clone(), fault_kind).into());
+        }
+        let step = self.propose_pre_validated()?;
+        Ok(step.join(self.try_output_batches()?))
+    }
+
+    /// Inputs our pre-validated proposal, if it has enough acknowledgments.
+    fn propose_pre_validated(&mut self) -> Result<Step<C, N>> {
+        let value = match self.pre_validation {
+            Some(ref mut pre_validation) => pre_validation.take_ready(&self.netinfo),
+            None => None,
+        };
+        match value {
+            Some(value) => self.epoch_state_mut(self.epoch)?.propose_prepared(value),
+            None => Ok(Step::default()),
+        }
+    }
+
     /// Increments the epoch number and clears any state that is local to the finished epoch.
     fn update_epoch(&mut self) {
-        // Clear the state of the old epoch.
-        self.epochs.remove(&self.epoch);
+        // Clear the state of the old epoch, and of any earlier ones.
         self.epoch += 1;
-        self.has_input = false;
+        self.epochs = self.epochs.split_off(&self.epoch);
+        self.next_proposal_epoch = cmp::max(self.next_proposal_epoch, self.epoch);
+        let transition = Transition::Epoch(self.epoch);
+        self.instrument
+            .state_transition(Algorithm::HoneyBadger, transition);
+        self.future_msg_counts = self.future_msg_counts.split_off(&(self.epoch + 1));
+    }
+
+    /// Reports the messages we send to the instrument. Each decryption or seed share we send is one
+    /// we computed. Also reports the faults, except for those detected by `Subset`'s instances.
+    fn report_sent(&self, step: &Step<C, N>) {
+        for msg in &step.messages {
+            let (algorithm, epoch) = (inner_algorithm(&msg.message.content), msg.message.epoch());
+            self.instrument.message_sent(&msg.target, algorithm, epoch);
+            match msg.message.content {
+                MessageContent::DecryptionShare { .. } => {
+                    self.instrument.crypto_op(CryptoOp::DecryptShare)
+                }
+                MessageContent::SeedShare(_) => self.instrument.crypto_op(CryptoOp::Sign),
+                _ => (),
+            }
+        }
+        for fault in &step.fault_log.0 {
+            if let FaultKind::SubsetFault(_) = fault.kind {
+                continue;
+            }
+            let algorithm = Algorithm::HoneyBadger;
+            self.instrument
+                .fault(&fault.node_id, algorithm, &fault.kind);
+        }
     }
 
     /// Tries to decrypt contributions from all proposers and output those in a batch.
     fn try_output_batches(&mut self) -> Result<Step<C, N>> {
         let mut step = Step::default();
-        while let Some((batch, fault_log)) = self
+        let start_epoch = self.epoch;
+        while let Some((mut batch, fault_log)) = self
             .epochs
             .get(&self.epoch)
             .and_then(EpochState::try_output_batch)
         {
+            step.fault_log.extend(fault_log);
+            if let Some(ref validator) = self.contribution_validator {
+                let invalid: Vec<N> = batch
+                    .contributions
+                    .iter()
+                    .filter(|(id, contrib)| !validator(id, contrib))
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in invalid {
+                    batch.contributions.remove(&id);
+                    step.fault_log.append(id, FaultKind::InvalidContribution);
+                }
+                let contributions = &batch.contributions;
+                batch.order.retain(|id| contributions.contains_key(id));
+            }
             // Queue the output and advance the epoch.
+            self.subscribers.notify(&batch);
             step.output.push(batch);
-            step.fault_log.extend(fault_log);
+            if let Some(ref mut pre_validation) = self.pre_validation {
+                step.fault_log
+                    .extend(pre_validation.end_epoch(&self.netinfo));
+            }
+            if let Some(epoch_state) = self.epochs.get(&self.epoch) {
+                self.coin_record.merge(epoch_state.coin_record());
+                epoch_state.report_timings(&*self.instrument);
+            }
+            step.fault_log.extend(self.report_biased_shares());
             self.update_epoch();
         }
+        if self.epoch != start_epoch && !self.future_queue.is_empty() {
+            step.extend(self.handle_queued_messages()?);
+        }
         Ok(step)
     }
 
+    /// Returns a `BiasedCoin` fault for each validator whose signature shares' parities are
+    /// improbable for a fair coin, and starts counting their shares afresh.
+    fn report_biased_shares(&mut self) -> FaultLog<N> {
+        let biased: Vec<N> = self
+            .coin_record
+            .shares
+            .iter()
+            .filter(|(_, stats)| stats.is_biased(COIN_BIAS_MIN_FLIPS, COIN_BIAS_MAX_DEVIATION))
+            .map(|(id, _)| id.clone())
+            .collect();
+        let mut fault_log = FaultLog::default();
+        for id in biased {
+            self.coin_record.shares.remove(&id);
+            fault_log.append(id, FaultKind::BiasedCoin);
+        }
+        fault_log
+    }
+
     /// Returns a mutable reference to the state of the given `epoch`. Initializes a new one, if it
     /// doesn't exist yet.
     fn epoch_state_mut(&mut self, epoch: u64) -> Result<&mut EpochState<C, N>> {
         Ok(match self.epochs.entry(epoch) {
             Entry::Occupied(entry) => entry.into_mut(),
-            Entry::Vacant(entry) => entry.insert(EpochState::new(
-                self.netinfo.clone(),
-                self.session_id,
-                epoch,
-                self.params.subset_handling_strategy.clone(),
-                self.params.encryption_schedule.use_on_epoch(epoch),
-            )?),
+            Entry::Vacant(entry) => {
+                let mut epoch_state = EpochState::new(
+                    self.netinfo.clone(),
+                    self.session_id,
+                    epoch,
+                    self.params.subset_handling_strategy.clone(),
+                    self.params.encryption_schedule.use_on_epoch(epoch),
+                    self.params.contribution_order,
+                    self.params.max_contribution_size,
+                )?;
+                epoch_state.set_instrument(self.instrument.clone());
+                if let Some(ref schedule) = self.params.agreement_coin_schedule {
+                    epoch_state.set_agreement_variant(Arc::new(schedule.clone()))?;
+                }
+                if let Some(ref coin) = self.agreement_local_coin {
+                    epoch_state.set_agreement_local_coin(coin.clone())?;
+                }
+                epoch_state.set_agreement_certify(self.params.agreement_certificates);
+                epoch_state
+                    .set_agreement_batch_verification(self.params.agreement_batch_verification);
+                if let Some(max_epochs) = self.params.agreement_max_epochs {
+                    epoch_state.set_agreement_max_epochs(max_epochs);
+                }
+                if self.params.agreement_precomputed_coin_shares > 0 {
+                    let count = self.params.agreement_precomputed_coin_shares;
+                    epoch_state.precompute_agreement_coin_shares(count)?;
+                }
+                entry.insert(epoch_state)
+            }
         })
     }
 
@@ -195,7 +611,55 @@ where
     }
 }
 
+/// Returns the algorithm a message is reported as: `Broadcast` or `BinaryAgreement` for the
+/// `Subset` messages, and `HoneyBadger` for the others.
+fn inner_algorithm<N>(content: &MessageContent<N>) -> Algorithm {
+    match content {
+        MessageContent::Subset(cs_msg) => match cs_msg.content {
+            subset::MessageContent::Broadcast(_) => Algorithm::Broadcast,
+            subset::MessageContent::Agreement(_) => Algorithm::BinaryAgreement,
+        },
+        MessageContent::DecryptionShare { .. }
+        | MessageContent::ContributionHash(_)
+        | MessageContent::ContributionAck(_)
+        | MessageContent::SeedShare(_) => Algorithm::HoneyBadger,
+    }
+}
+
+/// The messages a peer has sent us ahead of time, for epochs that haven't begun yet.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub struct BufferedMessages {
+    /// The number of messages.
+    pub count: usize,
+    /// The earliest epoch of any of the messages.
+    pub first_epoch: u64,
+    /// The latest epoch of any of the messages.
+    pub last_epoch: u64,
+}
+
+/// The amount of state a `HoneyBadger` instance holds in memory.
+#[derive(Clone, Copy, Default, Eq, PartialEq, Hash, Debug)]
+pub struct MemoryStats {
+    /// The number of epochs whose state is retained, i.e. that have not output their batch yet.
+    pub epochs: usize,
+    /// The number of running `Broadcast` instances in these epochs.
+    pub broadcasts: usize,
+    /// The number of running `BinaryAgreement` instances in these epochs.
+    pub agreements: usize,
+    /// The number of ongoing threshold decryptions in these epochs.
+    pub decryptions: usize,
+    /// The number of messages received for epochs after the current one.
+    pub buffered_messages: usize,
+    /// The number of messages for epochs too far in the future in the queue.
+    pub queued_messages: usize,
+}
+
 /// How frequently Threshold Encryption should be used.
+///
+/// The schedule only depends on the epoch number: Since the validators work on several epochs
+/// concurrently, they must agree on whether an epoch is encrypted before any of them has output
+/// the previous batches. To encrypt only while under suspected attack, switch to a stricter
+/// schedule at an era boundary, with a `DynamicHoneyBadger` `Change::EncryptionSchedule`.
 #[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize, Hash, Debug)]
 pub enum EncryptionSchedule {
     /// Always encrypt. All contributions are encrypted in every epoch.
@@ -220,3 +684,122 @@ impl EncryptionSchedule {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{EncryptionSchedule, HoneyBadger, MemoryStats};
+    use crate::honey_badger::{FaultKind, FutureEpochPolicy, MessageContent};
+    use crate::{NetworkInfo, Target};
+
+    #[test]
+    fn test_encryption_schedule() {
+        let encrypted = |schedule: EncryptionSchedule| -> Vec<u64> {
+            (0..10)
+                .filter(|&epoch| schedule.use_on_epoch(epoch))
+                .collect()
+        };
+        assert_eq!(10, encrypted(EncryptionSchedule::Always).len());
+        assert!(encrypted(EncryptionSchedule::Never).is_empty());
+        assert_eq!(
+            vec![0, 3, 6, 9],
+            encrypted(EncryptionSchedule::EveryNthEpoch(3))
+        );
+    }
+
+    #[test]
+    fn test_pipelining() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let mut hb = HoneyBadger::<Vec<u8>, usize>::builder(Arc::new(netinfos[&0].clone()))
+            .pipeline_depth(2)
+            .build();
+        // We can propose in the current epoch and the next two, without waiting for their output.
+        for epoch in 0..3 {
+            assert!(!hb.has_input());
+            let step = hb.propose(&vec![epoch as u8], &mut rng).expect("propose");
+            assert!(step.messages.iter().all(|msg| msg.message.epoch == epoch));
+            assert!(!step.messages.is_empty());
+        }
+        assert!(hb.has_input());
+        assert!(hb.propose(&vec![3], &mut rng).is_err());
+        assert_eq!(0, hb.next_epoch());
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let mut hb = HoneyBadger::<Vec<u8>, usize>::builder(Arc::new(netinfos[&0].clone()))
+            .pipeline_depth(1)
+            .max_future_epochs(1)
+            .future_epoch_policy(FutureEpochPolicy::Queue(5))
+            .build();
+        assert_eq!(MemoryStats::default(), hb.memory_stats());
+        for epoch in 0..2 {
+            let _ = hb.propose(&vec![epoch], &mut rng).expect("propose");
+        }
+        let msg = |epoch| MessageContent::ContributionHash([epoch as u8; 32]).with_epoch(epoch);
+        let _ = hb.handle_message(&1, msg(1)).expect("handle");
+        let _ = hb.handle_message(&1, msg(5)).expect("handle");
+        // Both epochs run a `Broadcast` and a `BinaryAgreement` instance for each validator.
+        let expected = MemoryStats {
+            epochs: 2,
+            broadcasts: 8,
+            agreements: 8,
+            decryptions: 0,
+            buffered_messages: 1,
+            queued_messages: 1,
+        };
+        assert_eq!(expected, hb.memory_stats());
+    }
+
+    #[test]
+    fn test_future_epoch_policy() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let netinfo = Arc::new(netinfos[&0].clone());
+        let new_hb = |policy| {
+            HoneyBadger::<Vec<u8>, usize>::builder(netinfo.clone())
+                .max_future_epochs(0)
+                .future_epoch_policy(policy)
+                .build()
+        };
+        let msg = |epoch| MessageContent::ContributionHash([epoch as u8; 32]).with_epoch(epoch);
+        let faults = |step: super::Step<Vec<u8>, usize>| -> Vec<_> {
+            let faults = step.fault_log.into_iter();
+            faults.map(|fault| (fault.node_id, fault.kind)).collect()
+        };
+
+        let mut hb = new_hb(FutureEpochPolicy::Reject);
+        let step = hb.handle_message(&1, msg(1)).expect("handle");
+        assert_eq!(vec![(1, FaultKind::UnexpectedHbMessageEpoch)], faults(step));
+
+        // Only two messages per peer are queued. They are handled once their epoch begins.
+        let mut hb = new_hb(FutureEpochPolicy::Queue(2));
+        for epoch in 1..3 {
+            assert!(faults(hb.handle_message(&1, msg(epoch)).expect("handle")).is_empty());
+        }
+        let step = hb.handle_message(&1, msg(3)).expect("handle");
+        assert_eq!(vec![(1, FaultKind::FutureEpochQueueFull)], faults(step));
+        assert!(faults(hb.handle_message(&2, msg(2)).expect("handle")).is_empty());
+        assert_eq!(Some(&2), hb.queued_messages().get(&1));
+        hb.update_epoch();
+        let step = hb.handle_queued_messages().expect("replay");
+        assert_eq!(1, step.messages.len());
+        assert_eq!(Target::Node(1), step.messages[0].target);
+        assert_eq!(Some(&1), hb.queued_messages().get(&1));
+        assert_eq!(Some(&1), hb.queued_messages().get(&2));
+
+        let forwarded = Arc::new(Mutex::new(Vec::new()));
+        let hook_forwarded = forwarded.clone();
+        let mut hb = new_hb(FutureEpochPolicy::Forward);
+        hb.set_future_message_hook(move |id, msg| {
+            hook_forwarded.lock().expect("lock").push((*id, msg.epoch));
+        });
+        assert!(faults(hb.handle_message(&3, msg(4)).expect("handle")).is_empty());
+        assert_eq!(vec![(3, 4)], *forwarded.lock().expect("lock"));
+        assert!(hb.queued_messages().is_empty());
+    }
+}
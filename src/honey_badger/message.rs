@@ -7,6 +7,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::subset;
 use crate::threshold_decrypt;
+use crate::threshold_sign;
 
 /// The content of a `HoneyBadger` message. It should be further annotated with an epoch.
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -20,6 +21,13 @@ pub enum MessageContent<N> {
         /// The decryption share: _f + 1_ of these are required to decrypt the contribution.
         share: threshold_decrypt::Message,
     },
+    /// The hash of the sender's proposal in this epoch, to be acknowledged before it is proposed.
+    ContributionHash([u8; 32]),
+    /// An acknowledgment of the recipient's `ContributionHash`.
+    ContributionAck([u8; 32]),
+    /// A signature share of the epoch's random seed, which determines the `Shuffled` order of the
+    /// batch. It is only sent once `Subset` is complete.
+    SeedShare(threshold_sign::Message),
 }
 
 impl<N> Distribution<MessageContent<N>> for Standard
@@ -27,7 +35,9 @@ where
     Standard: Distribution<N>,
 {
     fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> MessageContent<N> {
-        let message_type = *["subset", "dec_share"].choose(rng).unwrap();
+        let message_type = *["subset", "dec_share", "hash", "ack", "seed"]
+            .choose(rng)
+            .unwrap();
 
         match message_type {
             "subset" => MessageContent::Subset(rng.gen::<subset::Message<N>>()),
@@ -35,6 +45,9 @@ where
                 proposer_id: rng.gen::<N>(),
                 share: rng.gen::<threshold_decrypt::Message>(),
             },
+            "hash" => MessageContent::ContributionHash(rng.gen::<[u8; 32]>()),
+            "ack" => MessageContent::ContributionAck(rng.gen::<[u8; 32]>()),
+            "seed" => MessageContent::SeedShare(rng.gen::<threshold_sign::Message>()),
             _ => unreachable!(),
         }
     }
@@ -13,14 +13,57 @@
 //! In every epoch, every validator encrypts their contribution and proposes it to the others.
 //! A `Subset` instance determines which proposals are accepted and will be part of the new
 //! batch. Using threshold encryption, the nodes collaboratively decrypt all accepted
-//! contributions. Invalid contributions (that e.g. cannot be deserialized) are discarded - their
+//! contributions. Invalid contributions (that e.g. cannot be decoded) are discarded - their
 //! proposers must be faulty -, and the remaining ones are output as the new batch. The next epoch
-//! begins as soon as the validators propose new contributions again.
+//! begins as soon as the validators propose new contributions again. The application can discard
+//! further contributions by setting a `HoneyBadgerBuilder::contribution_validator`.
 //!
 //! So it is essentially an endlessly repeating `Subset`, but with the proposed values
 //! encrypted. The encryption makes it harder for an attacker to try and censor a particular value
 //! by influencing the set of proposals that make it into the subset, because they don't
 //! know the decrypted values before the subset is determined.
+//!
+//! ## Plaintext mode
+//!
+//! In a trusted deployment, censorship resistance may not be worth the latency of the threshold
+//! encryption and the extra round of decryption shares. With `EncryptionSchedule::Never`, the
+//! validators propose their contributions in plaintext, and every batch is output as soon as
+//! `Subset` completes. `EncryptionSchedule::EveryNthEpoch` and `EncryptionSchedule::TickTock`
+//! encrypt only some of the epochs.
+//!
+//! ## Pipelining
+//!
+//! Usually a validator proposes in the next epoch only once the current one has output its batch.
+//! With `HoneyBadgerBuilder::pipeline_depth`, it can propose in up to that many later epochs right
+//! away, so that their `Subset` instances already run while the earlier contributions are being
+//! decrypted.
+//!
+//! ## Pre-validation
+//!
+//! Encryption doesn't prevent faulty validators from censoring a particular _proposer_. If
+//! `Params::pre_validation` is set, each validator first sends the hash of its encrypted
+//! contribution to the others, and only proposes it once _f + 1_ validators, including itself,
+//! acknowledged it. Validators that fail to acknowledge our contributions for the configured number
+//! of consecutive epochs are reported as `FaultKind::WithheldContributionAck`. The acknowledgment
+//! only shows that the validator is live: It doesn't validate the contribution.
+//!
+//! Each instance also counts the outcomes of the threshold coin in its agreement instances, and the
+//! parities of the signature shares each validator contributed to them. If a validator's share
+//! parities deviate from a fair coin's by more than six standard deviations, it reports
+//! `FaultKind::BiasedCoin` for that validator, and starts counting its shares afresh.
+//!
+//! ## Messages from future epochs
+//!
+//! Messages for the current epoch and the next `max_future_epochs` are handled immediately. What
+//! happens to messages for later epochs is configured by `Params::future_epoch_policy`: By default
+//! they are rejected as faulty, but they can also be queued, up to a limit per peer, until their
+//! epoch comes within reach, or passed to a hook set with `set_future_message_hook`.
+//!
+//! ## Subscriptions
+//!
+//! Besides being returned in the step's output, each batch is passed to the hooks registered with
+//! `HoneyBadger::add_batch_hook`, and sent to the channels returned by `HoneyBadger::subscribe`.
+//! This way, the code that consumes the batches doesn't need to own the message loop.
 
 mod batch;
 mod builder;
@@ -29,11 +72,16 @@ mod error;
 mod honey_badger;
 mod message;
 mod params;
+mod pre_validation;
 
-pub use self::batch::Batch;
+pub(crate) use self::batch::shuffle_txs;
+pub use self::batch::{Batch, ContributionOrder};
 pub use self::builder::HoneyBadgerBuilder;
 pub use self::epoch_state::SubsetHandlingStrategy;
 pub use self::error::{Error, FaultKind, FaultLog, Result};
-pub use self::honey_badger::{EncryptionSchedule, HoneyBadger, Step};
+pub(crate) use self::honey_badger::FutureMessageHook;
+pub use self::honey_badger::{
+    BufferedMessages, EncryptionSchedule, HoneyBadger, MemoryStats, Step,
+};
 pub use self::message::{Message, MessageContent};
-pub use self::params::Params;
+pub use self::params::{ChangeQuorum, ConflictPolicy, FutureEpochPolicy, Params, ProtocolUpgrade};
@@ -1,24 +1,155 @@
 use serde::{Deserialize, Serialize};
 
-use super::{EncryptionSchedule, SubsetHandlingStrategy};
+use super::{ContributionOrder, EncryptionSchedule, SubsetHandlingStrategy};
+use crate::binary_agreement::CoinSchedule;
 
 /// Parameters controlling Honey Badger's behavior and performance.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Params {
     /// The maximum number of future epochs for which we handle messages simultaneously.
     pub max_future_epochs: u64,
+    /// How messages for epochs after the next `max_future_epochs` are handled.
+    pub future_epoch_policy: FutureEpochPolicy,
     /// Strategy used to handle the output of the `Subset` algorithm.
     pub subset_handling_strategy: SubsetHandlingStrategy,
     /// Schedule for adding threshold encryption to some percentage of rounds
     pub encryption_schedule: EncryptionSchedule,
+    /// The order in which the contributions of each batch are output. This is part of the genesis
+    /// configuration, and can't be changed with a `ParamChange`.
+    pub contribution_order: ContributionOrder,
+    /// The maximum size in bytes of each proposed value, i.e. of the encoded contribution, or of
+    /// its ciphertext if the epoch is encrypted. Larger proposals are rejected, and the validators
+    /// that broadcast them are reported.
+    pub max_contribution_size: Option<usize>,
+    /// The coin schedule of the `BinaryAgreement` instances, or `None` for the default
+    /// `TrueFalseCoin`.
+    pub agreement_coin_schedule: Option<CoinSchedule>,
+    /// The number of epochs after which stalled `BinaryAgreement` instances are reported to the
+    /// instrument, if any.
+    pub agreement_max_epochs: Option<u64>,
+    /// Whether the `BinaryAgreement` instances certify their decisions, so that each batch
+    /// contains a `DecisionCertificate` for each proposer.
+    pub agreement_certificates: bool,
+    /// Whether the `BinaryAgreement` instances verify the signature shares of each coin and
+    /// certificate together, instead of one by one as they arrive.
+    pub agreement_batch_verification: bool,
+    /// The number of coin flips for which each `BinaryAgreement` instance computes our coin
+    /// shares in advance, when it is created.
+    pub agreement_precomputed_coin_shares: usize,
+    /// The application-defined wire and protocol version currently in use.
+    pub protocol_version: u64,
+    /// A scheduled switch to a new protocol version, if any.
+    pub protocol_upgrade: Option<ProtocolUpgrade>,
+    /// The number of votes a change to the set of validators needs to take effect.
+    pub change_quorum: ChangeQuorum,
+    /// How contradictory changes to the set of validators are handled.
+    pub conflict_policy: ConflictPolicy,
+    /// The number of epochs after which votes for changes expire, if any.
+    pub vote_ttl: Option<u64>,
+    /// The number of epochs of an era after which the validators automatically generate new keys,
+    /// if any.
+    pub key_rotation_interval: Option<u64>,
+    /// If set, we collect _f + 1_ acknowledgments of the hash of each of our proposals before
+    /// proposing it, and report validators that didn't acknowledge it in this many consecutive
+    /// epochs.
+    pub pre_validation: Option<u64>,
+    /// Whether the validators sign each `DynamicHoneyBadger` batch with their key shares, so that
+    /// every node obtains a `BatchCertificate` for it.
+    pub batch_certificates: bool,
 }
 
 impl Default for Params {
     fn default() -> Params {
         Params {
             max_future_epochs: 3,
+            future_epoch_policy: FutureEpochPolicy::Reject,
             subset_handling_strategy: SubsetHandlingStrategy::Incremental,
             encryption_schedule: EncryptionSchedule::Always,
+            contribution_order: ContributionOrder::ProposerId,
+            max_contribution_size: None,
+            agreement_coin_schedule: None,
+            agreement_max_epochs: None,
+            agreement_certificates: false,
+            agreement_batch_verification: false,
+            agreement_precomputed_coin_shares: 0,
+            protocol_version: 0,
+            protocol_upgrade: None,
+            change_quorum: ChangeQuorum::FaultyPlusOne,
+            conflict_policy: ConflictPolicy::FirstWins,
+            vote_ttl: None,
+            key_rotation_interval: None,
+            pre_validation: None,
+            batch_certificates: false,
         }
     }
 }
+
+/// How messages for epochs too far in the future are handled, i.e. for epochs more than
+/// `max_future_epochs` after the current one.
+///
+/// Correct peers never send such messages if they are behind a `SenderQueue`, but nodes whose
+/// epochs are skewed by restarts or network partitions may. Rejecting them bounds our memory, while
+/// queueing them tolerates the skew.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FutureEpochPolicy {
+    /// The messages are dropped, and their senders reported as
+    /// `FaultKind::UnexpectedHbMessageEpoch`.
+    Reject,
+    /// Up to the given number of messages per peer are queued, and handled once their epochs are no
+    /// longer too far in the future. Further messages are dropped, and their senders reported as
+    /// `FaultKind::FutureEpochQueueFull`.
+    Queue(usize),
+    /// The messages are passed to the hook set with `set_future_message_hook`, or dropped if there
+    /// is none. Their senders are not reported.
+    Forward,
+}
+
+/// The number of votes a change to the set of validators needs to take effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChangeQuorum {
+    /// _f + 1_ votes, so that at least one correct validator voted for the change.
+    FaultyPlusOne,
+    /// _2 f + 1_ votes, so that at least _f + 1_ correct validators voted for the change.
+    TwoFaultyPlusOne,
+    /// The votes of more than half of all validators.
+    Majority,
+}
+
+impl ChangeQuorum {
+    /// Returns the number of votes required in a network with `num_nodes` validators, of which at
+    /// most `num_faulty` are faulty.
+    pub fn threshold(self, num_nodes: usize, num_faulty: usize) -> usize {
+        match self {
+            ChangeQuorum::FaultyPlusOne => num_faulty + 1,
+            ChangeQuorum::TwoFaultyPlusOne => 2 * num_faulty + 1,
+            ChangeQuorum::Majority => num_nodes / 2 + 1,
+        }
+    }
+}
+
+/// How contradictory changes to the set of validators are handled: changes that both alter the
+/// same node in different ways, e.g. one removes it and the other gives it a new key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ConflictPolicy {
+    /// Conflicting changes are counted like any other: The first one to reach its quorum wins.
+    FirstWins,
+    /// A change that conflicts with one that has committed votes of more than _f_ weight can't
+    /// win, so a single faulty validator can't block a change. If both have more than _f_, neither
+    /// wins until enough voters vote again.
+    RejectConflicting,
+    /// A change that conflicts with one that has committed votes needs at least _2 f + 1_ votes.
+    Supermajority,
+}
+
+/// A switch to a new protocol version, taking effect in the given epoch.
+///
+/// The version numbers are defined by the application: There is no restriction on their order, so
+/// that an upgrade can also be rolled back. The switch takes place at an era boundary, so that no
+/// `HoneyBadger` instance receives messages of different versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProtocolUpgrade {
+    /// The new protocol version.
+    pub version: u64,
+    /// The first epoch that uses the new version.
+    pub epoch: u64,
+}
@@ -0,0 +1,150 @@
+//! Optional pre-validation of our own contributions.
+//!
+//! Threshold encryption prevents the faulty validators from censoring a contribution based on its
+//! content, but not from censoring a particular _proposer_, e.g. by never voting for its proposals
+//! in `Subset`. With pre-validation, a proposer first sends the hash of its contribution to all
+//! validators, and only proposes it once _f + 1_ of them, including itself, acknowledged it. If
+//! the epoch is encrypted, the hash is that of the ciphertext, so it reveals nothing about the
+//! content. That way, it learns which validators are willing to accept its contributions: A
+//! validator that doesn't acknowledge them in many consecutive epochs, even though the network
+//! keeps making progress, is reported in the fault log.
+//!
+//! The acknowledgments only measure liveness: A correct validator acknowledges every hash it
+//! receives in the current or a future epoch, without validating the contribution, since it only
+//! sees the hash, and the `ContributionValidator` can only run once the contribution is decrypted.
+//! So a reported validator either doesn't receive our messages or deliberately ignores us, but one
+//! that acknowledges our hashes and then doesn't support our proposals in `Subset` is not detected.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::{FaultKind, FaultLog};
+use crate::{NetworkInfo, NodeIdT};
+
+/// The state of the acknowledgments of our contributions.
+#[derive(Debug)]
+pub(super) struct PreValidation<N> {
+    /// The number of consecutive epochs in which a validator must fail to acknowledge our
+    /// contribution to be reported.
+    threshold: u64,
+    /// The hash of our contribution in the current epoch, if we made one.
+    hash: Option<[u8; 32]>,
+    /// The value we input to `Subset`, until it has enough acknowledgments to be proposed.
+    pending: Option<Vec<u8>>,
+    /// The validators that acknowledged our contribution in the current epoch, including us.
+    acks: BTreeSet<N>,
+    /// The number of consecutive epochs in which each validator didn't acknowledge ours.
+    misses: BTreeMap<N, u64>,
+}
+
+impl<N: NodeIdT> PreValidation<N> {
+    /// Creates a new instance that reports validators after `threshold` missing acknowledgments.
+    pub(super) fn new(threshold: u64) -> Self {
+        PreValidation {
+            threshold,
+            hash: None,
+            pending: None,
+            acks: BTreeSet::new(),
+            misses: BTreeMap::new(),
+        }
+    }
+
+    /// Stores the value we want to input to `Subset` in the current epoch, with its hash.
+    pub(super) fn start(&mut self, our_id: &N, value: Vec<u8>, hash: [u8; 32]) {
+        self.hash = Some(hash);
+        self.pending = Some(value);
+        self.acks.insert(our_id.clone());
+    }
+
+    /// Handles an acknowledgment of the hash for the current epoch. Returns a fault if it is not
+    /// the hash of our contribution.
+    pub(super) fn handle_ack(&mut self, sender_id: &N, hash: &[u8; 32]) -> Option<FaultKind> {
+        if self.hash.as_ref() != Some(hash) {
+            return Some(FaultKind::InvalidContributionAck);
+        }
+        self.acks.insert(sender_id.clone());
+        None
+    }
+
+    /// Returns the value to input to `Subset`, if it hasn't been proposed yet and now has at least
+    /// _f + 1_ acknowledgments.
+    pub(super) fn take_ready(&mut self, netinfo: &NetworkInfo<N>) -> Option<Vec<u8>> {
+        if self.acks.len() > netinfo.num_faulty() {
+            self.pending.take()
+        } else {
+            None
+        }
+    }
+
+    /// Concludes the current epoch: Counts the validators that didn't acknowledge our contribution,
+    /// and reports the ones that reached the threshold.
+    pub(super) fn end_epoch(&mut self, netinfo: &NetworkInfo<N>) -> FaultLog<N> {
+        let mut fault_log = FaultLog::new();
+        self.pending = None;
+        if self.hash.take().is_none() {
+            return fault_log;
+        }
+        let acks = &self.acks;
+        self.misses = self
+            .misses
+            .iter()
+            .filter(|(id, _)| netinfo.is_node_validator(id))
+            .map(|(id, misses)| (id.clone(), *misses))
+            .collect();
+        for id in netinfo.all_ids().filter(|id| *id != netinfo.our_id()) {
+            if acks.contains(id) {
+                self.misses.remove(id);
+                continue;
+            }
+            let misses = self.misses.entry(id.clone()).or_insert(0);
+            *misses += 1;
+            if *misses >= self.threshold {
+                *misses = 0;
+                fault_log.append(id.clone(), FaultKind::WithheldContributionAck);
+            }
+        }
+        self.acks.clear();
+        fault_log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PreValidation;
+    use crate::honey_badger::FaultKind;
+    use crate::NetworkInfo;
+
+    #[test]
+    fn test_withheld_acks() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let netinfo = &netinfos[&0];
+        let mut pv = PreValidation::new(2);
+        for epoch in 0..4u8 {
+            let hash = [epoch; 32];
+            pv.start(&0, vec![epoch], hash);
+            assert_eq!(None, pv.take_ready(netinfo));
+            assert_eq!(
+                Some(FaultKind::InvalidContributionAck),
+                pv.handle_ack(&1, &[9; 32])
+            );
+            assert_eq!(None, pv.handle_ack(&1, &hash));
+            assert_eq!(Some(vec![epoch]), pv.take_ready(netinfo));
+            assert_eq!(None, pv.take_ready(netinfo));
+            if epoch % 2 == 1 {
+                assert_eq!(None, pv.handle_ack(&3, &hash));
+            }
+            // Node 2 never acknowledges, and node 3 only in every second epoch, so only node 2 is
+            // reported, every second epoch.
+            let faulty: Vec<_> = pv
+                .end_epoch(netinfo)
+                .into_iter()
+                .map(|fault| (fault.node_id, fault.kind))
+                .collect();
+            if epoch % 2 == 1 {
+                assert_eq!(vec![(2, FaultKind::WithheldContributionAck)], faulty);
+            } else {
+                assert!(faulty.is_empty());
+            }
+        }
+    }
+}
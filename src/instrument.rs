@@ -0,0 +1,125 @@
+//! # Instrumentation
+//!
+//! Metrics, tracing and research logging all need to observe the same events: messages being
+//! received and sent, the algorithms moving from one state to the next, how long they take, the
+//! faults they detect, and the expensive cryptographic operations. Instead of separate integration
+//! points for each of them, the builders of `HoneyBadger`, `DynamicHoneyBadger`,
+//! `QueueingHoneyBadger` and `SenderQueue` accept an `Instrument`, whose methods are called
+//! synchronously whenever one of these events occurs. All methods default to doing nothing, so an
+//! implementation only needs to override the ones it is interested in.
+//!
+//! An algorithm passes its instrument on to the algorithms it creates internally, and each message
+//! is only reported by the innermost algorithm that handles it: A `HoneyBadger` message inside a
+//! `DynamicHoneyBadger` message is reported once, by the `HoneyBadger` instance. The sent messages
+//! are those in the steps returned by `handle_message` and by the methods that handle the user's
+//! input, e.g. `propose`. The `Subset` messages inside a `HoneyBadger` message are reported as
+//! `Broadcast` or `BinaryAgreement` messages, with the `HoneyBadger` epoch.
+//!
+//! `HoneyBadger` passes its instrument on to its `Subset`, `Broadcast` and `BinaryAgreement`
+//! instances, which report their durations, and each fault is reported by the algorithm that
+//! detected it.
+//!
+//! The methods must be cheap, since they are called many times per epoch: Expensive processing,
+//! like writing to disk, should be deferred to another thread.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use failure::Fail;
+
+use crate::binary_agreement::Stall;
+use crate::fault_log::FaultLog;
+use crate::header::Algorithm;
+use crate::Target;
+
+/// A cryptographic operation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CryptoOp {
+    /// A contribution was threshold-encrypted.
+    Encrypt,
+    /// We computed our decryption share of a ciphertext.
+    DecryptShare,
+    /// A message was signed.
+    Sign,
+    /// A signature was verified.
+    Verify,
+}
+
+/// A state transition of an algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Transition {
+    /// A new epoch began.
+    Epoch(u64),
+    /// A new `DynamicHoneyBadger` era began, in the given epoch.
+    Era(u64),
+    /// A key generation began.
+    KeyGenStarted,
+    /// A key generation completed.
+    KeyGenCompleted,
+}
+
+/// A duration that is measured by an algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Timing {
+    /// A `HoneyBadger` epoch, from the first message or proposal until its batch is output.
+    Epoch(u64),
+    /// The decryption in a `HoneyBadger` epoch, from the `Subset` output until the batch is output.
+    Decryption(u64),
+    /// A `Subset` instance, from its creation until the set is complete.
+    Subset,
+    /// A `Broadcast` instance, from its creation until it outputs the value.
+    Broadcast,
+    /// A `BinaryAgreement` instance, from its creation until it decides.
+    Agreement,
+}
+
+/// A receiver of instrumentation events.
+pub trait Instrument<N>: Send + Sync {
+    /// Called when a message of the given algorithm and epoch has been received from `sender_id`,
+    /// before it is handled.
+    fn message_received(&self, _sender_id: &N, _algorithm: Algorithm, _epoch: u64) {}
+
+    /// Called for each message of the given algorithm and epoch that is to be sent to `target`.
+    fn message_sent(&self, _target: &Target<N>, _algorithm: Algorithm, _epoch: u64) {}
+
+    /// Called when the given algorithm makes a state transition.
+    fn state_transition(&self, _algorithm: Algorithm, _transition: Transition) {}
+
+    /// Called whenever a cryptographic operation is performed.
+    fn crypto_op(&self, _op: CryptoOp) {}
+
+    /// Called when an algorithm has finished a measured part of its work.
+    fn timing(&self, _timing: Timing, _duration: Duration) {}
+
+    /// Called when the given algorithm detected that `node_id` is faulty.
+    fn fault(&self, _node_id: &N, _algorithm: Algorithm, _kind: &dyn Fail) {}
+
+    /// Called when a `BinaryAgreement` instance has reached its epoch limit without deciding, and
+    /// again at the end of every further epoch, so that operators can be alerted. The validators
+    /// listed in `stall` are not necessarily faulty.
+    fn agreement_stalled(&self, _stall: &Stall<N>) {}
+
+    /// Called when a connectivity check finds that fewer than _N - f_ validators are reachable, so
+    /// that epochs will stall. `unreachable` contains the validators that are currently
+    /// unreachable.
+    fn connectivity_degraded(&self, _unreachable: &BTreeSet<N>) {}
+}
+
+/// An instrument that ignores all events. This is the default.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoInstrument;
+
+impl<N> Instrument<N> for NoInstrument {}
+
+/// Reports the faults in a fault log to the instrument, as detected by the given algorithm.
+pub(crate) fn report_faults<N, F>(
+    instrument: &dyn Instrument<N>,
+    algorithm: Algorithm,
+    fault_log: &FaultLog<N, F>,
+) where
+    F: Fail,
+{
+    for fault in &fault_log.0 {
+        instrument.fault(&fault.node_id, algorithm, &fault.kind);
+    }
+}
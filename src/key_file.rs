@@ -0,0 +1,352 @@
+//! # Encrypted key files
+//!
+//! A `KeyFile` is a versioned format for storing a node's keys at rest, e.g. to back up a
+//! validator's identity and restore it on another machine: the node ID, the secret key and, if it
+//! is a validator, its threshold keys including the secret key share, encrypted with a password.
+//!
+//! The format is fixed for each version, so that files can be exchanged between deployments.
+//! Version 1 derives a 256-bit key from the password with Argon2id (version 0x13), using a random
+//! 16-byte salt and the `KdfParams` stored in the file, and encrypts the `bincode` serialization
+//! of the `NodeKeys` with ChaCha20-Poly1305 (RFC 8439), using a random 12-byte nonce. The header,
+//! i.e. the magic bytes, the version, the `KdfParams`, the salt and the nonce, is authenticated as
+//! associated data, so that none of it can be modified without the decryption failing.
+//!
+//! The encoding of a key file, returned by `KeyFile::to_bytes`, starts with the magic bytes
+//! `HBBFTKEY`, followed by the `bincode` serialization of the `KeyFile`. Its first field is the
+//! version, encoded as a little-endian `u16`, which determines the layout of the rest of the file.
+//!
+//! Since the `KdfParams` are read from the file, a file could request an arbitrarily expensive key
+//! derivation. Parameters above `MAX_MEMORY_KIB`, `MAX_ITERATIONS` or `MAX_PARALLELISM` are
+//! rejected before deriving the key.
+
+use std::fmt;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use failure::Fail;
+use rand::RngCore;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::crypto::serde_impl::SerdeSecret;
+use crate::crypto::SecretKey;
+use crate::secret::SecretBytes;
+use crate::{KeyMaterial, NetworkInfo, NetworkInfoError, NodeIdT};
+
+/// The magic bytes at the beginning of an encoded key file.
+const MAGIC: &[u8; 8] = b"HBBFTKEY";
+
+/// The current version of the key file format.
+pub const VERSION: u16 = 1;
+
+/// The length of the Argon2id salt, in bytes.
+const SALT_LEN: usize = 16;
+
+/// The length of the ChaCha20-Poly1305 nonce, in bytes.
+const NONCE_LEN: usize = 12;
+
+/// The length of the derived key, in bytes.
+const KEY_LEN: usize = 32;
+
+/// The maximum memory size of the key derivation, in KiB: 4 GiB.
+pub const MAX_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+/// The maximum number of passes of the key derivation.
+pub const MAX_ITERATIONS: u32 = 64;
+
+/// The maximum degree of parallelism of the key derivation.
+pub const MAX_PARALLELISM: u32 = 64;
+
+/// A key file error.
+#[derive(Clone, Debug, Fail, PartialEq, Eq)]
+pub enum Error {
+    /// The bytes don't start with the magic bytes of a key file.
+    #[fail(display = "Not a key file")]
+    NotAKeyFile,
+    /// The key file has a version this crate doesn't support.
+    #[fail(display = "Unsupported key file version {}", _0)]
+    UnsupportedVersion(u16),
+    /// The key derivation parameters are invalid.
+    #[fail(display = "Invalid key derivation parameters: {}", _0)]
+    InvalidKdfParams(String),
+    /// The password is wrong, or the key file was modified.
+    #[fail(display = "Wrong password, or corrupted key file")]
+    Decrypt,
+    /// Failed to serialize the key file or the keys.
+    #[fail(display = "Serialization error: {}", _0)]
+    Serialize(String),
+    /// Failed to deserialize the key file or the keys.
+    #[fail(display = "Deserialization error: {}", _0)]
+    Deserialize(String),
+    /// Our secret key is held by an external `Signer`, so it can't be exported.
+    #[fail(display = "The secret key is not available")]
+    UnavailableSecretKey,
+    /// The stored keys are inconsistent.
+    #[fail(display = "Invalid keys: {}", _0)]
+    InvalidKeys(NetworkInfoError),
+}
+
+/// A key file result.
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+/// The cost parameters of the Argon2id key derivation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// The memory size, in KiB.
+    pub memory_kib: u32,
+    /// The number of passes over the memory.
+    pub iterations: u32,
+    /// The degree of parallelism.
+    pub parallelism: u32,
+}
+
+impl Default for KdfParams {
+    /// Returns the `argon2` crate's defaults: 19 MiB of memory, two passes and no parallelism.
+    fn default() -> Self {
+        KdfParams {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+impl KdfParams {
+    /// Returns an error if any of the parameters exceeds its maximum.
+    fn check_limits(&self) -> Result<()> {
+        let limits = [
+            ("memory size", self.memory_kib, MAX_MEMORY_KIB),
+            ("iterations", self.iterations, MAX_ITERATIONS),
+            ("parallelism", self.parallelism, MAX_PARALLELISM),
+        ];
+        for &(name, value, max) in &limits {
+            if value > max {
+                let msg = format!("{} {} exceeds the maximum {}", name, value, max);
+                return Err(Error::InvalidKdfParams(msg));
+            }
+        }
+        Ok(())
+    }
+
+    /// Derives the encryption key from the password and salt.
+    fn derive_key(&self, password: &[u8], salt: &[u8]) -> Result<Zeroizing<[u8; KEY_LEN]>> {
+        self.check_limits()?;
+        let invalid = |err: argon2::Error| Error::InvalidKdfParams(err.to_string());
+        let params = Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.parallelism,
+            Some(KEY_LEN),
+        )
+        .map_err(invalid)?;
+        let mut key = Zeroizing::new([0; KEY_LEN]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password, salt, &mut *key)
+            .map_err(invalid)?;
+        Ok(key)
+    }
+}
+
+/// A node's keys, as stored in a key file.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct NodeKeys<N: Ord> {
+    /// The node's ID.
+    pub our_id: N,
+    /// The node's secret key for signing and decryption.
+    pub secret_key: SerdeSecret<SecretKey>,
+    /// The threshold keys, including the node's secret key share if it is a validator.
+    pub key_material: KeyMaterial<N>,
+}
+
+impl<N: NodeIdT> NodeKeys<N> {
+    /// Returns the keys of the given `NetworkInfo`, or an error if the secret keys are held by
+    /// external signers.
+    pub fn from_network_info(netinfo: &NetworkInfo<N>) -> Result<Self> {
+        let secret_key = netinfo.secret_key().ok_or(Error::UnavailableSecretKey)?;
+        let key_material = netinfo.key_material();
+        if netinfo.is_validator() && key_material.secret_key_share.is_none() {
+            return Err(Error::UnavailableSecretKey);
+        }
+        Ok(NodeKeys {
+            our_id: netinfo.our_id().clone(),
+            secret_key: SerdeSecret(secret_key.clone()),
+            key_material,
+        })
+    }
+
+    /// Creates a `NetworkInfo` with these keys, after verifying that they are consistent.
+    pub fn into_network_info(self) -> Result<NetworkInfo<N>> {
+        let NodeKeys {
+            our_id,
+            secret_key,
+            key_material,
+        } = self;
+        NetworkInfo::from_key_material(our_id, secret_key.into_inner(), key_material)
+            .map_err(Error::InvalidKeys)
+    }
+}
+
+/// A node's keys, encrypted with a password.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyFile {
+    /// The version of the format.
+    version: u16,
+    /// The parameters of the key derivation.
+    kdf: KdfParams,
+    /// The random salt of the key derivation.
+    salt: [u8; SALT_LEN],
+    /// The random nonce of the encryption.
+    nonce: [u8; NONCE_LEN],
+    /// The encrypted `NodeKeys`, followed by the authentication tag.
+    ciphertext: Vec<u8>,
+}
+
+impl fmt::Debug for KeyFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyFile")
+            .field("version", &self.version)
+            .field("kdf", &self.kdf)
+            .field("ciphertext", &format!("<{} bytes>", self.ciphertext.len()))
+            .finish()
+    }
+}
+
+impl KeyFile {
+    /// Encrypts the keys with the password, using the default `KdfParams`.
+    pub fn seal<N, R>(keys: &NodeKeys<N>, password: &[u8], rng: &mut R) -> Result<Self>
+    where
+        N: Ord + Serialize,
+        R: RngCore,
+    {
+        KeyFile::seal_with_params(keys, password, KdfParams::default(), rng)
+    }
+
+    /// Encrypts the keys with the password, using the given key derivation parameters.
+    pub fn seal_with_params<N, R>(
+        keys: &NodeKeys<N>,
+        password: &[u8],
+        kdf: KdfParams,
+        rng: &mut R,
+    ) -> Result<Self>
+    where
+        N: Ord + Serialize,
+        R: RngCore,
+    {
+        let plaintext = SecretBytes::new(
+            bincode::serialize(keys).map_err(|err| Error::Serialize(format!("{:?}", err)))?,
+        );
+        let mut salt = [0; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let mut key_file = KeyFile {
+            version: VERSION,
+            kdf,
+            salt,
+            nonce,
+            ciphertext: Vec::new(),
+        };
+        let key = kdf.derive_key(password, &salt)?;
+        let aad = key_file.header()?;
+        let payload = Payload {
+            msg: &plaintext,
+            aad: &aad,
+        };
+        key_file.ciphertext = ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Serialize("encryption failed".to_string()))?;
+        Ok(key_file)
+    }
+
+    /// Encrypts the keys of the given `NetworkInfo` with the password, using the default
+    /// `KdfParams`.
+    pub fn from_network_info<N, R>(
+        netinfo: &NetworkInfo<N>,
+        password: &[u8],
+        rng: &mut R,
+    ) -> Result<Self>
+    where
+        N: NodeIdT + Serialize,
+        R: RngCore,
+    {
+        KeyFile::seal(&NodeKeys::from_network_info(netinfo)?, password, rng)
+    }
+
+    /// Decrypts the keys with the password.
+    pub fn open<N>(&self, password: &[u8]) -> Result<NodeKeys<N>>
+    where
+        N: Ord + DeserializeOwned,
+    {
+        if self.version != VERSION {
+            return Err(Error::UnsupportedVersion(self.version));
+        }
+        let key = self.kdf.derive_key(password, &self.salt)?;
+        let aad = self.header()?;
+        let payload = Payload {
+            msg: &self.ciphertext,
+            aad: &aad,
+        };
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .decrypt(Nonce::from_slice(&self.nonce), payload)
+            .map(SecretBytes::new)
+            .map_err(|_| Error::Decrypt)?;
+        bincode::deserialize(&plaintext).map_err(|err| Error::Deserialize(format!("{:?}", err)))
+    }
+
+    /// Decrypts the keys with the password, and creates a `NetworkInfo` with them.
+    pub fn into_network_info<N>(&self, password: &[u8]) -> Result<NetworkInfo<N>>
+    where
+        N: NodeIdT + DeserializeOwned,
+    {
+        self.open(password)?.into_network_info()
+    }
+
+    /// Returns the version of the format the file was written in.
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    /// Returns the parameters of the key derivation.
+    pub fn kdf_params(&self) -> &KdfParams {
+        &self.kdf
+    }
+
+    /// Returns the encoding of the key file, for writing it to disk.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        bincode::serialize_into(&mut bytes, self)
+            .map_err(|err| Error::Serialize(format!("{:?}", err)))?;
+        Ok(bytes)
+    }
+
+    /// Decodes a key file. This doesn't decrypt it yet.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(MAGIC) {
+            return Err(Error::NotAKeyFile);
+        }
+        let deser_err = |err| Error::Deserialize(format!("{:?}", err));
+        // The version comes first, so that we can tell a future format from a corrupted file.
+        let version: u16 = bincode::deserialize(&bytes[MAGIC.len()..]).map_err(deser_err)?;
+        if version != VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+        let key_file: KeyFile = bincode::deserialize(&bytes[MAGIC.len()..]).map_err(deser_err)?;
+        let len = bincode::serialized_size(&key_file).map_err(deser_err)?;
+        if MAGIC.len() as u64 + len != bytes.len() as u64 {
+            return Err(Error::Deserialize("trailing bytes".to_string()));
+        }
+        Ok(key_file)
+    }
+
+    /// Returns the header that is authenticated as associated data: the magic bytes, the version,
+    /// the key derivation parameters, the salt and the nonce.
+    fn header(&self) -> Result<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        let header = (self.version, &self.kdf, &self.salt, &self.nonce);
+        bincode::serialize_into(&mut bytes, &header)
+            .map_err(|err| Error::Serialize(format!("{:?}", err)))?;
+        Ok(bytes)
+    }
+}
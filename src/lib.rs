@@ -91,7 +91,8 @@
 //!
 //! Each node inputs `()` to broadcast signature shares. Once _f + 1_ nodes have input, all nodes
 //! receive a valid signature. The outcome cannot be known by the adversary before at least one
-//! correct node has provided input, and can be used as a source of pseudorandomness.
+//! correct node has provided input, and can be used as a source of pseudorandomness. Applications
+//! can also use it to collectively sign their own documents, e.g. checkpoints.
 //!
 //! [**Threshold Decrypt**](threshold_decrypt/index.html)
 //!
@@ -108,6 +109,12 @@
 //! Unlike the other algorithms, this one is _not_ asynchronous: All nodes must handle the same
 //! messages, in the same order.
 //!
+//! [**Asynchronous Key Generation**](async_key_gen/index.html)
+//!
+//! Like Synchronous Key Generation, but it runs over an asynchronous network and tolerates _f_
+//! faulty nodes, without a coordinator or an existing consensus instance. It can be used to
+//! bootstrap a new network from the nodes' regular key pairs.
+//!
 //! ## Serialization
 //!
 //! `hbbft` supports [serde](https://serde.rs/): All message types implement the `Serialize` and
@@ -121,15 +128,26 @@
 
 pub use threshold_crypto as crypto;
 
+mod fair_queue;
 mod fault_log;
 mod messaging;
 mod network_info;
+mod secret;
+mod signer;
+mod subscribers;
 mod traits;
 
+pub mod async_key_gen;
+pub mod ban_list;
 pub mod binary_agreement;
 pub mod broadcast;
+pub mod canonical;
+pub mod direct_message;
 pub mod dynamic_honey_badger;
+pub mod header;
 pub mod honey_badger;
+pub mod instrument;
+pub mod key_file;
 pub mod queueing_honey_badger;
 pub mod sender_queue;
 pub mod subset;
@@ -138,11 +156,13 @@ pub mod threshold_decrypt;
 pub mod threshold_sign;
 pub mod transaction_queue;
 pub mod util;
+pub mod witness;
 
 pub use crate::crypto::pairing;
 pub use crate::fault_log::{Fault, FaultLog};
 pub use crate::messaging::{SourcedMessage, Target, TargetedMessage};
-pub use crate::network_info::NetworkInfo;
+pub use crate::network_info::{Connectivity, KeyMaterial, NetworkInfo, NetworkInfoError, PeerInfo};
+pub use crate::signer::{IntoSigner, ShareSigner, Signer};
 pub use crate::traits::{
     ConsensusProtocol, Contribution, CpStep, Epoched, Message, NodeIdT, SessionIdT, Step,
 };
@@ -1,3 +1,5 @@
+use std::collections::BTreeSet;
+
 /// Message sent by a given source.
 #[derive(Clone, Debug)]
 pub struct SourcedMessage<M, N> {
@@ -14,6 +16,8 @@ pub enum Target<N> {
     All,
     /// The message must be sent to the node with the given ID.
     Node(N),
+    /// The message must be sent to all remote nodes except the ones with the given IDs.
+    AllExcept(BTreeSet<N>),
 }
 
 impl<N> Target<N> {
@@ -24,6 +28,18 @@ impl<N> Target<N> {
             message,
         }
     }
+
+    /// Returns `true` if the node with the given ID is a recipient of messages with this target.
+    pub fn contains(&self, id: &N) -> bool
+    where
+        N: Ord,
+    {
+        match self {
+            Target::All => true,
+            Target::Node(target_id) => target_id == id,
+            Target::AllExcept(exclude) => !exclude.contains(id),
+        }
+    }
 }
 
 /// Message with a designated target.
@@ -1,13 +1,100 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
 
+use crate::crypto::poly::Poly;
+use crate::crypto::serde_impl::SerdeSecret;
 use crate::crypto::{self, PublicKey, PublicKeySet, PublicKeyShare, SecretKey, SecretKeyShare};
+use failure::Fail;
+use log::warn;
 use rand;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tiny_keccak::sha3_256;
 
+use crate::signer::{ShareSigner, Signer};
 use crate::{util, NodeIdT};
 
+/// An inconsistency in the parameters of a `NetworkInfo`.
+#[derive(Clone, Eq, PartialEq, Debug, Fail)]
+pub enum NetworkInfoError {
+    /// The set of validators is empty.
+    #[fail(display = "The set of validators is empty")]
+    NoValidators,
+    /// The threshold of the public key set doesn't match the number of validators.
+    #[fail(
+        display = "The public key set's threshold {} doesn't match the {} tolerated faulty nodes",
+        _0, _1
+    )]
+    ThresholdMismatch(usize, usize),
+    /// We are a validator, but our public key doesn't match our secret key.
+    #[fail(display = "Our public key doesn't match our secret key")]
+    PublicKeyMismatch,
+    /// We are a validator, but have no secret key share.
+    #[fail(display = "We are a validator, but have no secret key share")]
+    MissingSecretKeyShare,
+    /// We are an observer, but have a secret key share.
+    #[fail(display = "We are an observer, but have a secret key share")]
+    UnexpectedSecretKeyShare,
+    /// Our secret key share doesn't match our public key share.
+    #[fail(display = "Our secret key share doesn't match our public key share")]
+    SecretKeyShareMismatch,
+}
+
+/// Threshold keys produced outside of this crate, e.g. by an offline ceremony or another
+/// distributed key generation tool, from which a node's `NetworkInfo` can be created with
+/// `NetworkInfo::from_key_material`.
+///
+/// The format is this struct's `serde` representation, e.g. with `bincode` or JSON, and uses
+/// `threshold_crypto`'s representations of the keys. The `public_key_set` and `public_keys` must
+/// be identical for all nodes, and the validators' indices in the `public_key_set` are their
+/// positions among the sorted IDs in `public_keys`. The `secret_key_share` is the node's own share
+/// if it is a validator, and must be kept secret: Unlike everything else, it is specific to one
+/// node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct KeyMaterial<N: Ord> {
+    /// The public key set for threshold cryptography.
+    pub public_key_set: PublicKeySet,
+    /// The validators' public keys, by node ID.
+    pub public_keys: BTreeMap<N, PublicKey>,
+    /// The node's secret key share, or `None` if it is an observer.
+    pub secret_key_share: Option<SerdeSecret<SecretKeyShare>>,
+}
+
+/// Whether a node is connected to enough validators for the network to make progress.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Connectivity<N> {
+    /// At least _N - f_ validators are reachable, so the protocols can make progress.
+    Sufficient,
+    /// Fewer than _N - f_ validators are reachable: Epochs will stall until enough of them become
+    /// reachable again. Contains the validators that are currently unreachable.
+    Degraded(BTreeSet<N>),
+}
+
+impl<N> Connectivity<N> {
+    /// Returns `true` if not enough validators are reachable to make progress.
+    pub fn is_degraded(&self) -> bool {
+        match self {
+            Connectivity::Sufficient => false,
+            Connectivity::Degraded(_) => true,
+        }
+    }
+}
+
 /// Common data shared between algorithms: the nodes' IDs and key shares.
+///
+/// The keys and the set of validators only change with a new key generation, so they are kept
+/// behind an `Arc`, and cloning a `NetworkInfo` only copies the `PeerInfo`.
 #[derive(Debug, Clone)]
 pub struct NetworkInfo<N> {
+    /// The keys and the set of validators.
+    keys: Arc<KeyInfo<N>>,
+    /// The metadata about the peers, which can be updated at any time.
+    peers: PeerInfo<N>,
+}
+
+/// The immutable part of a `NetworkInfo`.
+#[derive(Debug)]
+struct KeyInfo<N> {
     /// This node's ID.
     our_id: N,
     /// The number _N_ of nodes in the network. Equal to the size of `public_keys`.
@@ -16,18 +103,41 @@ pub struct NetworkInfo<N> {
     num_faulty: usize,
     /// Whether this node is a validator. This is true if `public_keys` contains our own ID.
     is_validator: bool,
-    /// This node's secret key share. Only validators have one.
-    secret_key_share: Option<SecretKeyShare>,
-    /// This node's secret key.
-    secret_key: SecretKey,
+    /// The operations with this node's secret key share. Only validators have one.
+    share_signer: Option<Arc<dyn ShareSigner>>,
+    /// The operations with this node's secret key.
+    signer: Arc<dyn Signer>,
     /// The public key set for threshold cryptography. Each validator has a secret key share.
     public_key_set: PublicKeySet,
+    /// The SHA3-256 hash of the master public key in `public_key_set`.
+    key_set_hash: [u8; 32],
     /// The validators' public key shares, computed from `public_key_set`.
     public_key_shares: BTreeMap<N, PublicKeyShare>,
     /// The validators' public keys.
     public_keys: BTreeMap<N, PublicKey>,
     /// The indices in the list of sorted validator IDs.
     node_indices: BTreeMap<N, usize>,
+    /// The result of checking the keys for consistency, as returned by `validate`.
+    validity: Result<(), NetworkInfoError>,
+}
+
+/// Metadata about the peers that is not part of the keys, and is carried over when the keys change.
+#[derive(Debug, Clone)]
+pub struct PeerInfo<N> {
+    /// The known network addresses of validators and observers, in an application-defined
+    /// encoding.
+    addresses: BTreeMap<N, Vec<u8>>,
+    /// The voting weights of nodes, e.g. their stakes. Validators without an entry have weight 1.
+    weights: BTreeMap<N, usize>,
+}
+
+impl<N: Ord> Default for PeerInfo<N> {
+    fn default() -> Self {
+        PeerInfo {
+            addresses: BTreeMap::new(),
+            weights: BTreeMap::new(),
+        }
+    }
 }
 
 impl<N: NodeIdT> NetworkInfo<N> {
@@ -39,13 +149,38 @@ impl<N: NodeIdT> NetworkInfo<N> {
     ///
     /// # Panics
     ///
-    /// Panics if `public_keys` is empty.
+    /// Panics if `public_keys` is empty. Use `try_new` to validate untrusted parameters.
     pub fn new<SKS: Into<Option<SecretKeyShare>>>(
         our_id: N,
         secret_key_share: SKS,
         public_key_set: PublicKeySet,
         secret_key: SecretKey,
         public_keys: BTreeMap<N, PublicKey>,
+    ) -> Self {
+        let share_signer = secret_key_share
+            .into()
+            .map(|sks| Arc::new(sks) as Arc<dyn ShareSigner>);
+        NetworkInfo::with_signers(
+            our_id,
+            share_signer,
+            public_key_set,
+            Arc::new(secret_key),
+            public_keys,
+        )
+    }
+
+    /// Creates a new `NetworkInfo` with the given ID and public keys, whose secret keys are only
+    /// accessible via the given signers, e.g. in a hardware security module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `public_keys` is empty. Use `try_with_signers` to validate untrusted parameters.
+    pub fn with_signers(
+        our_id: N,
+        share_signer: Option<Arc<dyn ShareSigner>>,
+        public_key_set: PublicKeySet,
+        signer: Arc<dyn Signer>,
+        public_keys: BTreeMap<N, PublicKey>,
     ) -> Self {
         let num_nodes = public_keys.len();
         let num_faulty = util::max_faulty(num_nodes);
@@ -60,43 +195,191 @@ impl<N: NodeIdT> NetworkInfo<N> {
             .iter()
             .map(|(id, idx)| (id.clone(), public_key_set.public_key_share(*idx)))
             .collect();
-        NetworkInfo {
+        let key_set_hash = sha3_256(&public_key_set.public_key().to_bytes());
+        let validity = NetworkInfo::check_keys(
+            &our_id,
+            share_signer.as_ref(),
+            &public_key_set,
+            &*signer,
+            &public_key_shares,
+            &public_keys,
+        );
+        let keys = KeyInfo {
             our_id,
             num_nodes,
             num_faulty,
             is_validator,
-            secret_key_share: secret_key_share.into(),
-            secret_key,
+            share_signer,
+            signer,
             public_key_set,
+            key_set_hash,
             public_key_shares,
             node_indices,
             public_keys,
+            validity,
+        };
+        NetworkInfo {
+            keys: Arc::new(keys),
+            peers: PeerInfo::default(),
+        }
+    }
+
+    /// Creates a `NetworkInfo` without threshold keys, e.g. for the broadcasts and agreements of a
+    /// key generation that runs before there are any. It passes `validate`, but its public key set
+    /// is a placeholder, so it must not be used for threshold signatures or decryption.
+    pub(crate) fn without_threshold_keys(
+        our_id: N,
+        signer: Arc<dyn Signer>,
+        public_keys: BTreeMap<N, PublicKey>,
+    ) -> Self {
+        let pk_set = PublicKeySet::from(Poly::one().commitment());
+        let mut netinfo = NetworkInfo::with_signers(our_id, None, pk_set, signer, public_keys);
+        Arc::get_mut(&mut netinfo.keys)
+            .expect("the keys are not shared yet")
+            .validity = Ok(());
+        netinfo
+    }
+
+    /// Creates a new `NetworkInfo` with the given ID and keys, after verifying that they are
+    /// consistent.
+    ///
+    /// Unlike `new`, this doesn't panic, but returns an error if there are no validators, if the
+    /// threshold of the `public_key_set` doesn't match the number of validators, if our own keys
+    /// don't match the public ones, or if we have a secret key share but are not a validator or
+    /// vice versa. Node IDs are unique, since they are the keys of the `public_keys` map.
+    pub fn try_new<SKS: Into<Option<SecretKeyShare>>>(
+        our_id: N,
+        secret_key_share: SKS,
+        public_key_set: PublicKeySet,
+        secret_key: SecretKey,
+        public_keys: BTreeMap<N, PublicKey>,
+    ) -> Result<Self, NetworkInfoError> {
+        let share_signer = secret_key_share
+            .into()
+            .map(|sks| Arc::new(sks) as Arc<dyn ShareSigner>);
+        NetworkInfo::try_with_signers(
+            our_id,
+            share_signer,
+            public_key_set,
+            Arc::new(secret_key),
+            public_keys,
+        )
+    }
+
+    /// Creates a new `NetworkInfo` with the given ID, public keys and signers, after verifying
+    /// that they are consistent, as in `try_new`.
+    pub fn try_with_signers(
+        our_id: N,
+        share_signer: Option<Arc<dyn ShareSigner>>,
+        public_key_set: PublicKeySet,
+        signer: Arc<dyn Signer>,
+        public_keys: BTreeMap<N, PublicKey>,
+    ) -> Result<Self, NetworkInfoError> {
+        if public_keys.is_empty() {
+            return Err(NetworkInfoError::NoValidators);
+        }
+        let netinfo =
+            NetworkInfo::with_signers(our_id, share_signer, public_key_set, signer, public_keys);
+        netinfo.validate()?;
+        Ok(netinfo)
+    }
+
+    /// Returns an error if the keys are inconsistent, as in `try_new`.
+    ///
+    /// The algorithms' constructors call this, so that a `NetworkInfo` that was created with `new`
+    /// from inconsistent keys is rejected up front, instead of causing a panic or a stall later in
+    /// the protocol. The keys are only checked once, when the `NetworkInfo` is created.
+    pub fn validate(&self) -> Result<(), NetworkInfoError> {
+        self.keys.validity.clone()
+    }
+
+    /// Checks that the threshold matches the number of validators, and that our own keys match
+    /// the public ones.
+    fn check_keys(
+        our_id: &N,
+        share_signer: Option<&Arc<dyn ShareSigner>>,
+        public_key_set: &PublicKeySet,
+        signer: &dyn Signer,
+        public_key_shares: &BTreeMap<N, PublicKeyShare>,
+        public_keys: &BTreeMap<N, PublicKey>,
+    ) -> Result<(), NetworkInfoError> {
+        let num_faulty = util::max_faulty(public_keys.len());
+        if public_key_set.threshold() != num_faulty {
+            let threshold = public_key_set.threshold();
+            return Err(NetworkInfoError::ThresholdMismatch(threshold, num_faulty));
+        }
+        match (public_keys.get(our_id), share_signer) {
+            (None, None) => Ok(()), // We are an observer.
+            (None, Some(_)) => Err(NetworkInfoError::UnexpectedSecretKeyShare),
+            (Some(_), None) => Err(NetworkInfoError::MissingSecretKeyShare),
+            (Some(pk), Some(_)) if *pk != signer.public_key() => {
+                Err(NetworkInfoError::PublicKeyMismatch)
+            }
+            (Some(_), Some(share_signer)) => {
+                if public_key_shares.get(our_id) != Some(&share_signer.public_key_share()) {
+                    return Err(NetworkInfoError::SecretKeyShareMismatch);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates a new `NetworkInfo` from externally generated threshold keys and our secret key,
+    /// after verifying that they are consistent, as in `try_new`.
+    pub fn from_key_material(
+        our_id: N,
+        secret_key: SecretKey,
+        key_material: KeyMaterial<N>,
+    ) -> Result<Self, NetworkInfoError> {
+        let KeyMaterial {
+            public_key_set,
+            public_keys,
+            secret_key_share,
+        } = key_material;
+        let secret_key_share = secret_key_share.map(SerdeSecret::into_inner);
+        NetworkInfo::try_new(
+            our_id,
+            secret_key_share,
+            public_key_set,
+            secret_key,
+            public_keys,
+        )
+    }
+
+    /// Returns our threshold keys in the format accepted by `from_key_material`, e.g. to back them
+    /// up, or to move them to another machine. This includes our secret key share, unless it is
+    /// held by an external `ShareSigner`.
+    pub fn key_material(&self) -> KeyMaterial<N> {
+        KeyMaterial {
+            public_key_set: self.keys.public_key_set.clone(),
+            public_keys: self.keys.public_keys.clone(),
+            secret_key_share: self.secret_key_share().cloned().map(SerdeSecret),
         }
     }
 
     /// The ID of the node the algorithm runs on.
     #[inline]
     pub fn our_id(&self) -> &N {
-        &self.our_id
+        &self.keys.our_id
     }
 
     /// ID of all nodes in the network.
     #[inline]
     pub fn all_ids(&self) -> impl Iterator<Item = &N> {
-        self.public_keys.keys()
+        self.keys.public_keys.keys()
     }
 
     /// The total number _N_ of nodes.
     #[inline]
     pub fn num_nodes(&self) -> usize {
-        self.num_nodes
+        self.keys.num_nodes
     }
 
     /// The maximum number _f_ of faulty, Byzantine nodes up to which Honey Badger is guaranteed to
     /// be correct.
     #[inline]
     pub fn num_faulty(&self) -> usize {
-        self.num_faulty
+        self.keys.num_faulty
     }
 
     /// The minimum number _N - f_ of correct nodes with which Honey Badger is guaranteed to be
@@ -104,70 +387,192 @@ impl<N: NodeIdT> NetworkInfo<N> {
     #[inline]
     pub fn num_correct(&self) -> usize {
         // As asserted in `new`, `num_faulty` is never greater than `num_nodes`.
-        self.num_nodes - self.num_faulty
+        self.keys.num_nodes - self.keys.num_faulty
     }
 
-    /// Returns our secret key share for threshold cryptography, or `None` if not a validator.
+    /// Returns our secret key share for threshold cryptography, or `None` if not a validator or
+    /// if it is held by an external `ShareSigner`.
     #[inline]
     pub fn secret_key_share(&self) -> Option<&SecretKeyShare> {
-        self.secret_key_share.as_ref()
+        self.share_signer()
+            .and_then(|signer| signer.secret_key_share())
+    }
+
+    /// Returns our secret key for encryption and signing, or `None` if it is held by an external
+    /// `Signer`.
+    #[inline]
+    pub fn secret_key(&self) -> Option<&SecretKey> {
+        self.keys.signer.secret_key()
     }
 
-    /// Returns our secret key for encryption and signing.
+    /// Returns the operations with our secret key share, or `None` if not a validator.
     #[inline]
-    pub fn secret_key(&self) -> &SecretKey {
-        &self.secret_key
+    pub fn share_signer(&self) -> Option<&Arc<dyn ShareSigner>> {
+        self.keys.share_signer.as_ref()
+    }
+
+    /// Returns the operations with our secret key, for decryption and signing.
+    #[inline]
+    pub fn signer(&self) -> &Arc<dyn Signer> {
+        &self.keys.signer
     }
 
     /// Returns the public key set for threshold cryptography.
     #[inline]
     pub fn public_key_set(&self) -> &PublicKeySet {
-        &self.public_key_set
+        &self.keys.public_key_set
+    }
+
+    /// Returns the SHA3-256 hash of the master public key, which identifies the current public key
+    /// set. Every key generation produces a new one, so signatures that include the hash can't be
+    /// replayed after the set of validators has changed.
+    #[inline]
+    pub fn key_set_hash(&self) -> &[u8; 32] {
+        &self.keys.key_set_hash
     }
 
     /// Returns the public key share if a node with that ID exists, otherwise `None`.
     #[inline]
     pub fn public_key_share(&self, id: &N) -> Option<&PublicKeyShare> {
-        self.public_key_shares.get(id)
+        self.keys.public_key_shares.get(id)
     }
 
     /// Returns a map of all node IDs to their public key shares.
     #[inline]
     pub fn public_key_share_map(&self) -> &BTreeMap<N, PublicKeyShare> {
-        &self.public_key_shares
+        &self.keys.public_key_shares
     }
 
     /// Returns a map of all node IDs to their public keys.
     #[inline]
     pub fn public_key(&self, id: &N) -> Option<&PublicKey> {
-        self.public_keys.get(id)
+        self.keys.public_keys.get(id)
     }
 
     /// Returns a map of all node IDs to their public keys.
     #[inline]
     pub fn public_key_map(&self) -> &BTreeMap<N, PublicKey> {
-        &self.public_keys
+        &self.keys.public_keys
     }
 
     /// The index of a node in a canonical numbering of all nodes. This is the index where the
     /// node appears in `all_ids`.
     #[inline]
     pub fn node_index(&self, id: &N) -> Option<usize> {
-        self.node_indices.get(id).cloned()
+        self.keys.node_indices.get(id).cloned()
     }
 
     /// Returns `true` if this node takes part in the consensus itself. If not, it is only an
     /// observer.
     #[inline]
     pub fn is_validator(&self) -> bool {
-        self.is_validator
+        self.keys.is_validator
     }
 
     /// Returns `true` if the given node takes part in the consensus itself. If not, it is only an
     /// observer.
     #[inline]
     pub fn is_node_validator(&self, id: &N) -> bool {
-        self.public_keys.contains_key(id)
+        self.keys.public_keys.contains_key(id)
+    }
+
+    /// Returns the network address of the given node, if it is known.
+    #[inline]
+    pub fn address(&self, id: &N) -> Option<&[u8]> {
+        self.peers.addresses.get(id).map(Vec::as_slice)
+    }
+
+    /// Returns a map of all node IDs with known network addresses to their addresses.
+    #[inline]
+    pub fn address_map(&self) -> &BTreeMap<N, Vec<u8>> {
+        &self.peers.addresses
+    }
+
+    /// Sets the network address of the given node. The encoding is defined by the application's
+    /// transport; the address is not interpreted by the algorithms.
+    pub fn set_address(&mut self, id: N, address: Vec<u8>) {
+        self.peers.addresses.insert(id, address);
+    }
+
+    /// Returns the voting weight of the given node: 1 by default, or the weight set with
+    /// `set_weight`. Nodes that are not validators have weight 0.
+    #[inline]
+    pub fn weight(&self, id: &N) -> usize {
+        if !self.is_node_validator(id) {
+            return 0;
+        }
+        self.peers.weights.get(id).cloned().unwrap_or(1)
+    }
+
+    /// Returns a map of all node IDs with explicitly set weights to their weights.
+    #[inline]
+    pub fn weight_map(&self) -> &BTreeMap<N, usize> {
+        &self.peers.weights
+    }
+
+    /// Returns the metadata about the peers, e.g. to carry it over to a new `NetworkInfo`.
+    #[inline]
+    pub fn peer_info(&self) -> &PeerInfo<N> {
+        &self.peers
+    }
+
+    /// Replaces the metadata about the peers.
+    pub fn set_peer_info(&mut self, peers: PeerInfo<N>) {
+        self.peers = peers;
+    }
+
+    /// Sets the voting weight of the given node, e.g. its stake. It only counts while the node is
+    /// a validator. All nodes in the network must use the same weights.
+    ///
+    /// The weights only apply to the votes for changes, e.g. to the set of validators: The other
+    /// algorithms still tolerate fewer than a third faulty validators, regardless of their weight.
+    pub fn set_weight(&mut self, id: N, weight: usize) {
+        self.peers.weights.insert(id, weight);
+    }
+
+    /// The total voting weight of all validators.
+    pub fn total_weight(&self) -> usize {
+        self.all_ids().map(|id| self.weight(id)).sum()
+    }
+
+    /// The maximum total weight of faulty validators up to which votes for changes are counted
+    /// correctly: the greatest number less than a third of `total_weight`. If all weights are 1,
+    /// this is equal to `num_faulty`.
+    pub fn num_faulty_weight(&self) -> usize {
+        match self.total_weight() {
+            0 => 0,
+            total => util::max_faulty(total),
+        }
+    }
+
+    /// Returns whether enough validators are reachable for the network to make progress, given the
+    /// IDs of the peers from whom messages were recently received.
+    ///
+    /// If we are a validator ourselves, we count as reachable. IDs of nodes that are not
+    /// validators are ignored. If fewer than _N - f_ validators are reachable, a warning is
+    /// logged and `Connectivity::Degraded` is returned. `HoneyBadger::check_connectivity` and
+    /// `DynamicHoneyBadger::check_connectivity` also report it to their instrument.
+    pub fn connectivity<'a, I>(&self, reachable: I) -> Connectivity<N>
+    where
+        I: IntoIterator<Item = &'a N>,
+        N: 'a,
+    {
+        let mut unreachable: BTreeSet<N> = self.all_ids().cloned().collect();
+        unreachable.remove(&self.keys.our_id);
+        for id in reachable {
+            unreachable.remove(id);
+        }
+        if self.keys.num_nodes - unreachable.len() >= self.num_correct() {
+            return Connectivity::Sufficient;
+        }
+        warn!(
+            "Node {:?} can only reach {} out of {} validators; at least {} are required.",
+            self.keys.our_id,
+            self.keys.num_nodes - unreachable.len(),
+            self.keys.num_nodes,
+            self.num_correct()
+        );
+        Connectivity::Degraded(unreachable)
     }
 
     /// Generates a map of matching `NetworkInfo`s for testing.
@@ -214,3 +619,142 @@ impl<N: NodeIdT> NetworkInfo<N> {
             .collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use rand::Rng;
+
+    use super::{Connectivity, NetworkInfo, NetworkInfoError};
+    use crate::binary_agreement::{self, BinaryAgreement};
+    use crate::broadcast::{self, Broadcast};
+    use crate::crypto::{PublicKey, SecretKey, SecretKeySet};
+    use crate::honey_badger::{self, HoneyBadger};
+    use crate::subset::{self, Subset};
+
+    type Keys = (
+        SecretKeySet,
+        BTreeMap<usize, SecretKey>,
+        BTreeMap<usize, PublicKey>,
+    );
+
+    /// Generates consistent keys for four validators.
+    fn generate_keys<R: Rng>(rng: &mut R) -> Keys {
+        let sk_set = SecretKeySet::random(1, rng);
+        let sec_keys: BTreeMap<usize, SecretKey> = (0..4).map(|id| (id, rng.gen())).collect();
+        let pub_keys = sec_keys
+            .iter()
+            .map(|(id, sk)| (*id, sk.public_key()))
+            .collect();
+        (sk_set, sec_keys, pub_keys)
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        let mut rng = rand::thread_rng();
+        let (sk_set, sec_keys, pub_keys) = generate_keys(&mut rng);
+        let try_new = |id, idx: Option<usize>, sk_set: &SecretKeySet, sk: &SecretKey, pks| {
+            let sks = idx.map(|idx| sk_set.secret_key_share(idx));
+            NetworkInfo::try_new(id, sks, sk_set.public_keys(), sk.clone(), pks)
+        };
+
+        assert!(try_new(0, Some(0), &sk_set, &sec_keys[&0], pub_keys.clone()).is_ok());
+        assert!(try_new(4, None, &sk_set, &rng.gen(), pub_keys.clone()).is_ok());
+
+        let expect_err = |err, result: Result<NetworkInfo<usize>, _>| {
+            assert_eq!(Some(err), result.err());
+        };
+        expect_err(
+            NetworkInfoError::NoValidators,
+            try_new(0, None, &sk_set, &sec_keys[&0], BTreeMap::new()),
+        );
+        let sk_set2 = SecretKeySet::random(2, &mut rng);
+        expect_err(
+            NetworkInfoError::ThresholdMismatch(2, 1),
+            try_new(0, Some(0), &sk_set2, &sec_keys[&0], pub_keys.clone()),
+        );
+        expect_err(
+            NetworkInfoError::PublicKeyMismatch,
+            try_new(0, Some(0), &sk_set, &sec_keys[&1], pub_keys.clone()),
+        );
+        expect_err(
+            NetworkInfoError::MissingSecretKeyShare,
+            try_new(0, None, &sk_set, &sec_keys[&0], pub_keys.clone()),
+        );
+        expect_err(
+            NetworkInfoError::UnexpectedSecretKeyShare,
+            try_new(4, Some(0), &sk_set, &rng.gen(), pub_keys.clone()),
+        );
+        expect_err(
+            NetworkInfoError::SecretKeyShareMismatch,
+            try_new(0, Some(1), &sk_set, &sec_keys[&0], pub_keys.clone()),
+        );
+    }
+
+    #[test]
+    fn test_connectivity() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..7usize, &mut rng).expect("netinfos");
+        let netinfo = &netinfos[&0];
+        assert_eq!(5, netinfo.num_correct());
+
+        // Together with ourselves, five out of seven validators are reachable: just enough.
+        let reachable = [1, 2, 3, 4];
+        assert_eq!(Connectivity::Sufficient, netinfo.connectivity(&reachable));
+        assert!(!netinfo.connectivity(&reachable).is_degraded());
+
+        // With only four, epochs stall. Unknown IDs don't count.
+        let degraded = netinfo.connectivity(&[1, 2, 3, 8, 9]);
+        assert!(degraded.is_degraded());
+        assert_eq!(
+            Connectivity::Degraded(vec![4, 5, 6].into_iter().collect()),
+            degraded
+        );
+
+        // An observer doesn't count itself.
+        let observer = NetworkInfo::new(
+            7,
+            None,
+            netinfo.public_key_set().clone(),
+            rng.gen::<SecretKey>(),
+            netinfo.public_key_map().clone(),
+        );
+        assert!(observer.connectivity(&[0, 1, 2, 3]).is_degraded());
+        assert!(!observer.connectivity(&[0, 1, 2, 3, 4]).is_degraded());
+    }
+
+    #[test]
+    fn test_algorithms_reject_invalid_keys() {
+        let mut rng = rand::thread_rng();
+        let (sk_set, sec_keys, pub_keys) = generate_keys(&mut rng);
+        // Node 0 has node 1's key share.
+        let netinfo = Arc::new(NetworkInfo::new(
+            0,
+            sk_set.secret_key_share(1),
+            sk_set.public_keys(),
+            sec_keys[&0].clone(),
+            pub_keys,
+        ));
+        let err = NetworkInfoError::SecretKeyShareMismatch;
+        assert_eq!(Err(err.clone()), netinfo.validate());
+
+        match Broadcast::new(netinfo.clone(), 0) {
+            Err(broadcast::Error::InvalidNetworkInfo(ref e)) if *e == err => (),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match BinaryAgreement::new(netinfo.clone(), 0) {
+            Err(binary_agreement::Error::InvalidNetworkInfo(ref e)) if *e == err => (),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match Subset::new(netinfo.clone(), 0) {
+            Err(subset::Error::InvalidNetworkInfo(ref e)) if *e == err => (),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+        match HoneyBadger::<Vec<usize>, usize>::builder(netinfo).try_build() {
+            Err(honey_badger::Error::InvalidNetworkInfo(ref e)) if *e == err => (),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+}
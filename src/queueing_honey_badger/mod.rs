@@ -8,6 +8,12 @@
 //! removed and not enough other nodes have proposed yet, no automatic proposal will be made: The
 //! network then waits until at least _f + 1_ have any content for the next epoch.
 //!
+//! With `QueueingHoneyBadgerBuilder::wake_on_first_proposal`, the network stays idle only until
+//! _any_ validator has content: Its proposal serves as the wake-up message, and every node that
+//! receives it proposes, too, so that the epoch can complete. This lets the network process a
+//! single node's transactions without delay, but also lets a faulty validator make it run epochs
+//! with only its own contributions.
+//!
 //! ## How it works
 //!
 //! Queueing Honey Badger runs a Dynamic Honey Badger internally, and automatically inputs a list
@@ -21,22 +27,73 @@
 //! roughly the same entries in their queues. By selecting a random fraction of the first _B_
 //! entries, any two nodes will likely make almost disjoint contributions instead of proposing
 //! the same transaction multiple times.
+//!
+//! With `ProposalStrategy::HashPartitioned`, the overlap is reduced further: The transactions are
+//! assigned to the validators by their hash, with a different assignment in each epoch, and each
+//! validator prefers the ones assigned to itself. Only if it doesn't have enough of them, it fills
+//! its proposal with randomly chosen other ones.
+//!
+//! The queue can be replaced by any implementation of `TransactionQueue`. For example, a
+//! `LaneQueue` separates transactions into lanes, e.g. for system, user and bulk transactions,
+//! each of which is guaranteed a share of every proposal, and a `PriorityQueue` proposes the
+//! transactions with the highest priority, e.g. by fee or deadline, first. An application with its
+//! own mempool, e.g. a fee market, can implement `TransactionQueue` for it and pass it to
+//! `QueueingHoneyBadger::builder_with_queue`: It only needs to select the transactions for a
+//! proposal, remove the committed ones, and report its length.
+//!
+//! ## Persistence
+//!
+//! The queue is kept in memory. To not lose the submitted transactions if the node crashes, a
+//! `TransactionJournal` can be passed to the builder: The transactions are stored before they are
+//! added to the queue and removed once they are committed, and on startup, the stored ones are
+//! loaded into the queue again.
+//!
+//! ## Adaptive batch size
+//!
+//! By default, the target batch size _B_ is fixed. With `adaptive_batch_size`, it is adjusted
+//! before each proposal, within the given bounds: It is doubled if more than _B_ transactions are
+//! pending, and halved if fewer than _B / 2_ are. This way, the nodes catch up quickly after a
+//! burst of transactions, without making the batches of an idle network unnecessarily large.
+//!
+//! ## Expiry
+//!
+//! With `transaction_ttl`, transactions that have been pending for more than the given number of
+//! epochs are dropped from our queue before we choose our proposal, so that stale client requests
+//! are not proposed much later. Since each node counts the epochs from the time the transaction
+//! reached its own queue, this is not deterministic: Another validator can still propose it.
+//!
+//! To guarantee that a transaction is not committed after a deadline, the transactions must carry
+//! the deadline themselves, e.g. as a last epoch or a latest timestamp, and an `expiry` hook must
+//! check it against a batch's epoch and timestamp. Expired transactions are dropped from the queue
+//! before proposing, and excluded from the output batches: Since the hook only depends on the
+//! transaction and on the batch the validators agreed on, all correct nodes exclude the same ones.
+//!
+//! The batch certificates are signatures of the batches as agreed, including the expired
+//! transactions: `Batch::export` and `Batch::agreed_contributions` still contain them, so the
+//! exports of the output batches match the certificates, and a consumer that verifies a
+//! certificate can apply the same rule to the exported contributions.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::marker::PhantomData;
-use std::{cmp, iter};
+use std::sync::Arc;
+use std::{cmp, io, iter};
 
+use byteorder::{BigEndian, ByteOrder};
 use derivative::Derivative;
 use failure::Fail;
 use rand::distributions::{Distribution, Standard};
 use rand::Rng;
 use serde::{de::DeserializeOwned, Serialize};
+use tiny_keccak::sha3_256;
 
-use crate::crypto::{PublicKey, SecretKey};
+use crate::crypto::PublicKey;
 use crate::dynamic_honey_badger::{
-    self, Batch as DhbBatch, DynamicHoneyBadger, FaultKind, JoinPlan, Message, Step as DhbStep,
+    self, Batch as DhbBatch, DynamicHoneyBadger, FaultKind, JoinPlan, KeyGenCheckpoint, Message,
+    Step as DhbStep,
 };
-use crate::transaction_queue::TransactionQueue;
-use crate::{ConsensusProtocol, Contribution, NetworkInfo, NodeIdT};
+use crate::instrument::Instrument;
+use crate::transaction_queue::{TransactionJournal, TransactionQueue};
+use crate::{ConsensusProtocol, Contribution, IntoSigner, NetworkInfo, NodeIdT};
 
 pub use crate::dynamic_honey_badger::{Change, ChangeState, Input};
 
@@ -55,11 +112,32 @@ pub enum Error {
     /// Failed to create a Dynamic Honey Badger instance according to a join plan.
     #[fail(display = "New joining error: {}", _0)]
     NewJoining(dynamic_honey_badger::Error),
+    /// The transaction queue is full.
+    #[fail(display = "The transaction queue is full")]
+    QueueFull,
+    /// Failed to store or load transactions in the journal.
+    #[fail(display = "Transaction journal error: {}", _0)]
+    Journal(io::Error),
 }
 
 /// The result of `QueueingHoneyBadger` handling an input or message.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
+/// How a validator chooses the transactions it proposes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposalStrategy {
+    /// A random choice among the first `batch_size` transactions in the queue.
+    Random,
+    /// A choice among the first `batch_size` transactions, preferring those whose hash is assigned
+    /// to us in the current epoch.
+    HashPartitioned,
+}
+
+/// A hook that returns `true` if the transaction has expired in a batch with the given epoch and
+/// timestamp, if any. It must only depend on its arguments, since all nodes need to exclude the
+/// same transactions from a batch.
+pub type ExpiryHook<T> = Box<dyn Fn(&T, u64, Option<u64>) -> bool + Send + Sync>;
+
 /// A Queueing Honey Badger builder, to configure the parameters and create new instances of
 /// `QueueingHoneyBadger`.
 pub struct QueueingHoneyBadgerBuilder<T, N, Q>
@@ -75,6 +153,20 @@ where
     queue: Q,
     /// The initial step of the managed `DynamicHoneyBadger` instance.
     step: Option<DhbStep<Vec<T>, N>>,
+    /// Whether to propose as soon as any other validator has proposed.
+    wake_on_first_proposal: bool,
+    /// How we choose the transactions we propose.
+    proposal_strategy: ProposalStrategy,
+    /// The maximum number of pending transactions, if any.
+    queue_capacity: Option<usize>,
+    /// The number of epochs after which a pending transaction is dropped, if any.
+    transaction_ttl: Option<u64>,
+    /// The hook that decides whether a transaction has expired, if any.
+    expiry: Option<ExpiryHook<T>>,
+    /// The minimum and maximum batch size, if it is adjusted to the queue length.
+    batch_size_bounds: Option<(usize, usize)>,
+    /// The persistent storage of the pending transactions, if any.
+    journal: Option<Box<dyn TransactionJournal<T>>>,
     _phantom: PhantomData<T>,
 }
 
@@ -88,14 +180,31 @@ where
     Standard: Distribution<N>,
 {
     /// Returns a new `QueueingHoneyBadgerBuilder` wrapping the given instance of
-    /// `DynamicHoneyBadger`.
-    pub fn new(dyn_hb: DynamicHoneyBadger<Vec<T>, N>) -> Self {
+    /// `DynamicHoneyBadger`, with an empty default queue.
+    pub fn new(dyn_hb: DynamicHoneyBadger<Vec<T>, N>) -> Self
+    where
+        Q: Default,
+    {
+        Self::with_queue(dyn_hb, Q::default())
+    }
+
+    /// Returns a new `QueueingHoneyBadgerBuilder` wrapping the given instance of
+    /// `DynamicHoneyBadger`, with the given transaction queue. Unlike `new`, this doesn't require
+    /// the queue to implement `Default`, so it can be used with an existing mempool.
+    pub fn with_queue(dyn_hb: DynamicHoneyBadger<Vec<T>, N>, queue: Q) -> Self {
         // TODO: Use the defaults from `HoneyBadgerBuilder`.
         QueueingHoneyBadgerBuilder {
             dyn_hb,
             batch_size: 100,
-            queue: Default::default(),
+            queue,
             step: None,
+            wake_on_first_proposal: false,
+            proposal_strategy: ProposalStrategy::Random,
+            queue_capacity: None,
+            transaction_ttl: None,
+            expiry: None,
+            batch_size_bounds: None,
+            journal: None,
             _phantom: PhantomData,
         }
     }
@@ -112,6 +221,73 @@ where
         self
     }
 
+    /// Sets the bounds within which the batch size is adjusted to the number of pending
+    /// transactions. The batch size set with `batch_size` is the initial value. By default, the
+    /// batch size is fixed.
+    pub fn adaptive_batch_size(mut self, min_batch_size: usize, max_batch_size: usize) -> Self {
+        self.batch_size_bounds = Some((min_batch_size, max_batch_size));
+        self
+    }
+
+    /// Sets whether an idle node without pending transactions proposes as soon as it has received
+    /// any other validator's proposal for the current epoch, instead of waiting for _f + 1_ of
+    /// them. The default is `false`.
+    pub fn wake_on_first_proposal(mut self, wake_on_first_proposal: bool) -> Self {
+        self.wake_on_first_proposal = wake_on_first_proposal;
+        self
+    }
+
+    /// Sets how the transactions for our proposals are chosen. The default is
+    /// `ProposalStrategy::Random`.
+    pub fn proposal_strategy(mut self, proposal_strategy: ProposalStrategy) -> Self {
+        self.proposal_strategy = proposal_strategy;
+        self
+    }
+
+    /// Sets the maximum number of pending transactions. If the queue is full, `push_transaction`
+    /// returns `Error::QueueFull`, so that the application can apply backpressure. The same applies
+    /// to the initial transactions in `build_with_transactions`. By default, the queue is
+    /// unbounded.
+    pub fn queue_capacity(mut self, queue_capacity: usize) -> Self {
+        self.queue_capacity = Some(queue_capacity);
+        self
+    }
+
+    /// Sets the number of epochs after which a transaction that is still pending is dropped from
+    /// the queue. By default, transactions never expire.
+    pub fn transaction_ttl(mut self, transaction_ttl: u64) -> Self {
+        self.transaction_ttl = Some(transaction_ttl);
+        self
+    }
+
+    /// Sets a hook that decides whether a transaction has expired, given a batch's epoch and
+    /// timestamp. The transactions for which it returns `true` are excluded from the output batch,
+    /// and pending transactions are dropped from the queue before we propose if it returns `true`
+    /// for the epoch of the proposal and the timestamp of the latest batch.
+    pub fn expiry<F>(mut self, expiry: F) -> Self
+    where
+        F: Fn(&T, u64, Option<u64>) -> bool + Send + Sync + 'static,
+    {
+        self.expiry = Some(Box::new(expiry));
+        self
+    }
+
+    /// Sets the persistent storage of the pending transactions. The stored transactions are loaded
+    /// into the queue when the instance is built.
+    pub fn journal<J>(mut self, journal: J) -> Self
+    where
+        J: TransactionJournal<T> + 'static,
+    {
+        self.journal = Some(Box::new(journal));
+        self
+    }
+
+    /// Sets the receiver of instrumentation events for the managed `DynamicHoneyBadger` instance.
+    pub fn instrument(mut self, instrument: Arc<dyn Instrument<N>>) -> Self {
+        self.dyn_hb.set_instrument(instrument);
+        self
+    }
+
     /// Sets the transaction queue object.
     pub fn queue(mut self, queue: Q) -> Self {
         self.queue = queue;
@@ -125,8 +301,10 @@ where
 
     /// Returns a new Queueing Honey Badger instance that starts with the given transactions in its
     /// buffer.
+    ///
+    /// Returns `Error::QueueFull` if there are more transactions than `queue_capacity`.
     pub fn build_with_transactions<TI, R>(
-        mut self,
+        self,
         txs: TI,
         rng: &mut R,
     ) -> Result<QueueingHoneyBadgerWithStep<T, N, Q>>
@@ -134,12 +312,34 @@ where
         TI: IntoIterator<Item = T>,
         R: Rng,
     {
-        self.queue.extend(txs);
+        let batch_size = match self.batch_size_bounds {
+            Some((min, max)) => cmp::max(min, cmp::min(max, self.batch_size)),
+            None => self.batch_size,
+        };
         let mut qhb = QueueingHoneyBadger {
             dyn_hb: self.dyn_hb,
-            batch_size: self.batch_size,
+            batch_size,
             queue: self.queue,
+            wake_on_first_proposal: self.wake_on_first_proposal,
+            proposal_strategy: self.proposal_strategy,
+            queue_capacity: self.queue_capacity,
+            transaction_ttl: self.transaction_ttl,
+            expiry: self.expiry,
+            pending_since: HashMap::new(),
+            timestamp: None,
+            batch_size_bounds: self.batch_size_bounds,
+            journal: self.journal,
         };
+        if let Some(journal) = qhb.journal.as_ref() {
+            for tx in journal.transactions().map_err(Error::Journal)? {
+                qhb.enqueue(tx);
+            }
+        }
+        for tx in txs {
+            qhb.check_capacity()?;
+            qhb.journal_put(&tx)?;
+            qhb.enqueue(tx);
+        }
         let mut step = qhb.propose(rng)?;
         if let Some(dhb_step) = self.step {
             step.extend(dhb_step);
@@ -159,6 +359,26 @@ pub struct QueueingHoneyBadger<T, N: Ord, Q> {
     dyn_hb: DynamicHoneyBadger<Vec<T>, N>,
     /// The queue of pending transactions that haven't been output in a batch yet.
     queue: Q,
+    /// Whether to propose as soon as any other validator has proposed.
+    wake_on_first_proposal: bool,
+    /// How we choose the transactions we propose.
+    proposal_strategy: ProposalStrategy,
+    /// The maximum number of pending transactions, if any.
+    queue_capacity: Option<usize>,
+    /// The number of epochs after which a pending transaction is dropped, if any.
+    transaction_ttl: Option<u64>,
+    /// The hook that decides whether a transaction has expired, if any.
+    #[derivative(Debug = "ignore")]
+    expiry: Option<ExpiryHook<T>>,
+    /// The epochs in which the pending transactions were added, if they can expire.
+    pending_since: HashMap<T, u64>,
+    /// The timestamp of the latest batch, if any, which is passed to the expiry hook.
+    timestamp: Option<u64>,
+    /// The minimum and maximum batch size, if it is adjusted to the queue length.
+    batch_size_bounds: Option<(usize, usize)>,
+    /// The persistent storage of the pending transactions, if any.
+    #[derivative(Debug = "ignore")]
+    journal: Option<Box<dyn TransactionJournal<T>>>,
 }
 
 /// A `QueueingHoneyBadger` step, possibly containing multiple outputs.
@@ -215,21 +435,36 @@ where
 {
     /// Returns a new `QueueingHoneyBadgerBuilder` configured to use the node IDs and cryptographic
     /// keys specified by `netinfo`.
-    pub fn builder(dyn_hb: DynamicHoneyBadger<Vec<T>, N>) -> QueueingHoneyBadgerBuilder<T, N, Q> {
+    pub fn builder(dyn_hb: DynamicHoneyBadger<Vec<T>, N>) -> QueueingHoneyBadgerBuilder<T, N, Q>
+    where
+        Q: Default,
+    {
         QueueingHoneyBadgerBuilder::new(dyn_hb)
     }
 
+    /// Returns a new `QueueingHoneyBadgerBuilder` that uses the given transaction queue, e.g. an
+    /// application's own mempool.
+    pub fn builder_with_queue(
+        dyn_hb: DynamicHoneyBadger<Vec<T>, N>,
+        queue: Q,
+    ) -> QueueingHoneyBadgerBuilder<T, N, Q> {
+        QueueingHoneyBadgerBuilder::with_queue(dyn_hb, queue)
+    }
+
     /// Creates a new `QueueingHoneyBadgerBuilder` for joining the network specified in the
     /// `JoinPlan`.
     ///
     /// Returns a `QueueingHoneyBadgerBuilder` or an error if creation of the managed
     /// `DynamicHoneyBadger` instance has failed.
-    pub fn builder_joining<R: Rng>(
+    pub fn builder_joining<S: IntoSigner, R: Rng>(
         our_id: N,
-        secret_key: SecretKey,
+        secret_key: S,
         join_plan: JoinPlan<N>,
         rng: &mut R,
-    ) -> Result<QueueingHoneyBadgerBuilder<T, N, Q>> {
+    ) -> Result<QueueingHoneyBadgerBuilder<T, N, Q>>
+    where
+        Q: Default,
+    {
         let (dhb, step) = DynamicHoneyBadger::new_joining(our_id, secret_key, join_plan, rng)
             .map_err(Error::NewJoining)?;
         Ok(QueueingHoneyBadgerBuilder::new(dhb).step(step))
@@ -243,11 +478,86 @@ where
     /// If no proposal has yet been made for the current epoch, this may trigger one. In this case,
     /// a nonempty step will returned, with the corresponding messages. (Or, if we are the only
     /// validator, even with the completed batch as an output.)
+    ///
+    /// Returns `Error::QueueFull` if the queue already contains `queue_capacity` transactions.
     pub fn push_transaction<R: Rng>(&mut self, tx: T, rng: &mut R) -> Result<Step<T, N>> {
-        self.queue.extend(iter::once(tx));
+        self.check_capacity()?;
+        self.journal_put(&tx)?;
+        self.enqueue(tx);
         self.propose(rng)
     }
 
+    /// Returns `Error::QueueFull` if the queue already contains `queue_capacity` transactions.
+    fn check_capacity(&self) -> Result<()> {
+        match self.queue_capacity {
+            Some(capacity) if self.queue.len() >= capacity => Err(Error::QueueFull),
+            _ => Ok(()),
+        }
+    }
+
+    /// Stores the transaction in the journal, if any.
+    fn journal_put(&mut self, tx: &T) -> Result<()> {
+        match self.journal.as_mut() {
+            Some(journal) => journal.put(tx).map_err(Error::Journal),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the transactions from the journal, if any.
+    fn journal_remove<'a, I>(&mut self, txs: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a,
+    {
+        if let Some(journal) = self.journal.as_mut() {
+            for tx in txs {
+                journal.remove(tx).map_err(Error::Journal)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a transaction to the queue, and records its epoch if it can expire.
+    fn enqueue(&mut self, tx: T) {
+        if self.transaction_ttl.is_some() || self.expiry.is_some() {
+            let epoch = self.dyn_hb.next_epoch();
+            self.pending_since.entry(tx.clone()).or_insert(epoch);
+        }
+        self.queue.extend(iter::once(tx));
+    }
+
+    /// Removes the pending transactions for which `f` returns `true`, e.g. because the client
+    /// cancelled or superseded them, and returns them.
+    ///
+    /// Transactions that we already proposed in an ongoing epoch can still be committed, as can
+    /// those other validators propose.
+    pub fn remove_transactions<F>(&mut self, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut removed = Vec::new();
+        self.queue.retain(|tx| {
+            if f(tx) {
+                removed.push(tx.clone());
+                false
+            } else {
+                true
+            }
+        });
+        for tx in &removed {
+            self.pending_since.remove(tx);
+        }
+        self.journal_remove(&removed)?;
+        Ok(removed)
+    }
+
+    /// Removes the given transaction from the queue, if it is pending, and returns `true` if it
+    /// was. See `remove_transactions`.
+    pub fn remove_transaction(&mut self, tx: &T) -> Result<bool> {
+        let removed = self.remove_transactions(|pending| pending == tx)?;
+        Ok(!removed.is_empty())
+    }
+
     /// Casts a vote to change the set of validators.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -256,6 +566,14 @@ where
         self.apply(|dyn_hb, _| dyn_hb.vote_for(change), rng)
     }
 
+    /// Retracts our vote, without voting for another change.
+    ///
+    /// This stores a pending vote that revokes our previous one. Once it is included in some future
+    /// batch, the previous vote doesn't count anymore.
+    pub fn retract_vote<R: Rng>(&mut self, rng: &mut R) -> Result<Step<T, N>> {
+        self.apply(|dyn_hb, _| dyn_hb.retract_vote(), rng)
+    }
+
     /// Casts a vote to add a node as a validator.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -269,6 +587,19 @@ where
         self.apply(|dyn_hb, _| dyn_hb.vote_to_add(node_id, pub_key), rng)
     }
 
+    /// Casts a vote to add all the given nodes as validators.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, all nodes are added in a single
+    /// key generation.
+    pub fn vote_to_add_all<R: Rng>(
+        &mut self,
+        added: BTreeMap<N, PublicKey>,
+        rng: &mut R,
+    ) -> Result<Step<T, N>> {
+        self.apply(|dyn_hb, _| dyn_hb.vote_to_add_all(added), rng)
+    }
+
     /// Casts a vote to demote a validator to observer.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -277,6 +608,21 @@ where
         self.apply(|dyn_hb, _| dyn_hb.vote_to_remove(node_id), rng)
     }
 
+    /// Casts a vote to demote the `removed` validators to observers and add the `added` nodes as
+    /// validators, all at once.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, it will take effect in a single
+    /// key generation.
+    pub fn vote_to_replace<R: Rng>(
+        &mut self,
+        removed: &BTreeSet<N>,
+        added: BTreeMap<N, PublicKey>,
+        rng: &mut R,
+    ) -> Result<Step<T, N>> {
+        self.apply(|dyn_hb, _| dyn_hb.vote_to_replace(removed, added), rng)
+    }
+
     /// Handles a message received from `sender_id`.
     ///
     /// This must be called with every message we receive from another node.
@@ -292,6 +638,17 @@ where
         )
     }
 
+    /// Restores the key generation from a checkpoint taken before a restart in the current era.
+    ///
+    /// See `DynamicHoneyBadger::restore_key_gen`.
+    pub fn restore_key_gen<R: Rng>(
+        &mut self,
+        checkpoint: KeyGenCheckpoint<N>,
+        rng: &mut R,
+    ) -> Result<Step<T, N>> {
+        self.apply(|dyn_hb, rng| dyn_hb.restore_key_gen(checkpoint, rng), rng)
+    }
+
     /// Returns a reference to the internal managed `DynamicHoneyBadger` instance.
     pub fn dyn_hb(&self) -> &DynamicHoneyBadger<Vec<T>, N> {
         &self.dyn_hb
@@ -307,6 +664,16 @@ where
         &self.queue
     }
 
+    /// Returns the number of pending transactions in the queue.
+    pub fn queue_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns the current target number of transactions per batch.
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
     /// Applies a function `f` to the `DynamicHoneyBadger` instance and processes the step.
     fn apply<R, F>(&mut self, f: F, rng: &mut R) -> Result<Step<T, N>>
     where
@@ -316,12 +683,49 @@ where
         ) -> dynamic_honey_badger::Result<Step<T, N>>,
         R: Rng,
     {
-        let step = f(&mut self.dyn_hb, rng).map_err(Error::Input)?;
+        let mut step = f(&mut self.dyn_hb, rng).map_err(Error::Input)?;
         self.queue
             .remove_multiple(step.output.iter().flat_map(Batch::iter));
+        for batch in &mut step.output {
+            for tx in batch.iter() {
+                self.pending_since.remove(tx);
+            }
+            self.journal_remove(batch.iter())?;
+            if let Some(expiry) = self.expiry.as_ref() {
+                let (epoch, timestamp) = (batch.epoch(), batch.timestamp());
+                batch.retain_txs(|tx| !expiry(tx, epoch, timestamp));
+            }
+        }
+        if let Some(batch) = step.output.last() {
+            self.timestamp = batch.timestamp();
+        }
         Ok(step.join(self.propose(rng)?))
     }
 
+    /// Removes the pending transactions that have expired by the given epoch, i.e. that must not be
+    /// proposed in it.
+    fn remove_expired(&mut self, epoch: u64) -> Result<()> {
+        let (ttl, timestamp) = (self.transaction_ttl, self.timestamp);
+        let expiry = self.expiry.as_ref();
+        let expired: Vec<T> = self
+            .pending_since
+            .iter()
+            .filter(|(tx, since)| {
+                let too_old = match ttl {
+                    Some(ttl) => epoch > *since + ttl,
+                    None => false,
+                };
+                too_old || expiry.iter().any(|expiry| expiry(tx, epoch, timestamp))
+            })
+            .map(|(tx, _)| tx.clone())
+            .collect();
+        self.queue.remove_multiple(&expired);
+        for tx in &expired {
+            self.pending_since.remove(tx);
+        }
+        self.journal_remove(&expired)
+    }
+
     /// Returns the epoch of the next batch that will be output.
     pub fn next_epoch(&self) -> u64 {
         self.dyn_hb.next_epoch()
@@ -334,15 +738,63 @@ where
         if self.dyn_hb.has_input() {
             return false; // Previous epoch is still in progress.
         }
+        if self.wake_on_first_proposal && self.dyn_hb.honey_badger().received_proposals() > 0 {
+            return true; // Another validator woke the network up.
+        }
         !self.queue.is_empty() || self.dyn_hb.should_propose()
     }
 
+    /// Doubles the batch size if more than a batch is pending, and halves it if less than half a
+    /// batch is, within the configured bounds, if any.
+    fn adapt_batch_size(&mut self) {
+        if let Some((min, max)) = self.batch_size_bounds {
+            let pending = self.queue.len();
+            if pending > self.batch_size {
+                self.batch_size = cmp::min(max, self.batch_size.saturating_mul(2));
+            } else if pending < self.batch_size / 2 {
+                self.batch_size = cmp::max(min, self.batch_size / 2);
+            }
+        }
+    }
+
+    /// Returns `amount` of the candidates, preferring the ones whose hash is assigned to us in the
+    /// next epoch.
+    fn partition(&self, candidates: Vec<T>, amount: usize) -> Vec<T> {
+        let netinfo = self.dyn_hb.netinfo();
+        let our_idx = match netinfo.node_index(netinfo.our_id()) {
+            Some(our_idx) => our_idx,
+            None => return candidates.into_iter().take(amount).collect(),
+        };
+        let epoch = self.dyn_hb.next_epoch();
+        let num_nodes = netinfo.num_nodes() as u64;
+        let is_ours = |tx: &T| match bincode::serialize(&(epoch, tx)) {
+            Ok(bytes) => BigEndian::read_u64(&sha3_256(&bytes)) % num_nodes == our_idx as u64,
+            Err(_) => false,
+        };
+        let (mut chosen, others): (Vec<T>, Vec<T>) = candidates.into_iter().partition(is_ours);
+        chosen.truncate(amount);
+        let missing = amount - chosen.len();
+        chosen.extend(others.into_iter().take(missing));
+        chosen
+    }
+
     /// Initiates the next epoch by proposing a batch from the queue.
     fn propose<R: Rng>(&mut self, rng: &mut R) -> Result<Step<T, N>> {
         let mut step = Step::default();
+        if self.can_propose() && !self.pending_since.is_empty() {
+            let epoch = self.dyn_hb.next_epoch();
+            self.remove_expired(epoch)?;
+        }
         while self.can_propose() {
+            self.adapt_batch_size();
             let amount = cmp::max(1, self.batch_size / self.dyn_hb.netinfo().num_nodes());
-            let proposal = self.queue.choose(rng, amount, self.batch_size);
+            let proposal = match self.proposal_strategy {
+                ProposalStrategy::Random => self.queue.choose(rng, amount, self.batch_size),
+                ProposalStrategy::HashPartitioned => {
+                    let candidates = self.queue.choose(rng, self.batch_size, self.batch_size);
+                    self.partition(candidates, amount)
+                }
+            };
             step.extend(
                 self.dyn_hb
                     .handle_input(Input::User(proposal), rng)
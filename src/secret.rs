@@ -0,0 +1,79 @@
+//! Clearing of secret values from memory.
+//!
+//! `threshold_crypto` already overwrites `SecretKey`, `SecretKeyShare`, `SecretKeySet` and
+//! secret polynomials with zeros when they are dropped. The key generation additionally handles
+//! secret field elements and serialized secrets, e.g. the decrypted rows and values of `Part` and
+//! `Ack` messages. These are wrapped in the types in this module, which clear them on drop, so that
+//! a long-running node doesn't leave them behind in memory that could end up in a dump or in swap.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroizing;
+
+use crate::crypto::serde_impl::FieldWrap;
+use crate::crypto::{Fr, SecretKey};
+
+/// Serialized secret data, which is overwritten with zeros when dropped.
+pub(crate) type SecretBytes = Zeroizing<Vec<u8>>;
+
+/// Overwrites the field element with zeros.
+///
+/// `threshold_crypto`'s `SecretKey::from_mut` moves the value to the heap and clears the original;
+/// the `SecretKey` is then dropped, which clears the heap copy, too.
+pub(crate) fn clear_fr(fr: &mut Fr) {
+    drop(SecretKey::from_mut(fr));
+}
+
+/// A secret field element, which is overwritten with zeros when dropped, and not printed in debug
+/// output.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct SecretFr(pub(crate) Fr);
+
+impl Drop for SecretFr {
+    fn drop(&mut self) {
+        clear_fr(&mut self.0);
+    }
+}
+
+impl fmt::Debug for SecretFr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretFr(..)")
+    }
+}
+
+impl Serialize for SecretFr {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        FieldWrap(&self.0).serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretFr {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let mut wrap = FieldWrap::<Fr>::deserialize(d)?;
+        let secret = SecretFr(wrap.0);
+        clear_fr(&mut wrap.0);
+        Ok(secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::crypto::IntoFr;
+    use crate::pairing::Field;
+
+    #[test]
+    fn test_clear() {
+        let mut fr = 5.into_fr();
+        clear_fr(&mut fr);
+        assert_eq!(Fr::zero(), fr);
+
+        let secret = SecretFr(5.into_fr());
+        assert_eq!("SecretFr(..)", format!("{:?}", secret));
+        let ser = bincode::serialize(&secret).expect("serialize");
+        let de: SecretFr = bincode::deserialize(&ser).expect("deserialize");
+        assert_eq!(secret, de);
+    }
+}
@@ -1,6 +1,6 @@
 //! Convenience methods for a `SenderQueue` wrapping a `DynamicHoneyBadger`.
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::result;
 
 use crate::crypto::PublicKey;
@@ -14,7 +14,7 @@ use super::{
 use crate::{Contribution, CpStep, NodeIdT};
 
 use crate::dynamic_honey_badger::{
-    Batch, Change, ChangeState, DynamicHoneyBadger, Error as DhbError, JoinPlan,
+    Batch, Change, ChangeState, DynamicHoneyBadger, Error as DhbError, JoinPlan, KeyGenCheckpoint,
     Message as DhbMessage,
 };
 
@@ -54,6 +54,8 @@ impl<N: Ord> SenderQueueableMessage for DhbMessage<N> {
             }
             DhbMessage::KeyGen(era, _, _) => era > them_era,
             DhbMessage::SignedVote(ref signed_vote) => signed_vote.era() > them_era,
+            DhbMessage::Address(era, _, _) => era > them_era,
+            DhbMessage::BatchSignature(era, _, _) => era > them_era,
         }
     }
 
@@ -64,6 +66,9 @@ impl<N: Ord> SenderQueueableMessage for DhbMessage<N> {
             }
             DhbMessage::KeyGen(era, _, _) => era < them_era,
             DhbMessage::SignedVote(ref signed_vote) => signed_vote.era() < them_era,
+            DhbMessage::Address(era, _, _) => era < them_era,
+            // The last batch of an era is signed while the nodes are already in the next one.
+            DhbMessage::BatchSignature(..) => false,
         }
     }
 
@@ -72,6 +77,8 @@ impl<N: Ord> SenderQueueableMessage for DhbMessage<N> {
             DhbMessage::HoneyBadger(era, ref msg) => (era, msg.epoch()),
             DhbMessage::KeyGen(era, _, _) => (era, 0),
             DhbMessage::SignedVote(ref signed_vote) => (signed_vote.era(), 0),
+            DhbMessage::Address(era, _, _) => (era, 0),
+            DhbMessage::BatchSignature(era, epoch, _) => (era, epoch.saturating_sub(era)),
         }
     }
 }
@@ -84,6 +91,17 @@ where
     fn max_future_epochs(&self) -> u64 {
         self.max_future_epochs()
     }
+
+    fn vote_to_remove_banned<R: Rng>(
+        &mut self,
+        id: &N,
+        _rng: &mut R,
+    ) -> result::Result<CpStep<Self>, DhbError> {
+        if !self.netinfo().is_node_validator(id) {
+            return Ok(CpStep::<Self>::default());
+        }
+        self.vote_to_remove(id)
+    }
 }
 
 type Result<C, N> = result::Result<CpStep<SenderQueue<DynamicHoneyBadger<C, N>>>, Error<DhbError>>;
@@ -111,6 +129,14 @@ where
         self.apply(|algo| algo.vote_for(change))
     }
 
+    /// Retracts our vote, without voting for another change.
+    ///
+    /// This stores a pending vote that revokes our previous one. Once it is included in some future
+    /// batch, the previous vote doesn't count anymore.
+    pub fn retract_vote(&mut self) -> Result<C, N> {
+        self.apply(|algo| algo.retract_vote())
+    }
+
     /// Casts a vote to add a node as a validator.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -119,6 +145,15 @@ where
         self.apply(|algo| algo.vote_to_add(node_id, pub_key))
     }
 
+    /// Casts a vote to add all the given nodes as validators.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, all nodes are added in a single
+    /// key generation.
+    pub fn vote_to_add_all(&mut self, added: BTreeMap<N, PublicKey>) -> Result<C, N> {
+        self.apply(|algo| algo.vote_to_add_all(added))
+    }
+
     /// Casts a vote to demote a validator to observer.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -127,6 +162,37 @@ where
         self.apply(|algo| algo.vote_to_remove(node_id))
     }
 
+    /// Casts a vote to demote the `removed` validators to observers and add the `added` nodes as
+    /// validators, all at once.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, it will take effect in a single
+    /// key generation.
+    pub fn vote_to_replace(
+        &mut self,
+        removed: &BTreeSet<N>,
+        added: BTreeMap<N, PublicKey>,
+    ) -> Result<C, N> {
+        self.apply(|algo| algo.vote_to_replace(removed, added))
+    }
+
+    /// Announces our network address, in an application-defined encoding.
+    ///
+    /// The signed announcement will be included in some future batch. Once it is committed, all
+    /// nodes add it to their `NetworkInfo`.
+    pub fn announce_address(&mut self, address: Vec<u8>) -> Result<C, N> {
+        self.apply(|algo| algo.announce_address(address))
+    }
+
+    /// Restores the key generation from a checkpoint taken before a restart in the current era.
+    pub fn restore_key_gen<R: Rng>(
+        &mut self,
+        checkpoint: KeyGenCheckpoint<N>,
+        rng: &mut R,
+    ) -> Result<C, N> {
+        self.apply(|algo| algo.restore_key_gen(checkpoint, rng))
+    }
+
     /// Restarts the managed algorithm with the given join plan with a new list of peers and with
     /// the same secret key. In order to be restarted, the node should have completed the process of
     /// removing itself from the network. The node may not output a batch if it were not properly
@@ -143,7 +209,7 @@ where
         if !self.is_removed {
             return Err(Error::DynamicHoneyBadgerNotRemoved);
         }
-        let secret_key = self.algo().netinfo().secret_key().clone();
+        let secret_key = self.algo().netinfo().signer().clone();
         let id = self.algo().netinfo().our_id().clone();
         let (dhb, dhb_step) =
             DynamicHoneyBadger::new_joining(id.clone(), secret_key, join_plan, rng)
@@ -1,8 +1,7 @@
 use std::collections::BTreeSet;
 
-use serde::{de::DeserializeOwned, Serialize};
-
 use super::{SenderQueueableConsensusProtocol, SenderQueueableMessage, SenderQueueableOutput};
+use crate::canonical::CanonicalContribution;
 use crate::honey_badger::{Batch, HoneyBadger, Message};
 use crate::{Contribution, Epoched, NodeIdT};
 
@@ -38,7 +37,7 @@ impl<N> SenderQueueableMessage for Message<N> {
 
 impl<C, N> Epoched for HoneyBadger<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     type Epoch = u64;
@@ -50,7 +49,7 @@ where
 
 impl<C, N> SenderQueueableConsensusProtocol for HoneyBadger<C, N>
 where
-    C: Contribution + Serialize + DeserializeOwned,
+    C: CanonicalContribution,
     N: NodeIdT,
 {
     fn max_future_epochs(&self) -> u64 {
@@ -4,6 +4,9 @@
 //! messages based on their epochs. A message is sent to its recipient only when the recipient's
 //! epoch matches the epoch of the message. Thus no queueing is required for incoming messages since
 //! any incoming messages with non-matching epochs can be safely discarded.
+//!
+//! The sender queue also keeps a `BanList`: Messages from banned nodes are dropped, and nothing is
+//! queued for them.
 
 mod dynamic_honey_badger;
 mod error;
@@ -14,9 +17,15 @@ mod queueing_honey_badger;
 use rand::Rng;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
+use std::sync::Arc;
+
+use derivative::Derivative;
 
 use log::debug;
 
+use crate::ban_list::BanList;
+use crate::header::Algorithm;
+use crate::instrument::{Instrument, NoInstrument};
 use crate::traits::EpochT;
 use crate::{ConsensusProtocol, CpStep, Epoched, NodeIdT, Target};
 
@@ -66,6 +75,16 @@ pub trait SenderQueueableConsensusProtocol: Epoched + ConsensusProtocol {
     /// The maximum number of subsequent future epochs that the `ConsensusProtocol` is allowed to handle
     /// messages for.
     fn max_future_epochs(&self) -> u64;
+
+    /// Casts a vote to remove the given node, which has been banned, from the set of validators.
+    /// By default, this does nothing, for algorithms that don't support validator set changes.
+    fn vote_to_remove_banned<R: Rng>(
+        &mut self,
+        _id: &Self::NodeId,
+        _rng: &mut R,
+    ) -> Result<CpStep<Self>, Self::Error> {
+        Ok(CpStep::<Self>::default())
+    }
 }
 
 /// A map with outgoing messages, per epoch and per target node.
@@ -80,7 +99,8 @@ pub type OutgoingQueue<D> = BTreeMap<
 /// only when those nodes' epochs match the queued messages' epochs. Thus all nodes can handle
 /// incoming messages without queueing them and can ignore messages whose epochs are not currently
 /// acccepted.
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct SenderQueue<D>
 where
     D: SenderQueueableConsensusProtocol,
@@ -111,6 +131,11 @@ where
     /// `QueueingHoneyBadger`, it can be restarted on receipt of a join plan where this node is a
     /// validator.
     is_removed: bool,
+    /// The nodes whose messages are dropped.
+    ban_list: BanList<D::NodeId>,
+    /// The receiver of instrumentation events for the epoch announcements.
+    #[derivative(Debug = "ignore")]
+    instrument: Arc<dyn Instrument<D::NodeId>>,
 }
 
 /// A `SenderQueue` step. The output corresponds to the wrapped algorithm.
@@ -181,27 +206,74 @@ where
         if self.is_removed {
             return Ok(Step::<D>::default());
         }
-        self.apply(|algo| algo.handle_input(input, rng))
+        let step = self.apply(|algo| algo.handle_input(input, rng))?;
+        self.report_sent(&step);
+        Ok(step)
     }
 
     /// Handles a message received from `sender_id`.
     ///
-    /// This must be called with every message we receive from another node.
+    /// This must be called with every message we receive from another node. Messages from banned
+    /// nodes are dropped. Nodes that reach the ban list's maximum number of faults are banned, and
+    /// if the ban list is configured to, we vote for their removal.
     pub fn handle_message<R: Rng>(
         &mut self,
         sender_id: &D::NodeId,
         message: Message<D::Message>,
         rng: &mut R,
     ) -> Result<CpStep<Self>, Error<D::Error>> {
-        if self.is_removed {
+        if self.is_removed || self.ban_list.is_banned(sender_id) {
             return Ok(Step::<D>::default());
         }
-        match message {
-            Message::EpochStarted(epoch) => Ok(self.handle_epoch_started(sender_id, epoch)),
-            Message::Algo(msg) => self.handle_message_content(sender_id, msg, rng),
+        let mut step = match message {
+            Message::EpochStarted(epoch) => {
+                self.instrument
+                    .message_received(sender_id, Algorithm::SenderQueue, 0);
+                self.handle_epoch_started(sender_id, epoch)
+            }
+            Message::Algo(msg) => self.handle_message_content(sender_id, msg, rng)?,
+        };
+        for id in self.ban_list.record_faults(&step.fault_log) {
+            self.outgoing_queue.remove(&id);
+            if self.ban_list.vote_to_remove() {
+                step.extend(self.apply(|algo| algo.vote_to_remove_banned(&id, rng))?);
+            }
+        }
+        self.report_sent(&step);
+        Ok(step)
+    }
+
+    /// Reports the epoch announcements we send to the instrument. The wrapped algorithm's messages
+    /// are reported by the algorithm itself, when they are created, even if they are queued here.
+    fn report_sent(&self, step: &CpStep<Self>) {
+        for msg in &step.messages {
+            if let Message::EpochStarted(_) = msg.message {
+                self.instrument
+                    .message_sent(&msg.target, Algorithm::SenderQueue, 0);
+            }
         }
     }
 
+    /// Returns the list of banned nodes.
+    pub fn ban_list(&self) -> &BanList<D::NodeId> {
+        &self.ban_list
+    }
+
+    /// Bans the given node: All further messages from it are dropped, and no more messages are
+    /// queued for it. Returns `false` if it was already banned.
+    ///
+    /// This doesn't vote for the node's removal, even if the ban list is configured to do so for
+    /// automatic bans.
+    pub fn ban(&mut self, id: D::NodeId) -> bool {
+        self.outgoing_queue.remove(&id);
+        self.ban_list.ban(id)
+    }
+
+    /// Lifts the ban on the given node. Returns `false` if it was not banned.
+    pub fn unban(&mut self, id: &D::NodeId) -> bool {
+        self.ban_list.unban(id)
+    }
+
     /// Returns an immutable reference to the wrapped algorithm.
     pub fn inner(&self) -> &D {
         &self.algo
@@ -336,6 +408,9 @@ where
         let max_future_epochs = self.algo.max_future_epochs();
         // Append the deferred messages onto the queues.
         for (id, message) in step.defer_messages(&self.peer_epochs, max_future_epochs) {
+            if self.ban_list.is_banned(&id) {
+                continue;
+            }
             self.outgoing_queue
                 .entry(id)
                 .or_default()
@@ -406,6 +481,8 @@ where
 {
     algo: D,
     peer_epochs: BTreeMap<D::NodeId, D::Epoch>,
+    ban_list: BanList<D::NodeId>,
+    instrument: Arc<dyn Instrument<D::NodeId>>,
 }
 
 impl<D> SenderQueueBuilder<D>
@@ -423,9 +500,24 @@ where
         SenderQueueBuilder {
             algo,
             peer_epochs: peer_ids.map(|id| (id, D::Epoch::default())).collect(),
+            ban_list: BanList::new(),
+            instrument: Arc::new(NoInstrument),
         }
     }
 
+    /// Sets the list of banned nodes, e.g. one that was persisted before a restart.
+    pub fn ban_list(mut self, ban_list: BanList<D::NodeId>) -> Self {
+        self.ban_list = ban_list;
+        self
+    }
+
+    /// Sets the receiver of the instrumentation events for the epoch announcements. The wrapped
+    /// algorithm needs to be configured with its own instrument.
+    pub fn instrument(mut self, instrument: Arc<dyn Instrument<D::NodeId>>) -> Self {
+        self.instrument = instrument;
+        self
+    }
+
     /// Sets the peer epochs.
     pub fn peer_epochs(mut self, peer_epochs: BTreeMap<D::NodeId, D::Epoch>) -> Self {
         self.peer_epochs = peer_epochs;
@@ -443,8 +535,11 @@ where
             last_epochs: BTreeMap::new(),
             participants_after_change: BTreeSet::new(),
             is_removed: false,
+            ban_list: self.ban_list,
+            instrument: self.instrument,
         };
         let step = Target::All.message(Message::EpochStarted(epoch)).into();
+        sq.report_sent(&step);
         (sq, step)
     }
 }
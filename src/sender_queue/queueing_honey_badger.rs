@@ -1,5 +1,6 @@
 //! Convenience methods for a `SenderQueue` wrapping a `QueueingHoneyBadger`.
 
+use std::collections::BTreeMap;
 use std::result;
 
 use crate::crypto::PublicKey;
@@ -8,6 +9,7 @@ use rand::Rng;
 use serde::{de::DeserializeOwned, Serialize};
 
 use super::{Error, SenderQueue, SenderQueueableConsensusProtocol};
+use crate::dynamic_honey_badger::KeyGenCheckpoint;
 use crate::queueing_honey_badger::{Change, Error as QhbError, QueueingHoneyBadger};
 use crate::transaction_queue::TransactionQueue;
 use crate::{Contribution, CpStep, Epoched, NodeIdT};
@@ -36,6 +38,17 @@ where
     fn max_future_epochs(&self) -> u64 {
         self.dyn_hb().max_future_epochs()
     }
+
+    fn vote_to_remove_banned<R: Rng>(
+        &mut self,
+        id: &N,
+        rng: &mut R,
+    ) -> result::Result<CpStep<Self>, QhbError> {
+        if !self.netinfo().is_node_validator(id) {
+            return Ok(CpStep::<Self>::default());
+        }
+        self.vote_to_remove(id, rng)
+    }
 }
 
 type Result<T, N, Q> =
@@ -81,6 +94,19 @@ where
         self.apply(|algo| algo.vote_to_add(node_id, pub_key, rng))
     }
 
+    /// Casts a vote to add all the given nodes as validators.
+    ///
+    /// This stores a pending vote for the change. It will be included in some future batch, and
+    /// once enough validators have been voted for the same change, all nodes are added in a single
+    /// key generation.
+    pub fn vote_to_add_all<R: Rng>(
+        &mut self,
+        added: BTreeMap<N, PublicKey>,
+        rng: &mut R,
+    ) -> Result<T, N, Q> {
+        self.apply(|algo| algo.vote_to_add_all(added, rng))
+    }
+
     /// Casts a vote to demote a validator to observer.
     ///
     /// This stores a pending vote for the change. It will be included in some future batch, and
@@ -88,4 +114,13 @@ where
     pub fn vote_to_remove<R: Rng>(&mut self, node_id: &N, rng: &mut R) -> Result<T, N, Q> {
         self.apply(|algo| algo.vote_to_remove(node_id, rng))
     }
+
+    /// Restores the key generation from a checkpoint taken before a restart in the current era.
+    pub fn restore_key_gen<R: Rng>(
+        &mut self,
+        checkpoint: KeyGenCheckpoint<N>,
+        rng: &mut R,
+    ) -> Result<T, N, Q> {
+        self.apply(|algo| algo.restore_key_gen(checkpoint, rng))
+    }
 }
@@ -0,0 +1,112 @@
+//! Operations with a node's secret keys.
+//!
+//! The algorithms never use a node's secret key or secret key share directly, but only through
+//! the `Signer` and `ShareSigner` in its `NetworkInfo`. These are implemented by `SecretKey` and
+//! `SecretKeyShare`, but an application can implement them itself, e.g. to keep the keys in a
+//! hardware security module, so that they never have to be held in the process's memory.
+//!
+//! The operations are synchronous: An implementation that forwards them to a device or another
+//! process has to block until it responds.
+
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use crate::crypto::{
+    hash_g2, Ciphertext, DecryptionShare, PublicKey, PublicKeyShare, SecretKey, SecretKeyShare,
+    Signature, SignatureShare, G2,
+};
+
+/// The operations with a node's secret key, for signing and decrypting messages.
+pub trait Signer: Debug + Send + Sync {
+    /// Returns the public key that belongs to the secret key.
+    fn public_key(&self) -> PublicKey;
+
+    /// Signs the document with the secret key.
+    fn sign(&self, doc: &[u8]) -> Signature;
+
+    /// Decrypts the ciphertext, or returns `None` if it wasn't encrypted to our public key.
+    fn decrypt(&self, ct: &Ciphertext) -> Option<Vec<u8>>;
+
+    /// Returns the secret key itself if it is held in memory, or `None` if it isn't available.
+    fn secret_key(&self) -> Option<&SecretKey> {
+        None
+    }
+}
+
+/// The operations with a validator's secret key share, for threshold signatures and decryption.
+pub trait ShareSigner: Debug + Send + Sync {
+    /// Returns the public key share that belongs to the secret key share.
+    fn public_key_share(&self) -> PublicKeyShare;
+
+    /// Signs the hash of a document, i.e. the result of `hash_g2`, with the secret key share.
+    fn sign_g2(&self, hash: G2) -> SignatureShare;
+
+    /// Signs the document with the secret key share.
+    fn sign_share(&self, doc: &[u8]) -> SignatureShare {
+        self.sign_g2(hash_g2(doc))
+    }
+
+    /// Returns our decryption share of the ciphertext. The ciphertext must already be verified.
+    fn decrypt_share(&self, ct: &Ciphertext) -> DecryptionShare;
+
+    /// Returns the secret key share itself if it is held in memory, or `None` if it isn't
+    /// available. Resharing the keys requires it.
+    fn secret_key_share(&self) -> Option<&SecretKeyShare> {
+        None
+    }
+}
+
+impl Signer for SecretKey {
+    fn public_key(&self) -> PublicKey {
+        SecretKey::public_key(self)
+    }
+
+    fn sign(&self, doc: &[u8]) -> Signature {
+        SecretKey::sign(self, doc)
+    }
+
+    fn decrypt(&self, ct: &Ciphertext) -> Option<Vec<u8>> {
+        SecretKey::decrypt(self, ct)
+    }
+
+    fn secret_key(&self) -> Option<&SecretKey> {
+        Some(self)
+    }
+}
+
+impl ShareSigner for SecretKeyShare {
+    fn public_key_share(&self) -> PublicKeyShare {
+        SecretKeyShare::public_key_share(self)
+    }
+
+    fn sign_g2(&self, hash: G2) -> SignatureShare {
+        SecretKeyShare::sign_g2(self, hash)
+    }
+
+    fn decrypt_share(&self, ct: &Ciphertext) -> DecryptionShare {
+        self.decrypt_share_no_verify(ct)
+    }
+
+    fn secret_key_share(&self) -> Option<&SecretKeyShare> {
+        Some(self)
+    }
+}
+
+/// Conversion into a shared `Signer`, e.g. from a `SecretKey` or from the `Arc` returned by
+/// `NetworkInfo::signer`.
+pub trait IntoSigner {
+    /// Returns the signer as a shared trait object.
+    fn into_signer(self) -> Arc<dyn Signer>;
+}
+
+impl<S: Signer + 'static> IntoSigner for S {
+    fn into_signer(self) -> Arc<dyn Signer> {
+        Arc::new(self)
+    }
+}
+
+impl IntoSigner for Arc<dyn Signer> {
+    fn into_signer(self) -> Arc<dyn Signer> {
+        self
+    }
+}
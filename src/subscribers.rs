@@ -0,0 +1,92 @@
+//! Subscriptions to the output of an algorithm.
+//!
+//! Instead of taking the output from every step, an application can register hooks that are called
+//! with each output, or subscribe to a channel that receives a copy of it. This way, the code that
+//! consumes the batches doesn't need to own the message loop.
+
+use std::fmt;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+
+/// A hook that returns `false` once it doesn't need to be called anymore.
+type Hook<T> = Box<dyn FnMut(&T) -> bool + Send + Sync>;
+
+/// The registered hooks and channels.
+pub(crate) struct Subscribers<T> {
+    hooks: Vec<Hook<T>>,
+}
+
+impl<T> Default for Subscribers<T> {
+    fn default() -> Self {
+        Subscribers { hooks: Vec::new() }
+    }
+}
+
+impl<T> fmt::Debug for Subscribers<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Subscribers({})", self.hooks.len())
+    }
+}
+
+impl<T> Subscribers<T> {
+    /// Registers a hook that is called with each output.
+    pub(crate) fn add_hook<F>(&mut self, mut hook: F)
+    where
+        F: FnMut(&T) + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(move |output| {
+            hook(output);
+            true
+        }));
+    }
+
+    /// Returns a receiver for copies of each output. It is unsubscribed once it is dropped.
+    pub(crate) fn subscribe(&mut self) -> Receiver<T>
+    where
+        T: Clone + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        // The sender is not `Sync`, so it needs to be wrapped in a mutex.
+        let sender = Mutex::new(sender);
+        self.hooks
+            .push(Box::new(move |output: &T| match sender.lock() {
+                Ok(sender) => sender.send(output.clone()).is_ok(),
+                Err(_) => false,
+            }));
+        receiver
+    }
+
+    /// Passes the output to all hooks and channels, and drops the closed channels.
+    pub(crate) fn notify(&mut self, output: &T) {
+        let mut hooks = Vec::with_capacity(self.hooks.len());
+        for mut hook in self.hooks.drain(..) {
+            if hook(output) {
+                hooks.push(hook);
+            }
+        }
+        self.hooks = hooks;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Subscribers;
+
+    #[test]
+    fn test_subscribers() {
+        let mut subscribers = Subscribers::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        subscribers.add_hook(move |output: &u8| hook_seen.lock().expect("lock").push(*output));
+        let receiver = subscribers.subscribe();
+        subscribers.notify(&1);
+        subscribers.notify(&2);
+        assert_eq!(vec![1, 2], receiver.try_iter().collect::<Vec<_>>());
+        drop(receiver);
+        subscribers.notify(&3);
+        assert_eq!(1, subscribers.hooks.len());
+        assert_eq!(vec![1, 2, 3], *seen.lock().expect("lock"));
+    }
+}
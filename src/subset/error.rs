@@ -4,6 +4,7 @@ use std::result;
 
 use crate::binary_agreement;
 use crate::broadcast;
+use crate::NetworkInfoError;
 
 /// A subset error.
 #[derive(Clone, PartialEq, Debug, Fail)]
@@ -23,6 +24,9 @@ pub enum Error {
     /// Unknown proposer.
     #[fail(display = "Unknown proposer ID")]
     UnknownProposer,
+    /// The `NetworkInfo`'s keys are inconsistent.
+    #[fail(display = "Invalid network info: {}", _0)]
+    InvalidNetworkInfo(NetworkInfoError),
 }
 
 /// A subset result.
@@ -37,4 +41,7 @@ pub enum FaultKind {
     /// `Subset` received a faulty Binary Agreement message.
     #[fail(display = "`Subset` received a faulty Binary Agreement message.")]
     BaFault(binary_agreement::FaultKind),
+    /// The proposer's value does not satisfy the validity predicate.
+    #[fail(display = "The proposed value does not satisfy the validity predicate.")]
+    InvalidProposal,
 }
@@ -22,6 +22,20 @@
 //! remaining ones, where we haven't provided input yet.
 //! * Once all `BinaryAgreement` instances have decided, `Subset` returns the set of all proposed
 //! values for which the decision was "yes".
+//!
+//! The set is not output all at once: Each accepted value is output as a
+//! `SubsetOutput::Contribution` as soon as both its `Broadcast` and its `BinaryAgreement` instance
+//! have completed, and `SubsetOutput::Done` follows once all of them have decided. With the
+//! default `SubsetHandlingStrategy::Incremental`, `HoneyBadger` starts decrypting the early
+//! contributions while the remaining agreements are still running.
+//!
+//! ## Validity
+//!
+//! With `Subset::set_validity_predicate`, the set only contains values that satisfy an
+//! application-defined predicate: A node only inputs "yes" if the received value is valid, and
+//! inputs "no" and reports the proposer otherwise. Since every value that is accepted had a "yes"
+//! from at least one correct node, invalid values are never output, as long as all correct nodes
+//! use the same deterministic predicate.
 
 mod error;
 mod message;
@@ -30,4 +44,6 @@ mod subset;
 
 pub use self::error::{Error, FaultKind, Result};
 pub use self::message::{Message, MessageContent};
-pub use self::subset::{Step, Subset, SubsetOutput};
+pub use self::subset::{
+    BaSessionId, ProposerProgress, Step, Subset, SubsetOutput, ValidityPredicate,
+};
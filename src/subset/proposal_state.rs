@@ -1,11 +1,14 @@
 use std::mem;
 use std::sync::Arc;
 
-use super::subset::BaSessionId;
+use super::subset::{BaSessionId, ProposerProgress, ValidityPredicate};
 use super::{Error, FaultKind, MessageContent, Result};
-use crate::binary_agreement;
-use crate::broadcast::{self, Broadcast};
-use crate::{NetworkInfo, NodeIdT, SessionIdT};
+use crate::binary_agreement::{
+    self, CoinRecord, CoinStats, DecisionCertificate, LocalCoin, Variant,
+};
+use crate::broadcast::{self, Broadcast, EchoStrategy, MerkleHasher};
+use crate::instrument::Instrument;
+use crate::{ConsensusProtocol, NetworkInfo, NodeIdT, SessionIdT};
 
 type BaInstance<N, S> = binary_agreement::BinaryAgreement<N, BaSessionId<S>>;
 type ValueAndStep<N> = (Option<Vec<u8>>, Step<N>);
@@ -18,13 +21,68 @@ pub type Step<N> = crate::Step<MessageContent, Vec<u8>, N, FaultKind>;
 pub enum ProposalState<N, S> {
     /// We are still awaiting the value from the `Broadcast` protocol and the decision from
     /// `BinaryAgreement`.
-    Ongoing(Broadcast<N>, BaInstance<N, S>),
+    Ongoing(Box<Broadcast<N>>, BaInstance<N, S>),
     /// We received the value but are still waiting for `BinaryAgreement`, whether to output.
     HasValue(Vec<u8>, BaInstance<N, S>),
-    /// The values has been accepted, but we haven't received it yet.
-    Accepted(Broadcast<N>),
-    /// We are done: either we output (`true`) or we dropped the value (`false`).
-    Complete(bool),
+    /// The values has been accepted, but we haven't received it yet. This contains what remains
+    /// of the `BinaryAgreement` instance.
+    Accepted(Box<Broadcast<N>>, Decided<N, S>),
+    /// We are done: either we output (`true`) or we dropped the value (`false`). This contains
+    /// what remains of the `BinaryAgreement` instance.
+    Complete(bool, Decided<N, S>),
+}
+
+/// A `BinaryAgreement` instance that has decided.
+#[derive(Debug)]
+pub enum Decided<N, S> {
+    /// The instance is still collecting the signature shares for its decision certificate.
+    Certifying(Box<BaInstance<N, S>>),
+    /// The instance has terminated. This contains its coin outcomes, and its decision certificate
+    /// if certificates are enabled.
+    Terminated(CoinRecord<N>, Option<DecisionCertificate>),
+}
+
+impl<N: NodeIdT, S: SessionIdT> Decided<N, S> {
+    /// Keeps the instance if it still needs to certify its decision, and otherwise only its coin
+    /// outcomes and certificate.
+    fn new(ba: BaInstance<N, S>) -> Self {
+        if ba.terminated() {
+            Decided::Terminated(ba.coin_record().clone(), ba.certificate().cloned())
+        } else {
+            Decided::Certifying(Box::new(ba))
+        }
+    }
+
+    /// Returns the outcomes of the threshold coin in the `BinaryAgreement` instance.
+    fn coin_record(&self) -> &CoinRecord<N> {
+        match self {
+            Decided::Certifying(ba) => ba.coin_record(),
+            Decided::Terminated(record, _) => record,
+        }
+    }
+
+    /// Returns the decision certificate, if it is complete.
+    fn certificate(&self) -> Option<&DecisionCertificate> {
+        match self {
+            Decided::Certifying(_) => None,
+            Decided::Terminated(_, certificate) => certificate.as_ref(),
+        }
+    }
+
+    /// Applies `f` to the instance if it is still certifying its decision.
+    fn handle_agreement<F>(self, f: F) -> (Self, Result<Step<N>>)
+    where
+        F: FnOnce(&mut BaInstance<N, S>) -> BaResult<N>,
+    {
+        match self {
+            Decided::Certifying(mut ba) => match ProposalState::<N, S>::convert_ba(f(&mut ba)) {
+                Err(err) => (Decided::Certifying(ba), Err(err)),
+                // The instance has already output its decision.
+                Ok((_, step)) => (Decided::new(*ba), Ok(step)),
+            },
+            decided @ Decided::Terminated(_, _) => (decided, Ok(Step::default())),
+        }
+    }
 }
 
 impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
@@ -32,15 +90,15 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
     pub fn new(netinfo: Arc<NetworkInfo<N>>, ba_id: BaSessionId<S>, prop_id: N) -> Result<Self> {
         let agreement = BaInstance::new(netinfo.clone(), ba_id).map_err(Error::NewAgreement)?;
         let broadcast = Broadcast::new(netinfo, prop_id).map_err(Error::NewBroadcast)?;
-        Ok(ProposalState::Ongoing(broadcast, agreement))
+        Ok(ProposalState::Ongoing(Box::new(broadcast), agreement))
     }
 
     /// Returns `true` if we already received the `Broadcast` result.
     pub fn received(&self) -> bool {
         match self {
-            ProposalState::Ongoing(_, _) | ProposalState::Accepted(_) => false,
+            ProposalState::Ongoing(_, _) | ProposalState::Accepted(_, _) => false,
             ProposalState::HasValue(_, _) => true,
-            ProposalState::Complete(accepted) => *accepted,
+            ProposalState::Complete(accepted, _) => *accepted,
         }
     }
 
@@ -48,8 +106,8 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
     pub fn accepted(&self) -> bool {
         match self {
             ProposalState::Ongoing(_, _) | ProposalState::HasValue(_, _) => false,
-            ProposalState::Accepted(_) => true,
-            ProposalState::Complete(accepted) => *accepted,
+            ProposalState::Accepted(_, _) => true,
+            ProposalState::Complete(accepted, _) => *accepted,
         }
     }
 
@@ -58,25 +116,221 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
         match self {
             ProposalState::Ongoing(_, _)
             | ProposalState::HasValue(_, _)
-            | ProposalState::Accepted(_) => false,
-            ProposalState::Complete(_) => true,
+            | ProposalState::Accepted(_, _) => false,
+            ProposalState::Complete(_, _) => true,
+        }
+    }
+
+    /// Returns whether the `Broadcast` and the `BinaryAgreement` instance are still held in memory.
+    pub fn running(&self) -> (bool, bool) {
+        match self {
+            ProposalState::Ongoing(_, _) => (true, true),
+            ProposalState::HasValue(_, _) => (false, true),
+            ProposalState::Accepted(_, _) => (true, !self.agreement_terminated()),
+            ProposalState::Complete(_, _) => (false, !self.agreement_terminated()),
+        }
+    }
+
+    /// Returns whether the value has been received, and the decision, if any.
+    pub fn progress(&self) -> ProposerProgress {
+        let (delivered, decision) = match self {
+            ProposalState::Ongoing(_, _) => (false, None),
+            ProposalState::HasValue(_, _) => (true, None),
+            ProposalState::Accepted(_, _) => (false, Some(true)),
+            ProposalState::Complete(accepted, _) => (*accepted, Some(*accepted)),
+        };
+        ProposerProgress {
+            delivered,
+            decision,
+        }
+    }
+
+    /// Returns the outcomes of the threshold coin in the `BinaryAgreement` instance.
+    pub fn coin_stats(&self) -> CoinStats {
+        self.coin_record().outcomes
+    }
+
+    /// Returns the outcomes of the threshold coin in the `BinaryAgreement` instance, and the
+    /// parities of the signature shares they were combined from.
+    pub fn coin_record(&self) -> &CoinRecord<N> {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => ba.coin_record(),
+            ProposalState::Accepted(_, decided) | ProposalState::Complete(_, decided) => {
+                decided.coin_record()
+            }
         }
     }
 
-    /// Makes a proposal by broadcasting a value.
-    pub fn propose(&mut self, value: Vec<u8>) -> Result<Step<N>> {
-        self.transition(|state| state.handle_broadcast(|bc| bc.broadcast(value)))
+    /// Returns the certificate of the `BinaryAgreement` instance's decision, once it is complete.
+    pub fn certificate(&self) -> Option<&DecisionCertificate> {
+        match self {
+            ProposalState::Ongoing(_, _) | ProposalState::HasValue(_, _) => None,
+            ProposalState::Accepted(_, decided) | ProposalState::Complete(_, decided) => {
+                decided.certificate()
+            }
+        }
     }
 
-    /// Handles a message received from `sender_id`.
-    pub fn handle_message(&mut self, sender_id: &N, msg: MessageContent) -> Result<Step<N>> {
+    /// Returns `true` if the `BinaryAgreement` instance has terminated, i.e. decided and, if
+    /// certificates are enabled, certified its decision.
+    pub fn agreement_terminated(&self) -> bool {
+        match self {
+            ProposalState::Ongoing(_, _) | ProposalState::HasValue(_, _) => false,
+            ProposalState::Accepted(_, Decided::Certifying(_))
+            | ProposalState::Complete(_, Decided::Certifying(_)) => false,
+            ProposalState::Accepted(_, Decided::Terminated(_, _))
+            | ProposalState::Complete(_, Decided::Terminated(_, _)) => true,
+        }
+    }
+
+    /// Sets the maximum size of the value, if it hasn't been received yet.
+    pub fn set_max_value_size(&mut self, max_value_size: usize) {
+        match self {
+            ProposalState::Ongoing(bc, _) | ProposalState::Accepted(bc, _) => {
+                bc.set_max_value_size(max_value_size)
+            }
+            ProposalState::HasValue(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the chunk size of the `Broadcast` instance, if the value hasn't been received yet.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        match self {
+            ProposalState::Ongoing(bc, _) | ProposalState::Accepted(bc, _) => {
+                bc.set_chunk_size(chunk_size)
+            }
+            ProposalState::HasValue(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the echo strategy of the `Broadcast` instance, if the value hasn't been received yet.
+    pub fn set_echo_strategy(&mut self, echo_strategy: EchoStrategy) {
+        match self {
+            ProposalState::Ongoing(bc, _) | ProposalState::Accepted(bc, _) => {
+                bc.set_echo_strategy(echo_strategy)
+            }
+            ProposalState::HasValue(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the Merkle tree hash function of the `Broadcast` instance, if the value hasn't been
+    /// received yet.
+    pub fn set_merkle_hasher(&mut self, hasher: &Arc<dyn MerkleHasher>) {
+        match self {
+            ProposalState::Ongoing(bc, _) | ProposalState::Accepted(bc, _) => {
+                bc.set_merkle_hasher(hasher.clone())
+            }
+            ProposalState::HasValue(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the round structure of the `BinaryAgreement` instance, if it is still running.
+    pub fn set_agreement_variant(&mut self, variant: &Arc<dyn Variant>) -> Result<()> {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => {
+                ba.set_variant(variant.clone()).map_err(Error::NewAgreement)
+            }
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => Ok(()),
+        }
+    }
+
+    /// Sets the local coin of the `BinaryAgreement` instance, if it is still running.
+    pub fn set_agreement_local_coin(&mut self, coin: &Arc<dyn LocalCoin>) -> Result<()> {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => {
+                ba.set_local_coin(coin.clone()).map_err(Error::NewAgreement)
+            }
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => Ok(()),
+        }
+    }
+
+    /// Enables or disables decision certificates in the `BinaryAgreement` instance, if it is still
+    /// running.
+    pub fn set_agreement_certify(&mut self, certify: bool) {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => {
+                ba.set_certify(certify)
+            }
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Computes our coin shares for the `BinaryAgreement` instance's next `count` coin flips, if it
+    /// is still running.
+    pub fn precompute_agreement_coin_shares(&mut self, count: usize) -> Result<()> {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => ba
+                .precompute_coin_shares(count)
+                .map_err(Error::NewAgreement),
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => Ok(()),
+        }
+    }
+
+    /// Enables or disables batch verification of signature shares in the `BinaryAgreement`
+    /// instance, if it is still running.
+    pub fn set_agreement_batch_verification(&mut self, batch_verification: bool) {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => {
+                ba.set_batch_verification(batch_verification)
+            }
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the epoch limit of the `BinaryAgreement` instance, if it is still running.
+    pub fn set_agreement_max_epochs(&mut self, max_epochs: u64) {
+        match self {
+            ProposalState::Ongoing(_, ba) | ProposalState::HasValue(_, ba) => {
+                ba.set_max_epochs(max_epochs)
+            }
+            ProposalState::Accepted(_, _) | ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Sets the receiver of instrumentation events for the instances that are still running.
+    pub fn set_instrument(&mut self, instrument: &Arc<dyn Instrument<N>>) {
+        match self {
+            ProposalState::Ongoing(bc, ba) => {
+                bc.set_instrument(instrument.clone());
+                ba.set_instrument(instrument.clone());
+            }
+            ProposalState::HasValue(_, ba) => ba.set_instrument(instrument.clone()),
+            ProposalState::Accepted(bc, _) => bc.set_instrument(instrument.clone()),
+            ProposalState::Complete(_, _) => (),
+        }
+    }
+
+    /// Makes a proposal by broadcasting a value. Once the value is received, it is checked against
+    /// the predicate, if any.
+    pub fn propose(
+        &mut self,
+        value: Vec<u8>,
+        proposer_id: &N,
+        validity: Option<&ValidityPredicate<N>>,
+    ) -> Result<Step<N>> {
+        self.transition(|state| {
+            state.handle_broadcast(|bc| bc.broadcast(value), proposer_id, validity)
+        })
+    }
+
+    /// Handles a message received from `sender_id`. If the value is received, it is checked
+    /// against the predicate, if any.
+    pub fn handle_message(
+        &mut self,
+        sender_id: &N,
+        msg: MessageContent,
+        proposer_id: &N,
+        validity: Option<&ValidityPredicate<N>>,
+    ) -> Result<Step<N>> {
         self.transition(|state| match msg {
             MessageContent::Agreement(ba_msg) => {
                 state.handle_agreement(|ba| ba.handle_message(sender_id, ba_msg))
             }
-            MessageContent::Broadcast(bc_msg) => {
-                state.handle_broadcast(|bc| bc.handle_message(sender_id, bc_msg))
-            }
+            MessageContent::Broadcast(bc_msg) => state.handle_broadcast(
+                |bc| bc.handle_message(sender_id, bc_msg),
+                proposer_id,
+                validity,
+            ),
         })
     }
 
@@ -86,7 +340,15 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
     }
 
     /// Applies `f` to the `Broadcast` instance, and updates the state according to the outcome.
-    fn handle_broadcast<F>(self, f: F) -> (Self, Result<Step<N>>)
+    ///
+    /// If the value is received, we vote for accepting it if it satisfies the predicate, and
+    /// otherwise vote against it and report the proposer as faulty.
+    fn handle_broadcast<F>(
+        self,
+        f: F,
+        proposer_id: &N,
+        validity: Option<&ValidityPredicate<N>>,
+    ) -> (Self, Result<Step<N>>)
     where
         F: FnOnce(&mut Broadcast<N>) -> broadcast::Result<broadcast::Step<N>>,
     {
@@ -95,18 +357,26 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
             Ongoing(mut bc, ba) => match Self::convert_bc(f(&mut bc)) {
                 Err(err) => (Ongoing(bc, ba), Err(err)),
                 Ok((None, step)) => (Ongoing(bc, ba), Ok(step)),
-                Ok((Some(value), step)) => {
+                Ok((Some(value), mut step)) => {
+                    let valid = match validity {
+                        Some(predicate) => predicate(proposer_id, &value),
+                        None => true,
+                    };
+                    if !valid {
+                        let fault_kind = FaultKind::InvalidProposal;
+                        step.fault_log.append(proposer_id.clone(), fault_kind);
+                    }
                     let state = HasValue(value, ba);
-                    let (state, result) = state.handle_agreement(|ba| ba.propose(true));
+                    let (state, result) = state.handle_agreement(|ba| ba.propose(valid));
                     (state, result.map(|vote_step| step.join(vote_step)))
                 }
             },
-            Accepted(mut bc) => match Self::convert_bc(f(&mut bc)) {
-                Err(err) => (Accepted(bc), Err(err)),
-                Ok((None, step)) => (Accepted(bc), Ok(step)),
-                Ok((Some(value), step)) => (Complete(true), Ok(step.with_output(value))),
+            Accepted(mut bc, decided) => match Self::convert_bc(f(&mut bc)) {
+                Err(err) => (Accepted(bc, decided), Err(err)),
+                Ok((None, step)) => (Accepted(bc, decided), Ok(step)),
+                Ok((Some(value), step)) => (Complete(true, decided), Ok(step.with_output(value))),
             },
-            state @ HasValue(_, _) | state @ Complete(_) => (state, Ok(Step::default())),
+            state @ HasValue(_, _) | state @ Complete(_, _) => (state, Ok(Step::default())),
         }
     }
 
@@ -121,16 +391,26 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
             Ongoing(bc, mut ba) => match Self::convert_ba(f(&mut ba)) {
                 Err(err) => (Ongoing(bc, ba), Err(err)),
                 Ok((None, step)) => (Ongoing(bc, ba), Ok(step)),
-                Ok((Some(false), step)) => (Complete(false), Ok(step)),
-                Ok((Some(true), step)) => (Accepted(bc), Ok(step)),
+                Ok((Some(false), step)) => (Complete(false, Decided::new(ba)), Ok(step)),
+                Ok((Some(true), step)) => (Accepted(bc, Decided::new(ba)), Ok(step)),
             },
             HasValue(value, mut ba) => match Self::convert_ba(f(&mut ba)) {
                 Err(err) => (HasValue(value, ba), Err(err)),
                 Ok((None, step)) => (HasValue(value, ba), Ok(step)),
-                Ok((Some(false), step)) => (Complete(false), Ok(step)),
-                Ok((Some(true), step)) => (Complete(true), Ok(step.with_output(value))),
+                Ok((Some(false), step)) => (Complete(false, Decided::new(ba)), Ok(step)),
+                Ok((Some(true), step)) => {
+                    let state = Complete(true, Decided::new(ba));
+                    (state, Ok(step.with_output(value)))
+                }
             },
-            state @ Accepted(_) | state @ Complete(_) => (state, Ok(Step::default())),
+            Accepted(bc, decided) => {
+                let (decided, result) = decided.handle_agreement(f);
+                (Accepted(bc, decided), result)
+            }
+            Complete(accepted, decided) => {
+                let (decided, result) = decided.handle_agreement(f);
+                (Complete(accepted, decided), result)
+            }
         }
     }
 
@@ -164,7 +444,9 @@ impl<N: NodeIdT, S: SessionIdT> ProposalState<N, S> {
         F: FnOnce(Self) -> (Self, Result<Step<N>>),
     {
         // Temporary value: We need to take ownership of the state to make it transition.
-        let (new_state, result) = f(mem::replace(self, ProposalState::Complete(false)));
+        let placeholder =
+            ProposalState::Complete(false, Decided::Terminated(CoinRecord::default(), None));
+        let (new_state, result) = f(mem::replace(self, placeholder));
         *self = new_state;
         result
     }
@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Instant;
 use std::{fmt, result};
 
 use derivative::Derivative;
@@ -9,12 +10,19 @@ use serde::Serialize;
 
 use super::proposal_state::{ProposalState, Step as ProposalStep};
 use super::{Error, FaultKind, Message, MessageContent, Result};
+use crate::binary_agreement::{CoinRecord, CoinStats, DecisionCertificate, LocalCoin, Variant};
+use crate::broadcast::{EchoStrategy, MerkleHasher};
+use crate::instrument::{Instrument, NoInstrument, Timing};
 use crate::{util, ConsensusProtocol, NetworkInfo, NodeIdT, SessionIdT};
 use rand::Rng;
 
 /// A `Subset` step, possibly containing several outputs.
 pub type Step<N> = crate::Step<Message<N>, SubsetOutput<N>, N, FaultKind>;
 
+/// A predicate that returns `true` if the value proposed by the given node is valid. It must be
+/// deterministic, and agree on all correct nodes.
+pub type ValidityPredicate<N> = Arc<dyn Fn(&N, &[u8]) -> bool + Send + Sync>;
+
 /// An output with an accepted contribution or the end of the set.
 #[derive(Derivative, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[derivative(Debug)]
@@ -28,8 +36,20 @@ pub enum SubsetOutput<N> {
     Done,
 }
 
+/// The progress of a proposer's `Broadcast` and `BinaryAgreement` instances.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProposerProgress {
+    /// Whether the proposer's value has been delivered by `Broadcast`. Once a value has been
+    /// rejected, it is dropped, so this is `false` for all rejected proposals.
+    pub delivered: bool,
+    /// The decision of the `BinaryAgreement` instance, i.e. whether the value is accepted into the
+    /// set, or `None` if it hasn't decided yet.
+    pub decision: Option<bool>,
+}
+
 /// Subset algorithm instance
-#[derive(Debug)]
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct Subset<N, S> {
     /// Shared network information.
     netinfo: Arc<NetworkInfo<N>>,
@@ -39,6 +59,14 @@ pub struct Subset<N, S> {
     proposal_states: BTreeMap<N, ProposalState<N, S>>,
     /// Whether the instance has decided on a value.
     decided: bool,
+    /// The time the instance was created.
+    started: Instant,
+    /// The receiver of instrumentation events.
+    #[derivative(Debug = "ignore")]
+    instrument: Arc<dyn Instrument<N>>,
+    /// The predicate the proposed values must satisfy, if any.
+    #[derivative(Debug = "ignore")]
+    validity: Option<ValidityPredicate<N>>,
 }
 
 impl<N: NodeIdT, S: SessionIdT> ConsensusProtocol for Subset<N, S> {
@@ -77,12 +105,10 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
     /// If multiple `Subset`s are instantiated within a single network, they must use different
     /// session identifiers to foil replay attacks.
     pub fn new(netinfo: Arc<NetworkInfo<N>>, session_id: S) -> Result<Self> {
+        netinfo.validate().map_err(Error::InvalidNetworkInfo)?;
         let mut proposal_states = BTreeMap::new();
         for (proposer_idx, proposer_id) in netinfo.all_ids().enumerate() {
-            let ba_id = BaSessionId {
-                subset_id: session_id.clone(),
-                proposer_idx: proposer_idx as u32,
-            };
+            let ba_id = BaSessionId::new(session_id.clone(), proposer_idx as u32);
             proposal_states.insert(
                 proposer_id.clone(),
                 ProposalState::new(netinfo.clone(), ba_id, proposer_id.clone())?,
@@ -94,6 +120,9 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
             session_id,
             proposal_states,
             decided: false,
+            started: Instant::now(),
+            instrument: Arc::new(NoInstrument),
+            validity: None,
         })
     }
 
@@ -105,11 +134,12 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
             return Ok(Step::default());
         }
         debug!("{} proposing {:0.10}", self, HexFmt(&value));
+        let our_id = self.netinfo.our_id();
         let prop_step = self
             .proposal_states
-            .get_mut(self.netinfo.our_id())
+            .get_mut(our_id)
             .ok_or(Error::UnknownProposer)?
-            .propose(value)?;
+            .propose(value, our_id, self.validity.as_ref())?;
         let step = Self::convert_step(self.netinfo.our_id(), prop_step);
         Ok(step.join(self.try_output()?))
     }
@@ -122,7 +152,12 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
             .proposal_states
             .get_mut(&msg.proposer_id)
             .ok_or(Error::UnknownProposer)?
-            .handle_message(sender_id, msg.content)?;
+            .handle_message(
+                sender_id,
+                msg.content,
+                &msg.proposer_id,
+                self.validity.as_ref(),
+            )?;
         let step = Self::convert_step(&msg.proposer_id, prop_step);
         Ok(step.join(self.try_output()?))
     }
@@ -133,6 +168,165 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
         self.proposal_states.values().filter(received).count()
     }
 
+    /// Returns the number of `Broadcast` and of `BinaryAgreement` instances that are still held in
+    /// memory. Each of them is dropped as soon as it has terminated.
+    pub fn running_instances(&self) -> (usize, usize) {
+        let running = self.proposal_states.values().map(ProposalState::running);
+        running.fold((0, 0), |(bcs, bas), (bc, ba)| {
+            (bcs + bc as usize, bas + ba as usize)
+        })
+    }
+
+    /// Sets the maximum size of the proposed values in bytes. Larger values are never output, and
+    /// the nodes that send their shards are reported as faulty.
+    ///
+    /// All nodes must use the same maximum size.
+    pub fn set_max_value_size(&mut self, max_value_size: usize) {
+        for state in self.proposal_states.values_mut() {
+            state.set_max_value_size(max_value_size);
+        }
+    }
+
+    /// Sets the maximum size of a shard in a single `Broadcast` message in bytes. Larger shards are
+    /// split into chunks: see `Broadcast::set_chunk_size`.
+    ///
+    /// All nodes must use the same chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        for state in self.proposal_states.values_mut() {
+            state.set_chunk_size(chunk_size);
+        }
+    }
+
+    /// Sets which validators receive our full `Echo`s in the `Broadcast` instances: see
+    /// `Broadcast::set_echo_strategy`.
+    pub fn set_echo_strategy(&mut self, echo_strategy: EchoStrategy) {
+        for state in self.proposal_states.values_mut() {
+            state.set_echo_strategy(echo_strategy);
+        }
+    }
+
+    /// Sets the hash function of the Merkle trees in the `Broadcast` instances: see
+    /// `Broadcast::set_merkle_hasher`.
+    pub fn set_merkle_hasher(&mut self, hasher: Arc<dyn MerkleHasher>) {
+        for state in self.proposal_states.values_mut() {
+            state.set_merkle_hasher(&hasher);
+        }
+    }
+
+    /// Sets the round structure of the `BinaryAgreement` instances: see
+    /// `BinaryAgreement::set_variant`.
+    ///
+    /// All nodes must use equivalent variants.
+    pub fn set_agreement_variant(&mut self, variant: Arc<dyn Variant>) -> Result<()> {
+        for state in self.proposal_states.values_mut() {
+            state.set_agreement_variant(&variant)?;
+        }
+        Ok(())
+    }
+
+    /// Sets a coin that is computed locally, to use in the `BinaryAgreement` instances instead of
+    /// the threshold signature: see `BinaryAgreement::set_local_coin`. This must be called before
+    /// handling any messages, with equivalent coins on all nodes.
+    pub fn set_agreement_local_coin(&mut self, coin: Arc<dyn LocalCoin>) -> Result<()> {
+        for state in self.proposal_states.values_mut() {
+            state.set_agreement_local_coin(&coin)?;
+        }
+        Ok(())
+    }
+
+    /// Enables or disables decision certificates in the `BinaryAgreement` instances: see
+    /// `BinaryAgreement::set_certify`. This must be called before handling any messages, with the
+    /// same value on all nodes.
+    ///
+    /// If enabled, `SubsetOutput::Done` is only output once every instance has certified its
+    /// decision, which takes one more message round.
+    pub fn set_agreement_certify(&mut self, certify: bool) {
+        for state in self.proposal_states.values_mut() {
+            state.set_agreement_certify(certify);
+        }
+    }
+
+    /// Returns the complete certificates of the `BinaryAgreement` instances' decisions, by
+    /// proposer. Each one can be verified with the instance's `BaSessionId`.
+    pub fn agreement_certificates(&self) -> BTreeMap<N, DecisionCertificate> {
+        self.proposal_states
+            .iter()
+            .filter_map(|(id, state)| Some((id.clone(), state.certificate()?.clone())))
+            .collect()
+    }
+
+    /// Computes our coin shares for the next `count` coin flips of each `BinaryAgreement` instance
+    /// that is still running: see `BinaryAgreement::precompute_coin_shares`. This is expensive, and
+    /// meant to be called while the node is idle.
+    pub fn precompute_agreement_coin_shares(&mut self, count: usize) -> Result<()> {
+        for state in self.proposal_states.values_mut() {
+            state.precompute_agreement_coin_shares(count)?;
+        }
+        Ok(())
+    }
+
+    /// Enables or disables batch verification of the coin's and the certificates' signature shares
+    /// in the `BinaryAgreement` instances: see `BinaryAgreement::set_batch_verification`.
+    pub fn set_agreement_batch_verification(&mut self, batch_verification: bool) {
+        for state in self.proposal_states.values_mut() {
+            state.set_agreement_batch_verification(batch_verification);
+        }
+    }
+
+    /// Sets the number of epochs after which stalled `BinaryAgreement` instances are reported to
+    /// the instrument: see `BinaryAgreement::set_max_epochs`.
+    pub fn set_agreement_max_epochs(&mut self, max_epochs: u64) {
+        for state in self.proposal_states.values_mut() {
+            state.set_agreement_max_epochs(max_epochs);
+        }
+    }
+
+    /// Sets the receiver of instrumentation events, for this instance and its `Broadcast` and
+    /// `BinaryAgreement` instances.
+    pub fn set_instrument(&mut self, instrument: Arc<dyn Instrument<N>>) {
+        for state in self.proposal_states.values_mut() {
+            state.set_instrument(&instrument);
+        }
+        self.instrument = instrument;
+    }
+
+    /// Sets a predicate that each value must satisfy to be accepted into the set: We only vote for
+    /// accepting a proposal if its value is valid, and report the proposer as faulty otherwise.
+    /// This must be set before any values are received, and all nodes must use the same predicate.
+    pub fn set_validity_predicate<F>(&mut self, predicate: F)
+    where
+        F: Fn(&N, &[u8]) -> bool + Send + Sync + 'static,
+    {
+        self.validity = Some(Arc::new(predicate));
+    }
+
+    /// Returns the progress of each proposer's `Broadcast` and `BinaryAgreement` instance, e.g. to
+    /// find out which proposals an epoch is waiting for.
+    pub fn progress(&self) -> BTreeMap<N, ProposerProgress> {
+        self.proposal_states
+            .iter()
+            .map(|(id, state)| (id.clone(), state.progress()))
+            .collect()
+    }
+
+    /// Returns the outcomes of the threshold coin in each proposer's `BinaryAgreement` instance.
+    pub fn coin_stats(&self) -> BTreeMap<N, CoinStats> {
+        self.proposal_states
+            .iter()
+            .map(|(id, state)| (id.clone(), state.coin_stats()))
+            .collect()
+    }
+
+    /// Returns the outcomes of the threshold coin in all `BinaryAgreement` instances, and the
+    /// parities of the signature shares each validator contributed to them.
+    pub fn coin_record(&self) -> CoinRecord<N> {
+        let mut record = CoinRecord::default();
+        for state in self.proposal_states.values() {
+            record.merge(state.coin_record());
+        }
+        record
+    }
+
     fn convert_step(proposer_id: &N, prop_step: ProposalStep<N>) -> Step<N> {
         let from_p_msg = |p_msg: MessageContent| p_msg.with(proposer_id.clone());
         let mut step = Step::default();
@@ -161,8 +355,12 @@ impl<N: NodeIdT, S: SessionIdT> Subset<N, S> {
                 step.extend(Self::convert_step(proposer_id, state.vote_false()?));
             }
         }
-        if self.proposal_states.values().all(ProposalState::complete) {
+        let terminated =
+            |state: &ProposalState<N, S>| state.complete() && state.agreement_terminated();
+        if self.proposal_states.values().all(terminated) {
             self.decided = true;
+            let duration = self.started.elapsed();
+            self.instrument.timing(Timing::Subset, duration);
             step.output.push(SubsetOutput::Done);
         }
         Ok(step)
@@ -184,6 +382,17 @@ pub struct BaSessionId<S> {
     proposer_idx: u32,
 }
 
+impl<S> BaSessionId<S> {
+    /// Creates the session ID of the `BinaryAgreement` instance about the proposal of the
+    /// validator with the given index, in the order of the validators' IDs.
+    pub fn new(subset_id: S, proposer_idx: u32) -> Self {
+        BaSessionId {
+            subset_id,
+            proposer_idx,
+        }
+    }
+}
+
 impl<S: fmt::Display> fmt::Display for BaSessionId<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> result::Result<(), fmt::Error> {
         write!(
@@ -52,6 +52,27 @@
 //! * If a participant crashed and lost its `SyncKeyGen` instance, but still has its original
 //! key pair, and if the key generation messages were committed to some public ledger, it can
 //! create a new `SyncKeyGen`, handle all the messages in order, and compute its secret key share.
+//! * Alternatively, a participant can store `SyncKeyGen::checkpoint` after each message it handles,
+//!   and after a restart resume with `SyncKeyGen::restore`, without replaying the messages.
+//!
+//! Like `threshold_crypto`'s secret keys, the decrypted rows and values, and our share of the
+//! secret key while it is being computed, are overwritten with zeros once they are dropped. A
+//! checkpoint contains the values in plain text, however, so it must be stored as securely as the
+//! secret key itself.
+//!
+//! ## Resharing
+//!
+//! `SyncKeyGen::new_resharing` creates an instance that doesn't generate a new secret master key,
+//! but redistributes the existing one to a new set of nodes, so that the public master key stays
+//! the same across membership changes. The dealers are the validators of the existing key set,
+//! whether or not they are also among the new nodes: Each of them creates a `Part` whose
+//! polynomial has its existing secret key share as its value at _(0, 0)_, which the other nodes
+//! verify against its existing public key share. The new nodes handle the `Part`s and `Ack`s as
+//! usual, but `generate` combines the first _t' + 1_ complete `Part`s with Lagrange coefficients
+//! instead of adding them all, where _t'_ is the threshold of the existing key set. That is the
+//! same interpolation that combines signature shares, so the new key shares lie on a polynomial
+//! whose value at _0_ is the existing secret master key. The new threshold can differ from the
+//! existing one.
 //!
 //! ## Example
 //!
@@ -176,20 +197,22 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
 
 use crate::crypto::{
     error::Error as CryptoError,
-    poly::{BivarCommitment, BivarPoly, Poly},
-    serde_impl::FieldWrap,
-    Ciphertext, Fr, G1Affine, PublicKey, PublicKeySet, SecretKey, SecretKeyShare,
+    poly::{BivarCommitment, BivarPoly, Commitment, Poly},
+    serde_impl::{FieldWrap, SerdeSecret},
+    Ciphertext, Fr, G1Affine, IntoFr, PublicKey, PublicKeySet, SecretKeyShare, PK_SIZE, SIG_SIZE,
 };
 use crate::pairing::{CurveAffine, Field};
 use bincode;
 use failure::Fail;
 use rand;
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::{NetworkInfo, NodeIdT};
+use crate::secret::{self, SecretBytes, SecretFr};
+use crate::{IntoSigner, NetworkInfo, NodeIdT, ShareSigner, Signer};
 
 /// A local error while handling an `Ack` or `Part` message, that was not caused by that message
 /// being invalid.
@@ -207,6 +230,22 @@ pub enum Error {
     /// Failed to serialize message.
     #[fail(display = "Serialization error: {}", _0)]
     Serialize(String),
+    /// The degree of the supplied polynomial doesn't match the threshold.
+    #[fail(display = "The degree of the polynomial doesn't match the threshold")]
+    PolyDegree,
+    /// The supplied polynomial doesn't match the supplied commitment.
+    #[fail(display = "The polynomial doesn't match the commitment")]
+    PolyCommitment,
+    /// Too few `Part`s from the existing validators are complete to reshare the key.
+    #[fail(display = "Too few complete parts to reshare the key")]
+    NotEnoughReshares,
+    /// The secret key doesn't match our public key in the checkpoint.
+    #[fail(display = "The secret key doesn't match the checkpoint")]
+    CheckpointSecretKey,
+    /// We are an existing validator, but our secret key share is held by an external
+    /// `ShareSigner`, so it can't be reshared.
+    #[fail(display = "The secret key share to reshare is not available")]
+    UnavailableSecretKeyShare,
 }
 
 impl From<bincode::Error> for Error {
@@ -221,8 +260,19 @@ impl From<bincode::Error> for Error {
 /// The message contains a commitment to a bivariate polynomial, and for each node, an encrypted
 /// row of values. If this message receives enough `Ack`s, it will be used as summand to produce
 /// the the key set in the end.
+///
+/// In a resharing key generation, it also contains the serialized difference between the
+/// proposer's existing secret key share and the polynomial's value at _(0, 0)_. That difference is
+/// uniformly random, so it reveals nothing about the key share.
 #[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
-pub struct Part(BivarCommitment, Vec<Ciphertext>);
+pub struct Part(BivarCommitment, Vec<Ciphertext>, Option<Vec<u8>>);
+
+impl Part {
+    /// Returns the commitment to the proposer's bivariate polynomial.
+    pub fn commitment(&self) -> &BivarCommitment {
+        &self.0
+    }
+}
 
 impl Debug for Part {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
@@ -241,6 +291,13 @@ impl Debug for Part {
 #[derive(Deserialize, Serialize, Clone, Hash, Eq, PartialEq)]
 pub struct Ack(u64, Vec<Ciphertext>);
 
+impl Ack {
+    /// Returns the index of the proposer whose `Part` this acknowledges.
+    pub(crate) fn proposer_index(&self) -> u64 {
+        self.0
+    }
+}
+
 impl Debug for Ack {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_tuple("Ack")
@@ -251,23 +308,27 @@ impl Debug for Ack {
 }
 
 /// The information needed to track a single proposer's secret sharing process.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "WireProposalState", into = "WireProposalState")]
 struct ProposalState {
     /// The proposer's commitment.
     commit: BivarCommitment,
     /// The verified values we received from `Ack` messages.
-    values: BTreeMap<u64, Fr>,
+    values: BTreeMap<u64, SecretFr>,
     /// The nodes which have acked this part, valid or not.
     acks: BTreeSet<u64>,
+    /// The value to add to the polynomial's constant term. This is zero unless we are resharing.
+    offset: Fr,
 }
 
 impl ProposalState {
-    /// Creates a new part state with a commitment.
-    fn new(commit: BivarCommitment) -> ProposalState {
+    /// Creates a new part state with a commitment and an offset.
+    fn new(commit: BivarCommitment, offset: Fr) -> ProposalState {
         ProposalState {
             commit,
             values: BTreeMap::new(),
             acks: BTreeSet::new(),
+            offset,
         }
     }
 
@@ -277,6 +338,67 @@ impl ProposalState {
     }
 }
 
+/// The serialized form of a `ProposalState`, with the offset wrapped. The secret values clear
+/// themselves when dropped.
+#[derive(Serialize, Deserialize)]
+struct WireProposalState {
+    commit: BivarCommitment,
+    values: Vec<(u64, SecretFr)>,
+    acks: BTreeSet<u64>,
+    offset: FieldWrap<Fr>,
+}
+
+impl From<ProposalState> for WireProposalState {
+    fn from(state: ProposalState) -> Self {
+        WireProposalState {
+            commit: state.commit,
+            values: state.values.into_iter().collect(),
+            acks: state.acks,
+            offset: FieldWrap(state.offset),
+        }
+    }
+}
+
+impl From<WireProposalState> for ProposalState {
+    fn from(wire: WireProposalState) -> Self {
+        ProposalState {
+            commit: wire.commit,
+            values: wire.values.into_iter().collect(),
+            acks: wire.acks,
+            offset: wire.offset.into_inner(),
+        }
+    }
+}
+
+/// A serializable checkpoint of a `SyncKeyGen` instance, including all handled `Part`s and
+/// `Ack`s, so that a node that restarts during the key generation can resume it with
+/// `SyncKeyGen::restore`.
+///
+/// It doesn't include our secret key, which must be passed to `restore` again. It does include
+/// the values we received in `Ack`s, from which our new secret key share is computed, so it must
+/// be stored as securely as the secret key itself.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+pub struct SyncKeyGenCheckpoint<N: Ord> {
+    our_id: N,
+    pub_keys: BTreeMap<N, PublicKey>,
+    parts: BTreeMap<u64, ProposalState>,
+    threshold: usize,
+    reshare: Option<Reshare<N>>,
+}
+
+impl<N: Ord> SyncKeyGenCheckpoint<N> {
+    /// Returns our node ID.
+    pub fn our_id(&self) -> &N {
+        &self.our_id
+    }
+
+    /// Returns the participating nodes and their public keys.
+    pub fn public_keys(&self) -> &BTreeMap<N, PublicKey> {
+        &self.pub_keys
+    }
+}
+
 /// The outcome of handling and verifying a `Part` message.
 pub enum PartOutcome {
     /// The message was valid: the part of it that was encrypted to us matched the public
@@ -304,14 +426,26 @@ pub struct SyncKeyGen<N> {
     our_id: N,
     /// Our node index.
     our_idx: Option<u64>,
-    /// Our secret key.
-    sec_key: SecretKey,
+    /// The operations with our secret key.
+    sec_key: Arc<dyn Signer>,
     /// The public keys of all nodes, by node ID.
     pub_keys: BTreeMap<N, PublicKey>,
     /// Proposed bivariate polynomials.
     parts: BTreeMap<u64, ProposalState>,
     /// The degree of the generated polynomial.
     threshold: usize,
+    /// The existing key set, if we are resharing it instead of generating a new one.
+    reshare: Option<Reshare<N>>,
+}
+
+/// The existing key set that a resharing key generation redistributes.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(bound(deserialize = "N: Ord + DeserializeOwned"))]
+struct Reshare<N> {
+    /// The existing public key set.
+    pk_set: PublicKeySet,
+    /// The existing validators' indices in the existing key set. These are the proposers.
+    dealers: BTreeMap<N, u64>,
 }
 
 impl<N: NodeIdT> SyncKeyGen<N> {
@@ -320,42 +454,107 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     ///
     /// If we are not a validator but only an observer, no `Part` message is produced and no
     /// messages need to be sent.
-    pub fn new<R: rand::Rng>(
+    pub fn new<S: IntoSigner, R: rand::Rng>(
         our_id: N,
-        sec_key: SecretKey,
+        sec_key: S,
         pub_keys: BTreeMap<N, PublicKey>,
         threshold: usize,
         rng: &mut R,
     ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
-        let our_idx = pub_keys
-            .keys()
-            .position(|id| *id == our_id)
-            .map(|idx| idx as u64);
-        let key_gen = SyncKeyGen {
-            our_id,
-            our_idx,
-            sec_key,
-            pub_keys,
-            parts: BTreeMap::new(),
-            threshold,
-        };
-        if our_idx.is_none() {
+        let key_gen =
+            SyncKeyGen::new_without_part(our_id, sec_key.into_signer(), pub_keys, threshold);
+        if key_gen.our_idx.is_none() {
             return Ok((key_gen, None)); // No part: we are an observer.
         }
+        let our_part = BivarPoly::random(threshold, rng);
+        let part = key_gen.encrypt_part(&our_part, rng)?;
+        Ok((key_gen, Some(part)))
+    }
 
+    /// Creates a new `SyncKeyGen` instance, together with the `Part` message that should be
+    /// multicast to all nodes, using the given polynomial instead of a randomly generated one.
+    ///
+    /// This allows generating the polynomial in advance, e.g. on an air-gapped machine or from an
+    /// audited source of randomness, and publishing its commitment before the key generation
+    /// starts. If `commit` is given, it must match the polynomial. The `rng` is only used for
+    /// encrypting the rows for the other nodes.
+    ///
+    /// If we are not a validator but only an observer, the polynomial is ignored, and no `Part`
+    /// message is produced.
+    pub fn new_with_poly<S: IntoSigner, R: rand::Rng>(
+        our_id: N,
+        sec_key: S,
+        pub_keys: BTreeMap<N, PublicKey>,
+        threshold: usize,
+        our_part: &BivarPoly,
+        commit: Option<&BivarCommitment>,
+        rng: &mut R,
+    ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        if our_part.degree() != threshold {
+            return Err(Error::PolyDegree);
+        }
+        if let Some(commit) = commit {
+            if *commit != our_part.commitment() {
+                return Err(Error::PolyCommitment);
+            }
+        }
+        let key_gen =
+            SyncKeyGen::new_without_part(our_id, sec_key.into_signer(), pub_keys, threshold);
+        if key_gen.our_idx.is_none() {
+            return Ok((key_gen, None)); // No part: we are an observer.
+        }
+        let part = key_gen.encrypt_part(our_part, rng)?;
+        Ok((key_gen, Some(part)))
+    }
+
+    /// Creates a new `SyncKeyGen` instance that reshares the secret master key of `old_netinfo`'s
+    /// key set to the nodes in `pub_keys`, together with the `Part` message that should be
+    /// multicast to all nodes.
+    ///
+    /// The generated public key set has the same public master key as the existing one. The
+    /// proposers are the existing validators, which don't need to be in `pub_keys`: If we are an
+    /// existing validator, a `Part` with our existing secret key share is produced, even if we are
+    /// only an observer of the new key generation. Otherwise no `Part` is produced.
+    pub fn new_resharing<S: IntoSigner, R: rand::Rng>(
+        our_id: N,
+        sec_key: S,
+        pub_keys: BTreeMap<N, PublicKey>,
+        threshold: usize,
+        old_netinfo: &NetworkInfo<N>,
+        rng: &mut R,
+    ) -> Result<(SyncKeyGen<N>, Option<Part>), Error> {
+        let mut key_gen =
+            SyncKeyGen::new_without_part(our_id, sec_key.into_signer(), pub_keys, threshold);
+        let dealers = old_netinfo
+            .all_ids()
+            .map(|id| {
+                let idx = old_netinfo.node_index(id).ok_or(Error::UnknownSender)? as u64;
+                Ok((id.clone(), idx))
+            })
+            .collect::<Result<_, Error>>()?;
+        key_gen.reshare = Some(Reshare {
+            pk_set: old_netinfo.public_key_set().clone(),
+            dealers,
+        });
+        if !old_netinfo.is_validator() {
+            return Ok((key_gen, None)); // No part: we are not an existing validator.
+        }
+        let old_sk_share = old_netinfo
+            .secret_key_share()
+            .ok_or(Error::UnavailableSecretKeyShare)?;
+        let ser_share = SecretBytes::new(bincode::serialize(&SerdeSecret(old_sk_share))?);
+        let mut offset = bincode::deserialize::<FieldWrap<Fr>>(&ser_share)?.into_inner();
         let our_part = BivarPoly::random(threshold, rng);
-        let commit = our_part.commitment();
-        let encrypt = |(i, pk): (usize, &PublicKey)| {
-            let row = our_part.row(i + 1);
-            Ok(pk.encrypt_with_rng(rng, &bincode::serialize(&row)?))
-        };
-        let rows = key_gen
-            .pub_keys
-            .values()
-            .enumerate()
-            .map(encrypt)
-            .collect::<Result<Vec<_>, Error>>()?;
-        Ok((key_gen, Some(Part(commit, rows))))
+        offset.sub_assign(&our_part.evaluate(0, 0));
+        let Part(commit, rows, _) = key_gen.encrypt_part(&our_part, rng)?;
+        let ser_offset = bincode::serialize(&FieldWrap(offset))?;
+        Ok((key_gen, Some(Part(commit, rows, Some(ser_offset)))))
+    }
+
+    /// Returns `true` if this instance reshares an existing key set instead of generating a new
+    /// secret master key.
+    pub fn is_resharing(&self) -> bool {
+        self.reshare.is_some()
     }
 
     /// Returns the map of participating nodes and their public keys.
@@ -375,7 +574,7 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         part: Part,
         rng: &mut R,
     ) -> Result<PartOutcome, Error> {
-        let sender_idx = self.node_index(sender_id).ok_or(Error::UnknownSender)?;
+        let sender_idx = self.proposer_index(sender_id).ok_or(Error::UnknownSender)?;
         let row = match self.handle_part_or_fault(sender_idx, part) {
             Ok(Some(row)) => row,
             Ok(None) => return Ok(PartOutcome::Valid(None)),
@@ -384,9 +583,10 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         // The row is valid. Encrypt one value for each node and broadcast an `Ack`.
         let mut values = Vec::new();
         for (idx, pk) in self.pub_keys.values().enumerate() {
-            let val = row.evaluate(idx + 1);
-            let ser_val = bincode::serialize(&FieldWrap(val))?;
-            values.push(pk.encrypt_with_rng(rng, ser_val));
+            let mut val = row.evaluate(idx + 1);
+            let ser_val = SecretBytes::new(bincode::serialize(&FieldWrap(val))?);
+            secret::clear_fr(&mut val);
+            values.push(pk.encrypt_with_rng(rng, &*ser_val));
         }
         Ok(PartOutcome::Valid(Some(Ack(sender_idx, values))))
     }
@@ -397,10 +597,176 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     /// Note that `handle_ack` also needs to explicitly be called with this instance's own `Ack`s.
     pub fn handle_ack(&mut self, sender_id: &N, ack: Ack) -> Result<AckOutcome, Error> {
         let sender_idx = self.node_index(sender_id).ok_or(Error::UnknownSender)?;
-        Ok(match self.handle_ack_or_fault(sender_idx, ack) {
-            Ok(()) => AckOutcome::Valid,
-            Err(fault) => AckOutcome::Invalid(fault),
-        })
+        let mut commit_rows = BTreeMap::new();
+        Ok(
+            match self.handle_ack_or_fault(sender_idx, ack, &mut commit_rows) {
+                Ok(()) => AckOutcome::Valid,
+                Err(fault) => AckOutcome::Invalid(fault),
+            },
+        )
+    }
+
+    /// Handles a sequence of `Ack` messages, and returns one outcome for each of them, in order.
+    ///
+    /// This is equivalent to calling `handle_ack` for each message, but the commitment to our
+    /// row of each proposer's polynomial is only computed once, instead of once per `Ack`. For
+    /// large committees this makes verifying all `Ack`s for a given `Part` considerably cheaper.
+    ///
+    /// If any of the senders is unknown, an error is returned and none of the messages is handled.
+    pub fn handle_acks<'a, I>(&mut self, acks: I) -> Result<Vec<AckOutcome>, Error>
+    where
+        I: IntoIterator<Item = (&'a N, Ack)>,
+        N: 'a,
+    {
+        let indexed_acks = acks
+            .into_iter()
+            .map(|(sender_id, ack)| {
+                let sender_idx = self.node_index(sender_id).ok_or(Error::UnknownSender)?;
+                Ok((sender_idx, ack))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut commit_rows = BTreeMap::new();
+        let mut outcomes = Vec::with_capacity(indexed_acks.len());
+        for (sender_idx, ack) in indexed_acks {
+            outcomes.push(
+                match self.handle_ack_or_fault(sender_idx, ack, &mut commit_rows) {
+                    Ok(()) => AckOutcome::Valid,
+                    Err(fault) => AckOutcome::Invalid(fault),
+                },
+            );
+        }
+        Ok(outcomes)
+    }
+
+    /// Returns a serializable checkpoint of this instance, without our secret key.
+    pub fn checkpoint(&self) -> SyncKeyGenCheckpoint<N> {
+        SyncKeyGenCheckpoint {
+            our_id: self.our_id.clone(),
+            pub_keys: self.pub_keys.clone(),
+            parts: self.parts.clone(),
+            threshold: self.threshold,
+            reshare: self.reshare.clone(),
+        }
+    }
+
+    /// Restores an instance from a checkpoint and our secret key, which must match our public key
+    /// in the checkpoint, if we are a participant.
+    ///
+    /// The restored instance doesn't produce a `Part` again: Our own `Part` and `Ack`s that were
+    /// created but not delivered before the checkpoint need to be stored and sent separately.
+    pub fn restore<S: IntoSigner>(
+        sec_key: S,
+        checkpoint: SyncKeyGenCheckpoint<N>,
+    ) -> Result<Self, Error> {
+        let sec_key = sec_key.into_signer();
+        let SyncKeyGenCheckpoint {
+            our_id,
+            pub_keys,
+            parts,
+            threshold,
+            reshare,
+        } = checkpoint;
+        if let Some(pk) = pub_keys.get(&our_id) {
+            if *pk != sec_key.public_key() {
+                return Err(Error::CheckpointSecretKey);
+            }
+        }
+        let mut key_gen = SyncKeyGen::new_without_part(our_id, sec_key, pub_keys, threshold);
+        key_gen.parts = parts;
+        key_gen.reshare = reshare;
+        Ok(key_gen)
+    }
+
+    /// Checks the structure of a `Part` message: the number of rows, the degree of the
+    /// commitment, the sizes of the encrypted rows and of the offset. This is cheap compared to
+    /// `handle_part`, which also decrypts and verifies our row, and can be used to reject
+    /// malformed messages before buffering them.
+    pub fn validate_part(&self, part: &Part) -> Result<(), PartFault> {
+        self.validate_part_parts(&part.0, &part.1, part.2.as_ref())
+    }
+
+    /// Checks the structure of an `Ack` message: the number of values, and their sizes. This is
+    /// cheap compared to `handle_ack`, which also decrypts and verifies our value.
+    pub fn validate_ack(&self, ack: &Ack) -> Result<(), AckFault> {
+        self.validate_ack_values(&ack.1)
+    }
+
+    /// Creates a new `SyncKeyGen` instance, without generating our own `Part`.
+    fn new_without_part(
+        our_id: N,
+        sec_key: Arc<dyn Signer>,
+        pub_keys: BTreeMap<N, PublicKey>,
+        threshold: usize,
+    ) -> SyncKeyGen<N> {
+        let our_idx = pub_keys
+            .keys()
+            .position(|id| *id == our_id)
+            .map(|idx| idx as u64);
+        SyncKeyGen {
+            our_id,
+            our_idx,
+            sec_key,
+            pub_keys,
+            parts: BTreeMap::new(),
+            threshold,
+            reshare: None,
+        }
+    }
+
+    /// Returns the `Part` message for the given polynomial, with each row encrypted to its node.
+    fn encrypt_part<R: rand::Rng>(&self, our_part: &BivarPoly, rng: &mut R) -> Result<Part, Error> {
+        let commit = our_part.commitment();
+        let encrypt = |(i, pk): (usize, &PublicKey)| {
+            let row = our_part.row(i + 1);
+            let ser_row = SecretBytes::new(bincode::serialize(&row)?);
+            Ok(pk.encrypt_with_rng(rng, &*ser_row))
+        };
+        let rows = self
+            .pub_keys
+            .values()
+            .enumerate()
+            .map(encrypt)
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Part(commit, rows, None))
+    }
+
+    /// Checks the structure of a `Part` message's fields. See `validate_part`.
+    fn validate_part_parts(
+        &self,
+        commit: &BivarCommitment,
+        rows: &[Ciphertext],
+        opt_offset: Option<&Vec<u8>>,
+    ) -> Result<(), PartFault> {
+        if rows.len() != self.pub_keys.len() {
+            return Err(PartFault::RowCount);
+        }
+        if commit.degree() != self.threshold {
+            return Err(PartFault::CommitmentDegree);
+        }
+        let max_row_size = bincode::serialized_size(&Poly::monomial(self.threshold))
+            .map_err(|_| PartFault::RowSize)?;
+        if rows.iter().any(|row| !fits(row, max_row_size)) {
+            return Err(PartFault::RowSize);
+        }
+        match (&self.reshare, opt_offset) {
+            (None, None) => Ok(()),
+            (Some(_), Some(ser_offset)) if ser_offset.len() as u64 > field_size() => {
+                Err(PartFault::DeserializeOffset)
+            }
+            (Some(_), Some(_)) => Ok(()),
+            (_, _) => Err(PartFault::Offset),
+        }
+    }
+
+    /// Checks the structure of an `Ack` message's values. See `validate_ack`.
+    fn validate_ack_values(&self, values: &[Ciphertext]) -> Result<(), AckFault> {
+        if values.len() != self.pub_keys.len() {
+            return Err(AckFault::ValueCount);
+        }
+        if values.iter().any(|value| !fits(value, field_size())) {
+            return Err(AckFault::ValueSize);
+        }
+        Ok(())
     }
 
     /// Returns the index of the node, or `None` if it is unknown.
@@ -411,6 +777,29 @@ impl<N: NodeIdT> SyncKeyGen<N> {
             .map(|idx| idx as u64)
     }
 
+    /// Returns the index of the proposer, or `None` if it is unknown. If we are resharing, this
+    /// is the index in the existing key set.
+    fn proposer_index(&self, proposer_id: &N) -> Option<u64> {
+        match self.reshare {
+            Some(ref reshare) => reshare.dealers.get(proposer_id).cloned(),
+            None => self.node_index(proposer_id),
+        }
+    }
+
+    /// Returns the IDs of all proposers. If we are resharing, these are the existing validators.
+    fn proposer_ids(&self) -> Vec<&N> {
+        match self.reshare {
+            Some(ref reshare) => reshare.dealers.keys().collect(),
+            None => self.pub_keys.keys().collect(),
+        }
+    }
+
+    /// Returns the state of the given proposer's part, if it has been handled.
+    fn proposal(&self, proposer_id: &N) -> Option<&ProposalState> {
+        self.proposer_index(proposer_id)
+            .and_then(|idx| self.parts.get(&idx))
+    }
+
     /// Returns the number of complete parts. If this is at least `threshold + 1`, the keys can
     /// be generated, but it is possible to wait for more to increase security.
     pub fn count_complete(&self) -> usize {
@@ -420,16 +809,76 @@ impl<N: NodeIdT> SyncKeyGen<N> {
             .count()
     }
 
+    /// Returns the number of parts that have been handled.
+    pub fn count_parts(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Returns the total number of acks that have been handled, for all parts.
+    pub fn count_acks(&self) -> usize {
+        self.parts.values().map(|part| part.acks.len()).sum()
+    }
+
     /// Returns `true` if the part of the given node is complete.
     pub fn is_node_ready(&self, proposer_id: &N) -> bool {
-        self.node_index(proposer_id)
-            .and_then(|proposer_idx| self.parts.get(&proposer_idx))
+        self.proposal(proposer_id)
             .map_or(false, |part| part.is_complete(self.threshold))
     }
 
+    /// Returns the IDs of the proposers whose `Part` has been handled.
+    pub fn received_parts(&self) -> impl Iterator<Item = &N> {
+        self.proposer_ids()
+            .into_iter()
+            .filter(move |id| self.proposal(id).is_some())
+    }
+
+    /// Returns the IDs of the proposers whose `Part` has not been handled yet.
+    pub fn missing_parts(&self) -> impl Iterator<Item = &N> {
+        self.proposer_ids()
+            .into_iter()
+            .filter(move |id| self.proposal(id).is_none())
+    }
+
+    /// Returns the IDs of the nodes that have not acked the given proposer's `Part` yet. If the
+    /// part itself has not been handled, this is all nodes.
+    pub fn missing_acks<'a>(&'a self, proposer_id: &N) -> impl Iterator<Item = &'a N> {
+        let acks = self.proposal(proposer_id).map(|part| &part.acks);
+        self.pub_keys
+            .keys()
+            .enumerate()
+            .filter(move |(idx, _)| acks.filter(|acks| acks.contains(&(*idx as u64))).is_none())
+            .map(|(_, id)| id)
+    }
+
+    /// Returns `true` if `is_ready` returned `true` and we have received enough values to compute
+    /// our secret key share from every part that `generate` would use. Until then, `generate`
+    /// would return an incorrect share. If we are an observer, we don't need any values.
+    pub fn is_share_ready(&self) -> bool {
+        if !self.is_ready() {
+            return false;
+        }
+        let take = match self.reshare {
+            Some(ref reshare) => reshare.pk_set.threshold() + 1,
+            None => self.parts.len(),
+        };
+        self.our_idx.is_none()
+            || self
+                .parts
+                .values()
+                .filter(|part| part.is_complete(self.threshold))
+                .take(take)
+                .all(|part| part.values.len() > self.threshold)
+    }
+
     /// Returns `true` if enough parts are complete to safely generate the new key.
+    ///
+    /// If we are resharing, this is the threshold of the existing key set instead.
     pub fn is_ready(&self) -> bool {
-        self.count_complete() > self.threshold
+        let threshold = match self.reshare {
+            Some(ref reshare) => reshare.pk_set.threshold(),
+            None => self.threshold,
+        };
+        self.count_complete() > threshold
     }
 
     /// Returns the new secret key share and the public key set.
@@ -441,26 +890,113 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     ///
     /// All participating nodes must have handled the exact same sequence of `Part` and `Ack`
     /// messages before calling this method. Otherwise their key shares will not match.
+    ///
+    /// If we are resharing, an error is returned unless enough parts are complete, and the public
+    /// key set has the same public master key as the existing one.
     pub fn generate(&self) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error> {
+        if let Some(ref reshare) = self.reshare {
+            return self.generate_reshared(reshare);
+        }
+        let is_complete = |part: &&ProposalState| part.is_complete(self.threshold);
+        Ok(self.generate_from_states(self.parts.values().filter(is_complete)))
+    }
+
+    /// Returns the key set generated from the parts of the given proposers, whether or not they
+    /// are complete. Returns an error if any of them is unknown, or if we have no part from it.
+    ///
+    /// This is used by `AsyncKeyGen`, which doesn't count `Ack`s to select the parts, but agrees
+    /// on them instead. We must have received enough values for each of them: see `has_values`.
+    pub(crate) fn generate_from<'a, I>(
+        &self,
+        proposer_ids: I,
+    ) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error>
+    where
+        I: IntoIterator<Item = &'a N>,
+        N: 'a,
+    {
+        let parts = proposer_ids
+            .into_iter()
+            .map(|id| {
+                let idx = self.proposer_index(id).ok_or(Error::UnknownSender)?;
+                self.parts.get(&idx).ok_or(Error::UnknownSender)
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(self.generate_from_states(parts))
+    }
+
+    /// Returns `true` if we have received enough valid values to compute our share of the given
+    /// proposer's part, or if we are an observer.
+    pub(crate) fn has_values(&self, proposer_id: &N) -> bool {
+        self.our_idx.is_none()
+            || self
+                .proposal(proposer_id)
+                .filter(|part| part.values.len() > self.threshold)
+                .is_some()
+    }
+
+    /// Returns the sum of the given parts' public key sets, and our share of their sum.
+    fn generate_from_states<'a, I>(&self, parts: I) -> (PublicKeySet, Option<SecretKeyShare>)
+    where
+        I: IntoIterator<Item = &'a ProposalState>,
+    {
         let mut pk_commit = Poly::zero().commitment();
         let mut opt_sk_val = self.our_idx.map(|_| Fr::zero());
-        let is_complete = |part: &&ProposalState| part.is_complete(self.threshold);
-        for part in self.parts.values().filter(is_complete) {
+        for part in parts {
             pk_commit += part.commit.row(0);
             if let Some(sk_val) = opt_sk_val.as_mut() {
-                let row = Poly::interpolate(part.values.iter().take(self.threshold + 1));
-                sk_val.add_assign(&row.evaluate(0));
+                let mut val = self.column_value(part);
+                sk_val.add_assign(&val);
+                secret::clear_fr(&mut val);
             }
         }
-        let opt_sk = if let Some(mut fr) = opt_sk_val {
-            let sk = SecretKeyShare::from_mut(&mut fr);
-            Some(sk)
-        } else {
-            None
-        };
+        // `from_mut` clears the value, so no copy of our key share is left behind.
+        let opt_sk = opt_sk_val.as_mut().map(SecretKeyShare::from_mut);
+        (pk_commit.into(), opt_sk)
+    }
+
+    /// Returns the key set that reshares the existing secret master key: the sum of the first
+    /// `t + 1` complete parts, each multiplied by its Lagrange coefficient, where `t` is the
+    /// existing threshold.
+    fn generate_reshared(
+        &self,
+        reshare: &Reshare<N>,
+    ) -> Result<(PublicKeySet, Option<SecretKeyShare>), Error> {
+        let old_threshold = reshare.pk_set.threshold();
+        let parts: Vec<_> = self
+            .parts
+            .iter()
+            .filter(|(_, part)| part.is_complete(self.threshold))
+            .take(old_threshold + 1)
+            .collect();
+        if parts.len() <= old_threshold {
+            return Err(Error::NotEnoughReshares);
+        }
+        let mut pk_commit = Poly::zero().commitment();
+        let mut opt_sk_val = self.our_idx.map(|_| Fr::zero());
+        for &(idx, part) in &parts {
+            let coeff = lagrange_coefficient(*idx, parts.iter().map(|(idx, _)| **idx));
+            let commit = part.commit.row(0) + Poly::constant(part.offset).commitment();
+            pk_commit += mul_commitment(&commit, coeff)?;
+            if let Some(sk_val) = opt_sk_val.as_mut() {
+                let mut val = self.column_value(part);
+                val.add_assign(&part.offset);
+                val.mul_assign(&coeff);
+                sk_val.add_assign(&val);
+                secret::clear_fr(&mut val);
+            }
+        }
+        let opt_sk = opt_sk_val.as_mut().map(SecretKeyShare::from_mut);
         Ok((pk_commit.into(), opt_sk))
     }
 
+    /// Returns the value at `0` of our column of the part's polynomial, interpolated from the
+    /// values in the `Ack`s.
+    fn column_value(&self, part: &ProposalState) -> Fr {
+        let values = part.values.iter().map(|(idx, val)| (*idx, val.0));
+        let column = Poly::interpolate(values.take(self.threshold + 1));
+        column.evaluate(0)
+    }
+
     /// Consumes the instance, generates the key set and returns a new `NetworkInfo` with the new
     /// keys.
     ///
@@ -468,7 +1004,14 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     /// messages before calling this method. Otherwise their key shares will not match.
     pub fn into_network_info(self) -> Result<NetworkInfo<N>, Error> {
         let (pk_set, sk_share) = self.generate()?;
-        let netinfo = NetworkInfo::new(self.our_id, sk_share, pk_set, self.sec_key, self.pub_keys);
+        let share_signer = sk_share.map(|sks| Arc::new(sks) as Arc<dyn ShareSigner>);
+        let netinfo = NetworkInfo::with_signers(
+            self.our_id,
+            share_signer,
+            pk_set,
+            self.sec_key,
+            self.pub_keys,
+        );
         Ok(netinfo)
     }
 
@@ -481,20 +1024,35 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     fn handle_part_or_fault(
         &mut self,
         sender_idx: u64,
-        Part(commit, rows): Part,
+        Part(commit, rows, opt_offset): Part,
     ) -> Result<Option<Poly>, PartFault> {
-        if rows.len() != self.pub_keys.len() {
-            return Err(PartFault::RowCount);
-        }
+        self.validate_part_parts(&commit, &rows, opt_offset.as_ref())?;
         if let Some(state) = self.parts.get(&sender_idx) {
             if state.commit != commit {
                 return Err(PartFault::MultipleParts);
             }
             return Ok(None); // We already handled this `Part` before.
         }
+        let offset = match (&self.reshare, opt_offset) {
+            (None, None) => Fr::zero(),
+            (Some(reshare), Some(ser_offset)) => {
+                let offset = bincode::deserialize::<FieldWrap<Fr>>(&ser_offset)
+                    .map_err(|_| PartFault::DeserializeOffset)?
+                    .into_inner();
+                // The offset constant term must commit to the proposer's existing key share.
+                let const_commit = commit.row(0) + Poly::constant(offset).commitment();
+                let pk_share = reshare.pk_set.public_key_share(sender_idx);
+                if PublicKeySet::from(const_commit).public_key().to_bytes() != pk_share.to_bytes() {
+                    return Err(PartFault::ShareCommitment);
+                }
+                offset
+            }
+            (_, _) => unreachable!("validated by validate_part_parts"),
+        };
         // Retrieve our own row's commitment, and store the full commitment.
         let opt_idx_commit_row = self.our_idx.map(|idx| (idx, commit.row(idx + 1)));
-        self.parts.insert(sender_idx, ProposalState::new(commit));
+        self.parts
+            .insert(sender_idx, ProposalState::new(commit, offset));
         let (our_idx, commit_row) = match opt_idx_commit_row {
             Some((idx, row)) => (idx, row),
             None => return Ok(None), // We are only an observer. Nothing to send or decrypt.
@@ -503,6 +1061,7 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         let ser_row = self
             .sec_key
             .decrypt(&rows[our_idx as usize])
+            .map(SecretBytes::new)
             .ok_or(PartFault::DecryptRow)?;
         let row: Poly = bincode::deserialize(&ser_row).map_err(|_| PartFault::DeserializeRow)?;
         if row.commitment() != commit_row {
@@ -512,14 +1071,16 @@ impl<N: NodeIdT> SyncKeyGen<N> {
     }
 
     /// Handles an `Ack` message, or returns an `AckFault` if it is invalid.
+    ///
+    /// The commitments to our row of each proposer's polynomial are cached in `commit_rows`, so
+    /// that they can be reused when handling several `Ack`s.
     fn handle_ack_or_fault(
         &mut self,
         sender_idx: u64,
         Ack(proposer_idx, values): Ack,
+        commit_rows: &mut BTreeMap<u64, Commitment>,
     ) -> Result<(), AckFault> {
-        if values.len() != self.pub_keys.len() {
-            return Err(AckFault::ValueCount);
-        }
+        self.validate_ack_values(&values)?;
         let part = self
             .parts
             .get_mut(&proposer_idx)
@@ -535,11 +1096,15 @@ impl<N: NodeIdT> SyncKeyGen<N> {
         let ser_val = self
             .sec_key
             .decrypt(&values[our_idx as usize])
+            .map(SecretBytes::new)
             .ok_or(AckFault::DecryptValue)?;
         let val = bincode::deserialize::<FieldWrap<Fr>>(&ser_val)
-            .map_err(|_| AckFault::DeserializeValue)?
-            .into_inner();
-        if part.commit.evaluate(our_idx + 1, sender_idx + 1) != G1Affine::one().mul(val) {
+            .map(|val| SecretFr(val.into_inner()))
+            .map_err(|_| AckFault::DeserializeValue)?;
+        let commit_row = commit_rows
+            .entry(proposer_idx)
+            .or_insert_with(|| part.commit.row(our_idx + 1));
+        if commit_row.evaluate(sender_idx + 1) != G1Affine::one().mul(val.0) {
             return Err(AckFault::ValueCommitment);
         }
         part.values.insert(sender_idx + 1, val);
@@ -565,6 +1130,9 @@ pub enum AckFault {
     /// Value doesn't match the commitment.
     #[fail(display = "Value doesn't match the commitment")]
     ValueCommitment,
+    /// An encrypted value is larger than a single field element.
+    #[fail(display = "An encrypted value is too large")]
+    ValueSize,
 }
 
 /// An error in a `Part` message sent by a faulty node.
@@ -585,4 +1153,72 @@ pub enum PartFault {
     /// Row does not match the commitment.
     #[fail(display = "Row does not match the commitment")]
     RowCommitment,
+    /// The Part has an offset but we are not resharing, or vice versa.
+    #[fail(display = "The Part's offset doesn't match whether we are resharing")]
+    Offset,
+    /// Could not deserialize the offset in the Part message.
+    #[fail(display = "Could not deserialize the offset in the Part message")]
+    DeserializeOffset,
+    /// The Part's constant term doesn't match the proposer's existing key share.
+    #[fail(display = "The constant term doesn't match the existing key share")]
+    ShareCommitment,
+    /// The degree of the commitment differs from the threshold.
+    #[fail(display = "The degree of the commitment differs from the threshold")]
+    CommitmentDegree,
+    /// An encrypted row is larger than a row of the expected degree.
+    #[fail(display = "An encrypted row is too large")]
+    RowSize,
+}
+
+/// Returns the serialized size of a field element.
+fn field_size() -> u64 {
+    bincode::serialized_size(&FieldWrap(Fr::zero())).unwrap_or(0)
+}
+
+/// Returns `true` if the ciphertext is not larger than the encryption of a plaintext of the given
+/// size: A ciphertext consists of two group elements and the length-prefixed masked plaintext.
+fn fits(ciphertext: &Ciphertext, max_plaintext_size: u64) -> bool {
+    let max_size = (PK_SIZE + SIG_SIZE) as u64 + 8 + max_plaintext_size;
+    bincode::serialized_size(ciphertext)
+        .ok()
+        .filter(|size| *size <= max_size)
+        .is_some()
+}
+
+/// Returns the value at `0` of the Lagrange polynomial that is `1` at `idx + 1` and `0` at all
+/// other `indices + 1`. The indices must be distinct.
+fn lagrange_coefficient<I: IntoIterator<Item = u64>>(idx: u64, indices: I) -> Fr {
+    let x = (idx + 1).into_fr();
+    let mut num = Fr::one();
+    let mut denom = Fr::one();
+    for other_x in indices
+        .into_iter()
+        .filter(|i| *i != idx)
+        .map(|i| (i + 1).into_fr())
+    {
+        num.mul_assign(&other_x);
+        let mut diff = other_x;
+        diff.sub_assign(&x);
+        denom.mul_assign(&diff);
+    }
+    num.mul_assign(&denom.inverse().expect("indices are distinct"));
+    num
+}
+
+/// Returns the commitment multiplied by the scalar.
+///
+/// `Commitment` only supports addition, so this doubles and adds, for each bit of the scalar's
+/// serialized little-endian representation.
+fn mul_commitment(commit: &Commitment, scalar: Fr) -> Result<Commitment, Error> {
+    let bytes = bincode::serialize(&FieldWrap(scalar))?;
+    let mut result = Poly::zero().commitment();
+    for byte in bytes.iter().rev() {
+        for bit in (0..8).rev() {
+            result = &result + &result;
+            if byte & (1 << bit) != 0 {
+                result += commit;
+            }
+        }
+    }
+    Ok(result)
 }
@@ -10,6 +10,12 @@
 //! validator holds a secret key share, and uses it to produce and multicast a decryption share once
 //! a ciphertext is provided. The algorithm outputs as soon as it receives a ciphertext and _f + 1_
 //! threshold shares.
+//!
+//! Besides decrypting the contributions in `HoneyBadger`, applications can use it with the
+//! validators' keys to decrypt any data that was encrypted to the network's public key, e.g.
+//! sealed bids that must only be opened once the bidding has closed: Create an instance with
+//! `ThresholdDecrypt::new`, and call `decrypt` with the ciphertext on every validator. Invalid
+//! decryption shares are reported in the fault log, and ignored.
 
 use std::collections::BTreeMap;
 use std::sync::Arc;
@@ -52,8 +58,8 @@ pub enum FaultKind {
     /// `ThresholdDecrypt` received multiple shares from the same sender.
     #[fail(display = "`ThresholdDecrypt` received multiple shares from the same sender.")]
     MultipleDecryptionShares,
-    /// `HoneyBadger` received a decryption share from an unverified sender.
-    #[fail(display = "`HoneyBadger` received a decryption share from an unverified sender.")]
+    /// `ThresholdDecrypt` received an invalid decryption share.
+    #[fail(display = "`ThresholdDecrypt` received an invalid decryption share.")]
     UnverifiedDecryptionShareSender,
 }
 
@@ -146,6 +152,18 @@ impl<N: NodeIdT> ThresholdDecrypt<N> {
         Ok(())
     }
 
+    /// Sets the ciphertext and sends our decryption share. If we have collected enough shares,
+    /// returns the decrypted message.
+    pub fn decrypt(&mut self, ct: Ciphertext) -> Result<Step<N>> {
+        self.set_ciphertext(ct)?;
+        self.start_decryption()
+    }
+
+    /// Returns the information about the node IDs in the network, and the cryptographic keys.
+    pub fn netinfo(&self) -> &Arc<NetworkInfo<N>> {
+        &self.netinfo
+    }
+
     /// Sends our decryption shares to peers, and if we have collected enough, returns the decrypted
     /// message. Returns an error if the ciphertext hasn't been received yet.
     pub fn start_decryption(&mut self) -> Result<Step<N>> {
@@ -157,8 +175,8 @@ impl<N: NodeIdT> ThresholdDecrypt<N> {
         step.fault_log.extend(self.remove_invalid_shares());
         self.had_input = true;
         let opt_idx = self.netinfo.node_index(self.our_id());
-        let (idx, share) = match (opt_idx, self.netinfo.secret_key_share()) {
-            (Some(idx), Some(sks)) => (idx, sks.decrypt_share_no_verify(&ct)),
+        let (idx, share) = match (opt_idx, self.netinfo.share_signer()) {
+            (Some(idx), Some(sks)) => (idx, sks.decrypt_share(&ct)),
             (_, _) => return Ok(step.join(self.try_output()?)), // Not a validator.
         };
         let our_id = self.our_id().clone();
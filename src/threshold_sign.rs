@@ -9,11 +9,27 @@
 //! In addition to signing, this can also be used as a source of pseudorandomness: The signature
 //! cannot be known until more than _f_ validators have contributed their shares.
 //!
+//! Applications can use it with the validators' keys to collectively sign any document, e.g. a
+//! checkpoint of their state or a payload for an external system: Create an instance with
+//! `ThresholdSign::builder`, and call `sign_document` on every validator. The output can be
+//! verified by anyone who knows the public key set, with `public_key().verify(&sig, doc)`.
+//!
 //! ## How it works
 //!
 //! The algorithm uses a threshold signature scheme with the uniqueness property: For each public
 //! key and message, there is exactly one valid signature. This group signature is produced using
 //! signature shares from any combination of _f + 1_ secret key share holders.
+//!
+//! Creating and verifying shares are pairing-based operations, and usually dominate the time spent
+//! in an instance. To take our own share off the critical path, it can be computed in advance and
+//! passed in with `set_our_share`. Our own share is never verified, and a share that is received
+//! again from the same sender is ignored without verifying it a second time.
+//!
+//! With `set_batch_verification`, the received shares are not verified individually at all.
+//! Instead, the first _f + 1_ are combined, and only the resulting signature is verified, which
+//! replaces _f + 1_ share verifications by a single one. Only if that fails, the shares are
+//! verified one by one, to remove and report the invalid ones. Shares that are not needed for the
+//! signature are never verified, so an invalid share is only reported if it was among them.
 
 use std::collections::BTreeMap;
 use std::sync::Arc;
@@ -82,6 +98,10 @@ pub struct ThresholdSign<N> {
     doc_hash: Option<G2>,
     /// All received threshold signature shares, together with the node index.
     received_shares: BTreeMap<N, (usize, SignatureShare)>,
+    /// Our own precomputed signature share, if any.
+    our_share: Option<SignatureShare>,
+    /// Whether shares are only verified individually if their combined signature is invalid.
+    batch_verification: bool,
     /// Whether we already sent our shares.
     had_input: bool,
     /// Termination flag.
@@ -124,13 +144,64 @@ impl<N: NodeIdT> ConsensusProtocol for ThresholdSign<N> {
     }
 }
 
+/// A builder for `ThresholdSign` instances.
+#[derive(Debug)]
+pub struct ThresholdSignBuilder<N> {
+    /// Shared network data.
+    netinfo: Arc<NetworkInfo<N>>,
+    /// The document to sign, if already known.
+    doc: Option<Vec<u8>>,
+    /// Whether to use batch verification.
+    batch_verification: bool,
+}
+
+impl<N: NodeIdT> ThresholdSignBuilder<N> {
+    /// Returns a new builder for instances using the validators and keys in `netinfo`.
+    pub fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
+        ThresholdSignBuilder {
+            netinfo,
+            doc: None,
+            batch_verification: false,
+        }
+    }
+
+    /// Sets the document to sign. Otherwise it needs to be passed to `sign_document` later.
+    pub fn document<M: AsRef<[u8]>>(&mut self, doc: M) -> &mut Self {
+        self.doc = Some(doc.as_ref().to_vec());
+        self
+    }
+
+    /// Enables or disables batch verification: see `ThresholdSign::set_batch_verification`.
+    pub fn batch_verification(&mut self, batch_verification: bool) -> &mut Self {
+        self.batch_verification = batch_verification;
+        self
+    }
+
+    /// Creates a new `ThresholdSign` instance.
+    pub fn build(&self) -> Result<ThresholdSign<N>> {
+        let mut ts = ThresholdSign::new(self.netinfo.clone());
+        ts.set_batch_verification(self.batch_verification);
+        if let Some(ref doc) = self.doc {
+            ts.set_document(doc)?;
+        }
+        Ok(ts)
+    }
+}
+
 impl<N: NodeIdT> ThresholdSign<N> {
+    /// Returns a new builder for instances using the validators and keys in `netinfo`.
+    pub fn builder(netinfo: Arc<NetworkInfo<N>>) -> ThresholdSignBuilder<N> {
+        ThresholdSignBuilder::new(netinfo)
+    }
+
     /// Creates a new instance of `ThresholdSign`, with the goal to collaboratively sign `doc`.
     pub fn new(netinfo: Arc<NetworkInfo<N>>) -> Self {
         ThresholdSign {
             netinfo,
             doc_hash: None,
             received_shares: BTreeMap::new(),
+            our_share: None,
+            batch_verification: false,
             had_input: false,
             terminated: false,
         }
@@ -152,6 +223,39 @@ impl<N: NodeIdT> ThresholdSign<N> {
         Ok(())
     }
 
+    /// Sets the document and sends our signature share. If we have collected enough shares, returns
+    /// the full signature.
+    pub fn sign_document<M: AsRef<[u8]>>(&mut self, doc: M) -> Result<Step<N>> {
+        self.set_document(doc)?;
+        self.sign()
+    }
+
+    /// Returns the information about the node IDs in the network, and the cryptographic keys.
+    pub fn netinfo(&self) -> &Arc<NetworkInfo<N>> {
+        &self.netinfo
+    }
+
+    /// Returns the signature shares received so far, including our own, by sender. Once the
+    /// instance has output the signature, these are the shares it was combined from.
+    pub fn received_shares(&self) -> impl Iterator<Item = (&N, &SignatureShare)> {
+        self.received_shares
+            .iter()
+            .map(|(id, (_, share))| (id, share))
+    }
+
+    /// Sets our own signature share of the document, e.g. because it was computed in advance while
+    /// the node was idle. It is not verified: It must be the signature of the document's
+    /// `hash_g2` by our secret key share.
+    pub fn set_our_share(&mut self, share: SignatureShare) {
+        self.our_share = Some(share);
+    }
+
+    /// Enables or disables batch verification: If enabled, shares are only verified individually
+    /// if the signature combined from them is invalid.
+    pub fn set_batch_verification(&mut self, batch_verification: bool) {
+        self.batch_verification = batch_verification;
+    }
+
     /// Sends our signature shares, and if we have collected enough, returns the full signature.
     /// Returns an error if the message to sign hasn't been received yet.
     pub fn sign(&mut self) -> Result<Step<N>> {
@@ -162,15 +266,21 @@ impl<N: NodeIdT> ThresholdSign<N> {
         let hash = self.doc_hash.ok_or(Error::DocumentHashIsNone)?;
         self.had_input = true;
         let mut step = Step::default();
-        step.fault_log.extend(self.remove_invalid_shares());
-        let msg = match self.netinfo.secret_key_share() {
-            Some(sks) => Message(sks.sign_g2(hash)),
-            None => return Ok(step.join(self.try_output()?)), // Not a validator.
+        if !self.batch_verification {
+            step.fault_log.extend(self.remove_invalid_shares());
+        }
+        let share = match (self.our_share.take(), self.netinfo.share_signer()) {
+            (Some(share), Some(_)) => share,
+            (None, Some(sks)) => sks.sign_g2(hash),
+            (_, None) => return Ok(step.join(self.try_output()?)), // Not a validator.
         };
-        step.messages.push(Target::All.message(msg.clone()));
+        step.messages
+            .push(Target::All.message(Message(share.clone())));
         let id = self.our_id().clone();
-        step.extend(self.handle_message(&id, msg)?);
-        Ok(step)
+        let idx = self.netinfo.node_index(&id).ok_or(Error::UnknownSender)?;
+        // Our own share doesn't need to be verified.
+        self.received_shares.insert(id, (idx, share));
+        Ok(step.join(self.try_output()?))
     }
 
     /// Handles a message with a signature share received from `sender_id`.
@@ -188,7 +298,17 @@ impl<N: NodeIdT> ThresholdSign<N> {
             .netinfo
             .node_index(sender_id)
             .ok_or(Error::UnknownSender)?;
-        if !self.is_share_valid(sender_id, &share) {
+        // Once we have signed, all stored shares have been verified.
+        if self.had_input
+            && self
+                .received_shares
+                .get(sender_id)
+                .filter(|(_, received)| *received == share)
+                .is_some()
+        {
+            return Ok(Step::default()); // We already verified this share.
+        }
+        if !self.batch_verification && !self.is_share_valid(sender_id, &share) {
             let fault_kind = FaultKind::UnverifiedSignatureShareSender;
             return Ok(Fault::new(sender_id.clone(), fault_kind).into());
         }
@@ -230,9 +350,21 @@ impl<N: NodeIdT> ThresholdSign<N> {
             None => return Ok(Step::default()),
         };
         if !self.terminated && self.received_shares.len() > self.netinfo.num_faulty() {
-            let sig = self.combine_and_verify_sig(hash)?;
+            let mut step = Step::default();
+            let sig = match self.combine_and_verify_sig(hash) {
+                Ok(sig) => sig,
+                Err(Error::VerificationFailed) if self.batch_verification => {
+                    // Some of the shares are invalid: Verify them individually.
+                    step.fault_log.extend(self.remove_invalid_shares());
+                    if self.received_shares.len() <= self.netinfo.num_faulty() {
+                        return Ok(step);
+                    }
+                    self.combine_and_verify_sig(hash)?
+                }
+                Err(err) => return Err(err),
+            };
             self.terminated = true;
-            let step = self.sign()?; // Before terminating, make sure we sent our share.
+            step.extend(self.sign()?); // Before terminating, make sure we sent our share.
             debug!("{} output {:?}", self, sig);
             Ok(step.with_output(sig))
         } else {
@@ -264,16 +264,18 @@ where
                         }
                     }
                 }
-                Target::All => {
+                Target::All | Target::AllExcept(_) => {
                     let is_accepted = |&them| msg.message.is_accepted(them, max_future_epochs);
                     let is_premature = |&them| msg.message.is_premature(them, max_future_epochs);
                     let is_obsolete = |&them| msg.message.is_obsolete(them);
-                    if peer_epochs.values().all(is_accepted) {
+                    let recipients =
+                        || peer_epochs.iter().filter(|(id, _)| msg.target.contains(id));
+                    if recipients().all(|(_, them)| is_accepted(them)) {
                         passed_msgs.push(msg);
                     } else {
-                        // The `Target::All` message is split into two sets of point messages: those
-                        // which can be sent without delay and those which should be postponed.
-                        for (id, them) in peer_epochs {
+                        // The message is split into two sets of point messages: those which can
+                        // be sent without delay and those which should be postponed.
+                        for (id, them) in recipients() {
                             if is_premature(them) {
                                 deferred_msgs.push((id.clone(), msg.message.clone()));
                             } else if !is_obsolete(them) {
@@ -1,7 +1,8 @@
 //! An interface for a transaction queue
 
+use std::cmp::{self, Reverse};
 use std::collections::HashSet;
-use std::{cmp, fmt};
+use std::{fmt, io};
 
 use rand::{self, seq::SliceRandom, Rng};
 
@@ -10,9 +11,17 @@ use crate::Contribution;
 /// An interface to the transaction queue. A transaction queue is a structural part of
 /// `QueueingHoneyBadger` that manages enqueueing of transactions for a future batch and dequeueing
 /// of transactions to become part of a current batch.
-pub trait TransactionQueue<T>: fmt::Debug + Default + Extend<T> + Sync + Send {
+///
+/// New transactions are added with `Extend`. `QueueingHoneyBadger` calls `choose` to select its
+/// proposal for each epoch, and `remove_multiple` with the transactions of each output batch, both
+/// its own and the other validators'. An implementation that doesn't hold all transactions in
+/// memory, or orders them by its own criteria, only needs to make sure that transactions are
+/// eventually chosen, so that they are committed.
+pub trait TransactionQueue<T>: fmt::Debug + Extend<T> + Sync + Send {
     /// Checks whether the queue is empty.
     fn is_empty(&self) -> bool;
+    /// Returns the number of pending transactions.
+    fn len(&self) -> usize;
     /// Returns a new set of `amount` transactions, randomly chosen from the first `batch_size`.
     /// No transactions are removed from the queue.
     // TODO: Return references, once the `HoneyBadger` API accepts them.
@@ -22,6 +31,25 @@ pub trait TransactionQueue<T>: fmt::Debug + Default + Extend<T> + Sync + Send {
     where
         I: IntoIterator<Item = &'a T>,
         T: 'a + Contribution;
+    /// Keeps only the transactions for which `f` returns `true`.
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool;
+}
+
+/// Persistent storage for the pending transactions, so that they survive a restart.
+///
+/// `QueueingHoneyBadger` puts each transaction that is added to its queue, and removes it once it
+/// is committed, expired or removed by the application. On startup, it loads the stored ones
+/// into its queue again. Removing a transaction that is not stored must not fail, since other
+/// validators' transactions that we don't know are removed, too.
+pub trait TransactionJournal<T>: Send + Sync {
+    /// Stores a new pending transaction.
+    fn put(&mut self, tx: &T) -> io::Result<()>;
+    /// Removes a transaction, if it is stored.
+    fn remove(&mut self, tx: &T) -> io::Result<()>;
+    /// Returns all stored transactions.
+    fn transactions(&self) -> io::Result<Vec<T>>;
 }
 
 impl<T> TransactionQueue<T> for Vec<T>
@@ -33,6 +61,11 @@ where
         self.is_empty()
     }
 
+    #[inline]
+    fn len(&self) -> usize {
+        self.len()
+    }
+
     #[inline]
     fn remove_multiple<'a, I>(&mut self, txs: I)
     where
@@ -40,7 +73,15 @@ where
         T: 'a + Contribution,
     {
         let tx_set: HashSet<_> = txs.into_iter().collect();
-        self.retain(|tx| !tx_set.contains(tx));
+        Vec::retain(self, |tx| !tx_set.contains(tx));
+    }
+
+    #[inline]
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        Vec::retain(self, f);
     }
 
     // TODO: Return references, once the `HoneyBadger` API accepts them. Remove `Clone` bound.
@@ -51,3 +92,302 @@ where
         sample.cloned().collect()
     }
 }
+
+/// A transaction that belongs to one of several lanes, e.g. system, user and bulk transactions.
+pub trait LaneTransaction {
+    /// Returns the index of the transaction's lane.
+    fn lane(&self) -> usize;
+}
+
+/// A transaction queue with several lanes, each of which is guaranteed a share of every proposal,
+/// so that low-volume, high-priority transactions are not starved by bulk ones.
+///
+/// Each lane `i` gets _a s<sub>i</sub> / S_ of the `amount` of transactions chosen for a proposal,
+/// where _s<sub>i</sub>_ is its share and _S_ the sum of all shares. Any remaining budget, e.g. if
+/// a lane has fewer transactions than its share, goes to the lanes in order, so lower indices have
+/// precedence. Transactions with a lane index beyond the configured ones are put in the last lane.
+#[derive(Clone, Debug)]
+pub struct LaneQueue<T> {
+    /// The pending transactions in each lane.
+    lanes: Vec<Vec<T>>,
+    /// Each lane's share of a proposal.
+    shares: Vec<usize>,
+}
+
+impl<T> LaneQueue<T> {
+    /// Returns a new queue with one lane for each of the given shares.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shares` is empty.
+    pub fn new(shares: Vec<usize>) -> Self {
+        assert!(!shares.is_empty(), "A lane queue needs at least one lane.");
+        LaneQueue {
+            lanes: shares.iter().map(|_| Vec::new()).collect(),
+            shares,
+        }
+    }
+
+    /// Returns the pending transactions in the given lane.
+    pub fn lane(&self, lane: usize) -> &[T] {
+        self.lanes.get(lane).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the index of the lane the transaction is stored in.
+    fn lane_index(&self, tx: &T) -> usize
+    where
+        T: LaneTransaction,
+    {
+        cmp::min(tx.lane(), self.lanes.len() - 1)
+    }
+}
+
+impl<T> Default for LaneQueue<T> {
+    /// Returns a queue with a single lane.
+    fn default() -> Self {
+        LaneQueue::new(vec![1])
+    }
+}
+
+impl<T: LaneTransaction> Extend<T> for LaneQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, txs: I) {
+        for tx in txs {
+            let lane = self.lane_index(&tx);
+            self.lanes[lane].push(tx);
+        }
+    }
+}
+
+impl<T> TransactionQueue<T> for LaneQueue<T>
+where
+    T: LaneTransaction + Clone + fmt::Debug + Sync + Send,
+{
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.lanes.iter().all(Vec::is_empty)
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.lanes.iter().map(Vec::len).sum()
+    }
+
+    #[inline]
+    fn remove_multiple<'a, I>(&mut self, txs: I)
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a + Contribution,
+    {
+        let mut tx_sets: Vec<HashSet<_>> = self.lanes.iter().map(|_| HashSet::new()).collect();
+        for tx in txs {
+            tx_sets[self.lane_index(tx)].insert(tx);
+        }
+        for (lane, tx_set) in self.lanes.iter_mut().zip(tx_sets) {
+            if !tx_set.is_empty() {
+                lane.retain(|tx| !tx_set.contains(tx));
+            }
+        }
+    }
+
+    #[inline]
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        for lane in &mut self.lanes {
+            lane.retain(|tx| f(tx));
+        }
+    }
+
+    /// Chooses the transactions from each lane randomly from its first `batch_size` entries.
+    fn choose<R: Rng>(&mut self, rng: &mut R, amount: usize, batch_size: usize) -> Vec<T> {
+        let total_shares = cmp::max(1, self.shares.iter().sum());
+        let available: Vec<usize> = self
+            .lanes
+            .iter()
+            .map(|lane| cmp::min(batch_size, lane.len()))
+            .collect();
+        // First give each lane its guaranteed share, then distribute the remaining budget.
+        let mut counts: Vec<usize> = self
+            .shares
+            .iter()
+            .zip(&available)
+            .map(|(share, avail)| cmp::min(amount * share / total_shares, *avail))
+            .collect();
+        let mut remaining = amount - counts.iter().sum::<usize>();
+        for (count, avail) in counts.iter_mut().zip(&available) {
+            let extra = cmp::min(remaining, avail - *count);
+            *count += extra;
+            remaining -= extra;
+        }
+        let mut chosen = Vec::with_capacity(amount - remaining);
+        for ((lane, avail), count) in self.lanes.iter().zip(available).zip(counts) {
+            chosen.extend(lane[..avail].choose_multiple(rng, count).cloned());
+        }
+        chosen
+    }
+}
+
+/// A transaction with a priority, e.g. based on its fee or deadline.
+pub trait PriorityTransaction {
+    /// Returns the transaction's priority. Higher values are proposed first.
+    ///
+    /// This is called whenever transactions are chosen for a proposal, so the priority may change
+    /// over time, e.g. as a deadline approaches.
+    fn priority(&self) -> u64;
+}
+
+/// A transaction queue that proposes the transactions with the highest priority first.
+///
+/// The transactions are chosen from the first `batch_size` ones by priority. Within each priority,
+/// the choice is random, so that the validators don't all propose the same transactions, and
+/// equal-priority transactions can't be censored by a single validator.
+#[derive(Clone, Debug)]
+pub struct PriorityQueue<T> {
+    /// The pending transactions, in the order they were added.
+    txs: Vec<T>,
+}
+
+impl<T> PriorityQueue<T> {
+    /// Returns a new, empty queue.
+    pub fn new() -> Self {
+        PriorityQueue { txs: Vec::new() }
+    }
+
+    /// Returns the pending transactions, in the order they were added.
+    pub fn transactions(&self) -> &[T] {
+        &self.txs
+    }
+}
+
+impl<T> Default for PriorityQueue<T> {
+    fn default() -> Self {
+        PriorityQueue::new()
+    }
+}
+
+impl<T> Extend<T> for PriorityQueue<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, txs: I) {
+        self.txs.extend(txs);
+    }
+}
+
+impl<T> TransactionQueue<T> for PriorityQueue<T>
+where
+    T: PriorityTransaction + Clone + fmt::Debug + Sync + Send,
+{
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    #[inline]
+    fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    #[inline]
+    fn remove_multiple<'a, I>(&mut self, txs: I)
+    where
+        I: IntoIterator<Item = &'a T>,
+        T: 'a + Contribution,
+    {
+        self.txs.remove_multiple(txs);
+    }
+
+    #[inline]
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.txs.retain(f);
+    }
+
+    /// Chooses the transactions with the highest priorities among the first `batch_size`, and
+    /// randomly among the transactions with the lowest of the chosen priorities.
+    fn choose<R: Rng>(&mut self, rng: &mut R, amount: usize, batch_size: usize) -> Vec<T> {
+        let mut by_priority: Vec<(u64, &T)> =
+            self.txs.iter().map(|tx| (tx.priority(), tx)).collect();
+        // The sort is stable, so among equal priorities, the older transactions come first.
+        by_priority.sort_by_key(|&(priority, _)| Reverse(priority));
+        by_priority.truncate(batch_size);
+        let mut chosen = Vec::with_capacity(cmp::min(amount, by_priority.len()));
+        let mut start = 0;
+        while start < by_priority.len() && chosen.len() < amount {
+            let priority = by_priority[start].0;
+            let len = by_priority[start..]
+                .iter()
+                .take_while(|&&(p, _)| p == priority)
+                .count();
+            let class = &by_priority[start..(start + len)];
+            let count = cmp::min(amount - chosen.len(), len);
+            chosen.extend(class.choose_multiple(rng, count).map(|&(_, tx)| tx.clone()));
+            start += len;
+        }
+        chosen
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LaneQueue, LaneTransaction, PriorityQueue, PriorityTransaction, TransactionQueue};
+
+    impl LaneTransaction for (usize, u32) {
+        fn lane(&self) -> usize {
+            self.0
+        }
+    }
+
+    impl PriorityTransaction for (usize, u32) {
+        fn priority(&self) -> u64 {
+            self.0 as u64
+        }
+    }
+
+    #[test]
+    fn test_lane_quotas() {
+        let mut rng = rand::thread_rng();
+        let mut queue = LaneQueue::new(vec![1, 3]);
+        queue.extend((0..100).map(|i| (1, i)));
+        queue.extend((0..2).map(|i| (0, i)));
+        // Lane 0 is guaranteed a quarter of the budget, even though lane 1 has more transactions.
+        let chosen = queue.choose(&mut rng, 8, 50);
+        assert_eq!(8, chosen.len());
+        assert_eq!(2, chosen.iter().filter(|tx| tx.0 == 0).count());
+        // Unused budget goes to the other lanes.
+        queue.remove_multiple(&[(0, 0), (0, 1)]);
+        assert!(queue.lane(0).is_empty());
+        let chosen = queue.choose(&mut rng, 8, 50);
+        assert!(chosen.iter().all(|tx| tx.0 == 1));
+        assert_eq!(8, chosen.len());
+        // Unknown lanes are mapped to the last one.
+        queue.extend(Some((5, 0)));
+        assert_eq!(101, queue.lane(1).len());
+    }
+
+    #[test]
+    fn test_priorities() {
+        let mut rng = rand::thread_rng();
+        let mut queue = PriorityQueue::new();
+        queue.extend((0..20).map(|i| (1, i)));
+        queue.extend((0..3).map(|i| (2, i)));
+        queue.extend((0..20).map(|i| (0, i)));
+        // All transactions with priority 2 are chosen, and randomly five with priority 1.
+        let chosen = queue.choose(&mut rng, 8, 50);
+        assert_eq!(8, chosen.len());
+        assert_eq!(3, chosen.iter().filter(|tx| tx.0 == 2).count());
+        assert_eq!(5, chosen.iter().filter(|tx| tx.0 == 1).count());
+        // Only the first `batch_size` by priority are considered.
+        let chosen = queue.choose(&mut rng, 8, 4);
+        assert_eq!(4, chosen.len());
+        assert_eq!(
+            vec![(1, 0)],
+            chosen
+                .into_iter()
+                .filter(|tx| tx.0 == 1)
+                .collect::<Vec<_>>()
+        );
+        queue.remove_multiple(&[(2, 0), (2, 1), (2, 2)]);
+        assert_eq!(40, queue.transactions().len());
+    }
+}
@@ -7,11 +7,29 @@ use std::fmt;
 
 use hex_fmt::HexFmt;
 
+/// Whether the crate was built with the `use-insecure-test-only-mock-crypto` feature.
+///
+/// With that feature, `threshold_crypto` replaces pairings by a trivial, insecure mock: Encryption
+/// does not hide anything and signatures can be forged. It is meant for tests and large-scale
+/// simulations of the protocol logic only, and must never be enabled in a production build.
+pub const INSECURE_MOCK_CRYPTO: bool = cfg!(feature = "use-insecure-test-only-mock-crypto");
+
 /// Prints a byte slice as shortened hexadecimal in debug output.
 pub fn fmt_hex<T: AsRef<[u8]>>(bytes: T, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     write!(f, "{:10}", HexFmt(bytes))
 }
 
+/// Returns the lower median of the given values: the middle one of an odd number of values, or the
+/// lower of the two middle ones of an even number. Returns `None` if there are no values.
+pub fn lower_median<T: Ord>(mut values: Vec<T>) -> Option<T> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let index = (values.len() - 1) / 2;
+    Some(values.swap_remove(index))
+}
+
 /// Given a number of nodes, returns the maximum number of faulty nodes that can be tolerated: the
 /// greatest number less than one third of `n`.
 ///
@@ -0,0 +1,265 @@
+//! # Witness mode
+//!
+//! A node can record every input and message it handles, together with the outputs it produced
+//! in response. Given such a log, a _witness_ replays the inputs and messages in the same order on
+//! a fresh instance of the algorithm, and checks that it produces exactly the recorded outputs.
+//! Since the outputs of all algorithms in this crate are determined by the inputs and messages a
+//! node received, any difference means that the recorded outputs were not the correct ones, e.g.
+//! because the node that recorded them was faulty, or the log was tampered with.
+//!
+//! The witness instance needs the same `NetworkInfo` as the node that recorded the log, and its
+//! own outgoing messages are discarded. The result is a `WitnessReport`, which lists each
+//! divergence, and the faults the witness detected in the replayed messages. This is intended
+//! for forensics after an incident, not for use in a running network.
+
+use failure::Fail;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{ConsensusProtocol, FaultLog, Step};
+
+/// A recorded input or message, together with the outputs it caused.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum LogEntry<I, M, N, O> {
+    /// An input to the node.
+    Input {
+        /// The input.
+        input: I,
+        /// The outputs the node produced when handling the input.
+        outputs: Vec<O>,
+    },
+    /// A message the node received.
+    Message {
+        /// The sender of the message.
+        sender_id: N,
+        /// The message.
+        message: M,
+        /// The outputs the node produced when handling the message.
+        outputs: Vec<O>,
+    },
+}
+
+/// A log entry for the algorithm `D`.
+pub type ProtocolLogEntry<D> = LogEntry<
+    <D as ConsensusProtocol>::Input,
+    <D as ConsensusProtocol>::Message,
+    <D as ConsensusProtocol>::NodeId,
+    <D as ConsensusProtocol>::Output,
+>;
+
+impl<I, M, N, O> LogEntry<I, M, N, O> {
+    /// Records an input and the step that handling it returned.
+    pub fn input<F: Fail>(input: I, step: &Step<M, O, N, F>) -> Self
+    where
+        O: Clone,
+    {
+        LogEntry::Input {
+            input,
+            outputs: step.output.clone(),
+        }
+    }
+
+    /// Records a message and the step that handling it returned.
+    pub fn message<F: Fail>(sender_id: N, message: M, step: &Step<M, O, N, F>) -> Self
+    where
+        O: Clone,
+    {
+        LogEntry::Message {
+            sender_id,
+            message,
+            outputs: step.output.clone(),
+        }
+    }
+
+    /// Returns the recorded outputs.
+    pub fn outputs(&self) -> &[O] {
+        match self {
+            LogEntry::Input { outputs, .. } | LogEntry::Message { outputs, .. } => outputs,
+        }
+    }
+}
+
+/// A difference between the recorded and the replayed behavior.
+#[derive(Clone, Debug)]
+pub enum Divergence<O, E> {
+    /// The replayed entry produced different outputs than the recorded ones.
+    Output {
+        /// The index of the entry in the log.
+        index: usize,
+        /// The recorded outputs.
+        recorded: Vec<O>,
+        /// The outputs of the witness.
+        replayed: Vec<O>,
+    },
+    /// The witness failed to handle the entry.
+    Error {
+        /// The index of the entry in the log.
+        index: usize,
+        /// The error returned by the witness.
+        error: E,
+    },
+}
+
+/// The result of replaying a log.
+#[derive(Debug)]
+pub struct Report<N, O, E, F: Fail> {
+    /// The number of replayed entries.
+    pub entries: usize,
+    /// The entries where the witness' behavior differed from the recorded one.
+    pub divergences: Vec<Divergence<O, E>>,
+    /// The faults the witness detected in the replayed messages.
+    pub fault_log: FaultLog<N, F>,
+}
+
+/// The result of replaying a log for the algorithm `D`.
+pub type WitnessReport<D> = Report<
+    <D as ConsensusProtocol>::NodeId,
+    <D as ConsensusProtocol>::Output,
+    <D as ConsensusProtocol>::Error,
+    <D as ConsensusProtocol>::FaultKind,
+>;
+
+impl<N, O, E, F: Fail> Report<N, O, E, F> {
+    /// Returns `true` if the witness produced exactly the recorded outputs.
+    pub fn is_consistent(&self) -> bool {
+        self.divergences.is_empty()
+    }
+}
+
+/// Replays the log on the `witness` instance, and reports where its outputs differ from the
+/// recorded ones.
+pub fn replay<D, I, R>(witness: &mut D, log: I, rng: &mut R) -> WitnessReport<D>
+where
+    D: ConsensusProtocol,
+    D::Output: PartialEq,
+    I: IntoIterator<Item = ProtocolLogEntry<D>>,
+    R: Rng,
+{
+    replay_by(witness, log, rng, |recorded, replayed| recorded == replayed)
+}
+
+/// Replays the log on the `witness` instance, and reports where its outputs differ from the
+/// recorded ones, according to `eq`. This is useful for outputs like batches, which are compared
+/// using `public_eq`.
+pub fn replay_by<D, I, R, F>(witness: &mut D, log: I, rng: &mut R, eq: F) -> WitnessReport<D>
+where
+    D: ConsensusProtocol,
+    I: IntoIterator<Item = ProtocolLogEntry<D>>,
+    R: Rng,
+    F: Fn(&D::Output, &D::Output) -> bool,
+{
+    let mut report = Report {
+        entries: 0,
+        divergences: Vec::new(),
+        fault_log: FaultLog::new(),
+    };
+    for (index, entry) in log.into_iter().enumerate() {
+        report.entries += 1;
+        let (result, recorded) = match entry {
+            LogEntry::Input { input, outputs } => (witness.handle_input(input, rng), outputs),
+            LogEntry::Message {
+                sender_id,
+                message,
+                outputs,
+            } => (witness.handle_message(&sender_id, message, rng), outputs),
+        };
+        let step = match result {
+            Ok(step) => step,
+            Err(error) => {
+                report.divergences.push(Divergence::Error { index, error });
+                continue;
+            }
+        };
+        report.fault_log.extend(step.fault_log);
+        let replayed = step.output;
+        let matches = recorded.len() == replayed.len()
+            && recorded.iter().zip(&replayed).all(|(r0, r1)| eq(r0, r1));
+        if !matches {
+            report.divergences.push(Divergence::Output {
+                index,
+                recorded,
+                replayed,
+            });
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, VecDeque};
+    use std::sync::Arc;
+
+    use super::{replay, Divergence, LogEntry, ProtocolLogEntry};
+    use crate::threshold_sign::ThresholdSign;
+    use crate::{ConsensusProtocol, NetworkInfo, Target};
+
+    #[test]
+    fn test_replay_threshold_sign() {
+        let mut rng = rand::thread_rng();
+        let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+        let new_node = |netinfo: &NetworkInfo<usize>| {
+            ThresholdSign::new_with_document(Arc::new(netinfo.clone()), b"doc").expect("new")
+        };
+        let mut nodes: BTreeMap<_, _> = netinfos
+            .iter()
+            .map(|(id, ni)| (*id, new_node(ni)))
+            .collect();
+
+        // Run the network, and record node 0's log.
+        let mut log: Vec<ProtocolLogEntry<ThresholdSign<usize>>> = Vec::new();
+        let mut queue = VecDeque::new();
+        for (id, node) in &mut nodes {
+            let step = node.handle_input((), &mut rng).expect("sign");
+            if *id == 0 {
+                log.push(LogEntry::input((), &step));
+            }
+            for msg in step.messages {
+                queue.push_back((*id, msg));
+            }
+        }
+        while let Some((sender_id, msg)) = queue.pop_front() {
+            let recipients: Vec<usize> = match msg.target {
+                Target::Node(id) => vec![id],
+                ref target => (0..4).filter(|id| target.contains(id)).collect(),
+            };
+            for id in recipients.into_iter().filter(|id| *id != sender_id) {
+                let message = msg.message.clone();
+                let node = nodes.get_mut(&id).expect("node");
+                let step =
+                    ConsensusProtocol::handle_message(node, &sender_id, message.clone(), &mut rng)
+                        .expect("handle message");
+                if id == 0 {
+                    log.push(LogEntry::message(sender_id, message, &step));
+                }
+                for msg in step.messages {
+                    queue.push_back((id, msg));
+                }
+            }
+        }
+        assert!(log.iter().any(|entry| !entry.outputs().is_empty()));
+
+        // The honest log is confirmed by the witness.
+        let mut witness = new_node(&netinfos[&0]);
+        let report = replay(&mut witness, log.clone(), &mut rng);
+        assert_eq!(log.len(), report.entries);
+        assert!(report.is_consistent());
+
+        // A log that claims a different output is not.
+        let index = log
+            .iter()
+            .position(|entry| !entry.outputs().is_empty())
+            .expect("output");
+        if let LogEntry::Message { outputs, .. } = &mut log[index] {
+            outputs.clear();
+        }
+        let mut witness = new_node(&netinfos[&0]);
+        let report = replay(&mut witness, log, &mut rng);
+        match &report.divergences[..] {
+            [Divergence::Output {
+                index: i, recorded, ..
+            }] => assert!(*i == index && recorded.is_empty()),
+            divergences => panic!("unexpected divergences: {:?}", divergences),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+#![deny(unused_must_use)]
+//! Tests for the asynchronous distributed key generation.
+
+use std::collections::BTreeMap;
+
+use hbbft::async_key_gen::AsyncKeyGen;
+use hbbft::ConsensusProtocol;
+use hbbft_testing::adversary::ReorderingAdversary;
+use hbbft_testing::proptest::TestRng;
+use hbbft_testing::{NetBuilder, NewNodeInfo};
+use rand::SeedableRng;
+
+#[test]
+fn test_async_key_gen() {
+    let mut rng = TestRng::from_seed([3; 16]);
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(200_000)
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |node_info: NewNodeInfo<_>| {
+            let netinfo = node_info.netinfo;
+            let sec_key = netinfo.secret_key().expect("secret key").clone();
+            let pub_keys = netinfo.public_key_map().clone();
+            let mut rng = TestRng::from_seed([node_info.id as u8; 16]);
+            AsyncKeyGen::new(node_info.id, sec_key, pub_keys, 0, &mut rng)
+                .expect("failed to create `AsyncKeyGen` instance")
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    while !net
+        .correct_nodes()
+        .all(|node| node.algorithm().terminated())
+    {
+        let _ = net.crank_expect(&mut rng);
+    }
+
+    // All correct nodes output the same public key set, and matching secret key shares.
+    let msg = "Bootstrapped without a coordinator";
+    let mut pub_key_sets = Vec::new();
+    let mut sig_shares = BTreeMap::new();
+    for node in net.correct_nodes() {
+        assert!(node.algorithm().accepted_proposers().count() >= 5);
+        assert_eq!(1, node.outputs().len());
+        let netinfo = &node.outputs()[0];
+        let idx = netinfo.node_index(node.id()).expect("node index");
+        let sig = netinfo.secret_key_share().expect("key share").sign(msg);
+        assert!(netinfo
+            .public_key_set()
+            .public_key_share(idx)
+            .verify(&sig, msg));
+        pub_key_sets.push(netinfo.public_key_set().clone());
+        sig_shares.insert(idx, sig);
+    }
+    let pub_key_set = pub_key_sets[0].clone();
+    assert!(pub_key_sets.iter().all(|pks| *pks == pub_key_set));
+    assert_eq!(2, pub_key_set.threshold());
+    let sig = pub_key_set
+        .combine_signatures(&sig_shares)
+        .expect("signature shares match");
+    assert!(pub_key_set.public_key().verify(&sig, msg));
+}
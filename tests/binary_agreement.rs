@@ -14,13 +14,17 @@
 //! input.
 
 use std::iter::once;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time;
 
-use hbbft::binary_agreement::BinaryAgreement;
-use hbbft::ConsensusProtocol;
+use hbbft::binary_agreement::{
+    bool_set, BinaryAgreement, CoinSchedule, FaultKind, MessageContent, PrfCoin, SbvMessage, Stall,
+};
+use hbbft::instrument::Instrument;
+use hbbft::{ConsensusProtocol, NetworkInfo};
 use hbbft_testing::adversary::{Adversary, ReorderingAdversary};
 use hbbft_testing::proptest::{gen_seed, NetworkDimension, TestRng, TestRngSeed};
+use hbbft_testing::scenario::{Scenario, Strategy};
 use hbbft_testing::{NetBuilder, NewNodeInfo, VirtualNet};
 use proptest::arbitrary::any;
 use proptest::{prelude::ProptestConfig, prop_compose, proptest};
@@ -94,6 +98,7 @@ fn test_binary_agreement<A, R>(
             assert_eq!(1, node.outputs().len());
             expected = Some(node.outputs()[0]);
         }
+        assert!(node.algorithm().justification().is_some());
     }
     // TODO: As soon as observers are added to the test framework, compare the expected output
     // against the output of observers.
@@ -132,3 +137,279 @@ fn binary_agreement(cfg: TestConfig) {
         num_good_nodes, num_faulty_nodes, cfg.input
     );
 }
+
+/// Tests Binary Agreement against each adversary strategy, with a partition in the first epoch.
+#[test]
+fn binary_agreement_scenarios() {
+    let strategies = [
+        Strategy::Correct,
+        Strategy::Silent,
+        Strategy::Replay,
+        Strategy::Equivocate,
+    ];
+    for &strategy in &strategies {
+        let mut run = Scenario::new()
+            .nodes(7)
+            .adversaries(2, strategy)
+            .partition_at_epoch(0)
+            .heal_after(100)
+            .crank_limit(100_000)
+            .build(|node_info: NewNodeInfo<_>| {
+                let ba = BinaryAgreement::new(Arc::new(node_info.netinfo), 0)
+                    .expect("Failed to create a BinaryAgreement instance.");
+                (ba, Default::default())
+            })
+            .expect("Could not construct test network.");
+        let ids: Vec<NodeId> = run.net.nodes().map(|n| *n.id()).collect();
+        for id in ids {
+            let input = id % 2 == 0;
+            let _ = run.net.send_input(id, input, &mut run.rng);
+        }
+        let terminated = run
+            .run_until(|net| {
+                net.correct_nodes()
+                    .all(|node| node.algorithm().terminated())
+            })
+            .expect("crank");
+        assert!(terminated, "{:?}: the queue ran empty", strategy);
+        let output = run.net.correct_nodes().next().expect("node").outputs()[0];
+        for node in run.net.correct_nodes() {
+            assert!(once(&output).eq(node.outputs()), "{:?}", strategy);
+        }
+    }
+}
+
+/// Tests Binary Agreement with a coin that is flipped in every epoch, with random inputs.
+#[test]
+fn binary_agreement_coin_schedule() {
+    let mut rng: TestRng = TestRng::from_seed(Default::default());
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100_000)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let mut ba = BinaryAgreement::new(Arc::new(node_info.netinfo), 0)
+                .expect("Failed to create a BinaryAgreement instance.");
+            let schedule = CoinSchedule::new(vec![None]).expect("schedule");
+            ba.set_variant(Arc::new(schedule)).expect("set variant");
+            assert_eq!(None, ba.coin_value());
+            ba
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_binary_agreement(&mut net, None, TestRng::from_seed(rng.gen::<TestRngSeed>()));
+}
+
+/// Tests that with certificates enabled, every correct node obtains a certificate of the decision
+/// that verifies against the public key set.
+#[test]
+fn binary_agreement_certificates() {
+    let mut rng: TestRng = TestRng::from_seed(Default::default());
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100_000)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let mut ba = BinaryAgreement::new(Arc::new(node_info.netinfo), 5u8)
+                .expect("Failed to create a BinaryAgreement instance.");
+            ba.set_certify(true);
+            ba
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let ids: Vec<NodeId> = net.nodes().map(|n| *n.id()).collect();
+    for id in ids {
+        let _ = net.send_input(id, id % 2 == 0, &mut rng);
+    }
+    while !net
+        .correct_nodes()
+        .all(|node| node.algorithm().terminated())
+    {
+        let _ = net.crank_expect(&mut rng);
+    }
+    for node in net.correct_nodes() {
+        let ba = node.algorithm();
+        let cert = ba.certificate().expect("certificate");
+        assert!(once(&cert.decision).eq(node.outputs()));
+        let pub_key_set = ba.netinfo().public_key_set();
+        assert!(cert.verify(&5u8, pub_key_set).expect("verify"));
+        assert!(!cert.verify(&6u8, pub_key_set).expect("verify"));
+    }
+}
+
+/// Records the stalled `BinaryAgreement` instances.
+#[derive(Default)]
+struct StallInstrument(Mutex<Vec<Stall<u16>>>);
+
+impl Instrument<u16> for StallInstrument {
+    fn agreement_stalled(&self, stall: &Stall<u16>) {
+        self.0.lock().expect("lock").push(stall.clone());
+    }
+}
+
+/// Tests that an instance that exceeds the epoch limit reports the validators whose `Aux` is
+/// missing to the instrument, without reporting them as faulty.
+#[test]
+fn binary_agreement_epoch_limit() {
+    let mut rng = TestRng::from_seed([3; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    // Nodes 1 and 2 vote `false`, and node 3 is silent. Since the coin is `true` in epoch 0, this
+    // moves node 0 to epoch 1.
+    let run_epoch_0 = |max_epochs: u64| {
+        let instrument = Arc::new(StallInstrument::default());
+        let mut ba = BinaryAgreement::new(Arc::new(netinfos[&0].clone()), 0u8).expect("BA");
+        ba.set_max_epochs(max_epochs);
+        ba.set_instrument(instrument.clone());
+        let mut faults = ba.propose(false).expect("propose").fault_log.0;
+        for sbv_msg in &[SbvMessage::BVal(false), SbvMessage::Aux(false)] {
+            for id in 1..3 {
+                let msg = MessageContent::SbvBroadcast(sbv_msg.clone()).with_epoch(0);
+                let step = ba.handle_message(&id, msg).expect("handle message");
+                faults.extend(step.fault_log.0);
+            }
+        }
+        assert!(!ba.terminated());
+        assert!(faults.is_empty());
+        let stalls = instrument.0.lock().expect("lock").clone();
+        stalls
+    };
+    assert!(run_epoch_0(2).is_empty());
+    let expected = Stall {
+        session_id: "0".to_string(),
+        epoch: 0,
+        bin_values: bool_set::FALSE,
+        missing_aux: vec![3],
+    };
+    assert_eq!(vec![expected], run_epoch_0(1));
+}
+
+/// Tests the accessors for the state of a running instance.
+#[test]
+fn binary_agreement_state() {
+    let mut rng = TestRng::from_seed([4; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let mut ba = BinaryAgreement::new(Arc::new(netinfos[&0].clone()), 0u8).expect("BA");
+    assert_eq!((0, None), (ba.epoch(), ba.estimate()));
+    let _ = ba.propose(false).expect("propose");
+    assert_eq!(Some(false), ba.estimate());
+    assert_eq!(Some(true), ba.coin_value());
+    assert_eq!(bool_set::FALSE, ba.received_bval(&0));
+    assert_eq!(bool_set::NONE, ba.received_aux(&0));
+
+    let bval = MessageContent::SbvBroadcast(SbvMessage::BVal(true)).with_epoch(0);
+    let _ = ba.handle_message(&1, bval).expect("handle message");
+    for id in 2..4 {
+        let bval = MessageContent::SbvBroadcast(SbvMessage::BVal(false)).with_epoch(0);
+        let _ = ba.handle_message(&id, bval).expect("handle message");
+    }
+    assert_eq!(bool_set::TRUE, ba.received_bval(&1));
+    assert_eq!(bool_set::FALSE, ba.bin_values());
+    assert_eq!(bool_set::FALSE, ba.received_aux(&0));
+    assert_eq!(bool_set::NONE, ba.received_aux(&1));
+    assert_eq!(None, ba.received_conf(&1));
+}
+
+/// Tests that a peer sending more messages in an epoch than a correct node would is reported, and
+/// its further messages are ignored.
+#[test]
+fn binary_agreement_epoch_budget() {
+    let mut rng = TestRng::from_seed([5; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let mut ba = BinaryAgreement::new(Arc::new(netinfos[&0].clone()), 0u8).expect("BA");
+    let handle = |ba: &mut BinaryAgreement<NodeId, u8>, id: NodeId, content: MessageContent| {
+        let step = ba.handle_message(&id, content.with_epoch(0));
+        step.expect("handle message").fault_log.0
+    };
+    let bval = |b| MessageContent::SbvBroadcast(SbvMessage::BVal(b));
+    let aux = |b| MessageContent::SbvBroadcast(SbvMessage::Aux(b));
+    let conf = || MessageContent::Conf(bool_set::BOTH);
+
+    // Node 1 sends the messages of a correct node, and node 2 only some of them.
+    let mut faults = Vec::new();
+    faults.extend(handle(&mut ba, 1, bval(false)));
+    faults.extend(handle(&mut ba, 1, bval(true)));
+    faults.extend(handle(&mut ba, 1, aux(false)));
+    faults.extend(handle(&mut ba, 2, bval(false)));
+    // A repeated `Conf` is not a fault in itself, but it counts towards the budget. The budget is
+    // six ordinary messages and a coin share, which counts as 100, so 106 in total.
+    for _ in 3..106 {
+        faults.extend(handle(&mut ba, 1, conf()));
+    }
+    assert!(faults.is_empty(), "unexpected faults: {:?}", faults);
+    assert_eq!(bool_set::BOTH, ba.received_bval(&1));
+
+    // Once it exceeds the budget, node 1 is reported, and its messages are not handled.
+    let faults = handle(&mut ba, 1, aux(true));
+    assert_eq!(1, faults.len());
+    assert_eq!(1, faults[0].node_id);
+    assert_eq!(FaultKind::EpochBudgetExceeded, faults[0].kind);
+    assert_eq!(bool_set::FALSE, ba.received_aux(&1));
+    assert!(handle(&mut ba, 2, conf()).is_empty());
+}
+
+/// Tests that a peer can only send one decision share per instance, since decision shares are
+/// not subject to the epoch budget.
+#[test]
+fn binary_agreement_decision_share_limit() {
+    let mut rng = TestRng::from_seed([6; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let mut ba = BinaryAgreement::new(Arc::new(netinfos[&0].clone()), 0u8).expect("BA");
+    ba.set_certify(true);
+    let mut handle = |ba: &mut BinaryAgreement<NodeId, u8>, id: NodeId| {
+        let content = MessageContent::DecisionShare(Box::new(rng.gen()));
+        let step = ba.handle_message(&id, content.with_epoch(0));
+        step.expect("handle message").fault_log.0
+    };
+
+    // The first share of each peer is accepted, even if it is not verified yet.
+    assert!(handle(&mut ba, 1).is_empty());
+    assert!(handle(&mut ba, 2).is_empty());
+
+    // Any further share is a fault.
+    for _ in 0..3 {
+        let faults = handle(&mut ba, 1);
+        assert_eq!(1, faults.len());
+        assert_eq!(1, faults[0].node_id);
+        assert_eq!(FaultKind::MultipleDecisionShares, faults[0].kind);
+    }
+}
+
+/// Tests Binary Agreement with precomputed coin shares and batch verification.
+#[test]
+fn binary_agreement_precomputed_coin_shares() {
+    let mut rng: TestRng = TestRng::from_seed([7; 16]);
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100_000)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let mut ba = BinaryAgreement::new(Arc::new(node_info.netinfo), 0)
+                .expect("Failed to create a BinaryAgreement instance.");
+            ba.precompute_coin_shares(3).expect("precompute");
+            ba.set_batch_verification(true);
+            ba
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_binary_agreement(&mut net, None, TestRng::from_seed(rng.gen::<TestRngSeed>()));
+}
+
+/// Tests Binary Agreement with a local coin instead of the threshold signature.
+#[test]
+fn binary_agreement_local_coin() {
+    let mut rng: TestRng = TestRng::from_seed([9; 16]);
+    let coin = Arc::new(PrfCoin::new(b"shared key".to_vec()));
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100_000)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let mut ba = BinaryAgreement::new(Arc::new(node_info.netinfo), 0)
+                .expect("Failed to create a BinaryAgreement instance.");
+            ba.set_local_coin(coin.clone()).expect("set local coin");
+            ba
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_binary_agreement(&mut net, None, TestRng::from_seed(rng.gen::<TestRngSeed>()));
+}
@@ -1,8 +1,11 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::iter::once;
 use std::sync::{Arc, Mutex};
 
-use hbbft::{broadcast::Broadcast, util, ConsensusProtocol, CpStep, NetworkInfo};
+use hbbft::broadcast::{
+    self, Broadcast, CodingError, Digest, EchoStrategy, ErasureCoding, MerkleHasher,
+};
+use hbbft::{util, ConsensusProtocol, CpStep, NetworkInfo, Target, TargetedMessage};
 use hbbft_testing::adversary::{
     sort_ascending, swap_random, Adversary, NetMutHandle, NodeOrderAdversary, RandomAdversary,
     ReorderingAdversary,
@@ -12,6 +15,7 @@ use hbbft_testing::{CrankError, NetBuilder, NetMessage, NewNodeInfo, VirtualNet}
 use log::info;
 use proptest::{prelude::ProptestConfig, proptest};
 use rand::{Rng, SeedableRng};
+use tiny_keccak::keccak256;
 
 type NodeId = u16;
 type NetworkInfoMap = BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>;
@@ -117,6 +121,37 @@ impl Adversary<Broadcast<NodeId>> for ProposeAdversary {
     }
 }
 
+/// An adversary whose nodes never send a full `Echo`, so that the others have to make do with
+/// fewer shards.
+#[derive(Default)]
+struct WithholdingAdversary;
+
+impl Adversary<Broadcast<NodeId>> for WithholdingAdversary {
+    fn pre_crank<R: Rng>(
+        &mut self,
+        mut net: NetMutHandle<'_, Broadcast<NodeId>, Self>,
+        rng: &mut R,
+    ) {
+        swap_random(&mut net, rng);
+    }
+
+    fn tamper<R: Rng>(
+        &mut self,
+        mut net: NetMutHandle<'_, Broadcast<NodeId>, Self>,
+        msg: NetMessage<Broadcast<NodeId>>,
+        rng: &mut R,
+    ) -> Result<CpStep<Broadcast<NodeId>>, CrankError<Broadcast<NodeId>>> {
+        let mut step = net.dispatch_message(msg, rng)?;
+        step.messages.retain(|msg| {
+            !matches!(
+                msg.message,
+                broadcast::Message::Echo(_) | broadcast::Message::EchoChunk(_)
+            )
+        });
+        Ok(step)
+    }
+}
+
 /// Broadcasts a value from node 0 and expects all good nodes to receive it.
 fn test_broadcast<A: Adversary<Broadcast<NodeId>>>(
     mut net: VirtualNet<Broadcast<NodeId>, A>,
@@ -260,6 +295,379 @@ proptest! {
     }
 }
 
+#[test]
+fn test_broadcast_max_value_size() {
+    let mut rng = TestRng::from_seed([5; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let new_bc = |id: NodeId, max: Option<usize>| {
+        let mut bc = Broadcast::new(Arc::new(netinfos[&id].clone()), 0).expect("broadcast");
+        if let Some(max) = max {
+            bc.set_max_value_size(max);
+        }
+        bc
+    };
+
+    // The proposer can't broadcast a value larger than its own limit.
+    let value = vec![7; 100];
+    let result = new_bc(0, Some(99)).broadcast(value.clone());
+    assert_eq!(Err(broadcast::Error::ValueTooLarge), result.map(|_| ()));
+
+    // A node with a lower limit rejects its shard, and reports the proposer.
+    let step = new_bc(0, None).broadcast(value).expect("broadcast");
+    let msg = step
+        .messages
+        .iter()
+        .find(|msg| msg.target == Target::Node(1))
+        .expect("value for node 1")
+        .message
+        .clone();
+    let step = new_bc(1, Some(50)).handle_message(&0, msg.clone());
+    let fault = step.expect("handle").fault_log.0.into_iter().next();
+    let fault = fault.map(|fault| (fault.node_id, fault.kind));
+    assert_eq!(Some((0, broadcast::FaultKind::ValueTooLarge)), fault);
+
+    // A node with a higher limit echoes it.
+    let step = new_bc(1, Some(100))
+        .handle_message(&0, msg)
+        .expect("handle");
+    assert!(step.fault_log.is_empty());
+    assert!(!step.messages.is_empty());
+}
+
+#[test]
+fn test_broadcast_max_value_size_decoded() {
+    let mut rng = TestRng::from_seed([9; 16]);
+    // With 4 nodes, there are 2 data shards. The 104 bytes of the value and its length fit into
+    // two shards of 52 bytes, the same as a value of 99 bytes. So the shards are accepted, but the
+    // decoded value is rejected.
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .num_faulty(1)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |info| {
+            let mut bc = Broadcast::new(Arc::new(info.netinfo), 0).expect("broadcast");
+            if !info.faulty {
+                bc.set_max_value_size(99);
+            }
+            bc
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let _ = net
+        .send_input(0, vec![7; 100], &mut rng)
+        .expect("Setting input failed");
+    let mut reporters = BTreeSet::new();
+    while net.messages_len() > 0 {
+        let (id, step) = net.crank_expect(&mut rng);
+        for fault in step.fault_log.0 {
+            assert_eq!(
+                (0, broadcast::FaultKind::ValueTooLarge),
+                (fault.node_id, fault.kind)
+            );
+            reporters.insert(id);
+        }
+    }
+    for node in net.correct_nodes() {
+        assert!(node.outputs().is_empty());
+        assert!(reporters.contains(node.id()));
+    }
+}
+
+/// The Keccak-256 hash function.
+#[derive(Debug)]
+struct KeccakHasher;
+
+impl MerkleHasher for KeccakHasher {
+    fn hash(&self, bytes: &[u8]) -> Digest {
+        keccak256(bytes)
+    }
+}
+
+#[test]
+fn test_broadcast_merkle_hasher() {
+    let mut rng = TestRng::from_seed([10; 16]);
+    let hasher: Arc<dyn MerkleHasher> = Arc::new(KeccakHasher);
+
+    // A node with the default hash function rejects the proofs.
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let mut bc = Broadcast::new(Arc::new(netinfos[&0].clone()), 0).expect("broadcast");
+    bc.set_merkle_hasher(hasher.clone());
+    let step = bc.broadcast(b"Foo bar baz".to_vec()).expect("broadcast");
+    let msg = step
+        .messages
+        .iter()
+        .find(|msg| msg.target == Target::Node(1))
+        .expect("value for node 1")
+        .message
+        .clone();
+    let mut bc = Broadcast::new(Arc::new(netinfos[&1].clone()), 0).expect("broadcast");
+    let step = bc.handle_message(&0, msg).expect("handle");
+    let fault = step.fault_log.0.into_iter().next();
+    let fault = fault.map(|fault| (fault.node_id, fault.kind));
+    assert_eq!(Some((0, broadcast::FaultKind::InvalidProof)), fault);
+
+    // If all nodes use the same one, the value is delivered.
+    let (net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |info| {
+            let mut bc = Broadcast::new(Arc::new(info.netinfo), 3).expect("broadcast");
+            bc.set_merkle_hasher(hasher.clone());
+            bc.set_chunk_size(2);
+            bc
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_broadcast(net, b"Foo bar baz", &mut rng, 3);
+}
+
+/// An erasure coding scheme that sends the full value to every node.
+#[derive(Debug)]
+struct Replication(usize);
+
+impl ErasureCoding for Replication {
+    fn data_shard_count(&self) -> usize {
+        1
+    }
+
+    fn parity_shard_count(&self) -> usize {
+        self.0 - 1
+    }
+
+    fn encode(&self, shards: &mut [&mut [u8]]) -> Result<(), CodingError> {
+        let (data, parity) = shards.split_first_mut().ok_or(CodingError::InvalidShards)?;
+        for shard in parity {
+            shard.copy_from_slice(data);
+        }
+        Ok(())
+    }
+
+    fn reconstruct_shards(&self, shards: &mut [Option<Box<[u8]>>]) -> Result<(), CodingError> {
+        let present = shards.iter().flatten().next().cloned();
+        let present = present.ok_or(CodingError::TooFewShards)?;
+        for shard in shards.iter_mut().filter(|shard| shard.is_none()) {
+            *shard = Some(present.clone());
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_broadcast_erasure_coding() {
+    let mut rng = TestRng::from_seed([6; 16]);
+    let netinfo = NetworkInfo::generate_map(0..7u16, &mut rng).expect("netinfos")[&0].clone();
+    let mut bc = Broadcast::new(Arc::new(netinfo), 0).expect("broadcast");
+    // With 7 nodes, at most 2 are faulty, so at most 3 data shards are allowed.
+    assert_eq!(
+        Err(broadcast::Error::InvalidCoding),
+        bc.set_data_shard_count(4)
+    );
+    assert_eq!(
+        Err(broadcast::Error::InvalidCoding),
+        bc.set_coding(Replication(6))
+    );
+
+    for &data_shard_num in &[1, 2] {
+        let (net, _) = NetBuilder::new(0..7u16)
+            .num_faulty(2)
+            .no_time_limit()
+            .adversary(ReorderingAdversary::new())
+            .using(move |info| {
+                let mut bc = Broadcast::new(Arc::new(info.netinfo), 3).expect("broadcast");
+                bc.set_data_shard_count(data_shard_num).expect("coding");
+                bc
+            })
+            .build(&mut rng)
+            .expect("Could not construct test network.");
+        test_broadcast(net, b"Foo bar baz", &mut rng, 3);
+    }
+
+    let (net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |info| {
+            let mut bc = Broadcast::new(Arc::new(info.netinfo), 3).expect("broadcast");
+            bc.set_coding(Replication(7)).expect("coding");
+            bc
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_broadcast(net, b"Foo bar baz", &mut rng, 3);
+}
+
+#[test]
+fn test_broadcast_chunks() {
+    let mut rng = TestRng::from_seed([7; 16]);
+    let value: Vec<u8> = (0..200).map(|i| i as u8).collect();
+
+    let netinfo = NetworkInfo::generate_map(0..7u16, &mut rng).expect("netinfos")[&3].clone();
+    let mut bc = Broadcast::new(Arc::new(netinfo), 3).expect("broadcast");
+    bc.set_chunk_size(5);
+    let step = bc.broadcast(value.clone()).expect("broadcast");
+    assert!(!step.messages.is_empty());
+    for msg in &step.messages {
+        match msg.message {
+            broadcast::Message::ValueChunk(ref chunk) => assert!(chunk.value().len() <= 5),
+            broadcast::Message::EchoChunk(ref chunk) => assert!(chunk.value().len() <= 5),
+            ref msg => panic!("Unexpected message: {:?}", msg),
+        }
+    }
+
+    let (net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |info| {
+            let mut bc = Broadcast::new(Arc::new(info.netinfo), 3).expect("broadcast");
+            bc.set_chunk_size(5);
+            bc
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_broadcast(net, &value, &mut rng, 3);
+}
+
+#[test]
+fn test_broadcast_partial_echos() {
+    let mut rng = TestRng::from_seed([8; 16]);
+
+    // With 7 nodes, there are 3 data shards, so every node sends its full `Echo` to the next
+    // two and only an `EchoHash` to the others.
+    let netinfo = NetworkInfo::generate_map(0..7u16, &mut rng).expect("netinfos")[&3].clone();
+    let mut bc = Broadcast::new(Arc::new(netinfo), 3).expect("broadcast");
+    bc.set_echo_strategy(EchoStrategy::Partial(0));
+    let step = bc.broadcast(b"Foo bar baz".to_vec()).expect("broadcast");
+    let mut echo_hash_ids = BTreeSet::new();
+    for msg in &step.messages {
+        match (&msg.message, &msg.target) {
+            (broadcast::Message::Value(_), _) => (),
+            (broadcast::Message::Echo(_), Target::AllExcept(ids)) => {
+                assert_eq!(ids.iter().cloned().collect::<Vec<_>>(), vec![0, 1, 2, 6]);
+            }
+            (broadcast::Message::EchoHash(_), Target::Node(id)) => {
+                echo_hash_ids.insert(*id);
+            }
+            (msg, target) => panic!("Unexpected message: {:?} to {:?}", msg, target),
+        }
+    }
+    assert_eq!(echo_hash_ids, (0..3).chain(6..7).collect());
+
+    // The faulty nodes only send `EchoHash`, so some nodes need to request the missing shards.
+    for &redundancy in &[0, 1] {
+        let (net, _) = NetBuilder::new(0..7u16)
+            .num_faulty(2)
+            .no_time_limit()
+            .adversary(WithholdingAdversary)
+            .using(move |info| {
+                let mut bc = Broadcast::new(Arc::new(info.netinfo), 3).expect("broadcast");
+                bc.set_echo_strategy(EchoStrategy::Partial(redundancy));
+                bc
+            })
+            .build(&mut rng)
+            .expect("Could not construct test network.");
+        test_broadcast(net, b"Foo bar baz", &mut rng, 3);
+    }
+}
+
+/// Messages sent by the given nodes, that haven't been delivered yet.
+type Queue = Vec<(NodeId, TargetedMessage<broadcast::Message, NodeId>)>;
+
+/// A predicate selecting a kind of message.
+type MessageFilter = fn(&broadcast::Message) -> bool;
+
+/// Removes the first message for the node `to` that matches, and returns it with its sender.
+fn take_message(
+    queue: &mut Queue,
+    to: NodeId,
+    is_match: MessageFilter,
+) -> (NodeId, broadcast::Message) {
+    let pos = queue
+        .iter()
+        .position(|(_, msg)| msg.target.contains(&to) && is_match(&msg.message))
+        .expect("message");
+    let (sender, msg) = queue.remove(pos);
+    (sender, msg.message)
+}
+
+/// A node that has sent `CanDecode` only receives an `EchoHash` from the nodes that haven't sent
+/// their `Echo` yet, and still outputs the value.
+#[test]
+fn test_broadcast_can_decode() {
+    let mut rng = TestRng::from_seed([9; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let mut nodes: BTreeMap<NodeId, Broadcast<NodeId>> = netinfos
+        .into_iter()
+        .map(|(id, netinfo)| (id, Broadcast::new(Arc::new(netinfo), 0).expect("broadcast")))
+        .collect();
+    let value = b"Foo bar baz".to_vec();
+    let mut queue = Vec::new();
+    let mut outputs = BTreeMap::new();
+    let mut handle_step = |id: NodeId, step: broadcast::Step<NodeId>, queue: &mut Queue| {
+        assert!(step.fault_log.is_empty());
+        if let Some(output) = step.output.into_iter().next() {
+            outputs.insert(id, output);
+        }
+        queue.extend(step.messages.into_iter().map(|msg| (id, msg)));
+    };
+    let step = nodes.get_mut(&0).unwrap().broadcast(value.clone());
+    handle_step(0, step.expect("broadcast"), &mut queue);
+
+    // With four nodes, two `Echo`s suffice to decode: Node 1 sends `CanDecode` once it has
+    // handled the proposer's `Value` and `Echo`, before node 2 has received its `Value`.
+    let is_value: MessageFilter = |msg| match msg {
+        broadcast::Message::Value(_) => true,
+        _ => false,
+    };
+    let is_echo: MessageFilter = |msg| match msg {
+        broadcast::Message::Echo(_) => true,
+        _ => false,
+    };
+    let is_can_decode: MessageFilter = |msg| match msg {
+        broadcast::Message::CanDecode(_) => true,
+        _ => false,
+    };
+    for &is_match in &[is_value, is_echo] {
+        let (sender, msg) = take_message(&mut queue, 1, is_match);
+        let step = nodes.get_mut(&1).unwrap().handle_message(&sender, msg);
+        handle_step(1, step.expect("handle message"), &mut queue);
+    }
+    let (sender, msg) = take_message(&mut queue, 2, is_can_decode);
+    assert_eq!(1, sender);
+    let step = nodes.get_mut(&2).unwrap().handle_message(&sender, msg);
+    handle_step(2, step.expect("handle message"), &mut queue);
+
+    // Node 2's `Echo` goes to everyone but node 1, which only receives the `EchoHash`.
+    let (sender, msg) = take_message(&mut queue, 2, is_value);
+    let step = nodes.get_mut(&2).unwrap().handle_message(&sender, msg);
+    let step = step.expect("handle message");
+    let mut echo_targets = Vec::new();
+    for msg in &step.messages {
+        match (&msg.message, &msg.target) {
+            (broadcast::Message::Echo(_), target) => echo_targets.push(target.clone()),
+            (broadcast::Message::EchoHash(_), Target::Node(1)) => (),
+            (broadcast::Message::CanDecode(_), Target::All) => (),
+            (msg, target) => panic!("Unexpected message: {:?} to {:?}", msg, target),
+        }
+    }
+    assert_eq!(vec![Target::AllExcept(once(1).collect())], echo_targets);
+    handle_step(2, step, &mut queue);
+
+    // Deliver the remaining messages: All nodes output the value.
+    while !queue.is_empty() {
+        let (sender, msg) = queue.remove(0);
+        for (id, node) in &mut nodes {
+            if *id != sender && msg.target.contains(id) {
+                let step = node.handle_message(&sender, msg.message.clone());
+                handle_step(*id, step.expect("handle message"), &mut queue);
+            }
+        }
+    }
+    assert_eq!(4, outputs.len());
+    assert!(outputs.values().all(|output| *output == value));
+}
+
 fn do_test_8_broadcast_equal_leaves_silent(seed: TestRngSeed) {
     let mut rng: TestRng = TestRng::from_seed(seed);
     let size = 8;
@@ -1,17 +1,25 @@
 use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time;
 
+use hbbft::binary_agreement::{CoinSchedule, LocalCoin, PrfCoin};
 use hbbft::dynamic_honey_badger::{
-    Batch, Change, ChangeState, DynamicHoneyBadger, Input, JoinPlan,
+    Batch, Change, ChangeState, DynamicHoneyBadger, Input, JoinOutcome, JoinPlan, JoinSync,
 };
+use hbbft::header::Algorithm;
+use hbbft::honey_badger::EncryptionSchedule;
+use hbbft::instrument::{CryptoOp, Instrument};
 use hbbft::sender_queue::{SenderQueue, Step};
-use hbbft::{util, Epoched};
+use hbbft::{util, Epoched, KeyMaterial, NetworkInfo, NetworkInfoError, Target};
 use hbbft_testing::adversary::{Adversary, ReorderingAdversary};
 use hbbft_testing::proptest::{gen_seed, NetworkDimension, TestRng, TestRngSeed};
+use hbbft_testing::scenario::{Scenario, ScenarioRun};
 use hbbft_testing::{NetBuilder, NewNodeInfo, Node, VirtualNet};
 use proptest::{prelude::ProptestConfig, prop_compose, proptest};
-use rand::{seq::SliceRandom, SeedableRng};
-use threshold_crypto::PublicKey;
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use threshold_crypto::serde_impl::SerdeSecret;
+use threshold_crypto::{PublicKey, SecretKey, SecretKeySet};
 
 type DHB = SenderQueue<DynamicHoneyBadger<Vec<usize>, usize>>;
 
@@ -544,3 +552,375 @@ where
             .clone()
     }
 }
+
+/// Nodes can be created from keys generated in an offline ceremony, and inconsistent keys are
+/// rejected.
+#[test]
+fn test_dynamic_honey_badger_from_key_material() {
+    let mut rng = TestRng::from_seed([9; 16]);
+    let sk_set = SecretKeySet::random(1, &mut rng);
+    let sec_keys: BTreeMap<usize, SecretKey> = (0..4).map(|id| (id, rng.gen())).collect();
+    let public_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(|(id, sk)| (*id, sk.public_key()))
+        .collect();
+    let material = |idx: usize| {
+        let key_material = KeyMaterial {
+            public_key_set: sk_set.public_keys(),
+            public_keys: public_keys.clone(),
+            secret_key_share: Some(SerdeSecret(sk_set.secret_key_share(idx))),
+        };
+        let bytes = bincode::serialize(&key_material).expect("serialize key material");
+        bincode::deserialize::<KeyMaterial<usize>>(&bytes).expect("deserialize key material")
+    };
+
+    for (id, sk) in &sec_keys {
+        let netinfo = NetworkInfo::from_key_material(*id, sk.clone(), material(*id))
+            .expect("valid key material");
+        assert_eq!(material(*id), netinfo.key_material());
+        let dhb: DynamicHoneyBadger<Vec<usize>, usize> = DynamicHoneyBadger::builder()
+            .build_from_key_material(*id, sk.clone(), material(*id))
+            .expect("build from key material");
+        assert_eq!(sk_set.public_keys(), *dhb.netinfo().public_key_set());
+    }
+
+    // Another node's share, or a missing share, is rejected.
+    let result = NetworkInfo::from_key_material(0, sec_keys[&0].clone(), material(1));
+    assert_eq!(Some(NetworkInfoError::SecretKeyShareMismatch), result.err());
+    let mut no_share = material(0);
+    no_share.secret_key_share = None;
+    let result = NetworkInfo::from_key_material(0, sec_keys[&0].clone(), no_share);
+    assert_eq!(Some(NetworkInfoError::MissingSecretKeyShare), result.err());
+}
+
+/// Counts the signatures and the `DynamicHoneyBadger` messages a node sends.
+#[derive(Default)]
+struct VoteInstrument {
+    signatures: AtomicUsize,
+    messages: AtomicUsize,
+}
+
+impl Instrument<usize> for VoteInstrument {
+    fn message_sent(&self, _: &Target<usize>, algorithm: Algorithm, _: u64) {
+        if algorithm == Algorithm::DynamicHoneyBadger {
+            self.messages.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn crypto_op(&self, op: CryptoOp) {
+        if op == CryptoOp::Sign {
+            self.signatures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Retracting a vote is instrumented like casting one: a signature and a sent message each.
+#[test]
+fn test_dynamic_honey_badger_vote_instrument() {
+    let mut rng = TestRng::from_seed([5; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+    let counter = Arc::new(VoteInstrument::default());
+    let mut dhb: DynamicHoneyBadger<Vec<usize>, usize> = DynamicHoneyBadger::builder()
+        .instrument(counter.clone())
+        .build(netinfos[&0].clone());
+    let change = Change::EncryptionSchedule(EncryptionSchedule::EveryNthEpoch(2));
+    let step = dhb.vote_for(change).expect("vote");
+    assert_eq!(1, step.messages.len());
+    assert_eq!(1, counter.signatures.load(Ordering::SeqCst));
+    assert_eq!(1, counter.messages.load(Ordering::SeqCst));
+    let step = dhb.retract_vote().expect("retract vote");
+    assert_eq!(1, step.messages.len());
+    assert_eq!(2, counter.signatures.load(Ordering::SeqCst));
+    assert_eq!(2, counter.messages.load(Ordering::SeqCst));
+}
+
+/// Creates a network of four correct `DynamicHoneyBadger` nodes. Each node is passed to
+/// `configure`, together with its ID, before it is wrapped in a `SenderQueue`.
+fn new_dhb_run<F>(seed: TestRngSeed, configure: F) -> ScenarioRun<DHB>
+where
+    F: Fn(&mut DynamicHoneyBadger<Vec<usize>, usize>, usize) + 'static,
+{
+    Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node: NewNodeInfo<DHB>| {
+            let id = node.id;
+            let mut dhb = DynamicHoneyBadger::builder().build(node.netinfo.clone());
+            configure(&mut dhb, id);
+            SenderQueue::builder(
+                dhb,
+                node.netinfo.all_ids().filter(|&&them| them != id).cloned(),
+            )
+            .build(id)
+        })
+        .expect("could not construct test network")
+}
+
+/// Makes every correct node propose its own ID, and runs the network until they all have output
+/// the next batch.
+fn run_epoch(run: &mut ScenarioRun<DHB>) {
+    let ids: Vec<usize> = run.net.correct_nodes().map(|node| *node.id()).collect();
+    let epochs = run.net.correct_nodes().map(|node| node.outputs().len());
+    let next_epoch = epochs.min().expect("no correct nodes") + 1;
+    for id in ids {
+        let _ = run
+            .net
+            .send_input(id, Input::User(vec![id]), &mut run.rng)
+            .expect("could not send input");
+    }
+    let done = run
+        .run_until(|net| {
+            net.correct_nodes()
+                .all(|node| node.outputs().len() >= next_epoch)
+        })
+        .expect("crank");
+    assert!(done, "the network stalled");
+}
+
+/// A new node accepts the join plan once more than _f_ of the validators it trusts have signed it.
+#[test]
+fn test_dynamic_honey_badger_join_sync() {
+    let mut run = new_dhb_run([11; 16], |_, _| ());
+    let validators = run
+        .net
+        .correct_nodes()
+        .next()
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+
+    // Vote to remove node 3: The batches of the key generation contain join plans.
+    let mut new_pub_keys = validators.clone();
+    new_pub_keys.remove(&3);
+    let change = Change::NodeChange(new_pub_keys);
+    let _ = run
+        .net
+        .broadcast_input(&Input::Change(change.clone()), &mut run.rng)
+        .expect("could not vote");
+    let in_progress = ChangeState::InProgress(change);
+    let has_plan = |node: &Node<DHB>| {
+        let mut batches = node.outputs().iter();
+        batches.any(|batch| *batch.change() == in_progress)
+    };
+    while !run.net.correct_nodes().all(&has_plan) {
+        run_epoch(&mut run);
+    }
+
+    let mut join_sync = JoinSync::new(validators);
+    let mut outcomes = run.net.correct_nodes().map(|node| {
+        let batch = node
+            .outputs()
+            .iter()
+            .find(|batch| *batch.change() == in_progress);
+        let batch = batch.expect("batch with a join plan");
+        let response = batch.join_response().expect("sign").expect("join plan");
+        let outcome = join_sync
+            .handle_response(node.id(), response)
+            .expect("handle response");
+        (batch.clone(), outcome)
+    });
+
+    // One signature is not enough: One of the four validators could be faulty.
+    match outcomes.next().expect("outcome") {
+        (_, JoinOutcome::Pending) => (),
+        (_, outcome) => panic!("unexpected outcome: {:?}", outcome),
+    }
+    let (batch, plan) = match outcomes.next().expect("outcome") {
+        (batch, JoinOutcome::Ready(plan)) => (batch, *plan),
+        (_, outcome) => panic!("unexpected outcome: {:?}", outcome),
+    };
+    let expected_plan = batch.join_plan().expect("join plan");
+    let ser = |plan: &JoinPlan<usize>| bincode::serialize(plan).expect("serialize plan");
+    assert_eq!(ser(&expected_plan), ser(&plan));
+
+    // The plan can be used to join the network as an observer.
+    let (dhb, _) = DynamicHoneyBadger::<Vec<usize>, usize>::new_joining(
+        4,
+        SecretKey::random(),
+        plan,
+        &mut run.rng,
+    )
+    .expect("join");
+    assert_eq!(batch.epoch() + 1, dhb.next_epoch());
+}
+
+/// Announced network addresses are committed in a batch and added to every node's `NetworkInfo`.
+#[test]
+fn test_dynamic_honey_badger_addresses() {
+    let mut run = new_dhb_run([12; 16], |_, _| ());
+    let ids: Vec<usize> = run.net.correct_nodes().map(|node| *node.id()).collect();
+    for id in &ids {
+        let step = run
+            .net
+            .get_mut(*id)
+            .expect("node disappeared")
+            .algorithm_mut()
+            .announce_address(vec![*id as u8])
+            .expect("failed to announce address");
+        run.net
+            .process_step(*id, &step)
+            .expect("processing a step failed");
+    }
+    for _ in 0..3 {
+        run_epoch(&mut run);
+    }
+    for node in run.net.correct_nodes() {
+        let netinfo = node.algorithm().algo().netinfo();
+        for id in &ids {
+            assert_eq!(Some(&[*id as u8][..]), netinfo.address(id));
+        }
+    }
+}
+
+/// Each batch is timestamped with the median of the proposers' clocks, which lies within the range
+/// of the nodes' clocks even if they disagree.
+#[test]
+fn test_dynamic_honey_badger_timestamps() {
+    // Each node's clock is off by a different amount.
+    let mut run = new_dhb_run([13; 16], |dhb, id| {
+        let time = 1000 + id as u64;
+        dhb.set_clock(move || time);
+    });
+    for _ in 0..3 {
+        run_epoch(&mut run);
+    }
+    for node in run.net.correct_nodes() {
+        for batch in node.outputs() {
+            let time = batch.timestamp().expect("batch timestamp");
+            assert!(time >= 1000 && time < 1004, "unexpected timestamp {}", time);
+        }
+    }
+}
+
+/// During a node change, each node reports the same change state as its latest batch, and the
+/// progress of its key generation.
+#[test]
+fn test_dynamic_honey_badger_change_state() {
+    let mut run = new_dhb_run([14; 16], |_, _| ());
+    let mut new_pub_keys = run
+        .net
+        .correct_nodes()
+        .next()
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+    new_pub_keys.remove(&3);
+    let change = Change::NodeChange(new_pub_keys);
+    let _ = run
+        .net
+        .broadcast_input(&Input::Change(change.clone()), &mut run.rng)
+        .expect("could not vote");
+
+    let complete = ChangeState::Complete(change);
+    let is_complete = |node: &Node<DHB>| {
+        let mut batches = node.outputs().iter();
+        batches.any(|batch| *batch.change() == complete)
+    };
+    let mut in_progress_seen = false;
+    while !run.net.correct_nodes().all(&is_complete) {
+        run_epoch(&mut run);
+        for node in run.net.correct_nodes() {
+            let batch = node.outputs().last().expect("batch");
+            let dhb = node.algorithm().algo();
+            match batch.change() {
+                ChangeState::None => (),
+                ChangeState::InProgress(_) => {
+                    in_progress_seen = true;
+                    let progress = dhb.key_gen_progress().expect("key gen progress");
+                    let key_gen = dhb.key_gen().expect("key gen");
+                    assert_eq!(progress.parts, key_gen.received_parts().count());
+                    assert_eq!(progress.missing_parts, key_gen.missing_parts().count());
+                    assert_eq!(*batch.change(), dhb.change_state());
+                }
+                ChangeState::Complete(_) => {
+                    assert_eq!(batch.epoch() + 1, dhb.era());
+                    assert_eq!(*batch.change(), dhb.change_state());
+                }
+            }
+        }
+    }
+    assert!(in_progress_seen, "no key generation batch");
+}
+
+/// A local coin that counts how often it is evaluated.
+#[derive(Debug)]
+struct CountingCoin {
+    coin: PrfCoin,
+    count: AtomicUsize,
+}
+
+impl LocalCoin for CountingCoin {
+    fn value(&self, coin_id: &[u8]) -> bool {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.coin.value(coin_id)
+    }
+}
+
+/// The agreement instances of all eras use the configured local coin.
+#[test]
+fn test_dynamic_honey_badger_local_coin() {
+    let coin = Arc::new(CountingCoin {
+        coin: PrfCoin::new(b"shared key".to_vec()),
+        count: AtomicUsize::new(0),
+    });
+    let node_coin = coin.clone();
+    // The coin is flipped in every epoch, so every instance evaluates it at least once.
+    let schedule = CoinSchedule::new(vec![None]).expect("coin schedule");
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed([15; 16])
+        .no_time_limit()
+        .build(move |node: NewNodeInfo<DHB>| {
+            let id = node.id;
+            let dhb = DynamicHoneyBadger::builder()
+                .agreement_coin_schedule(schedule.clone())
+                .agreement_local_coin(node_coin.clone())
+                .build(node.netinfo.clone());
+            SenderQueue::builder(
+                dhb,
+                node.netinfo.all_ids().filter(|&&them| them != id).cloned(),
+            )
+            .build(id)
+        })
+        .expect("could not construct test network");
+
+    // Remove node 3. The first batch starts the key generation, and a new era.
+    let mut new_pub_keys = run
+        .net
+        .correct_nodes()
+        .next()
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+    new_pub_keys.remove(&3);
+    let change = Change::NodeChange(new_pub_keys);
+    let _ = run
+        .net
+        .broadcast_input(&Input::Change(change.clone()), &mut run.rng)
+        .expect("could not vote");
+    let complete = ChangeState::Complete(change);
+    let is_complete = |node: &Node<DHB>| {
+        let mut batches = node.outputs().iter();
+        batches.any(|batch| *batch.change() == complete)
+    };
+    run_epoch(&mut run);
+    let count = coin.count.load(Ordering::SeqCst);
+    assert!(count > 0);
+    for node in run.net.correct_nodes() {
+        assert_eq!(1, node.algorithm().algo().era());
+    }
+    while !run.net.correct_nodes().all(&is_complete) {
+        run_epoch(&mut run);
+    }
+    assert!(coin.count.load(Ordering::SeqCst) > count);
+}
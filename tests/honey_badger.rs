@@ -1,10 +1,19 @@
 #![deny(unused_must_use)]
 //! Network tests for Honey Badger.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
-
-use hbbft::honey_badger::{Batch, EncryptionSchedule, HoneyBadger, MessageContent};
+use std::time::Duration;
+
+use failure::Fail;
+use hbbft::binary_agreement::CoinSchedule;
+use hbbft::canonical::CanonicalContribution;
+use hbbft::header::Algorithm;
+use hbbft::honey_badger::{
+    Batch, ContributionOrder, EncryptionSchedule, FaultKind, HoneyBadger, MessageContent,
+};
+use hbbft::instrument::{CryptoOp, Instrument, Timing};
 use hbbft::sender_queue::{self, SenderQueue, Step};
 use hbbft::transaction_queue::TransactionQueue;
 use hbbft::{threshold_decrypt, util, CpStep, NetworkInfo, Target};
@@ -18,11 +27,17 @@ use itertools::Itertools;
 use log::info;
 use proptest::{prelude::ProptestConfig, proptest};
 use rand::{seq::SliceRandom, Rng, SeedableRng};
+use tiny_keccak::sha3_256;
 
 type NodeId = u16;
 type NetworkInfoMap = BTreeMap<NodeId, Arc<NetworkInfo<NodeId>>>;
 type UsizeHoneyBadger = SenderQueue<HoneyBadger<Vec<usize>, NodeId>>;
 type HoneyBadgerMessage = NetMessage<UsizeHoneyBadger>;
+type OrderedContributions<'a> = (
+    &'a BTreeMap<NodeId, Vec<usize>>,
+    &'a Vec<NodeId>,
+    Option<[u8; 32]>,
+);
 
 /// An adversary whose nodes only send messages with incorrect decryption shares.
 #[derive(Clone, Debug, Default)]
@@ -125,9 +140,35 @@ impl Adversary<UsizeHoneyBadger> for FaultyShareAdversary {
     }
 }
 
+/// An adversary whose nodes ignore all messages, and in particular never acknowledge any
+/// contribution hashes.
+#[derive(Clone, Debug, Default)]
+pub struct SilentAdversary;
+
+impl Adversary<UsizeHoneyBadger> for SilentAdversary {
+    #[inline]
+    fn pre_crank<R: Rng>(
+        &mut self,
+        mut net: NetMutHandle<'_, UsizeHoneyBadger, Self>,
+        rng: &mut R,
+    ) {
+        sort_by_random_node(&mut net, rng);
+    }
+
+    #[inline]
+    fn tamper<R: Rng>(
+        &mut self,
+        _net: NetMutHandle<'_, UsizeHoneyBadger, Self>,
+        _msg: HoneyBadgerMessage,
+        _rng: &mut R,
+    ) -> Result<CpStep<UsizeHoneyBadger>, CrankError<UsizeHoneyBadger>> {
+        Ok(CpStep::<UsizeHoneyBadger>::default())
+    }
+}
+
 /// Proposes `num_txs` values and expects nodes to output and order them.
 fn test_honey_badger<A>(
-    mut net: VirtualNet<UsizeHoneyBadger, A>,
+    net: &mut VirtualNet<UsizeHoneyBadger, A>,
     num_txs: usize,
     mut rng: &mut TestRng,
 ) where
@@ -161,7 +202,7 @@ fn test_honey_badger<A>(
             let _ = net.crank_expect(&mut rng);
         }
     }
-    verify_output_sequence(&net);
+    verify_output_sequence(net);
 }
 
 /// Verifies that all instances output the same sequence of batches.
@@ -169,13 +210,18 @@ fn verify_output_sequence<A>(network: &VirtualNet<UsizeHoneyBadger, A>)
 where
     A: Adversary<UsizeHoneyBadger>,
 {
-    let mut expected: Option<BTreeMap<u64, &_>> = None;
+    let mut expected: Option<BTreeMap<u64, _>> = None;
     for node in network.correct_nodes() {
         assert!(!node.outputs().is_empty());
-        let outputs: BTreeMap<u64, &BTreeMap<NodeId, Vec<usize>>> = node
+        let outputs: BTreeMap<u64, OrderedContributions<'_>> = node
             .outputs()
             .iter()
-            .map(|batch| (batch.epoch, &batch.contributions))
+            .map(|batch| {
+                (
+                    batch.epoch,
+                    (&batch.contributions, &batch.order, batch.seed),
+                )
+            })
             .collect();
         if expected.is_none() {
             expected = Some(outputs);
@@ -222,7 +268,7 @@ fn test_honey_badger_different_sizes<A, F>(
             num_good_nodes, num_adv_nodes
         );
 
-        let (net, _) = NetBuilder::new(0..size as u16)
+        let (mut net, _) = NetBuilder::new(0..size as u16)
             .num_faulty(num_adv_nodes as usize)
             .message_limit(10_000 * size as usize)
             .no_time_limit()
@@ -238,7 +284,7 @@ fn test_honey_badger_different_sizes<A, F>(
             .build(&mut rng)
             .expect("Could not construct test network.");
 
-        test_honey_badger(net, num_txs, &mut rng);
+        test_honey_badger(&mut net, num_txs, &mut rng);
     }
 }
 
@@ -270,6 +316,12 @@ proptest! {
     fn test_honey_badger_random_adversary(seed in gen_seed()) {
         do_test_honey_badger_random_adversary(seed)
     }
+
+    #[test]
+    #[allow(clippy::unnecessary_operation)]
+    fn test_honey_badger_pre_validation(seed in gen_seed()) {
+        do_test_honey_badger_pre_validation(seed)
+    }
 }
 
 fn do_test_honey_badger_random_delivery_silent(seed: TestRngSeed) {
@@ -293,3 +345,540 @@ fn do_test_honey_badger_random_adversary(seed: TestRngSeed) {
     };
     test_honey_badger_different_sizes(new_adversary, 8, seed, &Default::default());
 }
+
+/// Counts the threshold encryptions and decryption shares.
+#[derive(Default)]
+struct EncryptionCounter {
+    encryptions: AtomicUsize,
+    decryption_shares: AtomicUsize,
+}
+
+impl Instrument<NodeId> for EncryptionCounter {
+    fn crypto_op(&self, op: CryptoOp) {
+        match op {
+            CryptoOp::Encrypt => self.encryptions.fetch_add(1, Ordering::SeqCst),
+            CryptoOp::DecryptShare => self.decryption_shares.fetch_add(1, Ordering::SeqCst),
+            CryptoOp::Sign | CryptoOp::Verify => 0,
+        };
+    }
+}
+
+#[test]
+fn test_honey_badger_plaintext() {
+    let mut rng: TestRng = TestRng::from_seed([3; 16]);
+    let counter = Arc::new(EncryptionCounter::default());
+    let node_counter = counter.clone();
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .encryption_schedule(EncryptionSchedule::Never)
+                .instrument(node_counter.clone())
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    // Without encryption, there is no decryption round.
+    assert_eq!(0, counter.encryptions.load(Ordering::SeqCst));
+    assert_eq!(0, counter.decryption_shares.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_honey_badger_agreement_certificates() {
+    let mut rng: TestRng = TestRng::from_seed([9; 16]);
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .num_faulty(1)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .session_id(5)
+                .agreement_certificates(true)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 10, &mut rng);
+
+    // Every batch contains a certificate for each validator, that only verifies for its own
+    // session ID and epoch.
+    for node in net.correct_nodes() {
+        let netinfo = node.algorithm().inner().netinfo();
+        assert!(!node.outputs().is_empty());
+        for batch in node.outputs() {
+            assert_eq!(4, batch.agreement_certificates.len());
+            assert!(batch.verify_agreement_certificates(5, netinfo));
+            assert!(!batch.verify_agreement_certificates(6, netinfo));
+        }
+    }
+}
+
+#[test]
+fn test_honey_badger_precomputed_coin_shares() {
+    let mut rng: TestRng = TestRng::from_seed([10; 16]);
+    // The coin is flipped in every epoch, so the precomputed shares are used whenever an instance
+    // doesn't decide in its first epoch.
+    let schedule = CoinSchedule::new(vec![None]).expect("coin schedule");
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .num_faulty(1)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .agreement_coin_schedule(schedule.clone())
+                .agreement_precomputed_coin_shares(3)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 10, &mut rng);
+}
+
+#[test]
+fn test_honey_badger_batch_verification() {
+    let mut rng: TestRng = TestRng::from_seed([11; 16]);
+    let schedule = CoinSchedule::new(vec![None]).expect("coin schedule");
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .agreement_coin_schedule(schedule.clone())
+                .agreement_batch_verification(true)
+                .agreement_certificates(true)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 10, &mut rng);
+
+    for node in net.correct_nodes() {
+        let netinfo = node.algorithm().inner().netinfo();
+        for batch in node.outputs() {
+            assert!(batch.verify_agreement_certificates(0, netinfo));
+        }
+    }
+}
+
+#[test]
+fn test_honey_badger_shuffled() {
+    let mut rng: TestRng = TestRng::from_seed([5; 16]);
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            // Every other epoch is unencrypted: The seed is unpredictable in these, too.
+            let hb = HoneyBadger::builder(netinfo)
+                .encryption_schedule(EncryptionSchedule::EveryNthEpoch(2))
+                .contribution_order(ContributionOrder::Shuffled)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    // All nodes have computed the same seeds, and shuffle the transactions the same way.
+    let mut expected: Option<Vec<([u8; 32], Vec<usize>)>> = None;
+    let mut seeds = BTreeSet::new();
+    for node in net.correct_nodes() {
+        let shuffled: Vec<([u8; 32], Vec<usize>)> = node
+            .outputs()
+            .iter()
+            .map(|batch| {
+                let seed = batch.seed.expect("seed");
+                let txs = batch.shuffled_txs().expect("shuffle").expect("shuffled");
+                let mut sorted: Vec<usize> = txs.iter().map(|&&tx| tx).collect();
+                let mut unshuffled: Vec<usize> = batch.iter().cloned().collect();
+                sorted.sort();
+                unshuffled.sort();
+                assert_eq!(unshuffled, sorted);
+                (seed, txs.into_iter().cloned().collect())
+            })
+            .collect();
+        seeds.extend(shuffled.iter().map(|(seed, _)| *seed));
+        if expected.is_none() {
+            expected = Some(shuffled);
+        } else if let Some(expected) = &expected {
+            assert_eq!(expected, &shuffled);
+        }
+    }
+    // Each epoch has a different seed.
+    let num_batches = expected.map_or(0, |batches| batches.len());
+    assert!(num_batches > 1);
+    assert_eq!(num_batches, seeds.len());
+}
+
+#[test]
+fn test_honey_badger_pipelining() {
+    let mut rng: TestRng = TestRng::from_seed([8; 16]);
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(|info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo).pipeline_depth(2).build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 30, &mut rng);
+}
+
+/// Counts the timings, messages and faults, by kind.
+#[derive(Default)]
+struct MetricsCounter {
+    counts: Mutex<BTreeMap<String, usize>>,
+}
+
+impl MetricsCounter {
+    fn count(&self, event: String) {
+        *self.counts.lock().unwrap().entry(event).or_insert(0) += 1;
+    }
+
+    fn get(&self, event: &str) -> usize {
+        self.counts.lock().unwrap().get(event).cloned().unwrap_or(0)
+    }
+}
+
+impl Instrument<NodeId> for MetricsCounter {
+    fn message_received(&self, _: &NodeId, algorithm: Algorithm, _: u64) {
+        self.count(format!("received {:?}", algorithm));
+    }
+
+    fn timing(&self, timing: Timing, _: Duration) {
+        let timing = match timing {
+            Timing::Epoch(_) => "Epoch".to_string(),
+            Timing::Decryption(_) => "Decryption".to_string(),
+            timing => format!("{:?}", timing),
+        };
+        self.count(timing);
+    }
+
+    fn fault(&self, node_id: &NodeId, algorithm: Algorithm, _: &dyn Fail) {
+        self.count(format!("fault {} {:?}", node_id, algorithm));
+    }
+}
+
+#[test]
+fn test_honey_badger_metrics() {
+    let mut rng: TestRng = TestRng::from_seed([5; 16]);
+    let counter = Arc::new(MetricsCounter::default());
+    let node_counter = counter.clone();
+    // Node 0's contributions are considered invalid, so that there are faults to count.
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .no_time_limit()
+        .error_on_fault(false)
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .contribution_validator(|id, _: &Vec<usize>| *id != 0)
+                .instrument(node_counter.clone())
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    let num_epochs: usize = net.correct_nodes().map(|node| node.outputs().len()).sum();
+    assert_eq!(num_epochs, counter.get("Epoch"));
+    assert_eq!(num_epochs, counter.get("Decryption"));
+    assert!(counter.get("Subset") >= num_epochs);
+    assert!(counter.get("Broadcast") >= 3 * num_epochs);
+    assert!(counter.get("Agreement") >= 4 * num_epochs);
+    for algorithm in &["Broadcast", "BinaryAgreement", "HoneyBadger"] {
+        assert!(counter.get(&format!("received {}", algorithm)) > 0);
+    }
+    assert!(counter.get("fault 0 HoneyBadger") > 0);
+}
+
+/// Records the unreachable validators of each degraded connectivity check.
+#[derive(Default)]
+struct ConnectivityRecorder {
+    degraded: Mutex<Vec<BTreeSet<NodeId>>>,
+}
+
+impl Instrument<NodeId> for ConnectivityRecorder {
+    fn connectivity_degraded(&self, unreachable: &BTreeSet<NodeId>) {
+        self.degraded.lock().unwrap().push(unreachable.clone());
+    }
+}
+
+#[test]
+fn test_honey_badger_connectivity() {
+    let mut rng = rand::thread_rng();
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let recorder = Arc::new(ConnectivityRecorder::default());
+    let hb: HoneyBadger<Vec<usize>, NodeId> = HoneyBadger::builder(Arc::new(netinfos[&0].clone()))
+        .instrument(recorder.clone())
+        .build();
+
+    // Three out of four validators, including ourselves, are enough to make progress.
+    assert!(!hb.check_connectivity(&[1, 2]).is_degraded());
+    assert!(recorder.degraded.lock().unwrap().is_empty());
+
+    // With two, epochs stall, and the instrument is notified.
+    assert!(hb.check_connectivity(&[2]).is_degraded());
+    let expected: BTreeSet<NodeId> = vec![1, 3].into_iter().collect();
+    assert_eq!(vec![expected], *recorder.degraded.lock().unwrap());
+}
+
+#[test]
+fn test_honey_badger_subscribe() {
+    let mut rng: TestRng = TestRng::from_seed([9; 16]);
+    let receivers = Arc::new(Mutex::new(BTreeMap::new()));
+    let node_receivers = receivers.clone();
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(move |info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let mut hb = HoneyBadger::builder(netinfo).build();
+            let receiver = hb.subscribe();
+            node_receivers.lock().unwrap().insert(our_id, receiver);
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    // Each channel received exactly the node's output.
+    let receivers = receivers.lock().unwrap();
+    for node in net.correct_nodes() {
+        let received: Vec<_> = receivers[node.id()]
+            .try_iter()
+            .map(|batch| (batch.epoch, batch.contributions, batch.order))
+            .collect();
+        let outputs: Vec<_> = node
+            .outputs()
+            .iter()
+            .map(|batch| {
+                (
+                    batch.epoch,
+                    batch.contributions.clone(),
+                    batch.order.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(outputs, received);
+    }
+}
+
+#[test]
+fn test_honey_badger_contribution_validator() {
+    let mut rng: TestRng = TestRng::from_seed([4; 16]);
+    // Node 0 is correct, but its contributions are considered invalid, so it is reported anyway.
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .no_time_limit()
+        .error_on_fault(false)
+        .adversary(ReorderingAdversary::new())
+        .using_step(|info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .contribution_validator(|id, _: &Vec<usize>| *id != 0)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    for node in net.correct_nodes() {
+        assert!(node
+            .outputs()
+            .iter()
+            .all(|batch| !batch.contributions.contains_key(&0) && !batch.order.contains(&0)));
+        assert!(node.faults().iter().any(|fault| fault.node_id == 0));
+        assert!(node
+            .faults()
+            .iter()
+            .all(|fault| fault.node_id == 0 && fault.kind == FaultKind::InvalidContribution));
+    }
+}
+
+/// A contribution with its own encoding, that doesn't implement serde: the magic bytes `BLK`,
+/// followed by the content.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Block(Vec<u8>);
+
+impl CanonicalContribution for Block {
+    type Error = &'static str;
+
+    fn to_canonical_bytes(&self) -> Result<Vec<u8>, Self::Error> {
+        Ok([&b"BLK"[..], &self.0].concat())
+    }
+
+    fn from_canonical_bytes(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if !bytes.starts_with(b"BLK") {
+            return Err("missing magic bytes");
+        }
+        Ok(Block(bytes[3..].to_vec()))
+    }
+}
+
+/// Contributions that only implement `CanonicalContribution` are proposed, hashed and decoded in
+/// their own encoding.
+#[test]
+fn test_honey_badger_canonical_contribution() {
+    type BlockHoneyBadger = SenderQueue<HoneyBadger<Block, NodeId>>;
+    let mut rng: TestRng = TestRng::from_seed([5; 16]);
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using_step(|info: NewNodeInfo<BlockHoneyBadger>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo)
+                .encryption_schedule(EncryptionSchedule::EveryNthEpoch(2))
+                .contribution_order(ContributionOrder::Hash)
+                .build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let block = |id: NodeId, epoch: u8| Block(vec![id as u8, epoch]);
+
+    // Run two epochs, one encrypted and one not.
+    for epoch in 0..2 {
+        for id in 0..4 {
+            let _ = net
+                .send_input(id, block(id, epoch), &mut rng)
+                .expect("input");
+        }
+        while net
+            .correct_nodes()
+            .any(|node| node.outputs().len() <= epoch as usize)
+        {
+            let _ = net.crank_expect(&mut rng);
+        }
+    }
+
+    for node in net.correct_nodes() {
+        for (epoch, batch) in node.outputs().iter().enumerate() {
+            assert!(batch.contributions.len() >= 3);
+            for (id, contrib) in &batch.contributions {
+                assert_eq!(block(*id, epoch as u8), *contrib);
+            }
+            // The contributions are ordered by the hashes of their canonical encodings.
+            let hash = |id: &NodeId| {
+                let bytes = batch.contributions[id].to_canonical_bytes();
+                sha3_256(&bytes.expect("encode"))
+            };
+            let mut expected: Vec<NodeId> = batch.contributions.keys().cloned().collect();
+            expected.sort_by_key(hash);
+            assert_eq!(expected, batch.order);
+        }
+    }
+}
+
+fn do_test_honey_badger_pre_validation(seed: TestRngSeed) {
+    let mut rng: TestRng = TestRng::from_seed(seed);
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(70_000)
+        .no_time_limit()
+        .adversary(SilentAdversary)
+        .using_step(|info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(info.netinfo);
+            let our_id = *netinfo.our_id();
+            let peer_ids: Vec<_> = netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let hb = HoneyBadger::builder(netinfo).pre_validation(3).build();
+            SenderQueue::builder(hb, peer_ids.into_iter()).build(our_id)
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let faulty_ids: Vec<NodeId> = net.faulty_nodes().map(|node| *node.id()).collect();
+    test_honey_badger(&mut net, 20, &mut rng);
+
+    // The faulty nodes never acknowledge anything, so every correct node reports them.
+    for node in net.correct_nodes() {
+        for faulty_id in &faulty_ids {
+            assert!(node.faults().iter().any(|fault| fault.node_id == *faulty_id
+                && fault.kind == FaultKind::WithheldContributionAck));
+        }
+        assert!(node
+            .faults()
+            .iter()
+            .all(|fault| faulty_ids.contains(&fault.node_id)));
+    }
+}
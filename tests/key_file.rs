@@ -0,0 +1,173 @@
+#![deny(unused_must_use)]
+//! Tests for encrypted key files
+
+use hbbft::key_file::{self, Error, KdfParams, KeyFile, NodeKeys};
+use hbbft::NetworkInfo;
+use hbbft_testing::proptest::TestRng;
+use rand::SeedableRng;
+
+const PASSWORD: &[u8] = b"correct horse";
+
+/// Cheap key derivation parameters, so that the tests run quickly.
+const TEST_KDF: KdfParams = KdfParams {
+    memory_kib: 64,
+    iterations: 2,
+    parallelism: 1,
+};
+
+/// The offsets of the header fields in the encoding: the magic bytes are followed by the version,
+/// the three KDF parameters, the salt, the nonce and the length of the ciphertext.
+const VERSION_OFFSET: usize = 8;
+const MEMORY_OFFSET: usize = VERSION_OFFSET + 2;
+const ITERATIONS_OFFSET: usize = VERSION_OFFSET + 2 + 4;
+const SALT_OFFSET: usize = VERSION_OFFSET + 2 + 12;
+const NONCE_OFFSET: usize = SALT_OFFSET + 16;
+const CIPHERTEXT_OFFSET: usize = NONCE_OFFSET + 12 + 8;
+
+/// Returns the encoded key file of node 2, and the node's `NetworkInfo`.
+fn sealed_key_file(kdf: KdfParams) -> (Vec<u8>, NetworkInfo<u16>) {
+    let mut rng = TestRng::from_seed([3; 16]);
+    let mut netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let netinfo = netinfos.remove(&2).expect("netinfo");
+    let keys = NodeKeys::from_network_info(&netinfo).expect("keys");
+    let file = KeyFile::seal_with_params(&keys, PASSWORD, kdf, &mut rng).expect("seal key file");
+    assert_eq!(key_file::VERSION, file.version());
+    assert_eq!(kdf, *file.kdf_params());
+    (file.to_bytes().expect("encode key file"), netinfo)
+}
+
+/// Decodes the file and tries to open it with the correct password.
+fn open(bytes: &[u8]) -> key_file::Result<NodeKeys<u16>> {
+    KeyFile::from_bytes(bytes)?.open(PASSWORD)
+}
+
+#[test]
+fn test_key_file_round_trip() {
+    let (bytes, netinfo) = sealed_key_file(TEST_KDF);
+    assert!(bytes.starts_with(b"HBBFTKEY"));
+
+    // The keys are restored from the encoded file.
+    let file = KeyFile::from_bytes(&bytes).expect("decode key file");
+    let restored: NetworkInfo<u16> = file.into_network_info(PASSWORD).expect("open key file");
+    assert_eq!(2, *restored.our_id());
+    assert_eq!(netinfo.secret_key(), restored.secret_key());
+    assert_eq!(netinfo.secret_key_share(), restored.secret_key_share());
+    assert_eq!(netinfo.public_key_set(), restored.public_key_set());
+    assert_eq!(netinfo.public_key_map(), restored.public_key_map());
+    let keys = open(&bytes).expect("open");
+    assert_eq!(NodeKeys::from_network_info(&netinfo).expect("keys"), keys);
+
+    // The secret keys are encrypted.
+    let ser_key = bincode::serialize(&keys.secret_key).expect("serialize secret key");
+    let plain = bytes
+        .windows(ser_key.len())
+        .any(|window| *window == ser_key[..]);
+    assert!(!plain, "secret key stored in plain text");
+}
+
+#[test]
+fn test_key_file_default_params() {
+    let (bytes, netinfo) = sealed_key_file(KdfParams::default());
+    let keys = open(&bytes).expect("open");
+    assert_eq!(NodeKeys::from_network_info(&netinfo).expect("keys"), keys);
+}
+
+#[test]
+fn test_key_file_wrong_password() {
+    let (bytes, _) = sealed_key_file(TEST_KDF);
+    let file = KeyFile::from_bytes(&bytes).expect("decode key file");
+    assert_eq!(Some(Error::Decrypt), file.open::<u16>(b"wrong horse").err());
+    assert_eq!(Some(Error::Decrypt), file.open::<u16>(b"").err());
+}
+
+#[test]
+fn test_key_file_tampered() {
+    let (bytes, _) = sealed_key_file(TEST_KDF);
+    let offsets = vec![
+        ("KDF parameters", ITERATIONS_OFFSET),
+        ("salt", SALT_OFFSET),
+        ("nonce", NONCE_OFFSET),
+        ("ciphertext", CIPHERTEXT_OFFSET),
+        ("authentication tag", bytes.len() - 1),
+    ];
+    for (field, offset) in offsets {
+        let mut modified = bytes.clone();
+        modified[offset] ^= 1;
+        assert_eq!(Some(Error::Decrypt), open(&modified).err(), "{}", field);
+    }
+
+    // Truncated and extended ciphertexts are rejected, too.
+    let mut modified = bytes.clone();
+    modified.pop();
+    assert!(open(&modified).is_err());
+    let mut modified = bytes.clone();
+    modified.push(0);
+    assert!(open(&modified).is_err());
+}
+
+#[test]
+fn test_key_file_invalid_header() {
+    let (bytes, _) = sealed_key_file(TEST_KDF);
+    assert_eq!(Some(Error::NotAKeyFile), open(&bytes[1..]).err());
+
+    let mut modified = bytes.clone();
+    modified[VERSION_OFFSET] = 2;
+    assert_eq!(Some(Error::UnsupportedVersion(2)), open(&modified).err());
+
+    let mut modified = bytes.clone();
+    modified[ITERATIONS_OFFSET] = 0;
+    match open(&modified) {
+        Err(Error::InvalidKdfParams(_)) => (),
+        result => panic!("unexpected result: {:?}", result.map(|keys| keys.our_id)),
+    }
+}
+
+#[test]
+fn test_key_file_unknown_version() {
+    let (bytes, _) = sealed_key_file(TEST_KDF);
+
+    // A future version may have a different layout, so nothing after the version is parsed.
+    let mut modified = bytes[..VERSION_OFFSET].to_vec();
+    modified.extend_from_slice(&[7, 0, 1, 2, 3]);
+    assert_eq!(Some(Error::UnsupportedVersion(7)), open(&modified).err());
+    modified.truncate(VERSION_OFFSET + 2);
+    assert_eq!(Some(Error::UnsupportedVersion(7)), open(&modified).err());
+
+    // A truncated version is not a key file of any version.
+    match open(&bytes[..VERSION_OFFSET + 1]) {
+        Err(Error::Deserialize(_)) => (),
+        result => panic!("unexpected result: {:?}", result.map(|keys| keys.our_id)),
+    }
+}
+
+#[test]
+fn test_key_file_kdf_limits() {
+    let (bytes, _) = sealed_key_file(TEST_KDF);
+    let is_invalid = |result: key_file::Result<NodeKeys<u16>>| match result {
+        Err(Error::InvalidKdfParams(_)) => true,
+        _ => false,
+    };
+
+    // Excessive parameters are rejected before deriving the key, without allocating the memory.
+    let offsets = vec![
+        ("memory size", MEMORY_OFFSET),
+        ("iterations", ITERATIONS_OFFSET),
+        ("parallelism", ITERATIONS_OFFSET + 4),
+    ];
+    for (field, offset) in offsets {
+        let mut modified = bytes.clone();
+        modified[offset + 3] = 0xff;
+        assert!(is_invalid(open(&modified)), "{}", field);
+    }
+
+    // They can't be used for sealing either.
+    let mut rng = TestRng::from_seed([4; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let keys = NodeKeys::from_network_info(&netinfos[&0]).expect("keys");
+    let kdf = KdfParams {
+        memory_kib: key_file::MAX_MEMORY_KIB + 1,
+        ..TEST_KDF
+    };
+    let result = KeyFile::seal_with_params(&keys, PASSWORD, kdf, &mut rng);
+    assert!(is_invalid(result.map(|_| keys)));
+}
@@ -1,15 +1,34 @@
 #![deny(unused_must_use)]
 //! Network tests for Queueing Honey Badger.
 
-use std::collections::BTreeSet;
-use std::sync::Arc;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
-use hbbft::dynamic_honey_badger::{DynamicHoneyBadger, JoinPlan};
-use hbbft::queueing_honey_badger::{Change, ChangeState, Input, QueueingHoneyBadger};
+use hbbft::crypto::{
+    Ciphertext, DecryptionShare, PublicKey, PublicKeyShare, SecretKey, SecretKeyShare, Signature,
+    SignatureShare, G2,
+};
+use hbbft::dynamic_honey_badger::{
+    BatchCertificate, DynamicHoneyBadger, DynamicHoneyBadgerBuilder, JoinPlan, NodeSignature,
+    SignatureScheme,
+};
+use hbbft::header::Algorithm;
+use hbbft::instrument::{CryptoOp, Instrument, Transition};
+use hbbft::queueing_honey_badger::{
+    Change, ChangeState, Error as QhbError, Input, ProposalStrategy, QueueingHoneyBadger,
+    QueueingHoneyBadgerBuilder,
+};
 use hbbft::sender_queue::{Message, SenderQueue, Step};
-use hbbft::{util, NetworkInfo};
+use hbbft::transaction_queue::{TransactionJournal, TransactionQueue};
+use hbbft::{util, NetworkInfo, ShareSigner, Signer, Target};
 use hbbft_testing::adversary::{Adversary, NodeOrderAdversary, ReorderingAdversary};
 use hbbft_testing::proptest::{gen_seed, TestRng, TestRngSeed};
+use hbbft_testing::scenario::{Scenario, ScenarioRun, Strategy};
+use hbbft_testing::soak::Soak;
 use hbbft_testing::{NetBuilder, NewNodeInfo, Node, VirtualNet};
 use log::info;
 use proptest::{prelude::ProptestConfig, proptest};
@@ -17,6 +36,8 @@ use rand::{Rng, SeedableRng};
 
 type NodeId = u16;
 type QHB = SenderQueue<QueueingHoneyBadger<usize, NodeId, Vec<usize>>>;
+type QhbBuilder = QueueingHoneyBadgerBuilder<usize, NodeId, Vec<usize>>;
+type DhbBuilder = DynamicHoneyBadgerBuilder<Vec<usize>, NodeId>;
 
 // Send the second half of the transactions to the specified node.
 fn input_second_half<A>(
@@ -206,7 +227,7 @@ where
         .cloned()
         .collect();
 
-    let secret_key = node.algorithm().algo().netinfo().secret_key().clone();
+    let secret_key = node.algorithm().algo().netinfo().signer().clone();
     let (qhb, qhb_step) =
         QueueingHoneyBadger::builder_joining(our_id, secret_key, join_plan, &mut rng)
             .and_then(|builder| builder.batch_size(3).build(&mut rng))
@@ -238,6 +259,58 @@ fn new_queueing_hb(
     (sq, step)
 }
 
+/// Creates a network of four nodes that start with the transactions `txs`, and whose
+/// `QueueingHoneyBadger` instances are set up by `configure`.
+fn new_qhb_run<F>(seed: TestRngSeed, txs: Range<usize>, configure: F) -> ScenarioRun<QHB>
+where
+    F: Fn(QhbBuilder) -> QhbBuilder + 'static,
+{
+    new_qhb_run_with_dhb(seed, txs, |_| (), configure)
+}
+
+/// Like `new_qhb_run`, but additionally sets up the `DynamicHoneyBadger` instances with
+/// `configure_dhb`.
+fn new_qhb_run_with_dhb<D, F>(
+    seed: TestRngSeed,
+    txs: Range<usize>,
+    configure_dhb: D,
+    configure: F,
+) -> ScenarioRun<QHB>
+where
+    D: Fn(&mut DhbBuilder) + 'static,
+    F: Fn(QhbBuilder) -> QhbBuilder + 'static,
+{
+    Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let our_id = node_info.id;
+            // Give each node its own random number generator.
+            let mut node_seed = seed;
+            for (byte, id_byte) in node_seed.iter_mut().zip(&our_id.to_le_bytes()) {
+                *byte ^= id_byte;
+            }
+            let mut rng: TestRng = TestRng::from_seed(node_seed);
+            let peer_ids: Vec<NodeId> = node_info
+                .netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let mut dhb_builder = DynamicHoneyBadger::builder();
+            configure_dhb(&mut dhb_builder);
+            let dhb = dhb_builder.build(node_info.netinfo);
+            let (qhb, qhb_step) = configure(QueueingHoneyBadger::builder(dhb))
+                .build_with_transactions(txs.clone(), &mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.")
+}
+
 fn test_queueing_honey_badger_different_sizes<A, F>(
     new_adversary: F,
     num_txs: usize,
@@ -281,6 +354,794 @@ fn test_queueing_honey_badger_different_sizes<A, F>(
     }
 }
 
+/// Runs a small network for many epochs, and checks that the nodes keep producing consecutive
+/// batches without faults, and that the message queue stays bounded.
+#[test]
+fn test_queueing_honey_badger_soak() {
+    let seed = [7; 16];
+    let mut run = Scenario::new()
+        .nodes(4)
+        .adversaries(1, Strategy::Silent)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<_>| new_queueing_hb(Arc::new(node_info.netinfo), seed))
+        .expect("Could not construct test network.");
+    let mut next_tx = 0;
+    let report = Soak::<QHB>::new(60)
+        .check_every(10)
+        .invariant("consecutive batches", |net| {
+            net.correct_nodes().all(|node| {
+                let epochs: Vec<u64> = node.outputs().iter().map(|batch| batch.epoch()).collect();
+                epochs.windows(2).all(|pair| pair[0] + 1 == pair[1])
+            })
+        })
+        .invariant("no faults", |net| {
+            net.correct_nodes().all(|node| node.faults().is_empty())
+        })
+        .bound_messages(10_000)
+        .churn(move |run, _| {
+            // Enough transactions to keep the nodes proposing until the next check.
+            let ids: Vec<NodeId> = run.net.correct_nodes().map(|node| *node.id()).collect();
+            for tx in next_tx..(next_tx + 40) {
+                for id in &ids {
+                    let _ = run
+                        .net
+                        .send_input(*id, Input::User(tx), &mut run.rng)
+                        .expect("input");
+                }
+            }
+            next_tx += 40;
+        })
+        .run(&mut run)
+        .expect("soak test");
+    assert_eq!(60, report.epochs);
+    assert_eq!(6, report.checks);
+}
+
+/// With a key rotation interval, the validators generate new keys without changing the set of
+/// validators.
+#[test]
+fn test_queueing_honey_badger_key_rotation() {
+    let mut run = new_key_rotation_run([3; 16]);
+    let first = run.net.correct_nodes().next().expect("node");
+    let netinfo = first.algorithm().algo().dyn_hb().netinfo().clone();
+    for tx in 0..100 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    let rotated = |node: &Node<QHB>| {
+        node.outputs().iter().any(|batch| match batch.change() {
+            ChangeState::Complete(Change::NodeChange(pub_keys)) => {
+                *pub_keys == *netinfo.public_key_map()
+            }
+            _ => false,
+        })
+    };
+    // While the keys are being generated, the nodes report the rotation as the change in progress.
+    let mut key_gen_seen = false;
+    let done = run
+        .run_until(|net| {
+            for node in net.correct_nodes() {
+                let dhb = node.algorithm().algo().dyn_hb();
+                if dhb.key_gen().is_some() {
+                    let rotate_keys = ChangeState::InProgress(Change::RotateKeys);
+                    assert_eq!(rotate_keys, dhb.change_state());
+                    key_gen_seen = true;
+                }
+            }
+            net.correct_nodes().all(&rotated)
+        })
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+    assert!(key_gen_seen, "no key generation was observed");
+    for node in run.net.correct_nodes() {
+        assert!(node
+            .outputs()
+            .iter()
+            .any(|batch| *batch.change() == ChangeState::InProgress(Change::RotateKeys)));
+        let new_netinfo = node.algorithm().algo().dyn_hb().netinfo();
+        assert_eq!(netinfo.public_key_map(), new_netinfo.public_key_map());
+        assert_ne!(netinfo.public_key_set(), new_netinfo.public_key_set());
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_restore_key_gen() {
+    let mut run = new_key_rotation_run([4; 16]);
+    let netinfo = run
+        .net
+        .nodes()
+        .next()
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .clone();
+    for tx in 0..100 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    // Wait until some key generation parts have been committed.
+    let started = |node: &Node<QHB>| {
+        let progress = node.algorithm().algo().dyn_hb().key_gen_progress();
+        progress.into_iter().any(|progress| progress.parts > 0)
+    };
+    let done = run
+        .run_until(|net| net.correct_nodes().any(&started))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+
+    // Restore one node's key generation from a serialized checkpoint, replacing its state.
+    let id = *run
+        .net
+        .correct_nodes()
+        .find(|node| started(node))
+        .expect("node")
+        .id();
+    let dhb = run.net.get(id).expect("node").algorithm().algo().dyn_hb();
+    let checkpoint = dhb.key_gen_checkpoint().expect("key generation");
+    assert_eq!(dhb.era(), checkpoint.era());
+    let bytes = bincode::serialize(&checkpoint).expect("serialize");
+    let restored = bincode::deserialize(&bytes).expect("deserialize");
+    assert_eq!(checkpoint, restored);
+    let node = run.net.get_mut(id).expect("node");
+    let step = node
+        .algorithm_mut()
+        .restore_key_gen(restored, &mut run.rng)
+        .expect("restore");
+    run.net.process_step(id, &step).expect("process step");
+
+    // The key generation completes, and all nodes agree on the new keys.
+    let rotated = |node: &Node<QHB>| {
+        node.algorithm().algo().dyn_hb().netinfo().public_key_set() != netinfo.public_key_set()
+    };
+    let done = run
+        .run_until(|net| net.correct_nodes().all(&rotated))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+    let pub_key_sets: BTreeSet<_> = run
+        .net
+        .correct_nodes()
+        .map(|node| {
+            node.algorithm()
+                .algo()
+                .dyn_hb()
+                .netinfo()
+                .public_key_set()
+                .clone()
+        })
+        .collect();
+    assert_eq!(1, pub_key_sets.len());
+}
+
+/// A node built as an observer becomes a validator once the others vote to add it, and then
+/// contributes to the batches.
+#[test]
+fn test_queueing_honey_badger_observer_promotion() {
+    let seed = [5; 16];
+    let mut rng: TestRng = TestRng::from_seed(seed);
+    let validators = NetworkInfo::generate_map(0..4, &mut rng).expect("netinfos");
+    let observer_sk: SecretKey = rng.gen();
+    let observer_id: NodeId = 4;
+    let pub_key_set = validators[&0].public_key_set().clone();
+    let pub_keys = validators[&0].public_key_map().clone();
+    let mut run = Scenario::new()
+        .nodes(5)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = (0..5).filter(|&them| them != our_id).collect();
+            let dhb = match validators.get(&our_id) {
+                Some(netinfo) => DynamicHoneyBadger::builder().build(netinfo.clone()),
+                None => DynamicHoneyBadger::builder()
+                    .build_observer(
+                        our_id,
+                        observer_sk.clone(),
+                        pub_key_set.clone(),
+                        pub_keys.clone(),
+                    )
+                    .expect("observer"),
+            };
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder(dhb)
+                .batch_size(3)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+    let observer = run.net.get(observer_id).expect("observer");
+    assert!(!observer
+        .algorithm()
+        .algo()
+        .dyn_hb()
+        .netinfo()
+        .is_validator());
+    let observer_pk = observer.algorithm().algo().netinfo().signer().public_key();
+
+    let mut new_pub_keys = run
+        .net
+        .get(0)
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+    new_pub_keys.insert(observer_id, observer_pk);
+    for id in 0..4 {
+        let change = Input::Change(Change::NodeChange(new_pub_keys.clone()));
+        let _ = run.net.send_input(id, change, &mut run.rng).expect("vote");
+    }
+    for tx in 0..200 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+
+    // Once the change is complete, the observer contributes to a batch as a validator.
+    let contributed = |node: &Node<QHB>| {
+        node.outputs()
+            .iter()
+            .any(|batch| batch.contributions().any(|(id, _)| *id == observer_id))
+    };
+    let done = run
+        .run_until(|net| net.correct_nodes().all(&contributed))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+    for node in run.net.correct_nodes() {
+        let netinfo = node.algorithm().algo().dyn_hb().netinfo();
+        assert_eq!(new_pub_keys, *netinfo.public_key_map());
+        assert!(netinfo.is_validator());
+    }
+}
+
+/// With batch certificates, every node obtains a valid certificate for each batch, including the
+/// ones that conclude an era.
+#[test]
+fn test_queueing_honey_badger_batch_certificates() {
+    let seed = [6; 16];
+    let certificates: Arc<Mutex<BTreeMap<NodeId, Vec<BatchCertificate>>>> = Default::default();
+    let hook_certificates = certificates.clone();
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = node_info
+                .netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let mut dhb = DynamicHoneyBadger::builder()
+                .key_rotation_interval(5)
+                .batch_certificates(true)
+                .build(node_info.netinfo);
+            let node_certificates = hook_certificates.clone();
+            dhb.set_certificate_hook(move |certificate| {
+                let mut certificates = node_certificates.lock().expect("lock");
+                certificates
+                    .entry(our_id)
+                    .or_default()
+                    .push(certificate.clone());
+            });
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder(dhb)
+                .batch_size(3)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+    let first = run.net.correct_nodes().next().expect("node");
+    let mut pub_key_set = first.algorithm().algo().netinfo().public_key_set().clone();
+    for tx in 0..100 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    let done = run
+        .run_until(|net| net.correct_nodes().all(|node| node.outputs().len() >= 12))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+
+    // Each batch's certificate is signed with the key set of the batch's era, and is complete
+    // on every node once the next batch has been output.
+    let node = run.net.correct_nodes().next().expect("node");
+    let certificates = certificates.lock().expect("lock");
+    let mut eras = BTreeSet::new();
+    for (batch, next) in node.outputs().iter().zip(&node.outputs()[1..]) {
+        let exported = batch.export().expect("export");
+        eras.insert(batch.era());
+        for id in run.net.correct_nodes().map(Node::id) {
+            let certificate = certificates[id]
+                .iter()
+                .find(|certificate| certificate.epoch == batch.epoch())
+                .expect("certificate");
+            assert!(certificate.verify(&exported, &pub_key_set).expect("verify"));
+        }
+        pub_key_set = batch.network_info().public_key_set().clone();
+        assert_eq!(next.epoch(), batch.epoch() + 1);
+    }
+    assert!(eras.len() > 1, "no era changed");
+}
+
+/// Counts the instrumentation events, by their description.
+#[derive(Default)]
+struct CountingInstrument {
+    counts: Mutex<BTreeMap<String, usize>>,
+}
+
+impl CountingInstrument {
+    fn count(&self, event: String) {
+        *self.counts.lock().expect("lock").entry(event).or_insert(0) += 1;
+    }
+
+    fn get(&self, event: &str) -> usize {
+        self.counts
+            .lock()
+            .expect("lock")
+            .get(event)
+            .cloned()
+            .unwrap_or(0)
+    }
+}
+
+impl Instrument<NodeId> for CountingInstrument {
+    fn message_received(&self, _: &NodeId, algorithm: Algorithm, _: u64) {
+        self.count(format!("received {:?}", algorithm));
+    }
+
+    fn message_sent(&self, _: &Target<NodeId>, algorithm: Algorithm, _: u64) {
+        self.count(format!("sent {:?}", algorithm));
+    }
+
+    fn state_transition(&self, _: Algorithm, transition: Transition) {
+        let transition = match transition {
+            Transition::Epoch(_) => "epoch".to_string(),
+            Transition::Era(_) => "era".to_string(),
+            transition => format!("{:?}", transition),
+        };
+        self.count(transition);
+    }
+
+    fn crypto_op(&self, op: CryptoOp) {
+        self.count(format!("{:?}", op));
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_instrument() {
+    let seed = [7; 16];
+    let instrument = Arc::new(CountingInstrument::default());
+    let node_instrument = instrument.clone();
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = node_info
+                .netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let dhb = DynamicHoneyBadger::builder()
+                .key_rotation_interval(5)
+                .instrument(node_instrument.clone())
+                .build(node_info.netinfo);
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder(dhb)
+                .batch_size(3)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter())
+                .instrument(node_instrument.clone())
+                .build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+    for tx in 0..100 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    let done = run
+        .run_until(|net| net.correct_nodes().all(|node| node.outputs().len() >= 8))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+
+    for algorithm in &["HoneyBadger", "DynamicHoneyBadger", "SenderQueue"] {
+        assert!(instrument.get(&format!("received {}", algorithm)) > 0);
+        assert!(instrument.get(&format!("sent {}", algorithm)) > 0);
+    }
+    // Every node has reached at least eight epochs, and started a new era with new keys.
+    assert!(instrument.get("epoch") >= 4 * 8);
+    assert!(instrument.get("era") >= 4);
+    assert!(instrument.get("KeyGenStarted") >= 4);
+    assert!(instrument.get("KeyGenCompleted") >= 4);
+    for op in &["Encrypt", "DecryptShare", "Sign", "Verify"] {
+        assert!(instrument.get(op) > 0, "no {} operations", op);
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_queue_capacity() {
+    let mut rng: TestRng = TestRng::from_seed([9; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let dhb = DynamicHoneyBadger::builder().build(netinfos[&0].clone());
+    let (mut qhb, _) = QueueingHoneyBadger::<usize, NodeId, Vec<usize>>::builder(dhb)
+        .queue_capacity(2)
+        .build(&mut rng)
+        .expect("failed to build QueueingHoneyBadger");
+    for tx in 0..2 {
+        let _ = qhb.push_transaction(tx, &mut rng).expect("push");
+    }
+    match qhb.push_transaction(2, &mut rng) {
+        Err(QhbError::QueueFull) => (),
+        result => panic!("unexpected result: {:?}", result),
+    }
+    assert_eq!(2, qhb.queue_len());
+
+    // The initial transactions are subject to the same limit.
+    let dhb = DynamicHoneyBadger::builder().build(netinfos[&0].clone());
+    let result = QueueingHoneyBadger::<usize, NodeId, Vec<usize>>::builder(dhb)
+        .queue_capacity(2)
+        .build_with_transactions(0..3, &mut rng);
+    match result {
+        Err(QhbError::QueueFull) => (),
+        Err(err) => panic!("unexpected error: {:?}", err),
+        Ok(_) => panic!("the queue capacity was exceeded"),
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_remove_transactions() {
+    let mut rng: TestRng = TestRng::from_seed([13; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let dhb = DynamicHoneyBadger::builder().build(netinfos[&0].clone());
+    let (mut qhb, _) = QueueingHoneyBadger::<usize, NodeId, Vec<usize>>::builder(dhb)
+        .build_with_transactions(0..10, &mut rng)
+        .expect("failed to build QueueingHoneyBadger");
+    let removed = qhb.remove_transactions(|tx| tx % 2 == 1).expect("remove");
+    assert_eq!(vec![1, 3, 5, 7, 9], removed);
+    assert!(qhb.remove_transaction(&4).expect("remove"));
+    assert!(!qhb.remove_transaction(&4).expect("remove"));
+    assert_eq!(&vec![0, 2, 6, 8], qhb.queue());
+}
+
+/// A journal that keeps the transactions in a shared set, which survives the instance.
+#[derive(Clone, Default)]
+struct SharedJournal(Arc<Mutex<BTreeSet<usize>>>);
+
+impl TransactionJournal<usize> for SharedJournal {
+    fn put(&mut self, tx: &usize) -> io::Result<()> {
+        self.0.lock().expect("lock").insert(*tx);
+        Ok(())
+    }
+
+    fn remove(&mut self, tx: &usize) -> io::Result<()> {
+        self.0.lock().expect("lock").remove(tx);
+        Ok(())
+    }
+
+    fn transactions(&self) -> io::Result<Vec<usize>> {
+        Ok(self.0.lock().expect("lock").iter().cloned().collect())
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_journal() {
+    let mut rng: TestRng = TestRng::from_seed([14; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let journal = SharedJournal::default();
+    let new_qhb = |txs: Vec<usize>, rng: &mut TestRng| {
+        let dhb = DynamicHoneyBadger::builder().build(netinfos[&0].clone());
+        let (qhb, _) = QueueingHoneyBadger::<usize, NodeId, Vec<usize>>::builder(dhb)
+            .journal(journal.clone())
+            .build_with_transactions(txs, rng)
+            .expect("failed to build QueueingHoneyBadger");
+        qhb
+    };
+    let mut qhb = new_qhb(vec![0, 1], &mut rng);
+    let _ = qhb.push_transaction(2, &mut rng).expect("push");
+    assert!(qhb.remove_transaction(&1).expect("remove"));
+    assert_eq!(vec![0, 2], journal.transactions().expect("journal"));
+
+    // After a restart, the pending transactions are loaded from the journal.
+    drop(qhb);
+    let qhb = new_qhb(vec![3], &mut rng);
+    assert_eq!(&vec![0, 2, 3], qhb.queue());
+}
+
+/// A mempool that proposes the largest transactions first, and records the committed ones.
+#[derive(Debug)]
+struct Mempool {
+    pending: Vec<usize>,
+    committed: Vec<usize>,
+}
+
+impl Extend<usize> for Mempool {
+    fn extend<I: IntoIterator<Item = usize>>(&mut self, iter: I) {
+        self.pending.extend(iter);
+    }
+}
+
+impl TransactionQueue<usize> for Mempool {
+    fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn choose<R: Rng>(&mut self, _rng: &mut R, amount: usize, _batch_size: usize) -> Vec<usize> {
+        self.pending.sort_by_key(|&tx| Reverse(tx));
+        self.pending.iter().take(amount).cloned().collect()
+    }
+
+    fn remove_multiple<'a, I>(&mut self, txs: I)
+    where
+        I: IntoIterator<Item = &'a usize>,
+    {
+        for tx in txs {
+            self.pending.retain(|pending| pending != tx);
+            self.committed.push(*tx);
+        }
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&usize) -> bool,
+    {
+        self.pending.retain(f);
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_custom_queue() {
+    type CustomQHB = SenderQueue<QueueingHoneyBadger<usize, NodeId, Mempool>>;
+    let seed = [10; 16];
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<CustomQHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = node_info
+                .netinfo
+                .all_ids()
+                .filter(|&&them| them != our_id)
+                .cloned()
+                .collect();
+            let dhb = DynamicHoneyBadger::builder().build(node_info.netinfo);
+            let mempool = Mempool {
+                pending: (0..10).collect(),
+                committed: Vec::new(),
+            };
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder_with_queue(dhb, mempool)
+                .batch_size(4)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+    let done = run
+        .run_until(|net| {
+            net.correct_nodes()
+                .all(|node| node.algorithm().algo().queue().is_empty())
+        })
+        .expect("crank");
+    assert!(done, "the network stalled");
+    for node in run.net.correct_nodes() {
+        let mut committed = node.algorithm().algo().queue().committed.clone();
+        // Each node proposed its largest transactions first.
+        assert!(committed.starts_with(&[9]));
+        committed.sort();
+        committed.dedup();
+        assert_eq!((0..10).collect::<Vec<_>>(), committed);
+    }
+}
+
+/// Creates a network of four nodes with the transactions `0..200` and an initial batch size of 4,
+/// which is adjusted to the queue length if `adaptive`.
+fn new_batch_size_run(adaptive: bool) -> ScenarioRun<QHB> {
+    new_qhb_run([12; 16], 0..200, move |qhb| {
+        let qhb = qhb.batch_size(4);
+        if adaptive {
+            qhb.adaptive_batch_size(4, 64)
+        } else {
+            qhb
+        }
+    })
+}
+
+#[test]
+fn test_queueing_honey_badger_adaptive_batch_size() {
+    let queues_empty = |net: &VirtualNet<QHB, _>| {
+        net.correct_nodes()
+            .all(|node| node.algorithm().algo().queue().is_empty())
+    };
+    let mut fixed = new_batch_size_run(false);
+    assert!(fixed.run_until(queues_empty).expect("crank"));
+    let mut adaptive = new_batch_size_run(true);
+    assert!(adaptive.run_until(queues_empty).expect("crank"));
+
+    // With a deep queue, the batches grow, so that fewer epochs are needed to commit everything.
+    let epochs = |run: &ScenarioRun<QHB>| {
+        let node = run.net.correct_nodes().next().expect("node");
+        node.outputs().len()
+    };
+    assert!(epochs(&adaptive) < epochs(&fixed) / 2);
+    for node in adaptive.net.correct_nodes() {
+        assert!(node.algorithm().algo().batch_size() <= 64);
+    }
+}
+
+/// Returns the number of transactions that were proposed more than once in the first batch of a
+/// network where all nodes start with the same 40 transactions, but sample them with their own
+/// random number generators.
+fn count_duplicates(proposal_strategy: ProposalStrategy) -> usize {
+    let mut run = new_qhb_run([15; 16], 0..40, move |qhb| {
+        qhb.batch_size(40).proposal_strategy(proposal_strategy)
+    });
+    let done = run
+        .run_until(|net| net.correct_nodes().all(|node| !node.outputs().is_empty()))
+        .expect("crank");
+    assert!(done, "the network stalled");
+    let node = run.net.correct_nodes().next().expect("node");
+    let batch = &node.outputs()[0];
+    let distinct: BTreeSet<_> = batch.iter().collect();
+    batch.len() - distinct.len()
+}
+
+#[test]
+fn test_queueing_honey_badger_hash_partitioned_proposals() {
+    let random = count_duplicates(ProposalStrategy::Random);
+    let partitioned = count_duplicates(ProposalStrategy::HashPartitioned);
+    assert!(
+        partitioned < random,
+        "{} duplicates with hash partitioning, {} without",
+        partitioned,
+        random
+    );
+}
+
+/// Creates a network of four nodes with the transactions `0..10`, that drop pending transactions
+/// after `transaction_ttl` epochs, and if `expire_before_epoch`, remove all transactions smaller
+/// than the batch's epoch.
+fn new_expiry_run(transaction_ttl: Option<u64>, expire_before_epoch: bool) -> ScenarioRun<QHB> {
+    new_qhb_run([11; 16], 0..10, move |qhb| {
+        let mut qhb = qhb.batch_size(4);
+        if let Some(ttl) = transaction_ttl {
+            qhb = qhb.transaction_ttl(ttl);
+        }
+        if expire_before_epoch {
+            qhb = qhb.expiry(|&tx, epoch, _| (tx as u64) < epoch);
+        }
+        qhb
+    })
+}
+
+#[test]
+fn test_queueing_honey_badger_transaction_ttl() {
+    let mut run = new_expiry_run(Some(1), false);
+    let done = run
+        .run_until(|net| {
+            net.correct_nodes()
+                .all(|node| node.algorithm().algo().queue().is_empty())
+        })
+        .expect("crank");
+    assert!(done, "the network stalled");
+    for node in run.net.correct_nodes() {
+        // Only the proposals of the first two epochs were committed, then the rest expired.
+        assert!(node.outputs().iter().skip(2).all(|batch| batch.is_empty()));
+        let committed: BTreeSet<usize> = node
+            .outputs()
+            .iter()
+            .flat_map(|batch| batch.iter())
+            .cloned()
+            .collect();
+        assert!(committed.len() < 10);
+    }
+}
+
+#[test]
+fn test_queueing_honey_badger_expiry() {
+    let mut run = new_expiry_run(None, true);
+    let done = run
+        .run_until(|net| {
+            net.correct_nodes()
+                .all(|node| node.algorithm().algo().queue().is_empty())
+        })
+        .expect("crank");
+    assert!(done, "the network stalled");
+    // Expired transactions are never committed, and all nodes exclude the same ones.
+    let first = run.net.correct_nodes().next().expect("node");
+    for node in run.net.correct_nodes() {
+        for (batch, first_batch) in node.outputs().iter().zip(first.outputs()) {
+            assert!(batch.iter().all(|&tx| tx as u64 >= batch.epoch()));
+            assert!(batch.public_eq(first_batch));
+        }
+    }
+}
+
+/// Creates a network of four idle nodes, that only propose if they have pending transactions, or
+/// if other validators did.
+fn new_idle_run(wake_on_first_proposal: bool) -> ScenarioRun<QHB> {
+    new_qhb_run([8; 16], 0..0, move |qhb| {
+        qhb.batch_size(3)
+            .wake_on_first_proposal(wake_on_first_proposal)
+    })
+}
+
+#[test]
+fn test_queueing_honey_badger_wake_on_first_proposal() {
+    let has_tx = |node: &Node<QHB>| {
+        node.outputs()
+            .iter()
+            .any(|batch| batch.iter().any(|&tx| tx == 5))
+    };
+
+    // By default, a single node's transaction is not enough to start an epoch.
+    let mut run = new_idle_run(false);
+    let _ = run
+        .net
+        .send_input(0, Input::User(5), &mut run.rng)
+        .expect("input");
+    let done = run
+        .run_until(|net| net.correct_nodes().all(has_tx))
+        .expect("crank");
+    assert!(!done);
+    assert!(run
+        .net
+        .correct_nodes()
+        .all(|node| node.outputs().is_empty()));
+
+    // If the nodes wake up on the first proposal, the transaction is output.
+    let mut run = new_idle_run(true);
+    let _ = run
+        .net
+        .send_input(0, Input::User(5), &mut run.rng)
+        .expect("input");
+    let done = run
+        .run_until(|net| net.correct_nodes().all(has_tx))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+}
+
+/// Creates a network of four nodes that rotate their keys every five epochs.
+fn new_key_rotation_run(seed: TestRngSeed) -> ScenarioRun<QHB> {
+    new_qhb_run_with_dhb(
+        seed,
+        0..0,
+        |dhb| {
+            dhb.key_rotation_interval(5);
+        },
+        |qhb| qhb.batch_size(3),
+    )
+}
+
 proptest! {
     #![proptest_config(ProptestConfig {
         cases: 1, .. ProptestConfig::default()
@@ -306,3 +1167,216 @@ fn do_test_queueing_honey_badger_random_delivery_silent(seed: TestRngSeed) {
 fn do_test_queueing_honey_badger_first_delivery_silent(seed: TestRngSeed) {
     test_queueing_honey_badger_different_sizes(NodeOrderAdversary::new, 30, seed);
 }
+
+/// A toy signature scheme, for testing only: Anyone who knows a node's public key can forge its
+/// signatures.
+#[derive(Debug, Default)]
+struct HashSignatures {
+    verified: AtomicUsize,
+}
+
+impl SignatureScheme<NodeId> for HashSignatures {
+    fn sign(&self, signer: &dyn Signer, doc: &[u8]) -> NodeSignature {
+        let mut bytes = signer.public_key().to_bytes().to_vec();
+        bytes.extend_from_slice(doc);
+        NodeSignature::new(tiny_keccak::sha3_256(&bytes).to_vec())
+    }
+
+    fn verify(&self, _: &NodeId, pk: &PublicKey, doc: &[u8], sig: &NodeSignature) -> bool {
+        self.verified.fetch_add(1, Ordering::SeqCst);
+        let mut bytes = pk.to_bytes().to_vec();
+        bytes.extend_from_slice(doc);
+        tiny_keccak::sha3_256(&bytes)[..] == *sig.as_bytes()
+    }
+}
+
+/// Votes and key generation messages can be signed with a different scheme than the pairing-based
+/// one, if all nodes use it.
+#[test]
+fn test_queueing_honey_badger_signature_scheme() {
+    let seed = [7; 16];
+    let scheme = Arc::new(HashSignatures::default());
+    let node_scheme = scheme.clone();
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = (0..4).filter(|&them| them != our_id).collect();
+            let dhb = DynamicHoneyBadger::builder()
+                .signature_scheme(node_scheme.clone())
+                .build(node_info.netinfo);
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder(dhb)
+                .batch_size(3)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+
+    // Remove node 3: The votes and the key generation messages are signed with the scheme.
+    let mut new_pub_keys = run
+        .net
+        .get(0)
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+    new_pub_keys.remove(&3);
+    for id in 0..4 {
+        let change = Input::Change(Change::NodeChange(new_pub_keys.clone()));
+        let _ = run.net.send_input(id, change, &mut run.rng).expect("vote");
+    }
+    for tx in 0..200 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    let removed = |node: &Node<QHB>| {
+        *node.algorithm().algo().dyn_hb().netinfo().public_key_map() == new_pub_keys
+    };
+    let done = run
+        .run_until(|net| net.correct_nodes().all(&removed))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+    assert!(scheme.verified.load(Ordering::SeqCst) > 0);
+    for node in run.net.correct_nodes() {
+        assert!(node.faults().is_empty());
+    }
+}
+
+/// A signer that only exposes the operations with its key, like a hardware security module.
+#[derive(Debug)]
+struct ExternalSigner {
+    key: SecretKey,
+    count: Arc<AtomicUsize>,
+}
+
+impl Signer for ExternalSigner {
+    fn public_key(&self) -> PublicKey {
+        self.key.public_key()
+    }
+
+    fn sign(&self, doc: &[u8]) -> Signature {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.key.sign(doc)
+    }
+
+    fn decrypt(&self, ct: &Ciphertext) -> Option<Vec<u8>> {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.key.decrypt(ct)
+    }
+}
+
+/// A share signer that only exposes the operations with its key share.
+#[derive(Debug)]
+struct ExternalShareSigner {
+    key_share: SecretKeyShare,
+    count: Arc<AtomicUsize>,
+}
+
+impl ShareSigner for ExternalShareSigner {
+    fn public_key_share(&self) -> PublicKeyShare {
+        self.key_share.public_key_share()
+    }
+
+    fn sign_g2(&self, hash: G2) -> SignatureShare {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.key_share.sign_g2(hash)
+    }
+
+    fn decrypt_share(&self, ct: &Ciphertext) -> DecryptionShare {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.key_share.decrypt_share_no_verify(ct)
+    }
+}
+
+/// The nodes can run with external signers instead of secret keys in memory, and keep using them
+/// after a key generation.
+#[test]
+fn test_queueing_honey_badger_external_signers() {
+    let seed = [8; 16];
+    let signs = Arc::new(AtomicUsize::new(0));
+    let share_signs = Arc::new(AtomicUsize::new(0));
+    let (node_signs, node_share_signs) = (signs.clone(), share_signs.clone());
+    let mut run = Scenario::new()
+        .nodes(4)
+        .seed(seed)
+        .no_time_limit()
+        .build(move |node_info: NewNodeInfo<QHB>| {
+            let mut rng: TestRng = TestRng::from_seed(seed);
+            let our_id = node_info.id;
+            let peer_ids: Vec<NodeId> = (0..4).filter(|&them| them != our_id).collect();
+            let netinfo = node_info.netinfo;
+            let signer = Arc::new(ExternalSigner {
+                key: netinfo.secret_key().expect("secret key").clone(),
+                count: node_signs.clone(),
+            });
+            let share_signer = Arc::new(ExternalShareSigner {
+                key_share: netinfo.secret_key_share().expect("key share").clone(),
+                count: node_share_signs.clone(),
+            });
+            let netinfo = NetworkInfo::try_with_signers(
+                our_id,
+                Some(share_signer as Arc<dyn ShareSigner>),
+                netinfo.public_key_set().clone(),
+                signer as Arc<dyn Signer>,
+                netinfo.public_key_map().clone(),
+            )
+            .expect("consistent signers");
+            assert!(netinfo.secret_key().is_none());
+            assert!(netinfo.secret_key_share().is_none());
+            let dhb = DynamicHoneyBadger::builder().build(netinfo);
+            let (qhb, qhb_step) = QueueingHoneyBadger::builder(dhb)
+                .batch_size(3)
+                .build(&mut rng)
+                .expect("failed to build QueueingHoneyBadger");
+            let (sq, mut step) = SenderQueue::builder(qhb, peer_ids.into_iter()).build(our_id);
+            let _ = step.extend_with(qhb_step, |fault| fault, Message::from);
+            (sq, step)
+        })
+        .expect("Could not construct test network.");
+
+    // Remove node 3: The key generation decrypts with the signers.
+    let mut new_pub_keys = run
+        .net
+        .get(0)
+        .expect("node")
+        .algorithm()
+        .algo()
+        .netinfo()
+        .public_key_map()
+        .clone();
+    new_pub_keys.remove(&3);
+    for id in 0..4 {
+        let change = Input::Change(Change::NodeChange(new_pub_keys.clone()));
+        let _ = run.net.send_input(id, change, &mut run.rng).expect("vote");
+    }
+    for tx in 0..200 {
+        let _ = run
+            .net
+            .broadcast_input(&Input::User(tx), &mut run.rng)
+            .expect("input");
+    }
+    let removed = |node: &Node<QHB>| {
+        *node.algorithm().algo().dyn_hb().netinfo().public_key_map() == new_pub_keys
+    };
+    let done = run
+        .run_until(|net| net.correct_nodes().all(&removed))
+        .expect("crank");
+    assert!(done, "the queue ran empty");
+    assert!(signs.load(Ordering::SeqCst) > 0);
+    assert!(share_signs.load(Ordering::SeqCst) > 0);
+    for node in run.net.correct_nodes() {
+        assert!(node.faults().is_empty());
+        let netinfo = node.algorithm().algo().dyn_hb().netinfo();
+        assert!(netinfo.secret_key().is_none());
+    }
+}
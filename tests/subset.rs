@@ -2,9 +2,9 @@
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::iter::once;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use hbbft::subset::{Subset, SubsetOutput};
+use hbbft::subset::{BaSessionId, FaultKind, ProposerProgress, Subset, SubsetOutput};
 use hbbft::ConsensusProtocol;
 use hbbft_testing::adversary::{Adversary, NodeOrderAdversary, ReorderingAdversary};
 use hbbft_testing::proptest::{gen_seed, TestRng, TestRngSeed};
@@ -106,6 +106,103 @@ where
     net
 }
 
+#[test]
+fn test_subset_validity_predicate() {
+    let mut rng: TestRng = TestRng::from_seed([1; 16]);
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .num_faulty(1)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let mut subset =
+                Subset::new(Arc::new(node_info.netinfo), 0).expect("new Subset instance");
+            subset.set_validity_predicate(|_, value| !value.starts_with(b"bad"));
+            subset
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let undecided = ProposerProgress {
+        delivered: false,
+        decision: None,
+    };
+    let progress = net.get(1).expect("node").algorithm().progress();
+    assert!(progress.values().all(|p| *p == undecided));
+    for id in 0..4u16 {
+        let value = if id == 0 {
+            Vec::from("bad")
+        } else {
+            vec![id as u8]
+        };
+        let _ = net.send_input(id, value, &mut rng).expect("input");
+    }
+    while !net.nodes().all(|node| node.algorithm().terminated()) {
+        let _ = net.crank_expect(&mut rng);
+    }
+    // The faulty node's invalid value is rejected, and it is reported.
+    let expected: Vec<_> = (1..4u16)
+        .map(|id| SubsetOutput::Contribution(id, vec![id as u8]))
+        .chain(once(SubsetOutput::Done))
+        .collect();
+    for node in net.correct_nodes() {
+        let mut outputs = node.outputs().to_vec();
+        outputs.sort();
+        assert_eq!(expected, outputs);
+        assert!(node
+            .faults()
+            .iter()
+            .any(|fault| fault.node_id == 0 && fault.kind == FaultKind::InvalidProposal));
+        let progress = node.algorithm().progress();
+        assert_eq!(Some(false), progress[&0].decision);
+        assert!((1..4).all(|id| progress[&id].delivered && progress[&id].decision == Some(true)));
+    }
+}
+
+#[test]
+fn test_subset_agreement_certificates() {
+    let mut rng: TestRng = TestRng::from_seed([2; 16]);
+    let pub_key_set = Arc::new(Mutex::new(None));
+    let node_pub_key_set = pub_key_set.clone();
+    let (mut net, _) = NetBuilder::new(0..4u16)
+        .num_faulty(1)
+        .no_time_limit()
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            *node_pub_key_set.lock().expect("lock") =
+                Some(node_info.netinfo.public_key_set().clone());
+            let mut subset =
+                Subset::new(Arc::new(node_info.netinfo), 7u64).expect("new Subset instance");
+            subset.set_agreement_certify(true);
+            subset
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    // The faulty node doesn't propose, so its instance decides `false`.
+    for id in 1..4u16 {
+        let _ = net.send_input(id, vec![id as u8], &mut rng).expect("input");
+    }
+    while !net.nodes().all(|node| node.algorithm().terminated()) {
+        let _ = net.crank_expect(&mut rng);
+    }
+    // The validators' indices coincide with their IDs.
+    let pub_key_set = pub_key_set
+        .lock()
+        .expect("lock")
+        .clone()
+        .expect("public key set");
+    for node in net.correct_nodes() {
+        assert_eq!(Some(&SubsetOutput::Done), node.outputs().last());
+        let certs = node.algorithm().agreement_certificates();
+        assert_eq!(4, certs.len());
+        for (id, cert) in certs {
+            assert_eq!(id != 0, cert.decision);
+            let ba_id = BaSessionId::new(7u64, u32::from(id));
+            assert!(cert.verify(&ba_id, &pub_key_set).expect("verify"));
+            let wrong_id = BaSessionId::new(8u64, u32::from(id));
+            assert!(!cert.verify(&wrong_id, &pub_key_set).expect("verify"));
+        }
+    }
+}
+
 proptest! {
     #![proptest_config(ProptestConfig {
         cases: 1, .. ProptestConfig::default()
@@ -3,11 +3,16 @@
 
 use std::collections::BTreeMap;
 
-use hbbft::crypto::{PublicKey, SecretKey};
-use hbbft::sync_key_gen::{PartOutcome, SyncKeyGen};
-use hbbft::util;
+use hbbft::crypto::poly::{BivarCommitment, BivarPoly};
+use hbbft::crypto::{Ciphertext, PublicKey, SecretKey, SecretKeyShare};
+use hbbft::sync_key_gen::{
+    Ack, AckFault, AckOutcome, Error, Part, PartFault, PartOutcome, SyncKeyGen,
+    SyncKeyGenCheckpoint,
+};
+use hbbft::{util, NetworkInfo};
+use rand::{rngs::StdRng, SeedableRng};
 
-fn test_sync_key_gen_with(threshold: usize, node_num: usize) {
+fn test_sync_key_gen_with(threshold: usize, node_num: usize, batch_acks: bool) {
     // Generate individual key pairs for encryption. These are not suitable for threshold schemes.
     let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
     let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
@@ -52,13 +57,45 @@ fn test_sync_key_gen_with(threshold: usize, node_num: usize) {
         }
     }
 
+    // Every node knows which parts it has received, and that nobody has acked them yet.
+    for node in &nodes {
+        let received: Vec<_> = node.received_parts().cloned().collect();
+        let missing: Vec<_> = node.missing_parts().cloned().collect();
+        assert_eq!((0..=threshold).collect::<Vec<_>>(), received);
+        assert_eq!((threshold + 1..node_num).collect::<Vec<_>>(), missing);
+        assert_eq!(node_num, node.missing_acks(&0).count());
+        assert!(!node.is_share_ready());
+    }
+
     // Handle the `Ack`s from `2 * threshold + 1` nodes.
-    for (sender_id, ack) in acks {
+    if batch_acks {
         for node in &mut nodes {
             assert!(!node.is_ready()); // Not enough `Ack`s yet.
-            node.handle_ack(&sender_id, ack.clone())
-                .expect("error handling ack");
+            let outcomes = node
+                .handle_acks(acks.iter().map(|(sender_id, ack)| (sender_id, ack.clone())))
+                .expect("error handling acks");
+            assert_eq!(acks.len(), outcomes.len());
+            for outcome in outcomes {
+                if let AckOutcome::Invalid(fault) = outcome {
+                    panic!("invalid ack: {:?}", fault);
+                }
+            }
         }
+    } else {
+        for (sender_id, ack) in acks {
+            for node in &mut nodes {
+                assert!(!node.is_ready()); // Not enough `Ack`s yet.
+                node.handle_ack(&sender_id, ack.clone())
+                    .expect("error handling ack");
+            }
+        }
+    }
+
+    // Only the nodes whose `Ack`s were not handled are missing.
+    for node in &nodes {
+        let missing: Vec<_> = node.missing_acks(&threshold).cloned().collect();
+        assert_eq!((2 * threshold + 1..node_num).collect::<Vec<_>>(), missing);
+        assert!(node.is_share_ready());
     }
 
     // Compute the keys and test a threshold signature.
@@ -98,6 +135,336 @@ fn test_sync_key_gen() {
 
     for &node_num in &[1, 2, 3, 4, 8, 15] {
         let threshold = util::max_faulty(node_num);
-        test_sync_key_gen_with(threshold, node_num);
+        test_sync_key_gen_with(threshold, node_num, false);
+    }
+}
+
+#[test]
+fn test_sync_key_gen_batched_acks() {
+    // This returns an error in all but the first test.
+    let _ = env_logger::try_init();
+
+    for &node_num in &[1, 2, 3, 4, 8, 15] {
+        let threshold = util::max_faulty(node_num);
+        test_sync_key_gen_with(threshold, node_num, true);
+    }
+}
+
+#[test]
+fn test_sync_key_gen_with_poly() {
+    let node_num = 4;
+    let threshold = util::max_faulty(node_num);
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+
+    // The same seed always produces the same polynomial, so it can be committed to in advance.
+    let poly = BivarPoly::random(threshold, &mut StdRng::from_seed([7; 32]));
+    let commit = BivarPoly::random(threshold, &mut StdRng::from_seed([7; 32])).commitment();
+    let (_, part) = SyncKeyGen::new_with_poly(
+        0,
+        sec_keys[0].clone(),
+        pub_keys.clone(),
+        threshold,
+        &poly,
+        Some(&commit),
+        &mut rand::thread_rng(),
+    )
+    .expect("failed to create `SyncKeyGen` instance");
+    assert_eq!(&commit, part.expect("part").commitment());
+
+    // A polynomial that doesn't match the commitment is rejected.
+    let other_poly = BivarPoly::random(threshold, &mut StdRng::from_seed([8; 32]));
+    let result = SyncKeyGen::new_with_poly(
+        0,
+        sec_keys[0].clone(),
+        pub_keys.clone(),
+        threshold,
+        &other_poly,
+        Some(&commit),
+        &mut rand::thread_rng(),
+    );
+    assert_eq!(Some(Error::PolyCommitment), result.err());
+
+    // A polynomial with the wrong degree is rejected.
+    let result = SyncKeyGen::new_with_poly(
+        0,
+        sec_keys[0].clone(),
+        pub_keys,
+        threshold + 1,
+        &poly,
+        None,
+        &mut rand::thread_rng(),
+    );
+    assert_eq!(Some(Error::PolyDegree), result.err());
+}
+
+#[test]
+fn test_sync_key_gen_resharing() {
+    let mut rng = rand::thread_rng();
+    // The existing validators are 0 to 3. Nodes 0 and 1 leave, and nodes 4 to 8 join.
+    let old_netinfos = NetworkInfo::generate_map(0..4usize, &mut rng).expect("netinfos");
+    let old_pk_set = old_netinfos[&0].public_key_set().clone();
+    let new_ids = 2..9usize;
+    let threshold = util::max_faulty(new_ids.len());
+    let sec_keys: BTreeMap<usize, SecretKey> = new_ids
+        .map(|id| match old_netinfos.get(&id) {
+            Some(netinfo) => (id, netinfo.secret_key().expect("secret key").clone()),
+            None => (id, SecretKey::random()),
+        })
+        .collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(|(id, sk)| (*id, sk.public_key()))
+        .collect();
+
+    // Only the existing validators create a `Part`, including the ones that are leaving.
+    let mut parts = Vec::new();
+    for (id, netinfo) in &old_netinfos {
+        let sk = netinfo.secret_key().expect("secret key").clone();
+        let (key_gen, part) =
+            SyncKeyGen::new_resharing(*id, sk, pub_keys.clone(), threshold, netinfo, &mut rng)
+                .expect("failed to create resharing `SyncKeyGen` instance");
+        assert!(key_gen.is_resharing());
+        parts.push((*id, part.expect("existing validators create a part")));
+    }
+    let mut nodes: BTreeMap<usize, SyncKeyGen<usize>> = sec_keys
+        .into_iter()
+        .map(|(id, sk)| {
+            let netinfo = old_netinfos.get(&id).cloned().unwrap_or_else(|| {
+                let pk_shares = old_netinfos[&0].public_key_map().clone();
+                NetworkInfo::new(
+                    id,
+                    None::<SecretKeyShare>,
+                    old_pk_set.clone(),
+                    sk.clone(),
+                    pk_shares,
+                )
+            });
+            let (key_gen, part) =
+                SyncKeyGen::new_resharing(id, sk, pub_keys.clone(), threshold, &netinfo, &mut rng)
+                    .expect("failed to create resharing `SyncKeyGen` instance");
+            assert_eq!(old_netinfos.contains_key(&id), part.is_some());
+            (id, key_gen)
+        })
+        .collect();
+
+    // A `Part` that doesn't contain the proposer's existing key share is rejected.
+    let (_, other_part) = SyncKeyGen::new_resharing(
+        0,
+        old_netinfos[&1].secret_key().expect("secret key").clone(),
+        pub_keys.clone(),
+        threshold,
+        &old_netinfos[&1],
+        &mut rng,
+    )
+    .expect("failed to create resharing `SyncKeyGen` instance");
+    let other_part = other_part.expect("part");
+    for node in nodes.values_mut() {
+        match node.handle_part(&0, other_part.clone(), &mut rng) {
+            Ok(PartOutcome::Invalid(PartFault::ShareCommitment)) => (),
+            _ => panic!("the part with the wrong key share should be rejected"),
+        }
+    }
+
+    // The first `old_threshold + 1` proposers' parts get all acks.
+    let old_threshold = old_pk_set.threshold();
+    let mut acks = Vec::new();
+    for (sender_id, part) in &parts[1..=old_threshold + 1] {
+        for (id, node) in &mut nodes {
+            match node
+                .handle_part(sender_id, part.clone(), &mut rng)
+                .expect("failed to handle part")
+            {
+                PartOutcome::Valid(Some(ack)) => acks.push((*id, ack)),
+                PartOutcome::Valid(None) => panic!("missing ack message"),
+                PartOutcome::Invalid(fault) => panic!("invalid part: {:?}", fault),
+            }
+        }
+    }
+    for node in nodes.values_mut() {
+        assert!(!node.is_ready());
+        let outcomes = node
+            .handle_acks(acks.iter().map(|(sender_id, ack)| (sender_id, ack.clone())))
+            .expect("error handling acks");
+        for outcome in outcomes {
+            if let AckOutcome::Invalid(fault) = outcome {
+                panic!("invalid ack: {:?}", fault);
+            }
+        }
+        assert!(node.is_ready());
+    }
+
+    // The new key shares produce the same signature as the existing ones.
+    let msg = "The more things change, the more they stay the same";
+    let old_sig_shares: BTreeMap<_, _> = old_netinfos
+        .values()
+        .map(|netinfo| {
+            let idx = netinfo.node_index(netinfo.our_id()).expect("validator");
+            (idx, netinfo.secret_key_share().expect("share").sign(msg))
+        })
+        .collect();
+    let old_sig = old_pk_set
+        .combine_signatures(&old_sig_shares)
+        .expect("signature shares match");
+    let mut sig_shares = BTreeMap::new();
+    for (idx, node) in nodes.values().enumerate() {
+        let (pks, opt_sk) = node.generate().expect("failed to reshare the keys");
+        assert_eq!(old_pk_set.public_key(), pks.public_key());
+        let sig = opt_sk.expect("new secret key").sign(msg);
+        assert!(pks.public_key_share(idx).verify(&sig, msg));
+        sig_shares.insert(idx, sig);
+    }
+    let pub_key_set = nodes[&2].generate().expect("key set").0;
+    let sig = pub_key_set
+        .combine_signatures(sig_shares.iter().skip(2).take(threshold + 1))
+        .expect("signature shares match");
+    assert_eq!(old_sig, sig);
+}
+
+#[test]
+fn test_sync_key_gen_malformed_messages() {
+    let node_num = 4;
+    let threshold = util::max_faulty(node_num);
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+    let mut rng = rand::thread_rng();
+    let (mut key_gen, opt_part) = SyncKeyGen::new(
+        0,
+        sec_keys[0].clone(),
+        pub_keys.clone(),
+        threshold,
+        &mut rng,
+    )
+    .expect("failed to create `SyncKeyGen` instance");
+    let part = opt_part.expect("part");
+    assert_eq!(Ok(()), key_gen.validate_part(&part));
+
+    // Builds a `Part` from its commitment and the plaintext rows.
+    let make_part = |commit: BivarCommitment, rows: Vec<Vec<u8>>| -> Part {
+        let rows: Vec<Ciphertext> = pub_keys
+            .values()
+            .zip(rows)
+            .map(|(pk, row)| pk.encrypt(row))
+            .collect();
+        let bytes = bincode::serialize(&(commit, rows, None::<Vec<u8>>)).expect("serialize");
+        bincode::deserialize(&bytes).expect("deserialize")
+    };
+
+    // A commitment of the wrong degree is rejected before decrypting anything.
+    let poly = BivarPoly::random(threshold + 1, &mut rng);
+    let rows = (1..=node_num)
+        .map(|i| bincode::serialize(&poly.row(i)).expect("serialize row"))
+        .collect();
+    let bad_part = make_part(poly.commitment(), rows);
+    assert_eq!(
+        Err(PartFault::CommitmentDegree),
+        key_gen.validate_part(&bad_part)
+    );
+    match key_gen.handle_part(&1, bad_part, &mut rng) {
+        Ok(PartOutcome::Invalid(PartFault::CommitmentDegree)) => (),
+        _ => panic!("wrong outcome for a part with the wrong degree"),
+    }
+
+    // An encrypted row that is larger than a row of the expected degree is rejected.
+    let poly = BivarPoly::random(threshold, &mut rng);
+    let rows = (0..node_num).map(|_| vec![0; 1000]).collect();
+    let bad_part = make_part(poly.commitment(), rows);
+    assert_eq!(Err(PartFault::RowSize), key_gen.validate_part(&bad_part));
+
+    // The same applies to oversized values in an `Ack`.
+    let ack = match key_gen.handle_part(&0, part, &mut rng) {
+        Ok(PartOutcome::Valid(Some(ack))) => ack,
+        _ => panic!("our own part should be valid"),
+    };
+    assert_eq!(Ok(()), key_gen.validate_ack(&ack));
+    let values: Vec<Ciphertext> = pub_keys.values().map(|pk| pk.encrypt([0; 100])).collect();
+    let bytes = bincode::serialize(&(0u64, values)).expect("serialize");
+    let bad_ack: Ack = bincode::deserialize(&bytes).expect("deserialize");
+    assert_eq!(Err(AckFault::ValueSize), key_gen.validate_ack(&bad_ack));
+    match key_gen.handle_ack(&1, bad_ack) {
+        Ok(AckOutcome::Invalid(AckFault::ValueSize)) => (),
+        _ => panic!("wrong outcome for an oversized ack"),
+    }
+}
+
+#[test]
+fn test_sync_key_gen_checkpoint() {
+    let node_num = 4;
+    let threshold = util::max_faulty(node_num);
+    let sec_keys: Vec<SecretKey> = (0..node_num).map(|_| SecretKey::random()).collect();
+    let pub_keys: BTreeMap<usize, PublicKey> = sec_keys
+        .iter()
+        .map(SecretKey::public_key)
+        .enumerate()
+        .collect();
+    let mut rng = rand::thread_rng();
+    let (mut nodes, parts): (Vec<_>, Vec<_>) = sec_keys
+        .iter()
+        .enumerate()
+        .map(|(id, sk)| {
+            SyncKeyGen::new(id, sk.clone(), pub_keys.clone(), threshold, &mut rng)
+                .expect("failed to create `SyncKeyGen` instance")
+        })
+        .unzip();
+
+    // All nodes handle all parts.
+    let mut acks = Vec::new();
+    for (sender_id, part) in parts.into_iter().enumerate() {
+        for (node_id, node) in nodes.iter_mut().enumerate() {
+            let part = part.clone().expect("part");
+            match node.handle_part(&sender_id, part, &mut rng) {
+                Ok(PartOutcome::Valid(Some(ack))) => acks.push((node_id, ack)),
+                _ => panic!("invalid part"),
+            }
+        }
+    }
+
+    // Node 0 restarts after handling half of the acks, and resumes from its checkpoint.
+    let (first_acks, second_acks) = acks.split_at(acks.len() / 2);
+    for node in &mut nodes {
+        for (sender_id, ack) in first_acks {
+            node.handle_ack(sender_id, ack.clone())
+                .expect("error handling ack");
+        }
     }
+    let bytes = bincode::serialize(&nodes[0].checkpoint()).expect("serialize checkpoint");
+    let checkpoint: SyncKeyGenCheckpoint<usize> =
+        bincode::deserialize(&bytes).expect("deserialize checkpoint");
+    assert_eq!(nodes[0].checkpoint(), checkpoint);
+    let wrong_key = SyncKeyGen::restore(sec_keys[1].clone(), checkpoint.clone());
+    assert_eq!(Some(Error::CheckpointSecretKey), wrong_key.err());
+    nodes[0] = SyncKeyGen::restore(sec_keys[0].clone(), checkpoint).expect("restore");
+    assert_eq!(first_acks.len(), nodes[0].count_acks());
+
+    for node in &mut nodes {
+        for (sender_id, ack) in second_acks {
+            node.handle_ack(sender_id, ack.clone())
+                .expect("error handling ack");
+        }
+    }
+
+    // The restored node's key share matches the others'.
+    let msg = "The show must go on";
+    let (pub_key_set, _) = nodes[1].generate().expect("generate keys");
+    let sig_shares: BTreeMap<_, _> = nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, node)| {
+            let (pks, opt_sk) = node.generate().expect("generate keys");
+            assert_eq!(pub_key_set, pks);
+            (idx, opt_sk.expect("secret key share").sign(msg))
+        })
+        .collect();
+    let sig = pub_key_set
+        .combine_signatures(sig_shares.iter().take(threshold + 1))
+        .expect("signature shares match");
+    assert!(pub_key_set.public_key().verify(&sig, msg));
 }
@@ -0,0 +1,72 @@
+#![deny(unused_must_use)]
+//! Tests for the ThresholdDecrypt protocol
+
+use std::sync::Arc;
+
+use hbbft::threshold_decrypt::{FaultKind, Message, ThresholdDecrypt};
+use hbbft::{ConsensusProtocol, Fault, NetworkInfo};
+use hbbft_testing::adversary::ReorderingAdversary;
+use hbbft_testing::proptest::TestRng;
+use hbbft_testing::{NetBuilder, NewNodeInfo};
+use rand::SeedableRng;
+
+#[test]
+fn test_threshold_decrypt() {
+    let mut rng = TestRng::from_seed([1; 16]);
+    let netinfos = NetworkInfo::generate_map(0..7u16, &mut rng).expect("netinfos");
+    let pk = netinfos[&0].public_key_set().public_key();
+    let bid = b"sealed bid: 42".to_vec();
+    let ct = pk.encrypt_with_rng(&mut rng, &bid);
+
+    let (mut net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let netinfo = netinfos[&node_info.id].clone();
+            ThresholdDecrypt::new(Arc::new(netinfo))
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let ids: Vec<u16> = net.nodes().map(|node| *node.id()).collect();
+    for id in ids {
+        let td = net.get_mut(id).expect("node").algorithm_mut();
+        let step = td.decrypt(ct.clone()).expect("decrypt");
+        net.process_step(id, &step).expect("process step");
+    }
+    while !net
+        .correct_nodes()
+        .all(|node| node.algorithm().terminated())
+    {
+        let _ = net.crank_expect(&mut rng);
+    }
+    for node in net.correct_nodes() {
+        assert_eq!(&[bid.clone()][..], node.outputs());
+    }
+}
+
+#[test]
+fn test_threshold_decrypt_invalid_share() {
+    let mut rng = TestRng::from_seed([2; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let pk = netinfos[&0].public_key_set().public_key();
+    let sks = |id: u16| netinfos[&id].secret_key_share().expect("sks").clone();
+    let ct = pk.encrypt_with_rng(&mut rng, b"sealed bid");
+    let other_ct = pk.encrypt_with_rng(&mut rng, b"other bid");
+
+    let mut td = ThresholdDecrypt::new(Arc::new(netinfos[&0].clone()));
+    let step = td.decrypt(ct.clone()).expect("decrypt");
+    assert!(step.output.is_empty());
+
+    // Node 1's share is for a different ciphertext.
+    let bad_share = sks(1).decrypt_share_no_verify(&other_ct);
+    let step = td.handle_message(&1, Message(bad_share)).expect("handle");
+    let expected = Fault::new(1, FaultKind::UnverifiedDecryptionShareSender);
+    assert_eq!(vec![expected], step.fault_log.0);
+    assert!(step.output.is_empty());
+
+    let share = sks(2).decrypt_share_no_verify(&ct);
+    let step = td.handle_message(&2, Message(share)).expect("handle");
+    assert_eq!(vec![b"sealed bid".to_vec()], step.output);
+    assert!(td.terminated());
+}
@@ -1,9 +1,11 @@
 #![deny(unused_must_use)]
 //! Non-deterministic tests for the ThresholdSign protocol
 
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use hbbft::{crypto::Signature, threshold_sign::ThresholdSign, util, ConsensusProtocol};
+use hbbft::crypto::Signature;
+use hbbft::threshold_sign::{FaultKind, Message, ThresholdSign};
+use hbbft::{util, ConsensusProtocol, Fault, NetworkInfo};
 use hbbft_testing::adversary::{Adversary, NodeOrderAdversary, ReorderingAdversary};
 use hbbft_testing::proptest::{gen_seed, TestRng, TestRngSeed};
 use hbbft_testing::{NetBuilder, NewNodeInfo, VirtualNet};
@@ -136,6 +138,86 @@ proptest! {
     }
 }
 
+#[test]
+fn test_threshold_sign_precomputed_shares() {
+    let mut rng = TestRng::from_seed([6; 16]);
+    let doc = b"precomputed";
+    let pk_set = Arc::new(Mutex::new(None));
+    let node_pk_set = pk_set.clone();
+    let (net, _) = NetBuilder::new(0..7u16)
+        .num_faulty(2)
+        .message_limit(100)
+        .adversary(ReorderingAdversary::new())
+        .using(move |node_info: NewNodeInfo<_>| {
+            let netinfo = Arc::new(node_info.netinfo);
+            *node_pk_set.lock().unwrap() = Some(netinfo.public_key_set().clone());
+            let share = netinfo.secret_key_share().expect("sks").sign(doc);
+            let mut ts = ThresholdSign::new_with_document(netinfo, doc)
+                .expect("Failed to create a ThresholdSign instance.");
+            ts.set_our_share(share);
+            ts
+        })
+        .build(&mut rng)
+        .expect("Could not construct test network.");
+    let sig = test_threshold_sign(net, &mut rng);
+    let pk_set = pk_set.lock().unwrap().clone().expect("public key set");
+    assert!(pk_set.public_key().verify(&sig, doc));
+}
+
+#[test]
+fn test_threshold_sign_batch_verification() {
+    let mut rng = TestRng::from_seed([8; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let doc = b"batch";
+    let mut ts = ThresholdSign::new_with_document(Arc::new(netinfos[&0].clone()), doc)
+        .expect("Failed to create a ThresholdSign instance.");
+    ts.set_batch_verification(true);
+    let step = ts.sign().expect("sign");
+    assert!(step.output.is_empty());
+
+    // Node 1 signs the wrong document. With our own share, that's enough to try combining them,
+    // so it is detected and reported.
+    let sks = |id: u16| netinfos[&id].secret_key_share().expect("sks").clone();
+    let bad_share = Message(sks(1).sign(b"wrong document"));
+    let step = ts.handle_message(&1, bad_share).expect("handle message");
+    assert!(step.output.is_empty());
+    let expected = Fault::new(1, FaultKind::UnverifiedSignatureShareSender);
+    assert_eq!(vec![expected], step.fault_log.0);
+
+    // Node 2's share is valid, so it produces the signature.
+    let step = ts
+        .handle_message(&2, Message(sks(2).sign(doc)))
+        .expect("handle message");
+    assert!(step.fault_log.is_empty());
+    let pk = netinfos[&0].public_key_set().public_key();
+    assert!(pk.verify(&step.output[0], doc));
+}
+
+#[test]
+fn test_threshold_sign_document() {
+    let mut rng = TestRng::from_seed([10; 16]);
+    let netinfos = NetworkInfo::generate_map(0..4u16, &mut rng).expect("netinfos");
+    let doc = b"checkpoint 17";
+    let mut ts = ThresholdSign::builder(Arc::new(netinfos[&0].clone()))
+        .build()
+        .expect("Failed to create a ThresholdSign instance.");
+    let step = ts.sign_document(doc).expect("sign");
+    assert!(step.output.is_empty());
+    assert_eq!(1, step.messages.len());
+    assert_eq!(
+        Err(hbbft::threshold_sign::Error::MultipleMessagesToSign),
+        ts.sign_document(doc).map(|_| ())
+    );
+
+    let share = netinfos[&1].secret_key_share().expect("sks").sign(doc);
+    let step = ts
+        .handle_message(&1, Message(share))
+        .expect("handle message");
+    let pk = ts.netinfo().public_key_set().public_key();
+    assert!(pk.verify(&step.output[0], doc));
+    assert!(ts.terminated());
+}
+
 fn do_test_threshold_sign_random_silent_200_samples(seed: TestRngSeed) {
     let new_adversary = || ReorderingAdversary::new();
     test_threshold_sign_different_sizes(new_adversary, 200, seed);